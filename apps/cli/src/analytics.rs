@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Number of searches served per index, used to rebuild the most used indexes first.
+///
+/// Counts are kept in memory and written to `$BEETLE_HOME/search_counts.json` by
+/// [`SearchAnalytics::flush`], so they survive server restarts.
+pub struct SearchAnalytics {
+    path: PathBuf,
+    counts: Mutex<HashMap<String, u64>>,
+    dirty: AtomicBool,
+}
+
+impl SearchAnalytics {
+    /// Loads the persisted counts; a missing or unreadable file starts from zero.
+    pub fn load(beetle_home: PathBuf) -> Self {
+        let path = beetle_home.join(Self::FILE_NAME);
+        let counts = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        SearchAnalytics {
+            path,
+            counts: Mutex::new(counts),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    pub fn record_search(&self, index_name: &str) {
+        *self
+            .counts
+            .lock()
+            .unwrap()
+            .entry(index_name.to_string())
+            .or_default() += 1;
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    pub fn search_count(&self, index_name: &str) -> u64 {
+        self.counts
+            .lock()
+            .unwrap()
+            .get(index_name)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Writes the counts if they changed since the last flush.
+    pub fn flush(&self) -> Result<(), String> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let json = serde_json::to_string_pretty(&*self.counts.lock().unwrap())
+            .map_err(|e| format!("Failed to serialize search counts: {e}"))?;
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create directory {parent:?}: {e}"))?;
+        }
+        fs::write(&self.path, json).map_err(|e| {
+            self.dirty.store(true, Ordering::Relaxed);
+            format!("Failed to write search counts {:?}: {e}", self.path)
+        })
+    }
+
+    pub const FILE_NAME: &'static str = "search_counts.json";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_survive_reload() {
+        let home = tempfile::tempdir().unwrap();
+        let analytics = SearchAnalytics::load(home.path().to_path_buf());
+        analytics.record_search("api");
+        analytics.record_search("api");
+        analytics.record_search("web");
+        analytics.flush().unwrap();
+
+        let reloaded = SearchAnalytics::load(home.path().to_path_buf());
+        assert_eq!(reloaded.search_count("api"), 2);
+        assert_eq!(reloaded.search_count("web"), 1);
+        assert_eq!(reloaded.search_count("docs"), 0);
+    }
+}
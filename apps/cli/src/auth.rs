@@ -0,0 +1,185 @@
+mod oidc;
+
+use engine::ALL_INDEXES;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::time::SystemTime;
+
+pub use oidc::{OidcBackend, OidcConfig};
+
+/// The caller of an API request, as established by an [`AuthBackend`].
+#[derive(Debug, Clone)]
+pub enum Principal {
+    /// May call every `/api` route, including writes.
+    Admin,
+    /// May only call the read-only routes for the indexes of the token.
+    ReadOnly(ApiToken),
+}
+
+pub type AuthFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Option<Principal>, String>> + Send + 'a>>;
+
+/// A way of turning the bearer credential of an API request into a [`Principal`].
+///
+/// The server tries its backends in order; `Ok(None)` means the credential is not one the
+/// backend recognizes and the next backend gets a chance.
+pub trait AuthBackend: Send + Sync {
+    fn authenticate<'a>(&'a self, bearer: &'a str) -> AuthFuture<'a>;
+}
+
+/// The admin token passed to `beetle serve --token`.
+pub struct AdminToken(pub String);
+
+impl AuthBackend for AdminToken {
+    fn authenticate<'a>(&'a self, bearer: &'a str) -> AuthFuture<'a> {
+        let principal = secrets_match(bearer, &self.0).then_some(Principal::Admin);
+        Box::pin(async move { Ok(principal) })
+    }
+}
+
+/// A read-only API token scoped to a set of indexes.
+///
+/// Only the SHA-256 hash of the secret is persisted; the secret itself is shown once at creation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub indexes: Vec<String>,
+    pub created_at: u64,
+}
+
+impl ApiToken {
+    /// Whether the token may read `index_name`; `_all` in the token's indexes grants every index.
+    pub fn allows_index(&self, index_name: &str) -> bool {
+        self.indexes
+            .iter()
+            .any(|index| index == index_name || index == ALL_INDEXES)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredToken {
+    #[serde(flatten)]
+    token: ApiToken,
+    secret_hash: String,
+}
+
+pub struct TokenStore {
+    path: PathBuf,
+}
+
+impl TokenStore {
+    pub fn new(beetle_home: PathBuf) -> Self {
+        TokenStore {
+            path: beetle_home.join(Self::TOKENS_FILE_NAME),
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<ApiToken>, String> {
+        let tokens = self.load()?;
+
+        Ok(tokens.into_iter().map(|stored| stored.token).collect())
+    }
+
+    fn load(&self) -> Result<Vec<StoredToken>, String> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let json = fs::read_to_string(&self.path)
+            .map_err(|e| format!("Failed to read token file {:?}: {e}", self.path))?;
+        serde_json::from_str(&json)
+            .map_err(|e| format!("Failed to parse token file {:?}: {e}", self.path))
+    }
+
+    /// Creates a token for `indexes` and returns it together with its secret.
+    pub fn create(&self, indexes: Vec<String>) -> Result<(ApiToken, String), String> {
+        if indexes.is_empty() {
+            return Err("A token must be scoped to at least one index".to_string());
+        }
+
+        let secret = format!(
+            "btl_{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let token = ApiToken {
+            id: uuid::Uuid::new_v4().simple().to_string()[..8].to_string(),
+            indexes,
+            created_at: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+
+        let mut tokens = self.load()?;
+        tokens.push(StoredToken {
+            token: token.clone(),
+            secret_hash: hash_secret(&secret),
+        });
+        self.save(&tokens)?;
+
+        Ok((token, secret))
+    }
+
+    pub fn revoke(&self, id: &str) -> Result<(), String> {
+        let mut tokens = self.load()?;
+        let count = tokens.len();
+        tokens.retain(|stored| stored.token.id != id);
+        if tokens.len() == count {
+            return Err(format!("Token {id} not found"));
+        }
+
+        self.save(&tokens)
+    }
+
+    pub fn verify(&self, secret: &str) -> Result<Option<ApiToken>, String> {
+        let secret_hash = hash_secret(secret);
+        let token = self
+            .load()?
+            .into_iter()
+            .find(|stored| stored.secret_hash == secret_hash)
+            .map(|stored| stored.token);
+
+        Ok(token)
+    }
+
+    fn save(&self, tokens: &[StoredToken]) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create directory {parent:?}: {e}"))?;
+        }
+
+        let json = serde_json::to_string_pretty(tokens)
+            .map_err(|e| format!("Failed to serialize tokens: {e}"))?;
+        fs::write(&self.path, json)
+            .map_err(|e| format!("Failed to write token file {:?}: {e}", self.path))
+    }
+
+    pub const TOKENS_FILE_NAME: &'static str = "tokens.json";
+}
+
+impl AuthBackend for TokenStore {
+    fn authenticate<'a>(&'a self, bearer: &'a str) -> AuthFuture<'a> {
+        let principal = self
+            .verify(bearer)
+            .map(|token| token.map(Principal::ReadOnly));
+        Box::pin(async move { principal })
+    }
+}
+
+/// Compares two secrets through their hashes so the comparison time does not depend on how many
+/// leading characters match.
+pub fn secrets_match(secret: &str, expected: &str) -> bool {
+    hash_secret(secret) == hash_secret(expected)
+}
+
+fn hash_secret(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
@@ -0,0 +1,377 @@
+use super::{ApiToken, AuthBackend, AuthFuture, Principal};
+use engine::ALL_INDEXES;
+use jsonwebtoken::jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// Keys are refetched after this long even if every token still names a known key.
+const JWKS_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+/// Tokens naming an unknown key trigger a refetch at most this often, so a client sending
+/// garbage cannot make the server hammer the identity provider.
+const JWKS_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    /// Expected `iss` claim; also the base URL of the discovery document.
+    pub issuer: String,
+    /// Expected `aud` claim.
+    pub audience: String,
+    /// Where to fetch the signing keys; discovered from the issuer when not set.
+    pub jwks_url: Option<String>,
+    /// Claim listing the indexes the caller may read; every index when not set.
+    pub indexes_claim: Option<String>,
+}
+
+/// Validates OIDC/OAuth2 access tokens (JWTs) against the identity provider's published keys.
+///
+/// A valid token grants read-only access, like a token created with `beetle token create`.
+pub struct OidcBackend {
+    config: OidcConfig,
+    client: reqwest::Client,
+    keys: RwLock<CachedKeys>,
+}
+
+struct CachedKeys {
+    jwks: JwkSet,
+    fetched_at: Option<Instant>,
+}
+
+#[derive(Deserialize)]
+struct DiscoveryDocument {
+    jwks_uri: String,
+}
+
+impl OidcBackend {
+    pub fn new(config: OidcConfig) -> Self {
+        Self::with_keys(config, JwkSet { keys: Vec::new() }, None)
+    }
+
+    fn with_keys(config: OidcConfig, jwks: JwkSet, fetched_at: Option<Instant>) -> Self {
+        OidcBackend {
+            config,
+            client: reqwest::Client::new(),
+            keys: RwLock::new(CachedKeys { jwks, fetched_at }),
+        }
+    }
+
+    /// Fetches the signing keys from the identity provider, replacing the cached ones.
+    pub async fn refresh_keys(&self) -> Result<(), String> {
+        // Record the attempt even if it fails, so failures are also rate limited
+        self.keys.write().await.fetched_at = Some(Instant::now());
+        let jwks = self.fetch_keys().await?;
+        self.keys.write().await.jwks = jwks;
+
+        Ok(())
+    }
+
+    async fn fetch_keys(&self) -> Result<JwkSet, String> {
+        let jwks_url = match &self.config.jwks_url {
+            Some(jwks_url) => jwks_url.clone(),
+            None => {
+                let discovery_url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.config.issuer.trim_end_matches('/')
+                );
+                self.get_json::<DiscoveryDocument>(&discovery_url)
+                    .await?
+                    .jwks_uri
+            }
+        };
+        self.get_json(&jwks_url).await
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, String> {
+        self.client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to fetch {url}: {e}"))?
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse {url}: {e}"))
+    }
+
+    /// Returns the key for `kid` and the algorithms tokens signed with it may use, refetching the
+    /// key set when it is stale or lacks the key.
+    async fn decoding_key(&self, kid: Option<&str>) -> Option<(DecodingKey, Vec<Algorithm>)> {
+        let find = |keys: &CachedKeys| {
+            let jwk = match kid {
+                Some(kid) => keys.jwks.find(kid),
+                // Without a key id the token can only be matched against a single-key set
+                None if keys.jwks.keys.len() == 1 => keys.jwks.keys.first(),
+                None => None,
+            };
+            let jwk = jwk?;
+            let algorithms = key_algorithms(jwk);
+            if algorithms.is_empty() {
+                return None;
+            }
+            Some((DecodingKey::from_jwk(jwk).ok()?, algorithms))
+        };
+        let fetched_within = |keys: &CachedKeys, interval: Duration| {
+            keys.fetched_at
+                .is_some_and(|fetched_at| fetched_at.elapsed() < interval)
+        };
+
+        {
+            let keys = self.keys.read().await;
+            if fetched_within(&keys, JWKS_MAX_AGE) {
+                if let Some(key) = find(&keys) {
+                    return Some(key);
+                }
+            }
+        }
+
+        // Claimed under the lock, so that requests arriving during the fetch do not start
+        // their own, but fetched outside it, so that they are not blocked on the provider
+        let refresh = {
+            let mut keys = self.keys.write().await;
+            let refresh = !fetched_within(&keys, JWKS_MIN_REFRESH_INTERVAL);
+            if refresh {
+                keys.fetched_at = Some(Instant::now());
+            }
+            refresh
+        };
+        if refresh {
+            match self.fetch_keys().await {
+                Ok(jwks) => self.keys.write().await.jwks = jwks,
+                Err(e) => tracing::warn!("Failed to refresh OIDC signing keys: {e}"),
+            }
+        }
+        find(&*self.keys.read().await)
+    }
+
+    fn principal(&self, claims: &Map<String, Value>) -> Principal {
+        let indexes = match &self.config.indexes_claim {
+            Some(claim) => match claims.get(claim) {
+                Some(Value::Array(values)) => values
+                    .iter()
+                    .filter_map(|value| value.as_str().map(str::to_string))
+                    .collect(),
+                // Space-separated, like the standard `scope` claim
+                Some(Value::String(value)) => {
+                    value.split_whitespace().map(str::to_string).collect()
+                }
+                _ => Vec::new(),
+            },
+            None => vec![ALL_INDEXES.to_string()],
+        };
+
+        Principal::ReadOnly(ApiToken {
+            id: claims
+                .get("sub")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            indexes,
+            created_at: claims
+                .get("iat")
+                .and_then(Value::as_u64)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl AuthBackend for OidcBackend {
+    fn authenticate<'a>(&'a self, bearer: &'a str) -> AuthFuture<'a> {
+        Box::pin(async move {
+            // Not a JWT, e.g. a token created with `beetle token create`
+            let Ok(header) = decode_header(bearer) else {
+                return Ok(None);
+            };
+            let Some((key, algorithms)) = self.decoding_key(header.kid.as_deref()).await else {
+                return Ok(None);
+            };
+
+            // The key decides the algorithm: tokens whose header names another are refused
+            let mut validation = Validation::new(algorithms[0]);
+            validation.algorithms = algorithms;
+            validation.set_issuer(&[&self.config.issuer]);
+            validation.set_audience(&[&self.config.audience]);
+            match decode::<Map<String, Value>>(bearer, &key, &validation) {
+                Ok(token) => Ok(Some(self.principal(&token.claims))),
+                Err(e) => {
+                    tracing::debug!("Rejected OIDC token: {e}");
+                    Ok(None)
+                }
+            }
+        })
+    }
+}
+
+/// The signature algorithms of tokens signed with `jwk`: its `alg` when it has one, otherwise
+/// those of its key type. Never HMAC, whose secret would be the published key.
+fn key_algorithms(jwk: &Jwk) -> Vec<Algorithm> {
+    if let Some(key_algorithm) = jwk.common.key_algorithm {
+        return Algorithm::from_str(&key_algorithm.to_string())
+            .into_iter()
+            .filter(|algorithm| {
+                !matches!(
+                    algorithm,
+                    Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+                )
+            })
+            .collect();
+    }
+
+    match &jwk.algorithm {
+        AlgorithmParameters::RSA(_) => vec![
+            Algorithm::RS256,
+            Algorithm::RS384,
+            Algorithm::RS512,
+            Algorithm::PS256,
+            Algorithm::PS384,
+            Algorithm::PS512,
+        ],
+        AlgorithmParameters::EllipticCurve(params) => match params.curve {
+            EllipticCurve::P256 => vec![Algorithm::ES256],
+            EllipticCurve::P384 => vec![Algorithm::ES384],
+            _ => Vec::new(),
+        },
+        AlgorithmParameters::OctetKeyPair(_) => vec![Algorithm::EdDSA],
+        AlgorithmParameters::OctetKey(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use std::time::SystemTime;
+
+    const PRIVATE_KEY: &str = include_str!("../../tests/fixtures/oidc/private_key.pem");
+    const JWKS: &str = include_str!("../../tests/fixtures/oidc/jwks.json");
+
+    fn backend(indexes_claim: Option<&str>) -> OidcBackend {
+        backend_with_keys(indexes_claim, serde_json::from_str(JWKS).unwrap())
+    }
+
+    fn backend_with_keys(indexes_claim: Option<&str>, jwks: JwkSet) -> OidcBackend {
+        let config = OidcConfig {
+            issuer: "https://sso.example.com".to_string(),
+            audience: "beetle".to_string(),
+            // Unroutable, so a refresh attempt fails fast instead of reaching the network
+            jwks_url: Some("http://127.0.0.1:9/jwks.json".to_string()),
+            indexes_claim: indexes_claim.map(str::to_string),
+        };
+
+        OidcBackend::with_keys(config, jwks, Some(Instant::now()))
+    }
+
+    fn sign(claims: Value) -> String {
+        sign_with(Algorithm::RS256, claims)
+    }
+
+    fn sign_with(algorithm: Algorithm, claims: Value) -> String {
+        let mut header = Header::new(algorithm);
+        header.kid = Some("test-key".to_string());
+        let key = EncodingKey::from_rsa_pem(PRIVATE_KEY.as_bytes()).unwrap();
+
+        encode(&header, &claims, &key).unwrap()
+    }
+
+    fn expires_at() -> u64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 600
+    }
+
+    #[tokio::test]
+    async fn test_accepts_valid_token() {
+        let token = sign(serde_json::json!({
+            "iss": "https://sso.example.com",
+            "aud": "beetle",
+            "sub": "alice",
+            "exp": expires_at(),
+            "groups": ["backend", "frontend"],
+        }));
+
+        let principal = backend(Some("groups")).authenticate(&token).await.unwrap();
+        match principal {
+            Some(Principal::ReadOnly(token)) => {
+                assert_eq!(token.id, "alice");
+                assert_eq!(token.indexes, vec!["backend", "frontend"]);
+            }
+            other => panic!("Expected a read-only principal, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_grants_every_index_without_claim() {
+        let token = sign(serde_json::json!({
+            "iss": "https://sso.example.com",
+            "aud": "beetle",
+            "sub": "alice",
+            "exp": expires_at(),
+        }));
+
+        match backend(None).authenticate(&token).await.unwrap() {
+            Some(Principal::ReadOnly(token)) => assert!(token.allows_index("anything")),
+            other => panic!("Expected a read-only principal, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejects_wrong_audience_and_expired_tokens() {
+        let backend = backend(None);
+        let wrong_audience = sign(serde_json::json!({
+            "iss": "https://sso.example.com",
+            "aud": "another-app",
+            "exp": expires_at(),
+        }));
+        let expired = sign(serde_json::json!({
+            "iss": "https://sso.example.com",
+            "aud": "beetle",
+            "exp": 1,
+        }));
+
+        assert!(backend
+            .authenticate(&wrong_audience)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(backend.authenticate(&expired).await.unwrap().is_none());
+        assert!(backend
+            .authenticate("btl_not_a_jwt")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pins_the_algorithm_of_the_key() {
+        let claims = || {
+            serde_json::json!({
+                "iss": "https://sso.example.com",
+                "aud": "beetle",
+                "exp": expires_at(),
+            })
+        };
+        // The key is published for RS256 only
+        let rs384 = sign_with(Algorithm::RS384, claims());
+        assert!(backend(None).authenticate(&rs384).await.unwrap().is_none());
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("test-key".to_string());
+        let hs256 = encode(
+            &header,
+            &claims(),
+            &EncodingKey::from_secret(JWKS.as_bytes()),
+        )
+        .unwrap();
+        assert!(backend(None).authenticate(&hs256).await.unwrap().is_none());
+
+        // Without `alg`, any RSA algorithm, but still not HMAC
+        let mut jwks: JwkSet = serde_json::from_str(JWKS).unwrap();
+        jwks.keys[0].common.key_algorithm = None;
+        let backend = backend_with_keys(None, jwks);
+        assert!(backend.authenticate(&rs384).await.unwrap().is_some());
+        assert!(backend.authenticate(&hs256).await.unwrap().is_none());
+    }
+}
@@ -1,36 +1,120 @@
+mod bench;
+mod clean;
+mod doctor;
+mod editor_server;
+mod env;
 mod formatter;
+mod history;
+mod hook;
+mod info;
+mod ingest;
 mod list;
+mod mcp;
 mod new;
 mod option;
+mod progress;
 mod remove;
 mod runner;
 mod search;
 mod serve;
+mod snapshot;
+mod tags;
+mod token;
 mod update;
+mod verify;
 
 pub use runner::BeetleRunner;
 
-pub use formatter::{CommandOutput, JsonFormatter, PlainTextFormatter, ResultFormatter};
+pub use formatter::{
+    CommandOutput, CsvFormatter, JsonFormatter, NdjsonFormatter, PlainTextFormatter,
+    ResultFormatter,
+};
 
-pub use option::{format, index_name};
+pub use option::{color, format, index_name, tag, wait, writer_resources};
 
+pub use history::HistoryAction;
+
+pub use hook::HookAction;
+
+pub use list::ListSort;
+
+pub use new::IndexSource;
+
+pub use search::SearchScope;
+
+pub use serve::ServeControl;
+
+pub use snapshot::SnapshotAction;
+
+pub use token::TokenAction;
+
+use crate::auth::OidcConfig;
+use crate::logging::{LogFormat, LogOptions};
+use crate::rate_limit::RateLimit;
 use bpaf::*;
+use engine::options::{
+    AnalyzerOptions, ChangeDetection, ContentStorage, ContentTokenizer, FieldBoosts,
+    MergePolicyOptions, SchemaProfile, SubmodulePolicy, WriterResources,
+};
 use std::path::PathBuf;
+use std::time::Duration;
 
+use bench::bench_command;
+use clean::clean_command;
+use doctor::doctor_command;
+use editor_server::editor_server_command;
+use env::env_command;
+use history::history_command;
+use hook::hook_command;
+use info::info_command;
+use ingest::ingest_command;
 use list::list_command;
+use mcp::mcp_command;
 use new::new_command;
 use remove::remove_command;
 use search::search_command;
 use serve::serve_command;
+use snapshot::snapshot_command;
+use tags::tags_command;
+use token::token_command;
 use update::update_command;
+use verify::verify_command;
 
 /// Output format for search results
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum OutputFormat {
     /// Plain text format (default)
     Text,
     /// JSON format
     Json,
+    /// Comma-separated values with a header row
+    Csv,
+    /// Newline-delimited JSON, one record per line
+    Ndjson,
+}
+
+/// Whether text output highlights matches with terminal colors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    /// When standard output is a terminal and `NO_COLOR` is not set (default)
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        use std::io::IsTerminal;
+
+        match self {
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && std::io::stdout().is_terminal()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -38,29 +122,257 @@ pub enum BeetleCommand {
     New {
         index_name: String,
 
-        path_to_be_indexed: PathBuf,
+        source: IndexSource,
+        only_extensions: Vec<String>,
+        /// Bytes of each file that are indexed; whole files when unset.
+        max_file_size: Option<u64>,
+        content_storage: ContentStorage,
+        tokenizer: ContentTokenizer,
+        profile: SchemaProfile,
+        analyzer: AnalyzerOptions,
+        boosts: FieldBoosts,
+        merge_policy: MergePolicyOptions,
+        encrypt: bool,
+        /// Files are read as UTF-8 only, instead of transcoded from their encoding.
+        utf8_only: bool,
+        /// Files with the same content as another are indexed once.
+        dedup: bool,
+        /// Documents record the last commit of their file.
+        git_blame: bool,
+        /// The files git tracks are indexed instead of walking the path.
+        tracked_only: bool,
+        /// Walks index hidden files too.
+        hidden: bool,
+        /// Walks disregard the global gitignore.
+        no_global_gitignore: bool,
+        /// Walks disregard the repository's `.git/info/exclude`.
+        no_git_exclude: bool,
+        /// What becomes of the files of git submodules.
+        submodules: SubmodulePolicy,
+        /// The committed files of these branches are indexed instead of the files of the path.
+        branches: Vec<String>,
+        /// A companion index of the commit history of the path is kept along with the index.
+        commits: bool,
+        /// The files listed by `--files-from`, indexed instead of walking the path.
+        files: Vec<String>,
+        /// The index name is turned into a valid one instead of rejected when invalid.
+        slug: bool,
+        /// Recorded in the index's options and used by every update.
+        writer_resources: WriterResources,
+        tags: Vec<String>,
+        /// The files are scanned and listed instead of creating the index.
+        dry_run: bool,
+        format: OutputFormat,
     },
     Search {
-        index_name: String,
+        scope: SearchScope,
         query: String,
+        /// Search definitions named like `query` instead of file content.
+        symbols: bool,
+        /// Match `query` verbatim instead of parsing it.
+        exact: bool,
+        /// Search the companion commit indexes of the indexes instead of their files.
+        commits: bool,
         format: OutputFormat,
+        /// Highlighting of matches in text output.
+        color: ColorChoice,
+        max_query_cost: u64,
+        force: bool,
+        highlight_tag: String,
+        highlight_class: Option<String>,
+        /// Longest fragment of the snippets, in characters.
+        snippet_chars: usize,
+        /// Best non-overlapping fragments joined in each snippet.
+        max_fragments: usize,
+        /// Canonical names of the languages results are restricted to; empty for any language.
+        languages: Vec<String>,
+        /// Only files under this directory of the indexed directory.
+        within: Option<String>,
+        /// Leave out files with these extensions.
+        excluded_extensions: Vec<String>,
+        /// Leave out files under these directories, or these files.
+        excluded_paths: Vec<String>,
+        /// Only files last committed by an author with these words in their name.
+        author: Option<String>,
+        /// Only files of these branches; empty for every branch.
+        branches: Vec<String>,
+        /// Only files added or modified between this revision and HEAD.
+        changed_since: Option<String>,
+        /// Only files last modified at or after this time, in seconds since the Unix epoch.
+        modified_after: Option<i64>,
+        /// Only files last modified before this time, in seconds since the Unix epoch.
+        modified_before: Option<i64>,
+        /// Ranking weights overriding the index's.
+        boosts: FieldBoosts,
+        /// Report the number of results per language on stderr.
+        facets: bool,
+        /// Report resource usage on stderr.
+        stats: bool,
+        /// Do not record the search in the history.
+        no_history: bool,
     },
     List {
         format: OutputFormat,
+        sort: ListSort,
+        /// Only list indexes carrying every one of these tags.
+        tags: Vec<String>,
+        /// Only list indexes whose name or target path contains this text.
+        filter: Option<String>,
     },
     Remove {
         index_name: String,
     },
+    /// Prints the details of one index.
+    Info {
+        index_name: String,
+        format: OutputFormat,
+    },
     Update {
         index_name: String,
         reindex: bool,
+        /// Wait for the index's write lock instead of failing when it is busy.
+        wait: bool,
+        /// Overrides the index's writer resources for this update.
+        writer_resources: WriterResources,
+        /// How changed files are told apart; not used by full reindexes.
+        change_detection: ChangeDetection,
+        /// The changes are listed instead of written to the index.
+        dry_run: bool,
+        format: OutputFormat,
+    },
+    Ingest {
+        index_name: String,
+        path_prefix: String,
+        wait: bool,
+    },
+    Tags {
+        index_name: String,
+        output: Option<PathBuf>,
+        etags: bool,
     },
+    /// Options left unset fall back to the `serve` block of the config file.
     Serve {
-        port: u16,
+        port: Option<u16>,
+        host: Option<String>,
+        require_token: bool,
+        token: Option<String>,
+        oidc: Option<OidcConfig>,
+        max_query_cost: u64,
+        min_update_interval: u64,
+        rate_limit: Option<RateLimit>,
+        cors_origins: Vec<String>,
+        cors_methods: Vec<String>,
+        daemon: bool,
+        control: Option<ServeControl>,
+        /// Index jobs running at once; unlimited when unset.
+        max_index_jobs: Option<usize>,
+        /// Size of the pool reading files for every index job; one thread per core when unset.
+        reader_threads: Option<usize>,
+        /// Overrides the writer resources of every index the server updates.
+        writer_resources: WriterResources,
+        /// Refuse the API requests that write.
+        read_only: bool,
+        /// Time between two incremental updates of the indexes; never when unset.
+        auto_update: Option<Duration>,
+        /// Indexes updated by `auto_update`; every index when empty.
+        auto_update_indexes: Vec<String>,
+        /// Do not record the searches served in the history.
+        no_history: bool,
+    },
+    Mcp,
+    EditorServer,
+    Doctor {
+        fix: bool,
+        format: OutputFormat,
+    },
+    /// Finds the index directories and files that hold no usable index, and removes them.
+    Clean {
+        /// Remove them without asking.
+        yes: bool,
+        format: OutputFormat,
+    },
+    Verify {
+        index_name: String,
+        repair: bool,
+        format: OutputFormat,
+    },
+    /// Lists, re-runs or clears the searches recorded in the history.
+    History {
+        action: HistoryAction,
+        format: OutputFormat,
+    },
+    /// Prints where beetle keeps its indexes and state.
+    Env {
+        format: OutputFormat,
+    },
+    Token {
+        format: OutputFormat,
+        action: TokenAction,
     },
+    Snapshot {
+        format: OutputFormat,
+        action: SnapshotAction,
+    },
+    /// Git hooks updating an index, see [`crate::hooks`].
+    Hook {
+        action: HookAction,
+    },
+    Bench {
+        path: PathBuf,
+        /// Queries of the workload; empty for the names of some of the indexed files.
+        queries: Vec<String>,
+        /// Times each query is run.
+        iterations: usize,
+        writer_resources: WriterResources,
+        format: OutputFormat,
+    },
+}
+
+/// The whole command line: the logging options, then the command.
+pub fn beetle_cli() -> OptionParser<(LogOptions, BeetleCommand)> {
+    let verbose = short('v')
+        .long("verbose")
+        .help("Log more: what beetle does with -v, its details with -vv, everything with -vvv")
+        .req_flag(())
+        .many()
+        .map(|flags| flags.len().min(3) as i8);
+    let quiet = long("quiet").help("Only log errors").req_flag(-1);
+    let verbosity = construct!([quiet, verbose]);
+    let log_file = long("log-file")
+        .argument::<PathBuf>("PATH")
+        .help("Also log to PATH, at least what -v shows; rolled over at 10 MB, 3 old files kept")
+        .optional();
+    let format = long("log-format")
+        .argument::<String>("FORMAT")
+        .help("Log format: text (default), or json for a JSON object per line")
+        .parse(|s| match s.as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err("Invalid log format. Use 'text' or 'json'"),
+        })
+        .fallback(LogFormat::Text);
+    let logging = construct!(LogOptions {
+        verbosity,
+        log_file,
+        format
+    });
+    let command = commands();
+
+    construct!(logging, command)
+        .to_options()
+        .descr("Beetle - Source Code Repository Indexing Tool")
+        .header("Efficiently index and query source code repositories")
 }
 
+/// The commands, without the logging options of [`beetle_cli`] before them.
 pub fn beetle_command() -> OptionParser<BeetleCommand> {
+    commands()
+        .to_options()
+        .descr("Beetle - Source Code Repository Indexing Tool")
+        .header("Efficiently index and query source code repositories")
+}
+
+fn commands() -> impl Parser<BeetleCommand> {
     let new = new_command()
         .command("new")
         .help("Create a new index for a specified folder");
@@ -77,23 +389,98 @@ pub fn beetle_command() -> OptionParser<BeetleCommand> {
         .command("remove")
         .help("Remove an index from the system");
 
+    let info = info_command()
+        .command("info")
+        .help("Show the details of an index: options, schema, segments and pending changes");
+
     let update = update_command()
         .command("update")
         .help("Update an existing index with new changes or reindex");
 
+    let ingest = ingest_command()
+        .command("ingest")
+        .help("Index NDJSON records of path and content read from standard input");
+
+    let tags = tags_command()
+        .command("tags")
+        .help("Export the symbols of an index as a ctags or etags file");
+
     let serve = serve_command()
         .command("serve")
         .help("Start HTTP server for search API");
 
-    construct!([new, search, list, remove, update, serve])
-        .to_options()
-        .descr("Beetle - Source Code Repository Indexing Tool")
-        .header("Efficiently index and query source code repositories")
+    let mcp = mcp_command()
+        .command("mcp")
+        .help("Serve the indexes to LLM agents over the Model Context Protocol (stdio)");
+
+    let editor_server = editor_server_command()
+        .command("editor-server")
+        .help("Serve search to editor plugins over JSON-RPC on stdio");
+
+    let doctor = doctor_command()
+        .command("doctor")
+        .help("Check all indexes for consistency problems");
+
+    let clean = clean_command()
+        .command("clean")
+        .help("Remove broken or partial index directories and leftovers of interrupted runs");
+
+    let verify = verify_command()
+        .command("verify")
+        .help("Check an index's segment files and file snapshot for corruption");
+
+    let history = history_command()
+        .command("history")
+        .help("List the searches that ran, re-run one or clear them");
+
+    let env = env_command()
+        .command("env")
+        .help("Show the home directory beetle resolved and the files it keeps there");
+
+    let token = token_command()
+        .command("token")
+        .help("Manage read-only API tokens for the HTTP server");
+
+    let snapshot = snapshot_command()
+        .command("snapshot")
+        .help("Create, list and restore point-in-time copies of indexes");
+
+    let hook = hook_command()
+        .command("hook")
+        .help("Install git hooks that keep an index up to date with its repository");
+
+    let bench = bench_command()
+        .command("bench")
+        .help("Measure indexing throughput and search latency on a folder");
+
+    construct!([
+        new,
+        search,
+        list,
+        remove,
+        info,
+        update,
+        ingest,
+        tags,
+        serve,
+        mcp,
+        editor_server,
+        doctor,
+        clean,
+        verify,
+        history,
+        env,
+        token,
+        snapshot,
+        hook,
+        bench
+    ])
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use engine::options::Stemming;
 
     #[test]
     fn test_new_command_parsing() {
@@ -107,10 +494,278 @@ mod tests {
         match result.unwrap() {
             BeetleCommand::New {
                 index_name,
-                path_to_be_indexed: repo_path,
+                source,
+                only_extensions,
+                max_file_size,
+                content_storage,
+                tokenizer,
+                profile,
+                analyzer,
+                boosts,
+                merge_policy,
+                encrypt,
+                utf8_only,
+                dedup,
+                git_blame,
+                tracked_only,
+                hidden,
+                no_global_gitignore,
+                no_git_exclude,
+                submodules,
+                branches,
+                commits,
+                files,
+                slug,
+                writer_resources,
+                tags,
+                dry_run,
+                format,
             } => {
                 assert_eq!(index_name, "my-index");
-                assert_eq!(repo_path, PathBuf::from("/path/to/repo"));
+                assert_eq!(tokenizer, ContentTokenizer::Code);
+                assert_eq!(profile, SchemaProfile::Code);
+                assert!(boosts.is_default());
+                assert!(merge_policy.is_default());
+                assert!(analyzer.is_default());
+                assert_eq!(writer_resources, WriterResources::default());
+                assert_eq!(source, IndexSource::Path(PathBuf::from("/path/to/repo")));
+                assert!(only_extensions.is_empty());
+                assert_eq!(max_file_size, None);
+                assert_eq!(content_storage, ContentStorage::Lz4);
+                assert!(!encrypt);
+                assert!(!utf8_only);
+                assert!(!dedup);
+                assert!(!git_blame);
+                assert!(!tracked_only);
+                assert!(!hidden);
+                assert!(!no_global_gitignore);
+                assert!(!no_git_exclude);
+                assert_eq!(submodules, SubmodulePolicy::Index);
+                assert!(branches.is_empty());
+                assert!(!commits);
+                assert!(files.is_empty());
+                assert!(!slug);
+                assert!(tags.is_empty());
+                assert!(!dry_run);
+                assert_eq!(format, OutputFormat::Text);
+            }
+            _ => panic!("Expected Create command"),
+        }
+
+        let args = Args::from(&[
+            "new",
+            "-i",
+            "my-index",
+            "-p",
+            "/repo",
+            "--only-ext",
+            "rs,toml,md",
+            "--max-file-size",
+            "10MB",
+            "--content-storage",
+            "zstd",
+            "--tokenizer",
+            "ngram:2-4",
+            "--profile",
+            "logs",
+            "--no-lowercase",
+            "--stemming",
+            "french",
+            "--stop-words",
+            "le, la,les",
+            "--boost",
+            "filename=5,content=0.5",
+            "--merge-policy",
+            "min-segments=4,deletes-ratio=0.2",
+            "--encrypt",
+            "--utf8-only",
+            "--dedup",
+            "--git-blame",
+            "--tracked-only",
+            "--hidden",
+            "--no-global-gitignore",
+            "--no-git-exclude",
+            "--submodules",
+            "separate",
+            "--slug",
+            "--memory-budget",
+            "1.5GB",
+            "--index-threads",
+            "4",
+            "--batch-size",
+            "500",
+            "--commit-every",
+            "20",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::New {
+                only_extensions,
+                max_file_size,
+                content_storage,
+                tokenizer,
+                profile,
+                analyzer,
+                boosts,
+                merge_policy,
+                encrypt,
+                utf8_only,
+                dedup,
+                git_blame,
+                tracked_only,
+                hidden,
+                no_global_gitignore,
+                no_git_exclude,
+                submodules,
+                slug,
+                writer_resources,
+                ..
+            } => {
+                assert_eq!(only_extensions, vec!["rs", "toml", "md"]);
+                assert_eq!(max_file_size, Some(10 * 1024 * 1024));
+                assert_eq!(content_storage, ContentStorage::Zstd);
+                assert_eq!(
+                    tokenizer,
+                    ContentTokenizer::Ngram {
+                        min_gram: 2,
+                        max_gram: 4
+                    }
+                );
+                assert_eq!(profile, SchemaProfile::Logs);
+                assert_eq!(
+                    analyzer,
+                    AnalyzerOptions {
+                        lowercase: Some(false),
+                        stemming: Some(Stemming::French),
+                        stop_words: vec!["le".to_string(), "la".to_string(), "les".to_string()],
+                    }
+                );
+                assert_eq!(boosts.to_string(), "filename=5,content=0.5");
+                assert_eq!(merge_policy.to_string(), "min-segments=4,deletes-ratio=0.2");
+                assert!(encrypt);
+                assert!(utf8_only);
+                assert!(dedup);
+                assert!(git_blame);
+                assert!(tracked_only);
+                assert!(hidden);
+                assert!(no_global_gitignore);
+                assert!(no_git_exclude);
+                assert_eq!(submodules, SubmodulePolicy::Separate);
+                assert!(slug);
+                assert_eq!(
+                    writer_resources,
+                    WriterResources {
+                        memory_budget: Some(1536 * 1024 * 1024),
+                        index_threads: Some(4),
+                        batch_size: Some(500),
+                        commit_every: Some(20),
+                    }
+                );
+            }
+            _ => panic!("Expected Create command"),
+        }
+
+        let args = Args::from(&["new", "-i", "x", "-p", "/repo", "--content-storage", "gzip"]);
+        assert!(parser.run_inner(args).is_err());
+        let args = Args::from(&["new", "-i", "x", "-p", "/repo", "--tokenizer", "ngram:0"]);
+        assert!(parser.run_inner(args).is_err());
+        let args = Args::from(&["new", "-i", "x", "-p", "/repo", "--profile", "prose"]);
+        assert!(parser.run_inner(args).is_err());
+        let args = Args::from(&["new", "-i", "x", "-p", "/repo", "--boost", "title=2"]);
+        assert!(parser.run_inner(args).is_err());
+        let args = Args::from(&["new", "-i", "x", "-p", "/repo", "--stemming", "klingon"]);
+        assert!(parser.run_inner(args).is_err());
+
+        let args = Args::from(&[
+            "new",
+            "-i",
+            "my-index",
+            "-p",
+            "/repo",
+            "--branch",
+            "main",
+            "--branch",
+            "release/2.x",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::New { branches, .. } => {
+                assert_eq!(branches, vec!["main", "release/2.x"])
+            }
+            _ => panic!("Expected Create command"),
+        }
+
+        let args = Args::from(&[
+            "new",
+            "-i",
+            "my-index",
+            "--git-url",
+            "https://github.com/example/repo.git",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::New { source, .. } => assert_eq!(
+                source,
+                IndexSource::GitUrl("https://github.com/example/repo.git".to_string())
+            ),
+            _ => panic!("Expected Create command"),
+        }
+        // Either a path or a URL
+        let args = Args::from(&[
+            "new",
+            "-i",
+            "my-index",
+            "-p",
+            "/repo",
+            "--git-url",
+            "git@host:repo.git",
+        ]);
+        assert!(parser.run_inner(args).is_err());
+
+        let args = Args::from(&["new", "-i", "my-index", "-p", "/repo", "--commits"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::New { commits, .. } => assert!(commits),
+            _ => panic!("Expected Create command"),
+        }
+
+        let args = Args::from(&[
+            "new", "-i", "my-index", "-p", "/repo", "--tag", "backend", "--tag", "rust",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::New { tags, .. } => assert_eq!(tags, vec!["backend", "rust"]),
+            _ => panic!("Expected Create command"),
+        }
+
+        // Test file lists, NUL-separated or one per line
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("files");
+        let list_path = list.to_string_lossy().to_string();
+        let list_args = ["new", "-i", "x", "-p", "/repo", "--files-from", &list_path];
+        for content in ["src/main.rs\0README.md\0", "src/main.rs\r\nREADME.md\n\n"] {
+            std::fs::write(&list, content).unwrap();
+            match parser.run_inner(Args::from(&list_args[..])).unwrap() {
+                BeetleCommand::New { files, .. } => {
+                    assert_eq!(files, vec!["src/main.rs", "README.md"])
+                }
+                _ => panic!("Expected Create command"),
+            }
+        }
+        std::fs::write(&list, "\n").unwrap();
+        assert!(parser.run_inner(Args::from(&list_args[..])).is_err());
+
+        let args = Args::from(&[
+            "new",
+            "-i",
+            "x",
+            "-p",
+            "/repo",
+            "--dry-run",
+            "--format",
+            "json",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::New {
+                dry_run, format, ..
+            } => {
+                assert!(dry_run);
+                assert_eq!(format, OutputFormat::Json);
             }
             _ => panic!("Expected Create command"),
         }
@@ -132,13 +787,59 @@ mod tests {
 
         match result.unwrap() {
             BeetleCommand::Search {
-                index_name,
+                scope,
                 query,
+                symbols,
+                exact,
+                commits,
                 format: formatter,
+                color,
+                max_query_cost,
+                force,
+                highlight_tag,
+                highlight_class,
+                snippet_chars,
+                max_fragments,
+                languages,
+                within,
+                excluded_extensions,
+                excluded_paths,
+                author,
+                branches,
+                changed_since,
+                modified_after,
+                modified_before,
+                boosts,
+                facets,
+                stats,
+                no_history,
             } => {
-                assert_eq!(index_name, "my-index");
+                assert_eq!(scope, SearchScope::Index("my-index".to_string()));
+                assert!(!no_history);
+                assert_eq!(snippet_chars, engine::search::DEFAULT_SNIPPET_CHARS);
+                assert_eq!(max_fragments, 1);
+                assert!(boosts.is_default());
+                assert!(!symbols);
+                assert!(!exact);
+                assert!(!commits);
+                assert!(languages.is_empty());
+                assert_eq!(within, None);
+                assert!(excluded_extensions.is_empty());
+                assert!(excluded_paths.is_empty());
+                assert_eq!(author, None);
+                assert!(branches.is_empty());
+                assert_eq!(changed_since, None);
+                assert_eq!(modified_after, None);
+                assert_eq!(modified_before, None);
+                assert!(!facets);
+                assert!(!stats);
+                assert_eq!(highlight_tag, "b");
+                assert_eq!(highlight_class, None);
                 assert_eq!(query, "main function");
                 matches!(formatter, OutputFormat::Text);
+                assert_eq!(color, ColorChoice::Auto);
+                assert_eq!(max_query_cost, engine::search::DEFAULT_MAX_QUERY_COST);
+                assert!(!force);
             }
             _ => panic!("Expected Query command"),
         }
@@ -158,6 +859,381 @@ mod tests {
             }
             _ => panic!("Expected Query command"),
         }
+
+        // Test query with CSV and NDJSON formats
+        let args = Args::from(&["search", "-i", "test-idx", "-q", "TODO", "--format", "csv"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { format, .. } => assert_eq!(format, OutputFormat::Csv),
+            _ => panic!("Expected Query command"),
+        }
+
+        let args = Args::from(&[
+            "search", "-i", "test-idx", "-q", "TODO", "--format", "ndjson",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { format, .. } => assert_eq!(format, OutputFormat::Ndjson),
+            _ => panic!("Expected Query command"),
+        }
+
+        // Test snippet sizes
+        let args = Args::from(&[
+            "search",
+            "-i",
+            "test-idx",
+            "-q",
+            "TODO",
+            "--snippet-chars",
+            "400",
+            "--max-fragments",
+            "3",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search {
+                snippet_chars,
+                max_fragments,
+                ..
+            } => {
+                assert_eq!(snippet_chars, 400);
+                assert_eq!(max_fragments, 3);
+            }
+            _ => panic!("Expected Query command"),
+        }
+        let args = Args::from(&[
+            "search",
+            "-i",
+            "test-idx",
+            "-q",
+            "TODO",
+            "--max-fragments",
+            "0",
+        ]);
+        assert!(parser.run_inner(args).is_err());
+        let args = Args::from(&[
+            "search",
+            "-i",
+            "test-idx",
+            "-q",
+            "TODO",
+            "--max-fragments",
+            "all",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { max_fragments, .. } => {
+                assert_eq!(max_fragments, engine::search::ALL_FRAGMENTS)
+            }
+            _ => panic!("Expected Query command"),
+        }
+
+        // Test the directory searched in
+        let args = Args::from(&[
+            "search",
+            "-i",
+            "monorepo",
+            "-q",
+            "TODO",
+            "--in",
+            "services/api",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { within, .. } => {
+                assert_eq!(within, Some("services/api".to_string()))
+            }
+            _ => panic!("Expected Query command"),
+        }
+
+        // Test negative filters
+        let args = Args::from(&[
+            "search",
+            "-i",
+            "monorepo",
+            "-q",
+            "TODO",
+            "--not-ext",
+            "json",
+            "--not-ext",
+            ".lock",
+            "--exclude-path",
+            "vendor",
+            "--exclude-path",
+            "tests/fixtures",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search {
+                excluded_extensions,
+                excluded_paths,
+                ..
+            } => {
+                assert_eq!(excluded_extensions, vec!["json", ".lock"]);
+                assert_eq!(excluded_paths, vec!["vendor", "tests/fixtures"]);
+            }
+            _ => panic!("Expected Query command"),
+        }
+
+        // Test the author filter
+        let args = Args::from(&[
+            "search",
+            "-i",
+            "monorepo",
+            "-q",
+            "TODO",
+            "--author",
+            "Alice Smith",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { author, .. } => {
+                assert_eq!(author, Some("Alice Smith".to_string()))
+            }
+            _ => panic!("Expected Query command"),
+        }
+
+        // Test the filter of files changed since a revision
+        let args = Args::from(&[
+            "search",
+            "-i",
+            "monorepo",
+            "-q",
+            "TODO",
+            "--changed-since",
+            "v1.2.0",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { changed_since, .. } => {
+                assert_eq!(changed_since, Some("v1.2.0".to_string()))
+            }
+            _ => panic!("Expected Query command"),
+        }
+
+        // Test the branch filter
+        let args = Args::from(&[
+            "search", "-i", "monorepo", "-q", "TODO", "--branch", "main", "--branch", "dev",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { branches, .. } => assert_eq!(branches, vec!["main", "dev"]),
+            _ => panic!("Expected Query command"),
+        }
+
+        // Test color choices
+        let args = Args::from(&["search", "-i", "test-idx", "-q", "TODO", "--color", "never"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { color, .. } => assert_eq!(color, ColorChoice::Never),
+            _ => panic!("Expected Query command"),
+        }
+        let args = Args::from(&[
+            "search", "-i", "test-idx", "-q", "TODO", "--color", "rainbow",
+        ]);
+        assert!(parser.run_inner(args).is_err());
+
+        // Test query cost overrides
+        let args = Args::from(&[
+            "search",
+            "--index",
+            "test-idx",
+            "--query",
+            "\"a\"*",
+            "--max-query-cost",
+            "10",
+            "--force",
+        ]);
+        let result = parser.run_inner(args);
+        assert!(result.is_ok());
+
+        match result.unwrap() {
+            BeetleCommand::Search {
+                max_query_cost,
+                force,
+                ..
+            } => {
+                assert_eq!(max_query_cost, 10);
+                assert!(force);
+            }
+            _ => panic!("Expected Query command"),
+        }
+
+        // Test language filters, which accept extensions and are checked while parsing
+        let args = Args::from(&[
+            "search", "-i", "test-idx", "-q", "TODO", "--lang", "Rust", "--lang", "py", "--facets",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search {
+                languages, facets, ..
+            } => {
+                assert_eq!(languages, vec!["rust", "python"]);
+                assert!(facets);
+            }
+            _ => panic!("Expected Query command"),
+        }
+
+        let args = Args::from(&[
+            "search", "-i", "test-idx", "-q", "TODO", "--lang", "klingon",
+        ]);
+        assert!(parser.run_inner(args).is_err());
+
+        // Test modification time filters, which are checked while parsing
+        let args = Args::from(&[
+            "search",
+            "-i",
+            "test-idx",
+            "-q",
+            "TODO",
+            "--modified-after",
+            "2024-05-01",
+            "--modified-before",
+            "2024-05-01T12:00:00Z",
+            "--boost",
+            "path=4",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search {
+                modified_after,
+                modified_before,
+                boosts,
+                ..
+            } => {
+                assert_eq!(modified_after, Some(1714521600));
+                assert_eq!(modified_before, Some(1714564800));
+                assert_eq!(boosts.path, Some(4.0));
+            }
+            _ => panic!("Expected Query command"),
+        }
+
+        let args = Args::from(&[
+            "search",
+            "-i",
+            "test-idx",
+            "-q",
+            "TODO",
+            "--modified-after",
+            "last week",
+        ]);
+        assert!(parser.run_inner(args).is_err());
+
+        // Test symbol searches, which replace the query
+        let args = Args::from(&["search", "-i", "test-idx", "--symbols", "parseHttp"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { query, symbols, .. } => {
+                assert_eq!(query, "parseHttp");
+                assert!(symbols);
+            }
+            _ => panic!("Expected Query command"),
+        }
+
+        let args = Args::from(&[
+            "search",
+            "-i",
+            "test-idx",
+            "-q",
+            "TODO",
+            "--symbols",
+            "parseHttp",
+        ]);
+        assert!(parser.run_inner(args).is_err());
+
+        // Test exact searches, which replace the query too
+        let args = Args::from(&["search", "-i", "test-idx", "--exact", "Result<Vec<u8>, E>"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search {
+                query,
+                symbols,
+                exact,
+                ..
+            } => {
+                assert_eq!(query, "Result<Vec<u8>, E>");
+                assert!(exact);
+                assert!(!symbols);
+            }
+            _ => panic!("Expected Query command"),
+        }
+
+        // Test commit searches, which replace the query too
+        let args = Args::from(&["search", "-i", "test-idx", "--commits", "fix deadlock"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { query, commits, .. } => {
+                assert_eq!(query, "fix deadlock");
+                assert!(commits);
+            }
+            _ => panic!("Expected Query command"),
+        }
+
+        // Test reading the query from a file, without its last line break
+        let dir = tempfile::tempdir().unwrap();
+        let query_file = dir.path().join("query.txt");
+        std::fs::write(&query_file, "\"it's \\\"quoted\\\"\"\nfn\n").unwrap();
+        let query_path = query_file.to_string_lossy().to_string();
+        let query_args = ["search", "-i", "test-idx", "--query-file", &query_path];
+        let args = Args::from(&query_args[..]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { query, .. } => {
+                assert_eq!(query, "\"it's \\\"quoted\\\"\"\nfn")
+            }
+            _ => panic!("Expected Query command"),
+        }
+        std::fs::write(&query_file, "\n").unwrap();
+        let args = Args::from(&query_args[..]);
+        assert!(parser.run_inner(args).is_err());
+        let args = Args::from(&["search", "-i", "test-idx", "--query-file", "missing.txt"]);
+        assert!(parser.run_inner(args).is_err());
+
+        // Test searching the current directory
+        let args = Args::from(&["search", "--here", "-q", "TODO"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { scope, .. } => assert_eq!(scope, SearchScope::Here),
+            _ => panic!("Expected Query command"),
+        }
+
+        // Test searching several indexes, or all of them
+        let args = Args::from(&["search", "-i", "a", "--index", "b", "-q", "TODO"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { scope, .. } => assert_eq!(
+                scope,
+                SearchScope::Indexes(vec!["a".to_string(), "b".to_string()])
+            ),
+            _ => panic!("Expected Query command"),
+        }
+
+        let args = Args::from(&["search", "--all", "-q", "TODO"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { scope, .. } => assert_eq!(scope, SearchScope::All),
+            _ => panic!("Expected Query command"),
+        }
+
+        let args = Args::from(&["search", "--tag", "backend", "-q", "TODO"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { scope, .. } => {
+                assert_eq!(scope, SearchScope::Tagged(vec!["backend".to_string()]))
+            }
+            _ => panic!("Expected Query command"),
+        }
+
+        // --here and --index are mutually exclusive
+        let args = Args::from(&["search", "--here", "-i", "test-idx", "-q", "TODO"]);
+        assert!(parser.run_inner(args).is_err());
+    }
+
+    #[test]
+    fn test_logging_options_parsing() {
+        let parser = beetle_cli();
+
+        let args = Args::from(&["list"]);
+        let (logging, command) = parser.run_inner(args).unwrap();
+        assert_eq!(logging, LogOptions::default());
+        assert!(matches!(command, BeetleCommand::List { .. }));
+
+        let args = Args::from(&["-vv", "--log-file", "beetle.log", "list"]);
+        let (logging, _) = parser.run_inner(args).unwrap();
+        assert_eq!(logging.verbosity, 2);
+        assert_eq!(logging.log_file, Some(PathBuf::from("beetle.log")));
+        assert_eq!(logging.format, LogFormat::Text);
+
+        let args = Args::from(&["--log-format", "json", "serve"]);
+        assert_eq!(parser.run_inner(args).unwrap().0.format, LogFormat::Json);
+        let args = Args::from(&["--log-format", "xml", "serve"]);
+        assert!(parser.run_inner(args).is_err());
+
+        let args = Args::from(&["--quiet", "list"]);
+        assert_eq!(parser.run_inner(args).unwrap().0.verbosity, -1);
+
+        let args = Args::from(&["--quiet", "-v", "list"]);
+        assert!(parser.run_inner(args).is_err());
     }
 
     #[test]
@@ -169,9 +1245,30 @@ mod tests {
         assert!(result.is_ok());
 
         match result.unwrap() {
-            BeetleCommand::List { .. } => {}
+            BeetleCommand::List { sort, .. } => assert_eq!(sort, ListSort::Name),
+            _ => panic!("Expected List command"),
+        }
+
+        let args = Args::from(&["list", "--sort", "updated"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::List { sort, .. } => assert_eq!(sort, ListSort::Updated),
+            _ => panic!("Expected List command"),
+        }
+
+        let args = Args::from(&["list", "--filter", "api"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::List { filter, .. } => assert_eq!(filter, Some("api".to_string())),
+            _ => panic!("Expected List command"),
+        }
+
+        let args = Args::from(&["list", "--tag", "backend", "--tag", "rust"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::List { tags, .. } => assert_eq!(tags, vec!["backend", "rust"]),
             _ => panic!("Expected List command"),
         }
+
+        let args = Args::from(&["list", "--sort", "age"]);
+        assert!(parser.run_inner(args).is_err());
     }
 
     #[test]
@@ -195,6 +1292,29 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_info_command_parsing() {
+        let parser = beetle_command();
+
+        let args = Args::from(&["info", "--index", "my-index"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Info { index_name, format } => {
+                assert_eq!(index_name, "my-index");
+                assert_eq!(format, OutputFormat::Text);
+            }
+            _ => panic!("Expected Info command"),
+        }
+
+        let args = Args::from(&["info", "-i", "my-index", "--format", "json"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Info { format, .. } => assert_eq!(format, OutputFormat::Json),
+            _ => panic!("Expected Info command"),
+        }
+
+        let args = Args::from(&["info"]);
+        assert!(parser.run_inner(args).is_err());
+    }
+
     #[test]
     fn test_update_command_parsing() {
         let parser = beetle_command();
@@ -208,9 +1328,19 @@ mod tests {
             BeetleCommand::Update {
                 index_name,
                 reindex,
+                wait,
+                writer_resources,
+                change_detection,
+                dry_run,
+                format,
             } => {
                 assert_eq!(index_name, "my-index");
                 assert!(!reindex);
+                assert!(!wait);
+                assert!(!dry_run);
+                assert_eq!(format, OutputFormat::Text);
+                assert_eq!(writer_resources, WriterResources::default());
+                assert_eq!(change_detection, ChangeDetection::Auto);
             }
             _ => panic!("Expected Update command"),
         }
@@ -224,6 +1354,7 @@ mod tests {
             BeetleCommand::Update {
                 index_name,
                 reindex,
+                ..
             } => {
                 assert_eq!(index_name, "my-index");
                 assert!(reindex);
@@ -232,18 +1363,609 @@ mod tests {
         }
 
         // Test both flags
-        let args = Args::from(&["update", "--index", "my-index", "--reindex"]);
+        let args = Args::from(&["update", "--index", "my-index", "--reindex", "--wait"]);
         let result = parser.run_inner(args);
         assert!(result.is_ok());
 
         match result.unwrap() {
-            BeetleCommand::Update { reindex, .. } => {
+            BeetleCommand::Update { reindex, wait, .. } => {
                 assert!(reindex);
+                assert!(wait);
+            }
+            _ => panic!("Expected Update command"),
+        }
+
+        let args = Args::from(&["update", "-i", "my-index", "--memory-budget", "256m"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Update {
+                writer_resources, ..
+            } => assert_eq!(writer_resources.memory_budget, Some(256 * 1024 * 1024)),
+            _ => panic!("Expected Update command"),
+        }
+
+        let args = Args::from(&["update", "-i", "my-index", "--memory-budget", "lots"]);
+        assert!(parser.run_inner(args).is_err());
+
+        let args = Args::from(&["update", "-i", "my-index", "--change-detection", "hash"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Update {
+                change_detection, ..
+            } => assert_eq!(change_detection, ChangeDetection::Hash),
+            _ => panic!("Expected Update command"),
+        }
+
+        let args = Args::from(&["update", "-i", "my-index", "--change-detection", "mtime"]);
+        assert!(parser.run_inner(args).is_err());
+
+        let args = Args::from(&["update", "-i", "my-index", "--dry-run", "--format", "csv"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Update {
+                dry_run, format, ..
+            } => {
+                assert!(dry_run);
+                assert_eq!(format, OutputFormat::Csv);
             }
             _ => panic!("Expected Update command"),
         }
     }
 
+    #[test]
+    fn test_ingest_command_parsing() {
+        let parser = beetle_command();
+
+        let args = Args::from(&[
+            "ingest",
+            "--index",
+            "generated",
+            "--path-prefix",
+            "virtual/",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Ingest {
+                index_name,
+                path_prefix,
+                ..
+            } => {
+                assert_eq!(index_name, "generated");
+                assert_eq!(path_prefix, "virtual/");
+            }
+            _ => panic!("Expected Ingest command"),
+        }
+
+        let args = Args::from(&["ingest", "-i", "generated"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Ingest { path_prefix, .. } => assert_eq!(path_prefix, ""),
+            _ => panic!("Expected Ingest command"),
+        }
+    }
+
+    #[test]
+    fn test_tags_command_parsing() {
+        let parser = beetle_command();
+
+        let args = Args::from(&["tags", "--index", "my-index", "-o", "/tmp/tags"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Tags {
+                index_name,
+                output,
+                etags,
+            } => {
+                assert_eq!(index_name, "my-index");
+                assert_eq!(output, Some(PathBuf::from("/tmp/tags")));
+                assert!(!etags);
+            }
+            _ => panic!("Expected Tags command"),
+        }
+
+        let args = Args::from(&["tags", "-i", "my-index", "--etags"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Tags { output, etags, .. } => {
+                assert_eq!(output, None);
+                assert!(etags);
+            }
+            _ => panic!("Expected Tags command"),
+        }
+    }
+
+    #[test]
+    fn test_serve_command_parsing() {
+        let parser = beetle_command();
+
+        let args = Args::from(&["serve"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Serve {
+                port,
+                host,
+                require_token,
+                token,
+                min_update_interval,
+                read_only,
+                auto_update,
+                ..
+            } => {
+                // Unset, for the config file to fill in
+                assert_eq!(port, None);
+                assert_eq!(host, None);
+                assert!(!require_token);
+                assert_eq!(token, None);
+                assert_eq!(min_update_interval, 5);
+                assert!(!read_only);
+                assert_eq!(auto_update, None);
+            }
+            _ => panic!("Expected Serve command"),
+        }
+
+        let args = Args::from(&[
+            "serve",
+            "--cors-origin",
+            "https://wiki.example.com",
+            "--cors-origin",
+            "vscode-webview://abc",
+            "--cors-method",
+            "GET",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Serve {
+                cors_origins,
+                cors_methods,
+                ..
+            } => {
+                assert_eq!(
+                    cors_origins,
+                    vec!["https://wiki.example.com", "vscode-webview://abc"]
+                );
+                assert_eq!(cors_methods, vec!["GET"]);
+            }
+            _ => panic!("Expected Serve command"),
+        }
+
+        let args = Args::from(&["serve", "--token", "s3cret"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Serve { token, .. } => assert_eq!(token.as_deref(), Some("s3cret")),
+            _ => panic!("Expected Serve command"),
+        }
+
+        let args = Args::from(&["serve", "--min-update-interval", "60"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Serve {
+                min_update_interval,
+                ..
+            } => assert_eq!(min_update_interval, 60),
+            _ => panic!("Expected Serve command"),
+        }
+
+        let args = Args::from(&[
+            "serve",
+            "--port",
+            "8080",
+            "--host",
+            "0.0.0.0",
+            "--read-only",
+            "--auto-update",
+            "15m",
+            "--auto-update-index",
+            "api",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Serve {
+                port,
+                host,
+                read_only,
+                auto_update,
+                auto_update_indexes,
+                ..
+            } => {
+                assert_eq!(port, Some(8080));
+                assert_eq!(host.as_deref(), Some("0.0.0.0"));
+                assert!(read_only);
+                assert_eq!(auto_update, Some(Duration::from_secs(15 * 60)));
+                assert_eq!(auto_update_indexes, vec!["api"]);
+            }
+            _ => panic!("Expected Serve command"),
+        }
+        assert!(parser
+            .run_inner(Args::from(&["serve", "--auto-update", "soon"]))
+            .is_err());
+
+        let args = Args::from(&[
+            "serve",
+            "--oidc-issuer",
+            "https://sso.example.com",
+            "--oidc-audience",
+            "beetle",
+            "--oidc-indexes-claim",
+            "groups",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Serve {
+                oidc: Some(oidc), ..
+            } => {
+                assert_eq!(oidc.issuer, "https://sso.example.com");
+                assert_eq!(oidc.audience, "beetle");
+                assert_eq!(oidc.jwks_url, None);
+                assert_eq!(oidc.indexes_claim.as_deref(), Some("groups"));
+            }
+            _ => panic!("Expected Serve command with OIDC settings"),
+        }
+
+        let args = Args::from(&["serve", "--oidc-issuer", "https://sso.example.com"]);
+        assert!(parser.run_inner(args).is_err());
+
+        let args = Args::from(&["serve", "--rate-limit", "120"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Serve { rate_limit, .. } => assert_eq!(
+                rate_limit,
+                Some(RateLimit {
+                    requests_per_minute: 120,
+                    burst: 10
+                })
+            ),
+            _ => panic!("Expected Serve command"),
+        }
+
+        let args = Args::from(&["serve", "--rate-limit-burst", "20"]);
+        assert!(parser.run_inner(args).is_err());
+
+        let args = Args::from(&["serve", "--daemon", "--port", "3001"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Serve {
+                daemon, control, ..
+            } => {
+                assert!(daemon);
+                assert_eq!(control, None);
+            }
+            _ => panic!("Expected Serve command"),
+        }
+
+        let args = Args::from(&[
+            "serve",
+            "--max-concurrent-index-jobs",
+            "2",
+            "--reader-threads",
+            "4",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Serve {
+                max_index_jobs,
+                reader_threads,
+                ..
+            } => {
+                assert_eq!(max_index_jobs, Some(2));
+                assert_eq!(reader_threads, Some(4));
+            }
+            _ => panic!("Expected Serve command"),
+        }
+
+        let args = Args::from(&["serve", "--stop"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Serve { control, .. } => assert_eq!(control, Some(ServeControl::Stop)),
+            _ => panic!("Expected Serve command"),
+        }
+
+        let args = Args::from(&["serve", "--status", "--stop"]);
+        assert!(parser.run_inner(args).is_err());
+    }
+
+    #[test]
+    fn test_mcp_command_parsing() {
+        let parser = beetle_command();
+
+        let args = Args::from(&["mcp"]);
+        assert!(matches!(parser.run_inner(args), Ok(BeetleCommand::Mcp)));
+
+        let args = Args::from(&["editor-server"]);
+        assert!(matches!(
+            parser.run_inner(args),
+            Ok(BeetleCommand::EditorServer)
+        ));
+    }
+
+    #[test]
+    fn test_doctor_command_parsing() {
+        let parser = beetle_command();
+
+        let args = Args::from(&["doctor"]);
+        let result = parser.run_inner(args);
+        assert!(result.is_ok());
+
+        match result.unwrap() {
+            BeetleCommand::Doctor { fix, .. } => {
+                assert!(!fix);
+            }
+            _ => panic!("Expected Doctor command"),
+        }
+
+        let args = Args::from(&["doctor", "--fix"]);
+        let result = parser.run_inner(args);
+        assert!(result.is_ok());
+
+        match result.unwrap() {
+            BeetleCommand::Doctor { fix, .. } => {
+                assert!(fix);
+            }
+            _ => panic!("Expected Doctor command"),
+        }
+    }
+
+    #[test]
+    fn test_clean_command_parsing() {
+        let parser = beetle_command();
+
+        let args = Args::from(&["clean"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Clean { yes, format } => {
+                assert!(!yes);
+                assert_eq!(format, OutputFormat::Text);
+            }
+            _ => panic!("Expected Clean command"),
+        }
+
+        let args = Args::from(&["clean", "-y", "--format", "json"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Clean { yes, format } => {
+                assert!(yes);
+                assert_eq!(format, OutputFormat::Json);
+            }
+            _ => panic!("Expected Clean command"),
+        }
+    }
+
+    #[test]
+    fn test_verify_command_parsing() {
+        let parser = beetle_command();
+
+        let args = Args::from(&["verify", "-i", "foo"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Verify {
+                index_name, repair, ..
+            } => {
+                assert_eq!(index_name, "foo");
+                assert!(!repair);
+            }
+            _ => panic!("Expected Verify command"),
+        }
+
+        let args = Args::from(&["verify", "--index", "foo", "--repair"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Verify { repair, .. } => assert!(repair),
+            _ => panic!("Expected Verify command"),
+        }
+
+        let args = Args::from(&["verify", "--repair"]);
+        assert!(parser.run_inner(args).is_err());
+    }
+
+    #[test]
+    fn test_history_command_parsing() {
+        let parser = beetle_command();
+
+        let args = Args::from(&["history"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::History { action, format } => {
+                assert_eq!(
+                    action,
+                    HistoryAction::List {
+                        index_name: None,
+                        limit: 20
+                    }
+                );
+                assert_eq!(format, OutputFormat::Text);
+            }
+            _ => panic!("Expected History command"),
+        }
+
+        let args = Args::from(&["history", "-i", "api", "-n", "5", "--format", "json"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::History { action, format } => {
+                assert_eq!(
+                    action,
+                    HistoryAction::List {
+                        index_name: Some("api".to_string()),
+                        limit: 5
+                    }
+                );
+                assert_eq!(format, OutputFormat::Json);
+            }
+            _ => panic!("Expected History command"),
+        }
+
+        let args = Args::from(&["history", "--run", "3"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::History { action, .. } => {
+                assert_eq!(action, HistoryAction::Run { number: 3 })
+            }
+            _ => panic!("Expected History command"),
+        }
+
+        let args = Args::from(&["history", "--clear"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::History { action, .. } => assert_eq!(action, HistoryAction::Clear),
+            _ => panic!("Expected History command"),
+        }
+
+        let args = Args::from(&["history", "--run", "1", "--clear"]);
+        assert!(parser.run_inner(args).is_err());
+
+        // Searches can opt out of being recorded
+        let args = Args::from(&["search", "-i", "api", "-q", "main", "--no-history"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Search { no_history, .. } => assert!(no_history),
+            _ => panic!("Expected Search command"),
+        }
+        let args = Args::from(&["serve", "--no-history"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Serve { no_history, .. } => assert!(no_history),
+            _ => panic!("Expected Serve command"),
+        }
+    }
+
+    #[test]
+    fn test_env_command_parsing() {
+        let parser = beetle_command();
+
+        let args = Args::from(&["env"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Env { format } => assert_eq!(format, OutputFormat::Text),
+            _ => panic!("Expected Env command"),
+        }
+
+        let args = Args::from(&["env", "--format", "json"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Env { format } => assert_eq!(format, OutputFormat::Json),
+            _ => panic!("Expected Env command"),
+        }
+    }
+
+    #[test]
+    fn test_token_command_parsing() {
+        let parser = beetle_command();
+
+        let args = Args::from(&["token", "create", "-i", "docs", "-i", "api"]);
+        let result = parser.run_inner(args);
+        assert!(result.is_ok());
+
+        match result.unwrap() {
+            BeetleCommand::Token {
+                action: TokenAction::Create { indexes },
+                ..
+            } => {
+                assert_eq!(indexes, vec!["docs", "api"]);
+            }
+            _ => panic!("Expected Token Create command"),
+        }
+
+        let args = Args::from(&["token", "--format", "json", "revoke", "--id", "abc"]);
+        let result = parser.run_inner(args);
+        assert!(result.is_ok());
+
+        match result.unwrap() {
+            BeetleCommand::Token {
+                action: TokenAction::Revoke { id },
+                format,
+            } => {
+                assert_eq!(id, "abc");
+                assert!(matches!(format, OutputFormat::Json));
+            }
+            _ => panic!("Expected Token Revoke command"),
+        }
+
+        // A token without any index scope is rejected
+        let args = Args::from(&["token", "create"]);
+        let result = parser.run_inner(args);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_hook_command_parsing() {
+        let parser = beetle_command();
+
+        let args = Args::from(&["hook", "install", "-i", "api"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Hook {
+                action: HookAction::Install { index_name },
+            } => assert_eq!(index_name, "api"),
+            _ => panic!("Expected Hook Install command"),
+        }
+
+        assert!(parser.run_inner(Args::from(&["hook", "install"])).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_command_parsing() {
+        let parser = beetle_command();
+
+        let args = Args::from(&["snapshot", "create", "-i", "docs"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Snapshot {
+                action: SnapshotAction::Create { index_name },
+                ..
+            } => assert_eq!(index_name, "docs"),
+            _ => panic!("Expected Snapshot Create command"),
+        }
+
+        let args = Args::from(&["snapshot", "--format", "json", "list"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Snapshot {
+                action: SnapshotAction::List { index_name },
+                format,
+            } => {
+                assert_eq!(index_name, None);
+                assert!(matches!(format, OutputFormat::Json));
+            }
+            _ => panic!("Expected Snapshot List command"),
+        }
+
+        let args = Args::from(&["snapshot", "restore", "-i", "docs", "--id", "1a2b3c4d"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Snapshot {
+                action: SnapshotAction::Restore { index_name, id },
+                ..
+            } => {
+                assert_eq!(index_name, "docs");
+                assert_eq!(id, "1a2b3c4d");
+            }
+            _ => panic!("Expected Snapshot Restore command"),
+        }
+
+        // Restoring needs to know which snapshot
+        let args = Args::from(&["snapshot", "restore", "-i", "docs"]);
+        assert!(parser.run_inner(args).is_err());
+    }
+
+    #[test]
+    fn test_bench_command_parsing() {
+        let parser = beetle_command();
+
+        let args = Args::from(&["bench", "-p", "/repo"]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Bench {
+                path,
+                queries,
+                iterations,
+                writer_resources,
+                format,
+            } => {
+                assert_eq!(path, PathBuf::from("/repo"));
+                assert!(queries.is_empty());
+                assert_eq!(iterations, 10);
+                assert_eq!(writer_resources, WriterResources::default());
+                assert!(matches!(format, OutputFormat::Text));
+            }
+            _ => panic!("Expected Bench command"),
+        }
+
+        let args = Args::from(&[
+            "bench",
+            "-p",
+            "/repo",
+            "-q",
+            "main",
+            "--query",
+            "fn parse",
+            "--iterations",
+            "50",
+            "--index-threads",
+            "2",
+            "--format",
+            "json",
+        ]);
+        match parser.run_inner(args).unwrap() {
+            BeetleCommand::Bench {
+                queries,
+                iterations,
+                writer_resources,
+                format,
+                ..
+            } => {
+                assert_eq!(queries, ["main", "fn parse"]);
+                assert_eq!(iterations, 50);
+                assert_eq!(writer_resources.index_threads, Some(2));
+                assert!(matches!(format, OutputFormat::Json));
+            }
+            _ => panic!("Expected Bench command"),
+        }
+    }
+
     #[test]
     fn test_invalid_commands() {
         let parser = beetle_command();
@@ -287,10 +2009,8 @@ mod tests {
         assert!(result.is_ok());
 
         match result.unwrap() {
-            BeetleCommand::Search {
-                index_name, query, ..
-            } => {
-                assert_eq!(index_name, "");
+            BeetleCommand::Search { scope, query, .. } => {
+                assert_eq!(scope, SearchScope::Index(String::new()));
                 assert_eq!(query, "");
             }
             _ => panic!("Expected Query command"),
@@ -0,0 +1,30 @@
+use super::{format, writer_resources, BeetleCommand};
+use bpaf::*;
+use std::path::PathBuf;
+
+pub fn bench_command() -> OptionParser<BeetleCommand> {
+    let path = long("path")
+        .short('p')
+        .argument::<PathBuf>("PATH")
+        .help("Path to the folder to index into a temporary in-memory index");
+
+    let queries = long("query")
+        .short('q')
+        .argument::<String>("QUERY")
+        .help("Query of the workload; repeatable, the names of some indexed files by default")
+        .many();
+
+    let iterations = long("iterations")
+        .argument::<usize>("N")
+        .help("Times each query is run, 10 by default")
+        .fallback(10);
+
+    construct!(BeetleCommand::Bench {
+        path,
+        queries,
+        iterations,
+        writer_resources(),
+        format()
+    })
+    .to_options()
+}
@@ -0,0 +1,11 @@
+use super::{format, BeetleCommand};
+use bpaf::*;
+
+pub fn clean_command() -> OptionParser<BeetleCommand> {
+    let yes = short('y')
+        .long("yes")
+        .switch()
+        .help("Remove what is found without asking");
+
+    construct!(BeetleCommand::Clean { yes, format() }).to_options()
+}
@@ -0,0 +1,10 @@
+use super::{format, BeetleCommand};
+use bpaf::*;
+
+pub fn doctor_command() -> OptionParser<BeetleCommand> {
+    let fix = long("fix")
+        .switch()
+        .help("Repair recoverable problems by recreating the affected indexes");
+
+    construct!(BeetleCommand::Doctor { fix, format() }).to_options()
+}
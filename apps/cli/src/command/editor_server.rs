@@ -0,0 +1,6 @@
+use super::BeetleCommand;
+use bpaf::*;
+
+pub fn editor_server_command() -> OptionParser<BeetleCommand> {
+    pure(BeetleCommand::EditorServer).to_options()
+}
@@ -0,0 +1,6 @@
+use super::{format, BeetleCommand};
+use bpaf::*;
+
+pub fn env_command() -> OptionParser<BeetleCommand> {
+    construct!(BeetleCommand::Env { format() }).to_options()
+}
@@ -1,16 +1,51 @@
+mod csv;
+#[cfg(test)]
+mod golden;
 mod json;
+mod ndjson;
 mod text;
 
+pub use csv::CsvFormatter;
 pub use json::JsonFormatter;
+pub use ndjson::NdjsonFormatter;
 pub use text::PlainTextFormatter;
 
-use engine::search::SearchResultItem;
-
-use engine::storage::IndexStorageMetadata;
+use crate::auth::ApiToken;
+use crate::history::NumberedEntry;
+use crate::home::HomeLocations;
+use engine::bench::BenchReport;
+use engine::doctor::{IndexIssue, OrphanedEntry};
+use engine::search::{SearchResultItem, SymbolMatch};
+use engine::snapshot::SnapshotInfo;
+use engine::storage::{RecoveredUpdate, SkipReason, SkippedFile, UpdateRecord};
+use engine::usage::ResourceUsage;
+use engine::{IndexInfo, IndexSummary, UpdatePlan};
+use serde::Serialize;
 
 pub enum CommandOutput {
     Search(Vec<SearchResultItem>),
-    List(Vec<IndexStorageMetadata>),
+    /// Definitions found by `beetle search --symbols`.
+    Symbols(Vec<SymbolMatch>),
+    List(Vec<IndexSummary>),
+    /// The details of one index, printed by `beetle info`.
+    Info(Box<IndexInfo>),
+    Doctor(Vec<IndexIssue>),
+    /// What `beetle clean` found, and removed unless it was declined.
+    Clean(Vec<OrphanedEntry>),
+    /// Problems found by `beetle verify`, which repairs them with `--repair` rather than `--fix`.
+    Verify(Vec<IndexIssue>),
+    Tokens(Vec<ApiToken>),
+    TokenCreated(ApiToken, String),
+    Snapshots(Vec<SnapshotInfo>),
+    Bench(BenchReport),
+    /// The recorded searches listed by `beetle history`, the most recent first.
+    History(Vec<NumberedEntry>),
+    /// Where beetle keeps its indexes and state, printed by `beetle env`.
+    Env(HomeLocations),
+    /// What `beetle new` or `beetle update` would index, printed by `--dry-run`.
+    Plan(UpdatePlan),
+    /// The record of an update run by `beetle update`, with the name of its index.
+    Update(String, Box<UpdateRecord>),
     Success(String),
     Error(String),
 }
@@ -18,3 +53,122 @@ pub enum CommandOutput {
 pub trait ResultFormatter {
     fn format(&self, output: CommandOutput) -> String;
 }
+
+/// A file of a dry run, with what would happen to it: `added`, `modified`, `removed`, `renamed`
+/// (from another path) or `skipped` (for a reason).
+#[derive(Serialize)]
+struct PlannedChange<'a> {
+    change: &'static str,
+    path: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<SkipReason>,
+}
+
+/// The files of `plan`, one change each, in the order of [`UpdatePlan`]'s fields.
+fn planned_changes(plan: &UpdatePlan) -> Vec<PlannedChange<'_>> {
+    let change = |change, path| PlannedChange {
+        change,
+        path,
+        from: None,
+        reason: None,
+    };
+    let mut changes: Vec<PlannedChange> = Vec::new();
+    changes.extend(plan.added.iter().map(|path| change("added", path)));
+    changes.extend(plan.modified.iter().map(|path| change("modified", path)));
+    changes.extend(plan.removed.iter().map(|path| change("removed", path)));
+    changes.extend(plan.renamed.iter().map(|rename| PlannedChange {
+        from: Some(&rename.from),
+        ..change("renamed", &rename.to)
+    }));
+    changes.extend(plan.skipped.iter().map(|skipped| PlannedChange {
+        reason: Some(skipped.reason),
+        ..change("skipped", &skipped.path)
+    }));
+
+    changes
+}
+
+/// The outcome of an update as printed by the JSON, NDJSON and CSV formatters.
+#[derive(Serialize)]
+struct UpdateOutcome<'a> {
+    index_name: &'a str,
+    files_added: usize,
+    files_modified: usize,
+    files_removed: usize,
+    files_renamed: usize,
+    files_duplicated: usize,
+    /// Every file skipped, including those not listed in `skipped`.
+    files_skipped: usize,
+    files_per_sec: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recovered: Option<&'a RecoveredUpdate>,
+    /// The first skipped files, see [`engine::storage::SkippedFiles::files`].
+    skipped: &'a [SkippedFile],
+    usage: &'a ResourceUsage,
+}
+
+fn update_outcome<'a>(index_name: &'a str, record: &'a UpdateRecord) -> UpdateOutcome<'a> {
+    UpdateOutcome {
+        index_name,
+        files_added: record.files_added,
+        files_modified: record.files_modified,
+        files_removed: record.files_removed,
+        files_renamed: record.files_renamed,
+        files_duplicated: record.files_duplicated,
+        files_skipped: record.skipped.total(),
+        files_per_sec: record.files_per_sec,
+        recovered: record.recovered.as_ref(),
+        skipped: &record.skipped.files,
+        usage: &record.usage,
+    }
+}
+
+/// The outcome of an update as printed by the text formatter, and in the error of an update whose
+/// companion indexes failed.
+pub(super) fn update_summary(index_name: &str, record: &UpdateRecord) -> String {
+    let slowest_batch = record
+        .batches
+        .iter()
+        .map(|batch| batch.duration_ms + batch.commit_ms.unwrap_or(0))
+        .max()
+        .map(|ms| format!(", slowest batch {ms}ms"))
+        .unwrap_or_default();
+    let recovered = record
+        .recovered
+        .as_ref()
+        .map(|recovered| {
+            format!(
+                "Recovered an interrupted update ({} commits kept, {} files rolled back); ",
+                recovered.commits, recovered.files_rolled_back
+            )
+        })
+        .unwrap_or_default();
+    let duplicated = match record.files_duplicated {
+        0 => String::new(),
+        files => format!(", {files} duplicates"),
+    };
+    let mut skipped = String::new();
+    if !record.skipped.is_empty() {
+        skipped = format!(
+            "\n{} files skipped ({})",
+            record.skipped.total(),
+            record.skipped
+        );
+        for file in &record.skipped.files {
+            skipped.push_str(&format!("\n  {}: {}", file.reason, file.path));
+        }
+    }
+
+    format!(
+        "{recovered}Incremental update for '{index_name}' successful: {} added, {} modified, {} \
+         removed, {} renamed{duplicated} ({} files/s{slowest_batch}, {}){skipped}",
+        record.files_added,
+        record.files_modified,
+        record.files_removed,
+        record.files_renamed,
+        record.files_per_sec,
+        record.usage
+    )
+}
@@ -0,0 +1,409 @@
+use super::*;
+
+pub struct CsvFormatter;
+
+impl ResultFormatter for CsvFormatter {
+    fn format(&self, output: CommandOutput) -> String {
+        let (header, rows): (&[&str], Vec<Vec<String>>) = match output {
+            CommandOutput::Success(message) | CommandOutput::Error(message) => {
+                (&["message"], vec![vec![message]])
+            }
+            CommandOutput::List(indexes) => (
+                &[
+                    "index_name",
+                    "index_path",
+                    "target_path",
+                    "tags",
+                    "docs",
+                    "size_bytes",
+                    "updated_at",
+                    "stale",
+                    "last_update_wall_time_ms",
+                    "last_update_cpu_time_ms",
+                    "last_update_peak_rss_bytes",
+                ],
+                indexes
+                    .into_iter()
+                    .map(|index| {
+                        let usage = index.last_update_usage;
+                        let figure = |figure: fn(ResourceUsage) -> u64| {
+                            usage.map(figure).map(|n| n.to_string()).unwrap_or_default()
+                        };
+                        vec![
+                            index.metadata.index_name,
+                            index.metadata.index_path,
+                            index.metadata.target_path,
+                            index.metadata.tags.join(","),
+                            index.docs.to_string(),
+                            index.size_bytes.to_string(),
+                            index.updated_at.to_string(),
+                            index.stale.to_string(),
+                            figure(|usage| usage.wall_time_ms),
+                            figure(|usage| usage.cpu_time_ms),
+                            figure(|usage| usage.peak_rss_bytes),
+                        ]
+                    })
+                    .collect(),
+            ),
+            CommandOutput::Info(info) => (&["name", "value"], info_entries(&info)),
+            CommandOutput::Env(locations) => (
+                &["name", "value"],
+                locations
+                    .entries()
+                    .into_iter()
+                    .map(|(name, value)| vec![name.to_string(), value])
+                    .collect(),
+            ),
+            CommandOutput::Plan(plan) => (
+                &["change", "path", "from", "reason"],
+                planned_changes(&plan)
+                    .into_iter()
+                    .map(|change| {
+                        vec![
+                            change.change.to_string(),
+                            change.path.to_string(),
+                            change.from.unwrap_or_default().to_string(),
+                            change
+                                .reason
+                                .map(|reason| reason.to_string())
+                                .unwrap_or_default(),
+                        ]
+                    })
+                    .collect(),
+            ),
+            CommandOutput::Clean(entries) => (
+                &["index_name", "path", "reason", "size_bytes", "removed"],
+                entries
+                    .into_iter()
+                    .map(|entry| {
+                        vec![
+                            entry.index_name,
+                            entry.path,
+                            entry.reason.to_string(),
+                            entry.size_bytes.to_string(),
+                            entry.removed.to_string(),
+                        ]
+                    })
+                    .collect(),
+            ),
+            CommandOutput::Doctor(issues) | CommandOutput::Verify(issues) => (
+                &["index_name", "problem", "recoverable", "fixed"],
+                issues
+                    .into_iter()
+                    .map(|issue| {
+                        vec![
+                            issue.index_name,
+                            issue.problem.to_string(),
+                            issue.problem.is_recoverable().to_string(),
+                            issue.fixed.to_string(),
+                        ]
+                    })
+                    .collect(),
+            ),
+            CommandOutput::Tokens(tokens) => (
+                &["id", "indexes", "created_at"],
+                tokens
+                    .into_iter()
+                    .map(|token| {
+                        vec![
+                            token.id,
+                            token.indexes.join(","),
+                            token.created_at.to_string(),
+                        ]
+                    })
+                    .collect(),
+            ),
+            CommandOutput::TokenCreated(token, secret) => (
+                &["id", "indexes", "created_at", "token"],
+                vec![vec![
+                    token.id,
+                    token.indexes.join(","),
+                    token.created_at.to_string(),
+                    secret,
+                ]],
+            ),
+            CommandOutput::Snapshots(snapshots) => (
+                &["id", "index_name", "created_at", "docs", "size_bytes"],
+                snapshots
+                    .into_iter()
+                    .map(|snapshot| {
+                        vec![
+                            snapshot.id,
+                            snapshot.index_name,
+                            snapshot.created_at.to_string(),
+                            snapshot.docs.to_string(),
+                            snapshot.size_bytes.to_string(),
+                        ]
+                    })
+                    .collect(),
+            ),
+            CommandOutput::History(entries) => (
+                &[
+                    "number",
+                    "time",
+                    "index",
+                    "query",
+                    "symbols",
+                    "exact",
+                    "hits",
+                    "duration_ms",
+                ],
+                entries
+                    .into_iter()
+                    .map(|numbered| {
+                        vec![
+                            numbered.number.to_string(),
+                            numbered.entry.time.to_string(),
+                            numbered.entry.index,
+                            numbered.entry.query,
+                            numbered.entry.symbols.to_string(),
+                            numbered.entry.exact.to_string(),
+                            numbered.entry.hits.to_string(),
+                            numbered.entry.duration_ms.to_string(),
+                        ]
+                    })
+                    .collect(),
+            ),
+            CommandOutput::Update(index_name, record) => {
+                let outcome = update_outcome(&index_name, &record);
+                (
+                    &[
+                        "index_name",
+                        "files_added",
+                        "files_modified",
+                        "files_removed",
+                        "files_renamed",
+                        "files_duplicated",
+                        "files_skipped",
+                        "files_per_sec",
+                        "skipped",
+                        "wall_time_ms",
+                        "cpu_time_ms",
+                        "peak_rss_bytes",
+                    ],
+                    vec![vec![
+                        outcome.index_name.to_string(),
+                        outcome.files_added.to_string(),
+                        outcome.files_modified.to_string(),
+                        outcome.files_removed.to_string(),
+                        outcome.files_renamed.to_string(),
+                        outcome.files_duplicated.to_string(),
+                        outcome.files_skipped.to_string(),
+                        outcome.files_per_sec.to_string(),
+                        serde_json::to_string(outcome.skipped).unwrap_or_default(),
+                        outcome.usage.wall_time_ms.to_string(),
+                        outcome.usage.cpu_time_ms.to_string(),
+                        outcome.usage.peak_rss_bytes.to_string(),
+                    ]],
+                )
+            }
+            CommandOutput::Bench(report) => (
+                &[
+                    "files",
+                    "bytes",
+                    "docs",
+                    "index_time_ms",
+                    "mb_per_sec",
+                    "docs_per_sec",
+                    "queries",
+                    "searches",
+                    "p50_us",
+                    "p90_us",
+                    "p99_us",
+                    "max_us",
+                ],
+                vec![vec![
+                    report.files.to_string(),
+                    report.bytes.to_string(),
+                    report.docs.to_string(),
+                    report.index_time_ms.to_string(),
+                    format!("{:.1}", report.mb_per_sec),
+                    format!("{:.0}", report.docs_per_sec),
+                    report.queries.join(","),
+                    report.searches.to_string(),
+                    report.latency.p50_us.to_string(),
+                    report.latency.p90_us.to_string(),
+                    report.latency.p99_us.to_string(),
+                    report.latency.max_us.to_string(),
+                ]],
+            ),
+            CommandOutput::Search(results) => (
+                &[
+                    "path",
+                    "extension",
+                    "score",
+                    "size",
+                    "last_modified",
+                    "snippet",
+                    "index_name",
+                    "last_author",
+                    "last_commit",
+                    "branch",
+                    "language",
+                ],
+                results
+                    .into_iter()
+                    .map(|result| {
+                        vec![
+                            result.path,
+                            result.extension,
+                            result.score.to_string(),
+                            result.size.to_string(),
+                            result.last_modified.to_string(),
+                            result.snippet,
+                            result.index_name.unwrap_or_default(),
+                            result.last_author.unwrap_or_default(),
+                            result.last_commit.unwrap_or_default(),
+                            result.branch.unwrap_or_default(),
+                            result.language.unwrap_or_default(),
+                        ]
+                    })
+                    .collect(),
+            ),
+            CommandOutput::Symbols(symbols) => (
+                &[
+                    "path",
+                    "line",
+                    "kind",
+                    "name",
+                    "score",
+                    "line_text",
+                    "index_name",
+                ],
+                symbols
+                    .into_iter()
+                    .map(|symbol| {
+                        vec![
+                            symbol.path,
+                            symbol.line.to_string(),
+                            symbol.kind.to_string(),
+                            symbol.name,
+                            symbol.score.to_string(),
+                            symbol.line_text,
+                            symbol.index_name.unwrap_or_default(),
+                        ]
+                    })
+                    .collect(),
+            ),
+        };
+
+        std::iter::once(header.iter().map(|h| h.to_string()).collect())
+            .chain(rows)
+            .map(|row: Vec<String>| {
+                row.iter()
+                    .map(|field| escape_field(field))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// Quotes a field per RFC 4180 when it contains a delimiter, quote or line break.
+fn escape_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The details of an index as `name,value` rows, with a row per field and per segment. The last
+/// update, fields and segments are JSON values.
+fn info_entries(info: &IndexInfo) -> Vec<Vec<String>> {
+    let metadata = &info.metadata;
+    let stats = &info.stats;
+    let mut entries = vec![
+        ("index_name", metadata.index_name.clone()),
+        ("index_path", metadata.index_path.clone()),
+        ("target_path", metadata.target_path.clone()),
+        ("target_path_exists", info.target_path_exists.to_string()),
+        ("tags", metadata.tags.join(",")),
+        ("format_version", metadata.format_version.to_string()),
+        (
+            "options",
+            serde_json::to_string(&metadata.options).unwrap_or_default(),
+        ),
+        ("docs", stats.docs.to_string()),
+        ("size_bytes", stats.size_bytes.to_string()),
+        ("updated_at", stats.updated_at.to_string()),
+        ("snapshot_files", info.snapshot_files.to_string()),
+        (
+            "latest_snapshot",
+            info.latest_snapshot
+                .as_ref()
+                .map(|snapshot| snapshot.id.clone())
+                .unwrap_or_default(),
+        ),
+    ];
+    if let Some(update) = &stats.last_update {
+        let value = serde_json::to_string(update).unwrap_or_default();
+        entries.push(("last_update", value));
+    }
+    if let Some(pending) = &info.pending {
+        entries.extend([
+            ("pending_added", pending.added.to_string()),
+            ("pending_modified", pending.modified.to_string()),
+            ("pending_removed", pending.removed.to_string()),
+            ("pending_renamed", pending.renamed.to_string()),
+            ("pending_skipped", pending.skipped.to_string()),
+        ]);
+    }
+    entries.extend(info.fields.iter().map(|field| {
+        let value = serde_json::to_string(field).unwrap_or_default();
+        ("field", value)
+    }));
+    entries.extend(info.segments.iter().map(|segment| {
+        let value = serde_json::to_string(segment).unwrap_or_default();
+        ("segment", value)
+    }));
+
+    entries
+        .into_iter()
+        .map(|(name, value)| vec![name.to_string(), value])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine::migration::FORMAT_VERSION;
+    use engine::storage::IndexStorageMetadata;
+
+    #[test]
+    fn test_escape_field() {
+        assert_eq!(escape_field("src/main.rs"), "src/main.rs");
+        assert_eq!(escape_field("a,b"), "\"a,b\"");
+        assert_eq!(escape_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape_field("fn main() {\n}"), "\"fn main() {\n}\"");
+    }
+
+    #[test]
+    fn test_csv_list() {
+        let output = CommandOutput::List(vec![IndexSummary {
+            metadata: IndexStorageMetadata {
+                index_name: "beetle".to_string(),
+                index_path: "/home/.beetle/indexes/beetle".to_string(),
+                target_path: "/src/beetle, fork".to_string(),
+                options: Default::default(),
+                tags: vec!["backend".to_string(), "rust".to_string()],
+                format_version: FORMAT_VERSION,
+                encryption_key_id: None,
+            },
+            docs: 42,
+            size_bytes: 2048,
+            updated_at: 1_700_000_000,
+            stale: true,
+            last_update_usage: None,
+        }]);
+
+        assert_eq!(
+            CsvFormatter.format(output),
+            "index_name,index_path,target_path,tags,docs,size_bytes,updated_at,stale,\
+             last_update_wall_time_ms,last_update_cpu_time_ms,last_update_peak_rss_bytes\n\
+             beetle,/home/.beetle/indexes/beetle,\"/src/beetle, fork\",\"backend,rust\",42,2048,\
+             1700000000,true,,,"
+        );
+    }
+}
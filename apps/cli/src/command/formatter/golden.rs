@@ -0,0 +1,554 @@
+//! Golden-file tests for the formatters.
+//!
+//! Every case below is rendered by every formatter and compared with
+//! `tests/golden/formatter/<case>.<format>`. Values that differ between runs or machines (ages,
+//! timestamps, durations, memory sizes, secrets and the checkout path) are replaced with
+//! placeholders first. After an intended output change, rewrite the files with
+//! `BEETLE_UPDATE_GOLDEN=1 cargo test -p beetle golden` and review the diff.
+
+use super::*;
+use crate::auth::ApiToken;
+use crate::history::{HistoryEntry, NumberedEntry};
+use crate::home::{BeetleHome, HomeLocations, HomeSource};
+use engine::bench::{BenchReport, SearchLatency};
+use engine::doctor::{IndexIssue, IndexProblem, OrphanReason, OrphanedEntry};
+use engine::migration::FORMAT_VERSION;
+use engine::options::IndexingOptions;
+use engine::storage::{BatchRecord, IndexStats, IndexStorageMetadata};
+use engine::symbols::SymbolKind;
+use engine::{PendingChanges, PlannedRename, SchemaField, SegmentInfo};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const UPDATE_ENV_VAR: &str = "BEETLE_UPDATE_GOLDEN";
+
+fn root() -> &'static str {
+    env!("CARGO_MANIFEST_DIR")
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+fn search_results() -> CommandOutput {
+    CommandOutput::Search(vec![
+        SearchResultItem {
+            path: format!("{}/src/main.rs", root()),
+            snippet: "fn <b>main</b>() -> CliRunResult { ... <b>main</b>_loop(args)".to_string(),
+            extension: "rs".to_string(),
+            score: 2.5,
+            size: 512,
+            last_modified: now() - 3 * 60 * 60,
+            language: Some("rust".to_string()),
+            index_name: None,
+            metadata: Default::default(),
+            duplicates: vec![format!("{}/vendor/cli/src/main.rs", root())],
+            last_author: Some("Alice Smith".to_string()),
+            last_commit: Some("3f9a2c1d8e7b6a5f4e3d2c1b0a9f8e7d6c5b4a39".to_string()),
+            branch: Some("main".to_string()),
+            snippets: vec![
+                "fn <b>main</b>() -> CliRunResult {".to_string(),
+                "<b>main</b>_loop(args)".to_string(),
+            ],
+        },
+        SearchResultItem {
+            path: format!("{}/docs/notes, draft.md", root()),
+            snippet: "Call \"<b>main</b>\" once,\nthen exit".to_string(),
+            extension: "md".to_string(),
+            score: 1.25,
+            size: 3 * 1024 * 1024,
+            last_modified: now() - 40 * 24 * 60 * 60,
+            language: Some("markdown".to_string()),
+            index_name: Some("docs".to_string()),
+            metadata: [("title".to_string(), "Notes".to_string())].into(),
+            duplicates: Vec::new(),
+            last_author: None,
+            last_commit: None,
+            branch: None,
+            snippets: vec!["Call \"<b>main</b>\" once,\nthen exit".to_string()],
+        },
+    ])
+}
+
+fn empty_search() -> CommandOutput {
+    CommandOutput::Search(Vec::new())
+}
+
+fn symbols() -> CommandOutput {
+    CommandOutput::Symbols(vec![
+        SymbolMatch {
+            path: format!("{}/src/server.rs", root()),
+            name: "search_index".to_string(),
+            kind: SymbolKind::Function,
+            line: 540,
+            line_text: "async fn search_index(".to_string(),
+            score: 3.75,
+            index_name: None,
+        },
+        SymbolMatch {
+            path: format!("{}/src/search.rs", root()),
+            name: "SearchIndex".to_string(),
+            kind: SymbolKind::Struct,
+            line: 12,
+            line_text: "    pub struct SearchIndex, Default {".to_string(),
+            score: 1.5,
+            index_name: Some("engine".to_string()),
+        },
+    ])
+}
+
+fn list() -> CommandOutput {
+    CommandOutput::List(vec![
+        IndexSummary {
+            metadata: IndexStorageMetadata {
+                index_name: "beetle".to_string(),
+                index_path: format!("{}/.beetle/beetle", root()),
+                target_path: root().to_string(),
+                options: Default::default(),
+                tags: vec!["backend".to_string(), "rust".to_string()],
+                format_version: FORMAT_VERSION,
+                encryption_key_id: None,
+            },
+            docs: 1234,
+            size_bytes: 5 * 1024 * 1024,
+            updated_at: now() - 2 * 60 * 60,
+            stale: false,
+            last_update_usage: Some(ResourceUsage {
+                wall_time_ms: 2350,
+                cpu_time_ms: 7120,
+                peak_rss_bytes: 180 * 1024 * 1024,
+            }),
+        },
+        IndexSummary {
+            metadata: IndexStorageMetadata {
+                index_name: "webui".to_string(),
+                index_path: format!("{}/.beetle/webui", root()),
+                target_path: format!("{}/webui", root()),
+                options: Default::default(),
+                tags: Vec::new(),
+                format_version: FORMAT_VERSION,
+                encryption_key_id: None,
+            },
+            docs: 87,
+            size_bytes: 96 * 1024,
+            updated_at: now() - 12 * 24 * 60 * 60,
+            stale: true,
+            last_update_usage: None,
+        },
+    ])
+}
+
+fn info() -> CommandOutput {
+    let field = |name: &str, value_type: &str, tokenizer: Option<&str>, stored| SchemaField {
+        name: name.to_string(),
+        value_type: value_type.to_string(),
+        tokenizer: tokenizer.map(str::to_string),
+        stored,
+    };
+
+    CommandOutput::Info(Box::new(IndexInfo {
+        metadata: IndexStorageMetadata {
+            index_name: "beetle".to_string(),
+            index_path: format!("{}/.beetle/beetle", root()),
+            target_path: root().to_string(),
+            options: IndexingOptions::default().with_only_extensions(["rs", "toml"]),
+            tags: vec!["backend".to_string()],
+            format_version: FORMAT_VERSION,
+            encryption_key_id: None,
+        },
+        target_path_exists: true,
+        stats: IndexStats {
+            docs: 1234,
+            size_bytes: 5 * 1024 * 1024,
+            updated_at: now() - 2 * 60 * 60,
+            last_update: Some(UpdateRecord {
+                finished_at: (now() - 2 * 60 * 60) as u64,
+                files_added: 3,
+                files_modified: 1,
+                ..UpdateRecord::default()
+            }),
+        },
+        snapshot_files: 1234,
+        latest_snapshot: None,
+        fields: vec![
+            field("path", "str", Some("raw"), true),
+            field("content", "str", Some("code_identifiers"), true),
+            field("size", "u64", None, true),
+        ],
+        segments: vec![
+            SegmentInfo {
+                id: "6ee3a6a6d7d04b0e9fb4c8b1f1a6bd21".to_string(),
+                docs: 1200,
+                deleted_docs: 12,
+                size_bytes: 5 * 1024 * 1024 - 40 * 1024,
+            },
+            SegmentInfo {
+                id: "0c1d4e0f3b8a4d7c93d2d0b1c6a5f4e7".to_string(),
+                docs: 34,
+                deleted_docs: 0,
+                size_bytes: 40 * 1024,
+            },
+        ],
+        pending: Some(PendingChanges {
+            added: 2,
+            modified: 1,
+            skipped: 5,
+            ..PendingChanges::default()
+        }),
+    }))
+}
+
+fn doctor() -> CommandOutput {
+    let mut fixed = IndexIssue::new(
+        "beetle",
+        IndexProblem::CorruptedSnapshot("checksum mismatch".to_string()),
+    );
+    fixed.fixed = true;
+
+    CommandOutput::Doctor(vec![
+        fixed,
+        IndexIssue::new(
+            "webui",
+            IndexProblem::UnopenableIndex("missing meta.json".to_string()),
+        ),
+        IndexIssue::new(
+            "old",
+            IndexProblem::MissingTargetPath(format!("{}/old", root())),
+        ),
+    ])
+}
+
+fn doctor_clean() -> CommandOutput {
+    CommandOutput::Doctor(Vec::new())
+}
+
+fn clean() -> CommandOutput {
+    let entry = |index_name: &str, path: &str, reason, size_bytes, removed| OrphanedEntry {
+        index_name: index_name.to_string(),
+        path: format!("{}/.beetle/{path}", root()),
+        reason,
+        size_bytes,
+        removed,
+    };
+
+    CommandOutput::Clean(vec![
+        entry(
+            "beetle",
+            ".rebuilds/beetle",
+            OrphanReason::StaleRebuild,
+            3 * 1024 * 1024,
+            true,
+        ),
+        entry("old", "old", OrphanReason::MissingMetadata, 2048, true),
+        entry("webui", "webui.lock", OrphanReason::StaleLock, 0, false),
+    ])
+}
+
+fn verify() -> CommandOutput {
+    let mut fixed = IndexIssue::new("beetle", IndexProblem::UnindexedFiles(3));
+    fixed.fixed = true;
+
+    CommandOutput::Verify(vec![
+        fixed,
+        IndexIssue::new(
+            "beetle",
+            IndexProblem::DamagedSegmentFile("2f1c5a0e.store".to_string()),
+        ),
+    ])
+}
+
+fn env() -> CommandOutput {
+    let home = BeetleHome {
+        path: PathBuf::from("/home/dev/.local/share/beetle"),
+        source: HomeSource::Platform,
+        config_file: Some(PathBuf::from("/home/dev/.config/beetle/config.json")),
+        tenant: None,
+    };
+
+    CommandOutput::Env(HomeLocations {
+        remote: Some("s3://beetle-indexes/team".to_string()),
+        ..home.locations()
+    })
+}
+
+fn token() -> ApiToken {
+    ApiToken {
+        id: "1a2b3c4d".to_string(),
+        indexes: vec!["beetle".to_string(), "docs".to_string()],
+        created_at: now() as u64,
+    }
+}
+
+fn tokens() -> CommandOutput {
+    CommandOutput::Tokens(vec![token()])
+}
+
+fn token_created() -> CommandOutput {
+    let secret = format!(
+        "btl_{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+
+    CommandOutput::TokenCreated(token(), secret)
+}
+
+fn snapshots() -> CommandOutput {
+    CommandOutput::Snapshots(vec![
+        SnapshotInfo {
+            id: "5e6f7a8b".to_string(),
+            index_name: "beetle".to_string(),
+            created_at: (now() - 2 * 24 * 60 * 60) as u64,
+            docs: 1234,
+            size_bytes: 5 * 1024 * 1024 + 300 * 1024,
+        },
+        SnapshotInfo {
+            id: "9c0d1e2f".to_string(),
+            index_name: "webui".to_string(),
+            created_at: (now() - 5 * 60) as u64,
+            docs: 87,
+            size_bytes: 96 * 1024,
+        },
+    ])
+}
+
+fn history() -> CommandOutput {
+    let entry = |number, index: &str, query: &str, hits, duration_ms, age| NumberedEntry {
+        number,
+        entry: HistoryEntry {
+            time: now() - age,
+            index: index.to_string(),
+            query: query.to_string(),
+            symbols: false,
+            exact: false,
+            hits,
+            duration_ms,
+        },
+    };
+
+    let mut symbols = entry(2, "beetle,webui", "IndexCatalog", 2, 1.25, 5 * 60);
+    symbols.entry.symbols = true;
+
+    CommandOutput::History(vec![
+        entry(1, "beetle", "parse_query", 12, 3.4, 30),
+        symbols,
+        entry(3, "_all", "TODO", 0, 18.0, 3 * 60 * 60),
+    ])
+}
+
+fn bench() -> CommandOutput {
+    CommandOutput::Bench(BenchReport {
+        files: 1520,
+        bytes: 18 * 1024 * 1024 + 400 * 1024,
+        docs: 1524,
+        index_time_ms: 2350,
+        mb_per_sec: 7.83,
+        docs_per_sec: 648.5,
+        queries: vec!["main".to_string(), "parse_args".to_string()],
+        searches: 20,
+        latency: SearchLatency {
+            p50_us: 850,
+            p90_us: 1420,
+            p99_us: 3105,
+            max_us: 3105,
+        },
+    })
+}
+
+fn update() -> CommandOutput {
+    let mut record = UpdateRecord {
+        finished_at: now() as u64,
+        files_added: 3,
+        files_modified: 1,
+        files_per_sec: 850,
+        batches: vec![BatchRecord {
+            files: 4,
+            duration_ms: 120,
+            commit_ms: Some(35),
+        }],
+        usage: ResourceUsage {
+            wall_time_ms: 410,
+            cpu_time_ms: 380,
+            peak_rss_bytes: 48 * 1024 * 1024,
+        },
+        ..Default::default()
+    };
+    record
+        .skipped
+        .add(Some("assets/logo.png"), SkipReason::Binary);
+    record.skipped.add(Some("Cargo.lock"), SkipReason::Ignored);
+
+    CommandOutput::Update("beetle".to_string(), Box::new(record))
+}
+
+fn dry_run() -> CommandOutput {
+    let path = |path: &str| path.to_string();
+
+    CommandOutput::Plan(UpdatePlan {
+        added: vec![
+            path("src/command/progress.rs"),
+            path("docs/notes, draft.md"),
+        ],
+        modified: vec![path("src/main.rs")],
+        removed: vec![path("src/legacy.rs")],
+        renamed: vec![PlannedRename {
+            from: path("src/result.rs"),
+            to: path("src/exit.rs"),
+        }],
+        skipped: vec![
+            SkippedFile {
+                path: path("assets/logo.png"),
+                reason: SkipReason::Binary,
+            },
+            SkippedFile {
+                path: path("Cargo.lock"),
+                reason: SkipReason::Ignored,
+            },
+        ],
+    })
+}
+
+fn error() -> CommandOutput {
+    CommandOutput::Error(format!(
+        "Failed to open index beetle: {}/.beetle/beetle/index does not exist",
+        root()
+    ))
+}
+
+/// Builds a fresh output for each formatter, since formatting consumes it.
+type Case = (&'static str, fn() -> CommandOutput);
+
+const CASES: &[Case] = &[
+    ("search", search_results),
+    ("search_empty", empty_search),
+    ("symbols", symbols),
+    ("list", list),
+    ("info", info),
+    ("doctor", doctor),
+    ("doctor_clean", doctor_clean),
+    ("clean", clean),
+    ("verify", verify),
+    ("env", env),
+    ("tokens", tokens),
+    ("token_created", token_created),
+    ("snapshots", snapshots),
+    ("history", history),
+    ("bench", bench),
+    ("update", update),
+    ("dry_run", dry_run),
+    ("error", error),
+];
+
+fn formatters() -> Vec<(&'static str, Box<dyn ResultFormatter>)> {
+    vec![
+        ("txt", Box::new(PlainTextFormatter::new(false))),
+        ("json", Box::new(JsonFormatter::new(true))),
+        ("csv", Box::new(CsvFormatter)),
+        ("ndjson", Box::new(NdjsonFormatter)),
+    ]
+}
+
+/// Replaces the values that change between runs or machines with placeholders.
+fn canonicalize(output: &str) -> String {
+    let rules = [
+        (r"btl_[0-9a-f]{64}", "<SECRET>"),
+        (r"\b1\d{9}\b", "<TIMESTAMP>"),
+        (
+            r"\b(\d+ (minute|hour|day|month|year)s? ago|just now)\b",
+            "<AGE>",
+        ),
+        (r"\b\d+(\.\d+)?m?s\b", "<DURATION>"),
+        (r"peak RSS \d+(\.\d+)? MB", "peak RSS <SIZE>"),
+    ];
+
+    // Paths appear as-is in text and CSV, and with escaped separators in JSON
+    let json_root = serde_json::to_string(root()).unwrap();
+    let mut output = output
+        .replace(root(), "<ROOT>")
+        .replace(json_root.trim_matches('"'), "<ROOT>");
+    for (pattern, placeholder) in rules {
+        output = Regex::new(pattern)
+            .unwrap()
+            .replace_all(&output, placeholder)
+            .into_owned();
+    }
+
+    output
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(root()).join("tests/golden/formatter")
+}
+
+/// Lines that differ between `expected` and `actual`, in a unified-diff-like layout.
+fn line_diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+
+    (0..expected.len().max(actual.len()))
+        .filter(|&line| expected.get(line) != actual.get(line))
+        .map(|line| {
+            let mut hunk = format!("@@ line {}\n", line + 1);
+            if let Some(expected) = expected.get(line) {
+                hunk.push_str(&format!("-{expected}\n"));
+            }
+            if let Some(actual) = actual.get(line) {
+                hunk.push_str(&format!("+{actual}\n"));
+            }
+            hunk
+        })
+        .collect()
+}
+
+#[test]
+fn test_formatter_output_matches_golden_files() {
+    let update = std::env::var_os(UPDATE_ENV_VAR).is_some();
+    let mut failures = Vec::new();
+
+    for (extension, formatter) in formatters() {
+        for (case, output) in CASES {
+            let path = golden_dir().join(format!("{case}.{extension}"));
+            let actual = canonicalize(&formatter.format(output())) + "\n";
+
+            if update {
+                std::fs::create_dir_all(golden_dir()).unwrap();
+                std::fs::write(&path, &actual).unwrap();
+                continue;
+            }
+
+            match std::fs::read_to_string(&path) {
+                Ok(expected) if expected.replace("\r\n", "\n") == actual => {}
+                Ok(expected) => failures.push(format!(
+                    "{}:\n{}",
+                    path.display(),
+                    line_diff(&expected, &actual)
+                )),
+                Err(_) => failures.push(format!("{}: missing", path.display())),
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "Formatter output differs from the golden files; if the change is intended, rerun \
+         with {UPDATE_ENV_VAR}=1 and review the diff.\n\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn test_canonicalize() {
+    assert_eq!(
+        canonicalize(&format!(
+            "{}/src/main.rs (modified 3 hours ago, 1.5 KB) at 1792151017 \
+             (0.02s, CPU 0.00s, peak RSS 48.0 MB)",
+            root()
+        )),
+        "<ROOT>/src/main.rs (modified <AGE>, 1.5 KB) at <TIMESTAMP> \
+         (<DURATION>, CPU <DURATION>, peak RSS <SIZE>)"
+    );
+}
@@ -29,10 +29,63 @@ impl ResultFormatter for JsonFormatter {
                 "status": "success",
                 "payload": indexes
             }),
+            CommandOutput::Env(locations) => serde_json::json!({
+                "status": "success",
+                "payload": locations
+            }),
+            CommandOutput::Doctor(issues) | CommandOutput::Verify(issues) => serde_json::json!({
+                "status": "success",
+                "payload": issues
+            }),
+            CommandOutput::Tokens(tokens) => serde_json::json!({
+                "status": "success",
+                "payload": tokens
+            }),
+            CommandOutput::TokenCreated(token, secret) => serde_json::json!({
+                "status": "success",
+                "payload": {
+                    "id": token.id,
+                    "indexes": token.indexes,
+                    "created_at": token.created_at,
+                    "token": secret
+                }
+            }),
+            CommandOutput::Snapshots(snapshots) => serde_json::json!({
+                "status": "success",
+                "payload": snapshots
+            }),
+            CommandOutput::History(entries) => serde_json::json!({
+                "status": "success",
+                "payload": entries
+            }),
+            CommandOutput::Clean(entries) => serde_json::json!({
+                "status": "success",
+                "payload": entries
+            }),
+            CommandOutput::Info(info) => serde_json::json!({
+                "status": "success",
+                "payload": info
+            }),
+            CommandOutput::Plan(plan) => serde_json::json!({
+                "status": "success",
+                "payload": plan
+            }),
+            CommandOutput::Update(index_name, record) => serde_json::json!({
+                "status": "success",
+                "payload": update_outcome(&index_name, &record)
+            }),
+            CommandOutput::Bench(report) => serde_json::json!({
+                "status": "success",
+                "payload": report
+            }),
             CommandOutput::Search(results) => serde_json::json!({
                 "status": "success",
                 "payload": results
             }),
+            CommandOutput::Symbols(symbols) => serde_json::json!({
+                "status": "success",
+                "payload": symbols
+            }),
         };
 
         if self.pretty {
@@ -0,0 +1,54 @@
+use super::*;
+
+use serde::Serialize;
+
+/// Emits one JSON document per line so results can be consumed incrementally, e.g. by `jq`.
+pub struct NdjsonFormatter;
+
+impl ResultFormatter for NdjsonFormatter {
+    fn format(&self, output: CommandOutput) -> String {
+        match output {
+            CommandOutput::Success(message) => to_line(&serde_json::json!({
+                "status": "success",
+                "message": message
+            })),
+            CommandOutput::Error(message) => to_line(&serde_json::json!({
+                "status": "error",
+                "message": message
+            })),
+            CommandOutput::List(indexes) => to_lines(&indexes),
+            CommandOutput::Info(info) => to_line(&info),
+            CommandOutput::Doctor(issues) | CommandOutput::Verify(issues) => to_lines(&issues),
+            CommandOutput::Clean(entries) => to_lines(&entries),
+            CommandOutput::Tokens(tokens) => to_lines(&tokens),
+            CommandOutput::TokenCreated(token, secret) => to_line(&serde_json::json!({
+                "id": token.id,
+                "indexes": token.indexes,
+                "created_at": token.created_at,
+                "token": secret
+            })),
+            CommandOutput::Snapshots(snapshots) => to_lines(&snapshots),
+            CommandOutput::History(entries) => to_lines(&entries),
+            CommandOutput::Update(index_name, record) => {
+                to_line(&update_outcome(&index_name, &record))
+            }
+            CommandOutput::Bench(report) => to_line(&report),
+            CommandOutput::Env(locations) => to_line(&locations),
+            CommandOutput::Plan(plan) => to_lines(&planned_changes(&plan)),
+            CommandOutput::Search(results) => to_lines(&results),
+            CommandOutput::Symbols(symbols) => to_lines(&symbols),
+        }
+    }
+}
+
+fn to_line<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+fn to_lines<T: Serialize>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(to_line)
+        .collect::<Vec<String>>()
+        .join("\n")
+}
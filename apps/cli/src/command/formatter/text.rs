@@ -1,6 +1,21 @@
 use super::*;
+use engine::migration::needs_migration;
+use engine::options::IndexingOptions;
 
-pub struct PlainTextFormatter;
+/// Starts and ends the highlighting of matches in snippets printed to a terminal: bold red.
+const ANSI_HIGHLIGHT: &str = "\x1b[1;31m";
+const ANSI_RESET: &str = "\x1b[0m";
+
+pub struct PlainTextFormatter {
+    /// Whether matches are highlighted with terminal colors.
+    color: bool,
+}
+
+impl PlainTextFormatter {
+    pub fn new(color: bool) -> Self {
+        PlainTextFormatter { color }
+    }
+}
 
 impl ResultFormatter for PlainTextFormatter {
     fn format(&self, output: CommandOutput) -> String {
@@ -10,18 +25,419 @@ impl ResultFormatter for PlainTextFormatter {
             CommandOutput::List(indexes) => indexes
                 .iter()
                 .map(|index| {
+                    let metadata = &index.metadata;
+                    let line = format!(
+                        "{} {} ({} docs, {}, updated {}{})",
+                        metadata.index_name,
+                        metadata.target_path,
+                        index.docs,
+                        format_size(index.size_bytes),
+                        format_age(index.updated_at),
+                        if needs_migration(metadata) {
+                            ", needs migration"
+                        } else if index.stale {
+                            ", stale"
+                        } else {
+                            ""
+                        }
+                    );
+                    if metadata.tags.is_empty() {
+                        line
+                    } else {
+                        format!("{line} [{}]", metadata.tags.join(","))
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join("\n"),
+            CommandOutput::Info(info) => format_info(&info),
+            CommandOutput::Doctor(issues) => format_issues(&issues, "--fix"),
+            CommandOutput::Clean(entries) if entries.is_empty() => "Nothing to clean".to_string(),
+            CommandOutput::Clean(entries) => entries
+                .iter()
+                .map(|entry| {
+                    let status = match entry.removed {
+                        true => "removed",
+                        false => "run with --yes to remove",
+                    };
                     format!(
-                        "{} {} {}",
-                        index.index_name, index.index_path, index.target_path
+                        "{}: {}, {} ({status})",
+                        entry.path,
+                        entry.reason,
+                        format_size(entry.size_bytes)
                     )
                 })
                 .collect::<Vec<String>>()
                 .join("\n"),
+            CommandOutput::Verify(issues) => format_issues(&issues, "--repair"),
+            CommandOutput::Env(locations) => locations
+                .entries()
+                .into_iter()
+                .filter(|(name, _)| *name != "home_source")
+                .map(|(name, value)| match name {
+                    "home" => format!("home: {value} ({})", locations.home_source),
+                    "config_file" if value.is_empty() => "config file: none".to_string(),
+                    "config_file" if !locations.config_file_exists => {
+                        format!("config file: {value} (not found)")
+                    }
+                    name => format!("{}: {value}", name.replace('_', " ")),
+                })
+                .collect::<Vec<String>>()
+                .join("\n"),
+            CommandOutput::Plan(plan) => {
+                let summary = format!(
+                    "Dry run, nothing written: {} added, {} modified, {} removed, {} renamed, {} \
+                     skipped",
+                    plan.added.len(),
+                    plan.modified.len(),
+                    plan.removed.len(),
+                    plan.renamed.len(),
+                    plan.skipped.len()
+                );
+                let changes = planned_changes(&plan).into_iter().map(|change| {
+                    match (change.from, change.reason) {
+                        (Some(from), _) => format!("{} {from} -> {}", change.change, change.path),
+                        (_, Some(reason)) => {
+                            format!("{} {} ({reason})", change.change, change.path)
+                        }
+                        _ => format!("{} {}", change.change, change.path),
+                    }
+                });
+
+                std::iter::once(summary)
+                    .chain(changes)
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            }
+            CommandOutput::Update(index_name, record) => update_summary(&index_name, &record),
+            CommandOutput::Tokens(tokens) => tokens
+                .iter()
+                .map(|token| format!("{} {}", token.id, token.indexes.join(",")))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            CommandOutput::TokenCreated(token, secret) => format!(
+                "Token '{}' created for {}\n{}\nStore it now, it will not be shown again.",
+                token.id,
+                token.indexes.join(", "),
+                secret
+            ),
+            CommandOutput::Snapshots(snapshots) => snapshots
+                .iter()
+                .map(|snapshot| {
+                    format!(
+                        "{} {} ({} docs, {}, created {})",
+                        snapshot.id,
+                        snapshot.index_name,
+                        snapshot.docs,
+                        format_size(snapshot.size_bytes),
+                        format_age(snapshot.created_at as i64)
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n"),
+            CommandOutput::History(entries) if entries.is_empty() => {
+                "No searches recorded".to_string()
+            }
+            CommandOutput::History(entries) => entries
+                .iter()
+                .map(|numbered| {
+                    let entry = &numbered.entry;
+                    let kind = match (entry.symbols, entry.exact) {
+                        (true, _) => "--symbols ",
+                        (_, true) => "--exact ",
+                        _ => "",
+                    };
+                    format!(
+                        "{:>4} {} {kind}{} ({} results, {:.1}ms, {})",
+                        numbered.number,
+                        entry.index,
+                        entry.query,
+                        entry.hits,
+                        entry.duration_ms,
+                        format_age(entry.time)
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n"),
+            CommandOutput::Bench(report) => {
+                let ms = |us: u64| format!("{:.2}ms", us as f64 / 1000.0);
+                format!(
+                    "Indexed {} files ({}) into {} documents in {:.2}s: {:.1} MB/s, {:.0} docs/s\n\
+                     Ran {} searches of {} queries: p50 {}, p90 {}, p99 {}, max {}",
+                    report.files,
+                    format_size(report.bytes),
+                    report.docs,
+                    report.index_time_ms as f64 / 1000.0,
+                    report.mb_per_sec,
+                    report.docs_per_sec,
+                    report.searches,
+                    report.queries.len(),
+                    ms(report.latency.p50_us),
+                    ms(report.latency.p90_us),
+                    ms(report.latency.p99_us),
+                    ms(report.latency.max_us)
+                )
+            }
             CommandOutput::Search(results) => results
                 .iter()
-                .map(|result| format!("{}\n{}\n", result.path, result.snippet,))
+                .map(|result| {
+                    let index = match &result.index_name {
+                        Some(index_name) => format!("[{index_name}] "),
+                        None => String::new(),
+                    };
+                    let metadata: String = result
+                        .metadata
+                        .iter()
+                        .map(|(name, value)| format!(", {name} {value}"))
+                        .collect();
+                    let branch = match &result.branch {
+                        Some(branch) => format!(", on {branch}"),
+                        None => String::new(),
+                    };
+                    // Abbreviated like git does by default
+                    let last_commit = match (&result.last_author, &result.last_commit) {
+                        (Some(author), Some(commit)) => {
+                            format!(", by {author} in {}", &commit[..commit.len().min(7)])
+                        }
+                        _ => String::new(),
+                    };
+                    let duplicates: String = result
+                        .duplicates
+                        .iter()
+                        .map(|path| format!("also at {path}\n"))
+                        .collect();
+                    // Each fragment of the file under it, apart like the groups of grep
+                    let snippets = match result.snippets.is_empty() {
+                        true => std::slice::from_ref(&result.snippet),
+                        false => result.snippets.as_slice(),
+                    };
+                    format!(
+                        "{index}{} (modified {}, {}{branch}{last_commit}{metadata})\n{duplicates}{}\n",
+                        result.path,
+                        format_age(result.last_modified),
+                        format_size(result.size),
+                        snippets
+                            .iter()
+                            .map(|snippet| terminal_snippet(snippet, self.color))
+                            .collect::<Vec<_>>()
+                            .join("\n--\n"),
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n"),
+            CommandOutput::Symbols(symbols) => symbols
+                .iter()
+                .map(|symbol| {
+                    let index = match &symbol.index_name {
+                        Some(index_name) => format!("[{index_name}] "),
+                        None => String::new(),
+                    };
+                    format!(
+                        "{index}{}:{} {} {}\n    {}",
+                        symbol.path,
+                        symbol.line,
+                        symbol.kind,
+                        symbol.name,
+                        symbol.line_text.trim(),
+                    )
+                })
                 .collect::<Vec<String>>()
                 .join("\n"),
         }
     }
 }
+
+/// The details of an index, one `name: value` line each, then a line per field and segment.
+fn format_info(info: &IndexInfo) -> String {
+    let metadata = &info.metadata;
+    let options = &metadata.options;
+    let stats = &info.stats;
+    let mut lines = vec![
+        format!("index: {}", metadata.index_name),
+        format!(
+            "target path: {}{}",
+            metadata.target_path,
+            if info.target_path_exists {
+                ""
+            } else {
+                " (missing)"
+            }
+        ),
+        format!("index path: {}", metadata.index_path),
+    ];
+    if !metadata.tags.is_empty() {
+        lines.push(format!("tags: {}", metadata.tags.join(",")));
+    }
+    lines.push(format!(
+        "docs: {} ({}, updated {})",
+        stats.docs,
+        format_size(stats.size_bytes),
+        format_age(stats.updated_at)
+    ));
+    if let Some(update) = &stats.last_update {
+        lines.push(format!(
+            "last update: {} added, {} modified, {} removed, {} renamed ({}, {})",
+            update.files_added,
+            update.files_modified,
+            update.files_removed,
+            update.files_renamed,
+            format_age(update.finished_at as i64),
+            update.usage
+        ));
+    }
+    lines.push(format!("file snapshot: {} files", info.snapshot_files));
+    lines.push(match &info.latest_snapshot {
+        Some(snapshot) => format!(
+            "latest snapshot: {} (created {})",
+            snapshot.id,
+            format_age(snapshot.created_at as i64)
+        ),
+        None => "latest snapshot: none".to_string(),
+    });
+    lines.push(format!("profile: {}", options.profile));
+    lines.push(format!("tokenizer: {}", options.tokenizer));
+    if *options != IndexingOptions::default() {
+        let json = serde_json::to_string(options).unwrap_or_default();
+        lines.push(format!("options: {json}"));
+    }
+    lines.push(match &info.pending {
+        Some(pending) => format!(
+            "pending: {} added, {} modified, {} removed, {} renamed, {} skipped",
+            pending.added, pending.modified, pending.removed, pending.renamed, pending.skipped
+        ),
+        None => "pending: unknown, the target path is gone".to_string(),
+    });
+    lines.push(format!("fields: {}", info.fields.len()));
+    lines.extend(info.fields.iter().map(|field| {
+        let tokenizer = match &field.tokenizer {
+            Some(tokenizer) => format!(", {tokenizer}"),
+            None => String::new(),
+        };
+        let stored = if field.stored { ", stored" } else { "" };
+        format!("  {} ({}{tokenizer}{stored})", field.name, field.value_type)
+    }));
+    lines.push(format!("segments: {}", info.segments.len()));
+    lines.extend(info.segments.iter().map(|segment| {
+        format!(
+            "  {} {} docs, {} deleted, {}",
+            segment.id,
+            segment.docs,
+            segment.deleted_docs,
+            format_size(segment.size_bytes)
+        )
+    }));
+
+    lines.join("\n")
+}
+
+/// One line per issue, telling how to repair it with the `repair_flag` of the command.
+fn format_issues(issues: &[IndexIssue], repair_flag: &str) -> String {
+    if issues.is_empty() {
+        return "No problems found".to_string();
+    }
+
+    issues
+        .iter()
+        .map(|issue| {
+            let status = if issue.fixed {
+                "fixed".to_string()
+            } else if issue.problem.is_recoverable() {
+                format!("run with {repair_flag} to repair")
+            } else {
+                "manual action required".to_string()
+            };
+            format!("{}: {} ({})", issue.index_name, issue.problem, status)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// The text of a snippet as the engine marks it up in HTML, with its matches highlighted in
+/// `color` or left plain. The engine escapes the content, so every tag is highlight markup.
+fn terminal_snippet(snippet: &str, color: bool) -> String {
+    let mut text = String::with_capacity(snippet.len());
+    let mut rest = snippet;
+    while let Some(start) = rest.find(['<', '&']) {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(tag) = rest.strip_prefix('<') {
+            let end = tag.find('>').map_or(rest.len(), |end| end + 2);
+            if color {
+                text.push_str(match tag.starts_with('/') {
+                    true => ANSI_RESET,
+                    false => ANSI_HIGHLIGHT,
+                });
+            }
+            rest = &rest[end..];
+            continue;
+        }
+
+        let entity = [
+            ("&lt;", '<'),
+            ("&gt;", '>'),
+            ("&quot;", '"'),
+            ("&#x27;", '\''),
+            ("&amp;", '&'),
+        ]
+        .into_iter()
+        .find(|(entity, _)| rest.starts_with(entity));
+        let (len, c) = entity.map_or((1, '&'), |(entity, c)| (entity.len(), c));
+        text.push(c);
+        rest = &rest[len..];
+    }
+    text.push_str(rest);
+
+    text
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+fn format_age(timestamp_secs: i64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let elapsed = (now - timestamp_secs).max(0);
+
+    let (amount, unit) = match elapsed {
+        0..=59 => return "just now".to_string(),
+        60..=3599 => (elapsed / 60, "minute"),
+        3600..=86399 => (elapsed / 3600, "hour"),
+        86400..=2591999 => (elapsed / 86400, "day"),
+        2592000..=31535999 => (elapsed / 2592000, "month"),
+        _ => (elapsed / 31536000, "year"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+    format!("{amount} {unit}{plural} ago")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terminal_snippet() {
+        let snippet = "fn <b>main</b>() -&gt; &quot;a&#x27;s&quot; &amp;&amp; x &lt; y";
+        assert_eq!(
+            terminal_snippet(snippet, false),
+            "fn main() -> \"a's\" && x < y"
+        );
+        assert_eq!(
+            terminal_snippet("<mark class=\"hit\">parse</mark>_args", true),
+            format!("{ANSI_HIGHLIGHT}parse{ANSI_RESET}_args")
+        );
+    }
+}
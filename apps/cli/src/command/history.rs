@@ -0,0 +1,42 @@
+use super::{format, index_name, BeetleCommand};
+use bpaf::*;
+
+/// Searches listed by `beetle history` when `--limit` is not given.
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HistoryAction {
+    /// The most recent searches, of one index when given.
+    List {
+        index_name: Option<String>,
+        limit: usize,
+    },
+    /// Runs the search numbered by the listing again.
+    Run {
+        number: usize,
+    },
+    Clear,
+}
+
+pub fn history_command() -> OptionParser<BeetleCommand> {
+    let index_name = index_name().optional();
+    let limit = long("limit")
+        .short('n')
+        .argument::<usize>("COUNT")
+        .help("List the COUNT most recent searches; 20 by default")
+        .fallback(DEFAULT_HISTORY_LIMIT);
+    let list = construct!(HistoryAction::List { index_name, limit });
+
+    let number = long("run")
+        .argument::<usize>("NUMBER")
+        .help("Run the search listed with NUMBER again, 1 being the most recent");
+    let run = construct!(HistoryAction::Run { number });
+
+    let clear = long("clear")
+        .help("Forget every recorded search")
+        .req_flag(HistoryAction::Clear);
+
+    let action = construct!([run, clear, list]);
+
+    construct!(BeetleCommand::History { action, format() }).to_options()
+}
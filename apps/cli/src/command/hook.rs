@@ -0,0 +1,24 @@
+use super::{index_name, BeetleCommand};
+use bpaf::*;
+
+#[derive(Debug, Clone)]
+pub enum HookAction {
+    Install { index_name: String },
+}
+
+pub fn hook_command() -> OptionParser<BeetleCommand> {
+    let install = {
+        let index_name = index_name();
+        construct!(HookAction::Install { index_name })
+            .to_options()
+            .command("install")
+            .help(
+                "Install post-commit, post-merge and post-checkout hooks into the repository of \
+                 an index that update it in the background",
+            )
+    };
+
+    let action = construct!([install]);
+
+    construct!(BeetleCommand::Hook { action }).to_options()
+}
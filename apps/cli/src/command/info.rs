@@ -0,0 +1,10 @@
+use super::{format, index_name, BeetleCommand};
+use bpaf::*;
+
+pub fn info_command() -> OptionParser<BeetleCommand> {
+    construct!(BeetleCommand::Info {
+        index_name(),
+        format()
+    })
+    .to_options()
+}
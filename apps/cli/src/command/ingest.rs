@@ -0,0 +1,17 @@
+use super::{index_name, wait, BeetleCommand};
+
+use bpaf::*;
+
+pub fn ingest_command() -> OptionParser<BeetleCommand> {
+    let path_prefix = long("path-prefix")
+        .argument::<String>("PREFIX")
+        .help("Prefix prepended to the path of every ingested record")
+        .fallback(String::new());
+
+    construct!(BeetleCommand::Ingest {
+        index_name(),
+        path_prefix,
+        wait()
+    })
+    .to_options()
+}
@@ -1,9 +1,132 @@
-use super::{format, BeetleCommand};
+use super::{format, tag, BeetleCommand};
 use bpaf::*;
+use engine::storage::IndexStorageMetadata;
+use engine::IndexSummary;
+use std::cmp::Reverse;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListSort {
+    /// Natural order of index names (default)
+    Name,
+    /// Largest index first
+    Size,
+    /// Most documents first
+    Docs,
+    /// Most recently updated first
+    Updated,
+}
 
 pub fn list_command() -> OptionParser<BeetleCommand> {
+    let sort = long("sort")
+        .argument::<String>("KEY")
+        .help("Sort indexes by: name (default), size, docs or updated")
+        .parse(|s| match s.as_str() {
+            "name" => Ok(ListSort::Name),
+            "size" => Ok(ListSort::Size),
+            "docs" => Ok(ListSort::Docs),
+            "updated" => Ok(ListSort::Updated),
+            _ => Err("Invalid sort key. Use 'name', 'size', 'docs' or 'updated'"),
+        })
+        .fallback(ListSort::Name);
+
+    let tags = tag("Only list indexes with this tag; repeat to require several tags").many();
+
+    let filter = long("filter")
+        .argument::<String>("TEXT")
+        .help("Only list indexes whose name or target path contains TEXT, ignoring case")
+        .optional();
+
     construct!(BeetleCommand::List {
-        format()
+        format(),
+        sort,
+        tags,
+        filter
     })
     .to_options()
 }
+
+/// Whether the name or the target path of `index` contains `filter`, ignoring case.
+pub fn matches_filter(index: &IndexStorageMetadata, filter: &str) -> bool {
+    let filter = filter.to_lowercase();
+    index.index_name.to_lowercase().contains(&filter)
+        || index.target_path.to_lowercase().contains(&filter)
+}
+
+/// Orders naturally sorted `indexes` by `sort`; ties keep their natural order.
+pub fn sort_indexes(indexes: Vec<IndexSummary>, sort: ListSort) -> Vec<IndexSummary> {
+    let mut indexes = indexes;
+    match sort {
+        ListSort::Name => {}
+        ListSort::Size => indexes.sort_by_key(|index| Reverse(index.size_bytes)),
+        ListSort::Docs => indexes.sort_by_key(|index| Reverse(index.docs)),
+        ListSort::Updated => indexes.sort_by_key(|index| Reverse(index.updated_at)),
+    }
+
+    indexes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine::migration::FORMAT_VERSION;
+
+    fn entry(name: &str, docs: u64, size_bytes: u64, updated_at: i64) -> IndexSummary {
+        IndexSummary {
+            metadata: IndexStorageMetadata {
+                index_name: name.to_string(),
+                index_path: format!("/beetle/{name}"),
+                target_path: format!("/src/{name}"),
+                options: Default::default(),
+                tags: Vec::new(),
+                format_version: FORMAT_VERSION,
+                encryption_key_id: None,
+            },
+            docs,
+            size_bytes,
+            updated_at,
+            stale: false,
+            last_update_usage: None,
+        }
+    }
+
+    fn names(indexes: Vec<IndexSummary>) -> Vec<String> {
+        indexes
+            .into_iter()
+            .map(|index| index.metadata.index_name)
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_filter() {
+        let index = entry("Backend-API", 0, 0, 0).metadata;
+        assert!(matches_filter(&index, "api"));
+        assert!(matches_filter(&index, "src/backend"));
+        assert!(!matches_filter(&index, "web"));
+    }
+
+    #[test]
+    fn test_sort_indexes() {
+        let indexes = vec![
+            entry("idx2", 10, 300, 1),
+            entry("idx10", 30, 100, 3),
+            entry("web", 20, 300, 2),
+        ];
+
+        assert_eq!(
+            names(sort_indexes(indexes.clone(), ListSort::Name)),
+            vec!["idx2", "idx10", "web"]
+        );
+        assert_eq!(
+            names(sort_indexes(indexes.clone(), ListSort::Size)),
+            vec!["idx2", "web", "idx10"]
+        );
+        assert_eq!(
+            names(sort_indexes(indexes.clone(), ListSort::Docs)),
+            vec!["idx10", "web", "idx2"]
+        );
+        assert_eq!(
+            names(sort_indexes(indexes, ListSort::Updated)),
+            vec!["idx10", "web", "idx2"]
+        );
+    }
+}
@@ -0,0 +1,6 @@
+use super::BeetleCommand;
+use bpaf::*;
+
+pub fn mcp_command() -> OptionParser<BeetleCommand> {
+    pure(BeetleCommand::Mcp).to_options()
+}
@@ -1,17 +1,334 @@
-use super::{index_name, BeetleCommand};
+use super::option::parse_byte_size;
+use super::{format, index_name, tag, writer_resources, BeetleCommand};
 use bpaf::*;
-use std::path::PathBuf;
+use engine::options::{
+    AnalyzerOptions, ContentStorage, ContentTokenizer, FieldBoosts, MergePolicyOptions,
+    SchemaProfile, Stemming, SubmodulePolicy,
+};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// What `beetle new` indexes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexSource {
+    Path(PathBuf),
+    /// A remote repository, cloned into the checkouts of the beetle home and pulled by updates.
+    GitUrl(String),
+}
 
 pub fn new_command() -> OptionParser<BeetleCommand> {
     let path = long("path")
         .short('p')
         .argument::<PathBuf>("PATH")
-        .help("Path to the folder to be indexed");
+        .help("Path to the folder to be indexed")
+        .map(IndexSource::Path);
+    let git_url = long("git-url")
+        .argument::<String>("URL")
+        .help(
+            "HTTPS or SSH URL of a git repository to index instead of a folder: its latest commit \
+             is cloned into checkouts/NAME of the beetle home, and pulled by every update",
+        )
+        .map(IndexSource::GitUrl);
+    let source = construct!([path, git_url]);
 
-    construct!(path, index_name())
-        .map(|(repo_path, index_name)| BeetleCommand::New {
-            index_name,
-            path_to_be_indexed: repo_path,
+    let only_extensions = long("only-ext")
+        .argument::<String>("EXTENSIONS")
+        .help("Comma-separated extensions to index, e.g. rs,toml,md; all files by default")
+        .map(|list| list.split(',').map(str::to_string).collect())
+        .fallback(Vec::new());
+
+    let max_file_size = long("max-file-size")
+        .argument::<String>("SIZE")
+        .help(
+            "Index only the first SIZE bytes of each file, e.g. 50MB; larger files are read in \
+             chunks either way, whole files by default",
+        )
+        .parse(|size| parse_byte_size(&size).map(|size| size as u64))
+        .optional();
+
+    let content_storage = long("content-storage")
+        .argument::<String>("MODE")
+        .help(
+            "How file content is kept for snippets: lz4 (default), zstd (smaller index) or \
+             none (smallest index, snippets read from the files)",
+        )
+        .parse(|mode| mode.parse::<ContentStorage>())
+        .fallback(ContentStorage::default());
+
+    let tokenizer = long("tokenizer")
+        .argument::<String>("TOKENIZER")
+        .help(
+            "How content is split into search terms: code (default, the words of the profile) or \
+             ngram[:MIN[-MAX]] (match anywhere inside words, trigrams by default; larger index)",
+        )
+        .parse(|tokenizer| tokenizer.parse::<ContentTokenizer>())
+        .fallback(ContentTokenizer::default());
+
+    let profile = long("profile")
+        .argument::<String>("PROFILE")
+        .help(
+            "What the files are: code (default; identifier parts and symbols), docs (stemmed \
+             English words and titles), logs (plain words, severity levels and line counts) or \
+             commits (the commit history of a git repository, one document per commit)",
+        )
+        .parse(|profile| profile.parse::<SchemaProfile>())
+        .fallback(SchemaProfile::default());
+
+    let lowercase = long("no-lowercase")
+        .help("Keep the case of words (docs and logs profiles), so that searches match case")
+        .flag(Some(false), None);
+    let stemming = long("stemming")
+        .argument::<String>("LANGUAGE")
+        .help(
+            "Reduce words to their stem in LANGUAGE (docs and logs profiles), e.g. french, or \
+             none; english for docs by default",
+        )
+        .parse(|language| language.parse::<Stemming>())
+        .optional();
+    let stop_words = long("stop-words")
+        .argument::<String>("WORDS")
+        .help("Comma-separated words to leave out of the index and queries, e.g. the,a,an")
+        .map(|list| {
+            list.split(',')
+                .map(str::trim)
+                .filter(|word| !word.is_empty())
+                .map(str::to_string)
+                .collect()
         })
-        .to_options()
+        .fallback(Vec::new());
+    let analyzer = construct!(AnalyzerOptions {
+        lowercase,
+        stemming,
+        stop_words
+    });
+
+    let boosts = long("boost")
+        .argument::<String>("BOOSTS")
+        .help(
+            "How much matches in each field weigh when ranking results, e.g. \
+             filename=5,content=0.5; fields are path, filename, symbols and content",
+        )
+        .parse(|boosts| boosts.parse::<FieldBoosts>())
+        .fallback(FieldBoosts::default());
+
+    let merge_policy = long("merge-policy")
+        .argument::<String>("POLICY")
+        .help(
+            "How updates merge segments in the background, e.g. min-segments=4,max-docs=1000000; \
+             keys are min-segments, max-docs, min-layer-size, level-log-size and deletes-ratio, \
+             or none to never merge",
+        )
+        .parse(|policy| policy.parse::<MergePolicyOptions>())
+        .fallback(MergePolicyOptions::default());
+
+    let encrypt = long("encrypt")
+        .help(
+            "Encrypt the index files with the key in BEETLE_ENCRYPTION_KEY, or printed by \
+             BEETLE_ENCRYPTION_KEY_COMMAND",
+        )
+        .switch();
+
+    let utf8_only = long("utf8-only")
+        .help(
+            "Read files as UTF-8 only; by default UTF-16 and Latin-1 (Windows-1252) files are \
+             transcoded",
+        )
+        .switch();
+
+    let dedup = long("dedup")
+        .help(
+            "Index files with the same content as another once, and list them with its search \
+             results",
+        )
+        .switch();
+
+    let git_blame = long("git-blame")
+        .help(
+            "Record the author and commit of the last change of each file committed to git, shown \
+             with search results and filtered by beetle search --author",
+        )
+        .switch();
+
+    let tracked_only = long("tracked-only")
+        .help(
+            "Index only the files git tracks, as git ls-files lists them at every update, \
+             instead of walking the folder",
+        )
+        .switch();
+
+    let hidden = long("hidden")
+        .help("Also index hidden files and directories, whose name starts with a dot")
+        .switch();
+
+    let no_global_gitignore = long("no-global-gitignore")
+        .help("Index the files the global gitignore of git (core.excludesFile) ignores")
+        .switch();
+
+    let no_git_exclude = long("no-git-exclude")
+        .help("Index the files the .git/info/exclude of the repository ignores")
+        .switch();
+
+    let submodules = long("submodules")
+        .argument::<String>("POLICY")
+        .help(
+            "What becomes of the files of git submodules: index (default; along with the other \
+             files), skip, or separate (each checked out submodule in an index of its own, \
+             NAME.SUBMODULE, created and updated by the updates of the index)",
+        )
+        .parse(|policy| policy.parse::<SubmodulePolicy>())
+        .fallback(SubmodulePolicy::default());
+
+    let branches = long("branch")
+        .argument::<String>("BRANCH")
+        .help(
+            "Index the files committed to BRANCH instead of those of the folder, a git \
+             repository; repeat to index several branches in one index, filtered by beetle \
+             search --branch",
+        )
+        .many();
+
+    let commits = long("commits")
+        .help(
+            "Also index the commit history of the folder, a git repository, in the index \
+             NAME.commits, updated along with the index and searched by beetle search --commits",
+        )
+        .switch();
+
+    let files = long("files-from")
+        .argument::<PathBuf>("FILE")
+        .help(
+            "Index only the files listed in FILE, or stdin with -, one per line or NUL-separated \
+             (git ls-files -z), relative to the indexed folder; kept for every update",
+        )
+        .parse(|path| read_file_list(&path))
+        .fallback(Vec::new());
+
+    let slug = long("slug")
+        .help(
+            "Turn the index name into a valid one, e.g. 'My App' into my-app, instead of \
+             rejecting it",
+        )
+        .switch();
+
+    let tags = tag("Label used to group the index, e.g. backend; repeatable").many();
+
+    let dry_run = long("dry-run")
+        .help("List the files the first update would index and skip, without creating the index")
+        .switch();
+
+    construct!(
+        source,
+        index_name(),
+        only_extensions,
+        max_file_size,
+        content_storage,
+        tokenizer,
+        profile,
+        analyzer,
+        boosts,
+        merge_policy,
+        encrypt,
+        utf8_only,
+        dedup,
+        git_blame,
+        tracked_only,
+        hidden,
+        no_global_gitignore,
+        no_git_exclude,
+        submodules,
+        branches,
+        commits,
+        files,
+        slug,
+        writer_resources(),
+        tags,
+        dry_run,
+        format()
+    )
+    .map(
+        |(
+            source,
+            index_name,
+            only_extensions,
+            max_file_size,
+            content_storage,
+            tokenizer,
+            profile,
+            analyzer,
+            boosts,
+            merge_policy,
+            encrypt,
+            utf8_only,
+            dedup,
+            git_blame,
+            tracked_only,
+            hidden,
+            no_global_gitignore,
+            no_git_exclude,
+            submodules,
+            branches,
+            commits,
+            files,
+            slug,
+            writer_resources,
+            tags,
+            dry_run,
+            format,
+        )| {
+            BeetleCommand::New {
+                index_name,
+                source,
+                only_extensions,
+                max_file_size,
+                content_storage,
+                tokenizer,
+                profile,
+                analyzer,
+                boosts,
+                merge_policy,
+                encrypt,
+                utf8_only,
+                dedup,
+                git_blame,
+                tracked_only,
+                hidden,
+                no_global_gitignore,
+                no_git_exclude,
+                submodules,
+                branches,
+                commits,
+                files,
+                slug,
+                writer_resources,
+                tags,
+                dry_run,
+                format,
+            }
+        },
+    )
+    .to_options()
+}
+
+/// The paths listed in the file at `path`, or on stdin for `-`: NUL-separated when the list holds
+/// a NUL, as `git ls-files -z` and `fd -0` print them, and one per line otherwise.
+fn read_file_list(path: &Path) -> Result<Vec<String>, String> {
+    let mut list = String::new();
+    let read = if path == Path::new("-") {
+        std::io::stdin().read_to_string(&mut list)
+    } else {
+        std::fs::File::open(path).and_then(|mut file| file.read_to_string(&mut list))
+    };
+    read.map_err(|e| format!("Failed to read the file list {}: {e}", path.display()))?;
+
+    let files: Vec<String> = if list.contains('\0') {
+        list.split('\0').map(str::to_string).collect()
+    } else {
+        list.lines().map(str::to_string).collect()
+    };
+    let files: Vec<String> = files.into_iter().filter(|file| !file.is_empty()).collect();
+    if files.is_empty() {
+        return Err(format!("The file list {} is empty", path.display()));
+    }
+
+    Ok(files)
 }
@@ -1,6 +1,7 @@
 use bpaf::*;
+use engine::options::WriterResources;
 
-use crate::command::OutputFormat;
+use crate::command::{ColorChoice, OutputFormat};
 
 pub fn index_name() -> impl Parser<String> {
     long("index")
@@ -9,14 +10,104 @@ pub fn index_name() -> impl Parser<String> {
         .help("Name of the index to operate on")
 }
 
+/// `--wait`, for commands that write to an index another process may be writing to.
+pub fn wait() -> impl Parser<bool> {
+    long("wait")
+        .switch()
+        .help("Wait for other processes writing to the index instead of failing")
+}
+
+/// `--memory-budget`, `--index-threads`, `--batch-size` and `--commit-every`, for commands that
+/// index files.
+pub fn writer_resources() -> impl Parser<WriterResources> {
+    let memory_budget = long("memory-budget")
+        .argument::<String>("SIZE")
+        .help(
+            "Memory the indexer may buffer documents in, e.g. 512MB or 2GB; an eighth of the \
+             system memory by default",
+        )
+        .parse(|size| parse_byte_size(&size))
+        .optional();
+
+    let index_threads = long("index-threads")
+        .argument::<usize>("THREADS")
+        .help("Threads indexing files in parallel; one per core, up to 8, by default")
+        .optional();
+
+    let batch_size = long("batch-size")
+        .argument::<usize>("FILES")
+        .help(
+            "Files indexed in a batch, which progress and timings are reported for; 100 by default",
+        )
+        .optional();
+
+    let commit_every = long("commit-every")
+        .argument::<usize>("BATCHES")
+        .help(
+            "Commit after every BATCHES batches, so that an interrupted update resumes from the \
+             last commit; only at the end by default",
+        )
+        .optional();
+
+    construct!(WriterResources {
+        memory_budget,
+        index_threads,
+        batch_size,
+        commit_every
+    })
+}
+
+/// Parses sizes such as `1048576`, `512K`, `512MB` or `1.5GB`, in powers of 1024.
+pub fn parse_byte_size(size: &str) -> Result<usize, String> {
+    let size = size.trim();
+    let invalid = || format!("Invalid size '{size}'. Use e.g. 512MB or 2GB");
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => return Err(invalid()),
+    };
+
+    Ok((number * multiplier as f64) as usize)
+}
+
+/// `--tag`; `help` says what the tag is used for in the command.
+pub fn tag(help: &'static str) -> impl Parser<String> {
+    long("tag").argument::<String>("TAG").help(help)
+}
+
 pub fn format() -> impl Parser<OutputFormat> {
     long("format")
         .argument::<String>("FORMAT")
-        .help("Output format: text (default) or json")
+        .help("Output format: text (default), json, csv or ndjson")
         .parse(|s| match s.as_str() {
             "text" => Ok(OutputFormat::Text),
             "json" => Ok(OutputFormat::Json),
-            _ => Err("Invalid format. Use 'text' or 'json'"),
+            "csv" => Ok(OutputFormat::Csv),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _ => Err("Invalid format. Use 'text', 'json', 'csv' or 'ndjson'"),
         })
         .fallback(OutputFormat::Text)
 }
+
+pub fn color() -> impl Parser<ColorChoice> {
+    long("color")
+        .argument::<String>("WHEN")
+        .help(
+            "Highlight matches with terminal colors: auto (default, when printing to a terminal \
+             and NO_COLOR is not set), always or never",
+        )
+        .parse(|s| match s.as_str() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err("Invalid color choice. Use 'auto', 'always' or 'never'"),
+        })
+        .fallback(ColorChoice::Auto)
+}
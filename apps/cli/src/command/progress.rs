@@ -0,0 +1,138 @@
+//! The progress bar `beetle update` draws on stderr while it scans, indexes and commits, fed by
+//! [`engine::IndexingProgress`] and drawn by indicatif. It is only drawn for text output, when
+//! both stdout and stderr are terminals, so that piped, redirected or JSON output stays free of
+//! it.
+
+use engine::{IndexingPhase, IndexingProgress};
+use indicatif::{ProgressDrawTarget, ProgressStyle};
+use std::io::IsTerminal;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Most redraws per second; indicatif throttles updates coming in faster.
+const REDRAWS_PER_SEC: u8 = 10;
+/// How often the spinner of the phases without a file count turns.
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct ProgressBar {
+    /// `None` when the bar is not drawn.
+    bar: Option<indicatif::ProgressBar>,
+    /// The phase the bar is styled for, restyled when the next one starts.
+    phase: Mutex<Option<IndexingPhase>>,
+}
+
+impl ProgressBar {
+    /// A bar drawn when `text` output goes to a terminal, and stderr is one too.
+    pub fn new(text: bool) -> Self {
+        let enabled = text && std::io::stdout().is_terminal() && std::io::stderr().is_terminal();
+        match enabled {
+            true => Self::drawn_to(ProgressDrawTarget::stderr_with_hz(REDRAWS_PER_SEC)),
+            false => ProgressBar {
+                bar: None,
+                phase: Mutex::new(None),
+            },
+        }
+    }
+
+    fn drawn_to(target: ProgressDrawTarget) -> Self {
+        ProgressBar {
+            bar: Some(indicatif::ProgressBar::with_draw_target(None, target)),
+            phase: Mutex::new(None),
+        }
+    }
+
+    pub fn update(&self, progress: IndexingProgress) {
+        let Some(bar) = &self.bar else {
+            return;
+        };
+        let mut phase = self.phase.lock().unwrap_or_else(|e| e.into_inner());
+        if *phase != Some(progress.phase) {
+            *phase = Some(progress.phase);
+            bar.set_style(style(progress.phase));
+            bar.set_message(message(progress.phase));
+            match progress.phase {
+                IndexingPhase::Indexing => bar.disable_steady_tick(),
+                _ => bar.enable_steady_tick(TICK_INTERVAL),
+            }
+        }
+        bar.set_length(progress.files_total as u64);
+        bar.set_position(progress.files_processed as u64);
+    }
+
+    /// Clears the bar, for the summary of the update to take its line.
+    pub fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+/// How the bar is drawn in `phase`, e.g. `Indexing [#########---------------------] 120/400
+/// files (30%)`, or a spinner for the phases without a file count.
+fn style(phase: IndexingPhase) -> ProgressStyle {
+    match phase {
+        IndexingPhase::Indexing => {
+            ProgressStyle::with_template("{msg} [{bar:30}] {pos}/{len} files ({percent}%)")
+                .expect("the progress template is valid")
+                .progress_chars("#-")
+        }
+        _ => {
+            ProgressStyle::with_template("{spinner} {msg}").expect("the spinner template is valid")
+        }
+    }
+}
+
+fn message(phase: IndexingPhase) -> &'static str {
+    match phase {
+        IndexingPhase::Scanning => "Scanning files...",
+        IndexingPhase::Removing => "Removing deleted files...",
+        IndexingPhase::Indexing => "Indexing",
+        IndexingPhase::Committing => "Committing...",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indicatif::InMemoryTerm;
+
+    #[test]
+    fn test_bar_left_out_of_non_text_output() {
+        let bar = ProgressBar::new(false);
+        assert!(bar.bar.is_none());
+
+        // Updates and finishing are no-ops
+        bar.update(IndexingProgress {
+            phase: IndexingPhase::Indexing,
+            files_processed: 120,
+            files_total: 400,
+        });
+        bar.finish();
+    }
+
+    #[test]
+    fn test_bar() {
+        let term = InMemoryTerm::new(1, 80);
+        let bar = ProgressBar::drawn_to(ProgressDrawTarget::term_like(Box::new(term.clone())));
+        let progress = |phase, files_processed, files_total| IndexingProgress {
+            phase,
+            files_processed,
+            files_total,
+        };
+
+        bar.update(progress(IndexingPhase::Scanning, 0, 0));
+        assert!(term.contents().ends_with("Scanning files..."));
+        bar.update(progress(IndexingPhase::Indexing, 120, 400));
+        assert_eq!(
+            term.contents(),
+            format!(
+                "Indexing [{}{}] 120/400 files (30%)",
+                "#".repeat(9),
+                "-".repeat(21)
+            )
+        );
+
+        bar.finish();
+        assert_eq!(term.contents(), "");
+    }
+}
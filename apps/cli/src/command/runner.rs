@@ -1,48 +1,366 @@
+use engine::bench::run_benchmark;
+use engine::doctor::OrphanedEntry;
+use engine::git::strip_credentials;
+use engine::options::IndexingOptions;
+use engine::search::{language_counts, SearchOptions};
 use engine::storage::FsStorage;
-use engine::IndexCatalog;
+use engine::usage::{ResourceSampler, ResourceUsage};
+use engine::{
+    commits_index_name, slugify_index_name, validate_index_name, IndexCatalog, ALL_INDEXES,
+};
 
+use bpaf::Args;
 use tracing::trace;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use super::{BeetleCommand, JsonFormatter, OutputFormat, PlainTextFormatter, ResultFormatter};
+use super::{
+    beetle_command,
+    list::{matches_filter, sort_indexes},
+    progress::ProgressBar,
+    tags::{render_ctags, render_etags},
+    BeetleCommand, ColorChoice, CsvFormatter, HistoryAction, HookAction, IndexSource,
+    JsonFormatter, NdjsonFormatter, OutputFormat, PlainTextFormatter, ResultFormatter, SearchScope,
+    ServeControl, SnapshotAction, TokenAction,
+};
 use crate::{
-    cli::{get_beetle_home, CliRunResult, Runner},
-    command::formatter::CommandOutput,
-    server::HttpServer,
+    auth::TokenStore,
+    cli::{beetle_home, get_remote_storage, CliRunResult, Runner},
+    command::formatter::{update_summary, CommandOutput},
+    config::{parse_interval, ServeConfig},
+    daemon::Daemon,
+    editor::EditorServer,
+    history::{HistoryEntry, SearchHistory},
+    home::validate_tenant_name,
+    hooks::{install_hooks, HOOKS},
+    mcp::McpServer,
+    server::{HttpServer, ServerOptions, DEFAULT_HOST, DEFAULT_PORT},
 };
 
 pub struct BeetleRunner {
     options: BeetleCommand,
     catalog: IndexCatalog,
+    /// Why the configured remote storage could not be set up, reported instead of running.
+    storage_error: Option<String>,
 }
 
 impl BeetleRunner {
+    /// The first index named by the command that does not exist. Commands that create an index,
+    /// or may name one that was removed, such as `snapshot restore`, are left to report it.
+    fn missing_index(&self) -> Option<&str> {
+        let named: Vec<&str> = match &self.options {
+            BeetleCommand::Search { scope, .. } => match scope {
+                SearchScope::Index(index_name) => vec![index_name],
+                SearchScope::Indexes(index_names) => {
+                    index_names.iter().map(String::as_str).collect()
+                }
+                _ => vec![],
+            },
+            BeetleCommand::Remove { index_name }
+            | BeetleCommand::Update { index_name, .. }
+            | BeetleCommand::Ingest { index_name, .. }
+            | BeetleCommand::Tags { index_name, .. }
+            | BeetleCommand::Verify { index_name, .. }
+            | BeetleCommand::Info { index_name, .. }
+            | BeetleCommand::Snapshot {
+                action: SnapshotAction::Create { index_name },
+                ..
+            } => vec![index_name],
+            _ => vec![],
+        };
+        if named.is_empty() {
+            return None;
+        }
+        // Failing to list the indexes is reported by the command itself
+        let indexes = self.catalog.list().ok()?;
+
+        named
+            .into_iter()
+            .find(|index_name| !indexes.iter().any(|index| index.index_name == *index_name))
+    }
+
     fn execute(self) -> Result<CommandOutput, String> {
         match self.options {
             BeetleCommand::New {
                 index_name,
-                path_to_be_indexed,
+                source,
+                only_extensions,
+                max_file_size,
+                content_storage,
+                tokenizer,
+                profile,
+                analyzer,
+                boosts,
+                merge_policy,
+                encrypt,
+                utf8_only,
+                dedup,
+                git_blame,
+                tracked_only,
+                hidden,
+                no_global_gitignore,
+                no_git_exclude,
+                submodules,
+                branches,
+                commits,
+                files,
+                slug,
+                writer_resources,
+                tags,
+                dry_run,
+                ..
             } => {
-                self.catalog
-                    .create(&index_name, &path_to_be_indexed.to_string_lossy())?;
+                let options = IndexingOptions::default()
+                    .with_only_extensions(only_extensions)
+                    .with_max_file_size(max_file_size)
+                    .with_content_storage(content_storage)
+                    .with_tokenizer(tokenizer)
+                    .with_profile(profile)
+                    .with_analyzer(analyzer)
+                    .with_boosts(boosts)
+                    .with_merge_policy(merge_policy)
+                    .with_encryption(encrypt)
+                    .with_utf8_only(utf8_only)
+                    .with_dedup(dedup)
+                    .with_git_blame(git_blame)
+                    .with_tracked_only(tracked_only)
+                    .with_hidden(hidden)
+                    .with_no_global_gitignore(no_global_gitignore)
+                    .with_no_git_exclude(no_git_exclude)
+                    .with_submodules(submodules)
+                    .with_branches(branches)
+                    .with_commits(commits)
+                    .with_files(files)
+                    .with_writer_resources(writer_resources);
+                let index_name = match slug {
+                    true => slugify_index_name(&index_name),
+                    false => index_name,
+                };
+                let cloned = match source {
+                    IndexSource::Path(path) => {
+                        if dry_run {
+                            validate_index_name(&index_name)?;
+                            let plan = self
+                                .catalog
+                                .plan_create(&path.to_string_lossy(), &options)?;
+                            return Ok(CommandOutput::Plan(plan));
+                        }
+                        self.catalog.create(
+                            &index_name,
+                            &path.to_string_lossy(),
+                            &options,
+                            &tags,
+                        )?;
+                        None
+                    }
+                    IndexSource::GitUrl(url) => {
+                        if dry_run {
+                            return Err(
+                                "--dry-run cannot plan an index of a repository before cloning it"
+                                    .to_string(),
+                            );
+                        }
+                        self.catalog
+                            .create_from_git(&index_name, &url, &options, &tags)?;
+                        Some(strip_credentials(&url))
+                    }
+                };
 
-                Ok(CommandOutput::Success(format!(
-                    "Index '{index_name}' created successfully"
-                )))
+                Ok(CommandOutput::Success(match cloned {
+                    Some(url) => format!("Index '{index_name}' of {url} created successfully"),
+                    None => format!("Index '{index_name}' created successfully"),
+                }))
+            }
+            BeetleCommand::Search {
+                scope,
+                query,
+                symbols: true,
+                languages,
+                modified_after,
+                modified_before,
+                boosts,
+                facets,
+                stats,
+                no_history,
+                format,
+                ..
+            } => {
+                if !languages.is_empty()
+                    || modified_after.is_some()
+                    || modified_before.is_some()
+                    || !boosts.is_default()
+                    || facets
+                {
+                    return Err(
+                        "--lang, --modified-after, --modified-before, --boost and --facets \
+                         only apply to content searches, not --symbols"
+                            .to_string(),
+                    );
+                }
+                let sampler = ResourceSampler::start();
+                let started = Instant::now();
+                let searched_all = scope == SearchScope::All;
+                let index_names = match scope {
+                    SearchScope::Index(index_name) => vec![index_name],
+                    SearchScope::Indexes(index_names) => index_names,
+                    SearchScope::All => self.catalog.searchable_indexes()?,
+                    SearchScope::Tagged(tags) => {
+                        let index_names = self.catalog.indexes_tagged(&tags)?;
+                        if index_names.is_empty() {
+                            return Err(format!("No index is tagged {}", tags.join(", ")));
+                        }
+                        index_names
+                    }
+                    SearchScope::Here => {
+                        return Err("--symbols cannot search --here; name an index".to_string())
+                    }
+                };
+                let symbols = self.catalog.search_symbols(&index_names, &query)?;
+                let searched = match searched_all {
+                    true => ALL_INDEXES.to_string(),
+                    false => index_names.join(","),
+                };
+                SearchHistory::open(beetle_home().path, !no_history).record(
+                    HistoryEntry::new(searched, &query, symbols.len(), started.elapsed())
+                        .with_symbols(true),
+                );
+                if stats {
+                    print_search_stats(&format, symbols.len(), "definitions", sampler.finish());
+                }
+
+                Ok(CommandOutput::Symbols(symbols))
             }
             BeetleCommand::Search {
-                index_name, query, ..
+                scope,
+                query,
+                exact,
+                commits,
+                max_query_cost,
+                force,
+                highlight_tag,
+                highlight_class,
+                snippet_chars,
+                max_fragments,
+                languages,
+                within,
+                excluded_extensions,
+                excluded_paths,
+                author,
+                branches,
+                changed_since,
+                modified_after,
+                modified_before,
+                boosts,
+                facets,
+                stats,
+                no_history,
+                format,
+                ..
             } => {
-                let searcher = self.catalog.get_searcher(&index_name)?;
-                let search_result = searcher.search(&query)?;
+                let sampler = ResourceSampler::start();
+                let started = Instant::now();
+                let options = SearchOptions {
+                    max_query_cost: (!force).then_some(max_query_cost),
+                    highlight_tag,
+                    highlight_class,
+                    languages,
+                    modified_after,
+                    modified_before,
+                    boosts,
+                    exact,
+                    snippet_chars,
+                    max_fragments,
+                    within,
+                    excluded_extensions,
+                    excluded_paths,
+                    author,
+                    branches,
+                    changed_since,
+                };
+                let scope = match commits {
+                    true => commits_scope(&self.catalog, scope)?,
+                    false => scope,
+                };
+                // The searched indexes are recorded in the history, unless no index covers --here
+                let (search_result, searched) = match scope {
+                    SearchScope::Index(index_name) => (
+                        self.catalog.search(&index_name, &query, &options)?,
+                        Some(index_name),
+                    ),
+                    SearchScope::Indexes(index_names) => (
+                        self.catalog
+                            .search_indexes(&index_names, &query, &options)?,
+                        Some(index_names.join(",")),
+                    ),
+                    SearchScope::All => (
+                        self.catalog.search(ALL_INDEXES, &query, &options)?,
+                        Some(ALL_INDEXES.to_string()),
+                    ),
+                    SearchScope::Tagged(tags) => {
+                        let index_names = self.catalog.indexes_tagged(&tags)?;
+                        if index_names.is_empty() {
+                            return Err(format!("No index is tagged {}", tags.join(", ")));
+                        }
+                        (
+                            self.catalog
+                                .search_indexes(&index_names, &query, &options)?,
+                            Some(index_names.join(",")),
+                        )
+                    }
+                    SearchScope::Here => {
+                        let current_dir = std::env::current_dir()
+                            .map_err(|e| format!("Failed to get current directory: {e}"))?;
+                        let scoped = self.catalog.search_within(&current_dir, &query, &options)?;
+                        if scoped.index_name.is_none() {
+                            eprintln!(
+                                "Warning: no index covers {}; searched it without an index. \
+                                 Create one with `beetle new --index <NAME> --path .` for faster searches",
+                                current_dir.display()
+                            );
+                        }
+                        (scoped.results, scoped.index_name)
+                    }
+                };
+                if let Some(searched) = searched {
+                    SearchHistory::open(beetle_home().path, !no_history).record(
+                        HistoryEntry::new(searched, &query, search_result.len(), started.elapsed())
+                            .with_exact(exact),
+                    );
+                }
+                if facets {
+                    let counts: Vec<_> = language_counts(&search_result)
+                        .into_iter()
+                        .map(|count| format!("{} {}", count.language, count.count))
+                        .collect();
+                    if counts.is_empty() {
+                        eprintln!("Languages: none");
+                    } else {
+                        eprintln!("Languages: {}", counts.join(", "));
+                    }
+                }
+                if stats {
+                    print_search_stats(&format, search_result.len(), "results", sampler.finish());
+                }
 
                 Ok(CommandOutput::Search(search_result))
             }
-            BeetleCommand::List { .. } => {
-                let indexes = self.catalog.list()?;
+            BeetleCommand::List {
+                sort, tags, filter, ..
+            } => {
+                let indexes: Vec<_> = self
+                    .catalog
+                    .stats_all()?
+                    .into_iter()
+                    .filter(|index| index.metadata.has_tags(&tags))
+                    .filter(|index| {
+                        filter
+                            .as_deref()
+                            .is_none_or(|filter| matches_filter(&index.metadata, filter))
+                    })
+                    .collect();
 
-                Ok(CommandOutput::List(indexes))
+                Ok(CommandOutput::List(sort_indexes(indexes, sort)))
             }
             BeetleCommand::Remove { index_name } => {
                 self.catalog.remove(&index_name)?;
@@ -54,52 +372,522 @@ impl BeetleRunner {
             BeetleCommand::Update {
                 index_name,
                 reindex,
+                wait,
+                writer_resources,
+                change_detection,
+                dry_run,
+                format,
             } => {
-                let mut writer = self.catalog.get_writer(&index_name)?;
+                if dry_run {
+                    let plan = if reindex {
+                        let metadata = self.catalog.get_matadata(&index_name)?;
+                        self.catalog
+                            .plan_create(&metadata.target_path, &metadata.options)?
+                    } else {
+                        self.catalog.plan_update(&index_name, change_detection)?
+                    };
+                    return Ok(CommandOutput::Plan(plan));
+                }
 
-                if reindex {
-                    self.catalog.reset(&index_name)?;
+                let catalog = self.catalog.with_writer_resources(writer_resources);
+                let progress = ProgressBar::new(format == OutputFormat::Text);
+                let on_progress = |update| progress.update(update);
+                let record = if reindex {
+                    catalog.reindex(&index_name, wait, &on_progress)
+                } else {
+                    catalog.get_writer(&index_name, wait).and_then(|writer| {
+                        writer
+                            .with_change_detection(change_detection)
+                            .index_with_progress(&on_progress)
+                    })
+                };
+                progress.finish();
+                let record = record?;
+
+                // The index is up to date, but a companion that is not fails the command
+                if !record.companion_failures.is_empty() {
+                    return Err(format!(
+                        "{}\n{}",
+                        update_summary(&index_name, &record),
+                        record.companion_failures.join("\n")
+                    ));
                 }
 
-                writer.index()?;
+                Ok(CommandOutput::Update(index_name, Box::new(record)))
+            }
+            BeetleCommand::Ingest {
+                index_name,
+                path_prefix,
+                wait,
+            } => {
+                let mut writer = self.catalog.get_writer(&index_name, wait)?;
+                let count = writer.ingest(std::io::stdin().lock(), &path_prefix)?;
+
+                Ok(CommandOutput::Success(format!(
+                    "Ingested {count} documents into '{index_name}'"
+                )))
+            }
+            BeetleCommand::Tags {
+                index_name,
+                output,
+                etags,
+            } => {
+                let metadata = self.catalog.get_matadata(&index_name)?;
+                // Reads every document: a frozen copy keeps updates by other processes from
+                // removing segments under it
+                let files = self.catalog.fork_reader_copy(&index_name)?.symbols()?;
+                let root = PathBuf::from(&metadata.target_path);
+                let (content, default_output) = if etags {
+                    (render_etags(&files, &root), "TAGS")
+                } else {
+                    (render_ctags(&files, &root), "tags")
+                };
 
+                let output = output.unwrap_or_else(|| PathBuf::from(default_output));
+                std::fs::write(&output, content)
+                    .map_err(|e| format!("Failed to write tags file {output:?}: {e}"))?;
+
+                let count: usize = files.iter().map(|file| file.symbols.len()).sum();
                 Ok(CommandOutput::Success(format!(
-                    "Incremental update for '{index_name}' successful"
+                    "Wrote {count} tags to {}",
+                    output.to_string_lossy()
                 )))
             }
-            BeetleCommand::Serve { port } => Ok(HttpServer::start(port)),
+            BeetleCommand::Serve {
+                control: Some(control),
+                ..
+            } => {
+                let daemon = Daemon::new(beetle_home().path);
+                let info = match control {
+                    ServeControl::Status => daemon.status()?,
+                    ServeControl::Stop => daemon.stop()?,
+                }
+                .ok_or("No beetle server is running")?;
+
+                Ok(CommandOutput::Success(match control {
+                    ServeControl::Status => format!(
+                        "Server running on http://localhost:{} (pid {}, up {})",
+                        info.port,
+                        info.pid,
+                        format_uptime(info.uptime())
+                    ),
+                    ServeControl::Stop => format!("Server (pid {}) stopped", info.pid),
+                }))
+            }
+            BeetleCommand::Serve { daemon: true, .. } if !Daemon::is_daemon_process() => {
+                let daemon = Daemon::new(beetle_home().path);
+                let info = daemon.spawn()?;
+
+                Ok(CommandOutput::Success(format!(
+                    "Server running in the background on http://localhost:{} (pid {}); \
+                     output goes to {}",
+                    info.port,
+                    info.pid,
+                    daemon.log_path().display()
+                )))
+            }
+            BeetleCommand::Serve {
+                port,
+                host,
+                no_history,
+                require_token,
+                token,
+                oidc,
+                max_query_cost,
+                min_update_interval,
+                rate_limit,
+                cors_origins,
+                cors_methods,
+                daemon,
+                max_index_jobs,
+                reader_threads,
+                writer_resources,
+                read_only,
+                auto_update,
+                auto_update_indexes,
+                ..
+            } => {
+                // Flags override the serve block of the config file
+                let config = ServeConfig::load(beetle_home().config_file.as_deref())?;
+                let or_config = |flags: Vec<String>, config: Vec<String>| match flags.is_empty() {
+                    true => config,
+                    false => flags,
+                };
+                let (config_interval, config_indexes) = match config.auto_update {
+                    Some(auto_update) => (Some(auto_update.interval), auto_update.indexes),
+                    None => (None, Vec::new()),
+                };
+                let auto_update = match auto_update {
+                    Some(interval) => Some(interval),
+                    None => config_interval.as_deref().map(parse_interval).transpose()?,
+                };
+
+                Ok(HttpServer::start(ServerOptions {
+                    port: port.or(config.port).unwrap_or(DEFAULT_PORT),
+                    host: host
+                        .or(config.host)
+                        .unwrap_or_else(|| DEFAULT_HOST.to_string()),
+                    require_token: require_token || config.require_token,
+                    admin_token: token.or(config.token),
+                    oidc,
+                    max_query_cost,
+                    min_update_interval: Duration::from_secs(min_update_interval),
+                    rate_limit,
+                    cors_origins: or_config(cors_origins, config.cors_origins),
+                    cors_methods: or_config(cors_methods, config.cors_methods),
+                    daemon,
+                    max_index_jobs,
+                    reader_threads,
+                    writer_resources,
+                    no_history,
+                    read_only: read_only || config.read_only,
+                    auto_update,
+                    auto_update_indexes: or_config(auto_update_indexes, config_indexes),
+                }))
+            }
+            BeetleCommand::Mcp | BeetleCommand::EditorServer => {
+                unreachable!("stdio servers are run by BeetleRunner::run")
+            }
+            BeetleCommand::Doctor { fix, .. } => {
+                let issues = self.catalog.doctor(fix)?;
+
+                Ok(CommandOutput::Doctor(issues))
+            }
+            BeetleCommand::Clean { yes, format } => {
+                let found = self.catalog.clean(false)?;
+                let remove = !found.is_empty()
+                    && (yes || (format == OutputFormat::Text && confirm_clean(&found)));
+                let entries = match remove {
+                    true => self.catalog.remove_orphans(found)?,
+                    false => found,
+                };
+
+                Ok(CommandOutput::Clean(entries))
+            }
+            BeetleCommand::Info { index_name, .. } => Ok(CommandOutput::Info(Box::new(
+                self.catalog.info(&index_name)?,
+            ))),
+            BeetleCommand::Verify {
+                index_name, repair, ..
+            } => {
+                let issues = self.catalog.verify(&index_name, repair)?;
+
+                Ok(CommandOutput::Verify(issues))
+            }
+            BeetleCommand::Env { .. } => Ok(CommandOutput::Env(beetle_home().locations())),
+            BeetleCommand::History { action, .. } => {
+                let history = SearchHistory::open(beetle_home().path, true);
+
+                match action {
+                    HistoryAction::List { index_name, limit } => Ok(CommandOutput::History(
+                        history.recent(index_name.as_deref(), limit)?,
+                    )),
+                    HistoryAction::Run { .. } => {
+                        unreachable!("history --run is run as a search by BeetleRunner::run")
+                    }
+                    HistoryAction::Clear => {
+                        history.clear()?;
+
+                        Ok(CommandOutput::Success("Search history cleared".to_string()))
+                    }
+                }
+            }
+            BeetleCommand::Token { action, .. } => {
+                let token_store = TokenStore::new(beetle_home().path);
+
+                match action {
+                    TokenAction::Create { indexes } => {
+                        for index_name in &indexes {
+                            self.catalog.get_matadata(index_name)?;
+                        }
+                        let (token, secret) = token_store.create(indexes)?;
+
+                        Ok(CommandOutput::TokenCreated(token, secret))
+                    }
+                    TokenAction::List => Ok(CommandOutput::Tokens(token_store.list()?)),
+                    TokenAction::Revoke { id } => {
+                        token_store.revoke(&id)?;
+
+                        Ok(CommandOutput::Success(format!(
+                            "Token '{id}' revoked successfully"
+                        )))
+                    }
+                }
+            }
+            BeetleCommand::Hook { action } => match action {
+                HookAction::Install { index_name } => {
+                    let metadata = self.catalog.get_matadata(&index_name)?;
+                    let executable = std::env::current_exe()
+                        .map_err(|e| format!("Failed to locate the beetle executable: {e}"))?;
+                    let hooks_dir = install_hooks(
+                        Path::new(&metadata.target_path),
+                        &index_name,
+                        &beetle_home().path,
+                        &executable,
+                    )?;
+
+                    Ok(CommandOutput::Success(format!(
+                        "Installed the {} hooks in {} to update index '{index_name}'",
+                        HOOKS.join(", "),
+                        hooks_dir.display()
+                    )))
+                }
+            },
+            BeetleCommand::Snapshot { action, .. } => match action {
+                SnapshotAction::Create { index_name } => {
+                    let snapshot = self.catalog.create_snapshot(&index_name)?;
+
+                    Ok(CommandOutput::Success(format!(
+                        "Snapshot '{}' of index '{index_name}' created ({} docs)",
+                        snapshot.id, snapshot.docs
+                    )))
+                }
+                SnapshotAction::List { index_name } => Ok(CommandOutput::Snapshots(
+                    self.catalog.list_snapshots(index_name.as_deref())?,
+                )),
+                SnapshotAction::Restore { index_name, id } => {
+                    let snapshot = self.catalog.restore_snapshot(&index_name, &id)?;
+
+                    Ok(CommandOutput::Success(format!(
+                        "Index '{index_name}' restored from snapshot '{id}' ({} docs)",
+                        snapshot.docs
+                    )))
+                }
+            },
+            BeetleCommand::Bench {
+                path,
+                queries,
+                iterations,
+                writer_resources,
+                ..
+            } => {
+                let options = IndexingOptions::default().with_writer_resources(writer_resources);
+                let report = run_benchmark(&path, &options, &queries, iterations)?;
+
+                Ok(CommandOutput::Bench(report))
+            }
+        }
+    }
+}
+
+/// The `beetle search` that recorded the history entry `number`, printing in `format`.
+fn rerun_command(number: usize, format: &OutputFormat) -> Result<BeetleCommand, String> {
+    let entry = SearchHistory::open(beetle_home().path, true).get(number)?;
+    let mut args = vec!["search".to_string()];
+    match entry.index_names()[..] {
+        [] => args.push("--all".to_string()),
+        ref index_names => args.extend(index_names.iter().map(|name| format!("--index={name}"))),
+    }
+    let query = match (entry.symbols, entry.exact) {
+        (true, _) => "--symbols",
+        (_, true) => "--exact",
+        _ => "--query",
+    };
+    // Joined with `=`, so that queries starting with `-` are not taken for options
+    args.push(format!("{query}={}", entry.query));
+    args.push(
+        match format {
+            OutputFormat::Text => "--format=text",
+            OutputFormat::Json => "--format=json",
+            OutputFormat::Csv => "--format=csv",
+            OutputFormat::Ndjson => "--format=ndjson",
         }
+        .to_string(),
+    );
+
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    beetle_command()
+        .run_inner(Args::from(args.as_slice()))
+        .map_err(|e| format!("Failed to run search {number} again: {}", e.unwrap_stderr()))
+}
+
+/// The commit indexes of the indexes of `scope`, kept by `beetle new --commits`, for
+/// `beetle search --commits`.
+fn commits_scope(catalog: &IndexCatalog, scope: SearchScope) -> Result<SearchScope, String> {
+    let index_names = match scope {
+        SearchScope::Index(index_name) => {
+            return Ok(SearchScope::Index(commits_index_name(&index_name)))
+        }
+        SearchScope::Indexes(index_names) => index_names,
+        SearchScope::Tagged(tags) => catalog.indexes_tagged(&tags)?,
+        SearchScope::All => catalog
+            .list()?
+            .into_iter()
+            .filter(|index| index.options.commits)
+            .map(|index| index.index_name)
+            .collect(),
+        SearchScope::Here => {
+            return Err("--commits cannot search --here; name the indexes to search".to_string())
+        }
+    };
+    if index_names.is_empty() {
+        return Err(
+            "No index has a commit index; create one with `beetle new --commits`".to_string(),
+        );
+    }
+
+    Ok(SearchScope::Indexes(
+        index_names
+            .iter()
+            .map(|name| commits_index_name(name))
+            .collect(),
+    ))
+}
+
+/// Prints what `search --stats` reports on stderr: a sentence for text output, and otherwise a
+/// JSON object with the number of `results` and the resource `usage`, for scripts.
+fn print_search_stats(format: &OutputFormat, results: usize, noun: &str, usage: ResourceUsage) {
+    match format {
+        OutputFormat::Text => eprintln!("{results} {noun} ({usage})"),
+        _ => eprintln!(
+            "{}",
+            serde_json::json!({ "results": results, "usage": usage })
+        ),
+    }
+}
+
+/// Lists `entries` on the terminal and asks whether to remove them; `false` when stdin or
+/// stderr is not a terminal, for scripts to pass `--yes` instead.
+fn confirm_clean(entries: &[OrphanedEntry]) -> bool {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    if !(std::io::stdin().is_terminal() && std::io::stderr().is_terminal()) {
+        return false;
     }
+    let mut stderr = std::io::stderr().lock();
+    for entry in entries {
+        let _ = writeln!(stderr, "{}: {}", entry.path, entry.reason);
+    }
+    let _ = write!(stderr, "Remove these {} entries? [y/N] ", entries.len());
+    let _ = stderr.flush();
+    drop(stderr);
+
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer).is_ok()
+        && matches!(answer.trim(), "y" | "Y" | "yes")
 }
 
 impl Runner for BeetleRunner {
     type Options = BeetleCommand;
 
     fn new(options: Self::Options) -> Self {
-        let storage = FsStorage::new(PathBuf::from(get_beetle_home()));
-        let catalog = IndexCatalog::new(storage);
+        let home = beetle_home();
+        let beetle_home = home.path;
+        let (catalog, mut storage_error) = match get_remote_storage(beetle_home.clone()) {
+            Ok(Some(storage)) => (IndexCatalog::new(storage), None),
+            Ok(None) => (IndexCatalog::new(FsStorage::new(beetle_home)), None),
+            Err(e) => (IndexCatalog::new(FsStorage::new(beetle_home)), Some(e)),
+        };
+        // A tenant name that escapes `tenants` is reported before anything is written there
+        if let Some(Err(e)) = home.tenant.as_deref().map(validate_tenant_name) {
+            storage_error = Some(e);
+        }
 
-        Self { options, catalog }
+        Self {
+            options,
+            catalog,
+            storage_error,
+        }
     }
 
-    fn run(self) -> CliRunResult {
+    fn run(mut self) -> CliRunResult {
+        // `env` shows where the storage is configured even when it cannot be set up
+        if let Some(e) = &self.storage_error {
+            if !matches!(self.options, BeetleCommand::Env { .. }) {
+                return CliRunResult::Error(e.clone());
+            }
+        }
+
+        // `history --run` runs the recorded search as `beetle search` would
+        if let BeetleCommand::History {
+            action: HistoryAction::Run { number },
+            format,
+        } = &self.options
+        {
+            match rerun_command(*number, format) {
+                Ok(search) => self.options = search,
+                Err(e) => return CliRunResult::Error(e),
+            }
+        }
+
+        if let Some(index_name) = self.missing_index() {
+            return CliRunResult::IndexNotFound(format!("Index '{index_name}' not found"));
+        }
+
+        // Standard output carries the protocol, so nothing else may be printed there. The locks
+        // are only taken here: other commands such as `ingest` read stdin themselves
+        let stdio = || (std::io::stdin().lock(), std::io::stdout().lock());
+        let served = match self.options {
+            BeetleCommand::Mcp => {
+                let (stdin, stdout) = stdio();
+                Some(McpServer::new(&self.catalog).run(stdin, stdout))
+            }
+            BeetleCommand::EditorServer => {
+                let (stdin, stdout) = stdio();
+                Some(EditorServer::new(&self.catalog).run(stdin, stdout))
+            }
+            _ => None,
+        };
+        if let Some(served) = served {
+            return match served {
+                Ok(()) => CliRunResult::None,
+                Err(e) => CliRunResult::Error(e),
+            };
+        }
+
         let output_format = match &self.options {
+            BeetleCommand::New { format, .. } => format.clone(),
             BeetleCommand::Search { format, .. } => format.clone(),
-            BeetleCommand::List { format } => format.clone(),
+            BeetleCommand::Update { format, .. } => format.clone(),
+            BeetleCommand::List { format, .. } => format.clone(),
+            BeetleCommand::Doctor { format, .. } => format.clone(),
+            BeetleCommand::Clean { format, .. } => format.clone(),
+            BeetleCommand::Info { format, .. } => format.clone(),
+            BeetleCommand::Verify { format, .. } => format.clone(),
+            BeetleCommand::Env { format } => format.clone(),
+            BeetleCommand::History { format, .. } => format.clone(),
+            BeetleCommand::Token { format, .. } => format.clone(),
+            BeetleCommand::Snapshot { format, .. } => format.clone(),
+            BeetleCommand::Bench { format, .. } => format.clone(),
             _ => OutputFormat::Text,
         };
 
+        let color = match &self.options {
+            BeetleCommand::Search { color, .. } => *color,
+            _ => ColorChoice::Auto,
+        };
+
         trace!("output format: {:?}", output_format);
 
         match self.execute() {
             Ok(output) => {
+                let matched = match &output {
+                    CommandOutput::Search(results) => !results.is_empty(),
+                    CommandOutput::Symbols(symbols) => !symbols.is_empty(),
+                    _ => true,
+                };
                 let formatted_string = match output_format {
                     OutputFormat::Json => JsonFormatter::new(true).format(output),
-                    OutputFormat::Text => PlainTextFormatter.format(output),
+                    OutputFormat::Csv => CsvFormatter.format(output),
+                    OutputFormat::Ndjson => NdjsonFormatter.format(output),
+                    OutputFormat::Text => PlainTextFormatter::new(color.enabled()).format(output),
                 };
-                CliRunResult::Success(formatted_string)
+                if matched {
+                    CliRunResult::Success(formatted_string)
+                } else {
+                    CliRunResult::NoMatches(formatted_string)
+                }
             }
             Err(message) => CliRunResult::Error(message),
         }
     }
 }
+
+fn format_uptime(uptime: Duration) -> String {
+    let seconds = uptime.as_secs();
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, seconds) => format!("{seconds}s"),
+        (0, minutes, seconds) => format!("{minutes}m {seconds}s"),
+        (hours, minutes, _) => format!("{hours}h {minutes}m"),
+    }
+}
@@ -1,16 +1,300 @@
-use super::{format, index_name, BeetleCommand};
+use super::{color, format, index_name, tag, BeetleCommand};
 use bpaf::*;
+use engine::language::normalize_language;
+use engine::options::FieldBoosts;
+use engine::search::{
+    parse_max_fragments, parse_modified_time, DEFAULT_HIGHLIGHT_TAG, DEFAULT_MAX_FRAGMENTS,
+    DEFAULT_MAX_QUERY_COST, DEFAULT_SNIPPET_CHARS,
+};
+use std::io::Read;
+use std::path::PathBuf;
+
+/// Where `beetle search` looks for matches.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchScope {
+    Index(String),
+    /// Several indexes searched in parallel, with results merged by score.
+    Indexes(Vec<String>),
+    All,
+    /// Every index carrying all of the tags.
+    Tagged(Vec<String>),
+    /// The current directory, through the index covering it or an in-memory scan.
+    Here,
+}
 
 pub fn search_command() -> OptionParser<BeetleCommand> {
-    let query = long("query")
+    let here = long("here")
+        .help("Search the current directory with the index covering it, or without an index")
+        .req_flag(SearchScope::Here);
+    let all = long("all")
+        .help("Search every index")
+        .req_flag(SearchScope::All);
+    let index = index_name()
+        .some("at least one --index is required")
+        .map(|mut index_names| {
+            if index_names.len() == 1 {
+                SearchScope::Index(index_names.remove(0))
+            } else {
+                SearchScope::Indexes(index_names)
+            }
+        });
+    let tagged = tag("Search every index with this tag; repeat to require several tags")
+        .some("at least one --tag is required")
+        .map(SearchScope::Tagged);
+    let scope = construct!([index, all, tagged, here]);
+
+    let text_query = long("query")
         .short('q')
         .argument::<String>("QUERY_EXPRESSION")
-        .help("Search query expression");
+        .help("Search query expression")
+        .map(|query| (query, false, false, false));
+    let file_query = long("query-file")
+        .argument::<PathBuf>("FILE")
+        .help("Read the query expression from FILE, e.g. a multi-line query or one full of quotes")
+        .parse(|path| {
+            let text = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read query file {}: {e}", path.display()))?;
+            query_text(text, &path.display().to_string())
+        })
+        .map(|query| (query, false, false, false));
+    let stdin_query = literal("-")
+        .anywhere()
+        .help("Read the query expression from stdin")
+        .parse(|()| {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| format!("Failed to read the query from stdin: {e}"))?;
+            query_text(text, "stdin")
+        })
+        .map(|query| (query, false, false, false));
+    let symbol_query = long("symbols")
+        .argument::<String>("NAME")
+        .help("Find the definitions of functions, types and classes named like NAME")
+        .map(|name| (name, true, false, false));
+    let exact_query = long("exact")
+        .argument::<String>("LITERAL")
+        .help("Find LITERAL verbatim, punctuation and case included, e.g. \"Result<Vec<u8>, String>\"")
+        .map(|literal| (literal, false, true, false));
+    let commit_query = long("commits")
+        .argument::<String>("QUERY_EXPRESSION")
+        .help(
+            "Search the messages, authors and touched paths of the commits of the indexes' \
+             repositories, indexed by beetle new --commits",
+        )
+        .map(|query| (query, false, false, true));
+    let query = construct!([
+        text_query,
+        file_query,
+        stdin_query,
+        symbol_query,
+        exact_query,
+        commit_query
+    ]);
+
+    let max_query_cost = long("max-query-cost")
+        .argument::<u64>("TERMS")
+        .help("Refuse queries expanding to more terms than this")
+        .fallback(DEFAULT_MAX_QUERY_COST);
+
+    let force = long("force")
+        .switch()
+        .help("Run the query even if it exceeds --max-query-cost");
+
+    let highlight_tag = long("highlight-tag")
+        .argument::<String>("TAG")
+        .help("HTML tag wrapping matches in snippets: b, strong, em, mark or span")
+        .fallback(DEFAULT_HIGHLIGHT_TAG.to_string());
+
+    let highlight_class = long("highlight-class")
+        .argument::<String>("CLASS")
+        .help("CSS class set on the highlight tag")
+        .optional();
+
+    let snippet_chars = long("snippet-chars")
+        .argument::<usize>("CHARS")
+        .help("Longest fragment of the snippets, in characters: more for context, less for one-liners")
+        .guard(|chars| *chars > 0, "--snippet-chars must be at least 1")
+        .fallback(DEFAULT_SNIPPET_CHARS);
+
+    let max_fragments = long("max-fragments")
+        .argument::<String>("COUNT")
+        .help(
+            "Show the best COUNT non-overlapping fragments of each file, or every fragment with \
+             a match with all",
+        )
+        .parse(|count| parse_max_fragments(&count))
+        .fallback(DEFAULT_MAX_FRAGMENTS);
+
+    let languages = long("lang")
+        .argument::<String>("LANGUAGE")
+        .help("Only return files in this language (e.g. rust or rs); repeat to allow several")
+        .parse(|language| normalize_language(&language).map(str::to_string))
+        .many();
+
+    let within = long("in")
+        .argument::<String>("DIR")
+        .help("Only return files under DIR, relative to the indexed directory, e.g. services/api")
+        .optional();
+
+    let excluded_extensions = long("not-ext")
+        .argument::<String>("EXT")
+        .help("Leave out files with this extension (e.g. json); repeat to leave out several")
+        .many();
 
-    construct!(BeetleCommand::Search {
-        index_name(),
+    let excluded_paths = long("exclude-path")
+        .argument::<String>("PATH")
+        .help(
+            "Leave out the files under PATH, or the file at PATH, relative to the indexed \
+             directory (e.g. vendor); repeat to leave out several",
+        )
+        .many();
+
+    let author = long("author")
+        .argument::<String>("NAME")
+        .help(
+            "Only return files last committed by an author with these words in their name, e.g. \
+             alice; the index must be created with --git-blame",
+        )
+        .optional();
+
+    let branches = long("branch")
+        .argument::<String>("BRANCH")
+        .help(
+            "Only return files of BRANCH; repeat to allow several. The index must be created \
+             with --branch",
+        )
+        .many();
+
+    let changed_since = long("changed-since")
+        .argument::<String>("REV")
+        .help(
+            "Only return files added or modified between REV, e.g. a commit, tag or branch, and \
+             HEAD, as git diff lists them in the indexed folder",
+        )
+        .optional();
+
+    let modified_after = long("modified-after")
+        .argument::<String>("TIME")
+        .help(
+            "Only return files last modified at or after TIME: a date (2024-05-01), an RFC 3339 \
+             timestamp or an age such as 30m, 12h, 7d or 2w",
+        )
+        .parse(|time| parse_modified_time(&time))
+        .optional();
+
+    let modified_before = long("modified-before")
+        .argument::<String>("TIME")
+        .help("Only return files last modified before TIME, in the same forms")
+        .parse(|time| parse_modified_time(&time))
+        .optional();
+
+    let boosts = long("boost")
+        .argument::<String>("BOOSTS")
+        .help("Override the index's ranking weights of fields for this search, e.g. path=5")
+        .parse(|boosts| boosts.parse::<FieldBoosts>())
+        .fallback(FieldBoosts::default());
+
+    let facets = long("facets")
+        .switch()
+        .help("Print the number of results in every language to stderr");
+
+    let stats = long("stats")
+        .switch()
+        .help("Print the time, CPU and memory used by the search to stderr");
+
+    let no_history = long("no-history")
+        .switch()
+        .help("Do not record the search in the history of `beetle history`");
+
+    construct!(
+        scope,
         query,
-        format()
-    })
+        format(),
+        color(),
+        max_query_cost,
+        force,
+        highlight_tag,
+        highlight_class,
+        snippet_chars,
+        max_fragments,
+        languages,
+        within,
+        excluded_extensions,
+        excluded_paths,
+        author,
+        branches,
+        changed_since,
+        modified_after,
+        modified_before,
+        boosts,
+        facets,
+        stats,
+        no_history
+    )
+    .map(
+        |(
+            scope,
+            (query, symbols, exact, commits),
+            format,
+            color,
+            max_query_cost,
+            force,
+            highlight_tag,
+            highlight_class,
+            snippet_chars,
+            max_fragments,
+            languages,
+            within,
+            excluded_extensions,
+            excluded_paths,
+            author,
+            branches,
+            changed_since,
+            modified_after,
+            modified_before,
+            boosts,
+            facets,
+            stats,
+            no_history,
+        )| BeetleCommand::Search {
+            scope,
+            query,
+            symbols,
+            exact,
+            commits,
+            format,
+            color,
+            max_query_cost,
+            force,
+            highlight_tag,
+            highlight_class,
+            snippet_chars,
+            max_fragments,
+            languages,
+            within,
+            excluded_extensions,
+            excluded_paths,
+            author,
+            branches,
+            changed_since,
+            modified_after,
+            modified_before,
+            boosts,
+            facets,
+            stats,
+            no_history,
+        },
+    )
     .to_options()
 }
+
+/// A query read from `source`, without the line break files and pipes end with.
+fn query_text(text: String, source: &str) -> Result<String, String> {
+    let query = text.trim_end_matches(['\n', '\r']);
+    if query.trim().is_empty() {
+        return Err(format!("The query read from {source} is empty"));
+    }
+
+    Ok(query.to_string())
+}
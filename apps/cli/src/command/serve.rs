@@ -1,14 +1,173 @@
-use super::BeetleCommand;
+use super::{writer_resources, BeetleCommand};
+use crate::auth::OidcConfig;
+use crate::config::parse_interval;
+use crate::rate_limit::RateLimit;
 use bpaf::*;
+use engine::search::DEFAULT_MAX_QUERY_COST;
+
+/// Manages a server started with `beetle serve --daemon` instead of starting one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServeControl {
+    Status,
+    Stop,
+}
 
 pub fn serve_command() -> OptionParser<BeetleCommand> {
     let port = long("port")
         .short('p')
-        .help("Port to bind the server to")
+        .help("Port to bind the server to; 3000 by default")
         .argument("PORT")
-        .fallback(3000);
+        .optional();
+
+    let host = long("host")
+        .help(
+            "Address to bind the server to, e.g. 0.0.0.0 for every interface; localhost by default",
+        )
+        .argument::<String>("HOST")
+        .optional();
+
+    let require_token = long("require-token")
+        .switch()
+        .help("Reject API requests that do not carry a valid token");
+
+    let token = long("token")
+        .env("BEETLE_SERVER_TOKEN")
+        .argument::<String>("TOKEN")
+        .help("Admin bearer token granting full API access; implies --require-token")
+        .optional();
+
+    let issuer = long("oidc-issuer")
+        .env("BEETLE_OIDC_ISSUER")
+        .argument::<String>("URL")
+        .help("Also accept access tokens issued by this OIDC provider; implies --require-token");
+
+    let audience = long("oidc-audience")
+        .env("BEETLE_OIDC_AUDIENCE")
+        .argument::<String>("AUDIENCE")
+        .help("Audience (`aud` claim) OIDC access tokens must be issued for");
+
+    let jwks_url = long("oidc-jwks-url")
+        .argument::<String>("URL")
+        .help("URL of the provider's signing keys; discovered from the issuer by default")
+        .optional();
+
+    let indexes_claim = long("oidc-indexes-claim")
+        .argument::<String>("CLAIM")
+        .help("Claim listing the indexes an OIDC caller may search; every index by default")
+        .optional();
+
+    let oidc = construct!(OidcConfig {
+        issuer,
+        audience,
+        jwks_url,
+        indexes_claim,
+    })
+    .optional();
+
+    let max_query_cost = long("max-query-cost")
+        .argument::<u64>("TERMS")
+        .help("Refuse search queries expanding to more terms than this unless `force=true`")
+        .fallback(DEFAULT_MAX_QUERY_COST);
+
+    let min_update_interval = long("min-update-interval")
+        .argument::<u64>("SECONDS")
+        .help("Minimum time between two background rebuilds of the same index")
+        .fallback(5);
+
+    let requests_per_minute = long("rate-limit")
+        .argument::<u32>("REQUESTS")
+        .help("Maximum search requests per minute for each API token or client IP");
+
+    let burst = long("rate-limit-burst")
+        .argument::<u32>("REQUESTS")
+        .help("Search requests a client may send at once before --rate-limit applies")
+        .fallback(10);
+
+    let rate_limit = construct!(RateLimit {
+        requests_per_minute,
+        burst,
+    })
+    .optional();
+
+    let cors_origins = long("cors-origin")
+        .argument::<String>("ORIGIN")
+        .help("Origin allowed to call the API from a browser, or `*` for any; repeatable")
+        .many();
+
+    let cors_methods = long("cors-method")
+        .argument::<String>("METHOD")
+        .help("HTTP method allowed for cross-origin requests; repeatable, GET and POST by default")
+        .many();
+
+    let daemon = long("daemon")
+        .switch()
+        .help("Run the server in the background; manage it with --status and --stop");
+
+    let status = long("status")
+        .help("Show whether a background server is running")
+        .req_flag(ServeControl::Status);
+
+    let stop = long("stop")
+        .help("Stop the background server")
+        .req_flag(ServeControl::Stop);
+
+    let control = construct!([status, stop]).optional();
+
+    let max_index_jobs = long("max-concurrent-index-jobs")
+        .argument::<usize>("JOBS")
+        .help(
+            "Index creations, updates and rebuilds running at once; later ones wait for a slot, \
+             unlimited by default",
+        )
+        .optional();
+
+    let reader_threads = long("reader-threads")
+        .argument::<usize>("THREADS")
+        .help(
+            "Threads reading and parsing files, shared by every index job; one per core by \
+             default",
+        )
+        .optional();
+
+    let read_only = long("read-only").switch().help(
+        "Refuse every API request that writes, such as creating, updating or deleting indexes",
+    );
+
+    let auto_update = long("auto-update")
+        .argument::<String>("INTERVAL")
+        .help("Update the indexes incrementally every INTERVAL, e.g. 15m or 1h")
+        .parse(|interval| parse_interval(&interval))
+        .optional();
+
+    let auto_update_indexes = long("auto-update-index")
+        .argument::<String>("INDEX_NAME")
+        .help("Index --auto-update updates; repeatable, every index by default")
+        .many();
+
+    let no_history = long("no-history")
+        .switch()
+        .help("Do not record the searches served in the history of `beetle history`");
 
-    construct!(port)
-        .map(|port| BeetleCommand::Serve { port })
-        .to_options()
+    construct!(BeetleCommand::Serve {
+        port,
+        host,
+        require_token,
+        token,
+        oidc,
+        max_query_cost,
+        min_update_interval,
+        rate_limit,
+        cors_origins,
+        cors_methods,
+        daemon,
+        control,
+        max_index_jobs,
+        reader_threads,
+        writer_resources(),
+        read_only,
+        auto_update,
+        auto_update_indexes,
+        no_history
+    })
+    .to_options()
 }
@@ -0,0 +1,42 @@
+use super::{format, index_name, BeetleCommand};
+use bpaf::*;
+
+#[derive(Debug, Clone)]
+pub enum SnapshotAction {
+    Create { index_name: String },
+    List { index_name: Option<String> },
+    Restore { index_name: String, id: String },
+}
+
+pub fn snapshot_command() -> OptionParser<BeetleCommand> {
+    let create = {
+        let index_name = index_name();
+        construct!(SnapshotAction::Create { index_name })
+            .to_options()
+            .command("create")
+            .help("Save a point-in-time copy of an index")
+    };
+
+    let list = {
+        let index_name = index_name().optional();
+        construct!(SnapshotAction::List { index_name })
+            .to_options()
+            .command("list")
+            .help("List the snapshots of an index, or of every index")
+    };
+
+    let restore = {
+        let index_name = index_name();
+        let id = long("id")
+            .argument::<String>("SNAPSHOT_ID")
+            .help("Identifier of the snapshot to restore, as shown by `snapshot list`");
+        construct!(SnapshotAction::Restore { index_name, id })
+            .to_options()
+            .command("restore")
+            .help("Replace an index with one of its snapshots")
+    };
+
+    let action = construct!([create, list, restore]);
+
+    construct!(BeetleCommand::Snapshot { format(), action }).to_options()
+}
@@ -0,0 +1,133 @@
+use super::{index_name, BeetleCommand};
+use bpaf::*;
+use engine::symbols::FileSymbols;
+use std::path::{Path, PathBuf};
+
+pub fn tags_command() -> OptionParser<BeetleCommand> {
+    let output = short('o')
+        .long("output")
+        .argument::<PathBuf>("FILE")
+        .help("File to write the tags to, `tags` (or `TAGS` with --etags) by default")
+        .optional();
+
+    let etags = long("etags")
+        .switch()
+        .help("Write an Emacs etags file instead of a ctags file");
+
+    construct!(BeetleCommand::Tags {
+        index_name(),
+        output,
+        etags
+    })
+    .to_options()
+}
+
+/// Renders a sorted ctags file. Paths are written relative to `root` when they are inside it.
+pub fn render_ctags(files: &[FileSymbols], root: &Path) -> String {
+    let mut lines = Vec::new();
+    for file in files {
+        let path = relative_path(&file.path, root);
+        for symbol in &file.symbols {
+            let pattern = symbol.line_text.replace('\\', "\\\\").replace('/', "\\/");
+            lines.push(format!(
+                "{}\t{}\t/^{}$/;\"\t{}\tline:{}",
+                symbol.name,
+                path,
+                pattern,
+                symbol.kind.ctags_kind(),
+                symbol.line
+            ));
+        }
+    }
+    lines.sort();
+
+    let mut output = String::from(
+        "!_TAG_FILE_FORMAT\t2\t/extended format/\n!_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted/\n",
+    );
+    for line in lines {
+        output.push_str(&line);
+        output.push('\n');
+    }
+    output
+}
+
+/// Renders an etags file, one section per source file.
+pub fn render_etags(files: &[FileSymbols], root: &Path) -> String {
+    let mut output = String::new();
+    for file in files {
+        let section: String = file
+            .symbols
+            .iter()
+            .map(|symbol| {
+                format!(
+                    "{}\x7f{}\x01{},{}\n",
+                    symbol.line_text, symbol.name, symbol.line, symbol.offset
+                )
+            })
+            .collect();
+
+        output.push_str(&format!(
+            "\x0c\n{},{}\n{}",
+            relative_path(&file.path, root),
+            section.len(),
+            section
+        ));
+    }
+    output
+}
+
+fn relative_path(path: &str, root: &Path) -> String {
+    Path::new(path)
+        .strip_prefix(root)
+        .map(|relative| relative.to_string_lossy().replace('\\', "/"))
+        .unwrap_or_else(|_| path.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine::symbols::{Symbol, SymbolKind};
+
+    fn files() -> Vec<FileSymbols> {
+        vec![FileSymbols {
+            path: "/repo/src/main.c".to_string(),
+            symbols: vec![
+                Symbol {
+                    name: "main".to_string(),
+                    kind: SymbolKind::Function,
+                    line: 3,
+                    offset: 20,
+                    line_text: "int main(void) /* entry */".to_string(),
+                },
+                Symbol {
+                    name: "MAX".to_string(),
+                    kind: SymbolKind::Macro,
+                    line: 1,
+                    offset: 0,
+                    line_text: "#define MAX 10".to_string(),
+                },
+            ],
+        }]
+    }
+
+    #[test]
+    fn test_render_ctags() {
+        assert_eq!(
+            render_ctags(&files(), Path::new("/repo")),
+            "!_TAG_FILE_FORMAT\t2\t/extended format/\n\
+             !_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted/\n\
+             MAX\tsrc/main.c\t/^#define MAX 10$/;\"\td\tline:1\n\
+             main\tsrc/main.c\t/^int main(void) \\/* entry *\\/$/;\"\tf\tline:3\n"
+        );
+    }
+
+    #[test]
+    fn test_render_etags() {
+        let section = "int main(void) /* entry */\x7fmain\x013,20\n#define MAX 10\x7fMAX\x011,0\n";
+
+        assert_eq!(
+            render_etags(&files(), Path::new("/elsewhere")),
+            format!("\x0c\n/repo/src/main.c,{}\n{section}", section.len())
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use super::{format, BeetleCommand};
+use bpaf::*;
+
+#[derive(Debug, Clone)]
+pub enum TokenAction {
+    Create { indexes: Vec<String> },
+    List,
+    Revoke { id: String },
+}
+
+pub fn token_command() -> OptionParser<BeetleCommand> {
+    let indexes = long("index")
+        .short('i')
+        .argument::<String>("INDEX_NAME")
+        .help("Index the token may search; repeat to grant several indexes")
+        .some("At least one --index is required");
+    let create = construct!(TokenAction::Create { indexes })
+        .to_options()
+        .command("create")
+        .help("Create a read-only token scoped to the given indexes");
+
+    let list = pure(TokenAction::List)
+        .to_options()
+        .command("list")
+        .help("List issued tokens");
+
+    let id = long("id")
+        .argument::<String>("TOKEN_ID")
+        .help("Identifier of the token to revoke");
+    let revoke = construct!(TokenAction::Revoke { id })
+        .to_options()
+        .command("revoke")
+        .help("Revoke a token");
+
+    let action = construct!([create, list, revoke]);
+
+    construct!(BeetleCommand::Token { format(), action }).to_options()
+}
@@ -1,13 +1,35 @@
-use super::{index_name, BeetleCommand};
+use super::{format, index_name, wait, writer_resources, BeetleCommand};
 
 use bpaf::*;
+use engine::options::ChangeDetection;
 
 pub fn update_command() -> OptionParser<BeetleCommand> {
     let reindex = long("reindex").switch().help("Perform full reindex");
 
+    let change_detection = long("change-detection")
+        .argument::<String>("MODE")
+        .help(
+            "How changed files are found: fast (size and modification time), hash (size and \
+             content) or auto (default; content of files whose time changed but not their size)",
+        )
+        .parse(|mode| mode.parse::<ChangeDetection>())
+        .fallback(ChangeDetection::default());
+
+    let dry_run = long("dry-run")
+        .help(
+            "List the files the update would add, modify, remove, move and skip, without \
+             writing to the index",
+        )
+        .switch();
+
     construct!(BeetleCommand::Update {
         index_name(),
-        reindex
+        reindex,
+        wait(),
+        writer_resources(),
+        change_detection,
+        dry_run,
+        format()
     })
     .to_options()
 }
@@ -0,0 +1,16 @@
+use super::{format, index_name, BeetleCommand};
+use bpaf::*;
+
+pub fn verify_command() -> OptionParser<BeetleCommand> {
+    let repair = long("repair").switch().help(
+        "Drop damaged segments, rebuild the file snapshot and reindex the files that lost their \
+         documents (or the whole index if it cannot be opened)",
+    );
+
+    construct!(BeetleCommand::Verify {
+        index_name(),
+        repair,
+        format()
+    })
+    .to_options()
+}
@@ -0,0 +1,164 @@
+//! The `serve` block of the config file, see [`crate::home`], which lets a deployment keep the
+//! settings of `beetle serve` in the file rather than on its command line:
+//!
+//! ```json
+//! {
+//!   "home": "/data/beetle",
+//!   "serve": {
+//!     "port": 8080,
+//!     "host": "0.0.0.0",
+//!     "token": "admin-secret",
+//!     "cors_origins": ["https://wiki.example.com"],
+//!     "read_only": true,
+//!     "auto_update": { "interval": "15m", "indexes": ["api"] }
+//!   }
+//! }
+//! ```
+//!
+//! Flags given to `beetle serve` override the values of the file.
+
+use serde::Deserialize;
+use std::path::Path;
+use std::time::Duration;
+
+/// Settings of `beetle serve` read from the config file; every one is optional.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServeConfig {
+    pub port: Option<u16>,
+    /// Address to bind, e.g. `0.0.0.0` to accept connections from other machines.
+    pub host: Option<String>,
+    /// Admin bearer token, as `--token`.
+    pub token: Option<String>,
+    pub require_token: bool,
+    pub cors_origins: Vec<String>,
+    pub cors_methods: Vec<String>,
+    pub read_only: bool,
+    pub auto_update: Option<AutoUpdateConfig>,
+}
+
+/// Updates indexes on a schedule, as `--auto-update` and `--auto-update-index`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutoUpdateConfig {
+    /// Time between two updates, e.g. `90s`, `15m` or `1h`.
+    pub interval: String,
+    /// Indexes to update; every index when empty.
+    #[serde(default)]
+    pub indexes: Vec<String>,
+}
+
+impl ServeConfig {
+    /// The `serve` block of the config file at `path`; the defaults when there is no file or
+    /// the file has no such block. A file that cannot be parsed is an error here, where the home
+    /// resolution only warns about it, so that a server never runs without the settings it was
+    /// given.
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let Some(path) = path else {
+            return Ok(ServeConfig::default());
+        };
+        let json = match std::fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(ServeConfig::default()),
+            Err(e) => {
+                return Err(format!(
+                    "Failed to read config file {}: {e}",
+                    path.display()
+                ))
+            }
+        };
+
+        #[derive(Deserialize)]
+        struct ConfigFile {
+            #[serde(default)]
+            serve: ServeConfig,
+        }
+        let config: ConfigFile = serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid config file {}: {e}", path.display()))?;
+
+        Ok(config.serve)
+    }
+}
+
+/// Parses intervals such as `90`, `90s`, `15m`, `1h` or `1d`; bare numbers are seconds.
+pub fn parse_interval(interval: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid interval '{interval}'. Use e.g. 90s, 15m, 1h or 1d");
+    let interval = interval.trim();
+    let split = interval
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(interval.len());
+    let (amount, unit) = interval.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let unit_secs = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    if amount == 0 {
+        return Err(invalid());
+    }
+
+    Ok(Duration::from_secs(amount * unit_secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+
+        // No file, or no block, is the defaults
+        assert_eq!(ServeConfig::load(None).unwrap(), ServeConfig::default());
+        assert_eq!(
+            ServeConfig::load(Some(&path)).unwrap(),
+            ServeConfig::default()
+        );
+        std::fs::write(&path, r#"{"home": "/data/beetle"}"#).unwrap();
+        assert_eq!(
+            ServeConfig::load(Some(&path)).unwrap(),
+            ServeConfig::default()
+        );
+
+        std::fs::write(
+            &path,
+            r#"{"serve": {"port": 8080, "host": "0.0.0.0", "read_only": true,
+                "auto_update": {"interval": "15m", "indexes": ["api"]}}}"#,
+        )
+        .unwrap();
+        let config = ServeConfig::load(Some(&path)).unwrap();
+        assert_eq!(config.port, Some(8080));
+        assert_eq!(config.host.as_deref(), Some("0.0.0.0"));
+        assert!(config.read_only);
+        assert!(!config.require_token);
+        assert_eq!(
+            config.auto_update,
+            Some(AutoUpdateConfig {
+                interval: "15m".to_string(),
+                indexes: vec!["api".to_string()]
+            })
+        );
+
+        // Typos are reported rather than ignored
+        std::fs::write(&path, r#"{"serve": {"prot": 8080}}"#).unwrap();
+        assert!(ServeConfig::load(Some(&path))
+            .unwrap_err()
+            .contains("unknown field `prot`"));
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_interval("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_interval("15m").unwrap(), Duration::from_secs(900));
+        assert_eq!(parse_interval("1h").unwrap(), Duration::from_secs(3600));
+        assert_eq!(parse_interval("2d").unwrap(), Duration::from_secs(172_800));
+        assert!(parse_interval("0m").is_err());
+        assert!(parse_interval("15 minutes").is_err());
+        assert!(parse_interval("m").is_err());
+    }
+}
@@ -0,0 +1,363 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tokio::sync::Notify;
+
+/// Set in the environment of the background process started by `beetle serve --daemon`.
+const DAEMON_ENV_VAR: &str = "BEETLE_DAEMON_CHILD";
+/// How long `--daemon` and `--stop` wait for the server to come up or go away.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long a control command may take to answer.
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Describes a server running in the background; also the contents of the pidfile.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonInfo {
+    pub pid: u32,
+    pub port: u16,
+    pub started_at: u64,
+}
+
+impl DaemonInfo {
+    pub fn current(port: u16) -> Self {
+        DaemonInfo {
+            pid: std::process::id(),
+            port,
+            started_at: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        }
+    }
+
+    pub fn uptime(&self) -> Duration {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        Duration::from_secs(now.saturating_sub(self.started_at))
+    }
+}
+
+/// The files of a `beetle serve --daemon` instance under `BEETLE_HOME`: the pidfile, the control
+/// socket answering `status` and `stop`, and the log the server's output goes to.
+///
+/// There is at most one daemon per `BEETLE_HOME`.
+pub struct Daemon {
+    beetle_home: PathBuf,
+}
+
+impl Daemon {
+    pub fn new(beetle_home: PathBuf) -> Self {
+        Daemon { beetle_home }
+    }
+
+    /// Whether this process is the background server started by [`Daemon::spawn`].
+    pub fn is_daemon_process() -> bool {
+        std::env::var_os(DAEMON_ENV_VAR).is_some()
+    }
+
+    pub fn pid_path(&self) -> PathBuf {
+        self.beetle_home.join(Self::PID_FILE_NAME)
+    }
+
+    pub fn socket_path(&self) -> PathBuf {
+        self.beetle_home.join(Self::SOCKET_FILE_NAME)
+    }
+
+    pub fn log_path(&self) -> PathBuf {
+        self.beetle_home.join(Self::LOG_FILE_NAME)
+    }
+
+    /// Starts this executable again with the same arguments as a detached background process
+    /// and waits until it answers on the control socket.
+    pub fn spawn(&self) -> Result<DaemonInfo, String> {
+        if let Some(info) = self.status()? {
+            return Err(format!(
+                "A beetle server is already running (pid {}, port {})",
+                info.pid, info.port
+            ));
+        }
+
+        fs::create_dir_all(&self.beetle_home)
+            .map_err(|e| format!("Failed to create directory {:?}: {e}", self.beetle_home))?;
+        let log = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_path())
+            .map_err(|e| format!("Failed to open log file {:?}: {e}", self.log_path()))?;
+        let log_for_stderr = log
+            .try_clone()
+            .map_err(|e| format!("Failed to open log file {:?}: {e}", self.log_path()))?;
+        let executable = std::env::current_exe()
+            .map_err(|e| format!("Failed to locate the beetle executable: {e}"))?;
+
+        let mut command = Command::new(executable);
+        command
+            .args(std::env::args_os().skip(1))
+            .env(DAEMON_ENV_VAR, "1")
+            .env("BEETLE_HOME", &self.beetle_home)
+            // The home is already the tenant's when one was set
+            .env_remove("BEETLE_TENANT")
+            .stdin(Stdio::null())
+            .stdout(log)
+            .stderr(log_for_stderr);
+        #[cfg(unix)]
+        {
+            // Leave the terminal's process group so Ctrl+C in the shell does not reach the server
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("Failed to start the background server: {e}"))?;
+
+        let started = std::time::Instant::now();
+        while started.elapsed() < STARTUP_TIMEOUT {
+            if let Some(status) = child
+                .try_wait()
+                .map_err(|e| format!("Failed to check the background server: {e}"))?
+            {
+                return Err(format!(
+                    "The background server exited during startup ({status}); see {}",
+                    self.log_path().display()
+                ));
+            }
+            // Not `status`, which would remove the files the starting server is writing
+            if let Ok(Some(info)) = self.send("status") {
+                if info.pid == child.id() {
+                    return Ok(info);
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        Err(format!(
+            "The background server did not start within {}s; see {}",
+            STARTUP_TIMEOUT.as_secs(),
+            self.log_path().display()
+        ))
+    }
+
+    /// Asks the running daemon for its status; `None` when no daemon is running.
+    pub fn status(&self) -> Result<Option<DaemonInfo>, String> {
+        let status = self.send("status")?;
+        if status.is_none() {
+            self.remove_stale_files();
+        }
+
+        Ok(status)
+    }
+
+    /// Asks the running daemon to shut down gracefully and waits until it has.
+    pub fn stop(&self) -> Result<Option<DaemonInfo>, String> {
+        let Some(info) = self.send("stop")? else {
+            self.remove_stale_files();
+            return Ok(None);
+        };
+
+        let started = std::time::Instant::now();
+        while self.socket_path().exists() {
+            if started.elapsed() >= STARTUP_TIMEOUT {
+                return Err(format!(
+                    "The server (pid {}) did not stop within {}s",
+                    info.pid,
+                    STARTUP_TIMEOUT.as_secs()
+                ));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        Ok(Some(info))
+    }
+
+    #[cfg(unix)]
+    fn send(&self, command: &str) -> Result<Option<DaemonInfo>, String> {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixStream;
+
+        let Ok(mut stream) = UnixStream::connect(self.socket_path()) else {
+            return Ok(None);
+        };
+        let control_error = |e: std::io::Error| format!("Failed to reach the server: {e}");
+        stream
+            .set_read_timeout(Some(CONTROL_TIMEOUT))
+            .map_err(control_error)?;
+        writeln!(stream, "{command}").map_err(control_error)?;
+
+        let mut response = String::new();
+        BufReader::new(stream)
+            .read_line(&mut response)
+            .map_err(control_error)?;
+        serde_json::from_str(&response)
+            .map(Some)
+            .map_err(|e| format!("Unexpected response from the server: {e}"))
+    }
+
+    #[cfg(not(unix))]
+    fn send(&self, _command: &str) -> Result<Option<DaemonInfo>, String> {
+        Err("Daemon mode is only supported on Unix".to_string())
+    }
+
+    /// Writes the pidfile and answers control commands in the background; `stop` notifies
+    /// `shutdown`.
+    #[cfg(unix)]
+    pub fn listen(&self, info: DaemonInfo, shutdown: Arc<Notify>) -> Result<(), String> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        use tokio::net::UnixListener;
+
+        let socket_path = self.socket_path();
+        // A socket that nothing answers on was left behind by a crashed server
+        let _ = fs::remove_file(&socket_path);
+        let listener = UnixListener::bind(&socket_path)
+            .map_err(|e| format!("Failed to bind control socket {socket_path:?}: {e}"))?;
+
+        let response = serde_json::to_string(&info)
+            .map_err(|e| format!("Failed to serialize daemon status: {e}"))?;
+        fs::write(self.pid_path(), &response)
+            .map_err(|e| format!("Failed to write pidfile {:?}: {e}", self.pid_path()))?;
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (reader, mut writer) = stream.into_split();
+                let mut command = String::new();
+                if BufReader::new(reader)
+                    .read_line(&mut command)
+                    .await
+                    .is_err()
+                {
+                    continue;
+                }
+
+                let _ = writer.write_all(format!("{response}\n").as_bytes()).await;
+                if command.trim() == "stop" {
+                    shutdown.notify_one();
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn listen(&self, _info: DaemonInfo, _shutdown: Arc<Notify>) -> Result<(), String> {
+        Err("Daemon mode is only supported on Unix".to_string())
+    }
+
+    /// Removes the pidfile and control socket once the server has stopped.
+    pub fn remove_files(&self) {
+        let _ = fs::remove_file(self.pid_path());
+        let _ = fs::remove_file(self.socket_path());
+    }
+
+    /// Removes the files left behind by a server that did not shut down cleanly, once the
+    /// process its pidfile records is gone. A starting server binds its socket before writing
+    /// its pidfile, so a socket without a pidfile is left alone.
+    fn remove_stale_files(&self) {
+        let recorded = fs::read_to_string(self.pid_path())
+            .ok()
+            .and_then(|json| serde_json::from_str::<DaemonInfo>(&json).ok());
+        if recorded.is_some_and(|info| !is_running(info.pid)) {
+            self.remove_files();
+        }
+    }
+
+    pub const PID_FILE_NAME: &'static str = "serve.pid";
+    pub const SOCKET_FILE_NAME: &'static str = "serve.sock";
+    pub const LOG_FILE_NAME: &'static str = "serve.log";
+}
+
+/// Whether the process `pid` exists; signal 0 is not delivered, only checked.
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_status_and_stop() {
+        let home = tempfile::tempdir().unwrap();
+        let daemon = Arc::new(Daemon::new(home.path().to_path_buf()));
+        let shutdown = Arc::new(Notify::new());
+        daemon
+            .listen(DaemonInfo::current(3000), shutdown.clone())
+            .unwrap();
+        // What the server does once it has stopped
+        let server = daemon.clone();
+        tokio::spawn(async move {
+            shutdown.notified().await;
+            server.remove_files();
+        });
+
+        let client = daemon.clone();
+        let status = tokio::task::spawn_blocking(move || client.status())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(status.pid, std::process::id());
+        assert_eq!(status.port, 3000);
+
+        let client = daemon.clone();
+        let stopped = tokio::task::spawn_blocking(move || client.stop())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stopped.unwrap().pid, std::process::id());
+        assert!(!daemon.pid_path().exists());
+        assert!(!daemon.socket_path().exists());
+    }
+
+    #[test]
+    fn test_stale_files_are_removed_once_their_process_is_gone() {
+        let home = tempfile::tempdir().unwrap();
+        let daemon = Daemon::new(home.path().to_path_buf());
+        let record = |pid| {
+            let info = DaemonInfo {
+                pid,
+                port: 3000,
+                started_at: 0,
+            };
+            fs::write(daemon.pid_path(), serde_json::to_string(&info).unwrap()).unwrap();
+            // Nothing listens on it
+            fs::write(daemon.socket_path(), "").unwrap();
+        };
+
+        // A server that is running but does not answer yet, e.g. while starting
+        record(std::process::id());
+        assert!(daemon.status().unwrap().is_none());
+        assert!(daemon.pid_path().exists());
+        assert!(daemon.socket_path().exists());
+
+        let mut exited = Command::new("true").spawn().unwrap();
+        exited.wait().unwrap();
+        record(exited.id());
+        assert!(daemon.status().unwrap().is_none());
+        assert!(!daemon.pid_path().exists());
+        assert!(!daemon.socket_path().exists());
+
+        // A socket without a pidfile belongs to a server that has not written it yet
+        fs::write(daemon.socket_path(), "").unwrap();
+        assert!(daemon.stop().unwrap().is_none());
+        assert!(daemon.socket_path().exists());
+    }
+}
@@ -0,0 +1,251 @@
+use crate::jsonrpc::{self, RpcError, RpcHandler};
+use engine::options::FieldBoosts;
+use engine::search::{language_counts, IndexSearcher, SearchOptions};
+use engine::IndexCatalog;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+/// Serves editor plugins over stdio JSON-RPC, keeping opened indexes in memory between requests.
+///
+/// Plugins start one `beetle editor-server` per editor session instead of spawning the CLI for
+/// every search, so searches skip opening the index. Opened indexes pick up commits made by other
+/// processes (e.g. `beetle update`) on their own.
+pub struct EditorServer<'a> {
+    catalog: &'a IndexCatalog,
+    searchers: HashMap<String, IndexSearcher>,
+    /// The index searched when a request does not name one; the last one opened.
+    current: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IndexParams {
+    index: String,
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    query: String,
+    /// The last opened index when omitted.
+    index: Option<String>,
+    limit: Option<usize>,
+    #[serde(default)]
+    force: bool,
+    highlight_tag: Option<String>,
+    highlight_class: Option<String>,
+    /// Languages results are restricted to.
+    #[serde(default)]
+    languages: Vec<String>,
+}
+
+impl<'a> EditorServer<'a> {
+    pub fn new(catalog: &'a IndexCatalog) -> Self {
+        EditorServer {
+            catalog,
+            searchers: HashMap::new(),
+            current: None,
+        }
+    }
+
+    /// Answers requests read from `input` until it is closed.
+    pub fn run(mut self, input: impl BufRead, output: impl Write) -> Result<(), String> {
+        jsonrpc::serve(&mut self, input, output)
+    }
+
+    fn searcher(&mut self, index_name: &str) -> Result<&IndexSearcher, String> {
+        if !self.searchers.contains_key(index_name) {
+            let searcher = self.catalog.get_searcher(index_name)?;
+            self.searchers.insert(index_name.to_string(), searcher);
+        }
+
+        Ok(&self.searchers[index_name])
+    }
+
+    fn open_index(&mut self, params: IndexParams) -> Result<Value, RpcError> {
+        self.searcher(&params.index)?;
+        self.current = Some(params.index.clone());
+
+        self.index_status(params)
+    }
+
+    fn index_status(&self, params: IndexParams) -> Result<Value, RpcError> {
+        let metadata = self.catalog.get_matadata(&params.index)?;
+        let stats = self.catalog.stats(&params.index)?;
+
+        Ok(json!({
+            "index": metadata.index_name,
+            "path": metadata.target_path,
+            "tags": metadata.tags,
+            "open": self.searchers.contains_key(&params.index),
+            "docs": stats.docs,
+            "size_bytes": stats.size_bytes,
+            "updated_at": stats.updated_at,
+        }))
+    }
+
+    fn search(&mut self, params: SearchParams) -> Result<Value, RpcError> {
+        let index_name = params
+            .index
+            .or_else(|| self.current.clone())
+            .ok_or("No index given and none opened with open_index".to_string())?;
+        let defaults = SearchOptions::default();
+        let options = SearchOptions {
+            max_query_cost: defaults.max_query_cost.filter(|_| !params.force),
+            highlight_tag: params.highlight_tag.unwrap_or(defaults.highlight_tag),
+            highlight_class: params.highlight_class,
+            languages: params.languages,
+            modified_after: None,
+            modified_before: None,
+            boosts: FieldBoosts::default(),
+            exact: false,
+            snippet_chars: defaults.snippet_chars,
+            max_fragments: defaults.max_fragments,
+            within: None,
+            excluded_extensions: Vec::new(),
+            excluded_paths: Vec::new(),
+            author: None,
+            branches: Vec::new(),
+            changed_since: None,
+        };
+
+        let started = std::time::Instant::now();
+        let mut results = self
+            .searcher(&index_name)?
+            .search_with_options(&params.query, &options)
+            .map_err(String::from)?;
+        let total_results = results.len();
+        let languages = language_counts(&results);
+        if let Some(limit) = params.limit {
+            results.truncate(limit);
+        }
+
+        Ok(json!({
+            "index": index_name,
+            "results": results,
+            "total_results": total_results,
+            "languages": languages,
+            "duration_ms": started.elapsed().as_secs_f64() * 1000.0,
+        }))
+    }
+
+    fn list_indexes(&self) -> Result<Value, RpcError> {
+        let indexes: Vec<Value> = self
+            .catalog
+            .list()?
+            .into_iter()
+            .map(|index| {
+                json!({
+                    "index": index.index_name,
+                    "path": index.target_path,
+                    "tags": index.tags,
+                    "open": self.searchers.contains_key(&index.index_name),
+                })
+            })
+            .collect();
+
+        Ok(Value::Array(indexes))
+    }
+}
+
+impl RpcHandler for EditorServer<'_> {
+    fn handle_request(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "open_index" => self.open_index(jsonrpc::parse_params(params)?),
+            "close_index" => {
+                let params: IndexParams = jsonrpc::parse_params(params)?;
+                self.searchers.remove(&params.index);
+                if self.current.as_ref() == Some(&params.index) {
+                    self.current = None;
+                }
+                Ok(Value::Null)
+            }
+            "index_status" => self.index_status(jsonrpc::parse_params(params)?),
+            "list_indexes" => self.list_indexes(),
+            "search" => self.search(jsonrpc::parse_params(params)?),
+            _ => Err(RpcError::method_not_found(method)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::{respond, INVALID_PARAMS, REQUEST_FAILED};
+    use engine::storage::RamStorage;
+
+    #[test]
+    fn test_requests_fail_without_index() {
+        let catalog = IndexCatalog::new(RamStorage::new());
+        let mut server = EditorServer::new(&catalog);
+
+        let response = respond(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "search", "params": { "query": "fn" } }),
+        )
+        .unwrap();
+        assert_eq!(response["error"]["code"], REQUEST_FAILED);
+
+        let response = respond(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "open_index", "params": { "index": "x" } }),
+        )
+        .unwrap();
+        assert_eq!(response["error"]["code"], REQUEST_FAILED);
+
+        let response = respond(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "open_index" }),
+        )
+        .unwrap();
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+
+        let response = respond(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 4, "method": "list_indexes" }),
+        )
+        .unwrap();
+        assert_eq!(response["result"], json!([]));
+    }
+
+    #[test]
+    fn test_open_index_and_search() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("parser.rs"), "fn parse_header() {}").unwrap();
+        std::fs::write(dir.path().join("notes.md"), "nothing to see").unwrap();
+        let catalog = IndexCatalog::new(RamStorage::new());
+        let options = engine::options::IndexingOptions::default();
+        catalog
+            .create("code", &dir.path().to_string_lossy(), &options, &[])
+            .unwrap();
+        catalog.get_writer("code", false).unwrap().index().unwrap();
+        let mut server = EditorServer::new(&catalog);
+
+        let response = respond(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "open_index", "params": { "index": "code" } }),
+        )
+        .unwrap();
+        assert_eq!(response["result"]["open"], true);
+        assert_eq!(response["result"]["docs"], 2);
+
+        // The opened index is searched when the request names none
+        let response = respond(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "search", "params": { "query": "header" } }),
+        )
+        .unwrap();
+        let result = &response["result"];
+        assert_eq!(result["index"], "code");
+        assert_eq!(result["total_results"], 1);
+        let path = result["results"][0]["path"].as_str().unwrap();
+        assert!(path.ends_with("/parser.rs"), "{path}");
+
+        let response = respond(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "list_indexes" }),
+        )
+        .unwrap();
+        assert_eq!(response["result"][0]["open"], true);
+    }
+}
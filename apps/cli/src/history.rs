@@ -0,0 +1,253 @@
+use engine::ALL_INDEXES;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+
+/// Size the history file is compacted at, down to its last [`ENTRIES_KEPT`] entries.
+const MAX_HISTORY_FILE_SIZE: u64 = 1024 * 1024;
+const ENTRIES_KEPT: usize = 1000;
+
+/// A search that ran, from the command line or the HTTP API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct HistoryEntry {
+    /// When it ran, in seconds since the Unix epoch.
+    pub time: i64,
+    /// The searched index, the comma-separated indexes of a search over several, or `_all`.
+    pub index: String,
+    pub query: String,
+    /// The query named definitions, see `beetle search --symbols`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub symbols: bool,
+    /// The query was matched verbatim, see `beetle search --exact`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exact: bool,
+    /// Number of results.
+    pub hits: usize,
+    pub duration_ms: f64,
+}
+
+impl HistoryEntry {
+    pub fn new(index: String, query: &str, hits: usize, duration: Duration) -> Self {
+        HistoryEntry {
+            time: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64,
+            index,
+            query: query.to_string(),
+            symbols: false,
+            exact: false,
+            hits,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+        }
+    }
+
+    pub fn with_symbols(mut self, symbols: bool) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    pub fn with_exact(mut self, exact: bool) -> Self {
+        self.exact = exact;
+        self
+    }
+
+    /// The names of the searched indexes, empty for a search of every index.
+    pub fn index_names(&self) -> Vec<&str> {
+        match self.index.as_str() {
+            ALL_INDEXES => Vec::new(),
+            index => index.split(',').collect(),
+        }
+    }
+}
+
+/// An entry as `beetle history` lists it, numbered from 1 for the most recent search so that
+/// `beetle history --run <NUMBER>` can name it.
+#[derive(Debug, Clone, PartialEq, Serialize, utoipa::ToSchema)]
+pub struct NumberedEntry {
+    pub number: usize,
+    #[serde(flatten)]
+    pub entry: HistoryEntry,
+}
+
+/// The searches that ran, appended as JSON lines to `$BEETLE_HOME/history.jsonl`.
+///
+/// The file is only appended to, by the command line and the server alike; once it grows past
+/// [`MAX_HISTORY_FILE_SIZE`] it is rewritten with its most recent entries.
+pub struct SearchHistory {
+    path: PathBuf,
+    /// Searches are only recorded when this is set; `--no-history` clears it.
+    recording: bool,
+    /// Serializes the appends of the server's handlers.
+    append: Mutex<()>,
+}
+
+impl SearchHistory {
+    pub fn open(beetle_home: PathBuf, recording: bool) -> Self {
+        SearchHistory {
+            path: beetle_home.join(Self::FILE_NAME),
+            recording,
+            append: Mutex::new(()),
+        }
+    }
+
+    /// Appends `entry`; failing to is logged, since the search itself succeeded.
+    pub fn record(&self, entry: HistoryEntry) {
+        if !self.recording {
+            return;
+        }
+        let _append = self.append.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = self.append(&entry) {
+            warn!(
+                "Failed to record the search in {}: {e}",
+                self.path.display()
+            );
+        }
+    }
+
+    fn append(&self, entry: &HistoryEntry) -> Result<(), String> {
+        let mut line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        line.push('\n');
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| e.to_string())?;
+        file.write_all(line.as_bytes()).map_err(|e| e.to_string())?;
+
+        if file.metadata().map_err(|e| e.to_string())?.len() > MAX_HISTORY_FILE_SIZE {
+            let entries = self.entries()?;
+            let kept = &entries[entries.len().saturating_sub(ENTRIES_KEPT)..];
+            let mut lines = String::new();
+            for entry in kept {
+                lines.push_str(&serde_json::to_string(entry).map_err(|e| e.to_string())?);
+                lines.push('\n');
+            }
+            // Written aside and renamed, so that a reader never sees half of it
+            let compacted = self.path.with_extension("jsonl.tmp");
+            fs::write(&compacted, lines).map_err(|e| e.to_string())?;
+            fs::rename(&compacted, &self.path).map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    /// Every recorded search, oldest first. Lines that cannot be read, e.g. the last one of a
+    /// write that was cut short, are skipped.
+    pub fn entries(&self) -> Result<Vec<HistoryEntry>, String> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(format!(
+                    "Failed to read search history {}: {e}",
+                    self.path.display()
+                ))
+            }
+        };
+
+        Ok(text
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    /// The most recent searches first, numbered, at most `limit` of them; only those of
+    /// `index_name` when given.
+    pub fn recent(
+        &self,
+        index_name: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<NumberedEntry>, String> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .rev()
+            .zip(1..)
+            .filter(|(entry, _)| {
+                index_name.is_none_or(|index_name| entry.index_names().contains(&index_name))
+            })
+            .take(limit)
+            .map(|(entry, number)| NumberedEntry { number, entry })
+            .collect())
+    }
+
+    /// The search numbered `number` by [`SearchHistory::recent`].
+    pub fn get(&self, number: usize) -> Result<HistoryEntry, String> {
+        let entries = self.entries()?;
+        number
+            .checked_sub(1)
+            .and_then(|back| entries.len().checked_sub(back + 1))
+            .map(|position| entries[position].clone())
+            .ok_or_else(|| {
+                format!(
+                    "No search numbered {number} in the history, which holds {}",
+                    entries.len()
+                )
+            })
+    }
+
+    pub fn clear(&self) -> Result<(), String> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!(
+                "Failed to clear search history {}: {e}",
+                self.path.display()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub const FILE_NAME: &'static str = "history.jsonl";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let home = tempfile::tempdir().unwrap();
+        let history = SearchHistory::open(home.path().to_path_buf(), true);
+        let entry = |index: &str, query: &str| {
+            HistoryEntry::new(index.to_string(), query, 3, Duration::from_millis(5))
+        };
+        history.record(entry("api", "parse"));
+        history.record(entry("api,web", "main").with_symbols(true));
+        history.record(entry("_all", "todo"));
+
+        let recent = history.recent(None, 10).unwrap();
+        let queries: Vec<_> = recent
+            .iter()
+            .map(|numbered| (numbered.number, numbered.entry.query.as_str()))
+            .collect();
+        assert_eq!(queries, [(1, "todo"), (2, "main"), (3, "parse")]);
+        assert!(recent[1].entry.symbols);
+
+        // Filtering keeps the numbers --run takes
+        let web: Vec<_> = history
+            .recent(Some("web"), 10)
+            .unwrap()
+            .into_iter()
+            .map(|numbered| numbered.number)
+            .collect();
+        assert_eq!(web, [2]);
+        assert_eq!(history.recent(None, 1).unwrap().len(), 1);
+        assert_eq!(history.get(3).unwrap().query, "parse");
+        assert!(history.get(4).is_err());
+        assert!(history.get(0).is_err());
+
+        // Nothing is recorded with --no-history
+        SearchHistory::open(home.path().to_path_buf(), false).record(entry("api", "skipped"));
+        assert_eq!(history.entries().unwrap().len(), 3);
+
+        history.clear().unwrap();
+        assert!(history.entries().unwrap().is_empty());
+    }
+}
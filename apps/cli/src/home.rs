@@ -0,0 +1,306 @@
+//! Where beetle keeps its indexes and state: the home directory every command, the server and
+//! its daemon resolve the same way, see [`beetle_home`].
+
+use crate::analytics::SearchAnalytics;
+use crate::auth::TokenStore;
+use crate::daemon::Daemon;
+use crate::history::SearchHistory;
+use engine::{validate_index_name, FsStorage};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Names the config file in the beetle directory of the platform's config directory.
+const CONFIG_FILE_NAME: &str = "config.json";
+/// Home of the versions that only knew `$HOME/.beetle`, still used when it exists.
+const LEGACY_HOME_DIR_NAME: &str = ".beetle";
+
+/// What told the home directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HomeSource {
+    /// The `BEETLE_HOME` environment variable.
+    Env,
+    /// The `home` of the config file.
+    ConfigFile,
+    /// `$HOME/.beetle`, which exists.
+    Legacy,
+    /// The data directory of the platform.
+    Platform,
+}
+
+impl std::fmt::Display for HomeSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = match self {
+            HomeSource::Env => "BEETLE_HOME",
+            HomeSource::ConfigFile => "config file",
+            HomeSource::Legacy => "legacy default",
+            HomeSource::Platform => "platform default",
+        };
+        write!(f, "{source}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeetleHome {
+    /// Absolute path of the directory.
+    pub path: PathBuf,
+    pub source: HomeSource,
+    /// The config file that is read, when it exists, or looked for.
+    pub config_file: Option<PathBuf>,
+    /// `BEETLE_TENANT`, whose home in `tenants` of the resolved directory is used instead.
+    pub tenant: Option<String>,
+}
+
+/// Resolves the home directory, from the first of:
+///
+/// - the `BEETLE_HOME` environment variable;
+/// - the `home` of the JSON config file at `BEETLE_CONFIG`, or else `beetle/config.json` in the
+///   config directory of the platform (`$XDG_CONFIG_HOME`, `~/.config` or `%APPDATA%`), relative
+///   to the config file's directory;
+/// - `~/.beetle`, when it exists;
+/// - `beetle` in the data directory of the platform (`$XDG_DATA_HOME`, `~/.local/share`,
+///   `~/Library/Application Support` or `%LOCALAPPDATA%`).
+///
+/// Relative paths are made absolute, so that a server started elsewhere, or its daemon, sees the
+/// same indexes. With `BEETLE_TENANT`, the home is that tenant's directory in `tenants` of it,
+/// the one `beetle serve` serves under `/api/tenants/{tenant}`.
+pub fn beetle_home() -> BeetleHome {
+    resolve(&|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+}
+
+impl BeetleHome {
+    /// The files and directories beetle uses under this home.
+    pub fn locations(&self) -> HomeLocations {
+        let daemon = Daemon::new(self.path.clone());
+        HomeLocations {
+            home: self.path.clone(),
+            home_source: self.source,
+            tenant: self.tenant.clone(),
+            config_file: self.config_file.clone(),
+            config_file_exists: self.config_file.as_deref().is_some_and(Path::is_file),
+            snapshots: self.path.join(FsStorage::SNAPSHOTS_DIR_NAME),
+            tokens_file: self.path.join(TokenStore::TOKENS_FILE_NAME),
+            search_counts_file: self.path.join(SearchAnalytics::FILE_NAME),
+            history_file: self.path.join(SearchHistory::FILE_NAME),
+            daemon_pid_file: daemon.pid_path(),
+            daemon_socket: daemon.socket_path(),
+            daemon_log: daemon.log_path(),
+            remote: std::env::var("BEETLE_REMOTE")
+                .ok()
+                .filter(|remote| !remote.is_empty()),
+        }
+    }
+}
+
+/// What `beetle env` prints.
+#[derive(Debug, Clone, Serialize)]
+pub struct HomeLocations {
+    /// Holds an index directory per index.
+    pub home: PathBuf,
+    pub home_source: HomeSource,
+    /// `BEETLE_TENANT`, when the home is a tenant's.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub config_file: Option<PathBuf>,
+    pub config_file_exists: bool,
+    pub snapshots: PathBuf,
+    pub tokens_file: PathBuf,
+    pub search_counts_file: PathBuf,
+    pub history_file: PathBuf,
+    pub daemon_pid_file: PathBuf,
+    pub daemon_socket: PathBuf,
+    pub daemon_log: PathBuf,
+    /// `BEETLE_REMOTE`, when indexes are kept in object storage and cached under the home.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+}
+
+impl HomeLocations {
+    /// The locations by name, in the order `beetle env` prints them; the config file is empty
+    /// when there is none to look for.
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        let path = |path: &Path| path.display().to_string();
+        let mut entries = vec![
+            ("home", path(&self.home)),
+            ("home_source", self.home_source.to_string()),
+        ];
+        if let Some(tenant) = &self.tenant {
+            entries.push(("tenant", tenant.clone()));
+        }
+        entries.extend([
+            (
+                "config_file",
+                self.config_file.as_deref().map(path).unwrap_or_default(),
+            ),
+            ("snapshots", path(&self.snapshots)),
+            ("tokens_file", path(&self.tokens_file)),
+            ("search_counts_file", path(&self.search_counts_file)),
+            ("history_file", path(&self.history_file)),
+            ("daemon_pid_file", path(&self.daemon_pid_file)),
+            ("daemon_socket", path(&self.daemon_socket)),
+            ("daemon_log", path(&self.daemon_log)),
+        ]);
+        if let Some(remote) = &self.remote {
+            entries.push(("remote", remote.clone()));
+        }
+
+        entries
+    }
+}
+
+/// Tenants are named like indexes, so that their names are valid directory names everywhere.
+pub fn validate_tenant_name(tenant: &str) -> Result<(), String> {
+    validate_index_name(tenant).map_err(|e| e.replace("index name", "tenant name"))
+}
+
+fn resolve(env: &dyn Fn(&str) -> Option<String>) -> BeetleHome {
+    let mut home = resolve_base(env);
+    if let Some(tenant) = env("BEETLE_TENANT") {
+        home.path = home.path.join(FsStorage::TENANTS_DIR_NAME).join(&tenant);
+        home.tenant = Some(tenant);
+    }
+
+    home
+}
+
+fn resolve_base(env: &dyn Fn(&str) -> Option<String>) -> BeetleHome {
+    let user_home = env("HOME")
+        .or_else(|| env("USERPROFILE"))
+        .map(PathBuf::from);
+    let config_file = env("BEETLE_CONFIG").map(PathBuf::from).or_else(|| {
+        let config_dir = env("XDG_CONFIG_HOME")
+            .or_else(|| cfg!(windows).then(|| env("APPDATA")).flatten())
+            .map(PathBuf::from)
+            .or_else(|| Some(user_home.as_ref()?.join(".config")))?;
+        Some(config_dir.join("beetle").join(CONFIG_FILE_NAME))
+    });
+    let home = |path: PathBuf, source| BeetleHome {
+        path: absolute(&path),
+        source,
+        config_file: config_file.clone(),
+        tenant: None,
+    };
+
+    if let Some(path) = env("BEETLE_HOME") {
+        return home(PathBuf::from(path), HomeSource::Env);
+    }
+    if let Some(path) = config_file.as_deref().and_then(read_config_home) {
+        return home(path, HomeSource::ConfigFile);
+    }
+    let legacy = user_home
+        .as_ref()
+        .map_or_else(|| PathBuf::from("."), PathBuf::clone)
+        .join(LEGACY_HOME_DIR_NAME);
+    if legacy.is_dir() {
+        return home(legacy, HomeSource::Legacy);
+    }
+
+    let data_dir = env("XDG_DATA_HOME")
+        .or_else(|| cfg!(windows).then(|| env("LOCALAPPDATA")).flatten())
+        .map(PathBuf::from)
+        .or_else(|| {
+            let user_home = user_home.as_ref()?;
+            Some(if cfg!(target_os = "macos") {
+                user_home.join("Library").join("Application Support")
+            } else {
+                user_home.join(".local").join("share")
+            })
+        });
+    match data_dir {
+        Some(data_dir) => home(data_dir.join("beetle"), HomeSource::Platform),
+        None => home(legacy, HomeSource::Legacy),
+    }
+}
+
+/// The `home` of the config file at `path`; `None` when it does not exist or has none.
+fn read_config_home(path: &Path) -> Option<PathBuf> {
+    let json = std::fs::read_to_string(path).ok()?;
+    let config: serde_json::Value = match serde_json::from_str(&json) {
+        Ok(config) => config,
+        Err(e) => {
+            warn!("ignoring config file {}: {e}", path.display());
+            return None;
+        }
+    };
+    let home = config.get("home")?.as_str()?;
+
+    Some(path.parent().unwrap_or(Path::new("")).join(home))
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn resolve_with(vars: &[(&str, &Path)]) -> BeetleHome {
+        let vars: HashMap<_, _> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string_lossy().to_string()))
+            .collect();
+        resolve(&|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_resolve() {
+        let user_home = tempfile::tempdir().unwrap();
+        let user_home = user_home.path();
+        let config = user_home.join(".config");
+
+        // The platform's data directory by default
+        let home = resolve_with(&[("HOME", user_home), ("XDG_CONFIG_HOME", &config)]);
+        assert_eq!(home.source, HomeSource::Platform);
+        assert!(home.path.starts_with(user_home));
+        assert!(home.path.ends_with("beetle"));
+        assert_eq!(
+            home.config_file,
+            Some(config.join("beetle").join(CONFIG_FILE_NAME))
+        );
+        let data = user_home.join("data");
+        let home = resolve_with(&[("HOME", user_home), ("XDG_DATA_HOME", &data)]);
+        assert_eq!(home.path, data.join("beetle"));
+
+        // The directory of earlier versions when it exists
+        std::fs::create_dir(user_home.join(LEGACY_HOME_DIR_NAME)).unwrap();
+        let home = resolve_with(&[("HOME", user_home), ("XDG_CONFIG_HOME", &config)]);
+        assert_eq!(home.source, HomeSource::Legacy);
+        assert_eq!(home.path, user_home.join(LEGACY_HOME_DIR_NAME));
+
+        // The config file, relative to its directory
+        std::fs::create_dir_all(config.join("beetle")).unwrap();
+        std::fs::write(
+            config.join("beetle").join(CONFIG_FILE_NAME),
+            r#"{"home": "../../indexes"}"#,
+        )
+        .unwrap();
+        let home = resolve_with(&[("HOME", user_home), ("XDG_CONFIG_HOME", &config)]);
+        assert_eq!(home.source, HomeSource::ConfigFile);
+        assert_eq!(home.path, config.join("beetle").join("../../indexes"));
+
+        // The environment variable above all, made absolute
+        let home = resolve_with(&[
+            ("HOME", user_home),
+            ("XDG_CONFIG_HOME", &config),
+            ("BEETLE_HOME", Path::new("relative")),
+        ]);
+        assert_eq!(home.source, HomeSource::Env);
+        assert_eq!(home.path, std::env::current_dir().unwrap().join("relative"));
+
+        // A tenant's directory in the resolved home
+        let home = resolve_with(&[
+            ("BEETLE_HOME", Path::new("/srv/beetle")),
+            ("BEETLE_TENANT", Path::new("team-a")),
+        ]);
+        assert_eq!(home.path, Path::new("/srv/beetle/tenants/team-a"));
+        assert_eq!(home.tenant.as_deref(), Some("team-a"));
+        assert!(validate_tenant_name("team-a").is_ok());
+        assert_eq!(
+            validate_tenant_name("../team").unwrap_err(),
+            "Invalid tenant name '../team': tenant names may only contain alphanumerics, '-', '_' and '.'"
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! Git hooks that keep an index up to date with the working tree of its repository, installed
+//! by `beetle hook install`.
+//!
+//! Each hook gets a block per index, a marker comment naming the index and the command updating
+//! it, added to the hook the repository already has, if any: before the `exit` or `exec` that
+//! ends it, or else at its end. Hooks in other languages than `sh` are left alone, with an error
+//! telling the command to add to them.
+//!
+//! ```sh
+//! #!/bin/sh
+//! # beetle: update index 'api'
+//! BEETLE_HOME='/home/me/.beetle' BEETLE_TENANT= '/usr/local/bin/beetle' --quiet update --index 'api' --wait >/dev/null 2>&1 &
+//! ```
+//!
+//! The update runs in the background, so that git does not wait for it, and with the home and
+//! executable of the `beetle` that installed it, since hooks run with git's environment.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The hooks installed: after commits, after merges (pulls included) and after checkouts
+/// (switching branches included).
+pub const HOOKS: [&str; 3] = ["post-commit", "post-merge", "post-checkout"];
+
+/// Installs the [`HOOKS`] of the repository `repo_path` is in, to update `index_name` of
+/// `beetle_home` with `executable`, and returns the directory they are in. Installing them again
+/// replaces the block of the index, e.g. after beetle moved.
+pub fn install_hooks(
+    repo_path: &Path,
+    index_name: &str,
+    beetle_home: &Path,
+    executable: &Path,
+) -> Result<PathBuf, String> {
+    let hooks_dir = engine::git::hooks_dir(repo_path)?;
+    fs::create_dir_all(&hooks_dir)
+        .map_err(|e| format!("Failed to create {}: {e}", hooks_dir.display()))?;
+    let marker = format!("# beetle: update index {}", shell_quote(index_name));
+    let command = format!(
+        "BEETLE_HOME={} BEETLE_TENANT= {} --quiet update --index {} --wait >/dev/null 2>&1 &",
+        shell_quote(&beetle_home.to_string_lossy()),
+        shell_quote(&executable.to_string_lossy()),
+        shell_quote(index_name)
+    );
+
+    // Every hook is checked before any is written, so that a refused one leaves them all as
+    // they were
+    let mut scripts = Vec::new();
+    for hook in HOOKS {
+        let path = hooks_dir.join(hook);
+        let script = match fs::read_to_string(&path) {
+            Ok(script) => Some(script),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(format!("Failed to read hook {}: {e}", path.display())),
+        };
+        let script = with_update(script.as_deref(), &marker, &command)
+            .map_err(|e| format!("Cannot install hook {}: {e}", path.display()))?;
+        scripts.push((path, script));
+    }
+    for (path, script) in scripts {
+        fs::write(&path, script)
+            .map_err(|e| format!("Failed to write hook {}: {e}", path.display()))?;
+        make_executable(&path)?;
+    }
+
+    Ok(hooks_dir)
+}
+
+/// `script` with `command` after `marker`, in place of the line after it when the script has the
+/// marker already, or else before the `exit` or `exec` ending the script, or at its end; a new
+/// shell script when there is none. Fails for scripts that are not run by a shell.
+fn with_update(script: Option<&str>, marker: &str, command: &str) -> Result<String, String> {
+    let Some(script) = script else {
+        return Ok(format!("#!/bin/sh\n{marker}\n{command}\n"));
+    };
+    if let Some(interpreter) = script
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("#!"))
+    {
+        if !is_shell(interpreter) {
+            return Err(format!(
+                "it is run by '{}', not sh; add the following to it instead:\n{command}",
+                interpreter.trim()
+            ));
+        }
+    }
+
+    let mut lines: Vec<&str> = script.lines().collect();
+    match lines.iter().position(|line| *line == marker) {
+        Some(position) if position + 1 < lines.len() => lines[position + 1] = command,
+        Some(_) => lines.push(command),
+        None => {
+            // Lines after the last command would never run
+            let end = lines
+                .iter()
+                .rposition(|line| !line.trim().is_empty() && !line.trim().starts_with('#'))
+                .filter(|&last| ends_script(lines[last]))
+                .unwrap_or(lines.len());
+            lines.splice(end..end, [marker, command]);
+        }
+    }
+
+    let mut script = lines.join("\n");
+    script.push('\n');
+    Ok(script)
+}
+
+/// Whether the `#!` line `interpreter` runs the script with a shell `sh` commands run in.
+fn is_shell(interpreter: &str) -> bool {
+    let mut words = interpreter.split_whitespace();
+    let mut program = words.next().unwrap_or_default();
+    if program.ends_with("/env") {
+        program = words.next().unwrap_or_default();
+    }
+    let name = program.rsplit('/').next().unwrap_or_default();
+    matches!(name, "sh" | "bash" | "dash" | "ksh" | "zsh")
+}
+
+/// Whether `line` ends the script it is the last command of.
+fn ends_script(line: &str) -> bool {
+    let line = line.trim_start();
+    ["exit", "exec"].iter().any(|command| {
+        line.strip_prefix(command)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t', ';']))
+    })
+}
+
+/// `value` quoted for `sh`, as a single-quoted word.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = fs::metadata(path)
+        .map_err(|e| format!("Failed to read hook {}: {e}", path.display()))?
+        .permissions();
+    permissions.set_mode(permissions.mode() | 0o755);
+    fs::set_permissions(path, permissions)
+        .map_err(|e| format!("Failed to make hook {} executable: {e}", path.display()))
+}
+
+/// Git for Windows runs hooks with its shell whatever their permissions.
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_update() {
+        let marker = "# beetle: update index 'api'";
+        let script = with_update(None, marker, "update v1").unwrap();
+        assert_eq!(
+            script,
+            "#!/bin/sh\n# beetle: update index 'api'\nupdate v1\n"
+        );
+
+        // Installing again replaces the command
+        assert_eq!(
+            with_update(Some(&script), marker, "update v2").unwrap(),
+            "#!/bin/sh\n# beetle: update index 'api'\nupdate v2\n"
+        );
+
+        // Other hooks, and the blocks of other indexes, are kept
+        let existing = "#!/bin/sh\nnpm run lint\n# beetle: update index 'web'\nupdate web\n";
+        assert_eq!(
+            with_update(Some(existing), marker, "update v1").unwrap(),
+            format!("{existing}{marker}\nupdate v1\n")
+        );
+    }
+
+    #[test]
+    fn test_with_update_runs_before_the_end_of_the_script() {
+        let marker = "# beetle: update index 'api'";
+        assert_eq!(
+            with_update(Some("#!/bin/bash\nnpm test\nexit 0\n\n"), marker, "update").unwrap(),
+            format!("#!/bin/bash\nnpm test\n{marker}\nupdate\nexit 0\n\n")
+        );
+        assert_eq!(
+            with_update(
+                Some("#!/usr/bin/env sh\nexec lint-staged # run it\n"),
+                marker,
+                "update"
+            )
+            .unwrap(),
+            format!("#!/usr/bin/env sh\n{marker}\nupdate\nexec lint-staged # run it\n")
+        );
+        // Only an exit ending the script
+        let early = "#!/bin/sh\n[ -n \"$CI\" ] && exit 0\nexitcode=1\n";
+        assert_eq!(
+            with_update(Some(early), marker, "update").unwrap(),
+            format!("{early}{marker}\nupdate\n")
+        );
+    }
+
+    #[test]
+    fn test_with_update_refuses_other_languages() {
+        let marker = "# beetle: update index 'api'";
+        for script in [
+            "#!/usr/bin/env python3\nprint('hi')\n",
+            "#!/usr/bin/node\nconsole.log('hi')\n",
+        ] {
+            let error = with_update(Some(script), marker, "update").unwrap_err();
+            assert!(error.contains("not sh"), "{error}");
+            assert!(error.ends_with("\nupdate"), "{error}");
+        }
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/usr/bin/beetle"), "'/usr/bin/beetle'");
+        assert_eq!(shell_quote("it's here"), r"'it'\''s here'");
+    }
+}
@@ -0,0 +1,344 @@
+use crate::analytics::SearchAnalytics;
+use engine::storage::SkippedFiles;
+use engine::usage::ResourceUsage;
+use engine::{IndexCatalog, IndexingPhase};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use utoipa::ToSchema;
+
+/// Number of succeeded and failed jobs kept for `GET /api/jobs`, the most recent ones.
+const FINISHED_JOBS_KEPT: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// A reindex running on the server's background worker.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Job {
+    pub id: String,
+    pub index_name: String,
+    pub status: JobStatus,
+    /// Searches served from the index; queued jobs with a higher priority run first.
+    pub priority: u64,
+    /// Number of reindex requests coalesced into this job while it was queued.
+    pub triggers: usize,
+    pub phase: Option<IndexingPhase>,
+    pub files_processed: usize,
+    pub files_total: usize,
+    pub errors: Vec<String>,
+    /// Resources used by the rebuild, once it has succeeded.
+    pub usage: Option<ResourceUsage>,
+    /// Files the rebuild skipped, once it has succeeded.
+    pub skipped: Option<SkippedFiles>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct JobQueueStatus {
+    pub queue_depth: usize,
+    /// Ids of the queued jobs, in the order they will run.
+    pub queue: Vec<String>,
+    pub jobs: Vec<Job>,
+}
+
+/// Runs index writes one at a time on a background task so HTTP requests return immediately.
+///
+/// Queued jobs run in order of [`Job::priority`], the number of searches the server has served
+/// from their index, so the most used indexes come back first when many need rebuilding; ties
+/// run in submission order. Requests for an index that already has a queued job are coalesced
+/// into that job, and an index is not rebuilt again until `min_interval` has passed since its
+/// previous run finished. Only the last [`FINISHED_JOBS_KEPT`] finished jobs are kept.
+pub struct JobQueue {
+    jobs: Arc<Mutex<Vec<Job>>>,
+    analytics: Arc<SearchAnalytics>,
+    wakeup: Arc<Notify>,
+}
+
+/// What the worker should do next.
+enum NextJob {
+    Run(String),
+    /// Every queued index is throttled; the first becomes eligible after this long.
+    Wait(Duration),
+    Idle,
+}
+
+impl JobQueue {
+    /// Creates the queue and spawns its worker on the current tokio runtime.
+    pub fn start(
+        catalog: Arc<IndexCatalog>,
+        analytics: Arc<SearchAnalytics>,
+        min_interval: Duration,
+    ) -> Self {
+        let jobs = Arc::new(Mutex::new(Vec::new()));
+        let wakeup = Arc::new(Notify::new());
+
+        let worker_jobs = jobs.clone();
+        let worker_analytics = analytics.clone();
+        let worker_wakeup = wakeup.clone();
+        tokio::spawn(async move {
+            let mut last_finished: HashMap<String, Instant> = HashMap::new();
+            loop {
+                // Throttled jobs stay queued, so new requests keep coalescing into them
+                match next_job(
+                    &worker_jobs,
+                    &worker_analytics,
+                    &last_finished,
+                    min_interval,
+                ) {
+                    NextJob::Run(id) => {
+                        let Some(index_name) =
+                            find_job(&worker_jobs, &id).map(|job| job.index_name)
+                        else {
+                            continue;
+                        };
+                        let jobs = worker_jobs.clone();
+                        let catalog = catalog.clone();
+                        let _ =
+                            tokio::task::spawn_blocking(move || run_reindex(&catalog, &jobs, &id))
+                                .await;
+                        last_finished.insert(index_name, Instant::now());
+                    }
+                    NextJob::Wait(wait) => {
+                        tokio::select! {
+                            _ = tokio::time::sleep(wait) => {}
+                            _ = worker_wakeup.notified() => {}
+                        }
+                    }
+                    NextJob::Idle => worker_wakeup.notified().await,
+                }
+            }
+        });
+
+        JobQueue {
+            jobs,
+            analytics,
+            wakeup,
+        }
+    }
+
+    pub fn enqueue_reindex(&self, index_name: &str) -> Job {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs
+            .iter_mut()
+            .find(|job| job.index_name == index_name && job.status == JobStatus::Queued)
+        {
+            job.triggers += 1;
+            return job.clone();
+        }
+
+        let job = Job {
+            id: uuid::Uuid::new_v4().simple().to_string(),
+            index_name: index_name.to_string(),
+            status: JobStatus::Queued,
+            priority: self.analytics.search_count(index_name),
+            triggers: 1,
+            phase: None,
+            files_processed: 0,
+            files_total: 0,
+            errors: Vec::new(),
+            usage: None,
+            skipped: None,
+        };
+        jobs.push(job.clone());
+        self.wakeup.notify_one();
+
+        job
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        find_job(&self.jobs, id)
+    }
+
+    pub fn status(&self) -> JobQueueStatus {
+        let mut jobs = self.jobs.lock().unwrap();
+        refresh_priorities(&mut jobs, &self.analytics);
+        let queue: Vec<String> = queue_order(&jobs)
+            .into_iter()
+            .map(|job| job.id.clone())
+            .collect();
+
+        JobQueueStatus {
+            queue_depth: queue.len(),
+            queue,
+            jobs: jobs.clone(),
+        }
+    }
+}
+
+/// Updates the priority of queued jobs to the current search counts of their indexes.
+fn refresh_priorities(jobs: &mut [Job], analytics: &SearchAnalytics) {
+    for job in jobs
+        .iter_mut()
+        .filter(|job| job.status == JobStatus::Queued)
+    {
+        job.priority = analytics.search_count(&job.index_name);
+    }
+}
+
+/// Drops the oldest finished jobs beyond [`FINISHED_JOBS_KEPT`]; queued and running jobs stay.
+fn prune_finished(jobs: &mut Vec<Job>) {
+    let finished = jobs.iter().filter(|job| is_finished(job)).count();
+    let mut excess = finished.saturating_sub(FINISHED_JOBS_KEPT);
+    jobs.retain(|job| {
+        if excess > 0 && is_finished(job) {
+            excess -= 1;
+            return false;
+        }
+        true
+    });
+}
+
+fn is_finished(job: &Job) -> bool {
+    matches!(job.status, JobStatus::Succeeded | JobStatus::Failed)
+}
+
+/// Queued jobs by descending priority; jobs are stored in submission order, which breaks ties.
+fn queue_order(jobs: &[Job]) -> Vec<&Job> {
+    let mut queued: Vec<&Job> = jobs
+        .iter()
+        .filter(|job| job.status == JobStatus::Queued)
+        .collect();
+    queued.sort_by_key(|job| Reverse(job.priority));
+
+    queued
+}
+
+fn next_job(
+    jobs: &Mutex<Vec<Job>>,
+    analytics: &SearchAnalytics,
+    last_finished: &HashMap<String, Instant>,
+    min_interval: Duration,
+) -> NextJob {
+    let mut jobs = jobs.lock().unwrap();
+    refresh_priorities(&mut jobs, analytics);
+
+    let mut shortest_wait: Option<Duration> = None;
+    for job in queue_order(&jobs) {
+        let wait = last_finished
+            .get(&job.index_name)
+            .map(|finished| min_interval.saturating_sub(finished.elapsed()))
+            .unwrap_or_default();
+        if wait.is_zero() {
+            return NextJob::Run(job.id.clone());
+        }
+        shortest_wait = Some(shortest_wait.map_or(wait, |shortest| shortest.min(wait)));
+    }
+
+    match shortest_wait {
+        Some(wait) => NextJob::Wait(wait),
+        None => NextJob::Idle,
+    }
+}
+
+fn find_job(jobs: &Mutex<Vec<Job>>, id: &str) -> Option<Job> {
+    jobs.lock()
+        .unwrap()
+        .iter()
+        .find(|job| job.id == id)
+        .cloned()
+}
+
+fn run_reindex(catalog: &IndexCatalog, jobs: &Mutex<Vec<Job>>, id: &str) {
+    let update = |f: &dyn Fn(&mut Job)| {
+        if let Some(job) = jobs.lock().unwrap().iter_mut().find(|job| job.id == id) {
+            f(job);
+        }
+    };
+
+    let Some(index_name) = find_job(jobs, id).map(|job| job.index_name) else {
+        return;
+    };
+    update(&|job| job.status = JobStatus::Running);
+
+    // Jobs run in the background, so they wait for writers in other processes
+    let result = catalog.reindex(&index_name, true, &|progress| {
+        update(&|job| {
+            job.phase = Some(progress.phase);
+            job.files_processed = progress.files_processed;
+            job.files_total = progress.files_total;
+        })
+    });
+
+    match result {
+        Ok(record) => update(&|job| {
+            job.status = JobStatus::Succeeded;
+            job.usage = Some(record.usage);
+            job.skipped = Some(record.skipped.clone());
+        }),
+        Err(e) => update(&|job| {
+            job.status = JobStatus::Failed;
+            job.errors.push(e.clone());
+        }),
+    }
+    prune_finished(&mut jobs.lock().unwrap());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(id: &str, status: JobStatus, priority: u64) -> Job {
+        Job {
+            id: id.to_string(),
+            index_name: id.to_string(),
+            status,
+            priority,
+            triggers: 1,
+            phase: None,
+            files_processed: 0,
+            files_total: 0,
+            errors: Vec::new(),
+            usage: None,
+            skipped: None,
+        }
+    }
+
+    #[test]
+    fn test_queue_order_prefers_most_searched() {
+        let jobs = vec![
+            job("rare", JobStatus::Queued, 1),
+            job("done", JobStatus::Succeeded, 100),
+            job("popular", JobStatus::Queued, 42),
+            job("unused", JobStatus::Queued, 0),
+            job("also-rare", JobStatus::Queued, 1),
+        ];
+
+        let order: Vec<&str> = queue_order(&jobs)
+            .into_iter()
+            .map(|job| job.id.as_str())
+            .collect();
+        assert_eq!(order, vec!["popular", "rare", "also-rare", "unused"]);
+    }
+
+    #[test]
+    fn test_prune_finished_keeps_latest() {
+        let mut jobs = vec![job("queued", JobStatus::Queued, 0)];
+        for i in 0..FINISHED_JOBS_KEPT + 2 {
+            let status = match i % 2 {
+                0 => JobStatus::Succeeded,
+                _ => JobStatus::Failed,
+            };
+            jobs.push(job(&format!("done-{i}"), status, 0));
+        }
+        jobs.push(job("running", JobStatus::Running, 0));
+
+        prune_finished(&mut jobs);
+        assert_eq!(jobs.len(), FINISHED_JOBS_KEPT + 2);
+        let ids: Vec<&str> = jobs.iter().map(|job| job.id.as_str()).collect();
+        assert_eq!(ids[..2], ["queued", "done-2"]);
+        assert_eq!(ids.last(), Some(&"running"));
+
+        // Nothing to drop once within the limit
+        prune_finished(&mut jobs);
+        assert_eq!(jobs.len(), FINISHED_JOBS_KEPT + 2);
+    }
+}
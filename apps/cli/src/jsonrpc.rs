@@ -0,0 +1,106 @@
+//! Newline-delimited JSON-RPC 2.0 over stdio, shared by `beetle mcp` and `beetle editor-server`.
+
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// Server-defined error returned when a request is valid but fails, e.g. on an unknown index.
+pub const REQUEST_FAILED: i64 = -32000;
+
+#[derive(Debug)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn method_not_found(method: &str) -> Self {
+        Self::new(METHOD_NOT_FOUND, format!("Method '{method}' not found"))
+    }
+}
+
+/// Failures of the underlying operation become [`REQUEST_FAILED`] errors.
+impl From<String> for RpcError {
+    fn from(message: String) -> Self {
+        Self::new(REQUEST_FAILED, message)
+    }
+}
+
+pub trait RpcHandler {
+    /// Answers the request `method`; `params` is `null` when the request has none.
+    fn handle_request(&mut self, method: &str, params: Value) -> Result<Value, RpcError>;
+}
+
+/// Reads requests from `input` until it is closed, writing one response line per request.
+///
+/// Notifications (messages without an `id`) are not answered.
+pub fn serve(
+    handler: &mut impl RpcHandler,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> Result<(), String> {
+    for line in input.lines() {
+        let line = line.map_err(|e| format!("Failed to read request: {e}"))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(message) => respond(handler, message),
+            Err(e) => Some(error_response(
+                Value::Null,
+                RpcError::new(PARSE_ERROR, format!("Parse error: {e}")),
+            )),
+        };
+        if let Some(response) = response {
+            writeln!(output, "{response}")
+                .and_then(|_| output.flush())
+                .map_err(|e| format!("Failed to write response: {e}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the response to `message`, or `None` for notifications.
+pub fn respond(handler: &mut impl RpcHandler, message: Value) -> Option<Value> {
+    let id = message.get("id").cloned()?;
+    let Some(method) = message.get("method").and_then(Value::as_str) else {
+        return Some(error_response(
+            id,
+            RpcError::new(INVALID_REQUEST, "Missing method"),
+        ));
+    };
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+    match handler.handle_request(method, params) {
+        Ok(result) => Some(json!({ "jsonrpc": "2.0", "id": id, "result": result })),
+        Err(e) => Some(error_response(id, e)),
+    }
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+}
+
+/// Deserializes request parameters, treating missing parameters as an empty object.
+pub fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+
+    serde_json::from_value(params)
+        .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid params: {e}")))
+}
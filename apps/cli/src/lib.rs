@@ -1,29 +1,95 @@
+mod analytics;
+mod auth;
 mod command;
+mod config;
+mod daemon;
+mod editor;
+mod history;
+mod home;
+mod hooks;
+mod jobs;
+mod jsonrpc;
+mod logging;
+mod mcp;
+mod rate_limit;
 mod result;
 mod runner;
 mod server;
 pub mod static_files;
+mod widget;
 
 pub mod cli {
+    use engine::storage::{ObjectStorage, S3Config, S3Credentials};
     use std::path::PathBuf;
 
     pub use crate::{
-        command::{beetle_command, BeetleRunner, CommandOutput},
+        command::{beetle_cli, beetle_command, BeetleRunner, CommandOutput},
+        home::{beetle_home, BeetleHome, HomeSource},
+        logging::{init_logging, LogOptions},
         result::CliRunResult,
         runner::Runner,
         server::HttpServer,
     };
 
-    pub fn get_beetle_home() -> String {
-        std::env::var("BEETLE_HOME").unwrap_or_else(|_| {
-            let home_dir = std::env::var("HOME")
-                .or_else(|_| std::env::var("USERPROFILE"))
-                .unwrap_or_else(|_| ".".to_string());
+    /// Object storage configured with `BEETLE_REMOTE=s3://bucket[/prefix]` (or `gs://`), caching
+    /// indexes under `cache_root`; `None` when indexes are kept locally.
+    ///
+    /// The endpoint defaults to AWS (`gs://` to Google Cloud Storage) and can be overridden with
+    /// `BEETLE_S3_ENDPOINT`, e.g. for MinIO. Credentials come from the standard AWS variables;
+    /// without them requests are unsigned, which only works for public buckets. Indexes pulled
+    /// from the bucket are only pushed back with `BEETLE_REMOTE_PUSH=1`.
+    pub fn get_remote_storage(cache_root: PathBuf) -> Result<Option<ObjectStorage>, String> {
+        let Ok(remote) = std::env::var("BEETLE_REMOTE") else {
+            return Ok(None);
+        };
+        let (scheme, location) = remote.split_once("://").ok_or_else(|| {
+            format!("Invalid BEETLE_REMOTE '{remote}': expected s3://bucket[/prefix]")
+        })?;
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            return Err(format!(
+                "Invalid BEETLE_REMOTE '{remote}': missing bucket name"
+            ));
+        }
 
-            PathBuf::from(home_dir)
-                .join(".beetle")
-                .to_string_lossy()
-                .into_owned()
-        })
+        let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let (endpoint, region) = match scheme {
+            "s3" => {
+                let region = env("AWS_REGION")
+                    .or_else(|| env("AWS_DEFAULT_REGION"))
+                    .unwrap_or_else(|| "us-east-1".to_string());
+                (format!("https://s3.{region}.amazonaws.com"), region)
+            }
+            "gs" => (
+                "https://storage.googleapis.com".to_string(),
+                "auto".to_string(),
+            ),
+            _ => {
+                return Err(format!(
+                    "Invalid BEETLE_REMOTE '{remote}': unsupported scheme '{scheme}', expected \
+                     s3:// or gs://"
+                ))
+            }
+        };
+        let credentials = match (env("AWS_ACCESS_KEY_ID"), env("AWS_SECRET_ACCESS_KEY")) {
+            (Some(access_key_id), Some(secret_access_key)) => Some(S3Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: env("AWS_SESSION_TOKEN"),
+            }),
+            _ => None,
+        };
+        let config = S3Config {
+            endpoint: env("BEETLE_S3_ENDPOINT").unwrap_or(endpoint),
+            bucket: bucket.to_string(),
+            region,
+            credentials,
+        };
+
+        let push_pulled = env("BEETLE_REMOTE_PUSH").is_some_and(|value| value == "1");
+
+        Ok(Some(
+            ObjectStorage::new(cache_root, config, prefix).with_push_pulled(push_pulled),
+        ))
     }
 }
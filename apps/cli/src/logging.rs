@@ -0,0 +1,370 @@
+//! Where the tracing output of the engine and the CLI goes: stderr, at the level `-v`, `-vv` and
+//! `--quiet` choose, and the file of `--log-file`, rolled over by size, at least at the info
+//! level. `BEETLE_LOG` (e.g. `BEETLE_LOG=engine=trace`) overrides the levels of both. With
+//! `--log-format json` both get a JSON object per line, for log aggregators to ingest.
+
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::{Event, Subscriber};
+use tracing_subscriber::field::{MakeExt, RecordFields};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::{self, FormatEvent, FormatFields};
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::{FmtContext, FormattedFields};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{Layer, Registry};
+
+/// Size a log file is rolled over at.
+const MAX_LOG_FILE_SIZE: u64 = 10 * 1024 * 1024;
+/// Rolled over log files kept next to the log file, as `<log file>.1` (the most recent) to
+/// `<log file>.3`.
+const LOG_FILES_KEPT: usize = 3;
+/// The crates whose events `-v` and `-vv` show; the others only show with `-vvv`.
+const BEETLE_TARGETS: [&str; 2] = ["beetle", "engine"];
+
+/// The options before the command, e.g. `beetle -vv update --index my-project`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogOptions {
+    /// -1 for `--quiet`, 0 by default, and one more per `-v`, up to 3.
+    pub verbosity: i8,
+    pub log_file: Option<PathBuf>,
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Lines for people to read.
+    #[default]
+    Text,
+    /// A JSON object per line, see [`JsonFormat`].
+    Json,
+}
+
+/// Installs the tracing subscriber every command logs to.
+pub fn init_logging(options: &LogOptions) -> Result<(), String> {
+    let env_targets = match std::env::var("BEETLE_LOG") {
+        Ok(targets) if !targets.is_empty() => Some(
+            Targets::from_str(&targets)
+                .map_err(|e| format!("Invalid BEETLE_LOG '{targets}': {e}"))?,
+        ),
+        _ => None,
+    };
+    let filter = |verbosity| env_targets.clone().unwrap_or_else(|| targets(verbosity));
+
+    let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = Vec::new();
+    let console = tracing_subscriber::fmt::layer().with_writer(io::stderr);
+    layers.push(match options.format {
+        LogFormat::Text => console
+            .with_ansi(io::stderr().is_terminal())
+            .with_filter(filter(options.verbosity))
+            .boxed(),
+        LogFormat::Json => console
+            .event_format(JsonFormat)
+            .fmt_fields(JsonFields)
+            .with_filter(filter(options.verbosity))
+            .boxed(),
+    });
+    if let Some(path) = &options.log_file {
+        let file = tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(Mutex::new(RollingFile::open(path, MAX_LOG_FILE_SIZE)?));
+        layers.push(match options.format {
+            LogFormat::Text => file
+                // The fields of spans are formatted once per field formatter type, so the file
+                // needs a type of its own to leave out the colors of the console
+                .fmt_fields(
+                    format::debug_fn(|writer, field, value| match field.name() {
+                        "message" => write!(writer, "{value:?}"),
+                        name => write!(writer, "{name}={value:?}"),
+                    })
+                    .delimited(" "),
+                )
+                .with_filter(filter(options.verbosity.max(1)))
+                .boxed(),
+            LogFormat::Json => file
+                .event_format(JsonFormat)
+                .fmt_fields(JsonFields)
+                .with_filter(filter(options.verbosity.max(1)))
+                .boxed(),
+        });
+    }
+    tracing_subscriber::registry().with(layers).init();
+
+    Ok(())
+}
+
+/// The levels of `verbosity`: errors with `--quiet`, warnings by default, then the info, debug
+/// and trace events of beetle, and with `-vvv` those of its dependencies too.
+fn targets(verbosity: i8) -> Targets {
+    let (beetle, others) = match verbosity {
+        ..=-1 => (LevelFilter::ERROR, LevelFilter::ERROR),
+        0 => (LevelFilter::WARN, LevelFilter::WARN),
+        1 => (LevelFilter::INFO, LevelFilter::WARN),
+        2 => (LevelFilter::DEBUG, LevelFilter::WARN),
+        _ => (LevelFilter::TRACE, LevelFilter::TRACE),
+    };
+
+    Targets::new()
+        .with_targets(BEETLE_TARGETS.map(|target| (target, beetle)))
+        .with_default(others)
+}
+
+/// Formats events as a JSON object per line, e.g. `{"level":"INFO","message":"Request served",
+/// "method":"GET","status":200,"target":"beetle::server","timestamp":"2025-06-01T12:00:00.000000Z"}`:
+/// the fields of the event and of the spans it happened in, those of the event winning over the
+/// spans' and inner spans over outer ones.
+struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: format::Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut format::Writer::new(&mut timestamp))?;
+        let metadata = event.metadata();
+        let mut line = Map::new();
+        line.insert("timestamp".to_string(), Value::String(timestamp));
+        line.insert(
+            "level".to_string(),
+            Value::String(metadata.level().to_string()),
+        );
+        line.insert(
+            "target".to_string(),
+            Value::String(metadata.target().to_string()),
+        );
+
+        // The spans' fields were formatted by `JsonFields` when they were recorded
+        for span in ctx
+            .event_scope()
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+        {
+            if let Some(fields) = span.extensions().get::<FormattedFields<N>>() {
+                if let Ok(Value::Object(fields)) = serde_json::from_str(fields) {
+                    line.extend(fields);
+                }
+            }
+        }
+        let mut fields = JsonVisitor::default();
+        event.record(&mut fields);
+        line.extend(fields.0);
+
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+/// Formats the fields of spans as a JSON object, for [`JsonFormat`] to merge into the events.
+struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: format::Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor::default();
+        fields.record(&mut visitor);
+        write!(writer, "{}", Value::Object(visitor.0))
+    }
+
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &tracing::span::Record<'_>,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor(match serde_json::from_str(current) {
+            Ok(Value::Object(fields)) => fields,
+            _ => Map::new(),
+        });
+        fields.record(&mut visitor);
+        current.fields = Value::Object(visitor.0).to_string();
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct JsonVisitor(Map<String, Value>);
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}").into());
+    }
+}
+
+/// A log file appended to, and moved to `<path>.1` once it would grow past `max_size`, the
+/// older ones moving up to `<path>.<LOG_FILES_KEPT>`.
+struct RollingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+}
+
+impl RollingFile {
+    fn open(path: &Path, max_size: u64) -> Result<Self, String> {
+        let file =
+            append(path).map_err(|e| format!("Failed to open log file {}: {e}", path.display()))?;
+        let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+
+        Ok(RollingFile {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+        })
+    }
+
+    fn roll_over(&mut self) -> io::Result<()> {
+        for kept in (1..LOG_FILES_KEPT).rev() {
+            match fs::rename(rolled(&self.path, kept), rolled(&self.path, kept + 1)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        fs::rename(&self.path, rolled(&self.path, 1))?;
+        self.file = append(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+impl Write for RollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.roll_over()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+    fs::OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// `<path>.<number>`, the log file rolled over `number` times ago.
+fn rolled(path: &Path, number: usize) -> PathBuf {
+    let mut rolled = path.as_os_str().to_owned();
+    rolled.push(format!(".{number}"));
+    PathBuf::from(rolled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolling_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs").join("beetle.log");
+        let mut file = RollingFile::open(&path, 10).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n", "fifth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+
+        let read = |path: &Path| fs::read_to_string(path).unwrap();
+        assert_eq!(read(&path), "fifth\n");
+        assert_eq!(read(&rolled(&path, 1)), "fourth\n");
+        assert_eq!(read(&rolled(&path, 3)), "second\n");
+        assert!(!rolled(&path, 4).exists());
+
+        // Appends to the log file left by an earlier run
+        let mut file = RollingFile::open(&path, 100).unwrap();
+        file.write_all(b"sixth\n").unwrap();
+        assert_eq!(read(&path), "fifth\nsixth\n");
+    }
+
+    #[test]
+    fn test_json_format() {
+        let output = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let writer = output.clone();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .event_format(JsonFormat)
+                .fmt_fields(JsonFields)
+                .with_writer(move || SharedBuffer(writer.clone())),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "request",
+                path = "/api/indexes",
+                index = tracing::field::Empty
+            );
+            span.record("index", "docs");
+            let _entered = span.enter();
+            tracing::info!(status = 200u16, latency_ms = 1.5, "Request served");
+        });
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        let line: Value = serde_json::from_str(output.trim_end()).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["message"], "Request served");
+        assert_eq!(line["path"], "/api/indexes");
+        assert_eq!(line["index"], "docs");
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["latency_ms"], 1.5);
+        assert!(line["timestamp"].is_string());
+    }
+
+    struct SharedBuffer(std::sync::Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
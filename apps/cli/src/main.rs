@@ -1,29 +1,20 @@
 mod result;
 
-use beetle::cli::{beetle_command, BeetleRunner, CliRunResult, Runner};
+use beetle::cli::{beetle_cli, init_logging, BeetleRunner, CliRunResult, Runner};
+use bpaf::{Args, ParseFailure};
 
 fn main() -> CliRunResult {
-    init_tracing();
-
-    let command = beetle_command().run();
+    let (logging, command) = match beetle_cli().run_inner(Args::current_args()) {
+        Ok(parsed) => parsed,
+        Err(failure @ ParseFailure::Stderr(_)) => {
+            return CliRunResult::UsageError(failure.unwrap_stderr())
+        }
+        // --help, --version and shell completions
+        Err(failure) => return CliRunResult::Success(failure.unwrap_stdout()),
+    };
+    if let Err(e) = init_logging(&logging) {
+        return CliRunResult::Error(e);
+    }
 
     BeetleRunner::new(command).run()
 }
-
-/// `BEETLE_LOG=trace beetle list`
-fn init_tracing() {
-    use tracing_subscriber::{filter::Targets, prelude::*};
-
-    // Usage without the `regex` feature.
-    // <https://github.com/tokio-rs/tracing/issues/1436#issuecomment-918528013>
-    tracing_subscriber::registry()
-        .with(std::env::var("BEETLE_LOG").map_or_else(
-            |_| Targets::new(),
-            |env_var| {
-                use std::str::FromStr;
-                Targets::from_str(&env_var).unwrap()
-            },
-        ))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-}
@@ -0,0 +1,377 @@
+use crate::jsonrpc::{self, RpcError, RpcHandler, INVALID_PARAMS};
+use engine::paths::to_slashes;
+use engine::search::SearchOptions;
+use engine::{IndexCatalog, ALL_INDEXES};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// Protocol revision answered when the client asks for one this server does not know.
+const PROTOCOL_VERSION: &str = "2025-03-26";
+const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2024-11-05", "2025-03-26", "2025-06-18"];
+
+/// Results returned by `search_code` unless the caller asks for another number.
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+/// Serves the Model Context Protocol over stdio, so LLM agents can search local indexes.
+///
+/// The server exposes three tools: `search_code`, `list_indexes` and `get_file`. Tool failures
+/// (an unknown index, a bad query) are reported as tool results with `isError` set, so the agent
+/// sees them; protocol errors are JSON-RPC errors.
+pub struct McpServer<'a> {
+    catalog: &'a IndexCatalog,
+}
+
+#[derive(Deserialize)]
+struct ToolCall {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(Deserialize)]
+struct SearchCodeArguments {
+    query: String,
+    index: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct GetFileArguments {
+    index: String,
+    path: String,
+}
+
+impl<'a> McpServer<'a> {
+    pub fn new(catalog: &'a IndexCatalog) -> Self {
+        McpServer { catalog }
+    }
+
+    /// Answers requests read from `input` until it is closed.
+    pub fn run(mut self, input: impl BufRead, output: impl Write) -> Result<(), String> {
+        jsonrpc::serve(&mut self, input, output)
+    }
+
+    /// Runs the tool `name`; `None` when there is no such tool.
+    fn call_tool(&self, name: &str, arguments: Value) -> Option<Result<String, String>> {
+        let result = match name {
+            "search_code" => parse_arguments(arguments).and_then(|args| self.search_code(args)),
+            "list_indexes" => self.list_indexes(),
+            "get_file" => parse_arguments(arguments).and_then(|args| self.get_file(args)),
+            _ => return None,
+        };
+
+        Some(result)
+    }
+
+    fn search_code(&self, args: SearchCodeArguments) -> Result<String, String> {
+        let index_name = args.index.as_deref().unwrap_or(ALL_INDEXES);
+        let mut results =
+            self.catalog
+                .search(index_name, &args.query, &SearchOptions::default())?;
+        results.truncate(args.limit.unwrap_or(DEFAULT_SEARCH_LIMIT));
+
+        serde_json::to_string_pretty(&results)
+            .map_err(|e| format!("Failed to serialize results: {e}"))
+    }
+
+    fn list_indexes(&self) -> Result<String, String> {
+        let indexes: Vec<Value> = self
+            .catalog
+            .list()?
+            .into_iter()
+            .map(|index| {
+                json!({
+                    "name": index.index_name,
+                    "path": index.target_path,
+                    "tags": index.tags,
+                })
+            })
+            .collect();
+
+        serde_json::to_string_pretty(&indexes)
+            .map_err(|e| format!("Failed to serialize indexes: {e}"))
+    }
+
+    /// Returns the indexed content of a file; `path` is as returned by `search_code`, or
+    /// relative to the indexed folder.
+    fn get_file(&self, args: GetFileArguments) -> Result<String, String> {
+        let metadata = self.catalog.get_matadata(&args.index)?;
+        let searcher = self.catalog.get_searcher(&args.index)?;
+
+        let path = to_slashes(&args.path);
+        let relative = to_slashes(
+            &Path::new(&metadata.target_path)
+                .join(&path)
+                .to_string_lossy(),
+        );
+        let content = match searcher.file_content(&path)? {
+            Some(content) => Some(content),
+            None => searcher.file_content(&relative)?,
+        };
+
+        content.ok_or_else(|| format!("File '{}' is not in index '{}'", args.path, args.index))
+    }
+}
+
+impl RpcHandler for McpServer<'_> {
+    fn handle_request(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "initialize" => {
+                let requested = params.get("protocolVersion").and_then(Value::as_str);
+                let version = requested
+                    .filter(|version| SUPPORTED_PROTOCOL_VERSIONS.contains(version))
+                    .unwrap_or(PROTOCOL_VERSION);
+                Ok(json!({
+                    "protocolVersion": version,
+                    "capabilities": { "tools": {} },
+                    "serverInfo": { "name": "beetle", "version": env!("CARGO_PKG_VERSION") },
+                }))
+            }
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tools() })),
+            "tools/call" => {
+                let call: ToolCall = jsonrpc::parse_params(params)?;
+                let arguments = if call.arguments.is_null() {
+                    json!({})
+                } else {
+                    call.arguments
+                };
+                match self.call_tool(&call.name, arguments) {
+                    Some(Ok(text)) => Ok(tool_result(text, false)),
+                    Some(Err(e)) => Ok(tool_result(e, true)),
+                    None => Err(RpcError::new(
+                        INVALID_PARAMS,
+                        format!("Unknown tool '{}'", call.name),
+                    )),
+                }
+            }
+            _ => Err(RpcError::method_not_found(method)),
+        }
+    }
+}
+
+fn parse_arguments<T: serde::de::DeserializeOwned>(arguments: Value) -> Result<T, String> {
+    serde_json::from_value(arguments).map_err(|e| format!("Invalid arguments: {e}"))
+}
+
+fn tool_result(text: String, is_error: bool) -> Value {
+    json!({
+        "content": [{ "type": "text", "text": text }],
+        "isError": is_error,
+    })
+}
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "search_code",
+            "description": "Search indexed source code. Supports the Tantivy query syntax, e.g. \
+                `parse AND http`, `\"fn main\"` or `extension:rs`. Returns matching files with \
+                a highlighted snippet, best matches first.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "Search query" },
+                    "index": {
+                        "type": "string",
+                        "description": "Index to search; every index when omitted"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Maximum number of results, 20 by default"
+                    }
+                },
+                "required": ["query"]
+            }
+        },
+        {
+            "name": "list_indexes",
+            "description": "List the code indexes available to search, with the folder each \
+                one covers.",
+            "inputSchema": { "type": "object", "properties": {} }
+        },
+        {
+            "name": "get_file",
+            "description": "Return the full indexed content of a file.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "index": { "type": "string", "description": "Index containing the file" },
+                    "path": {
+                        "type": "string",
+                        "description": "Path as returned by search_code, or relative to the \
+                            indexed folder"
+                    }
+                },
+                "required": ["index", "path"]
+            }
+        }
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::{respond, METHOD_NOT_FOUND};
+    use engine::options::IndexingOptions;
+    use engine::storage::RamStorage;
+
+    #[test]
+    fn test_protocol_messages() {
+        let catalog = IndexCatalog::new(RamStorage::new());
+        let mut server = McpServer::new(&catalog);
+
+        let response = respond(
+            &mut server,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "initialize",
+                "params": { "protocolVersion": "2024-11-05", "capabilities": {} }
+            }),
+        )
+        .unwrap();
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["protocolVersion"], "2024-11-05");
+        assert_eq!(response["result"]["serverInfo"]["name"], "beetle");
+
+        let notification = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        assert!(respond(&mut server, notification).is_none());
+
+        let response = respond(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/list" }),
+        )
+        .unwrap();
+        let names: Vec<&str> = response["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["search_code", "list_indexes", "get_file"]);
+
+        let response = respond(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "resources/list" }),
+        )
+        .unwrap();
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_tool_errors_are_tool_results() {
+        let catalog = IndexCatalog::new(RamStorage::new());
+        let mut server = McpServer::new(&catalog);
+
+        let response = respond(
+            &mut server,
+            json!({
+                "jsonrpc": "2.0",
+                "id": "a",
+                "method": "tools/call",
+                "params": { "name": "get_file", "arguments": { "index": "missing", "path": "x" } }
+            }),
+        )
+        .unwrap();
+        assert_eq!(response["result"]["isError"], true);
+
+        let response = respond(
+            &mut server,
+            json!({
+                "jsonrpc": "2.0",
+                "id": "b",
+                "method": "tools/call",
+                "params": { "name": "list_indexes" }
+            }),
+        )
+        .unwrap();
+        assert_eq!(response["result"]["isError"], false);
+        assert_eq!(response["result"]["content"][0]["text"], "[]");
+
+        let response = respond(
+            &mut server,
+            json!({
+                "jsonrpc": "2.0",
+                "id": "c",
+                "method": "tools/call",
+                "params": { "name": "rm_rf" }
+            }),
+        )
+        .unwrap();
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+    }
+
+    /// Calls `tool` with `arguments` and returns the text it answered, failing on tool errors.
+    fn call(server: &mut McpServer, tool: &str, arguments: Value) -> String {
+        let response = respond(
+            server,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "tools/call",
+                "params": { "name": tool, "arguments": arguments }
+            }),
+        )
+        .unwrap();
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        assert_eq!(response["result"]["isError"], false, "{text}");
+
+        text.to_string()
+    }
+
+    #[test]
+    fn test_search_code_and_get_file() {
+        let target = tempfile::tempdir().unwrap();
+        std::fs::create_dir(target.path().join("src")).unwrap();
+        std::fs::write(target.path().join("src/main.rs"), "fn beetle_main() {}").unwrap();
+        std::fs::write(target.path().join("src/lib.rs"), "pub fn beetle_lib() {}").unwrap();
+        let catalog = IndexCatalog::new(RamStorage::new());
+        catalog
+            .create(
+                "code",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        catalog.get_writer("code", false).unwrap().index().unwrap();
+        let mut server = McpServer::new(&catalog);
+
+        let text = call(
+            &mut server,
+            "search_code",
+            json!({ "query": "beetle_main", "index": "code" }),
+        );
+        let results: Vec<Value> = serde_json::from_str(&text).unwrap();
+        assert_eq!(results.len(), 1);
+        let path = results[0]["path"].as_str().unwrap();
+        assert!(path.ends_with("src/main.rs"), "{path}");
+
+        // Every index is searched when none is named, up to the limit
+        let text = call(
+            &mut server,
+            "search_code",
+            json!({ "query": "beetle_main beetle_lib", "limit": 1 }),
+        );
+        let results: Vec<Value> = serde_json::from_str(&text).unwrap();
+        assert_eq!(results.len(), 1);
+
+        // By the path search_code returned, or relative to the indexed folder
+        let content = call(
+            &mut server,
+            "get_file",
+            json!({ "index": "code", "path": path }),
+        );
+        assert_eq!(content, "fn beetle_main() {}");
+        let content = call(
+            &mut server,
+            "get_file",
+            json!({ "index": "code", "path": "src/lib.rs" }),
+        );
+        assert_eq!(content, "pub fn beetle_lib() {}");
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of clients tracked before idle ones are forgotten.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Sustained number of requests a client may make per minute.
+    pub requests_per_minute: u32,
+    /// Requests a client may make at once before the per-minute rate applies.
+    pub burst: u32,
+}
+
+/// Token-bucket rate limiter keyed by client (API token or IP address).
+///
+/// Each client starts with `burst` requests available, which refill at the per-minute rate.
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+struct Bucket {
+    available: f64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn capacity(&self) -> f64 {
+        f64::from(self.limit.burst.max(1))
+    }
+
+    fn refill_per_second(&self) -> f64 {
+        f64::from(self.limit.requests_per_minute.max(1)) / 60.0
+    }
+
+    /// Takes one request from the client's bucket, or returns how long until one is available.
+    pub fn check(&self, client: &str) -> Result<(), Duration> {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= PRUNE_THRESHOLD && !buckets.contains_key(client) {
+            // A bucket that would be full again carries no state worth keeping
+            buckets.retain(|_, bucket| self.refilled(bucket, now) < self.capacity());
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            available: self.capacity(),
+            updated_at: now,
+        });
+        bucket.available = self.refilled(bucket, now);
+        bucket.updated_at = now;
+
+        if bucket.available >= 1.0 {
+            bucket.available -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.available) / self.refill_per_second(),
+            ))
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated_at);
+        (bucket.available + elapsed.as_secs_f64() * self.refill_per_second()).min(self.capacity())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_each_client_separately() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_minute: 60,
+            burst: 2,
+        });
+        let start = Instant::now();
+
+        assert!(limiter.check_at("10.0.0.1", start).is_ok());
+        assert!(limiter.check_at("10.0.0.1", start).is_ok());
+        let retry_after = limiter.check_at("10.0.0.1", start).unwrap_err();
+        assert_eq!(retry_after, Duration::from_secs(1));
+        assert!(limiter.check_at("token:1a2b3c4d", start).is_ok());
+
+        // One request per second refills
+        let later = start + Duration::from_secs(1);
+        assert!(limiter.check_at("10.0.0.1", later).is_ok());
+        assert!(limiter.check_at("10.0.0.1", later).is_err());
+    }
+}
@@ -1,25 +1,59 @@
+use std::io::Write;
 use std::process::{ExitCode, Termination};
 
+/// Exit codes of beetle, following grep's for searches so that scripts can tell "nothing
+/// matched" apart from a failure.
+pub mod exit_code {
+    /// The command succeeded; for `search`, something matched.
+    pub const SUCCESS: u8 = 0;
+    /// `search` ran but matched nothing.
+    pub const NO_MATCHES: u8 = 1;
+    /// The command line is invalid: unknown options, missing or malformed values.
+    pub const USAGE: u8 = 2;
+    /// An index the command names does not exist.
+    pub const INDEX_NOT_FOUND: u8 = 3;
+    /// Any other failure.
+    pub const ERROR: u8 = 4;
+}
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub enum CliRunResult {
     None,
     Success(String),
+    /// The output of a search that matched nothing, still printed for formats such as JSON.
+    NoMatches(String),
+    UsageError(String),
+    IndexNotFound(String),
     Error(String),
 }
 
+impl CliRunResult {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::None | Self::Success(_) => exit_code::SUCCESS,
+            Self::NoMatches(_) => exit_code::NO_MATCHES,
+            Self::UsageError(_) => exit_code::USAGE,
+            Self::IndexNotFound(_) => exit_code::INDEX_NOT_FOUND,
+            Self::Error(_) => exit_code::ERROR,
+        }
+    }
+}
+
 impl Termination for CliRunResult {
     fn report(self) -> ExitCode {
+        let code = self.exit_code();
         match self {
-            Self::None => ExitCode::SUCCESS,
-            Self::Success(text) => {
-                println!("{text}");
-                ExitCode::SUCCESS
+            Self::None => {}
+            // A reader that stops early, e.g. `beetle search ... | head`, is not a failure
+            Self::Success(text) | Self::NoMatches(text) => {
+                let _ = writeln!(std::io::stdout().lock(), "{text}");
             }
-            Self::Error(err_text) => {
+            Self::UsageError(err_text) | Self::IndexNotFound(err_text) | Self::Error(err_text) => {
                 eprintln!("Error: {err_text}");
-                ExitCode::FAILURE
             }
         }
+
+        ExitCode::from(code)
     }
 }
@@ -1,72 +1,827 @@
-use crate::cli::get_beetle_home;
+use crate::analytics::SearchAnalytics;
+use crate::auth::{
+    AdminToken, ApiToken, AuthBackend, OidcBackend, OidcConfig, Principal, TokenStore,
+};
+use crate::cli::beetle_home;
 use crate::cli::CommandOutput;
+use crate::daemon::{Daemon, DaemonInfo};
+use crate::history::{HistoryEntry, NumberedEntry, SearchHistory};
+use crate::home::validate_tenant_name;
+use crate::jobs::{Job, JobQueue, JobQueueStatus};
+use crate::rate_limit::{RateLimit, RateLimiter};
 use crate::static_files::serve_static_file;
+use crate::widget::render_search_widget;
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::Json as ResponseJson,
-    routing::{get, post},
-    Router,
+    extract::{ConnectInfo, Path, Query, Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Json as ResponseJson, Response},
+    routing::{any, get, post},
+    Extension, Router,
+};
+use engine::highlight::{highlight, themes, HighlightOptions, HighlightedFile, DEFAULT_THEME};
+use engine::options::{
+    AnalyzerOptions, ContentStorage, ContentTokenizer, FieldBoosts, IndexingOptions,
+    MergePolicyOptions, SchemaProfile, SubmodulePolicy, WriterResources,
+};
+use engine::paths::to_slashes;
+use engine::search::{
+    language_counts, parse_max_fragments, parse_modified_time, IndexBreakdown, LanguageCount,
+    SearchError, SearchOptions, SearchResultItem, Suggestion, SymbolMatch, TreeEntry,
+    DEFAULT_HIGHLIGHT_TAG, DEFAULT_LARGEST_FILES, DEFAULT_MAX_FRAGMENTS, DEFAULT_SNIPPET_CHARS,
+    DEFAULT_SUGGESTIONS,
+};
+use engine::snapshot::SnapshotInfo;
+use engine::storage::{FsStorage, UpdateRecord};
+use engine::usage::ResourceUsage;
+use engine::{
+    slugify_index_name, validate_index_name, IndexCatalog, PendingChanges, SchemaField, SegmentInfo,
 };
-use engine::search::SearchResultItem;
-use engine::storage::FsStorage;
-use engine::IndexCatalog;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::signal;
+use tokio::sync::Notify;
+use tower::ServiceExt;
+use tower_http::compression::predicate::{
+    DefaultPredicate, NotForContentType, Predicate, SizeAbove,
+};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::trace::TraceLayer;
+use tracing::Span;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct IndexResponse {
     name: String,
     path: String,
+    tags: Vec<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct IndexDetailResponse {
     index_name: String,
     index_path: String,
     target_path: String,
+    tags: Vec<String>,
+    target_path_exists: bool,
+    /// The options the index was created with; options left at their default are omitted.
+    #[schema(value_type = Object)]
+    options: IndexingOptions,
+    format_version: u32,
+    /// Documents of the last commit.
+    docs: u64,
+    size_bytes: u64,
+    /// Last time the index was written, in seconds since the Unix epoch.
+    updated_at: i64,
+    last_update: Option<UpdateRecord>,
+    /// Files recorded by the last update, which the next one is diffed against.
+    snapshot_files: usize,
+    /// The most recent snapshot of the index, if any.
+    #[schema(value_type = Option<Object>)]
+    latest_snapshot: Option<SnapshotInfo>,
+    fields: Vec<SchemaField>,
+    /// The segments of the last commit, largest first.
+    segments: Vec<SegmentInfo>,
+    /// What the next update would change, told by scanning the target path; `null` when the
+    /// target path is gone.
+    pending: Option<PendingChanges>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct SearchResponse {
     query: String,
     index_name: String,
     results: Vec<SearchResultItem>,
     total_results: usize,
+    /// Number of results in every language, most frequent first.
+    languages: Vec<LanguageCount>,
+    duration_ms: f64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SymbolSearchResponse {
+    query: String,
+    index_name: String,
+    /// Matching definitions, best matching files first.
+    results: Vec<SymbolMatch>,
+    total_results: usize,
+    duration_ms: f64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CrossIndexSearchResponse {
+    query: String,
+    /// The indexes that were searched.
+    indexes: Vec<String>,
+    /// Results of every index merged by score, each tagged with its `index_name`.
+    results: Vec<SearchResultItem>,
+    total_results: usize,
+    /// Number of results in every language, most frequent first.
+    languages: Vec<LanguageCount>,
     duration_ms: f64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
+struct SuggestResponse {
+    query: String,
+    index_name: String,
+    /// Completions of the last word of the query, best first.
+    suggestions: Vec<Suggestion>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct HistoryResponse {
+    /// The most recent searches first, numbered as `beetle history` lists them.
+    entries: Vec<NumberedEntry>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct IndexStatsResponse {
+    index_name: String,
+    docs: u64,
+    size_bytes: u64,
+    /// Last time the index was written, in seconds since the Unix epoch.
+    updated_at: i64,
+    /// The most recent update of the index.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_update: Option<UpdateRecord>,
+    #[serde(flatten)]
+    breakdown: IndexBreakdown,
+}
+
+#[derive(Serialize, ToSchema)]
+struct TreeResponse {
+    index_name: String,
+    /// The listed directory as requested; empty for the indexed directory.
+    path: String,
+    /// Directories first, then files.
+    entries: Vec<TreeEntry>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct HighlightResponse {
+    index_name: String,
+    path: String,
+    #[serde(flatten)]
+    file: HighlightedFile,
+}
+
+#[derive(Serialize, ToSchema)]
 struct ErrorResponse {
     error: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct SearchQuery {
+    /// Query string.
+    q: String,
+    /// Run queries that exceed the server's maximum query cost.
+    #[serde(default)]
+    force: bool,
+    /// Element wrapped around highlighted terms in snippets: `b` (the default), `strong`, `em`,
+    /// `mark` or `span`.
+    highlight_tag: Option<String>,
+    /// Class attribute added to the highlight element.
+    highlight_class: Option<String>,
+    /// Comma-separated languages (e.g. `rust,python` or `rs,py`); results in any language when
+    /// omitted.
+    lang: Option<String>,
+    /// Only return files last modified at or after this time: an RFC 3339 timestamp, a date
+    /// (`2024-05-01`) or an age (`7d`).
+    modified_after: Option<String>,
+    /// Only return files last modified before this time, in the same forms as `modified_after`.
+    modified_before: Option<String>,
+    /// Ranking weights of fields overriding the index's, e.g. `filename=5,content=0.5`.
+    boost: Option<String>,
+    /// Match `q` verbatim, punctuation and case included, instead of parsing it.
+    #[serde(default)]
+    exact: bool,
+    /// Longest fragment of the snippets, in characters; 150 by default.
+    snippet_chars: Option<usize>,
+    /// Best non-overlapping fragments of each file, or `all`; 1 by default.
+    max_fragments: Option<String>,
+    /// Only return files under this directory, relative to the indexed directory, e.g.
+    /// `services/api`.
+    #[serde(rename = "in")]
+    #[param(rename = "in")]
+    within: Option<String>,
+    /// Comma-separated extensions of files to leave out, e.g. `json,lock`.
+    not_ext: Option<String>,
+    /// Comma-separated directories or files to leave out, relative to the indexed directory,
+    /// e.g. `vendor,tests/fixtures`.
+    exclude_path: Option<String>,
+    /// Only return files last committed by an author with these words in their name, for
+    /// indexes created with `git_blame`.
+    author: Option<String>,
+    /// Comma-separated branches to return the files of, for indexes created with `branches`.
+    branch: Option<String>,
+    /// Only return files added or modified between this revision and `HEAD` of the indexed
+    /// directory's repository.
+    changed_since: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SuggestQuery {
+    /// The query typed so far, e.g. `par` or `src/pa`.
+    q: String,
+    /// Completions returned at most; 10 by default.
+    limit: Option<usize>,
+}
+
+/// Most completions a suggestion request may ask for.
+const MAX_SUGGESTIONS: usize = 100;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TreeQuery {
+    /// Directory to list, relative to the indexed directory, e.g. `src/`; the indexed directory
+    /// when omitted.
+    #[serde(default)]
+    path: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct IndexStatsQuery {
+    /// Number of largest files to list; 10 by default, at most 100.
+    largest: Option<usize>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct HistoryQuery {
+    /// Only list the searches of this index.
+    index: Option<String>,
+    /// Number of searches to list; 20 by default, at most 1000.
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct HighlightQuery {
+    /// The file, as returned by searches or relative to the indexed directory.
+    path: String,
+    /// `html` (the default) or `ansi`.
+    format: Option<String>,
+    /// Syntect theme; `base16-ocean.dark` by default.
+    theme: Option<String>,
+    /// Comma-separated 1-based numbers of lines to mark, e.g. the lines a search matched; in
+    /// HTML they are anchored as `L<number>`.
+    lines: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SymbolSearchQuery {
+    /// Symbol name or part of it, e.g. `parseHttp` or `http`.
     q: String,
 }
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CrossIndexSearchQuery {
+    /// Query string.
+    q: String,
+    /// Comma-separated index names; every index the caller may read when omitted.
+    indexes: Option<String>,
+    /// Comma-separated tags; only indexes carrying all of them are searched, and 404 is answered
+    /// when no index does.
+    tags: Option<String>,
+    /// Run queries that exceed the server's maximum query cost.
+    #[serde(default)]
+    force: bool,
+    /// Element wrapped around highlighted terms in snippets: `b` (the default), `strong`, `em`,
+    /// `mark` or `span`.
+    highlight_tag: Option<String>,
+    /// Class attribute added to the highlight element.
+    highlight_class: Option<String>,
+    /// Comma-separated languages (e.g. `rust,python` or `rs,py`); results in any language when
+    /// omitted.
+    lang: Option<String>,
+    /// Only return files last modified at or after this time: an RFC 3339 timestamp, a date
+    /// (`2024-05-01`) or an age (`7d`).
+    modified_after: Option<String>,
+    /// Only return files last modified before this time, in the same forms as `modified_after`.
+    modified_before: Option<String>,
+    /// Ranking weights of fields overriding the index's, e.g. `filename=5,content=0.5`.
+    boost: Option<String>,
+    /// Match `q` verbatim, punctuation and case included, instead of parsing it.
+    #[serde(default)]
+    exact: bool,
+    /// Longest fragment of the snippets, in characters; 150 by default.
+    snippet_chars: Option<usize>,
+    /// Best non-overlapping fragments of each file, or `all`; 1 by default.
+    max_fragments: Option<String>,
+    /// Only return files under this directory, relative to the indexed directory, e.g.
+    /// `services/api`.
+    #[serde(rename = "in")]
+    #[param(rename = "in")]
+    within: Option<String>,
+    /// Comma-separated extensions of files to leave out, e.g. `json,lock`.
+    not_ext: Option<String>,
+    /// Comma-separated directories or files to leave out, relative to the indexed directory,
+    /// e.g. `vendor,tests/fixtures`.
+    exclude_path: Option<String>,
+    /// Only return files last committed by an author with these words in their name, for
+    /// indexes created with `git_blame`.
+    author: Option<String>,
+    /// Comma-separated branches to return the files of, for indexes created with `branches`.
+    branch: Option<String>,
+    /// Only return files added or modified between this revision and `HEAD` of the indexed
+    /// directory's repository.
+    changed_since: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ListIndexesQuery {
+    /// Comma-separated tags; only indexes carrying all of them are listed.
+    tags: Option<String>,
+}
+
+/// The `indexes` query parameter, read by [`authorize`] before the handler runs.
 #[derive(Deserialize)]
+struct IndexesParam {
+    indexes: Option<String>,
+}
+
+fn split_names(indexes: Option<&str>) -> Vec<String> {
+    indexes
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|index| !index.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SearchWidgetQuery {
+    /// Query string.
+    q: String,
+    /// Id of the element to render into; by default results are inserted after the script tag.
+    target: Option<String>,
+    /// Maximum number of results to render.
+    #[serde(default = "default_widget_limit")]
+    limit: usize,
+}
+
+fn default_widget_limit() -> usize {
+    10
+}
+
+#[derive(Deserialize, ToSchema)]
 struct CreateIndexRequest {
     name: String,
     path: String,
+    /// Extensions to index, e.g. `["rs", "toml"]`; all files when empty.
+    #[serde(default)]
+    only_extensions: Vec<String>,
+    /// Bytes of each file that are indexed; whole files by default.
+    #[serde(default)]
+    max_file_size: Option<u64>,
+    /// How file content is kept for snippets: `lz4` (default), `zstd` or `none`.
+    #[serde(default)]
+    content_storage: ContentStorage,
+    /// How content is split into search terms: `code` (default, the words of the profile),
+    /// `ngram` (trigrams), `ngram:N` or `ngram:MIN-MAX`.
+    #[serde(default)]
+    tokenizer: Option<String>,
+    /// What the files are: `code` (default), `docs` or `logs`; decides the words and metadata
+    /// fields that are indexed.
+    #[serde(default)]
+    profile: SchemaProfile,
+    /// Lowercasing, stemming and stop words of the docs and logs profiles.
+    #[serde(default)]
+    analyzer: AnalyzerOptions,
+    /// How much matches in `path`, `file_name`, `symbols` and `content` weigh when ranking
+    /// results; searches can override them.
+    #[serde(default)]
+    boosts: FieldBoosts,
+    /// How updates merge segments in the background; tantivy's defaults when unset, or
+    /// `{"disabled": true}` to never merge.
+    #[serde(default)]
+    merge_policy: MergePolicyOptions,
+    /// Encrypts the index files with the key configured for the server.
+    #[serde(default)]
+    encrypt: bool,
+    /// Reads files as UTF-8 only, instead of transcoding UTF-16 and Windows-1252 files.
+    #[serde(default)]
+    utf8_only: bool,
+    /// Indexes files with the same content as another once, listing them with its results.
+    #[serde(default)]
+    dedup: bool,
+    /// Records the author and commit of the last change of each file committed to git.
+    #[serde(default)]
+    git_blame: bool,
+    /// Indexes the files git tracks, listed again by every update, instead of walking `path`.
+    #[serde(default)]
+    tracked_only: bool,
+    /// Also indexes hidden files and directories, whose name starts with a dot.
+    #[serde(default)]
+    hidden: bool,
+    /// Indexes the files the global gitignore of git ignores.
+    #[serde(default)]
+    no_global_gitignore: bool,
+    /// Indexes the files the `.git/info/exclude` of the repository ignores.
+    #[serde(default)]
+    no_git_exclude: bool,
+    /// What becomes of the files of git submodules: `index`, `skip` or `separate`.
+    #[serde(default)]
+    submodules: SubmodulePolicy,
+    /// Indexes the files committed to these branches, each result telling its branch, instead
+    /// of the files of `path`.
+    #[serde(default)]
+    branches: Vec<String>,
+    /// Also indexes the commit history of `path`, in the index `<name>.commits`.
+    #[serde(default)]
+    commits: bool,
+    /// Turns `name` into a valid index name, e.g. `My App` into `my-app`, instead of rejecting
+    /// it; the response has the name the index was created with.
+    #[serde(default)]
+    slug: bool,
+    /// Memory the indexer may buffer documents in, in bytes; sized for the machine by default.
+    #[serde(default)]
+    memory_budget: Option<usize>,
+    /// Threads indexing files in parallel; one per core, up to 8, by default.
+    #[serde(default)]
+    index_threads: Option<usize>,
+    /// Files indexed in a batch; 100 by default.
+    #[serde(default)]
+    batch_size: Option<usize>,
+    /// Batches between intermediate commits; only the final commit by default.
+    #[serde(default)]
+    commit_every: Option<usize>,
+    /// Labels used to group the index, e.g. `["backend"]`.
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// How often search counts are written to disk while the server runs.
+const ANALYTICS_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Indexes built at the same time by `POST /api/indexes/bulk` unless the request says otherwise.
+const DEFAULT_BULK_CONCURRENCY: usize = 4;
+const MAX_BULK_CONCURRENCY: usize = 16;
+
+#[derive(Deserialize, ToSchema)]
+struct BulkCreateRequest {
+    indexes: Vec<CreateIndexRequest>,
+    /// Number of indexes built at the same time; 4 by default, at most 16.
+    concurrency: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BulkCreateResponse {
+    /// One entry per requested index, in request order.
+    results: Vec<BulkCreateResult>,
+    succeeded: usize,
+    failed: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BulkCreateResult {
+    name: String,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index: Option<IndexResponse>,
+    files_indexed: usize,
+    /// Resources used by the build, once it has succeeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<ResourceUsage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl BulkCreateResult {
+    fn failed(name: String, error: String) -> Self {
+        BulkCreateResult {
+            name,
+            success: false,
+            index: None,
+            files_indexed: 0,
+            usage: None,
+            error: Some(error),
+        }
+    }
 }
 
 #[derive(Clone)]
 struct AppState {
     catalog: Arc<IndexCatalog>,
+    jobs: Arc<JobQueue>,
+    analytics: Arc<SearchAnalytics>,
+    history: Arc<SearchHistory>,
+    /// Tried in order to authenticate the bearer token of API requests.
+    auth_backends: Arc<Vec<Box<dyn AuthBackend>>>,
+    require_token: bool,
+    max_query_cost: u64,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    /// Refuse every request that writes, whatever its token.
+    read_only: bool,
+}
+
+pub const DEFAULT_PORT: u16 = 3000;
+pub const DEFAULT_HOST: &str = "localhost";
+
+pub struct ServerOptions {
+    pub port: u16,
+    pub host: String,
+    pub require_token: bool,
+    /// Bearer token granting full access; when set, every API request must be authenticated.
+    pub admin_token: Option<String>,
+    /// Accept access tokens issued by this OIDC provider; implies --require-token.
+    pub oidc: Option<OidcConfig>,
+    pub max_query_cost: u64,
+    pub min_update_interval: Duration,
+    /// Per-client limit on search requests; unlimited when not set.
+    pub rate_limit: Option<RateLimit>,
+    /// Origins allowed to make cross-origin requests; CORS is disabled when empty.
+    pub cors_origins: Vec<String>,
+    /// Methods allowed for cross-origin requests; GET and POST when empty.
+    pub cors_methods: Vec<String>,
+    /// Write a pidfile and answer `beetle serve --status`/`--stop` on the control socket.
+    pub daemon: bool,
+    /// Index creations, updates and rebuilds allowed to run at once; unlimited when unset.
+    pub max_index_jobs: Option<usize>,
+    /// Threads reading files for every index job; rayon's global pool when unset.
+    pub reader_threads: Option<usize>,
+    /// Overrides the writer resources of the indexes the server updates.
+    pub writer_resources: WriterResources,
+    /// Do not record the searches served in the search history.
+    pub no_history: bool,
+    /// Refuse the API requests that write, such as creating, updating or deleting indexes.
+    pub read_only: bool,
+    /// Time between two incremental updates of `auto_update_indexes`; never when unset.
+    pub auto_update: Option<Duration>,
+    /// Indexes updated every `auto_update`; every index when empty.
+    pub auto_update_indexes: Vec<String>,
+}
+
+/// Responses smaller than this are sent as they are, since compressing them would save little.
+const MIN_COMPRESSED_SIZE: u16 = 1024;
+
+/// Compresses responses with brotli or gzip, whichever the client prefers, as they are streamed;
+/// images, fonts, event streams and responses smaller than [`MIN_COMPRESSED_SIZE`] are sent as
+/// they are.
+fn compression_layer() -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new().compress_when(
+        DefaultPredicate::new()
+            .and(SizeAbove::new(MIN_COMPRESSED_SIZE))
+            .and(NotForContentType::const_new("font/")),
+    )
+}
+
+fn cors_layer(origins: &[String], methods: &[String]) -> Result<Option<CorsLayer>, String> {
+    if origins.is_empty() {
+        return Ok(None);
+    }
+
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = origins
+            .iter()
+            .map(|origin| {
+                HeaderValue::from_str(origin)
+                    .map_err(|e| format!("Invalid CORS origin '{origin}': {e}"))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        AllowOrigin::list(origins)
+    };
+
+    let methods = if methods.is_empty() {
+        vec![Method::GET, Method::POST]
+    } else {
+        methods
+            .iter()
+            .map(|method| {
+                Method::from_bytes(method.to_uppercase().as_bytes())
+                    .map_err(|e| format!("Invalid CORS method '{method}': {e}"))
+            })
+            .collect::<Result<Vec<_>, String>>()?
+    };
+
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(methods)
+            .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE]),
+    ))
+}
+
+const BEARER_TOKEN: &str = "bearer_token";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Beetle", description = "Source code search HTTP API"),
+    paths(
+        list_indexes,
+        create_index,
+        create_indexes_bulk,
+        get_index_details,
+        delete_index,
+        search_index,
+        search_symbols,
+        suggest,
+        search_widget,
+        get_index_tree,
+        get_index_stats,
+        highlight_file,
+        search_across_indexes,
+        search_history,
+        reindex_index,
+        update_index,
+        list_jobs,
+        get_job
+    ),
+    modifiers(&BearerTokenSecurity),
+    security((), ("bearer_token" = []))
+)]
+struct ApiDoc;
+
+/// Declares the `Authorization: Bearer` scheme used by admin and read-only API tokens.
+struct BearerTokenSecurity;
+
+impl utoipa::Modify for BearerTokenSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                BEARER_TOKEN,
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+    }
+}
+
+fn error_response(status: StatusCode, error: &str) -> Response {
+    (
+        status,
+        ResponseJson(ErrorResponse {
+            error: error.to_string(),
+        }),
+    )
+        .into_response()
+}
+
+/// Returns the index a read-only token may access through `path`, or `None` when the route is
+/// not one of the read-only routes (`/api/indexes`, `/api/indexes/{name}`,
+/// `/api/indexes/{name}/search`, `/api/indexes/{name}/search.js`,
+/// `/api/indexes/{name}/symbols`, `/api/indexes/{name}/suggest`, `/api/indexes/{name}/tree`,
+/// `/api/indexes/{name}/stats`, `/api/indexes/{name}/highlight`, `/api/search`, `/api/history`).
+///
+/// Routes without an index in their path may still name indexes in the `indexes` parameter.
+fn read_only_route(path: &str) -> Option<Option<&str>> {
+    if path == "/api/search" || path == "/api/history" {
+        return Some(None);
+    }
+
+    let rest = path.strip_prefix("/api/indexes")?;
+    if rest.is_empty() || rest == "/" {
+        return Some(None);
+    }
+
+    let segments: Vec<&str> = rest.trim_start_matches('/').split('/').collect();
+    match segments.as_slice() {
+        [index_name]
+        | [index_name, "search"]
+        | [index_name, "search.js"]
+        | [index_name, "symbols"]
+        | [index_name, "suggest"]
+        | [index_name, "tree"]
+        | [index_name, "stats"]
+        | [index_name, "highlight"] => Some(Some(index_name)),
+        _ => None,
+    }
+}
+
+async fn authorize(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    // Admins included, since a read-only server is one whose indexes are managed elsewhere
+    if state.read_only && request.method() != Method::GET {
+        return error_response(StatusCode::FORBIDDEN, "The server is read-only");
+    }
+
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|value| value.trim().to_string());
+
+    let Some(secret) = bearer else {
+        if state.require_token {
+            return error_response(StatusCode::UNAUTHORIZED, "Missing API token");
+        }
+        return next.run(request).await;
+    };
+
+    let mut principal = None;
+    for backend in state.auth_backends.iter() {
+        match backend.authenticate(&secret).await {
+            Ok(Some(authenticated)) => {
+                principal = Some(authenticated);
+                break;
+            }
+            Ok(None) => {}
+            Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, &e),
+        }
+    }
+    let token = match principal {
+        Some(Principal::Admin) => return next.run(request).await,
+        Some(Principal::ReadOnly(token)) => token,
+        None => return error_response(StatusCode::UNAUTHORIZED, "Invalid API token"),
+    };
+
+    let allowed = request.method() == Method::GET
+        && match read_only_route(request.uri().path()) {
+            Some(Some(index_name)) => token.allows_index(index_name),
+            Some(None) => {
+                let requested = Query::<IndexesParam>::try_from_uri(request.uri())
+                    .map(|Query(param)| split_names(param.indexes.as_deref()))
+                    .unwrap_or_default();
+                requested
+                    .iter()
+                    .all(|index_name| token.allows_index(index_name))
+            }
+            None => false,
+        };
+    if !allowed {
+        return error_response(
+            StatusCode::FORBIDDEN,
+            "API token does not grant access to this resource",
+        );
+    }
+
+    request.extensions_mut().insert(token);
+    next.run(request).await
+}
+
+/// Rejects search requests from clients that exceed `beetle serve --rate-limit`.
+///
+/// Runs after [`authorize`], so clients with a read-only token are limited per token and
+/// everyone else per IP address.
+async fn rate_limit(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(limiter) = &state.rate_limiter else {
+        return next.run(request).await;
+    };
+
+    let client = match request.extensions().get::<ApiToken>() {
+        Some(token) => format!("token:{}", token.id),
+        None => request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip().to_string())
+            .unwrap_or_default(),
+    };
+    if let Err(retry_after) = limiter.check(&client) {
+        let mut response = error_response(StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded");
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            HeaderValue::from(retry_after.as_secs_f64().ceil() as u64),
+        );
+        return response;
+    }
+
+    next.run(request).await
 }
 
-async fn list_indexes(State(state): State<AppState>) -> ResponseJson<Vec<IndexResponse>> {
+#[utoipa::path(
+    get,
+    path = "/api/indexes",
+    tag = "indexes",
+    params(ListIndexesQuery),
+    responses((status = 200, description = "Indexes visible to the caller", body = Vec<IndexResponse>))
+)]
+async fn list_indexes(
+    State(state): State<AppState>,
+    token: Option<Extension<ApiToken>>,
+    Query(params): Query<ListIndexesQuery>,
+) -> ResponseJson<Vec<IndexResponse>> {
+    let tags = split_names(params.tags.as_deref());
     match state.catalog.list() {
         Ok(indexes) => {
             let response: Vec<IndexResponse> = indexes
                 .into_iter()
+                .filter(|index| index.has_tags(&tags))
+                .filter(|index| match &token {
+                    Some(Extension(token)) => token.allows_index(&index.index_name),
+                    None => true,
+                })
                 .map(|index| IndexResponse {
                     name: index.index_name,
                     path: index.index_path,
+                    tags: index.tags,
                 })
                 .collect();
             ResponseJson(response)
@@ -78,28 +833,79 @@ async fn list_indexes(State(state): State<AppState>) -> ResponseJson<Vec<IndexRe
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/indexes/{index_name}",
+    tag = "indexes",
+    params(("index_name" = String, Path, description = "Index name")),
+    responses(
+        (status = 200, body = IndexDetailResponse),
+        (status = 404, description = "Index not found", body = ErrorResponse),
+        (status = 500, description = "The index cannot be read", body = ErrorResponse)
+    )
+)]
 async fn get_index_details(
     State(state): State<AppState>,
     Path(index_name): Path<String>,
 ) -> Result<ResponseJson<IndexDetailResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
-    match state.catalog.get_matadata(&index_name) {
-        Ok(metadata) => {
-            let response = IndexDetailResponse {
-                index_name: metadata.index_name.clone(),
-                index_path: metadata.index_path.clone(),
-                target_path: metadata.target_path.clone(),
-            };
-            Ok(ResponseJson(response))
-        }
-        Err(_) => Err((
+    if state.catalog.get_matadata(&index_name).is_err() {
+        return Err((
             StatusCode::NOT_FOUND,
             ResponseJson(ErrorResponse {
                 error: format!("Index '{index_name}' not found"),
             }),
+        ));
+    }
+
+    // Telling the pending changes scans the target path, so it runs off the async workers
+    let catalog = state.catalog.clone();
+    let info = tokio::task::spawn_blocking(move || catalog.info(&index_name))
+        .await
+        .unwrap_or_else(|e| Err(format!("Failed to read index details: {e}")));
+    match info {
+        Ok(info) => {
+            let metadata = info.metadata;
+            let response = IndexDetailResponse {
+                index_name: metadata.index_name,
+                index_path: metadata.index_path,
+                target_path: metadata.target_path,
+                tags: metadata.tags,
+                target_path_exists: info.target_path_exists,
+                options: metadata.options,
+                format_version: metadata.format_version,
+                docs: info.stats.docs,
+                size_bytes: info.stats.size_bytes,
+                updated_at: info.stats.updated_at,
+                last_update: info.stats.last_update,
+                snapshot_files: info.snapshot_files,
+                latest_snapshot: info.latest_snapshot,
+                fields: info.fields,
+                segments: info.segments,
+                pending: info.pending,
+            };
+            Ok(ResponseJson(response))
+        }
+        Err(error) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ResponseJson(ErrorResponse { error }),
         )),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/indexes/{index_name}/search",
+    tag = "search",
+    params(("index_name" = String, Path, description = "Index name"), SearchQuery),
+    responses(
+        (status = 200, body = SearchResponse),
+        (status = 400, description = "Invalid query, filter, modification time or boost", body = ErrorResponse),
+        (status = 404, description = "Index not found", body = ErrorResponse),
+        (status = 422, description = "The query is too expensive", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse),
+        (status = 500, description = "Search failed", body = ErrorResponse)
+    )
+)]
 async fn search_index(
     State(state): State<AppState>,
     Path(index_name): Path<String>,
@@ -107,161 +913,929 @@ async fn search_index(
 ) -> Result<ResponseJson<SearchResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
     let query = params.q;
 
-    match state.catalog.get_searcher(&index_name) {
-        Ok(searcher) => {
-            let start_time = std::time::Instant::now();
-            let results = searcher.search(&query).map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    ResponseJson(ErrorResponse {
-                        error: format!("Search failed: {e}"),
-                    }),
-                )
-            })?;
-            let duration = start_time.elapsed();
-            let duration_ms = duration.as_secs_f64() * 1000.0;
-
-            let total_results = results.len();
-            let response = SearchResponse {
-                query: query.clone(),
-                index_name: index_name.clone(),
-                results,
-                total_results,
-                duration_ms,
-            };
-            Ok(ResponseJson(response))
-        }
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            ResponseJson(ErrorResponse {
-                error: format!("Search failed: {e}"),
-            }),
-        )),
+    let start_time = std::time::Instant::now();
+    let options = SearchOptions {
+        max_query_cost: (!params.force).then_some(state.max_query_cost),
+        highlight_tag: params
+            .highlight_tag
+            .unwrap_or_else(|| DEFAULT_HIGHLIGHT_TAG.to_string()),
+        highlight_class: params.highlight_class,
+        languages: split_names(params.lang.as_deref()),
+        modified_after: parse_time_param(params.modified_after.as_deref())?,
+        modified_before: parse_time_param(params.modified_before.as_deref())?,
+        boosts: parse_boost_param(params.boost.as_deref())?,
+        exact: params.exact,
+        snippet_chars: params.snippet_chars.unwrap_or(DEFAULT_SNIPPET_CHARS),
+        max_fragments: parse_fragments_param(params.max_fragments.as_deref())?,
+        within: params.within,
+        excluded_extensions: split_names(params.not_ext.as_deref()),
+        excluded_paths: split_names(params.exclude_path.as_deref()),
+        author: params.author,
+        branches: split_names(params.branch.as_deref()),
+        changed_since: params.changed_since,
+    };
+    let results = state
+        .catalog
+        .search(&index_name, &query, &options)
+        .map_err(|e| {
+            (
+                search_error_status(&e),
+                ResponseJson(ErrorResponse {
+                    error: format!("Search failed: {e}"),
+                }),
+            )
+        })?;
+    state.analytics.record_search(&index_name);
+    let duration = start_time.elapsed();
+    let duration_ms = duration.as_secs_f64() * 1000.0;
+    state.history.record(
+        HistoryEntry::new(index_name.clone(), &query, results.len(), duration)
+            .with_exact(params.exact),
+    );
+
+    let total_results = results.len();
+    let response = SearchResponse {
+        query: query.clone(),
+        index_name: index_name.clone(),
+        languages: language_counts(&results),
+        results,
+        total_results,
+        duration_ms,
+    };
+    Ok(ResponseJson(response))
+}
+
+/// The status of a failed search: searches that cannot succeed as asked are the client's error.
+fn search_error_status(error: &SearchError) -> StatusCode {
+    match error {
+        SearchError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+        SearchError::TooExpensive(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        SearchError::IndexNotFound(_) => StatusCode::NOT_FOUND,
+        SearchError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
     }
 }
 
-async fn create_index(
+/// Parses the optional `modified_after` or `modified_before` parameter of a search.
+fn parse_time_param(
+    time: Option<&str>,
+) -> Result<Option<i64>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    time.map(parse_modified_time).transpose().map_err(|error| {
+        (
+            StatusCode::BAD_REQUEST,
+            ResponseJson(ErrorResponse { error }),
+        )
+    })
+}
+
+/// Parses the optional `max_fragments` parameter of a search.
+fn parse_fragments_param(
+    count: Option<&str>,
+) -> Result<usize, (StatusCode, ResponseJson<ErrorResponse>)> {
+    count
+        .map_or(Ok(DEFAULT_MAX_FRAGMENTS), parse_max_fragments)
+        .map_err(|error| {
+            (
+                StatusCode::BAD_REQUEST,
+                ResponseJson(ErrorResponse { error }),
+            )
+        })
+}
+
+/// Parses the optional `boost` parameter of a search.
+fn parse_boost_param(
+    boost: Option<&str>,
+) -> Result<FieldBoosts, (StatusCode, ResponseJson<ErrorResponse>)> {
+    boost
+        .map(str::parse)
+        .transpose()
+        .map(Option::unwrap_or_default)
+        .map_err(|error| {
+            (
+                StatusCode::BAD_REQUEST,
+                ResponseJson(ErrorResponse { error }),
+            )
+        })
+}
+
+/// Completes the last word of a query typed so far, for search-as-you-type.
+#[utoipa::path(
+    get,
+    path = "/api/indexes/{index_name}/suggest",
+    tag = "search",
+    params(("index_name" = String, Path, description = "Index name"), SuggestQuery),
+    responses(
+        (status = 200, body = SuggestResponse),
+        (status = 400, description = "The path is not a directory of the indexed directory", body = ErrorResponse),
+        (status = 404, description = "Index not found", body = ErrorResponse),
+        (status = 500, description = "The index cannot be read", body = ErrorResponse)
+    )
+)]
+async fn suggest(
     State(state): State<AppState>,
-    ResponseJson(payload): ResponseJson<CreateIndexRequest>,
-) -> Result<ResponseJson<IndexResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
-    // Validate path exists
-    let target_path = std::path::Path::new(&payload.path);
-    if !target_path.exists() {
+    Path(index_name): Path<String>,
+    Query(params): Query<SuggestQuery>,
+) -> Result<ResponseJson<SuggestResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    if state.catalog.get_matadata(&index_name).is_err() {
         return Err((
-            StatusCode::BAD_REQUEST,
+            StatusCode::NOT_FOUND,
             ResponseJson(ErrorResponse {
-                error: format!("Path does not exist: {}", payload.path),
+                error: format!("Index '{index_name}' not found"),
             }),
         ));
     }
 
-    if !target_path.is_dir() {
-        return Err((
+    let searcher = state.catalog.get_searcher(&index_name).map_err(|error| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ResponseJson(ErrorResponse { error }),
+        )
+    })?;
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_SUGGESTIONS)
+        .min(MAX_SUGGESTIONS);
+    let suggestions = searcher.suggest(&params.q, limit).map_err(|error| {
+        (
             StatusCode::BAD_REQUEST,
+            ResponseJson(ErrorResponse { error }),
+        )
+    })?;
+
+    Ok(ResponseJson(SuggestResponse {
+        query: params.q,
+        index_name,
+        suggestions,
+    }))
+}
+
+/// Lists the files and directories of a directory of the indexed files, for a file explorer.
+#[utoipa::path(
+    get,
+    path = "/api/indexes/{index_name}/tree",
+    tag = "indexes",
+    params(("index_name" = String, Path, description = "Index name"), TreeQuery),
+    responses(
+        (status = 200, body = TreeResponse),
+        (status = 400, description = "The path is not a directory of the indexed directory", body = ErrorResponse),
+        (status = 404, description = "Index not found", body = ErrorResponse),
+        (status = 500, description = "The index cannot be read", body = ErrorResponse)
+    )
+)]
+async fn get_index_tree(
+    State(state): State<AppState>,
+    Path(index_name): Path<String>,
+    Query(params): Query<TreeQuery>,
+) -> Result<ResponseJson<TreeResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    if state.catalog.get_matadata(&index_name).is_err() {
+        return Err((
+            StatusCode::NOT_FOUND,
             ResponseJson(ErrorResponse {
-                error: format!("Path is not a directory: {}", payload.path),
+                error: format!("Index '{index_name}' not found"),
             }),
         ));
     }
 
-    // Check if index already exists
-    match state.catalog.list() {
-        Ok(existing_indexes) => {
-            if existing_indexes
-                .iter()
-                .any(|idx| idx.index_name == payload.name)
-            {
-                return Err((
-                    StatusCode::CONFLICT,
-                    ResponseJson(ErrorResponse {
-                        error: format!("Index '{}' already exists", payload.name),
-                    }),
-                ));
-            }
-        }
-        Err(_) => {
-            // Continue with creation if we can't list existing indexes
-        }
+    let searcher = state.catalog.get_searcher(&index_name).map_err(|error| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ResponseJson(ErrorResponse { error }),
+        )
+    })?;
+    let entries = searcher.tree(&params.path).map_err(|error| {
+        (
+            StatusCode::BAD_REQUEST,
+            ResponseJson(ErrorResponse { error }),
+        )
+    })?;
+
+    Ok(ResponseJson(TreeResponse {
+        index_name,
+        path: params.path,
+        entries,
+    }))
+}
+
+/// Largest files listed by `GET /api/indexes/{name}/stats` at most.
+const MAX_LARGEST_FILES: usize = 100;
+
+/// Returns the size of an index and how its documents are spread over extensions and
+/// directories, with its largest files.
+#[utoipa::path(
+    get,
+    path = "/api/indexes/{index_name}/stats",
+    tag = "indexes",
+    params(("index_name" = String, Path, description = "Index name"), IndexStatsQuery),
+    responses(
+        (status = 200, body = IndexStatsResponse),
+        (status = 404, description = "Index not found", body = ErrorResponse),
+        (status = 500, description = "The index cannot be read", body = ErrorResponse)
+    )
+)]
+async fn get_index_stats(
+    State(state): State<AppState>,
+    Path(index_name): Path<String>,
+    Query(params): Query<IndexStatsQuery>,
+) -> Result<ResponseJson<IndexStatsResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let error = |status, error: String| (status, ResponseJson(ErrorResponse { error }));
+    if state.catalog.get_matadata(&index_name).is_err() {
+        return Err(error(
+            StatusCode::NOT_FOUND,
+            format!("Index '{index_name}' not found"),
+        ));
     }
+    let largest = params
+        .largest
+        .unwrap_or(DEFAULT_LARGEST_FILES)
+        .min(MAX_LARGEST_FILES);
 
-    match state.catalog.create(&payload.name, &payload.path) {
-        Ok(_) => {
-            let response = IndexResponse {
-                name: payload.name,
-                path: payload.path,
-            };
-            Ok(ResponseJson(response))
+    // Every term of the paths is read, which takes a while for large indexes
+    let catalog = state.catalog.clone();
+    tokio::task::spawn_blocking(move || {
+        let stats = catalog
+            .stats(&index_name)
+            .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        let breakdown = catalog
+            .get_searcher(&index_name)
+            .and_then(|searcher| searcher.breakdown(largest))
+            .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+        Ok(ResponseJson(IndexStatsResponse {
+            index_name,
+            docs: stats.docs,
+            size_bytes: stats.size_bytes,
+            updated_at: stats.updated_at,
+            last_update: stats.last_update,
+            breakdown,
+        }))
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to compute index stats: {e}"),
+        ))
+    })
+}
+
+/// Returns the indexed content of a file highlighted for a preview.
+#[utoipa::path(
+    get,
+    path = "/api/indexes/{index_name}/highlight",
+    tag = "indexes",
+    params(("index_name" = String, Path, description = "Index name"), HighlightQuery),
+    responses(
+        (status = 200, body = HighlightResponse),
+        (status = 400, description = "Invalid format, theme or lines", body = ErrorResponse),
+        (status = 404, description = "Index or file not found", body = ErrorResponse),
+        (status = 500, description = "The index cannot be read", body = ErrorResponse)
+    )
+)]
+async fn highlight_file(
+    State(state): State<AppState>,
+    Path(index_name): Path<String>,
+    Query(params): Query<HighlightQuery>,
+) -> Result<ResponseJson<HighlightResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let error = |status, error: String| (status, ResponseJson(ErrorResponse { error }));
+    let Ok(metadata) = state.catalog.get_matadata(&index_name) else {
+        return Err(error(
+            StatusCode::NOT_FOUND,
+            format!("Index '{index_name}' not found"),
+        ));
+    };
+    let options = HighlightOptions {
+        format: params
+            .format
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|e| error(StatusCode::BAD_REQUEST, e))?
+            .unwrap_or_default(),
+        theme: params.theme.unwrap_or_else(|| DEFAULT_THEME.to_string()),
+        match_lines: split_names(params.lines.as_deref())
+            .iter()
+            .map(|line| {
+                line.parse::<usize>()
+                    .map_err(|_| format!("Invalid line number '{line}'"))
+            })
+            .collect::<Result<_, _>>()
+            .map_err(|e| error(StatusCode::BAD_REQUEST, e))?,
+    };
+    if !themes().contains(&options.theme.as_str()) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Unknown theme '{}'. Known themes: {}",
+                options.theme,
+                themes().join(", ")
+            ),
+        ));
+    }
+
+    // Highlighting a large file takes a while, so it runs off the async workers
+    let catalog = state.catalog.clone();
+    let path = params.path;
+    tokio::task::spawn_blocking(move || {
+        let searcher = catalog
+            .get_searcher(&index_name)
+            .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        let stored = to_slashes(&path);
+        let relative = to_slashes(
+            &std::path::Path::new(&metadata.target_path)
+                .join(&stored)
+                .to_string_lossy(),
+        );
+        let mut found = None;
+        for candidate in [stored, relative] {
+            let content = searcher
+                .file_content(&candidate)
+                .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            if let Some(content) = content {
+                found = Some((candidate, content));
+                break;
+            }
         }
-        Err(e) => Err((
+        let Some((file_path, content)) = found else {
+            return Err(error(
+                StatusCode::NOT_FOUND,
+                format!("File '{path}' is not in index '{index_name}'"),
+            ));
+        };
+        let file = highlight(&file_path, &content, &options)
+            .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+        Ok(ResponseJson(HighlightResponse {
+            index_name,
+            path,
+            file,
+        }))
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(error(
             StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to highlight file: {e}"),
+        ))
+    })
+}
+
+/// Finds the definitions of functions, types and classes named like the query.
+#[utoipa::path(
+    get,
+    path = "/api/indexes/{index_name}/symbols",
+    tag = "search",
+    params(("index_name" = String, Path, description = "Index name"), SymbolSearchQuery),
+    responses(
+        (status = 200, body = SymbolSearchResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse),
+        (status = 500, description = "Search failed", body = ErrorResponse)
+    )
+)]
+async fn search_symbols(
+    State(state): State<AppState>,
+    Path(index_name): Path<String>,
+    Query(params): Query<SymbolSearchQuery>,
+) -> Result<ResponseJson<SymbolSearchResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let start_time = std::time::Instant::now();
+    let results = state
+        .catalog
+        .search_symbols(std::slice::from_ref(&index_name), &params.q)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ResponseJson(ErrorResponse {
+                    error: format!("Search failed: {e}"),
+                }),
+            )
+        })?;
+    state.analytics.record_search(&index_name);
+    let duration = start_time.elapsed();
+    let duration_ms = duration.as_secs_f64() * 1000.0;
+    state.history.record(
+        HistoryEntry::new(index_name.clone(), &params.q, results.len(), duration)
+            .with_symbols(true),
+    );
+
+    Ok(ResponseJson(SymbolSearchResponse {
+        query: params.q,
+        index_name,
+        total_results: results.len(),
+        results,
+        duration_ms,
+    }))
+}
+
+/// Searches several indexes in parallel and merges the results by score.
+#[utoipa::path(
+    get,
+    path = "/api/search",
+    tag = "search",
+    params(CrossIndexSearchQuery),
+    responses(
+        (status = 200, body = CrossIndexSearchResponse),
+        (status = 400, description = "Invalid query, filter, modification time or boost", body = ErrorResponse),
+        (status = 404, description = "Index not found", body = ErrorResponse),
+        (status = 422, description = "The query is too expensive", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse),
+        (status = 500, description = "Search failed", body = ErrorResponse)
+    )
+)]
+async fn search_across_indexes(
+    State(state): State<AppState>,
+    token: Option<Extension<ApiToken>>,
+    Query(params): Query<CrossIndexSearchQuery>,
+) -> Result<ResponseJson<CrossIndexSearchResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let start_time = std::time::Instant::now();
+    let search_error = |e: SearchError| {
+        (
+            search_error_status(&e),
             ResponseJson(ErrorResponse {
-                error: format!("Failed to create index: {e}"),
+                error: format!("Search failed: {e}"),
             }),
-        )),
+        )
+    };
+
+    let mut indexes = split_names(params.indexes.as_deref());
+    if indexes.is_empty() {
+        indexes = state
+            .catalog
+            .searchable_indexes()
+            .map_err(|e| search_error(e.into()))?
+            .into_iter()
+            .filter(|index_name| match &token {
+                Some(Extension(token)) => token.allows_index(index_name),
+                None => true,
+            })
+            .collect();
     }
+    let tags = split_names(params.tags.as_deref());
+    if !tags.is_empty() {
+        let tagged = state
+            .catalog
+            .indexes_tagged(&tags)
+            .map_err(|e| search_error(e.into()))?;
+        // As `beetle search --tag`, rather than searching nothing
+        if tagged.is_empty() {
+            return Err(search_error(SearchError::IndexNotFound(format!(
+                "No index is tagged {}",
+                tags.join(", ")
+            ))));
+        }
+        indexes.retain(|index_name| tagged.contains(index_name));
+    }
+
+    let options = SearchOptions {
+        max_query_cost: (!params.force).then_some(state.max_query_cost),
+        highlight_tag: params
+            .highlight_tag
+            .unwrap_or_else(|| DEFAULT_HIGHLIGHT_TAG.to_string()),
+        highlight_class: params.highlight_class,
+        languages: split_names(params.lang.as_deref()),
+        modified_after: parse_time_param(params.modified_after.as_deref())?,
+        modified_before: parse_time_param(params.modified_before.as_deref())?,
+        boosts: parse_boost_param(params.boost.as_deref())?,
+        exact: params.exact,
+        snippet_chars: params.snippet_chars.unwrap_or(DEFAULT_SNIPPET_CHARS),
+        max_fragments: parse_fragments_param(params.max_fragments.as_deref())?,
+        within: params.within,
+        excluded_extensions: split_names(params.not_ext.as_deref()),
+        excluded_paths: split_names(params.exclude_path.as_deref()),
+        author: params.author,
+        branches: split_names(params.branch.as_deref()),
+        changed_since: params.changed_since,
+    };
+    let results = state
+        .catalog
+        .search_indexes(&indexes, &params.q, &options)
+        .map_err(search_error)?;
+    for index_name in &indexes {
+        state.analytics.record_search(index_name);
+    }
+    let duration = start_time.elapsed();
+    state.history.record(
+        HistoryEntry::new(indexes.join(","), &params.q, results.len(), duration)
+            .with_exact(params.exact),
+    );
+
+    Ok(ResponseJson(CrossIndexSearchResponse {
+        query: params.q,
+        indexes,
+        total_results: results.len(),
+        languages: language_counts(&results),
+        results,
+        duration_ms: duration.as_secs_f64() * 1000.0,
+    }))
 }
 
-async fn reindex_index(
+/// Searches listed by `GET /api/history` when `limit` is not given, and the most it lists.
+const DEFAULT_HISTORY_ENTRIES: usize = 20;
+const MAX_HISTORY_ENTRIES: usize = 1000;
+
+/// Lists the searches that ran, from the command line and the API alike, the most recent first.
+///
+/// Read-only tokens only see the searches of indexes they may read.
+#[utoipa::path(
+    get,
+    path = "/api/history",
+    tag = "search",
+    params(HistoryQuery),
+    responses(
+        (status = 200, body = HistoryResponse),
+        (status = 500, description = "The history cannot be read", body = ErrorResponse)
+    )
+)]
+async fn search_history(
+    State(state): State<AppState>,
+    token: Option<Extension<ApiToken>>,
+    Query(params): Query<HistoryQuery>,
+) -> Result<ResponseJson<HistoryResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_HISTORY_ENTRIES)
+        .min(MAX_HISTORY_ENTRIES);
+    let entries = state
+        .history
+        .recent(params.index.as_deref(), usize::MAX)
+        .map_err(|error| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ResponseJson(ErrorResponse { error }),
+            )
+        })?
+        .into_iter()
+        .filter(|numbered| match &token {
+            Some(Extension(token)) => {
+                let index_names = numbered.entry.index_names();
+                !index_names.is_empty()
+                    && index_names
+                        .iter()
+                        .all(|index_name| token.allows_index(index_name))
+            }
+            None => true,
+        })
+        .take(limit)
+        .collect();
+
+    Ok(ResponseJson(HistoryResponse { entries }))
+}
+
+/// Returns a script that renders the results of a search into the embedding page.
+#[utoipa::path(
+    get,
+    path = "/api/indexes/{index_name}/search.js",
+    tag = "search",
+    params(("index_name" = String, Path, description = "Index name"), SearchWidgetQuery),
+    responses(
+        (status = 200, description = "Search widget script", content_type = "application/javascript", body = String),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+async fn search_widget(
     State(state): State<AppState>,
     Path(index_name): Path<String>,
-) -> Result<ResponseJson<IndexResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
-    // Get existing index metadata to retrieve the target path
-    let metadata = match state.catalog.get_matadata(&index_name) {
-        Ok(metadata) => metadata,
-        Err(_) => {
-            return Err((
-                StatusCode::NOT_FOUND,
-                ResponseJson(ErrorResponse {
-                    error: format!("Index '{index_name}' not found"),
+    Query(params): Query<SearchWidgetQuery>,
+) -> Response {
+    let options = SearchOptions {
+        max_query_cost: Some(state.max_query_cost),
+        highlight_tag: "mark".to_string(),
+        highlight_class: Some("beetle-match".to_string()),
+        languages: Vec::new(),
+        modified_after: None,
+        modified_before: None,
+        boosts: FieldBoosts::default(),
+        exact: false,
+        snippet_chars: DEFAULT_SNIPPET_CHARS,
+        max_fragments: DEFAULT_MAX_FRAGMENTS,
+        within: None,
+        excluded_extensions: Vec::new(),
+        excluded_paths: Vec::new(),
+        author: None,
+        branches: Vec::new(),
+        changed_since: None,
+    };
+    let search = state.catalog.search(&index_name, &params.q, &options);
+
+    let (status, data) = match search {
+        Ok(results) => {
+            state.analytics.record_search(&index_name);
+            (
+                StatusCode::OK,
+                serde_json::json!({
+                    "query": params.q,
+                    "index_name": index_name,
+                    "target": params.target,
+                    "total_results": results.len(),
+                    "results": results.into_iter().take(params.limit).collect::<Vec<_>>(),
                 }),
-            ));
+            )
         }
+        Err(e) => (
+            search_error_status(&e),
+            serde_json::json!({
+                "target": params.target,
+                "error": format!("Search failed: {e}"),
+            }),
+        ),
     };
 
-    // Reset the index (clear existing data)
-    match state.catalog.reset(&index_name) {
-        Ok(_) => {}
-        Err(e) => {
+    match render_search_widget(&data) {
+        Ok(script) => (
+            status,
+            [
+                (
+                    header::CONTENT_TYPE,
+                    "application/javascript; charset=utf-8",
+                ),
+                (header::CACHE_CONTROL, "no-store"),
+            ],
+            script,
+        )
+            .into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/indexes",
+    tag = "indexes",
+    request_body = CreateIndexRequest,
+    responses(
+        (status = 200, description = "Index created and built", body = IndexResponse),
+        (status = 400, description = "Invalid target path", body = ErrorResponse),
+        (status = 409, description = "Index already exists", body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
+async fn create_index(
+    State(state): State<AppState>,
+    ResponseJson(payload): ResponseJson<CreateIndexRequest>,
+) -> Result<ResponseJson<IndexResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    create_from_request(&state.catalog, payload)
+        .map(ResponseJson)
+        .map_err(|(status, error)| (status, ResponseJson(ErrorResponse { error })))
+}
+
+/// Validates `request` and creates the (empty) index it describes.
+fn create_from_request(
+    catalog: &IndexCatalog,
+    mut request: CreateIndexRequest,
+) -> Result<IndexResponse, (StatusCode, String)> {
+    if request.slug {
+        request.name = slugify_index_name(&request.name);
+    }
+    validate_index_name(&request.name).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    // Validate path exists
+    let target_path = std::path::Path::new(&request.path);
+    if !target_path.exists() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Path does not exist: {}", request.path),
+        ));
+    }
+
+    if !target_path.is_dir() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Path is not a directory: {}", request.path),
+        ));
+    }
+
+    // Check if index already exists; continue with creation if we can't list existing indexes
+    if let Ok(existing_indexes) = catalog.list() {
+        if existing_indexes
+            .iter()
+            .any(|idx| idx.index_name == request.name)
+        {
             return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                ResponseJson(ErrorResponse {
-                    error: format!("Failed to reset index: {e}"),
-                }),
+                StatusCode::CONFLICT,
+                format!("Index '{}' already exists", request.name),
             ));
         }
     }
 
-    // Create a new writer to rebuild the index
-    let mut writer = match state.catalog.get_writer(&index_name) {
-        Ok(writer) => writer,
-        Err(e) => {
-            return Err((
+    let tokenizer = match &request.tokenizer {
+        Some(tokenizer) => tokenizer
+            .parse::<ContentTokenizer>()
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?,
+        None => ContentTokenizer::default(),
+    };
+    request
+        .boosts
+        .validate()
+        .and_then(|_| request.merge_policy.validate())
+        .and_then(|_| request.analyzer.validate(request.profile, tokenizer))
+        .and_then(|_| match request.max_file_size {
+            Some(0) => Err("The maximum file size must be at least 1 byte".to_string()),
+            _ => Ok(()),
+        })
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let options = IndexingOptions::default()
+        .with_only_extensions(&request.only_extensions)
+        .with_max_file_size(request.max_file_size)
+        .with_content_storage(request.content_storage)
+        .with_tokenizer(tokenizer)
+        .with_profile(request.profile)
+        .with_analyzer(request.analyzer.clone())
+        .with_boosts(request.boosts)
+        .with_merge_policy(request.merge_policy)
+        .with_encryption(request.encrypt)
+        .with_utf8_only(request.utf8_only)
+        .with_dedup(request.dedup)
+        .with_git_blame(request.git_blame)
+        .with_tracked_only(request.tracked_only)
+        .with_hidden(request.hidden)
+        .with_no_global_gitignore(request.no_global_gitignore)
+        .with_no_git_exclude(request.no_git_exclude)
+        .with_submodules(request.submodules)
+        .with_branches(request.branches.clone())
+        .with_commits(request.commits)
+        .with_writer_resources(WriterResources {
+            memory_budget: request.memory_budget,
+            index_threads: request.index_threads,
+            batch_size: request.batch_size,
+            commit_every: request.commit_every,
+        });
+    catalog
+        .create(&request.name, &request.path, &options, &request.tags)
+        .map_err(|e| {
+            (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                ResponseJson(ErrorResponse {
-                    error: format!("Failed to create index writer: {e}"),
-                }),
+                format!("Failed to create index: {e}"),
+            )
+        })?;
+
+    Ok(IndexResponse {
+        name: request.name,
+        path: request.path,
+        tags: request.tags,
+    })
+}
+
+/// Creates several indexes and builds them, a few at a time, reporting the outcome per index.
+#[utoipa::path(
+    post,
+    path = "/api/indexes/bulk",
+    tag = "indexes",
+    request_body = BulkCreateRequest,
+    responses((status = 200, description = "Outcome of every requested index", body = BulkCreateResponse))
+)]
+async fn create_indexes_bulk(
+    State(state): State<AppState>,
+    ResponseJson(payload): ResponseJson<BulkCreateRequest>,
+) -> ResponseJson<BulkCreateResponse> {
+    let concurrency = payload
+        .concurrency
+        .unwrap_or(DEFAULT_BULK_CONCURRENCY)
+        .clamp(1, MAX_BULK_CONCURRENCY);
+    let permits = Arc::new(tokio::sync::Semaphore::new(concurrency));
+
+    let mut seen = std::collections::HashSet::new();
+    let mut tasks = tokio::task::JoinSet::new();
+    let mut results: Vec<Option<BulkCreateResult>> = Vec::new();
+    for (position, mut request) in payload.indexes.into_iter().enumerate() {
+        results.push(None);
+        if request.slug {
+            request.name = slugify_index_name(&request.name);
+        }
+        // Creating the same index twice concurrently would race on its directory
+        if !seen.insert(request.name.clone()) {
+            results[position] = Some(BulkCreateResult::failed(
+                request.name,
+                "Index listed more than once in the request".to_string(),
             ));
+            continue;
         }
+
+        let catalog = state.catalog.clone();
+        let permits = permits.clone();
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            let name = request.name.clone();
+            let result = tokio::task::spawn_blocking(move || create_and_build(&catalog, request))
+                .await
+                .unwrap_or_else(|e| BulkCreateResult::failed(name, format!("Worker failed: {e}")));
+            (position, result)
+        });
+    }
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok((position, result)) = joined {
+            results[position] = Some(result);
+        }
+    }
+
+    let results: Vec<BulkCreateResult> = results.into_iter().flatten().collect();
+    let succeeded = results.iter().filter(|result| result.success).count();
+    ResponseJson(BulkCreateResponse {
+        failed: results.len() - succeeded,
+        succeeded,
+        results,
+    })
+}
+
+/// Creates and builds one index of a bulk request, removing it again if the build fails so the
+/// request can simply be retried.
+fn create_and_build(catalog: &IndexCatalog, request: CreateIndexRequest) -> BulkCreateResult {
+    let name = request.name.clone();
+    let index = match create_from_request(catalog, request) {
+        Ok(index) => index,
+        Err((_, error)) => return BulkCreateResult::failed(name, error),
     };
 
-    // Build the index from the target path
-    match writer.index() {
-        Ok(_) => {
-            let response = IndexResponse {
-                name: index_name.clone(),
-                path: metadata.target_path,
-            };
-            Ok(ResponseJson(response))
+    match catalog
+        .get_writer(&name, false)
+        .and_then(|mut writer| writer.index())
+    {
+        Ok(record) => BulkCreateResult {
+            name,
+            success: true,
+            index: Some(index),
+            files_indexed: record.files_added,
+            usage: Some(record.usage),
+            error: None,
+        },
+        Err(e) => {
+            let _ = catalog.remove(&name);
+            BulkCreateResult::failed(name, format!("Failed to build index: {e}"))
         }
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Schedules a full rebuild of the index on the job queue.
+#[utoipa::path(
+    post,
+    path = "/api/indexes/{index_name}/reindex",
+    tag = "indexes",
+    params(("index_name" = String, Path, description = "Index name")),
+    responses(
+        (status = 202, description = "Reindex job queued", body = Job),
+        (status = 404, description = "Index not found", body = ErrorResponse)
+    )
+)]
+async fn reindex_index(
+    State(state): State<AppState>,
+    Path(index_name): Path<String>,
+) -> Result<(StatusCode, ResponseJson<Job>), (StatusCode, ResponseJson<ErrorResponse>)> {
+    if state.catalog.get_matadata(&index_name).is_err() {
+        return Err((
+            StatusCode::NOT_FOUND,
             ResponseJson(ErrorResponse {
-                error: format!("Failed to rebuild index: {e}"),
+                error: format!("Index '{index_name}' not found"),
+            }),
+        ));
+    }
+
+    // The rebuild runs on the job worker; clients poll `GET /api/jobs/{id}` for its progress
+    let job = state.jobs.enqueue_reindex(&index_name);
+    Ok((StatusCode::ACCEPTED, ResponseJson(job)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/jobs",
+    tag = "jobs",
+    responses((status = 200, body = JobQueueStatus))
+)]
+async fn list_jobs(State(state): State<AppState>) -> ResponseJson<JobQueueStatus> {
+    ResponseJson(state.jobs.status())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/jobs/{job_id}",
+    tag = "jobs",
+    params(("job_id" = String, Path, description = "Job id")),
+    responses(
+        (status = 200, body = Job),
+        (status = 404, description = "Job not found", body = ErrorResponse)
+    )
+)]
+async fn get_job(
+    State(state): State<AppState>,
+    Path(job_id): Path<String>,
+) -> Result<ResponseJson<Job>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    match state.jobs.get(&job_id) {
+        Some(job) => Ok(ResponseJson(job)),
+        None => Err((
+            StatusCode::NOT_FOUND,
+            ResponseJson(ErrorResponse {
+                error: format!("Job '{job_id}' not found"),
             }),
         )),
     }
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/indexes/{index_name}",
+    tag = "indexes",
+    params(("index_name" = String, Path, description = "Index name")),
+    responses(
+        (status = 200, description = "Index removed", body = IndexResponse),
+        (status = 404, description = "Index not found", body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
 async fn delete_index(
     State(state): State<AppState>,
     Path(index_name): Path<String>,
@@ -283,6 +1857,7 @@ async fn delete_index(
             let response = IndexResponse {
                 name: index_name.clone(),
                 path: metadata.target_path,
+                tags: metadata.tags,
             };
             Ok(ResponseJson(response))
         }
@@ -295,6 +1870,18 @@ async fn delete_index(
     }
 }
 
+/// Incrementally updates the index and waits for the update to finish.
+#[utoipa::path(
+    post,
+    path = "/api/indexes/{index_name}/update",
+    tag = "indexes",
+    params(("index_name" = String, Path, description = "Index name")),
+    responses(
+        (status = 200, description = "Index updated", body = IndexResponse),
+        (status = 404, description = "Index not found", body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
 async fn update_index(
     State(state): State<AppState>,
     Path(index_name): Path<String>,
@@ -311,74 +1898,484 @@ async fn update_index(
         }
     };
 
-    let mut writer = match state.catalog.get_writer(&index_name) {
-        Ok(writer) => writer,
-        Err(e) => {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                ResponseJson(ErrorResponse {
-                    error: format!("Failed to create index writer: {e}"),
-                }),
-            ));
-        }
-    };
+    // Opening the writer may wait for a free index job slot, so it runs off the async workers
+    let catalog = state.catalog.clone();
+    let name = index_name.clone();
+    let update = tokio::task::spawn_blocking(move || {
+        let mut writer = catalog
+            .get_writer(&name, false)
+            .map_err(|e| format!("Failed to create index writer: {e}"))?;
+        writer
+            .index()
+            .map_err(|e| format!("Failed to update index: {e}"))
+    })
+    .await
+    .unwrap_or_else(|e| Err(format!("Failed to update index: {e}")));
 
-    match writer.index() {
+    match update {
         Ok(_) => {
             let response = IndexResponse {
                 name: index_name.clone(),
                 path: metadata.target_path,
+                tags: metadata.tags,
             };
             Ok(ResponseJson(response))
         }
-        Err(e) => Err((
+        Err(error) => Err((
             StatusCode::INTERNAL_SERVER_ERROR,
-            ResponseJson(ErrorResponse {
-                error: format!("Failed to update index: {e}"),
-            }),
+            ResponseJson(ErrorResponse { error }),
         )),
     }
 }
 
+/// Updates `indexes`, or every index when empty, incrementally every `interval`.
+fn spawn_auto_update(catalog: Arc<IndexCatalog>, interval: Duration, indexes: Vec<String>) {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        // The first tick completes right away; the first update is one interval after startup
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            let catalog = catalog.clone();
+            let indexes = indexes.clone();
+            // Opening a writer may wait for a free index job slot, so it runs off the async
+            // workers, one index after the other
+            let _ = tokio::task::spawn_blocking(move || auto_update(&catalog, indexes)).await;
+        }
+    });
+}
+
+/// Updates `indexes`, or every index; an index whose target path is gone is left as it is
+/// rather than emptied, e.g. while its drive is unmounted.
+fn auto_update(catalog: &IndexCatalog, mut indexes: Vec<String>) {
+    if indexes.is_empty() {
+        indexes = match catalog.list() {
+            Ok(list) => list.into_iter().map(|index| index.index_name).collect(),
+            Err(e) => {
+                tracing::warn!("Auto-update cannot list the indexes: {e}");
+                return;
+            }
+        };
+    }
+
+    for index_name in indexes {
+        let target_path = match catalog.get_matadata(&index_name) {
+            Ok(metadata) => PathBuf::from(metadata.target_path),
+            Err(e) => {
+                tracing::warn!("Auto-update of '{index_name}' failed: {e}");
+                continue;
+            }
+        };
+        if !target_path.is_dir() {
+            tracing::warn!(
+                "Auto-update skips '{index_name}': its target path {} is missing",
+                target_path.display()
+            );
+            continue;
+        }
+        let update = catalog
+            .get_writer(&index_name, false)
+            .and_then(|mut writer| writer.index());
+        match update {
+            Ok(record) => tracing::info!(
+                "Auto-updated '{index_name}': {} added, {} modified, {} removed",
+                record.files_added,
+                record.files_modified,
+                record.files_removed
+            ),
+            Err(e) => tracing::warn!("Auto-update of '{index_name}' failed: {e}"),
+        }
+    }
+}
+
+/// How the API is served, for the home and every tenant alike; see [`ServerOptions`].
+#[derive(Clone)]
+struct ApiSettings {
+    /// Bearer token granting full access to the home and to every tenant.
+    admin_token: Option<String>,
+    require_token: bool,
+    max_query_cost: u64,
+    min_update_interval: Duration,
+    /// Shared by the home and the tenants, so a client cannot multiply its limit.
+    rate_limiter: Option<Arc<RateLimiter>>,
+    read_only: bool,
+    no_history: bool,
+    writer_resources: WriterResources,
+    max_index_jobs: Option<usize>,
+    reader_threads: Option<usize>,
+}
+
+/// The catalog of the indexes in `home`, whose writes share its job slots and reader threads
+/// so that concurrent creations, updates and rebuilds cannot saturate the machine.
+fn open_catalog(home: PathBuf, settings: &ApiSettings) -> Result<Arc<IndexCatalog>, String> {
+    // Searchers stay open between requests instead of reopening the index every time
+    let mut catalog = IndexCatalog::new(FsStorage::new(home))
+        .with_writer_resources(settings.writer_resources)
+        .with_searcher_cache();
+    if let Some(max_jobs) = settings.max_index_jobs {
+        catalog = catalog.with_max_index_jobs(max_jobs)?;
+    }
+    if let Some(threads) = settings.reader_threads {
+        catalog = catalog.with_reader_threads(threads)?;
+    }
+
+    Ok(Arc::new(catalog))
+}
+
+impl AppState {
+    /// The state of the API serving `catalog`, the indexes of `home`. Starts its job queue and
+    /// the periodic flush of its search counts, so it must be called within the runtime.
+    fn new(
+        home: PathBuf,
+        catalog: Arc<IndexCatalog>,
+        auth_backends: Vec<Box<dyn AuthBackend>>,
+        settings: &ApiSettings,
+    ) -> Self {
+        let analytics = Arc::new(SearchAnalytics::load(home.clone()));
+        let flusher = analytics.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(ANALYTICS_FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = flusher.flush() {
+                    tracing::warn!("{e}");
+                }
+            }
+        });
+
+        AppState {
+            jobs: Arc::new(JobQueue::start(
+                catalog.clone(),
+                analytics.clone(),
+                settings.min_update_interval,
+            )),
+            analytics,
+            history: Arc::new(SearchHistory::open(home, !settings.no_history)),
+            catalog,
+            auth_backends: Arc::new(auth_backends),
+            require_token: settings.require_token,
+            max_query_cost: settings.max_query_cost,
+            rate_limiter: settings.rate_limiter.clone(),
+            read_only: settings.read_only,
+        }
+    }
+}
+
+/// The `/api` routes served with `app_state`, behind its authorization.
+fn api_router(app_state: AppState) -> Router {
+    Router::new()
+        .route("/api/indexes/{index_name}/search", get(search_index))
+        .route("/api/indexes/{index_name}/search.js", get(search_widget))
+        .route("/api/indexes/{index_name}/symbols", get(search_symbols))
+        .route("/api/search", get(search_across_indexes))
+        // Only the search routes are limited, since they are what occupies the searchers
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            rate_limit,
+        ))
+        .route("/api/indexes", get(list_indexes).post(create_index))
+        .route("/api/indexes/bulk", post(create_indexes_bulk))
+        .route(
+            "/api/indexes/{index_name}",
+            get(get_index_details).delete(delete_index),
+        )
+        .route("/api/indexes/{index_name}/suggest", get(suggest))
+        .route("/api/indexes/{index_name}/tree", get(get_index_tree))
+        .route("/api/indexes/{index_name}/stats", get(get_index_stats))
+        .route("/api/indexes/{index_name}/highlight", get(highlight_file))
+        .route("/api/indexes/{index_name}/reindex", post(reindex_index))
+        .route("/api/indexes/{index_name}/update", post(update_index))
+        .route("/api/history", get(search_history))
+        .route("/api/jobs", get(list_jobs))
+        .route("/api/jobs/{job_id}", get(get_job))
+        .route_layer(middleware::from_fn_with_state(app_state.clone(), authorize))
+        .with_state(app_state)
+}
+
+/// The tenants served under `/api/tenants/{tenant}`: the homes in `tenants` of the server's
+/// home, each with its own indexes, jobs, search history and tokens. A tenant's API is opened
+/// on its first request; its tokens grant nothing elsewhere, and OIDC tokens are only accepted
+/// by the home.
+struct Tenants {
+    root: PathBuf,
+    settings: ApiSettings,
+    opened: Mutex<HashMap<String, Tenant>>,
+}
+
+struct Tenant {
+    router: Router,
+    analytics: Arc<SearchAnalytics>,
+}
+
+impl Tenants {
+    fn new(root: PathBuf, settings: ApiSettings) -> Self {
+        Tenants {
+            root,
+            settings,
+            opened: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The API of `tenant`, opened when it is first asked for. Tenants are not created through
+    /// the API: their directory must exist, e.g. from `BEETLE_TENANT=<tenant> beetle new`.
+    fn router(&self, tenant: &str) -> Result<Router, (StatusCode, String)> {
+        validate_tenant_name(tenant).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        if let Some(opened) = self.opened().get(tenant) {
+            return Ok(opened.router.clone());
+        }
+
+        let home = self.root.join(tenant);
+        if !home.is_dir() {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("Tenant '{tenant}' not found"),
+            ));
+        }
+        let catalog = open_catalog(home.clone(), &self.settings)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        let mut auth_backends: Vec<Box<dyn AuthBackend>> = Vec::new();
+        if let Some(admin_token) = &self.settings.admin_token {
+            auth_backends.push(Box::new(AdminToken(admin_token.clone())));
+        }
+        auth_backends.push(Box::new(TokenStore::new(home.clone())));
+        let app_state = AppState::new(home, catalog, auth_backends, &self.settings);
+        let analytics = app_state.analytics.clone();
+        let router = api_router(app_state);
+
+        // Opened without holding the lock, which would stall the requests of every tenant;
+        // concurrent first requests may open the tenant twice and the first one opened is kept
+        let tenant = self
+            .opened()
+            .entry(tenant.to_string())
+            .or_insert(Tenant { router, analytics })
+            .router
+            .clone();
+        Ok(tenant)
+    }
+
+    fn opened(&self) -> std::sync::MutexGuard<'_, HashMap<String, Tenant>> {
+        self.opened.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Writes the search counts of the opened tenants, when the server stops.
+    fn flush(&self) {
+        for tenant in self.opened().values() {
+            if let Err(e) = tenant.analytics.flush() {
+                eprintln!("Warning: {e}");
+            }
+        }
+    }
+}
+
+/// The `/api` routes of the home, with the API of every tenant below `/api/tenants/{tenant}/`.
+fn home_router(app_state: AppState, tenants: Arc<Tenants>) -> Router {
+    api_router(app_state)
+        // Outside the auth layer of the home, since tenants check their own tokens
+        .route(
+            "/api/tenants/{tenant}/{*path}",
+            any(tenant_api).with_state(tenants),
+        )
+}
+
+/// Serves `/api/tenants/{tenant}/{path}` as the tenant's own API serves `/api/{path}`.
+async fn tenant_api(
+    State(tenants): State<Arc<Tenants>>,
+    Path((tenant, _)): Path<(String, String)>,
+    request: Request,
+) -> Response {
+    let router = match tenants.router(&tenant) {
+        Ok(router) => router,
+        Err((status, e)) => return error_response(status, &e),
+    };
+
+    // The path as it was sent, since the extracted one is percent-decoded
+    let path = request
+        .uri()
+        .path()
+        .strip_prefix("/api/tenants/")
+        .and_then(|path| path.split_once('/'))
+        .map(|(_, path)| path)
+        .unwrap_or_default();
+    let uri = match request.uri().query() {
+        Some(query) => format!("/api/{path}?{query}"),
+        None => format!("/api/{path}"),
+    };
+    let uri = match uri.parse() {
+        Ok(uri) => uri,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &format!("Invalid path: {e}")),
+    };
+
+    // A new request rather than the received one, whose extensions hold the path parameters of
+    // this route and would be seen by the tenant's handlers
+    let (parts, body) = request.into_parts();
+    let mut forwarded = Request::new(body);
+    *forwarded.method_mut() = parts.method;
+    *forwarded.uri_mut() = uri;
+    *forwarded.version_mut() = parts.version;
+    *forwarded.headers_mut() = parts.headers;
+    if let Some(&connect_info) = parts.extensions.get::<ConnectInfo<SocketAddr>>() {
+        forwarded.extensions_mut().insert(connect_info);
+    }
+
+    match router.oneshot(forwarded).await {
+        Ok(response) => response,
+        Err(infallible) => match infallible {},
+    }
+}
+
+/// The span of a request, which the events logged while serving it belong to, e.g. `request{
+/// method=GET path=/api/indexes/docs/search tenant=team-a index=docs}`.
+fn request_span(request: &Request) -> Span {
+    let path = request.uri().path();
+    let (tenant, api_path) = match path
+        .strip_prefix("/api/tenants/")
+        .and_then(|path| path.split_once('/'))
+    {
+        Some((tenant, path)) => (Some(tenant), path),
+        None => (None, path.strip_prefix("/api/").unwrap_or_default()),
+    };
+    let index = api_path
+        .strip_prefix("indexes/")
+        .map(|path| path.split('/').next().unwrap_or_default())
+        .filter(|index_name| !index_name.is_empty() && *index_name != "bulk");
+
+    let span = tracing::info_span!(
+        "request",
+        method = %request.method(),
+        path,
+        tenant = tracing::field::Empty,
+        index = tracing::field::Empty,
+    );
+    if let Some(tenant) = tenant {
+        span.record("tenant", tenant);
+    }
+    if let Some(index_name) = index {
+        span.record("index", index_name);
+    }
+
+    span
+}
+
+/// Logs a request once answered, with its status and latency; server errors as warnings.
+fn log_response(response: &Response, latency: Duration, _span: &Span) {
+    let status = response.status().as_u16();
+    let latency_ms = latency.as_micros() as f64 / 1000.0;
+    if response.status().is_server_error() {
+        tracing::warn!(status, latency_ms, "Request failed");
+    } else {
+        tracing::info!(status, latency_ms, "Request served");
+    }
+}
+
 pub struct HttpServer;
 
 impl HttpServer {
-    pub fn start(port: u16) -> CommandOutput {
+    pub fn start(options: ServerOptions) -> CommandOutput {
+        let port = options.port;
         let runtime = tokio::runtime::Runtime::new().unwrap();
 
+        let cors = match cors_layer(&options.cors_origins, &options.cors_methods) {
+            Ok(cors) => cors,
+            Err(e) => return CommandOutput::Error(e),
+        };
+
+        let beetle_home_path = beetle_home().path;
+        let settings = ApiSettings {
+            require_token: options.require_token
+                || options.admin_token.is_some()
+                || options.oidc.is_some(),
+            admin_token: options.admin_token,
+            max_query_cost: options.max_query_cost,
+            min_update_interval: options.min_update_interval,
+            rate_limiter: options
+                .rate_limit
+                .map(|limit| Arc::new(RateLimiter::new(limit))),
+            read_only: options.read_only,
+            no_history: options.no_history,
+            writer_resources: options.writer_resources,
+            max_index_jobs: options.max_index_jobs,
+            reader_threads: options.reader_threads,
+        };
+        let catalog = match open_catalog(beetle_home_path.clone(), &settings) {
+            Ok(catalog) => catalog,
+            Err(e) => return CommandOutput::Error(e),
+        };
+
         runtime.block_on(async move {
-            // Create shared catalog once
-            let beetle_home_path = PathBuf::from(get_beetle_home());
-            let storage = FsStorage::new(beetle_home_path);
-            let catalog = IndexCatalog::new(storage);
-            let app_state = AppState {
-                catalog: Arc::new(catalog),
+            let mut auth_backends: Vec<Box<dyn AuthBackend>> = Vec::new();
+            if let Some(admin_token) = &settings.admin_token {
+                auth_backends.push(Box::new(AdminToken(admin_token.clone())));
+            }
+            auth_backends.push(Box::new(TokenStore::new(beetle_home_path.clone())));
+            if let Some(oidc) = options.oidc {
+                let backend = OidcBackend::new(oidc);
+                // Not fatal: the keys are fetched again when the first token arrives
+                if let Err(e) = backend.refresh_keys().await {
+                    eprintln!("Warning: {e}");
+                }
+                auth_backends.push(Box::new(backend));
+            }
+
+            let app_state =
+                AppState::new(beetle_home_path.clone(), catalog, auth_backends, &settings);
+            let analytics = app_state.analytics.clone();
+            if let Some(interval) = options.auto_update {
+                spawn_auto_update(
+                    app_state.catalog.clone(),
+                    interval,
+                    options.auto_update_indexes,
+                );
+            }
+            let tenants = Arc::new(Tenants::new(
+                beetle_home_path.join(FsStorage::TENANTS_DIR_NAME),
+                settings,
+            ));
+
+            let app = home_router(app_state, tenants.clone())
+                // Added after the auth layer so clients can fetch the spec without a token
+                .merge(SwaggerUi::new("/api/docs").url("/api/openapi.json", ApiDoc::openapi()))
+                .fallback(serve_static_file);
+            let app = match cors {
+                Some(cors) => app.layer(cors),
+                None => app,
             };
+            let app = app.layer(compression_layer()).layer(
+                TraceLayer::new_for_http()
+                    .make_span_with(request_span)
+                    .on_request(())
+                    .on_response(log_response)
+                    .on_failure(()),
+            );
 
-            let app = Router::new()
-                .route("/api/indexes", get(list_indexes).post(create_index))
-                .route(
-                    "/api/indexes/{index_name}",
-                    get(get_index_details).delete(delete_index),
-                )
-                .route("/api/indexes/{index_name}/search", get(search_index))
-                .route("/api/indexes/{index_name}/reindex", post(reindex_index))
-                .route("/api/indexes/{index_name}/update", post(update_index))
-                .fallback(serve_static_file)
-                .with_state(app_state);
-
-            let address = format!("{}:{}", "localhost", port);
+            let address = format!("{}:{}", options.host, port);
             let listener = match tokio::net::TcpListener::bind(&address).await {
                 Ok(listener) => listener,
                 Err(e) => {
                     return CommandOutput::Error(format!("Failed to bind to {address}: {e}"));
                 }
             };
+            let stop = Arc::new(Notify::new());
+            let daemon = options.daemon.then(|| Daemon::new(beetle_home().path));
+            if let Some(daemon) = &daemon {
+                if let Err(e) = daemon.listen(DaemonInfo::current(port), stop.clone()) {
+                    return CommandOutput::Error(e);
+                }
+            }
             println!("Server running on http://{address}");
 
-            let result = axum::serve(listener, app)
-                .with_graceful_shutdown(Self::shutdown_signal())
-                .await;
+            let result = axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(Self::shutdown_signal(stop))
+            .await;
+            if let Some(daemon) = &daemon {
+                daemon.remove_files();
+            }
+            if let Err(e) = analytics.flush() {
+                eprintln!("Warning: {e}");
+            }
+            tenants.flush();
             match result {
                 Ok(_) => CommandOutput::Success("Server stopped gracefully".to_string()),
                 Err(e) => CommandOutput::Error(format!("Server error: {e}")),
@@ -386,7 +2383,8 @@ impl HttpServer {
         })
     }
 
-    async fn shutdown_signal() {
+    /// Resolves on Ctrl+C, SIGTERM or a `stop` command on the control socket.
+    async fn shutdown_signal(stop: Arc<Notify>) {
         let ctrl_c = async {
             signal::ctrl_c()
                 .await
@@ -407,8 +2405,375 @@ impl HttpServer {
         tokio::select! {
             _ = ctrl_c => {},
             _ = terminate => {},
+            _ = stop.notified() => {},
         }
 
         println!("Received shutdown signal, stopping server gracefully...");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    const ADMIN_TOKEN: &str = "admin-secret";
+
+    /// Creates the index `code` of a folder holding `file_name` with `content` in `home`.
+    fn create_index(home: &std::path::Path, file_name: &str, content: &str) {
+        let target = home.join("src");
+        std::fs::create_dir_all(&target).unwrap();
+        std::fs::write(target.join(file_name), content).unwrap();
+        let catalog = IndexCatalog::new(FsStorage::new(home.to_path_buf()));
+        catalog
+            .create(
+                "code",
+                &target.to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        catalog.get_writer("code", false).unwrap().index().unwrap();
+    }
+
+    fn settings() -> ApiSettings {
+        ApiSettings {
+            admin_token: Some(ADMIN_TOKEN.to_string()),
+            require_token: true,
+            max_query_cost: u64::MAX,
+            min_update_interval: Duration::ZERO,
+            rate_limiter: None,
+            read_only: false,
+            no_history: true,
+            writer_resources: WriterResources::default(),
+            max_index_jobs: None,
+            reader_threads: None,
+        }
+    }
+
+    /// The API of `home` and its tenants, as `beetle serve` routes it.
+    fn app(home: &std::path::Path, settings: ApiSettings) -> Router {
+        let catalog = open_catalog(home.to_path_buf(), &settings).unwrap();
+        let auth_backends: Vec<Box<dyn AuthBackend>> = vec![
+            Box::new(AdminToken(ADMIN_TOKEN.to_string())),
+            Box::new(TokenStore::new(home.to_path_buf())),
+        ];
+        let app_state = AppState::new(home.to_path_buf(), catalog, auth_backends, &settings);
+        let tenants = Arc::new(Tenants::new(
+            home.join(FsStorage::TENANTS_DIR_NAME),
+            settings,
+        ));
+        home_router(app_state, tenants)
+    }
+
+    async fn send(
+        app: &Router,
+        method: Method,
+        uri: &str,
+        token: &str,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, serde_json::Value) {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {token}"));
+        if body.is_some() {
+            request = request.header(header::CONTENT_TYPE, "application/json");
+        }
+        let body = body.map_or_else(Body::empty, |body| Body::from(body.to_string()));
+        let response = app
+            .clone()
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    async fn get(app: &Router, uri: &str, token: &str) -> (StatusCode, serde_json::Value) {
+        send(app, Method::GET, uri, token, None).await
+    }
+
+    #[tokio::test]
+    async fn test_search_error_statuses() {
+        let home = tempfile::tempdir().unwrap();
+        create_index(home.path(), "lib.rs", "fn kept() {}");
+        let app = app(home.path(), settings());
+        let status = |uri: &'static str| {
+            let app = app.clone();
+            async move { get(&app, uri, ADMIN_TOKEN).await.0 }
+        };
+
+        assert_eq!(
+            status("/api/indexes/code/search?q=kept").await,
+            StatusCode::OK
+        );
+        for uri in [
+            "/api/indexes/code/search?q=nosuchfield:kept",
+            "/api/indexes/code/search?q=kept&in=/elsewhere",
+            "/api/indexes/code/search?q=kept&exclude_path=/elsewhere",
+            "/api/indexes/code/search?q=kept&changed_since=HEAD",
+            "/api/search?indexes=code&q=kept&in=/elsewhere",
+        ] {
+            assert_eq!(status(uri).await, StatusCode::BAD_REQUEST, "{uri}");
+        }
+        for uri in [
+            "/api/indexes/missing/search?q=kept",
+            "/api/indexes/missing/search.js?q=kept",
+            "/api/search?indexes=code,missing&q=kept",
+        ] {
+            assert_eq!(status(uri).await, StatusCode::NOT_FOUND, "{uri}");
+        }
+
+        let cheap = ApiSettings {
+            max_query_cost: 0,
+            ..settings()
+        };
+        let app = super::tests::app(home.path(), cheap);
+        let (status, _) = get(&app, "/api/indexes/code/search?q=kept", ADMIN_TOKEN).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let forced = "/api/indexes/code/search?q=kept&force=true";
+        assert_eq!(get(&app, forced, ADMIN_TOKEN).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_search_by_tag() {
+        let home = tempfile::tempdir().unwrap();
+        create_index(home.path(), "lib.rs", "fn kept() {}");
+        let catalog = IndexCatalog::new(FsStorage::new(home.path().to_path_buf()));
+        let target = home.path().join("src").to_string_lossy().to_string();
+        let tags = ["backend".to_string()];
+        catalog
+            .create("tagged", &target, &IndexingOptions::default(), &tags)
+            .unwrap();
+        catalog
+            .get_writer("tagged", false)
+            .unwrap()
+            .index()
+            .unwrap();
+        let app = app(home.path(), settings());
+
+        let (status, body) = get(&app, "/api/search?q=kept&tags=backend", ADMIN_TOKEN).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_results"], 1);
+        assert_eq!(body["results"][0]["index_name"], "tagged");
+        let (status, body) = get(&app, "/api/search?q=kept&tags=frontend", ADMIN_TOKEN).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .contains("No index is tagged frontend"));
+    }
+
+    #[tokio::test]
+    async fn test_create_indexes_bulk() {
+        let home = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("lib.rs"), "fn bulk() {}").unwrap();
+        std::fs::write(target.path().join("main.rs"), "fn main() {}").unwrap();
+        let path = target.path().to_string_lossy().to_string();
+        let missing = target.path().join("missing").to_string_lossy().to_string();
+        let app = app(home.path(), settings());
+
+        let request = serde_json::json!({
+            "indexes": [
+                {"name": "first", "path": path},
+                {"name": "first", "path": path},
+                {"name": "gone", "path": missing},
+                {"name": "../escape", "path": path},
+                {"name": "Second Index", "path": path, "slug": true},
+            ],
+            "concurrency": 2,
+        });
+        let (status, body) = send(
+            &app,
+            Method::POST,
+            "/api/indexes/bulk",
+            ADMIN_TOKEN,
+            Some(request),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["succeeded"], 2);
+        assert_eq!(body["failed"], 3);
+        // One result per requested index, in request order
+        let results = body["results"].as_array().unwrap();
+        let names: Vec<&str> = results
+            .iter()
+            .map(|result| result["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            ["first", "first", "gone", "../escape", "second-index"]
+        );
+        let succeeded: Vec<bool> = results
+            .iter()
+            .map(|result| result["success"].as_bool().unwrap())
+            .collect();
+        assert_eq!(succeeded, [true, false, false, false, true]);
+        assert_eq!(results[0]["files_indexed"], 2);
+        assert!(results[0]["usage"]["wall_time_ms"].is_u64());
+        assert!(results[1].get("usage").is_none());
+        assert!(results[1]["error"]
+            .as_str()
+            .unwrap()
+            .contains("more than once"));
+        assert!(results[2]["index"].is_null());
+        assert!(results[2]["error"].is_string());
+
+        // Only the indexes that succeeded exist, and are searchable
+        let catalog = IndexCatalog::new(FsStorage::new(home.path().to_path_buf()));
+        let mut index_names: Vec<String> = catalog
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|index| index.index_name)
+            .collect();
+        index_names.sort();
+        assert_eq!(index_names, ["first", "second-index"]);
+        let (status, body) =
+            get(&app, "/api/indexes/second-index/search?q=bulk", ADMIN_TOKEN).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_results"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_routing() {
+        let home = tempfile::tempdir().unwrap();
+        create_index(home.path(), "home.rs", "fn home_only() {}");
+        let tenants = home.path().join(FsStorage::TENANTS_DIR_NAME);
+        create_index(&tenants.join("team-a"), "a.rs", "fn tenant_only() {}");
+        create_index(&tenants.join("team-b"), "b.rs", "fn tenant_only() {}");
+        let (_, tenant_token) = TokenStore::new(tenants.join("team-a"))
+            .create(vec!["code".to_string()])
+            .unwrap();
+        let app = app(home.path(), settings());
+
+        // A tenant's token opens its own indexes only
+        let search = "/api/tenants/team-a/indexes/code/search?q=tenant_only";
+        let (status, body) = get(&app, search, &tenant_token).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_results"], 1);
+        let (status, _) = get(&app, "/api/indexes/code/search?q=home_only", &tenant_token).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let other_tenant = "/api/tenants/team-b/indexes/code/search?q=tenant_only";
+        let (status, _) = get(&app, other_tenant, &tenant_token).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // The admin token opens every tenant
+        let (status, body) = get(&app, other_tenant, ADMIN_TOKEN).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_results"], 1);
+
+        for uri in [
+            "/api/tenants/../indexes",
+            "/api/tenants/%2E%2E/indexes",
+            "/api/tenants/team%2Fa/indexes",
+        ] {
+            let (status, _) = get(&app, uri, ADMIN_TOKEN).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+        }
+        let (status, _) = get(&app, "/api/tenants/team-c/indexes", ADMIN_TOKEN).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // The query reaches the tenant as it was sent, not decoded into other parameters
+        let uri = "/api/tenants/team-a/indexes/code/search?q=tenant_only%26lang%3Dpython";
+        let (status, body) = get(&app, uri, ADMIN_TOKEN).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["query"], "tenant_only&lang=python");
+        let uri = "/api/tenants/team-a/indexes/%63ode/search?q=tenant_only";
+        let (status, body) = get(&app, uri, ADMIN_TOKEN).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["index_name"], "code");
+    }
+
+    #[test]
+    fn test_openapi_documents_every_route() {
+        let openapi = ApiDoc::openapi();
+        let paths: Vec<&str> = openapi.paths.paths.keys().map(String::as_str).collect();
+
+        assert_eq!(
+            paths,
+            vec![
+                "/api/history",
+                "/api/indexes",
+                "/api/indexes/bulk",
+                "/api/indexes/{index_name}",
+                "/api/indexes/{index_name}/highlight",
+                "/api/indexes/{index_name}/reindex",
+                "/api/indexes/{index_name}/search",
+                "/api/indexes/{index_name}/search.js",
+                "/api/indexes/{index_name}/stats",
+                "/api/indexes/{index_name}/suggest",
+                "/api/indexes/{index_name}/symbols",
+                "/api/indexes/{index_name}/tree",
+                "/api/indexes/{index_name}/update",
+                "/api/jobs",
+                "/api/jobs/{job_id}",
+                "/api/search",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_auto_update_skips_missing_target_path() {
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("kept.rs"), "fn kept() {}").unwrap();
+        let catalog = IndexCatalog::new(engine::storage::RamStorage::new());
+        catalog
+            .create(
+                "code",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        catalog.get_writer("code", false).unwrap().index().unwrap();
+
+        drop(target);
+        assert!(!catalog.stats_all().unwrap()[0].stale);
+        auto_update(&catalog, Vec::new());
+        let results = catalog
+            .search("code", "kept", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_compression_layer() {
+        let app = Router::new()
+            .route("/large", axum::routing::get(|| async { "x".repeat(4096) }))
+            .route("/small", axum::routing::get(|| async { "x".repeat(100) }))
+            .route(
+                "/font",
+                axum::routing::get(|| async {
+                    ([(header::CONTENT_TYPE, "font/woff2")], "x".repeat(4096))
+                }),
+            )
+            .layer(compression_layer());
+        let encoding = |uri: &'static str, accept: &'static str| {
+            let app = app.clone();
+            async move {
+                let request = Request::builder()
+                    .uri(uri)
+                    .header(header::ACCEPT_ENCODING, accept)
+                    .body(Body::empty())
+                    .unwrap();
+                let response = app.oneshot(request).await.unwrap();
+                response
+                    .headers()
+                    .get(header::CONTENT_ENCODING)
+                    .map(|value| value.to_str().unwrap().to_string())
+            }
+        };
+
+        assert_eq!(encoding("/large", "gzip").await.as_deref(), Some("gzip"));
+        assert_eq!(encoding("/large", "gzip, br").await.as_deref(), Some("br"));
+        assert_eq!(encoding("/large", "identity").await, None);
+        assert_eq!(encoding("/small", "gzip, br").await, None);
+        assert_eq!(encoding("/font", "gzip, br").await, None);
+    }
+}
@@ -0,0 +1,37 @@
+use serde::Serialize;
+
+const SEARCH_WIDGET_TEMPLATE: &str = include_str!("widget/search.js");
+
+/// Renders the self-contained script served by `GET /api/indexes/{name}/search.js`.
+///
+/// `data` is inlined as a JS object literal; it is escaped so that indexed content can neither
+/// close the surrounding `<script>` element nor terminate the literal.
+pub fn render_search_widget<T: Serialize>(data: &T) -> Result<String, String> {
+    let json =
+        serde_json::to_string(data).map_err(|e| format!("Failed to serialize widget data: {e}"))?;
+    let literal = json
+        .replace('<', "\\u003c")
+        .replace('\u{2028}', "\\u2028")
+        .replace('\u{2029}', "\\u2029");
+
+    Ok(SEARCH_WIDGET_TEMPLATE.replace("__BEETLE_DATA__", &literal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_search_widget_escapes_data() {
+        let script = render_search_widget(&serde_json::json!({
+            "query": "</script><script>alert(1)</script>",
+            "snippet": "line\u{2028}separator"
+        }))
+        .unwrap();
+
+        assert!(!script.contains("</script>"));
+        assert!(!script.contains('\u{2028}'));
+        assert!(script.contains(r"\u003c/script>"));
+        assert!(!script.contains("__BEETLE_DATA__"));
+    }
+}
@@ -0,0 +1,48 @@
+(function () {
+  var data = __BEETLE_DATA__;
+  var script = document.currentScript;
+  var container = data.target ? document.getElementById(data.target) : null;
+
+  if (!container) {
+    container = document.createElement("div");
+    if (script && script.parentNode) {
+      script.parentNode.insertBefore(container, script.nextSibling);
+    } else {
+      document.body.appendChild(container);
+    }
+  }
+
+  container.className = (container.className ? container.className + " " : "") + "beetle-search";
+  container.innerHTML = "";
+
+  if (data.error) {
+    var error = document.createElement("p");
+    error.className = "beetle-error";
+    error.textContent = data.error;
+    container.appendChild(error);
+    return;
+  }
+
+  var summary = document.createElement("p");
+  summary.className = "beetle-summary";
+  summary.textContent = data.total_results + " results for “" + data.query + "” in " + data.index_name;
+  container.appendChild(summary);
+
+  data.results.forEach(function (result) {
+    var item = document.createElement("div");
+    item.className = "beetle-result";
+
+    var path = document.createElement("div");
+    path.className = "beetle-path";
+    path.textContent = result.path;
+    item.appendChild(path);
+
+    // Snippets are HTML-escaped by the server; only the highlight tags are markup.
+    var snippet = document.createElement("pre");
+    snippet.className = "beetle-snippet";
+    snippet.innerHTML = result.snippet;
+    item.appendChild(snippet);
+
+    container.appendChild(item);
+  });
+})();
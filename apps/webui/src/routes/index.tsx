@@ -16,6 +16,40 @@ interface SearchResult {
   score: number
   extension: string
   snippet: string
+  size: number
+  last_modified: number
+}
+
+function formatSize(bytes: number): string {
+  if (bytes < 1024) {
+    return `${bytes} B`
+  }
+  const units = ['KB', 'MB', 'GB', 'TB']
+  let size = bytes / 1024
+  let unit = 0
+  while (size >= 1024 && unit < units.length - 1) {
+    size /= 1024
+    unit += 1
+  }
+  return `${size.toFixed(1)} ${units[unit]}`
+}
+
+function formatAge(timestampSecs: number): string {
+  const elapsed = Math.max(0, Math.floor(Date.now() / 1000) - timestampSecs)
+  const steps: [number, string][] = [
+    [31536000, 'year'],
+    [2592000, 'month'],
+    [86400, 'day'],
+    [3600, 'hour'],
+    [60, 'minute'],
+  ]
+  for (const [seconds, unit] of steps) {
+    if (elapsed >= seconds) {
+      const amount = Math.floor(elapsed / seconds)
+      return `${amount} ${unit}${amount === 1 ? '' : 's'} ago`
+    }
+  }
+  return 'just now'
 }
 
 interface SearchResponse {
@@ -60,7 +94,7 @@ function IndexPage() {
 
     setLoading(true)
     try {
-      const response = await fetch(`/api/indexes/${selectedIndex}/search?q=${encodeURIComponent(query)}`)
+      const response = await fetch(`/api/indexes/${selectedIndex}/search?q=${encodeURIComponent(query)}&highlight_tag=mark&highlight_class=beetle-match`)
       if (!response.ok) throw new Error('Search failed')
       const data = await response.json()
       setSearchResults(data)
@@ -221,6 +255,9 @@ function IndexPage() {
                           <span className="inline-flex items-center px-2 py-1 rounded-full text-xs font-medium bg-blue-100 text-blue-800">
                             {Math.round(result.score * 100)}% match
                           </span>
+                          <span className="text-xs text-slate-500">
+                            modified {formatAge(result.last_modified)}, {formatSize(result.size)}
+                          </span>
                         </div>
                         <div className="bg-slate-50 border border-slate-200 rounded-md p-3 overflow-x-auto">
                           <pre
@@ -0,0 +1,93 @@
+//! Compares buffered and streaming encoding of the file index snapshot.
+//!
+//! Run with `cargo bench -p engine --bench snapshot`. Before the timings it prints the size of
+//! each snapshot next to the size the uncompressed version 1 format would take; the `alloc`
+//! columns show the memory each approach allocates.
+
+use engine::change::{self, FileIndexMetadata};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+#[global_allocator]
+static ALLOC: divan::AllocProfiler = divan::AllocProfiler::system();
+
+const FILE_COUNTS: &[usize] = &[10_000, 100_000];
+
+fn main() {
+    for &count in FILE_COUNTS {
+        let records = records(count);
+        let uncompressed = records
+            .iter()
+            .fold(20, |acc, record| acc + 18 + record.path.len());
+        let compressed = change::encode(&records).unwrap().len();
+        println!(
+            "{count} files: {uncompressed} bytes uncompressed, {compressed} bytes compressed ({:.1}x)",
+            uncompressed as f64 / compressed as f64
+        );
+    }
+
+    divan::main();
+}
+
+/// Paths shaped like a large monorepo checkout.
+fn records(count: usize) -> Vec<FileIndexMetadata> {
+    (0..count)
+        .map(|i| FileIndexMetadata {
+            path: format!(
+                "/home/dev/monorepo/services/service_{}/src/module_{}/file_{i}.rs",
+                i % 50,
+                i % 400
+            ),
+            size: 1024 + (i as u64 * 37) % 65536,
+            modified_time: 1_700_000_000 + i as u64,
+            content_hash: Some(change::content_hash(&i.to_le_bytes())),
+        })
+        .collect()
+}
+
+fn snapshot_path(count: usize) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "beetle-snapshot-bench-{}-{count}.bin",
+        std::process::id()
+    ))
+}
+
+#[divan::bench(args = FILE_COUNTS)]
+fn write_buffered(bencher: divan::Bencher, count: usize) {
+    let records = records(count);
+    let path = snapshot_path(count);
+
+    bencher.bench(|| fs::write(&path, change::encode(&records).unwrap()).unwrap());
+    let _ = fs::remove_file(path);
+}
+
+#[divan::bench(args = FILE_COUNTS)]
+fn write_streaming(bencher: divan::Bencher, count: usize) {
+    let records = records(count);
+    let path = snapshot_path(count);
+
+    bencher.bench(|| {
+        let file = BufWriter::new(File::create(&path).unwrap());
+        change::write_snapshot(file, &records).unwrap();
+    });
+    let _ = fs::remove_file(path);
+}
+
+#[divan::bench(args = FILE_COUNTS)]
+fn read_buffered(bencher: divan::Bencher, count: usize) {
+    let path = snapshot_path(count);
+    fs::write(&path, change::encode(&records(count)).unwrap()).unwrap();
+
+    bencher.bench(|| change::decode(&fs::read(&path).unwrap()).unwrap());
+    let _ = fs::remove_file(path);
+}
+
+#[divan::bench(args = FILE_COUNTS)]
+fn read_streaming(bencher: divan::Bencher, count: usize) {
+    let path = snapshot_path(count);
+    fs::write(&path, change::encode(&records(count)).unwrap()).unwrap();
+
+    bencher.bench(|| change::read_snapshot(File::open(&path).unwrap()).unwrap());
+    let _ = fs::remove_file(path);
+}
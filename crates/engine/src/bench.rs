@@ -0,0 +1,173 @@
+use crate::change::scan;
+use crate::options::IndexingOptions;
+use crate::search::SearchOptions;
+use crate::storage::RamStorage;
+use crate::IndexCatalog;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const BENCH_INDEX_NAME: &str = "bench";
+/// Queries of the default workload, taken from the names of the indexed files.
+const DEFAULT_QUERIES: usize = 10;
+
+/// Indexing throughput and search latencies measured by [`run_benchmark`].
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct BenchReport {
+    pub files: usize,
+    /// Bytes of the indexed files, up to the maximum file size.
+    pub bytes: u64,
+    /// Documents in the index, more than the files when large files are split in chunks.
+    pub docs: u64,
+    /// Time spent indexing the files and committing them.
+    pub index_time_ms: u64,
+    pub mb_per_sec: f64,
+    pub docs_per_sec: f64,
+    pub queries: Vec<String>,
+    /// Searches run: every query, as many times as requested.
+    pub searches: usize,
+    pub latency: SearchLatency,
+}
+
+/// Percentiles of the time searches took, in microseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct SearchLatency {
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+impl SearchLatency {
+    /// Nearest-rank percentiles of `durations`, which are sorted in place.
+    fn of(durations: &mut [Duration]) -> Self {
+        durations.sort_unstable();
+        let percentile = |percent: usize| {
+            let Some(rank) = (durations.len() * percent).div_ceil(100).checked_sub(1) else {
+                return 0;
+            };
+            durations[rank].as_micros() as u64
+        };
+
+        SearchLatency {
+            p50_us: percentile(50),
+            p90_us: percentile(90),
+            p99_us: percentile(99),
+            max_us: percentile(100),
+        }
+    }
+}
+
+/// Indexes `target_path` with `options` into a throwaway in-memory index, then runs each of
+/// `queries` `iterations` times against it. Without queries, the names of a few of the indexed
+/// files are searched for.
+pub fn run_benchmark(
+    target_path: &Path,
+    options: &IndexingOptions,
+    queries: &[String],
+    iterations: usize,
+) -> Result<BenchReport, String> {
+    if iterations == 0 {
+        return Err("Benchmarks must run every query at least once".to_string());
+    }
+    if !target_path.is_dir() {
+        return Err(format!(
+            "Path is not a directory: {}",
+            target_path.display()
+        ));
+    }
+    let target_path = target_path.to_string_lossy();
+
+    let mut files = scan(&target_path, options);
+    let bytes = files
+        .iter()
+        .map(|file| {
+            options
+                .max_file_size
+                .map_or(file.size, |max| file.size.min(max))
+        })
+        .sum();
+    let queries = if queries.is_empty() {
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let step = files.len().div_ceil(DEFAULT_QUERIES).max(1);
+        files
+            .iter()
+            .step_by(step)
+            .filter_map(|file| Path::new(&file.path).file_stem()?.to_str())
+            .map(str::to_string)
+            .collect()
+    } else {
+        queries.to_vec()
+    };
+
+    let catalog = IndexCatalog::new(RamStorage::new()).with_searcher_cache();
+    catalog.create(BENCH_INDEX_NAME, &target_path, options, &[])?;
+    let index_start = Instant::now();
+    let record = catalog.get_writer(BENCH_INDEX_NAME, false)?.index()?;
+    let index_time = index_start.elapsed();
+    let docs = catalog.stats(BENCH_INDEX_NAME)?.docs;
+
+    let search_options = SearchOptions::default();
+    let mut durations = Vec::with_capacity(queries.len() * iterations);
+    for _ in 0..iterations {
+        for query in &queries {
+            let search_start = Instant::now();
+            catalog.search(BENCH_INDEX_NAME, query, &search_options)?;
+            durations.push(search_start.elapsed());
+        }
+    }
+
+    let seconds = index_time.as_secs_f64().max(f64::EPSILON);
+    Ok(BenchReport {
+        files: record.files_added,
+        bytes,
+        docs,
+        index_time_ms: index_time.as_millis() as u64,
+        mb_per_sec: bytes as f64 / (1024.0 * 1024.0) / seconds,
+        docs_per_sec: docs as f64 / seconds,
+        searches: durations.len(),
+        latency: SearchLatency::of(&mut durations),
+        queries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_latency() {
+        let mut durations: Vec<_> = (1..=200).rev().map(Duration::from_micros).collect();
+        assert_eq!(
+            SearchLatency::of(&mut durations),
+            SearchLatency {
+                p50_us: 100,
+                p90_us: 180,
+                p99_us: 198,
+                max_us: 200
+            }
+        );
+        assert_eq!(SearchLatency::of(&mut []), SearchLatency::default());
+    }
+
+    #[test]
+    fn test_run_benchmark() {
+        let target = tempfile::tempdir().unwrap();
+        for name in ["alpha", "beta", "gamma"] {
+            let content = format!("fn {name}() {{}}\n");
+            std::fs::write(target.path().join(format!("{name}.rs")), content).unwrap();
+        }
+
+        let report = run_benchmark(target.path(), &IndexingOptions::default(), &[], 2).unwrap();
+        assert_eq!((report.files, report.docs), (3, 3));
+        assert_eq!(report.bytes, 41);
+        assert_eq!(report.queries, ["alpha", "beta", "gamma"]);
+        assert_eq!(report.searches, 6);
+        assert!(report.latency.p50_us <= report.latency.max_us);
+
+        let queries = ["beta".to_string()];
+        let report =
+            run_benchmark(target.path(), &IndexingOptions::default(), &queries, 3).unwrap();
+        assert_eq!(report.searches, 3);
+        assert!(run_benchmark(target.path(), &IndexingOptions::default(), &queries, 0).is_err());
+    }
+}
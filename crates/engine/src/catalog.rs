@@ -1,25 +1,330 @@
-use crate::search::IndexSearcher;
-use crate::storage::{IndexStorage, IndexStorageMetadata};
-use crate::writter::IndexWriter;
+use crate::change::{diff_file_index_metadata, relative_files, scan};
+use crate::doctor::{IndexIssue, IndexProblem, OrphanedEntry};
+use crate::git::{clone_repository, resolve_commit, strip_credentials, tracked_files};
+use crate::migration;
+use crate::options::{
+    ChangeDetection, ContentStorage, IndexingOptions, SchemaProfile, SubmodulePolicy,
+    WriterResources,
+};
+use crate::paths::{is_within, normalize_path};
+use crate::search::{
+    search_directory, IndexSearcher, SearchError, SearchOptions, SearchResultItem, SymbolMatch,
+};
+use crate::snapshot::SnapshotInfo;
+use crate::storage::{
+    segment_files, FsStorage, IndexFork, IndexLock, IndexStats, IndexStorage, IndexStorageMetadata,
+    UpdateRecord,
+};
+use crate::usage::ResourceUsage;
+use crate::verify;
+use crate::writter::{
+    branches_stale, history_stale, is_submodule_index, plan_create, plan_update, IndexWriter,
+    IndexingProgress, JobSlots, UpdatePlan,
+};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tantivy::schema::FieldType;
+use tantivy::{Directory, HasLen, Index};
+
+/// Pseudo-index name that stands for every index in the catalog.
+pub const ALL_INDEXES: &str = "_all";
+
+pub struct ReaderCopy {
+    searcher: IndexSearcher,
+    // Declared after `searcher` so the copy is deleted only once the searcher is gone.
+    _fork: IndexFork,
+}
+
+impl Deref for ReaderCopy {
+    type Target = IndexSearcher;
+
+    fn deref(&self) -> &Self::Target {
+        &self.searcher
+    }
+}
+
+/// Results of [`IndexCatalog::search_within`].
+pub struct ScopedSearch {
+    /// The index covering the searched directory, or `None` if it was searched without one.
+    pub index_name: Option<String>,
+    pub results: Vec<SearchResultItem>,
+}
+
+/// An index with its stats, listed by [`IndexCatalog::stats_all`].
+#[derive(Clone, serde::Serialize)]
+pub struct IndexSummary {
+    #[serde(flatten)]
+    pub metadata: IndexStorageMetadata,
+    /// Number of documents in the last commit.
+    pub docs: u64,
+    /// Size of the index directory on disk, in bytes.
+    pub size_bytes: u64,
+    /// Last time the index was written, in seconds since the Unix epoch.
+    pub updated_at: i64,
+    /// Whether files of the target path were added, modified or removed since the last update.
+    pub stale: bool,
+    /// Time, CPU and memory used by the last update; `None` if the history has no update.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_update_usage: Option<ResourceUsage>,
+}
+
+/// The details of one index, see [`IndexCatalog::info`].
+#[derive(Clone, serde::Serialize)]
+pub struct IndexInfo {
+    #[serde(flatten)]
+    pub metadata: IndexStorageMetadata,
+    pub target_path_exists: bool,
+    #[serde(flatten)]
+    pub stats: IndexStats,
+    /// Files recorded by the last update, which the next one is diffed against.
+    pub snapshot_files: usize,
+    /// The most recent point-in-time copy of the index, see [`crate::snapshot`]; `None` when
+    /// there is none or the storage does not keep them.
+    pub latest_snapshot: Option<SnapshotInfo>,
+    pub fields: Vec<SchemaField>,
+    /// The segments of the last commit, largest first.
+    pub segments: Vec<SegmentInfo>,
+    /// What the next update would change; `None` when the target path is gone.
+    pub pending: Option<PendingChanges>,
+}
+
+/// A field of an index's schema.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SchemaField {
+    pub name: String,
+    /// The type of its values, e.g. `str`, `u64` or `date`.
+    #[serde(rename = "type")]
+    pub value_type: String,
+    /// The tokenizer of text fields searched by words, e.g. `code_identifiers`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokenizer: Option<String>,
+    pub stored: bool,
+}
+
+/// A segment of an index's last commit.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SegmentInfo {
+    pub id: String,
+    /// Documents of the segment that are not deleted.
+    pub docs: u32,
+    /// Deleted documents, whose space is reclaimed when the segment is merged.
+    pub deleted_docs: u32,
+    pub size_bytes: u64,
+}
+
+/// Counts of the files the next update of an index would change, see [`UpdatePlan`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PendingChanges {
+    pub added: usize,
+    pub modified: usize,
+    pub removed: usize,
+    pub renamed: usize,
+    pub skipped: usize,
+}
+
+impl From<&UpdatePlan> for PendingChanges {
+    fn from(plan: &UpdatePlan) -> Self {
+        PendingChanges {
+            added: plan.added.len(),
+            modified: plan.modified.len(),
+            removed: plan.removed.len(),
+            renamed: plan.renamed.len(),
+            skipped: plan.skipped.len(),
+        }
+    }
+}
 
 pub struct IndexCatalog {
     storage: Box<dyn IndexStorage>,
+    /// Overrides the writer resources of every index for the writers opened by this catalog.
+    writer_resources: WriterResources,
+    /// Searchers kept open between searches, see [`IndexCatalog::with_searcher_cache`]; `None`
+    /// opens the index again for every search.
+    searchers: Option<RwLock<HashMap<String, IndexSearcher>>>,
+    /// Index jobs allowed to run at once, see [`IndexCatalog::with_max_index_jobs`]; unlimited
+    /// when unset.
+    index_jobs: Option<Arc<JobSlots>>,
+    /// Pool every writer of the catalog reads files on, see [`IndexCatalog::with_reader_threads`].
+    reader_pool: Option<Arc<rayon::ThreadPool>>,
 }
 
 impl IndexCatalog {
     pub fn new<T: IndexStorage + 'static>(storage: T) -> Self {
         IndexCatalog {
             storage: Box::new(storage),
+            writer_resources: WriterResources::default(),
+            searchers: None,
+            index_jobs: None,
+            reader_pool: None,
         }
     }
 
-    pub fn create(&self, index_name: &str, target_path: &str) -> Result<(), String> {
-        self.storage.create(index_name, target_path)?;
+    /// Keeps the searcher of every searched index open for later searches, for long-running
+    /// processes such as `beetle serve`. Cached searchers reload when a commit to their index is
+    /// detected, and are dropped when this catalog removes, rebuilds or restores the index.
+    pub fn with_searcher_cache(mut self) -> Self {
+        self.searchers = Some(RwLock::new(HashMap::new()));
+        self
+    }
+
+    pub fn with_writer_resources(mut self, writer_resources: WriterResources) -> Self {
+        self.writer_resources = writer_resources;
+        self
+    }
+
+    /// Lets at most `max_jobs` writers and rebuilds of this catalog run at once, for processes
+    /// such as `beetle serve` that write to several indexes concurrently. Further writers wait
+    /// for one of them to be dropped once they hold their index's lock.
+    pub fn with_max_index_jobs(mut self, max_jobs: usize) -> Result<Self, String> {
+        if max_jobs == 0 {
+            return Err("At least 1 index job must be allowed to run".to_string());
+        }
+        self.index_jobs = Some(Arc::new(JobSlots::new(max_jobs)));
+        Ok(self)
+    }
+
+    /// Reads and parses the files of every writer of this catalog on a shared pool of `threads`
+    /// threads, instead of rayon's global pool of one thread per core.
+    pub fn with_reader_threads(mut self, threads: usize) -> Result<Self, String> {
+        if threads == 0 {
+            return Err("At least 1 reader thread is needed".to_string());
+        }
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|thread| format!("beetle-reader-{thread}"))
+            .build()
+            .map_err(|e| format!("Failed to start the reader threads: {e}"))?;
+        self.reader_pool = Some(Arc::new(pool));
+        Ok(self)
+    }
+
+    pub fn create(
+        &self,
+        index_name: &str,
+        target_path: &str,
+        options: &IndexingOptions,
+        tags: &[String],
+    ) -> Result<(), String> {
+        validate_index_name(index_name)?;
+        if let Some(tag) = tags.iter().find(|tag| !is_valid_tag(tag)) {
+            return Err(format!(
+                "Invalid tag '{tag}': tags may only contain alphanumerics, '-', '_' and '.'"
+            ));
+        }
+        let options = validate_options(target_path, options)?;
+        let commits_index = options.commits.then(|| commits_index_name(index_name));
+        if let Some(commits_index) = &commits_index {
+            validate_index_name(commits_index)?;
+        }
+
+        let mut tags = tags.to_vec();
+        tags.sort();
+        tags.dedup();
+        self.storage
+            .create(index_name, target_path, &options, &tags)?;
+        // A searcher of an index of the same name removed by another process is stale
+        self.forget_searcher(index_name);
+
+        // Untagged, so that searches by tag leave commits out
+        if let Some(commits_index) = commits_index {
+            let commits_options = IndexingOptions::default()
+                .with_profile(SchemaProfile::Commits)
+                .with_encryption(options.encrypted);
+            if let Err(e) = self
+                .storage
+                .create(&commits_index, target_path, &commits_options, &[])
+            {
+                let _ = self.storage.remove(index_name);
+                return Err(e);
+            }
+            self.forget_searcher(&commits_index);
+        }
 
         Ok(())
     }
 
-    pub fn get_writer(&self, index_name: &str) -> Result<IndexWriter, String> {
+    /// Clones the repository at `url` into the storage's checkout directory of `index_name` and
+    /// creates the index of the clone, recording `url` without its credentials as its remote.
+    /// The clone is removed if the index cannot be created, and with the index otherwise.
+    pub fn create_from_git(
+        &self,
+        index_name: &str,
+        url: &str,
+        options: &IndexingOptions,
+        tags: &[String],
+    ) -> Result<(), String> {
+        validate_index_name(index_name)?;
+        if self.storage.get_metadata(index_name).is_ok() {
+            return Err(format!("Index '{index_name}' already exists"));
+        }
+        let checkout = self
+            .storage
+            .checkout_dir(index_name)
+            .ok_or_else(|| "This storage cannot hold clones of repositories".to_string())?;
+        if checkout.exists() {
+            return Err(format!(
+                "Cannot clone {url}: {} already exists",
+                checkout.display()
+            ));
+        }
+
+        clone_repository(url, &checkout)?;
+        let options = options.clone().with_remote(Some(strip_credentials(url)));
+        let created = self.create(index_name, &checkout.to_string_lossy(), &options, tags);
+        if created.is_err() {
+            let _ = std::fs::remove_dir_all(&checkout);
+        }
+        created
+    }
+
+    /// What creating an index of `target_path` with `options` and updating it would index,
+    /// without creating it. See [`IndexCatalog::plan_update`].
+    pub fn plan_create(
+        &self,
+        target_path: &str,
+        options: &IndexingOptions,
+    ) -> Result<UpdatePlan, String> {
+        let options = validate_options(target_path, options)?;
+        let target_path = normalize_path(Path::new(target_path));
+        if !Path::new(&target_path).exists() {
+            return Err(format!("Target path '{target_path}' does not exist"));
+        }
+
+        plan_create(&target_path, &options)
+    }
+
+    /// What the next update of `index_name` would add, modify, remove, move and skip, without
+    /// writing to the index or waiting for its lock.
+    pub fn plan_update(
+        &self,
+        index_name: &str,
+        change_detection: ChangeDetection,
+    ) -> Result<UpdatePlan, String> {
+        let metadata = self.storage.get_metadata(index_name)?;
+        plan_update(self.storage.as_ref(), &metadata, change_detection)
+    }
+
+    /// Takes the write lock of `index_name`, once the name is valid and the index exists, so that
+    /// no lock file is left behind for indexes that do not.
+    fn lock(&self, index_name: &str, wait: bool) -> Result<IndexLock, String> {
+        validate_index_name(index_name)?;
+        self.storage.get_metadata(index_name)?;
+        self.storage.lock(index_name, wait)
+    }
+
+    /// Opens a writer on `index_name`, holding its write lock until the writer is dropped. When
+    /// another writer holds the lock this fails, or blocks until it is released if `wait` is set.
+    pub fn get_writer(&self, index_name: &str, wait: bool) -> Result<IndexWriter<'_>, String> {
+        let lock = self.lock(index_name, wait)?;
+        self.storage.migrate(index_name)?;
+        // Taken before the writer allocates its memory budget
+        let permit = self.index_jobs.as_ref().map(|slots| slots.acquire());
         let metadata = self
             .storage
             .get_metadata(index_name)
@@ -30,23 +335,263 @@ impl IndexCatalog {
             .open(index_name)
             .map_err(|e| format!("Failed to open index {index_name}: {e}"))?;
 
-        let writer = IndexWriter::new(self.storage.as_ref(), metadata, index)
-            .map_err(|e| format!("Failed to create index writer for index {index_name}: {e}"))?;
+        let writer = IndexWriter::new(
+            self.storage.as_ref(),
+            metadata,
+            index,
+            lock,
+            self.writer_resources,
+        )
+        .map_err(|e| format!("Failed to create index writer for index {index_name}: {e}"))?
+        .with_reader_pool(self.reader_pool.clone())
+        .with_job_permit(permit);
 
         Ok(writer)
     }
 
+    /// Returns a searcher over `index_name` that ranks results with the index's boosts, the
+    /// cached one if the catalog has a searcher cache.
     pub fn get_searcher(&self, index_name: &str) -> Result<IndexSearcher, String> {
+        let Some(searchers) = &self.searchers else {
+            return self.open_searcher(index_name);
+        };
+        if let Some(searcher) = searchers.read().unwrap().get(index_name) {
+            return Ok(searcher.clone());
+        }
+
+        // Concurrent first searches may open the index twice; the last one opened is kept
+        let searcher = self.open_searcher(index_name)?;
+        searchers
+            .write()
+            .unwrap()
+            .insert(index_name.to_string(), searcher.clone());
+        Ok(searcher)
+    }
+
+    fn open_searcher(&self, index_name: &str) -> Result<IndexSearcher, String> {
         let index = self
             .storage
             .open(index_name)
             .map_err(|e| format!("Failed to open index {index_name}: {e}"))?;
+        let metadata = self
+            .storage
+            .get_metadata(index_name)
+            .map_err(|e| format!("Failed to get metadata for index {index_name}: {e}"))?;
+
+        Ok(IndexSearcher::new(index)?
+            .with_boosts(metadata.options.boosts)
+            .with_root(&metadata.target_path))
+    }
+
+    /// Returns a searcher over a hard-linked copy of the last commit of `index_name`.
+    ///
+    /// The copy is frozen: later commits to the live index are not visible to it, and it does
+    /// not keep the live index's files alive. It is deleted when the returned value is dropped.
+    pub fn fork_reader_copy(&self, index_name: &str) -> Result<ReaderCopy, String> {
+        let metadata = self
+            .storage
+            .get_metadata(index_name)
+            .map_err(|e| format!("Failed to get metadata for index {index_name}: {e}"))?;
+        let fork = self
+            .storage
+            .fork(index_name)
+            .map_err(|e| format!("Failed to fork index {index_name}: {e}"))?;
+        let searcher = IndexSearcher::new(fork.index.clone())?
+            .with_boosts(metadata.options.boosts)
+            .with_root(&metadata.target_path);
+
+        Ok(ReaderCopy {
+            searcher,
+            _fork: fork,
+        })
+    }
+
+    /// Searches `index_name`, or every index when it is [`ALL_INDEXES`] (see
+    /// [`IndexCatalog::search_indexes`]).
+    pub fn search(
+        &self,
+        index_name: &str,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResultItem>, SearchError> {
+        if index_name != ALL_INDEXES {
+            return self
+                .searcher_for_search(index_name)?
+                .search_with_options(query, options);
+        }
+
+        self.search_indexes(&self.searchable_indexes()?, query, options)
+    }
+
+    /// Searches `index_names` in parallel and merges the results by score, setting
+    /// [`SearchResultItem::index_name`] on each result.
+    ///
+    /// Scores are computed per index, so the merged order is only roughly comparable.
+    pub fn search_indexes(
+        &self,
+        index_names: &[String],
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResultItem>, SearchError> {
+        let mut index_names = index_names.to_vec();
+        index_names.sort();
+        index_names.dedup();
+
+        let per_index = index_names
+            .par_iter()
+            .map(|index_name| {
+                let results = self
+                    .searcher_for_search(index_name)?
+                    .search_with_options(query, options)?;
+                Ok(results.into_iter().map(|mut result| {
+                    result.index_name = Some(index_name.clone());
+                    result
+                }))
+            })
+            .collect::<Result<Vec<_>, SearchError>>()?;
+
+        let mut results: Vec<SearchResultItem> = per_index.into_iter().flatten().collect();
+        results.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        Ok(results)
+    }
+
+    /// [`IndexCatalog::get_searcher`], telling an index that does not exist from one that cannot
+    /// be opened.
+    fn searcher_for_search(&self, index_name: &str) -> Result<IndexSearcher, SearchError> {
+        self.get_searcher(index_name)
+            .map_err(|e| match self.storage.get_metadata(index_name) {
+                Ok(_) => SearchError::Failed(e),
+                Err(_) => SearchError::IndexNotFound(format!("Index '{index_name}' not found")),
+            })
+    }
+
+    /// Finds the definitions named like `query` in `index_names`, see
+    /// [`IndexSearcher::search_symbols`]. Matches are merged by score and carry their
+    /// [`SymbolMatch::index_name`] when several indexes are searched.
+    pub fn search_symbols(
+        &self,
+        index_names: &[String],
+        query: &str,
+    ) -> Result<Vec<SymbolMatch>, String> {
+        let mut index_names = index_names.to_vec();
+        index_names.sort();
+        index_names.dedup();
+        let several_indexes = index_names.len() > 1;
+
+        let per_index = index_names
+            .par_iter()
+            .map(|index_name| {
+                let matches = self.get_searcher(index_name)?.search_symbols(query)?;
+                Ok(matches.into_iter().map(|mut symbol| {
+                    if several_indexes {
+                        symbol.index_name = Some(index_name.clone());
+                    }
+                    symbol
+                }))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
 
-        IndexSearcher::new(index)
+        let mut matches: Vec<SymbolMatch> = per_index.into_iter().flatten().collect();
+        // Stable, so definitions of a file stay in line order
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        Ok(matches)
     }
 
+    /// Searches the files under `path`.
+    ///
+    /// Uses the index whose target path is the closest ancestor of `path`, keeping only results
+    /// below `path`; when no index covers it, the directory is indexed in memory and searched.
+    /// Either way a relative [`SearchOptions::within`] is relative to `path`.
+    pub fn search_within(
+        &self,
+        path: &Path,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<ScopedSearch, String> {
+        let path = dunce::canonicalize(path)
+            .map(|path| normalize_path(&path))
+            .map_err(|e| format!("Failed to resolve path {}: {e}", path.display()))?;
+
+        let covering_index = self
+            .list()?
+            .into_iter()
+            .filter(|index| is_within(&path, &index.target_path))
+            .max_by_key(|index| PathBuf::from(&index.target_path).components().count());
+
+        match covering_index {
+            Some(index) => {
+                // Restricted in the query, as a search of the directory alone would be, so that
+                // the best results below `path` are not crowded out by those elsewhere
+                let within = match options.within.as_deref() {
+                    Some(within) => normalize_path(&Path::new(&path).join(within)),
+                    None => path,
+                };
+                let options = SearchOptions {
+                    within: Some(within),
+                    ..options.clone()
+                };
+                let results = self.search(&index.index_name, query, &options)?;
+
+                Ok(ScopedSearch {
+                    index_name: Some(index.index_name),
+                    results,
+                })
+            }
+            None => Ok(ScopedSearch {
+                index_name: None,
+                results: search_directory(Path::new(&path), query, options)?,
+            }),
+        }
+    }
+
+    /// Drops the cached searcher of `index_name`, whose index is being replaced.
+    fn forget_searcher(&self, index_name: &str) {
+        if let Some(searchers) = &self.searchers {
+            searchers.write().unwrap().remove(index_name);
+        }
+    }
+
+    /// Removes `index_name`, and its companion commit index if it has one, and its clone if it was
+    /// created with [`IndexCatalog::create_from_git`].
     pub fn remove(&self, index_name: &str) -> Result<(), String> {
-        self.storage.remove(index_name)?;
+        let metadata = self.storage.get_metadata(index_name).ok();
+        let commits = metadata
+            .as_ref()
+            .is_some_and(|metadata| metadata.options.commits);
+        {
+            let _lock = self.lock(index_name, false)?;
+            self.forget_searcher(index_name);
+            self.storage.remove(index_name)?;
+        }
+
+        let checkout = self.storage.checkout_dir(index_name);
+        let cloned = metadata.as_ref().is_some_and(|metadata| {
+            metadata.options.remote.is_some()
+                && checkout
+                    .as_deref()
+                    .is_some_and(|checkout| Path::new(&metadata.target_path) == checkout)
+        });
+        if let Some(checkout) = checkout.filter(|_| cloned) {
+            std::fs::remove_dir_all(&checkout)
+                .map_err(|e| format!("Failed to remove the clone {}: {e}", checkout.display()))?;
+        }
+
+        // Unless it was removed on its own
+        let commits_index = commits_index_name(index_name);
+        if commits && self.storage.get_metadata(&commits_index).is_ok() {
+            self.remove(&commits_index)?;
+        }
+        let separate =
+            metadata.filter(|metadata| metadata.options.submodules == SubmodulePolicy::Separate);
+        if let Some(metadata) = separate {
+            for index in self.list()? {
+                if is_submodule_index(&metadata, &index) {
+                    self.remove(&index.index_name)?;
+                }
+            }
+        }
 
         Ok(())
     }
@@ -55,13 +600,1107 @@ impl IndexCatalog {
         self.storage.list()
     }
 
+    /// Returns the names of the indexes carrying every one of `tags`.
+    pub fn indexes_tagged(&self, tags: &[String]) -> Result<Vec<String>, String> {
+        let index_names = self
+            .list()?
+            .into_iter()
+            .filter(|index| index.has_tags(tags))
+            .map(|index| index.index_name)
+            .collect();
+
+        Ok(index_names)
+    }
+
+    /// Returns the names of the indexes a search of [`ALL_INDEXES`] covers: every index but the
+    /// companion commit indexes, which are searched for commits only.
+    pub fn searchable_indexes(&self) -> Result<Vec<String>, String> {
+        let index_names = self
+            .list()?
+            .into_iter()
+            .filter(|index| index.options.profile != SchemaProfile::Commits)
+            .map(|index| index.index_name)
+            .collect();
+
+        Ok(index_names)
+    }
+
+    /// Returns the stats of `index_name`, or the totals over every index for [`ALL_INDEXES`].
+    pub fn stats(&self, index_name: &str) -> Result<IndexStats, String> {
+        if index_name != ALL_INDEXES {
+            return self.storage.stats(index_name);
+        }
+
+        let mut total = IndexStats::default();
+        for index in self.list()? {
+            let stats = self.storage.stats(&index.index_name)?;
+            total.docs += stats.docs;
+            total.size_bytes += stats.size_bytes;
+            total.updated_at = total.updated_at.max(stats.updated_at);
+        }
+
+        Ok(total)
+    }
+
+    /// Every index with its stats, in the order of [`IndexCatalog::list`].
+    ///
+    /// An index written in an older format is listed as stale without stats until it is
+    /// migrated. Telling whether an index is stale scans its target path and compares the files with its
+    /// last update by size and modification time, as
+    /// [`crate::options::ChangeDetection::Fast`] does; indexes are scanned in parallel.
+    pub fn stats_all(&self) -> Result<Vec<IndexSummary>, String> {
+        self.list()?
+            .into_par_iter()
+            .map(|metadata| {
+                // An index to migrate cannot be opened; its next update migrates it
+                if migration::needs_migration(&metadata) {
+                    return Ok(IndexSummary {
+                        metadata,
+                        docs: 0,
+                        size_bytes: 0,
+                        updated_at: 0,
+                        stale: true,
+                        last_update_usage: None,
+                    });
+                }
+                let stats = self.storage.stats(&metadata.index_name)?;
+                let stale = self.is_stale(&metadata)?;
+                Ok(IndexSummary {
+                    metadata,
+                    docs: stats.docs,
+                    size_bytes: stats.size_bytes,
+                    updated_at: stats.updated_at,
+                    stale,
+                    last_update_usage: stats.last_update.map(|update| update.usage),
+                })
+            })
+            .collect()
+    }
+
+    /// An index whose target path is gone is not stale: updating it would only empty it.
+    fn is_stale(&self, metadata: &IndexStorageMetadata) -> Result<bool, String> {
+        if !Path::new(&metadata.target_path).exists() {
+            return Ok(false);
+        }
+        if metadata.options.profile == SchemaProfile::Commits {
+            return history_stale(self.storage.as_ref(), metadata);
+        }
+        if !metadata.options.branches.is_empty() {
+            return branches_stale(self.storage.as_ref(), metadata);
+        }
+        let snapshot = self
+            .storage
+            .read_file_index_metadata(&metadata.index_name)?;
+        let delta =
+            diff_file_index_metadata(&snapshot, &scan(&metadata.target_path, &metadata.options));
+
+        Ok(!(delta.added.is_empty() && delta.modified.is_empty() && delta.removed.is_empty()))
+    }
+
+    /// The details of `index_name`: its metadata, stats, schema and segments, and the changes its
+    /// next update would make, told by scanning and diffing its target path without writing.
+    pub fn info(&self, index_name: &str) -> Result<IndexInfo, String> {
+        let metadata = self.storage.get_metadata(index_name)?;
+        let index = self.storage.open(index_name)?;
+        let target_path_exists = Path::new(&metadata.target_path).exists();
+        let pending = match target_path_exists {
+            true => Some(PendingChanges::from(&plan_update(
+                self.storage.as_ref(),
+                &metadata,
+                ChangeDetection::default(),
+            )?)),
+            false => None,
+        };
+        // Storages without snapshots have no latest one
+        let latest_snapshot = self
+            .storage
+            .list_snapshots(Some(index_name))
+            .ok()
+            .and_then(|mut snapshots| snapshots.pop());
+
+        Ok(IndexInfo {
+            stats: self.storage.stats(index_name)?,
+            snapshot_files: self.storage.read_file_index_metadata(index_name)?.len(),
+            latest_snapshot,
+            fields: schema_fields(&index),
+            segments: segments(&index)?,
+            pending,
+            target_path_exists,
+            metadata,
+        })
+    }
+
     pub fn get_matadata(&self, index_name: &str) -> Result<IndexStorageMetadata, String> {
         self.storage.get_metadata(index_name)
     }
 
-    pub fn reset(&self, index_name: &str) -> Result<(), String> {
-        self.storage.reset(index_name)?;
+    /// Rebuilds `index_name` from scratch next to the live index, which stays searchable until
+    /// the rebuilt one replaces it. See [`IndexCatalog::get_writer`] for `wait`.
+    pub fn reindex(
+        &self,
+        index_name: &str,
+        wait: bool,
+        on_progress: &dyn Fn(IndexingProgress),
+    ) -> Result<UpdateRecord, String> {
+        let _lock = self.lock(index_name, wait)?;
+        self.storage.migrate(index_name)?;
+        let _permit = self.index_jobs.as_ref().map(|slots| slots.acquire());
+        let mut record = None;
+        self.storage.rebuild(index_name, &mut |rebuild| {
+            let metadata = rebuild.get_metadata(index_name)?;
+            let index = rebuild.open(index_name)?;
+            // The live index's lock is held for the whole rebuild
+            let mut writer = IndexWriter::new(
+                rebuild,
+                metadata,
+                index,
+                IndexLock::default(),
+                self.writer_resources,
+            )?
+            .with_reader_pool(self.reader_pool.clone());
+            record = Some(writer.index_with_progress(on_progress)?);
+            Ok(())
+        })?;
 
-        Ok(())
+        self.forget_searcher(index_name);
+        record.ok_or_else(|| format!("Index {index_name} was not rebuilt"))
+    }
+
+    pub fn doctor(&self, fix: bool) -> Result<Vec<IndexIssue>, String> {
+        self.storage.diagnose(fix)
+    }
+
+    /// Finds the directories and files of the catalog that hold no usable index, and removes
+    /// them when `remove` is set. See [`IndexStorage::clean`].
+    pub fn clean(&self, remove: bool) -> Result<Vec<OrphanedEntry>, String> {
+        self.storage.clean(remove)
+    }
+
+    /// Removes the `entries` found by [`IndexCatalog::clean`], see
+    /// [`IndexStorage::remove_orphans`].
+    pub fn remove_orphans(
+        &self,
+        entries: Vec<OrphanedEntry>,
+    ) -> Result<Vec<OrphanedEntry>, String> {
+        self.storage.remove_orphans(entries)
+    }
+
+    /// Checks the segment files of `index_name` against their checksums and its documents
+    /// against its file snapshot. With `repair`, an index that does not open is rebuilt, and
+    /// otherwise its damaged segments are dropped, its snapshot is rebuilt from the documents
+    /// left and the files that lost theirs are indexed again; repaired issues are marked fixed.
+    pub fn verify(&self, index_name: &str, repair: bool) -> Result<Vec<IndexIssue>, String> {
+        let metadata = self.storage.get_metadata(index_name)?;
+        let lock = self.lock(index_name, false)?;
+        let mut issues = verify::verify(self.storage.as_ref(), &metadata)?;
+        let has_problem =
+            |matches: fn(&IndexProblem) -> bool| issues.iter().any(|issue| matches(&issue.problem));
+        // Updating an index whose target path is gone would remove all of its documents
+        if !repair
+            || !has_problem(IndexProblem::is_recoverable)
+            || has_problem(|problem| matches!(problem, IndexProblem::MissingTargetPath(_)))
+        {
+            return Ok(issues);
+        }
+
+        if has_problem(|problem| matches!(problem, IndexProblem::UnopenableIndex(_))) {
+            drop(lock);
+            self.reindex(index_name, false, &|_| {})?;
+        } else {
+            let permit = self.index_jobs.as_ref().map(|slots| slots.acquire());
+            verify::repair(self.storage.as_ref(), &metadata)?;
+            let index = self.storage.open(index_name)?;
+            IndexWriter::new(
+                self.storage.as_ref(),
+                metadata,
+                index,
+                lock,
+                self.writer_resources,
+            )?
+            .with_reader_pool(self.reader_pool.clone())
+            .with_job_permit(permit)
+            .index()?;
+            self.forget_searcher(index_name);
+        }
+
+        for issue in issues.iter_mut() {
+            issue.fixed = issue.problem.is_recoverable();
+        }
+        Ok(issues)
+    }
+
+    pub fn create_snapshot(&self, index_name: &str) -> Result<SnapshotInfo, String> {
+        self.storage.create_snapshot(index_name)
+    }
+
+    pub fn list_snapshots(&self, index_name: Option<&str>) -> Result<Vec<SnapshotInfo>, String> {
+        self.storage.list_snapshots(index_name)
+    }
+
+    /// Rolls `index_name` back to its snapshot `snapshot_id`.
+    pub fn restore_snapshot(
+        &self,
+        index_name: &str,
+        snapshot_id: &str,
+    ) -> Result<SnapshotInfo, String> {
+        // The index may have been removed since the snapshot
+        validate_index_name(index_name)?;
+        let _lock = self.storage.lock(index_name, false)?;
+        let snapshot = self.storage.restore_snapshot(index_name, snapshot_id);
+        self.forget_searcher(index_name);
+        snapshot
+    }
+}
+
+/// Longest index name, in characters.
+const MAX_INDEX_NAME_LENGTH: usize = 64;
+
+/// Names Windows reserves for devices, with any extension and in any case.
+const WINDOWS_DEVICE_NAMES: [&str; 22] = [
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Checks that `index_name` can name the directory of an index on every platform: alphanumerics,
+/// `-`, `_` and `.`, not starting or ending with `.`, and none of the names reserved by beetle,
+/// its storage or Windows.
+fn schema_fields(index: &Index) -> Vec<SchemaField> {
+    index
+        .schema()
+        .fields()
+        .map(|(_, entry)| SchemaField {
+            name: entry.name().to_string(),
+            value_type: entry.field_type().value_type().name().to_lowercase(),
+            tokenizer: match entry.field_type() {
+                FieldType::Str(options) => options
+                    .get_indexing_options()
+                    .map(|indexing| indexing.tokenizer().to_string()),
+                _ => None,
+            },
+            stored: entry.is_stored(),
+        })
+        .collect()
+}
+
+fn segments(index: &Index) -> Result<Vec<SegmentInfo>, String> {
+    let metas = index
+        .searchable_segment_metas()
+        .map_err(|e| format!("Failed to read the segments of the index: {e}"))?;
+    let directory = index.directory();
+    let mut segments: Vec<SegmentInfo> = metas
+        .iter()
+        .map(|segment| SegmentInfo {
+            id: segment.id().uuid_string(),
+            docs: segment.num_docs(),
+            deleted_docs: segment.num_deleted_docs(),
+            size_bytes: segment_files(segment)
+                .filter_map(|path| directory.open_read(&path).ok())
+                .map(|file| file.len() as u64)
+                .sum(),
+        })
+        .collect();
+    segments.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes).then(a.id.cmp(&b.id)));
+
+    Ok(segments)
+}
+
+/// The name of the companion commit index of `index_name`, see [`IndexingOptions::commits`].
+pub fn commits_index_name(index_name: &str) -> String {
+    format!("{index_name}.commits")
+}
+
+/// The name of the index of the submodule at `submodule`, relative to the target path of
+/// `index_name`, see [`SubmodulePolicy::Separate`]: e.g. `app.libs-parser` for `libs/parser`.
+pub fn submodule_index_name(index_name: &str, submodule: &str) -> String {
+    format!("{index_name}.{}", slugify_index_name(submodule))
+}
+
+pub fn validate_index_name(index_name: &str) -> Result<(), String> {
+    let invalid = |reason: &str| Err(format!("Invalid index name '{index_name}': {reason}"));
+
+    if index_name.is_empty() {
+        return Err("Index names cannot be empty".to_string());
+    }
+    if index_name.chars().count() > MAX_INDEX_NAME_LENGTH {
+        return invalid(&format!(
+            "index names are at most {MAX_INDEX_NAME_LENGTH} characters long"
+        ));
+    }
+    if !index_name
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return invalid("index names may only contain alphanumerics, '-', '_' and '.'");
+    }
+    if index_name.starts_with('.') || index_name.ends_with('.') {
+        return invalid("index names cannot start or end with '.'");
+    }
+    if [
+        ALL_INDEXES,
+        FsStorage::SNAPSHOTS_DIR_NAME,
+        FsStorage::TENANTS_DIR_NAME,
+        FsStorage::CHECKOUTS_DIR_NAME,
+    ]
+    .contains(&index_name)
+    {
+        return invalid("the name is reserved");
+    }
+    if index_name
+        .rsplit_once('.')
+        .is_some_and(|(_, extension)| extension == FsStorage::LOCK_FILE_EXTENSION)
+    {
+        return invalid(&format!(
+            "index names cannot end with '.{}'",
+            FsStorage::LOCK_FILE_EXTENSION
+        ));
+    }
+    let stem = index_name.split('.').next().unwrap_or_default();
+    if WINDOWS_DEVICE_NAMES.contains(&stem.to_lowercase().as_str()) {
+        return invalid("the name is reserved for a device on Windows");
+    }
+
+    Ok(())
+}
+
+/// Turns `name` into a valid index name: lowercased, with runs of other characters than
+/// alphanumerics and `_` replaced by a `-`, cut to the longest name allowed, and with `-index`
+/// appended to reserved names. Slugs are their own slugs.
+pub fn slugify_index_name(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() || c == '_' {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug: String = slug.chars().take(MAX_INDEX_NAME_LENGTH).collect();
+    let slug = slug.trim_end_matches('-');
+    if validate_index_name(slug).is_ok() {
+        return slug.to_string();
+    }
+
+    const SUFFIX: &str = "-index";
+    let base: String = slug
+        .chars()
+        .take(MAX_INDEX_NAME_LENGTH - SUFFIX.len())
+        .collect();
+    match base.trim_end_matches('-') {
+        "" => "index".to_string(),
+        base => format!("{base}{SUFFIX}"),
+    }
+}
+
+fn is_valid_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// `options` as an index of `target_path` keeps them, once checked.
+fn validate_options(
+    target_path: &str,
+    options: &IndexingOptions,
+) -> Result<IndexingOptions, String> {
+    if options.max_file_size == Some(0) {
+        return Err("The maximum file size must be at least 1 byte".to_string());
+    }
+    options.writer.resolve()?;
+    options.tokenizer.validate()?;
+    options.boosts.validate()?;
+    options.merge_policy.validate()?;
+    options
+        .analyzer
+        .validate(options.profile, options.tokenizer)?;
+
+    if options.tracked_only {
+        if !options.files.is_empty() {
+            return Err(
+                "Index either the files git tracks or the files of a list, not both".to_string(),
+            );
+        }
+        tracked_files(target_path, false)
+            .map_err(|e| format!("Cannot index the files git tracks in {target_path}: {e}"))?;
+    }
+
+    // The files of other branches are not in the target path, to list or to read at search time
+    if !options.branches.is_empty() {
+        if options.tracked_only || !options.files.is_empty() {
+            return Err(
+                "Index either the files of branches or those of the target path, not both"
+                    .to_string(),
+            );
+        }
+        if options.dedup {
+            return Err("Indexes of branches do not support deduplication".to_string());
+        }
+        if options.content_storage == ContentStorage::None {
+            return Err("Indexes of branches must store the content of files".to_string());
+        }
+        for branch in &options.branches {
+            resolve_commit(Path::new(target_path), branch)?;
+        }
+    }
+
+    if options.profile == SchemaProfile::Commits
+        && (!options.branches.is_empty()
+            || options.tracked_only
+            || !options.files.is_empty()
+            || options.dedup
+            || options.commits)
+    {
+        return Err(
+            "Indexes of commits index the history of a repository, not its files".to_string(),
+        );
+    }
+    // Only walks find hidden or ignored files
+    if options.tunes_walks()
+        && (options.tracked_only
+            || !options.files.is_empty()
+            || !options.branches.is_empty()
+            || options.profile == SchemaProfile::Commits)
+    {
+        return Err(
+            "Hidden and ignored files can only be indexed by indexes that walk the target path"
+                .to_string(),
+        );
+    }
+
+    // The indexes of submodules are indexes of directories
+    if options.submodules == SubmodulePolicy::Separate
+        && (!options.branches.is_empty() || options.profile == SchemaProfile::Commits)
+    {
+        return Err(
+            "Submodules can only be indexed separately by indexes of the target path".to_string(),
+        );
+    }
+    if options.profile == SchemaProfile::Commits || options.commits {
+        resolve_commit(Path::new(target_path), "HEAD")
+            .map_err(|_| format!("Cannot index the commits of {target_path}: it has none"))?;
+    }
+
+    let mut options = options.clone();
+    options.files = relative_files(target_path, &options.files)?;
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::RamStorage;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_validate_index_name() {
+        for name in ["my-index", "api_v2", "docs.en", "résumé"] {
+            assert_eq!(validate_index_name(name), Ok(()), "{name}");
+        }
+        for name in [
+            "",
+            "a/b",
+            "..",
+            "../escape",
+            ".hidden",
+            "trailing.",
+            "with space",
+            "_all",
+            "snapshots",
+            "tenants",
+            "checkouts",
+            "repo.lock",
+            "CON",
+            "nul.txt",
+            "com1",
+            &"x".repeat(MAX_INDEX_NAME_LENGTH + 1),
+        ] {
+            assert!(validate_index_name(name).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_slugify_index_name() {
+        assert_eq!(
+            slugify_index_name("My Project / API v2"),
+            "my-project-api-v2"
+        );
+        assert_eq!(slugify_index_name("../escape"), "escape");
+        assert_eq!(slugify_index_name("repo.lock"), "repo-lock");
+        assert_eq!(slugify_index_name("Con"), "con-index");
+        assert_eq!(slugify_index_name("_all"), "_all-index");
+        assert_eq!(slugify_index_name("//"), "index");
+        let long = slugify_index_name(&"x".repeat(100));
+        assert_eq!(long.len(), MAX_INDEX_NAME_LENGTH);
+        for name in ["My Project / API v2", "Con", "//"] {
+            let slug = slugify_index_name(name);
+            assert_eq!(validate_index_name(&slug), Ok(()));
+            assert_eq!(slugify_index_name(&slug), slug);
+        }
+    }
+
+    #[test]
+    fn test_stats_all() {
+        let home = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let catalog = IndexCatalog::new(FsStorage::new(home.path().to_path_buf()));
+        std::fs::write(target.path().join("main.rs"), "fn main() {}").unwrap();
+        catalog
+            .create(
+                "files",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        catalog.get_writer("files", false).unwrap().index().unwrap();
+
+        let summary = catalog.stats_all().unwrap().remove(0);
+        assert_eq!(summary.metadata.index_name, "files");
+        assert_eq!(summary.docs, 1);
+        assert!(summary.size_bytes > 0);
+        assert!(!summary.stale);
+        assert!(summary.last_update_usage.is_some());
+
+        // A file added since the last update makes the index stale
+        std::fs::write(target.path().join("lib.rs"), "pub fn lib() {}").unwrap();
+        assert!(catalog.stats_all().unwrap()[0].stale);
+    }
+
+    #[test]
+    fn test_plan_update() {
+        let home = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let target_path = normalize_path(target.path());
+        let catalog = IndexCatalog::new(FsStorage::new(home.path().to_path_buf()));
+        let write = |name: &str, content: &str| {
+            std::fs::write(target.path().join(name), content).unwrap();
+        };
+        write("main.rs", "fn main() {}");
+        write("old.rs", "fn moved() {}");
+        write("gone.rs", "fn gone() {}");
+        write("notes.md", "# Notes");
+        let options = IndexingOptions::default().with_only_extensions(["rs"]);
+        let path = |name: &str| format!("{target_path}/{name}");
+
+        // Planning an index creates nothing
+        let plan = catalog.plan_create(&target_path, &options).unwrap();
+        assert_eq!(plan.added, ["gone.rs", "main.rs", "old.rs"].map(path));
+        assert_eq!(plan.skipped[0].path, path("notes.md"));
+        assert_eq!(plan.skipped[0].reason, crate::storage::SkipReason::Ignored);
+        assert!(catalog.list().unwrap().is_empty());
+
+        catalog
+            .create("files", &target_path, &options, &[])
+            .unwrap();
+        catalog.get_writer("files", false).unwrap().index().unwrap();
+        write("main.rs", "fn main() { run() }");
+        std::fs::rename(target.path().join("old.rs"), target.path().join("new.rs")).unwrap();
+        std::fs::remove_file(target.path().join("gone.rs")).unwrap();
+        write("added.rs", "fn added() {}");
+
+        let plan = catalog.plan_update("files", ChangeDetection::Hash).unwrap();
+        assert_eq!(plan.added, vec![path("added.rs")]);
+        assert_eq!(plan.modified, vec![path("main.rs")]);
+        assert_eq!(plan.removed, vec![path("gone.rs")]);
+        assert_eq!(plan.renamed[0].from, path("old.rs"));
+        assert_eq!(plan.renamed[0].to, path("new.rs"));
+        // Nothing was written: the update finds the same changes
+        let record = catalog.get_writer("files", false).unwrap().index().unwrap();
+        assert_eq!(
+            (
+                record.files_added,
+                record.files_modified,
+                record.files_removed
+            ),
+            (1, 1, 1)
+        );
+        assert_eq!(record.files_renamed, 1);
+    }
+
+    #[test]
+    fn test_update_keeps_creation_options() {
+        let home = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let target_path = normalize_path(target.path());
+        let write = |name: &str| {
+            std::fs::write(target.path().join(name), "fn f() {}").unwrap();
+        };
+        write("main.rs");
+        let options = IndexingOptions::default().with_only_extensions(["rs"]);
+        let catalog = IndexCatalog::new(FsStorage::new(home.path().to_path_buf()));
+        catalog
+            .create("files", &target_path, &options, &[])
+            .unwrap();
+        catalog.get_writer("files", false).unwrap().index().unwrap();
+        drop(catalog);
+
+        // A catalog opened later updates with the options stored at creation, and the walk
+        // leaves out hidden and ignored files as the creation did
+        write("lib.rs");
+        write("notes.md");
+        write(".hidden.rs");
+        write("generated.rs");
+        std::fs::write(target.path().join(".ignore"), "generated.rs\n").unwrap();
+        let catalog = IndexCatalog::new(FsStorage::new(home.path().to_path_buf()));
+        let plan = catalog.plan_update("files", ChangeDetection::Hash).unwrap();
+        assert_eq!(plan.added, vec![format!("{target_path}/lib.rs")]);
+        let record = catalog.get_writer("files", false).unwrap().index().unwrap();
+        assert_eq!(record.files_added, 1);
+        assert!(!catalog
+            .is_stale(&catalog.get_matadata("files").unwrap())
+            .unwrap());
+    }
+
+    #[test]
+    fn test_only_writers_migrate_indexes() {
+        let home = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("lib.rs"), "fn legacy() {}").unwrap();
+        let index_dir = home.path().join("legacy");
+        let legacy_index_path = index_dir.join("tantivy_index");
+        std::fs::create_dir_all(&legacy_index_path).unwrap();
+        Index::create_in_dir(
+            &legacy_index_path,
+            crate::schema::CodeIndexSchema::new().schema,
+        )
+        .unwrap();
+        let metadata = serde_json::json!({
+            "index_name": "legacy",
+            "index_path": "/old/legacy",
+            "target_path": target.path().to_string_lossy(),
+        });
+        std::fs::write(index_dir.join("metadata.json"), metadata.to_string()).unwrap();
+        let catalog = IndexCatalog::new(FsStorage::new(home.path().to_path_buf()));
+
+        // Listing, searching and diagnosing leave the index as it is
+        let summaries = catalog.stats_all().unwrap();
+        assert!(migration::needs_migration(&summaries[0].metadata));
+        let error = catalog
+            .search("legacy", "legacy", &SearchOptions::default())
+            .map(|_| ())
+            .unwrap_err();
+        assert!(error.to_string().contains("needs migration"), "{error}");
+        let issues = catalog.doctor(false).unwrap();
+        assert_eq!(issues[0].problem, IndexProblem::OutdatedFormat(0));
+        assert!(!issues[0].fixed);
+        assert!(index_dir.join("metadata.json").exists());
+
+        // An update migrates it first
+        catalog
+            .get_writer("legacy", false)
+            .unwrap()
+            .index()
+            .unwrap();
+        assert!(!index_dir.join("metadata.json").exists());
+        let results = catalog
+            .search("legacy", "legacy", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(catalog.doctor(false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_indexes_of_repositories_remove_their_clone() {
+        let repo = crate::git::tests::repository();
+        let home = tempfile::tempdir().unwrap();
+        let catalog = IndexCatalog::new(FsStorage::new(home.path().to_path_buf()));
+        let url = format!("file://{}", normalize_path(repo.path()));
+        catalog
+            .create_from_git("repo", &url, &IndexingOptions::default(), &[])
+            .unwrap();
+        let checkout = home.path().join(FsStorage::CHECKOUTS_DIR_NAME).join("repo");
+        let metadata = catalog.get_matadata("repo").unwrap();
+        assert_eq!(metadata.target_path, checkout.to_string_lossy());
+        assert_eq!(metadata.options.remote.as_deref(), Some(url.as_str()));
+        assert!(catalog
+            .create_from_git("repo", &url, &IndexingOptions::default(), &[])
+            .is_err());
+
+        catalog.remove("repo").unwrap();
+        assert!(!checkout.exists());
+
+        // Nor is a clone whose index cannot be created kept
+        let tags = ["not a tag".to_string()];
+        assert!(catalog
+            .create_from_git("repo", &url, &IndexingOptions::default(), &tags)
+            .is_err());
+        assert!(!checkout.exists());
+    }
+
+    #[test]
+    fn test_writes_to_unknown_indexes_leave_no_lock() {
+        let home = tempfile::tempdir().unwrap();
+        let catalog = IndexCatalog::new(FsStorage::new(home.path().to_path_buf()));
+        for index_name in ["missing", "../outside", "_all"] {
+            assert!(catalog.get_writer(index_name, false).is_err());
+            assert!(catalog.reindex(index_name, false, &|_| {}).is_err());
+            assert!(catalog.remove(index_name).is_err());
+        }
+        assert_eq!(std::fs::read_dir(home.path()).unwrap().count(), 0);
+        assert!(!home.path().parent().unwrap().join("outside.lock").exists());
+    }
+
+    #[test]
+    fn test_search_indexes() {
+        let catalog = IndexCatalog::new(RamStorage::new());
+        let backend = tempfile::tempdir().unwrap();
+        std::fs::write(
+            backend.path().join("parse.rs"),
+            "fn parse() { parse(parse) }",
+        )
+        .unwrap();
+        let frontend = tempfile::tempdir().unwrap();
+        std::fs::write(
+            frontend.path().join("parse.ts"),
+            "function parse() { render(); layout(); paint(); }",
+        )
+        .unwrap();
+        for (index_name, dir) in [("backend", &backend), ("frontend", &frontend)] {
+            catalog
+                .create(
+                    index_name,
+                    &dir.path().to_string_lossy(),
+                    &IndexingOptions::default(),
+                    &[],
+                )
+                .unwrap();
+            catalog
+                .get_writer(index_name, false)
+                .unwrap()
+                .index()
+                .unwrap();
+        }
+
+        // Each index is searched once, whatever the number of times it is named
+        let index_names = ["frontend", "backend", "frontend"].map(str::to_string);
+        let results = catalog
+            .search_indexes(&index_names, "parse", &SearchOptions::default())
+            .unwrap();
+        let searched: Vec<_> = results
+            .iter()
+            .map(|result| result.index_name.as_deref().unwrap())
+            .collect();
+        // The file of backend mentions parse more often, in fewer words
+        assert_eq!(searched, ["backend", "frontend"]);
+        assert!(results[0].score > results[1].score);
+        assert!(results[0].path.ends_with("parse.rs"));
+        assert!(results[1].path.ends_with("parse.ts"));
+
+        let error = catalog
+            .search_indexes(
+                &["backend".to_string(), "missing".to_string()],
+                "parse",
+                &SearchOptions::default(),
+            )
+            .map(|_| ())
+            .unwrap_err();
+        assert!(matches!(error, SearchError::IndexNotFound(_)), "{error}");
+    }
+
+    #[test]
+    fn test_tags() {
+        assert!(is_valid_tag("rust-1.x_beta"));
+        for tag in ["", "two words", "a/b", "a,b"] {
+            assert!(!is_valid_tag(tag), "{tag}");
+        }
+
+        let home = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let target_path = target.path().to_string_lossy().to_string();
+        let catalog = IndexCatalog::new(FsStorage::new(home.path().to_path_buf()));
+        let create = |index_name: &str, tags: &[&str]| {
+            let tags: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+            catalog.create(index_name, &target_path, &IndexingOptions::default(), &tags)
+        };
+        create("api", &["rust", "backend", "rust"]).unwrap();
+        create("web", &["frontend"]).unwrap();
+        create("core", &["backend"]).unwrap();
+        create("untagged", &[]).unwrap();
+        assert!(create("invalid", &["not a tag"]).is_err());
+
+        // Sorted, without duplicates, in meta.json
+        let meta: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(home.path().join("api").join("meta.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(meta["tags"], serde_json::json!(["backend", "rust"]));
+        let reopened = IndexCatalog::new(FsStorage::new(home.path().to_path_buf()));
+        let metadata = reopened.get_matadata("api").unwrap();
+        assert_eq!(metadata.tags, ["backend", "rust"]);
+        assert!(metadata.has_tags(&[]));
+        assert!(metadata.has_tags(&["rust".to_string(), "backend".to_string()]));
+        assert!(!metadata.has_tags(&["rust".to_string(), "frontend".to_string()]));
+
+        let tagged = |tags: &[&str]| {
+            let tags: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+            let mut index_names = reopened.indexes_tagged(&tags).unwrap();
+            index_names.sort();
+            index_names
+        };
+        assert_eq!(tagged(&["backend"]), ["api", "core"]);
+        assert_eq!(tagged(&["backend", "rust"]), ["api"]);
+        assert!(tagged(&["backend", "frontend"]).is_empty());
+    }
+
+    #[test]
+    fn test_search_within() {
+        let target = tempfile::tempdir().unwrap();
+        for dir in ["sub/inner", "other"] {
+            std::fs::create_dir_all(target.path().join(dir)).unwrap();
+        }
+        // Matches outside the searched directory
+        for name in ["other/a.rs", "other/b.rs", "sub.rs"] {
+            std::fs::write(target.path().join(name), "fn scoped() { scoped() }").unwrap();
+        }
+        std::fs::write(target.path().join("sub/lib.rs"), "fn scoped() { other() }").unwrap();
+        std::fs::write(target.path().join("sub/inner/deep.rs"), "fn scoped() {}").unwrap();
+        let catalog = IndexCatalog::new(RamStorage::new());
+        let target_path = normalize_path(target.path());
+        catalog
+            .create("code", &target_path, &IndexingOptions::default(), &[])
+            .unwrap();
+        catalog.get_writer("code", false).unwrap().index().unwrap();
+
+        let search = |within: Option<&str>| {
+            let options = SearchOptions {
+                within: within.map(str::to_string),
+                ..SearchOptions::default()
+            };
+            let scoped = catalog
+                .search_within(&target.path().join("sub"), "scoped", &options)
+                .unwrap();
+            let mut paths: Vec<String> = scoped.results.into_iter().map(|r| r.path).collect();
+            paths.sort();
+            (scoped.index_name, paths)
+        };
+        let (index_name, paths) = search(None);
+        assert_eq!(index_name.as_deref(), Some("code"));
+        assert_eq!(
+            paths,
+            [
+                format!("{target_path}/sub/inner/deep.rs"),
+                format!("{target_path}/sub/lib.rs")
+            ]
+        );
+        // Relative to the searched directory
+        let (_, paths) = search(Some("inner"));
+        assert_eq!(paths, [format!("{target_path}/sub/inner/deep.rs")]);
+
+        // Without an index covering it, the directory is searched as it is
+        let uncovered = tempfile::tempdir().unwrap();
+        std::fs::write(uncovered.path().join("main.rs"), "fn scoped() {}").unwrap();
+        let scoped = catalog
+            .search_within(uncovered.path(), "scoped", &SearchOptions::default())
+            .unwrap();
+        assert!(scoped.index_name.is_none());
+        assert_eq!(scoped.results.len(), 1);
+    }
+
+    #[test]
+    fn test_fork_reader_copy() {
+        let home = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("a.rs"), "fn forked() {}").unwrap();
+        let catalog = IndexCatalog::new(FsStorage::new(home.path().to_path_buf()));
+        catalog
+            .create(
+                "code",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        catalog.get_writer("code", false).unwrap().index().unwrap();
+
+        let copy = catalog.fork_reader_copy("code").unwrap();
+        let forks = home.path().join("code").join(FsStorage::FORKS_DIR_NAME);
+        assert_eq!(std::fs::read_dir(&forks).unwrap().count(), 1);
+
+        // Later commits, and the removal of the files they replace, leave the copy as it was
+        std::fs::write(target.path().join("a.rs"), "fn changed() {}").unwrap();
+        std::fs::write(target.path().join("b.rs"), "fn forked() {}").unwrap();
+        catalog.get_writer("code", false).unwrap().index().unwrap();
+        catalog.reindex("code", false, &|_| {}).unwrap();
+        let results = copy.search("forked").unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("a.rs"));
+        assert_eq!(copy.symbols().unwrap().len(), 1);
+        assert_eq!(
+            catalog
+                .search("code", "forked", &SearchOptions::default())
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Removed with the copy, or with the index directory the rebuild replaced
+        drop(copy);
+        assert!(std::fs::read_dir(&forks).map_or(true, |mut forks| forks.next().is_none()));
+    }
+
+    #[test]
+    fn test_reindex_keeps_update_history() {
+        let home = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("a.rs"), "fn a() {}").unwrap();
+        let catalog = IndexCatalog::new(FsStorage::new(home.path().to_path_buf()));
+        catalog
+            .create(
+                "code",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        catalog.get_writer("code", false).unwrap().index().unwrap();
+        std::fs::write(target.path().join("b.rs"), "fn b() {}").unwrap();
+
+        let record = catalog.reindex("code", false, &|_| {}).unwrap();
+        assert_eq!(record.files_added, 2);
+        let history = catalog.storage.read_update_history("code").unwrap();
+        assert_eq!(
+            history
+                .iter()
+                .map(|record| record.files_added)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        let last_update = catalog.stats("code").unwrap().last_update.unwrap();
+        assert_eq!(last_update.finished_at, record.finished_at);
+    }
+
+    #[test]
+    fn test_info() {
+        let home = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let catalog = IndexCatalog::new(FsStorage::new(home.path().to_path_buf()));
+        std::fs::write(target.path().join("main.rs"), "fn main() {}").unwrap();
+        let target_path = target.path().to_string_lossy().to_string();
+        let options = IndexingOptions::default();
+        catalog
+            .create("files", &target_path, &options, &[])
+            .unwrap();
+        catalog.get_writer("files", false).unwrap().index().unwrap();
+        std::fs::write(target.path().join("lib.rs"), "pub fn lib() {}").unwrap();
+
+        let info = catalog.info("files").unwrap();
+        assert!(info.target_path_exists);
+        assert_eq!(info.stats.docs, 1);
+        assert_eq!(info.snapshot_files, 1);
+        assert!(info.latest_snapshot.is_none());
+        let content = info.fields.iter().find(|field| field.name == "content");
+        assert_eq!(
+            content.unwrap().tokenizer.as_deref(),
+            Some("code_identifiers")
+        );
+        assert_eq!(info.segments.len(), 1);
+        assert_eq!(info.segments[0].docs, 1);
+        assert!(info.segments[0].size_bytes > 0);
+        assert_eq!(
+            info.pending,
+            Some(PendingChanges {
+                added: 1,
+                ..PendingChanges::default()
+            })
+        );
+
+        // Nothing is pending for a target path that is gone
+        drop(target);
+        let info = catalog.info("files").unwrap();
+        assert!(!info.target_path_exists);
+        assert_eq!(info.pending, None);
+    }
+
+    #[test]
+    fn test_searcher_cache() {
+        let target = tempfile::tempdir().unwrap();
+        let catalog = IndexCatalog::new(RamStorage::new()).with_searcher_cache();
+        let ingest = |line: &str| {
+            let mut writer = catalog.get_writer("docs", false).unwrap();
+            writer.ingest(line.as_bytes(), "").unwrap();
+        };
+        let count = |query: &str| {
+            catalog
+                .search("docs", query, &SearchOptions::default())
+                .unwrap()
+                .len()
+        };
+        let create = || {
+            catalog
+                .create(
+                    "docs",
+                    &target.path().to_string_lossy(),
+                    &IndexingOptions::default(),
+                    &[],
+                )
+                .unwrap()
+        };
+
+        create();
+        ingest(r#"{"path": "a.rs", "content": "fn alpha() {}"}"#);
+        assert_eq!(count("alpha"), 1);
+
+        // The cached searcher picks up later commits
+        ingest(r#"{"path": "b.rs", "content": "fn alpha_beta() {}"}"#);
+        let started = Instant::now();
+        while count("beta") == 0 && started.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(count("beta"), 1);
+
+        // A removed index is not searched through its old searcher
+        catalog.remove("docs").unwrap();
+        assert!(catalog.get_searcher("docs").is_err());
+        create();
+        assert_eq!(count("alpha"), 0);
+    }
+    #[test]
+    fn test_max_index_jobs() {
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("main.rs"), "fn main() {}").unwrap();
+        let catalog = IndexCatalog::new(RamStorage::new())
+            .with_max_index_jobs(1)
+            .unwrap()
+            .with_reader_threads(2)
+            .unwrap();
+        for name in ["first", "second"] {
+            catalog
+                .create(
+                    name,
+                    &target.path().to_string_lossy(),
+                    &IndexingOptions::default(),
+                    &[],
+                )
+                .unwrap();
+        }
+
+        let first = catalog.get_writer("first", false).unwrap();
+        let released = std::sync::Mutex::new(None);
+        std::thread::scope(|scope| {
+            let second = scope.spawn(|| {
+                let mut writer = catalog.get_writer("second", false).unwrap();
+                let acquired = Instant::now();
+                writer.index().unwrap();
+                acquired
+            });
+            std::thread::sleep(Duration::from_millis(100));
+            *released.lock().unwrap() = Some(Instant::now());
+            drop(first);
+
+            // The second writer only opened once the first one was dropped
+            let acquired = second.join().unwrap();
+            assert!(acquired >= released.lock().unwrap().unwrap());
+        });
+        assert_eq!(
+            catalog
+                .search("second", "main", &SearchOptions::default())
+                .unwrap()
+                .len(),
+            1
+        );
+
+        assert!(IndexCatalog::new(RamStorage::new())
+            .with_max_index_jobs(0)
+            .is_err());
     }
 }
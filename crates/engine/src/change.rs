@@ -1,78 +1,190 @@
+use crate::chunks::{ContentChunks, MAX_CHUNK_SIZE};
+use crate::encoding::open_text;
+use crate::git::{submodules, tracked_files};
+use crate::options::{ChangeDetection, IndexingOptions, SubmodulePolicy};
+use crate::paths::{is_within, normalize_path};
+use crate::storage::{SkipReason, SkippedFiles};
 use anyhow::{anyhow, Result};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use crc::Crc;
 use ignore::WalkBuilder;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::{Cursor, Read, Write};
-use std::sync::{Arc, Mutex};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use tracing::warn;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FileIndexMetadata {
     pub path: String,
     pub size: u64,
     pub modified_time: u64,
+    /// [`indexed_content_hash`] of the file when it was indexed; `None` until an update hashes
+    /// it, and for files scanned but not indexed yet.
+    pub content_hash: Option<u64>,
 }
 
 const MAGIC: &[u8; 4] = b"BTLX";
-const VERSION: u32 = 1;
+/// Version 1 stored the entries uncompressed; version 2 stores them in a single zstd frame;
+/// version 3 adds the content hash to the entries.
+const VERSION: u32 = 3;
+const UNHASHED_VERSION: u32 = 2;
+const LEGACY_VERSION: u32 = 1;
 const HEADER_SIZE: usize = 20; // 12 bytes for header + 8 bytes for checksum
+const COMPRESSION_LEVEL: i32 = 3;
 const CRC64_ECMA: Crc<u64> = Crc::<u64>::new(&crc::CRC_64_ECMA_182);
+/// Entries allocated up front when reading a snapshot; the count in its header is not trusted
+/// beyond that, since a damaged header could claim billions.
+const MAX_PREALLOCATED_ENTRIES: u32 = 1 << 16;
 
+/// Encodes `records` into an in-memory snapshot, see [`write_snapshot`].
 pub fn encode(records: &[FileIndexMetadata]) -> Result<Vec<u8>> {
-    let estimated_capacity = records.iter().fold(
-        HEADER_SIZE,
-        |acc, record| acc + 18 + record.path.len(), // Fixed fields (16) + path length (2) + path bytes
-    );
-    let mut writer = Vec::with_capacity(estimated_capacity);
+    let mut bytes = Vec::new();
+    write_snapshot(&mut bytes, records)?;
 
+    Ok(bytes)
+}
+
+/// Streams a snapshot of `records` into `writer` without buffering the encoded entries.
+///
+/// Layout: the 12-byte header (magic, version, entry count), a zstd frame holding the entries,
+/// then a CRC-64 over the header and the uncompressed entries. An entry is the size, the
+/// modification time, a byte telling whether the content hash is known, the hash (0 when it is
+/// not) and the path, prefixed with its length.
+pub fn write_snapshot<W: Write>(mut writer: W, records: &[FileIndexMetadata]) -> Result<()> {
     let mut digest = CRC64_ECMA.digest();
 
-    // Write header
-    writer.write_all(MAGIC)?;
-    writer.write_u32::<BigEndian>(VERSION)?;
-    writer.write_u32::<BigEndian>(records.len() as u32)?;
+    let mut header = Vec::with_capacity(12);
+    header.write_all(MAGIC)?;
+    header.write_u32::<BigEndian>(VERSION)?;
+    header.write_u32::<BigEndian>(records.len() as u32)?;
+    digest.update(&header);
+    writer.write_all(&header)?;
 
-    // Write entries
+    let mut encoder = zstd::stream::write::Encoder::new(&mut writer, COMPRESSION_LEVEL)?;
+    let mut entry = Vec::new();
     for record in records {
-        writer.write_u64::<BigEndian>(record.size)?;
-        writer.write_u64::<BigEndian>(record.modified_time)?;
-
         let path_bytes = record.path.as_bytes();
         if path_bytes.len() > u16::MAX as usize {
             return Err(anyhow!("Path too long: {} bytes", path_bytes.len()));
         }
 
-        writer.write_u16::<BigEndian>(path_bytes.len() as u16)?;
-        writer.write_all(path_bytes)?;
+        entry.clear();
+        entry.write_u64::<BigEndian>(record.size)?;
+        entry.write_u64::<BigEndian>(record.modified_time)?;
+        entry.write_u8(record.content_hash.is_some() as u8)?;
+        entry.write_u64::<BigEndian>(record.content_hash.unwrap_or_default())?;
+        entry.write_u16::<BigEndian>(path_bytes.len() as u16)?;
+        entry.write_all(path_bytes)?;
+
+        digest.update(&entry);
+        encoder.write_all(&entry)?;
     }
+    encoder.finish()?;
 
-    // Calculate incremental checksum
-    digest.update(&writer);
-    let checksum = digest.finalize();
-    writer.write_u64::<BigEndian>(checksum)?;
+    writer.write_u64::<BigEndian>(digest.finalize())?;
+    writer.flush()?;
 
-    Ok(writer)
+    Ok(())
 }
 
+/// Decodes an in-memory snapshot, see [`read_snapshot`].
 pub fn decode(bytes: &[u8]) -> Result<Vec<FileIndexMetadata>> {
     if bytes.len() < HEADER_SIZE {
         return Err(anyhow!("Invalid file: too short"));
     }
 
-    let mut cursor = Cursor::new(bytes);
+    read_snapshot(bytes)
+}
+
+/// Reads a snapshot written by [`write_snapshot`], or by an earlier version of the format.
+pub fn read_snapshot<R: Read>(reader: R) -> Result<Vec<FileIndexMetadata>> {
+    let mut reader = std::io::BufReader::new(reader);
 
-    // Read and verify header
-    let mut magic = [0u8; 4];
-    cursor.read_exact(&mut magic)?;
-    if &magic != MAGIC {
+    let mut header = [0u8; 12];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| anyhow!("Invalid file: too short"))?;
+    if &header[..4] != MAGIC {
         return Err(anyhow!("Invalid magic signature"));
     }
 
-    let version = cursor.read_u32::<BigEndian>()?;
-    if version != VERSION {
-        return Err(anyhow!("Unsupported version: {}", version));
+    let version = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    match version {
+        VERSION | UNHASHED_VERSION => {}
+        LEGACY_VERSION => {
+            let mut bytes = header.to_vec();
+            reader.read_to_end(&mut bytes)?;
+            return decode_legacy(&bytes);
+        }
+        _ => return Err(anyhow!("Unsupported version: {}", version)),
+    }
+
+    let num_entries = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+    let mut digest = CRC64_ECMA.digest();
+    digest.update(&header);
+
+    let mut decoder = zstd::stream::read::Decoder::with_buffer(reader)?.single_frame();
+    let mut records = Vec::with_capacity(num_entries.min(MAX_PREALLOCATED_ENTRIES) as usize);
+    let mut fixed = vec![0u8; if version == VERSION { 27 } else { 18 }];
+    for _ in 0..num_entries {
+        decoder
+            .read_exact(&mut fixed)
+            .map_err(|_| anyhow!("Truncated file: insufficient data for entry"))?;
+        digest.update(&fixed);
+
+        let mut fields = Cursor::new(&fixed);
+        let size = fields.read_u64::<BigEndian>()?;
+        let modified_time = fields.read_u64::<BigEndian>()?;
+        let content_hash = if version == VERSION {
+            let known = fields.read_u8()? != 0;
+            let hash = fields.read_u64::<BigEndian>()?;
+            known.then_some(hash)
+        } else {
+            None
+        };
+        let path_len = fields.read_u16::<BigEndian>()? as usize;
+
+        let mut path_bytes = vec![0u8; path_len];
+        decoder
+            .read_exact(&mut path_bytes)
+            .map_err(|_| anyhow!("Truncated file: insufficient data for path"))?;
+        digest.update(&path_bytes);
+
+        let path =
+            String::from_utf8(path_bytes).map_err(|e| anyhow!("Invalid UTF-8 in path: {}", e))?;
+        records.push(FileIndexMetadata {
+            path,
+            size,
+            modified_time,
+            content_hash,
+        });
+    }
+
+    // Drain the end of the frame so the checksum that follows it can be read.
+    if std::io::copy(&mut decoder, &mut std::io::sink())? != 0 {
+        return Err(anyhow!("Unexpected data after the last entry"));
+    }
+    let stored_checksum = decoder
+        .finish()
+        .read_u64::<BigEndian>()
+        .map_err(|_| anyhow!("Truncated file: missing checksum"))?;
+    if stored_checksum != digest.finalize() {
+        return Err(anyhow!("Checksum mismatch"));
+    }
+
+    Ok(records)
+}
+
+fn decode_legacy(bytes: &[u8]) -> Result<Vec<FileIndexMetadata>> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(anyhow!("Invalid file: too short"));
     }
 
+    let mut cursor = Cursor::new(bytes);
+    cursor.set_position(8);
     let num_entries = cursor.read_u32::<BigEndian>()?;
 
     // Verify checksum first
@@ -87,7 +199,7 @@ pub fn decode(bytes: &[u8]) -> Result<Vec<FileIndexMetadata>> {
     }
 
     // Read entries with optimized string handling
-    let mut records = Vec::with_capacity(num_entries as usize);
+    let mut records = Vec::with_capacity(num_entries.min(MAX_PREALLOCATED_ENTRIES) as usize);
     let remaining_bytes = &bytes[cursor.position() as usize..data_end];
     let mut offset = 0;
 
@@ -138,6 +250,7 @@ pub fn decode(bytes: &[u8]) -> Result<Vec<FileIndexMetadata>> {
             path,
             size,
             modified_time,
+            content_hash: None,
         });
     }
 
@@ -148,94 +261,379 @@ pub struct Delta {
     pub added: Vec<FileIndexMetadata>,
     pub modified: Vec<FileIndexMetadata>,
     pub removed: Vec<FileIndexMetadata>,
+    /// Filled by [`detect_renames`]; always empty straight out of [`diff_file_index_metadata`].
+    pub renamed: Vec<Rename>,
+}
+
+/// A file that disappeared from one path and reappeared with the same content at another.
+pub struct Rename {
+    pub from: FileIndexMetadata,
+    pub to: FileIndexMetadata,
 }
 
+/// How many same-sized added files are compared against each removed file, so a directory full
+/// of equally sized files does not make rename detection quadratic.
+const MAX_RENAME_CANDIDATES: usize = 8;
+
+/// Compares the files of the last update with the ones scanned now by size and modification
+/// time, see [`ChangeDetection::Fast`].
 pub fn diff_file_index_metadata(
     previous: &[FileIndexMetadata],
     current: &[FileIndexMetadata],
 ) -> Delta {
-    let mut added = Vec::new();
-    let mut modified = Vec::new();
-    let mut removed = Vec::new();
-
-    let previous_set: std::collections::HashSet<_> = previous.iter().map(|f| &f.path).collect();
+    detect_changes(
+        previous,
+        &mut current.to_vec(),
+        ChangeDetection::Fast,
+        |_| 0,
+    )
+}
 
-    for file in current {
-        if !previous_set.contains(&file.path) {
-            added.push(file.clone());
-        } else {
-            // Check if the file has been modified
-            if let Some(prev_file) = previous.iter().find(|f| f.path == file.path) {
-                if file.size != prev_file.size || file.modified_time != prev_file.modified_time {
-                    modified.push(file.clone());
-                }
+/// Compares the files of the last update with the ones scanned now, reading the files
+/// `detection` needs to with `hash`, which returns their [`indexed_content_hash`].
+///
+/// Hashes are carried over to unchanged files of `current`, and set on the files that were
+/// hashed, so that the next update can compare them. Files whose hash is not known are
+/// modified when they need one.
+pub fn detect_changes(
+    previous: &[FileIndexMetadata],
+    current: &mut [FileIndexMetadata],
+    detection: ChangeDetection,
+    hash: impl Fn(&FileIndexMetadata) -> u64 + Sync,
+) -> Delta {
+    let previous_by_path: HashMap<&str, &FileIndexMetadata> = previous
+        .iter()
+        .map(|file| (file.path.as_str(), file))
+        .collect();
+
+    let needs_hash = |file: &FileIndexMetadata, prev: &FileIndexMetadata| {
+        prev.content_hash.is_some()
+            && file.size == prev.size
+            && match detection {
+                ChangeDetection::Fast => false,
+                ChangeDetection::Hash => true,
+                ChangeDetection::Auto => file.modified_time != prev.modified_time,
             }
+    };
+    current.par_iter_mut().for_each(|file| {
+        let Some(prev) = previous_by_path.get(file.path.as_str()) else {
+            return;
+        };
+        if needs_hash(file, prev) {
+            file.content_hash = Some(hash(file));
+        } else if file.size == prev.size && file.modified_time == prev.modified_time {
+            file.content_hash = prev.content_hash;
         }
-    }
+    });
 
-    for file in previous {
-        if !current.iter().any(|f| f.path == file.path) {
-            removed.push(file.clone());
+    let mut added = Vec::new();
+    let mut modified = Vec::new();
+    for file in current.iter() {
+        let Some(prev) = previous_by_path.get(file.path.as_str()) else {
+            added.push(file.clone());
+            continue;
+        };
+        let changed = match (detection, prev.content_hash) {
+            _ if file.size != prev.size => true,
+            (ChangeDetection::Fast, _) => file.modified_time != prev.modified_time,
+            (ChangeDetection::Hash, None) => true,
+            (ChangeDetection::Auto, None) => file.modified_time != prev.modified_time,
+            (_, Some(prev_hash)) => file.content_hash != Some(prev_hash),
+        };
+        if changed {
+            modified.push(file.clone());
         }
     }
 
+    let current_paths: HashSet<&str> = current.iter().map(|file| file.path.as_str()).collect();
+    let removed = previous
+        .iter()
+        .filter(|file| !current_paths.contains(file.path.as_str()))
+        .cloned()
+        .collect();
+
     Delta {
         added,
         modified,
         removed,
+        renamed: Vec::new(),
     }
 }
 
-pub fn scan(root_path: &str) -> Vec<FileIndexMetadata> {
-    let results = Arc::new(Mutex::new(Vec::new()));
-    let walker = WalkBuilder::new(root_path).build_parallel();
+/// Moves probable renames out of `delta.removed` and `delta.added` into `delta.renamed`.
+///
+/// A removed and an added file are paired when they have the same size and `same_content`
+/// confirms that the removed file's last indexed content matches the added file.
+pub fn detect_renames(
+    delta: &mut Delta,
+    same_content: impl Fn(&FileIndexMetadata, &FileIndexMetadata) -> bool,
+) {
+    let mut added_by_size: std::collections::HashMap<u64, Vec<usize>> =
+        std::collections::HashMap::new();
+    for (idx, file) in delta.added.iter().enumerate() {
+        added_by_size.entry(file.size).or_default().push(idx);
+    }
+
+    let mut paired = vec![false; delta.added.len()];
+    let mut pairs = Vec::new();
+    let mut removed = Vec::new();
+    for from in std::mem::take(&mut delta.removed) {
+        let candidate = added_by_size.get(&from.size).and_then(|candidates| {
+            candidates
+                .iter()
+                .copied()
+                .filter(|&idx| !paired[idx])
+                .take(MAX_RENAME_CANDIDATES)
+                .find(|&idx| same_content(&from, &delta.added[idx]))
+        });
+
+        match candidate {
+            Some(idx) => {
+                paired[idx] = true;
+                pairs.push((from, idx));
+            }
+            None => removed.push(from),
+        }
+    }
+
+    delta.removed = removed;
+    delta
+        .renamed
+        .extend(pairs.into_iter().map(|(from, idx)| Rename {
+            from,
+            to: delta.added[idx].clone(),
+        }));
+    let mut idx = 0;
+    delta.added.retain(|_| {
+        idx += 1;
+        !paired[idx - 1]
+    });
+}
+
+/// Hash used to decide whether a removed and an added file have the same content.
+pub fn content_hash(content: &[u8]) -> u64 {
+    CRC64_ECMA.checksum(content)
+}
+
+/// A [`content_hash`] computed piece by piece.
+pub(crate) fn content_digest() -> crc::Digest<'static, u64> {
+    CRC64_ECMA.digest()
+}
+
+/// [`content_hash`] of the content of the file at `path` that is indexed: at most the first
+/// `max_size` bytes of its text, see [`crate::encoding`] and [`ContentChunks`]. Files that cannot
+/// be read have no content.
+pub fn indexed_content_hash(path: &str, max_size: Option<u64>, utf8_only: bool) -> u64 {
+    let reader = open_text(path, utf8_only).unwrap_or_else(|_| Box::new(std::io::empty()));
+    let mut digest = content_digest();
+    for (_, content) in
+        ContentChunks::new(reader, max_size, MAX_CHUNK_SIZE).with_utf8_only(utf8_only)
+    {
+        digest.update(content.as_bytes());
+    }
+    digest.finalize()
+}
+
+/// The files of `root_path` to index: those of [`IndexingOptions::files`] when the index lists
+/// its files, or else every file found walking `root_path`.
+pub fn scan(root_path: &str, options: &IndexingOptions) -> Vec<FileIndexMetadata> {
+    scan_with_skipped(root_path, options).0
+}
+
+/// [`scan`], also counting the files of `root_path` that are ignored, too large or cannot be
+/// listed.
+pub fn scan_with_skipped(
+    root_path: &str,
+    options: &IndexingOptions,
+) -> (Vec<FileIndexMetadata>, SkippedFiles) {
+    let skipped = Mutex::new(SkippedFiles::default());
+    let files = scan_files(root_path, options, &|path, reason| {
+        skipped.lock().unwrap().add(path.as_deref(), reason);
+    });
+
+    (files, skipped.into_inner().unwrap())
+}
+
+/// [`scan`], telling `on_skipped` about every file of `root_path` that is ignored, too large or
+/// cannot be listed, by path when it is known.
+pub fn scan_files(
+    root_path: &str,
+    options: &IndexingOptions,
+    on_skipped: &(dyn Fn(Option<String>, SkipReason) + Sync),
+) -> Vec<FileIndexMetadata> {
+    let results = Mutex::new(Vec::new());
+    let skip = |(path, reason): (Option<PathBuf>, SkipReason)| {
+        on_skipped(path.map(|path| path.to_string_lossy().to_string()), reason)
+    };
 
-    walker.run(|| {
-        let results = Arc::clone(&results);
-        Box::new(move |entry| {
-            process_entry(entry, &results);
+    // The directories of the submodules left out, checked out or not. Outside a repository
+    // there are none
+    let left_out: HashSet<PathBuf> = match options.submodules {
+        SubmodulePolicy::Index => HashSet::new(),
+        SubmodulePolicy::Skip | SubmodulePolicy::Separate => submodules(root_path)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|submodule| Path::new(root_path).join(submodule))
+            .collect(),
+    };
+
+    // The listed files of an index that lists them, or the files git tracks, relative to
+    // `root_path`. Listed files that no longer exist count as unreadable; directories and
+    // symbolic links, which walks leave out too, as ignored, like the files of submodules left
+    // out. Should git fail, the path is walked rather than every file of the index removed
+    let tracked;
+    let listed = if options.tracked_only {
+        let recurse_submodules = options.submodules == SubmodulePolicy::Index;
+        tracked = tracked_files(root_path, recurse_submodules)
+            .inspect_err(|e| warn!("Failed to list the files git tracks, walking instead: {e}"))
+            .ok();
+        tracked.as_deref()
+    } else {
+        Some(options.files.as_slice()).filter(|files| !files.is_empty())
+    };
+    if let Some(listed) = listed {
+        listed.par_iter().for_each(|file| {
+            let path = Path::new(root_path).join(file);
+            if left_out.iter().any(|submodule| path.starts_with(submodule)) {
+                skip((Some(path), SkipReason::Ignored));
+            } else if let Err(skipped) = process_file(&path, options, &results) {
+                skip(skipped);
+            }
+        });
+        return results.into_inner().unwrap();
+    }
+
+    let mut walk = WalkBuilder::new(root_path);
+    walk.hidden(!options.hidden)
+        .git_global(!options.no_global_gitignore)
+        .git_exclude(!options.no_git_exclude);
+    let hidden = options.hidden;
+    if hidden || !left_out.is_empty() {
+        // Hidden files are not those of git itself
+        walk.filter_entry(move |entry| {
+            let git_dir = hidden && entry.file_name() == ".git";
+            !git_dir && !left_out.contains(entry.path())
+        });
+    }
+    walk.build_parallel().run(|| {
+        Box::new(|entry| {
+            if let Err(skipped) = process_entry(entry, options, &results) {
+                skip(skipped);
+            }
             ignore::WalkState::Continue
         })
     });
 
-    Arc::try_unwrap(results).unwrap().into_inner().unwrap()
+    results.into_inner().unwrap()
 }
 
+/// `files` relative to `root_path`, in order and without duplicates, as
+/// [`IndexingOptions::files`] keeps them. Relative files are taken relative to `root_path`;
+/// files outside it are refused.
+pub(crate) fn relative_files(root_path: &str, files: &[String]) -> Result<Vec<String>, String> {
+    let root = normalize_path(Path::new(root_path));
+    let mut relative = Vec::with_capacity(files.len());
+    for file in files {
+        let path = Path::new(file);
+        let outside = || format!("Listed file '{file}' is not inside {root}");
+        let file = if path.is_absolute() {
+            let path = normalize_path(path);
+            if !is_within(&path, &root) {
+                return Err(outside());
+            }
+            path[root.len()..].trim_start_matches('/').to_string()
+        } else {
+            let mut parts = Vec::new();
+            for component in path.components() {
+                match component {
+                    Component::Normal(part) => parts.push(part.to_string_lossy()),
+                    Component::CurDir => {}
+                    _ => return Err(outside()),
+                }
+            }
+            parts.join("/")
+        };
+        if file.is_empty() {
+            return Err(outside());
+        }
+        relative.push(file);
+    }
+    relative.sort();
+    relative.dedup();
+
+    Ok(relative)
+}
+
+/// Adds the file of `entry` to `results`, or tells why it is skipped.
 fn process_entry(
     entry: Result<ignore::DirEntry, ignore::Error>,
-    results: &Arc<Mutex<Vec<FileIndexMetadata>>>,
-) {
+    options: &IndexingOptions,
+    results: &Mutex<Vec<FileIndexMetadata>>,
+) -> Result<(), (Option<PathBuf>, SkipReason)> {
     let dir_entry = match entry {
         Ok(entry) => entry,
-        Err(_) => return,
+        Err(e) => return Err((error_path(&e), SkipReason::Unreadable)),
     };
 
     if !dir_entry.file_type().is_some_and(|ft| ft.is_file()) {
-        return;
+        return Ok(());
     }
 
-    let metadata = match fs::metadata(dir_entry.path()) {
-        Ok(metadata) => metadata,
-        Err(_) => return,
-    };
+    process_file(dir_entry.path(), options, results)
+}
 
-    let path_str = match dir_entry.path().to_str() {
-        Some(path) => dunce::canonicalize(path)
-            .unwrap_or_else(|_| dir_entry.path().to_path_buf())
-            .to_string_lossy()
-            .to_string(),
-        None => return,
-    };
+/// Adds the file at `path` to `results`, or tells why it is skipped.
+fn process_file(
+    path: &Path,
+    options: &IndexingOptions,
+    results: &Mutex<Vec<FileIndexMetadata>>,
+) -> Result<(), (Option<PathBuf>, SkipReason)> {
+    let unreadable = || (Some(path.to_path_buf()), SkipReason::Unreadable);
+
+    if !options.includes(path) {
+        return Err((Some(path.to_path_buf()), SkipReason::Ignored));
+    }
+
+    let metadata = fs::symlink_metadata(path).map_err(|_| unreadable())?;
+    if !metadata.is_file() {
+        return Err((Some(path.to_path_buf()), SkipReason::Ignored));
+    }
+
+    if path.to_str().is_none() {
+        return Err(unreadable());
+    }
+    let path_str = normalize_path(path);
 
     let file_metadata = FileIndexMetadata {
         path: path_str.to_string(),
         size: metadata.len(),
         modified_time: get_modified_time(&metadata),
+        content_hash: None,
     };
 
     if let Ok(mut results) = results.lock() {
         results.push(file_metadata);
     }
+
+    // Still indexed, up to the maximum size
+    if options
+        .max_file_size
+        .is_some_and(|max| metadata.len() > max)
+    {
+        return Err((Some(PathBuf::from(path_str)), SkipReason::TooLarge));
+    }
+    Ok(())
+}
+
+/// The path a walk error is about, if it tells.
+fn error_path(error: &ignore::Error) -> Option<PathBuf> {
+    match error {
+        ignore::Error::WithPath { path, .. } => Some(path.clone()),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            error_path(err)
+        }
+        _ => None,
+    }
 }
 
 fn get_modified_time(metadata: &fs::Metadata) -> u64 {
@@ -270,6 +668,7 @@ mod tests {
                 path: "test.txt".to_string(),
                 size: 1024,
                 modified_time: 1622547800,
+                content_hash: None,
             }];
 
             let encoded = encode(&records).unwrap();
@@ -285,16 +684,19 @@ mod tests {
                     path: "test1.txt".to_string(),
                     size: 1024,
                     modified_time: 1622547800,
+                    content_hash: None,
                 },
                 FileIndexMetadata {
                     path: "src/lib.rs".to_string(),
                     size: 2048,
                     modified_time: 1622547900,
+                    content_hash: Some(0x1234_5678_9abc_def0),
                 },
                 FileIndexMetadata {
                     path: "docs/README.md".to_string(),
                     size: 512,
                     modified_time: 1622548000,
+                    content_hash: None,
                 },
             ];
 
@@ -311,16 +713,19 @@ mod tests {
                     path: "测试.txt".to_string(),
                     size: 100,
                     modified_time: 1622547800,
+                    content_hash: None,
                 },
                 FileIndexMetadata {
                     path: "файл.rs".to_string(),
                     size: 200,
                     modified_time: 1622547900,
+                    content_hash: None,
                 },
                 FileIndexMetadata {
                     path: "文档/自述文件.md".to_string(),
                     size: 300,
                     modified_time: 1622548000,
+                    content_hash: None,
                 },
             ];
 
@@ -337,6 +742,7 @@ mod tests {
                 path: long_path.clone(),
                 size: 1024,
                 modified_time: 1622547800,
+                content_hash: None,
             }];
 
             let encoded = encode(&records).unwrap();
@@ -352,6 +758,7 @@ mod tests {
                 path: too_long_path,
                 size: 1024,
                 modified_time: 1622547800,
+                content_hash: None,
             }];
 
             let result = encode(&records);
@@ -363,6 +770,111 @@ mod tests {
             );
         }
 
+        #[test]
+        fn test_decode_legacy_version() {
+            let mut bytes = Vec::new();
+            bytes.extend_from_slice(MAGIC);
+            bytes.extend_from_slice(&LEGACY_VERSION.to_be_bytes());
+            bytes.extend_from_slice(&1u32.to_be_bytes());
+            bytes.extend_from_slice(&1024u64.to_be_bytes());
+            bytes.extend_from_slice(&1622547800u64.to_be_bytes());
+            bytes.extend_from_slice(&8u16.to_be_bytes());
+            bytes.extend_from_slice(b"test.txt");
+            let checksum = CRC64_ECMA.checksum(&bytes);
+            bytes.extend_from_slice(&checksum.to_be_bytes());
+
+            let decoded = decode(&bytes).unwrap();
+
+            assert_eq!(
+                decoded,
+                vec![FileIndexMetadata {
+                    path: "test.txt".to_string(),
+                    size: 1024,
+                    modified_time: 1622547800,
+                    content_hash: None,
+                }]
+            );
+        }
+
+        #[test]
+        fn test_decode_unhashed_version() {
+            let mut bytes = Vec::new();
+            bytes.extend_from_slice(MAGIC);
+            bytes.extend_from_slice(&UNHASHED_VERSION.to_be_bytes());
+            bytes.extend_from_slice(&1u32.to_be_bytes());
+            let mut entries = Vec::new();
+            entries.extend_from_slice(&1024u64.to_be_bytes());
+            entries.extend_from_slice(&1622547800u64.to_be_bytes());
+            entries.extend_from_slice(&8u16.to_be_bytes());
+            entries.extend_from_slice(b"test.txt");
+            let mut digest = CRC64_ECMA.digest();
+            digest.update(&bytes);
+            digest.update(&entries);
+            bytes.extend(zstd::encode_all(&entries[..], COMPRESSION_LEVEL).unwrap());
+            bytes.extend_from_slice(&digest.finalize().to_be_bytes());
+
+            let decoded = decode(&bytes).unwrap();
+
+            assert_eq!(
+                decoded,
+                vec![FileIndexMetadata {
+                    path: "test.txt".to_string(),
+                    size: 1024,
+                    modified_time: 1622547800,
+                    content_hash: None,
+                }]
+            );
+        }
+
+        #[test]
+        fn test_oversized_entry_count() {
+            let mut bytes = Vec::new();
+            bytes.extend_from_slice(MAGIC);
+            bytes.extend_from_slice(&VERSION.to_be_bytes());
+            bytes.extend_from_slice(&u32::MAX.to_be_bytes());
+            bytes.extend(zstd::encode_all(&[][..], COMPRESSION_LEVEL).unwrap());
+            bytes.extend_from_slice(&0u64.to_be_bytes());
+
+            let result = decode(&bytes);
+            assert_eq!(
+                result.unwrap_err().to_string(),
+                "Truncated file: insufficient data for entry"
+            );
+
+            let mut bytes = Vec::new();
+            bytes.extend_from_slice(MAGIC);
+            bytes.extend_from_slice(&LEGACY_VERSION.to_be_bytes());
+            bytes.extend_from_slice(&u32::MAX.to_be_bytes());
+            let checksum = CRC64_ECMA.checksum(&bytes);
+            bytes.extend_from_slice(&checksum.to_be_bytes());
+
+            let result = decode(&bytes);
+            assert_eq!(
+                result.unwrap_err().to_string(),
+                "Truncated file: insufficient data for entry"
+            );
+        }
+
+        #[test]
+        fn test_compresses_repetitive_paths() {
+            let records: Vec<_> = (0..1000)
+                .map(|i| FileIndexMetadata {
+                    path: format!("/home/user/project/src/module_{}/file.rs", i % 10),
+                    size: 1024,
+                    modified_time: 1622547800,
+                    content_hash: Some(42),
+                })
+                .collect();
+            let uncompressed_size = records
+                .iter()
+                .fold(HEADER_SIZE, |acc, record| acc + 27 + record.path.len());
+
+            let encoded = encode(&records).unwrap();
+
+            assert!(encoded.len() * 10 < uncompressed_size);
+            assert_eq!(decode(&encoded).unwrap(), records);
+        }
+
         #[test]
         fn test_invalid_magic() {
             let mut bytes = vec![b'X', b'Y', b'Z', b'W']; // Wrong magic
@@ -396,6 +908,7 @@ mod tests {
                 path: "test.txt".to_string(),
                 size: 1024,
                 modified_time: 1622547800,
+                content_hash: None,
             }];
             let mut encoded = encode(&records).unwrap();
 
@@ -419,17 +932,20 @@ mod tests {
                 path: "a.c".to_string(),
                 size: 100,
                 modified_time: 1622547800,
+                content_hash: None,
             }];
             let manifest = vec![
                 FileIndexMetadata {
                     path: "a.c".to_string(),
                     size: 100,
                     modified_time: 1622547800,
+                    content_hash: None,
                 },
                 FileIndexMetadata {
                     path: "b.c".to_string(),
                     size: 200,
                     modified_time: 1622547800,
+                    content_hash: None,
                 },
             ];
 
@@ -450,11 +966,13 @@ mod tests {
                 path: "a.c".to_string(),
                 size: 100,
                 modified_time: 1622547800,
+                content_hash: None,
             }];
             let manifest = vec![FileIndexMetadata {
                 path: "a.c".to_string(),
                 size: 150,
                 modified_time: 1622547900,
+                content_hash: None,
             }];
 
             let delta = diff_file_index_metadata(&snapshot, &manifest);
@@ -474,6 +992,7 @@ mod tests {
                 path: "a.c".to_string(),
                 size: 100,
                 modified_time: 1622547800,
+                content_hash: None,
             }];
 
             let manifest = vec![];
@@ -488,5 +1007,192 @@ mod tests {
             assert_eq!(delta.removed[0].size, 100);
             assert_eq!(delta.removed[0].modified_time, 1622547800);
         }
+
+        #[test]
+        fn test_change_detection() {
+            let file = |path: &str, size, modified_time, content_hash| FileIndexMetadata {
+                path: path.to_string(),
+                size,
+                modified_time,
+                content_hash,
+            };
+            let snapshot = vec![
+                file("touched.c", 100, 1, Some(7)),
+                file("edited.c", 100, 1, Some(7)),
+                file("grown.c", 100, 1, Some(7)),
+                file("same_time.c", 100, 1, Some(7)),
+                file("unhashed.c", 100, 1, None),
+            ];
+            let manifest = vec![
+                file("touched.c", 100, 2, None),
+                file("edited.c", 100, 2, None),
+                file("grown.c", 200, 2, None),
+                file("same_time.c", 100, 1, None),
+                file("unhashed.c", 100, 2, None),
+            ];
+            // Only edited.c and same_time.c have new content
+            let hash = |file: &FileIndexMetadata| match file.path.as_str() {
+                "edited.c" | "same_time.c" => 8,
+                _ => 7,
+            };
+            let detect = |detection| {
+                let mut manifest = manifest.clone();
+                let delta = detect_changes(&snapshot, &mut manifest, detection, hash);
+                let modified: Vec<_> = delta.modified.into_iter().map(|f| f.path).collect();
+                let hashes: Vec<_> = manifest.into_iter().map(|f| f.content_hash).collect();
+                (modified, hashes)
+            };
+
+            let (modified, hashes) = detect(ChangeDetection::Fast);
+            assert_eq!(modified, ["touched.c", "edited.c", "grown.c", "unhashed.c"]);
+            assert_eq!(hashes, [None, None, None, Some(7), None]);
+
+            let (modified, hashes) = detect(ChangeDetection::Auto);
+            assert_eq!(modified, ["edited.c", "grown.c", "unhashed.c"]);
+            assert_eq!(hashes, [Some(7), Some(8), None, Some(7), None]);
+
+            let (modified, hashes) = detect(ChangeDetection::Hash);
+            assert_eq!(
+                modified,
+                ["edited.c", "grown.c", "same_time.c", "unhashed.c"]
+            );
+            assert_eq!(hashes, [Some(7), Some(8), None, Some(8), None]);
+        }
+
+        #[test]
+        fn test_file_rename() {
+            let file = |path: &str, size| FileIndexMetadata {
+                path: path.to_string(),
+                size,
+                modified_time: 1622547800,
+                content_hash: None,
+            };
+            let snapshot = vec![file("a.c", 100), file("b.c", 200), file("c.c", 300)];
+            let manifest = vec![file("src/a.c", 100), file("d.c", 200), file("c.c", 300)];
+
+            let mut delta = diff_file_index_metadata(&snapshot, &manifest);
+            detect_renames(&mut delta, |from, to| {
+                from.path == "a.c" && to.path == "src/a.c"
+            });
+
+            assert_eq!(delta.modified.len(), 0);
+            assert_eq!(delta.renamed.len(), 1);
+            assert_eq!(delta.renamed[0].from.path, "a.c");
+            assert_eq!(delta.renamed[0].to.path, "src/a.c");
+            // Same size but different content is still a removal plus an addition.
+            assert_eq!(delta.removed.len(), 1);
+            assert_eq!(delta.removed[0].path, "b.c");
+            assert_eq!(delta.added.len(), 1);
+            assert_eq!(delta.added[0].path, "d.c");
+        }
+    }
+
+    mod listed_files {
+        use super::*;
+
+        #[test]
+        fn test_relative_files() {
+            let root = tempfile::tempdir().unwrap();
+            let root_path = normalize_path(root.path());
+            let files = |files: &[&str]| {
+                let files: Vec<_> = files.iter().map(|file| file.to_string()).collect();
+                relative_files(&root_path, &files)
+            };
+
+            assert_eq!(
+                files(&[
+                    "./src/lib.rs",
+                    &format!("{root_path}/README.md"),
+                    "src/lib.rs"
+                ]),
+                Ok(vec!["README.md".to_string(), "src/lib.rs".to_string()])
+            );
+            assert!(files(&["../outside.rs"]).is_err());
+            assert!(files(&["/elsewhere/lib.rs"]).is_err());
+            assert!(files(&["."]).is_err());
+        }
+
+        #[test]
+        fn test_scan_listed() {
+            let root = tempfile::tempdir().unwrap();
+            let root_path = normalize_path(root.path());
+            std::fs::create_dir(root.path().join("src")).unwrap();
+            for file in ["src/lib.rs", "src/unlisted.rs", "notes.md"] {
+                std::fs::write(root.path().join(file), "fn listed() {}").unwrap();
+            }
+            let options = IndexingOptions::default()
+                .with_only_extensions(["rs"])
+                .with_files(
+                    ["src/lib.rs", "src", "notes.md", "deleted.rs"]
+                        .map(str::to_string)
+                        .to_vec(),
+                );
+
+            let (files, skipped) = scan_with_skipped(&root_path, &options);
+            let paths: Vec<_> = files.iter().map(|file| file.path.as_str()).collect();
+            assert_eq!(paths, vec![format!("{root_path}/src/lib.rs")]);
+            assert_eq!(skipped.ignored, 2);
+            assert_eq!(skipped.unreadable, 1);
+        }
+
+        #[test]
+        fn test_scan_tracked() {
+            let repo = crate::git::tests::repository();
+            let root_path = normalize_path(repo.path());
+            std::fs::write(repo.path().join("dir/untracked.rs"), "").unwrap();
+            std::fs::write(repo.path().join(".hidden.rs"), "").unwrap();
+            crate::git::git(repo.path(), &["add", ".hidden.rs"]).unwrap();
+            let options = IndexingOptions::default().with_tracked_only(true);
+
+            // Tracked hidden files are indexed, untracked ones are not
+            let (mut files, skipped) = scan_with_skipped(&root_path, &options);
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            let paths: Vec<_> = files
+                .iter()
+                .map(|file| &file.path[root_path.len()..])
+                .collect();
+            assert_eq!(
+                paths,
+                ["/.hidden.rs", "/dir/new.rs", "/dir/old.rs", "/top.rs"]
+            );
+            assert!(skipped.is_empty());
+
+            // Without git, the directory is walked
+            let dir = tempfile::tempdir().unwrap();
+            std::fs::write(dir.path().join("lib.rs"), "").unwrap();
+            let files = scan(&normalize_path(dir.path()), &options);
+            assert_eq!(files.len(), 1);
+        }
+
+        #[test]
+        fn test_scan_hidden_and_excluded() {
+            let repo = crate::git::tests::repository();
+            let root_path = normalize_path(repo.path());
+            std::fs::create_dir(repo.path().join(".config")).unwrap();
+            std::fs::write(repo.path().join(".config/settings.rs"), "").unwrap();
+            std::fs::write(repo.path().join("scratch.rs"), "").unwrap();
+            std::fs::write(repo.path().join(".git/info/exclude"), "scratch.rs\n").unwrap();
+            let scanned = |options: IndexingOptions| {
+                let mut paths: Vec<_> = scan(&root_path, &options)
+                    .into_iter()
+                    .map(|file| file.path[root_path.len() + 1..].to_string())
+                    .collect();
+                paths.sort();
+                paths
+            };
+
+            assert_eq!(
+                scanned(IndexingOptions::default()),
+                ["dir/new.rs", "dir/old.rs", "top.rs"]
+            );
+            assert_eq!(
+                scanned(IndexingOptions::default().with_hidden(true)),
+                [".config/settings.rs", "dir/new.rs", "dir/old.rs", "top.rs"]
+            );
+            assert_eq!(
+                scanned(IndexingOptions::default().with_no_git_exclude(true)),
+                ["dir/new.rs", "dir/old.rs", "scratch.rs", "top.rs"]
+            );
+        }
     }
 }
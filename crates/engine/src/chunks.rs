@@ -0,0 +1,242 @@
+use crate::encoding::{self, SNIFF_SIZE};
+use std::io::{BufRead, Read, Take};
+
+/// Largest piece of a file indexed as one document. Larger files are read and indexed in chunks
+/// of about this size, so that the memory indexing takes does not grow with them.
+pub const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Where a chunk of a file indexed in several documents starts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChunkStart {
+    /// Byte offset of the chunk in the UTF-8 text of the file, see [`crate::encoding`].
+    pub offset: u64,
+    /// 0-based line of the file the chunk starts on.
+    pub line: u64,
+}
+
+/// The content of a file streamed in chunks of at most `chunk_size` bytes, cut after the last line
+/// ending of each chunk when it has one, else between two characters.
+///
+/// Chunks come with their [`ChunkStart`], unless the file fits in a single chunk. Content that is
+/// not UTF-8 reads as U+FFFD, or ends the file when `utf8_only`, and an incomplete character at
+/// the cap is left out. A binary file, see [`encoding::is_binary`], is a single empty chunk, like a
+/// file that cannot be read.
+pub struct ContentChunks<R> {
+    reader: Take<R>,
+    chunk_size: usize,
+    utf8_only: bool,
+    /// Bytes read after the end of the previous chunk.
+    pending: Vec<u8>,
+    start: ChunkStart,
+    chunked: bool,
+    done: bool,
+    binary: bool,
+}
+
+impl<R: BufRead> ContentChunks<R> {
+    /// Reads at most the first `max_size` bytes of `reader`, or all of it when unset.
+    pub fn new(reader: R, max_size: Option<u64>, chunk_size: usize) -> Self {
+        ContentChunks {
+            reader: reader.take(max_size.unwrap_or(u64::MAX)),
+            chunk_size,
+            utf8_only: false,
+            pending: Vec::new(),
+            start: ChunkStart::default(),
+            chunked: false,
+            done: false,
+            binary: false,
+        }
+    }
+
+    /// Indexes files up to their first content that is not UTF-8, rather than replacing it.
+    pub fn with_utf8_only(mut self, utf8_only: bool) -> Self {
+        self.utf8_only = utf8_only;
+        self
+    }
+
+    /// Whether the file was found binary, or content that is not UTF-8 ended it, so far.
+    pub fn is_binary(&self) -> bool {
+        self.binary
+    }
+}
+
+impl<R: BufRead> Iterator for ContentChunks<R> {
+    type Item = (Option<ChunkStart>, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        // Read errors end the file like its end does, keeping what was read
+        let wanted = self.chunk_size.saturating_sub(self.pending.len()) as u64;
+        let _ = (&mut self.reader)
+            .take(wanted)
+            .read_to_end(&mut self.pending);
+        let at_end = self.pending.len() < self.chunk_size
+            || self.reader.fill_buf().map_or(true, |buf| buf.is_empty());
+
+        let end = if at_end {
+            self.pending.len()
+        } else {
+            chunk_end(&self.pending)
+        };
+        let rest = self.pending.split_off(end);
+        let chunk = std::mem::replace(&mut self.pending, rest);
+        self.chunked |= !at_end;
+        self.done = at_end;
+
+        let start = self.chunked.then_some(self.start);
+        let first = self.start.offset == 0;
+        self.start.offset += chunk.len() as u64;
+        self.start.line += chunk.iter().filter(|&&byte| byte == b'\n').count() as u64;
+
+        if first && encoding::is_binary(&chunk[..chunk.len().min(SNIFF_SIZE)]) {
+            self.done = true;
+            self.binary = true;
+            return Some((None, String::new()));
+        }
+        match String::from_utf8(chunk) {
+            Ok(content) => Some((start, content)),
+            // A cap can cut the last character
+            Err(e) if at_end && e.utf8_error().error_len().is_none() => {
+                let valid_up_to = e.utf8_error().valid_up_to();
+                let mut bytes = e.into_bytes();
+                bytes.truncate(valid_up_to);
+                Some((start, String::from_utf8(bytes).ok()?))
+            }
+            Err(e) if !self.utf8_only => {
+                Some((start, String::from_utf8_lossy(e.as_bytes()).into_owned()))
+            }
+            Err(e) => {
+                self.done = true;
+                self.binary = true;
+                let valid_up_to = e.utf8_error().valid_up_to();
+                let mut bytes = e.into_bytes();
+                bytes.truncate(valid_up_to);
+                match bytes.is_empty() {
+                    true => first.then(|| (None, String::new())),
+                    false => Some((start, String::from_utf8(bytes).ok()?)),
+                }
+            }
+        }
+    }
+}
+
+/// Where the next chunk starts in `bytes`: after the last line ending, or else after the last
+/// character they hold whole.
+fn chunk_end(bytes: &[u8]) -> usize {
+    if let Some(newline) = bytes.iter().rposition(|&byte| byte == b'\n') {
+        return newline + 1;
+    }
+    let is_continuation = |byte: u8| byte & 0b1100_0000 == 0b1000_0000;
+    let Some(last) = bytes.iter().rposition(|&byte| !is_continuation(byte)) else {
+        return bytes.len();
+    };
+    let width = match bytes[last] {
+        0xf0.. => 4,
+        0xe0.. => 3,
+        0xc0.. => 2,
+        _ => 1,
+    };
+    if last == 0 || last + width <= bytes.len() {
+        bytes.len()
+    } else {
+        last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(text: &[u8], max_size: Option<u64>, chunk_size: usize) -> Vec<(Option<u64>, String)> {
+        ContentChunks::new(text, max_size, chunk_size)
+            .map(|(start, content)| (start.map(|start| start.line), content))
+            .collect()
+    }
+
+    #[test]
+    fn test_content_chunks() {
+        // Small files are a single chunk, empty ones included
+        assert_eq!(
+            chunks(b"one\ntwo\n", None, 16),
+            [(None, "one\ntwo\n".into())]
+        );
+        assert_eq!(chunks(b"", None, 16), [(None, String::new())]);
+
+        // Larger files are cut after lines, and chunks know the line they start on
+        let starts: Vec<_> = ContentChunks::new(&b"one\ntwo\nthree\nfour"[..], None, 10)
+            .map(|(start, content)| (start.unwrap(), content))
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                (ChunkStart { offset: 0, line: 0 }, "one\ntwo\n".to_string()),
+                (ChunkStart { offset: 8, line: 2 }, "three\nfour".to_string()),
+            ]
+        );
+
+        // Lines longer than a chunk are cut between characters
+        assert_eq!(
+            chunks("aéééé".as_bytes(), None, 4),
+            [
+                (Some(0), "aé".into()),
+                (Some(0), "éé".into()),
+                (Some(0), "é".into())
+            ]
+        );
+
+        // The cap ends the content, even inside a character
+        assert_eq!(
+            chunks("abcé".as_bytes(), Some(4), 16),
+            [(None, "abc".into())]
+        );
+        assert_eq!(
+            chunks(b"one\ntwo\nthree\n", Some(9), 5),
+            [(Some(0), "one\n".into()), (Some(1), "two\nt".into())]
+        );
+
+        // Binary files are a single empty chunk
+        let mut binary = ContentChunks::new(&b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"[..], None, 8);
+        assert_eq!(binary.next(), Some((None, String::new())));
+        assert_eq!(binary.next(), None);
+        assert!(binary.is_binary());
+        let mut text = ContentChunks::new("abcé".as_bytes(), Some(4), 16);
+        text.by_ref().for_each(drop);
+        assert!(!text.is_binary());
+    }
+
+    #[test]
+    fn test_content_that_is_not_utf8() {
+        // Past the bytes binary files are told by, invalid bytes read as U+FFFD
+        let mut text = "fn first() {}\n".repeat(SNIFF_SIZE / 8).into_bytes();
+        text.extend_from_slice(b"\xff fn last() {}\n");
+        let mut content = ContentChunks::new(&text[..], None, 1024 * 1024);
+        let (start, chunk) = content.next().unwrap();
+        assert_eq!(start, None);
+        assert!(chunk.starts_with("fn first() {}\n"));
+        assert!(chunk.ends_with("\u{fffd} fn last() {}\n"));
+        assert!(!content.is_binary());
+
+        // With utf8_only, they end the file
+        let utf8_only = |text: &[u8], chunk_size| {
+            let mut chunks = ContentChunks::new(text, None, chunk_size).with_utf8_only(true);
+            let content: Vec<_> = chunks
+                .by_ref()
+                .map(|(start, content)| (start.map(|start| start.line), content))
+                .collect();
+            assert!(chunks.is_binary());
+            content
+        };
+        assert_eq!(
+            utf8_only(b"text\xff more", 16),
+            [(None, "text".to_string())]
+        );
+        assert_eq!(utf8_only(b"\xff\xfe text", 16), [(None, String::new())]);
+        assert_eq!(
+            utf8_only(b"text\n\xff\xfe binary", 8),
+            [(Some(0), "text\n".to_string())]
+        );
+    }
+}
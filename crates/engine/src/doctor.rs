@@ -0,0 +1,129 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "kind", content = "detail", rename_all = "snake_case")]
+pub enum IndexProblem {
+    MissingMetadata,
+    InvalidMetadata(String),
+    MissingTargetPath(String),
+    CorruptedSnapshot(String),
+    UnopenableIndex(String),
+    /// The index is encrypted and its key is not configured, or another key is.
+    UnavailableKey(String),
+    /// A file of a committed segment is gone, found by `beetle verify`.
+    MissingSegmentFile(String),
+    /// A file of a committed segment does not match its checksum, found by `beetle verify`.
+    DamagedSegmentFile(String),
+    /// Files of the file snapshot that have no document in the index.
+    UnindexedFiles(usize),
+    /// Indexed files of the target path that the file snapshot does not list.
+    UnlistedFiles(usize),
+    /// The index was written in this older format version and has to be migrated.
+    OutdatedFormat(u32),
+}
+
+impl IndexProblem {
+    /// Whether `beetle doctor --fix` (or `beetle verify --repair`, for the problems only it
+    /// finds) knows how to repair this problem without user input.
+    pub fn is_recoverable(&self) -> bool {
+        matches!(
+            self,
+            IndexProblem::CorruptedSnapshot(_)
+                | IndexProblem::UnopenableIndex(_)
+                | IndexProblem::MissingSegmentFile(_)
+                | IndexProblem::DamagedSegmentFile(_)
+                | IndexProblem::UnindexedFiles(_)
+                | IndexProblem::UnlistedFiles(_)
+        )
+    }
+}
+
+impl fmt::Display for IndexProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexProblem::MissingMetadata => write!(f, "metadata file is missing"),
+            IndexProblem::InvalidMetadata(e) => write!(f, "metadata file is invalid: {e}"),
+            IndexProblem::MissingTargetPath(path) => {
+                write!(f, "target path '{path}' does not exist")
+            }
+            IndexProblem::CorruptedSnapshot(e) => {
+                write!(f, "file index snapshot is corrupted: {e}")
+            }
+            IndexProblem::UnopenableIndex(e) => write!(f, "index cannot be opened: {e}"),
+            IndexProblem::UnavailableKey(e) => write!(f, "encryption key is unavailable: {e}"),
+            IndexProblem::MissingSegmentFile(path) => write!(f, "segment file '{path}' is missing"),
+            IndexProblem::DamagedSegmentFile(path) => {
+                write!(f, "segment file '{path}' does not match its checksum")
+            }
+            IndexProblem::OutdatedFormat(version) => {
+                write!(f, "index format version {version} needs migration")
+            }
+            IndexProblem::UnindexedFiles(count) => {
+                write!(
+                    f,
+                    "{count} files of the file index snapshot have no documents"
+                )
+            }
+            IndexProblem::UnlistedFiles(count) => {
+                write!(
+                    f,
+                    "{count} indexed files are missing from the file index snapshot"
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct IndexIssue {
+    pub index_name: String,
+    pub problem: IndexProblem,
+    pub fixed: bool,
+}
+
+impl IndexIssue {
+    pub fn new(index_name: &str, problem: IndexProblem) -> Self {
+        IndexIssue {
+            index_name: index_name.to_string(),
+            problem,
+            fixed: false,
+        }
+    }
+}
+
+/// Why an entry of the storage holds no usable index, see [`OrphanedEntry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanReason {
+    /// An index directory without `meta.json`, e.g. left by an interrupted creation.
+    MissingMetadata,
+    /// An index directory whose `meta.json` cannot be parsed.
+    InvalidMetadata,
+    /// The copy a full reindex was building when it was interrupted.
+    StaleRebuild,
+    /// The lock file of an index that no longer exists.
+    StaleLock,
+}
+
+impl fmt::Display for OrphanReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrphanReason::MissingMetadata => write!(f, "metadata file is missing"),
+            OrphanReason::InvalidMetadata => write!(f, "metadata file is invalid"),
+            OrphanReason::StaleRebuild => write!(f, "left by an interrupted reindex"),
+            OrphanReason::StaleLock => write!(f, "lock file of a removed index"),
+        }
+    }
+}
+
+/// A directory or file of the storage that holds no usable index, found by `beetle clean`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct OrphanedEntry {
+    /// The index the entry belongs to, going by its name.
+    pub index_name: String,
+    pub path: String,
+    pub reason: OrphanReason,
+    /// Space taken on disk, in bytes.
+    pub size_bytes: u64,
+    pub removed: bool,
+}
@@ -0,0 +1,252 @@
+//! Reading files in text encodings other than UTF-8.
+//!
+//! Files are indexed as UTF-8 text, and the encoding of those that are not is told by their first
+//! [`SNIFF_SIZE`] bytes: a byte order mark, UTF-16 without one, or else the legacy encoding
+//! (Windows-1252, Shift-JIS, GBK, ...) [`chardetng`] guesses for content that is not UTF-8 but
+//! holds no control characters binary files do. Those files are transcoded with [`encoding_rs`]
+//! as they are read, content that is not valid in their encoding reading as U+FFFD.
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Decoder, Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek};
+
+/// Bytes at the start of a file its encoding is told by.
+pub(crate) const SNIFF_SIZE: usize = 8 * 1024;
+
+/// The encoding of text starting with `prefix`, and the length of its byte order mark. Content
+/// that is neither UTF-8 nor text, such as that of binary files, is told UTF-8, so that it is read
+/// as it is and found binary, see [`is_binary`].
+pub fn detect(prefix: &[u8]) -> (&'static Encoding, usize) {
+    if let Some(bom) = Encoding::for_bom(prefix) {
+        return bom;
+    }
+    if let Some(encoding) = detect_utf16(prefix) {
+        return (encoding, 0);
+    }
+    if is_utf8(prefix) || is_binary(prefix) {
+        return (UTF_8, 0);
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(prefix, false);
+    (detector.guess(None, true), 0)
+}
+
+/// Whether content starting with `prefix` is not text: it is not UTF-8, and holds control
+/// characters that text files do not.
+pub(crate) fn is_binary(prefix: &[u8]) -> bool {
+    !is_utf8(prefix) && prefix.iter().any(|&byte| is_binary_control(byte))
+}
+
+/// Whether `prefix` is UTF-8, but for its last character that it can cut.
+fn is_utf8(prefix: &[u8]) -> bool {
+    std::str::from_utf8(prefix).map_or_else(|e| e.error_len().is_none(), |_| true)
+}
+
+/// UTF-16 without a byte order mark is told by the zero high bytes of ASCII characters: most of
+/// the bytes at one parity are zero, and almost none at the other.
+fn detect_utf16(prefix: &[u8]) -> Option<&'static Encoding> {
+    let units = prefix.len() / 2;
+    if units < 2 {
+        return None;
+    }
+    let zeros = |parity| {
+        prefix[..units * 2]
+            .iter()
+            .skip(parity)
+            .step_by(2)
+            .filter(|&&byte| byte == 0)
+            .count()
+    };
+    let (even, odd) = (zeros(0), zeros(1));
+    if odd * 2 > units && even * 10 < units {
+        Some(UTF_16LE)
+    } else if even * 2 > units && odd * 10 < units {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Control characters that text files do not hold, unlike tabs, line and page breaks, or the
+/// escapes of terminal colors.
+fn is_binary_control(byte: u8) -> bool {
+    matches!(byte, 0x00..=0x08 | 0x0e..=0x1a | 0x1c..=0x1f)
+}
+
+/// Opens the file at `path` as UTF-8 text, transcoded from the encoding it is detected in unless
+/// `utf8_only`. A UTF-8 byte order mark is left out either way.
+pub(crate) fn open_text(path: &str, utf8_only: bool) -> io::Result<Box<dyn BufRead>> {
+    open_text_at(path, utf8_only, 0)
+}
+
+/// [`open_text`], reading from `offset` bytes into the UTF-8 text.
+pub(crate) fn open_text_at(
+    path: &str,
+    utf8_only: bool,
+    offset: u64,
+) -> io::Result<Box<dyn BufRead>> {
+    text_at(File::open(path)?, utf8_only, offset)
+}
+
+/// [`open_text`] for content that is not read from a file, e.g. that of a file of a branch.
+pub(crate) fn bytes_text(content: Vec<u8>, utf8_only: bool) -> Box<dyn BufRead> {
+    // Reading from memory does not fail
+    text_at(io::Cursor::new(content), utf8_only, 0).unwrap_or_else(|_| Box::new(io::empty()))
+}
+
+fn text_at<R: Read + Seek + 'static>(
+    source: R,
+    utf8_only: bool,
+    offset: u64,
+) -> io::Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::with_capacity(SNIFF_SIZE, source);
+    let (encoding, bom) = detect(reader.fill_buf()?);
+    if encoding == UTF_8 || utf8_only {
+        if encoding == UTF_8 {
+            reader.consume(bom);
+        }
+        reader.seek_relative(offset as i64)?;
+        return Ok(Box::new(reader));
+    }
+    reader.consume(bom);
+
+    let mut reader = BufReader::new(Transcoder::new(reader, encoding));
+    io::copy(&mut (&mut reader).take(offset), &mut io::sink())?;
+    Ok(Box::new(reader))
+}
+
+/// Reads the whole file at `path` as text, see [`open_text`]; `None` when it cannot be read, or
+/// is not text.
+pub(crate) fn read_text(path: &str) -> Option<String> {
+    let mut content = Vec::new();
+    open_text(path, false)
+        .ok()?
+        .read_to_end(&mut content)
+        .ok()?;
+    if is_binary(&content[..content.len().min(SNIFF_SIZE)]) {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&content).into_owned())
+}
+
+/// Reads text in an encoding from a reader as UTF-8. Content that is not valid in the encoding is
+/// read as replacement characters.
+struct Transcoder<R> {
+    reader: R,
+    decoder: Decoder,
+    /// The end of the input was decoded.
+    finished: bool,
+    /// Transcoded content not read yet, from `position` on.
+    decoded: Vec<u8>,
+    position: usize,
+}
+
+impl<R: BufRead> Transcoder<R> {
+    fn new(reader: R, encoding: &'static Encoding) -> Self {
+        Transcoder {
+            reader,
+            decoder: encoding.new_decoder_without_bom_handling(),
+            finished: false,
+            decoded: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Read for Transcoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.decoded.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            let input = self.reader.fill_buf()?;
+            // The end of the input flushes the halves of characters the decoder holds
+            let last = input.is_empty();
+            let capacity = self
+                .decoder
+                .max_utf8_buffer_length(input.len())
+                .ok_or_else(|| io::Error::other("Text too large to transcode"))?;
+            self.decoded.resize(capacity, 0);
+            self.position = 0;
+            let (_, read, written, _) = self.decoder.decode_to_utf8(input, &mut self.decoded, last);
+            self.decoded.truncate(written);
+            self.reader.consume(read);
+            self.finished = last;
+        }
+
+        let decoded = &self.decoded[self.position..];
+        let len = decoded.len().min(buf.len());
+        buf[..len].copy_from_slice(&decoded[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+
+    fn transcode(bytes: &[u8]) -> String {
+        let (encoding, bom) = detect(bytes);
+        let mut content = String::new();
+        // Reads of a single byte cut every character
+        let reader = BufReader::with_capacity(1, &bytes[bom..]);
+        Transcoder::new(reader, encoding)
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| match big_endian {
+                true => unit.to_be_bytes(),
+                false => unit.to_le_bytes(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(b"fn main() {}"), (UTF_8, 0));
+        assert_eq!(detect("caf\u{e9}".as_bytes()), (UTF_8, 0));
+        assert_eq!(detect(b"\xef\xbb\xbfbom"), (UTF_8, 3));
+        assert_eq!(detect(b"\xff\xfea\0"), (UTF_16LE, 2));
+        assert_eq!(detect(b"\xfe\xff\0a"), (UTF_16BE, 2));
+        assert_eq!(detect(&utf16("fn main", false)), (UTF_16LE, 0));
+        assert_eq!(detect(&utf16("fn main", true)), (UTF_16BE, 0));
+        assert_eq!(detect(b"caf\xe9 cr\xe8me br\xfbl\xe9e"), (WINDOWS_1252, 0));
+        let (shift_jis, _, _) = SHIFT_JIS
+            .encode("// \u{691c}\u{7d22}\u{3059}\u{308b}\u{95a2}\u{6570}\u{3067}\u{3059}\u{3002}");
+        assert_eq!(detect(&shift_jis), (SHIFT_JIS, 0));
+        // A prefix can end inside a character
+        assert_eq!(detect(&"caf\u{e9}".as_bytes()[..4]), (UTF_8, 0));
+        // Binary content is left alone
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        assert_eq!(detect(png), (UTF_8, 0));
+        assert!(is_binary(png));
+        assert!(!is_binary(b"tab\tand\x1b[0m escape"));
+    }
+
+    #[test]
+    fn test_transcode() {
+        assert_eq!(transcode(b"caf\xe9 \x80 \x93"), "café € “");
+
+        let text = "fn café() { \"𝄞\" }\n";
+        assert_eq!(
+            transcode(&[b"\xff\xfe", &utf16(text, false)[..]].concat()),
+            text
+        );
+        assert_eq!(
+            transcode(&[b"\xfe\xff", &utf16(text, true)[..]].concat()),
+            text
+        );
+        assert_eq!(transcode(&utf16(text, false)), text);
+
+        // Lone surrogates and a last odd byte are replacement characters
+        assert_eq!(transcode(b"\xff\xfea\0\x00\xd8b\0c"), "a\u{fffd}b\u{fffd}");
+    }
+}
@@ -0,0 +1,488 @@
+//! Encryption at rest for the files of an index, with AES-256-GCM.
+//!
+//! Encrypted indexes keep every tantivy file, and the file index snapshot, sealed by
+//! [`EncryptedDirectory`]; snapshots and forks copy the sealed files as they are. The index
+//! `meta.json` stays readable so indexes can be listed without the key; it holds the target path,
+//! the options and the fingerprint of the key, never the key itself.
+//!
+//! Files are sealed in chunks of [`CHUNK_SIZE`] bytes so tantivy can read any range of them
+//! without decrypting the whole file:
+//!
+//! ```text
+//! MAGIC | nonce prefix (8 bytes) | chunk 0 + tag | chunk 1 + tag | ... | last chunk + tag
+//! ```
+//!
+//! The nonce of each chunk is the random prefix of its file followed by the chunk number, and
+//! the last chunk is sealed as such, so chunks cannot be reordered, moved between files or cut
+//! off without failing to decrypt.
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use tantivy::directory::error::{DeleteError, LockError, OpenReadError, OpenWriteError};
+use tantivy::directory::{
+    AntiCallToken, DirectoryLock, FileHandle, FileSlice, Lock, OwnedBytes, TerminatingWrite,
+    WatchCallback, WatchHandle, WritePtr,
+};
+use tantivy::{Directory, HasLen};
+
+/// Environment variable holding the key, as 64 hexadecimal digits.
+pub const KEY_ENV: &str = "BEETLE_ENCRYPTION_KEY";
+/// Environment variable holding a shell command that prints the key, used when [`KEY_ENV`] is
+/// unset, e.g. `security find-generic-password -s beetle -w` to read it from the macOS keychain.
+pub const KEY_COMMAND_ENV: &str = "BEETLE_ENCRYPTION_KEY_COMMAND";
+
+const MAGIC: &[u8; 8] = b"BTLENC01";
+const NONCE_PREFIX_LEN: usize = 8;
+const HEADER_LEN: usize = MAGIC.len() + NONCE_PREFIX_LEN;
+const TAG_LEN: usize = 16;
+/// Plaintext bytes per chunk; every chunk but the last is full.
+pub const CHUNK_SIZE: usize = 16 * 1024;
+
+/// An AES-256 key, identified by a fingerprint that is safe to store next to the index.
+#[derive(Clone)]
+pub struct EncryptionKey {
+    key: Arc<LessSafeKey>,
+    id: String,
+}
+
+impl EncryptionKey {
+    /// Parses a key written as 64 hexadecimal digits, as printed by `openssl rand -hex 32`.
+    pub fn parse(hex: &str) -> Result<Self, String> {
+        let hex = hex.trim();
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .filter(|bytes| bytes.len() == 32)
+            .ok_or("The encryption key must be 64 hexadecimal digits (32 bytes)")?;
+
+        let digest = ring::digest::digest(&ring::digest::SHA256, &bytes);
+        let id = digest.as_ref()[..8]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        let key = UnboundKey::new(&AES_256_GCM, &bytes)
+            .map_err(|_| "The encryption key is invalid".to_string())?;
+
+        Ok(Self {
+            key: Arc::new(LessSafeKey::new(key)),
+            id,
+        })
+    }
+
+    /// Reads the key from [`KEY_ENV`], or from the output of [`KEY_COMMAND_ENV`].
+    pub fn from_env() -> Result<Self, String> {
+        if let Ok(hex) = std::env::var(KEY_ENV) {
+            return Self::parse(&hex).map_err(|e| format!("{e} in {KEY_ENV}"));
+        }
+        let Ok(command) = std::env::var(KEY_COMMAND_ENV) else {
+            return Err(format!(
+                "No encryption key: set {KEY_ENV} to 64 hexadecimal digits (e.g. from \
+                 `openssl rand -hex 32`) or {KEY_COMMAND_ENV} to a command printing them"
+            ));
+        };
+
+        #[cfg(unix)]
+        let output = std::process::Command::new("sh")
+            .args(["-c", &command])
+            .output();
+        #[cfg(windows)]
+        let output = std::process::Command::new("cmd")
+            .args(["/C", &command])
+            .output();
+        let output = output.map_err(|e| format!("Failed to run {KEY_COMMAND_ENV}: {e}"))?;
+        if !output.status.success() {
+            return Err(format!(
+                "{KEY_COMMAND_ENV} failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Self::parse(&String::from_utf8_lossy(&output.stdout))
+            .map_err(|e| format!("{e} in the output of {KEY_COMMAND_ENV}"))
+    }
+
+    /// Fingerprint of the key, recorded by the indexes it encrypts.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Encrypts `data` as a whole file.
+    pub fn seal(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut writer = EncryptingWriter::new(Vec::new(), self.clone())?;
+        writer.write_all(data)?;
+        writer.seal_chunk(true)?;
+
+        Ok(writer.inner)
+    }
+
+    /// Decrypts a whole file written by [`EncryptionKey::seal`] or [`EncryptedDirectory`].
+    pub fn open(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let file = EncryptedFile::new(FileSlice::from(data.to_vec()), self.clone())?;
+        // Every chunk is checked, even the only chunk of an empty file
+        let mut plaintext = Vec::with_capacity(file.len);
+        for chunk in 0..file.chunks {
+            plaintext.extend_from_slice(&file.decrypt_chunk(chunk)?);
+        }
+
+        Ok(plaintext)
+    }
+
+    fn chunk_nonce(nonce_prefix: &[u8; NONCE_PREFIX_LEN], chunk: usize) -> io::Result<Nonce> {
+        let chunk = u32::try_from(chunk).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "file too large to encrypt")
+        })?;
+        let mut nonce = [0; NONCE_LEN];
+        nonce[..NONCE_PREFIX_LEN].copy_from_slice(nonce_prefix);
+        nonce[NONCE_PREFIX_LEN..].copy_from_slice(&chunk.to_be_bytes());
+
+        Ok(Nonce::assume_unique_for_key(nonce))
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptionKey")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A tantivy directory storing the files of another directory encrypted with a key.
+#[derive(Clone, Debug)]
+pub struct EncryptedDirectory {
+    inner: Box<dyn Directory>,
+    key: EncryptionKey,
+}
+
+impl EncryptedDirectory {
+    pub fn new(inner: impl Into<Box<dyn Directory>>, key: EncryptionKey) -> Self {
+        Self {
+            inner: inner.into(),
+            key,
+        }
+    }
+}
+
+impl Directory for EncryptedDirectory {
+    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+        let ciphertext = self.inner.open_read(path)?;
+        let file = EncryptedFile::new(ciphertext, self.key.clone())
+            .map_err(|e| OpenReadError::wrap_io_error(e, path.to_path_buf()))?;
+
+        Ok(Arc::new(file))
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), DeleteError> {
+        self.inner.delete(path)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        self.inner.exists(path)
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        let writer = EncryptingWriter::new(self.inner.open_write(path)?, self.key.clone())
+            .map_err(|e| OpenWriteError::wrap_io_error(e, path.to_path_buf()))?;
+
+        Ok(io::BufWriter::new(Box::new(writer)))
+    }
+
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        let data = self.inner.atomic_read(path)?;
+        self.key
+            .open(&data)
+            .map_err(|e| OpenReadError::wrap_io_error(e, path.to_path_buf()))
+    }
+
+    fn atomic_write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.inner.atomic_write(path, &self.key.seal(data)?)
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        self.inner.sync_directory()
+    }
+
+    // Lock files are empty, so they are left to the inner directory
+    fn acquire_lock(&self, lock: &Lock) -> Result<DirectoryLock, LockError> {
+        self.inner.acquire_lock(lock)
+    }
+
+    fn watch(&self, watch_callback: WatchCallback) -> tantivy::Result<WatchHandle> {
+        self.inner.watch(watch_callback)
+    }
+}
+
+/// Seals what is written to it chunk by chunk; the last chunk is sealed when terminated.
+struct EncryptingWriter<W: Write> {
+    inner: W,
+    key: EncryptionKey,
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    /// Plaintext of the chunk being filled. A full chunk is only sealed once more data comes, as
+    /// it may turn out to be the last one.
+    buffer: Vec<u8>,
+    /// Number of chunks written so far.
+    chunks: usize,
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    fn new(inner: W, key: EncryptionKey) -> io::Result<Self> {
+        let mut nonce_prefix = [0; NONCE_PREFIX_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce_prefix)
+            .map_err(|_| io::Error::other("failed to generate a nonce"))?;
+
+        Ok(Self {
+            inner,
+            key,
+            nonce_prefix,
+            buffer: Vec::with_capacity(CHUNK_SIZE + TAG_LEN),
+            chunks: 0,
+        })
+    }
+
+    fn seal_chunk(&mut self, last: bool) -> io::Result<()> {
+        if self.chunks == 0 {
+            self.inner.write_all(MAGIC)?;
+            self.inner.write_all(&self.nonce_prefix)?;
+        }
+
+        let nonce = EncryptionKey::chunk_nonce(&self.nonce_prefix, self.chunks)?;
+        self.key
+            .key
+            .seal_in_place_append_tag(nonce, Aad::from([u8::from(last)]), &mut self.buffer)
+            .map_err(|_| io::Error::other("failed to encrypt"))?;
+        self.inner.write_all(&self.buffer)?;
+        self.buffer.clear();
+        self.chunks += 1;
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, mut data: &[u8]) -> io::Result<usize> {
+        let written = data.len();
+        while !data.is_empty() {
+            if self.buffer.len() == CHUNK_SIZE {
+                self.seal_chunk(false)?;
+            }
+            let taken = data.len().min(CHUNK_SIZE - self.buffer.len());
+            self.buffer.extend_from_slice(&data[..taken]);
+            data = &data[taken..];
+        }
+
+        Ok(written)
+    }
+
+    /// Only flushes the sealed chunks: the buffered one cannot be sealed before it is full.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: TerminatingWrite> TerminatingWrite for EncryptingWriter<W> {
+    fn terminate_ref(&mut self, token: AntiCallToken) -> io::Result<()> {
+        self.seal_chunk(true)?;
+        self.inner.terminate_ref(token)
+    }
+}
+
+/// Decrypts the chunks of a sealed file as they are read.
+#[derive(Debug)]
+struct EncryptedFile {
+    ciphertext: FileSlice,
+    key: EncryptionKey,
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    chunks: usize,
+    /// Length of the plaintext.
+    len: usize,
+}
+
+impl EncryptedFile {
+    fn new(ciphertext: FileSlice, key: EncryptionKey) -> io::Result<Self> {
+        let not_sealed = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the file is not encrypted by beetle",
+            )
+        };
+        if ciphertext.len() < HEADER_LEN + TAG_LEN {
+            return Err(not_sealed());
+        }
+        let header = ciphertext.read_bytes_slice(0..HEADER_LEN)?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(not_sealed());
+        }
+
+        let body_len = ciphertext.len() - HEADER_LEN;
+        let chunks = body_len.div_ceil(CHUNK_SIZE + TAG_LEN);
+        if body_len - (chunks - 1) * (CHUNK_SIZE + TAG_LEN) < TAG_LEN {
+            return Err(not_sealed());
+        }
+
+        Ok(Self {
+            ciphertext,
+            key,
+            nonce_prefix: header[MAGIC.len()..].try_into().unwrap(),
+            chunks,
+            len: body_len - chunks * TAG_LEN,
+        })
+    }
+
+    fn decrypt_chunk(&self, chunk: usize) -> io::Result<Vec<u8>> {
+        let start = HEADER_LEN + chunk * (CHUNK_SIZE + TAG_LEN);
+        let end = (start + CHUNK_SIZE + TAG_LEN).min(self.ciphertext.len());
+        let mut data = self.ciphertext.read_bytes_slice(start..end)?.to_vec();
+
+        let nonce = EncryptionKey::chunk_nonce(&self.nonce_prefix, chunk)?;
+        let last = chunk + 1 == self.chunks;
+        let plaintext_len = self
+            .key
+            .key
+            .open_in_place(nonce, Aad::from([u8::from(last)]), &mut data)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "failed to decrypt: wrong encryption key or corrupted file",
+                )
+            })?
+            .len();
+        data.truncate(plaintext_len);
+
+        Ok(data)
+    }
+}
+
+impl HasLen for EncryptedFile {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl FileHandle for EncryptedFile {
+    fn read_bytes(&self, range: Range<usize>) -> io::Result<OwnedBytes> {
+        if range.is_empty() {
+            return Ok(OwnedBytes::empty());
+        }
+
+        let first = range.start / CHUNK_SIZE;
+        let last = (range.end - 1) / CHUNK_SIZE;
+        let mut plaintext = self.decrypt_chunk(first)?;
+        for chunk in first + 1..=last {
+            plaintext.extend_from_slice(&self.decrypt_chunk(chunk)?);
+        }
+
+        let offset = first * CHUNK_SIZE;
+        Ok(OwnedBytes::new(plaintext).slice(range.start - offset..range.end - offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{CodeIndexDocument, CodeIndexSchema};
+    use crate::storage::committed_segment_files;
+    use crate::tokenizers::register_tokenizers;
+    use std::time::SystemTime;
+    use tantivy::collector::Count;
+    use tantivy::directory::RamDirectory;
+    use tantivy::query::QueryParser;
+    use tantivy::Index;
+
+    fn key(byte: &str) -> EncryptionKey {
+        EncryptionKey::parse(&byte.repeat(32)).unwrap()
+    }
+
+    #[test]
+    fn test_sealed_files_round_trip() {
+        assert!(EncryptionKey::parse("00ff").is_err());
+        assert!(EncryptionKey::parse(&"zz".repeat(32)).is_err());
+        assert_ne!(key("01").id(), key("02").id());
+
+        let key = key("01");
+        for len in [0, 1, CHUNK_SIZE, 2 * CHUNK_SIZE + 5] {
+            let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let sealed = key.seal(&data).unwrap();
+            assert_eq!(key.open(&sealed).unwrap(), data);
+
+            let file = EncryptedFile::new(FileSlice::from(sealed.clone()), key.clone()).unwrap();
+            if len > 3 {
+                assert_eq!(
+                    file.read_bytes(2..len - 1).unwrap().as_slice(),
+                    &data[2..len - 1]
+                );
+            }
+
+            let mut tampered = sealed.clone();
+            *tampered.last_mut().unwrap() ^= 1;
+            assert!(key.open(&tampered).is_err());
+        }
+
+        // Dropping the last chunk of a file is detected, not only damaged chunks
+        let sealed = key.seal(&vec![7; 2 * CHUNK_SIZE + 5]).unwrap();
+        assert!(key
+            .open(&sealed[..HEADER_LEN + 2 * (CHUNK_SIZE + TAG_LEN)])
+            .is_err());
+        assert!(EncryptionKey::parse(&"02".repeat(32))
+            .unwrap()
+            .open(&sealed)
+            .is_err());
+    }
+
+    #[test]
+    fn test_encrypted_index() {
+        let storage = RamDirectory::create();
+        let schema = CodeIndexSchema::new();
+        let index = Index::create(
+            EncryptedDirectory::new(storage.clone(), key("01")),
+            schema.schema.clone(),
+            Default::default(),
+        )
+        .unwrap();
+        register_tokenizers(&index);
+        let mut writer = index.writer_with_num_threads(1, 15_000_000).unwrap();
+        writer
+            .add_document(
+                CodeIndexDocument::from_content(
+                    "secret.rs".to_string(),
+                    "fn launch_codes() {}".to_string(),
+                    SystemTime::now(),
+                )
+                .to_tantivy_document(&schema.schema),
+            )
+            .unwrap();
+        writer.commit().unwrap();
+
+        // Nothing of the document is readable in the stored files
+        let metas = index.load_metas().unwrap();
+        let files = committed_segment_files(&metas);
+        assert!(!files.is_empty());
+        for file in files.iter().chain([&"meta.json".into()]) {
+            let bytes = storage.open_read(file).unwrap().read_bytes().unwrap();
+            assert!(bytes.as_slice().starts_with(MAGIC), "{file:?}");
+            let text = String::from_utf8_lossy(bytes.as_slice());
+            assert!(!text.contains("launch_codes") && !text.contains("secret"));
+        }
+
+        let index = Index::open(EncryptedDirectory::new(storage.clone(), key("01"))).unwrap();
+        register_tokenizers(&index);
+        let query = QueryParser::for_index(&index, vec![schema.content])
+            .parse_query("launch_codes")
+            .unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        assert_eq!(searcher.search(&query, &Count).unwrap(), 1);
+
+        assert!(Index::open(EncryptedDirectory::new(storage.clone(), key("02"))).is_err());
+        assert!(Index::open(storage).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+//! Renders file content highlighted with syntect, as HTML for the web UI or ANSI escapes for
+//! terminals, so that previews are highlighted the same way everywhere.
+//!
+//! The syntax is chosen from the language [`crate::language`] detects, the one stored in the
+//! index, and plain text is used for languages syntect has no grammar for.
+
+use crate::language::{detect_language, extensions_of};
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, Style, Theme, ThemeSet};
+use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+/// Background of the lines marked by [`HighlightOptions::match_lines`] for themes that do not
+/// set one.
+const MATCH_LINE_BACKGROUND: Color = Color {
+    r: 0x4f,
+    g: 0x5b,
+    b: 0x66,
+    a: 0xff,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum HighlightFormat {
+    /// A `<pre>` of `<span>`s with inline styles.
+    #[default]
+    Html,
+    /// 24-bit color escape sequences.
+    Ansi,
+}
+
+impl std::str::FromStr for HighlightFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "html" => Ok(Self::Html),
+            "ansi" => Ok(Self::Ansi),
+            _ => Err(format!(
+                "Invalid highlight format '{s}'. Use 'html' or 'ansi'"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HighlightOptions {
+    pub format: HighlightFormat,
+    /// Name of a theme of syntect's default set, see [`themes`].
+    pub theme: String,
+    /// 1-based numbers of the lines to mark, e.g. those a search matched. In HTML they get an
+    /// `id` of `L<number>` to link to and the class `match`; both formats give them a background.
+    pub match_lines: Vec<usize>,
+}
+
+impl Default for HighlightOptions {
+    fn default() -> Self {
+        HighlightOptions {
+            format: HighlightFormat::default(),
+            theme: DEFAULT_THEME.to_string(),
+            match_lines: Vec::new(),
+        }
+    }
+}
+
+/// A file rendered by [`highlight`].
+#[derive(Debug, Clone, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HighlightedFile {
+    /// The detected language, see [`crate::language`]; not set for files in no known language.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    pub format: HighlightFormat,
+    pub theme: String,
+    /// Number of lines of the file.
+    pub lines: usize,
+    pub content: String,
+}
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme_set() -> &'static ThemeSet {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    THEMES.get_or_init(ThemeSet::load_defaults)
+}
+
+/// Names of the themes [`HighlightOptions::theme`] may name.
+pub fn themes() -> Vec<&'static str> {
+    theme_set().themes.keys().map(String::as_str).collect()
+}
+
+/// Highlights `content`, the content of the file at `path`.
+pub fn highlight(
+    path: &str,
+    content: &str,
+    options: &HighlightOptions,
+) -> Result<HighlightedFile, String> {
+    let theme = theme_set().themes.get(&options.theme).ok_or_else(|| {
+        format!(
+            "Unknown theme '{}'. Known themes: {}",
+            options.theme,
+            themes().join(", ")
+        )
+    })?;
+    let language = detect_language(path, content);
+    let syntax = syntax_of(language);
+    let match_background = theme
+        .settings
+        .line_highlight
+        .unwrap_or(MATCH_LINE_BACKGROUND);
+
+    let mut highlighter = HighlightLines::new(syntax, theme);
+    let mut rendered = String::new();
+    let mut lines = 0;
+    for (number, line) in (1..).zip(LinesWithEndings::from(content)) {
+        let mut regions = highlighter
+            .highlight_line(line, syntaxes())
+            .map_err(|e| format!("Failed to highlight {path}: {e}"))?;
+        let matched = options.match_lines.contains(&number);
+        rendered.push_str(&match options.format {
+            HighlightFormat::Html => {
+                html_line(&regions, number, matched.then_some(match_background))?
+            }
+            HighlightFormat::Ansi => {
+                if matched {
+                    for (style, _) in &mut regions {
+                        style.background = match_background;
+                    }
+                }
+                ansi_line(&regions, matched)
+            }
+        });
+        lines = number;
+    }
+    if options.format == HighlightFormat::Html {
+        rendered = html_block(theme, &rendered);
+    }
+
+    Ok(HighlightedFile {
+        language: language.map(str::to_string),
+        format: options.format,
+        theme: options.theme.clone(),
+        lines,
+        content: rendered,
+    })
+}
+
+/// The grammar of `language`, looked up by name and then by its extensions, or plain text.
+fn syntax_of(language: Option<&str>) -> &'static SyntaxReference {
+    let syntaxes = syntaxes();
+    language
+        .and_then(|language| {
+            syntaxes.find_syntax_by_token(language).or_else(|| {
+                extensions_of(language)
+                    .iter()
+                    .find_map(|extension| syntaxes.find_syntax_by_extension(extension))
+            })
+        })
+        .unwrap_or_else(|| syntaxes.find_syntax_plain_text())
+}
+
+/// The line `number` in HTML, wrapped in an anchor when it is a match line with `background`.
+fn html_line(
+    regions: &[(Style, &str)],
+    number: usize,
+    background: Option<Color>,
+) -> Result<String, String> {
+    let html = styled_line_to_highlighted_html(regions, IncludeBackground::No)
+        .map_err(|e| format!("Failed to render line {number}: {e}"))?;
+    Ok(match background {
+        Some(Color { r, g, b, .. }) => format!(
+            "<span id=\"L{number}\" class=\"match\" style=\"background-color:#{r:02x}{g:02x}{b:02x};\">{html}</span>"
+        ),
+        None => html,
+    })
+}
+
+/// The `<pre>` holding the rendered lines, with the background of `theme`.
+fn html_block(theme: &Theme, lines: &str) -> String {
+    match theme.settings.background {
+        Some(Color { r, g, b, .. }) => format!(
+            "<pre class=\"beetle-highlight\" style=\"background-color:#{r:02x}{g:02x}{b:02x};\">\n{lines}</pre>\n"
+        ),
+        None => format!("<pre class=\"beetle-highlight\">\n{lines}</pre>\n"),
+    }
+}
+
+/// The line in ANSI escapes, reset before its line break so that backgrounds stop there.
+fn ansi_line(regions: &[(Style, &str)], background: bool) -> String {
+    let trimmed = regions
+        .iter()
+        .map(|(style, text)| (*style, text.trim_end_matches(['\r', '\n'])))
+        .collect::<Vec<_>>();
+    let line_break = regions
+        .last()
+        .map(|(_, text)| &text[text.trim_end_matches(['\r', '\n']).len()..])
+        .unwrap_or_default();
+
+    format!(
+        "{}\x1b[0m{line_break}",
+        as_24_bit_terminal_escaped(&trimmed, background)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        let content = "fn main() {\n    println!(\"<hi>\");\n}\n";
+
+        let html = highlight("src/main.rs", content, &HighlightOptions::default()).unwrap();
+        assert_eq!(html.language.as_deref(), Some("rust"));
+        assert_eq!(html.lines, 3);
+        assert!(html.content.starts_with("<pre class=\"beetle-highlight\""));
+        assert!(html.content.contains("&lt;hi&gt;"));
+        assert!(!html.content.contains("id=\"L"));
+
+        // Match lines are anchored in HTML and get a background in ANSI
+        let options = HighlightOptions {
+            match_lines: vec![2],
+            ..HighlightOptions::default()
+        };
+        let html = highlight("src/main.rs", content, &options).unwrap();
+        assert!(html.content.contains("<span id=\"L2\" class=\"match\""));
+        assert!(!html.content.contains("id=\"L1\""));
+        let ansi = highlight(
+            "src/main.rs",
+            content,
+            &HighlightOptions {
+                format: HighlightFormat::Ansi,
+                ..options
+            },
+        )
+        .unwrap();
+        let lines: Vec<&str> = ansi.content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].contains("\x1b[48;2;"));
+        assert!(!lines[0].contains("\x1b[48;2;"));
+        assert!(lines.iter().all(|line| line.ends_with("\x1b[0m")));
+
+        // Files in no known language are plain text, still escaped
+        let plain = highlight("notes.unknown", "a < b\n", &HighlightOptions::default()).unwrap();
+        assert_eq!(plain.language, None);
+        assert!(plain.content.contains("a &lt; b"));
+
+        let unknown_theme = HighlightOptions {
+            theme: "missing".to_string(),
+            ..HighlightOptions::default()
+        };
+        assert!(highlight("src/main.rs", content, &unknown_theme).is_err());
+    }
+}
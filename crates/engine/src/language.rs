@@ -0,0 +1,216 @@
+//! Detects the programming language of indexed files.
+//!
+//! The language is looked up by file name, then by extension, and for extensionless scripts by
+//! the interpreter named on their shebang line. Names are lowercase and stable since they are
+//! stored in the index and used by `--lang` filters.
+
+use std::path::Path;
+
+/// Every detected language with the extensions (lowercase, without the dot) mapping to it.
+const LANGUAGES: &[(&str, &[&str])] = &[
+    ("c", &["c", "h"]),
+    ("clojure", &["clj", "cljs", "cljc", "edn"]),
+    ("cmake", &["cmake"]),
+    (
+        "cpp",
+        &["cc", "cpp", "cxx", "c++", "hh", "hpp", "hxx", "h++", "ipp"],
+    ),
+    ("csharp", &["cs", "csx"]),
+    ("css", &["css", "scss", "sass", "less"]),
+    ("dart", &["dart"]),
+    ("dockerfile", &["dockerfile"]),
+    ("elixir", &["ex", "exs"]),
+    ("erlang", &["erl", "hrl"]),
+    ("fsharp", &["fs", "fsi", "fsx"]),
+    ("go", &["go"]),
+    ("graphql", &["graphql", "gql"]),
+    ("groovy", &["groovy", "gradle"]),
+    ("haskell", &["hs", "lhs"]),
+    ("html", &["html", "htm", "xhtml"]),
+    ("java", &["java"]),
+    ("javascript", &["js", "jsx", "mjs", "cjs"]),
+    ("json", &["json", "jsonc", "json5"]),
+    ("julia", &["jl"]),
+    ("kotlin", &["kt", "kts"]),
+    ("lua", &["lua"]),
+    ("make", &["mk", "mak"]),
+    ("markdown", &["md", "markdown", "mdx"]),
+    ("nix", &["nix"]),
+    ("objc", &["m", "mm"]),
+    ("ocaml", &["ml", "mli"]),
+    ("perl", &["pl", "pm"]),
+    ("php", &["php"]),
+    ("powershell", &["ps1", "psm1", "psd1"]),
+    ("protobuf", &["proto"]),
+    ("python", &["py", "pyi", "pyw"]),
+    ("r", &["r"]),
+    ("ruby", &["rb", "rake", "gemspec"]),
+    ("rust", &["rs"]),
+    ("scala", &["scala", "sc"]),
+    ("shell", &["sh", "bash", "zsh", "fish", "ksh"]),
+    ("sql", &["sql"]),
+    ("svelte", &["svelte"]),
+    ("swift", &["swift"]),
+    ("terraform", &["tf", "tfvars", "hcl"]),
+    ("toml", &["toml"]),
+    ("typescript", &["ts", "tsx", "mts", "cts"]),
+    ("vue", &["vue"]),
+    ("xml", &["xml", "xsd", "xsl", "svg", "csproj"]),
+    ("yaml", &["yaml", "yml"]),
+    ("zig", &["zig"]),
+];
+
+/// Files recognised by their whole name, compared case-insensitively.
+const FILE_NAMES: &[(&str, &str)] = &[
+    ("cmakelists.txt", "cmake"),
+    ("dockerfile", "dockerfile"),
+    ("containerfile", "dockerfile"),
+    ("gemfile", "ruby"),
+    ("rakefile", "ruby"),
+    ("makefile", "make"),
+    ("gnumakefile", "make"),
+];
+
+/// Interpreters named on shebang lines, without their version suffix.
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("sh", "shell"),
+    ("bash", "shell"),
+    ("dash", "shell"),
+    ("zsh", "shell"),
+    ("ksh", "shell"),
+    ("fish", "shell"),
+    ("python", "python"),
+    ("node", "javascript"),
+    ("nodejs", "javascript"),
+    ("deno", "typescript"),
+    ("ruby", "ruby"),
+    ("perl", "perl"),
+    ("php", "php"),
+    ("lua", "lua"),
+    ("rscript", "r"),
+    ("pwsh", "powershell"),
+];
+
+/// Returns the language of the file at `path`, looking at the shebang line of `content` when the
+/// name does not tell.
+pub fn detect_language(path: &str, content: &str) -> Option<&'static str> {
+    let path = Path::new(path);
+    let file_name = path.file_name()?.to_string_lossy().to_lowercase();
+    if let Some((_, language)) = FILE_NAMES.iter().find(|(name, _)| *name == file_name) {
+        return Some(language);
+    }
+    // Dockerfile.dev, Makefile.am, ...
+    if let Some((_, language)) = FILE_NAMES
+        .iter()
+        .find(|(name, _)| !name.contains('.') && file_name.starts_with(&format!("{name}.")))
+    {
+        return Some(language);
+    }
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => language_of_extension(&ext.to_lowercase()),
+        None => shebang_language(content),
+    }
+}
+
+/// Returns the canonical name of the language `name` designates: a language name or one of its
+/// extensions (`rs`, `py`, ...), in any case.
+pub fn normalize_language(name: &str) -> Result<&'static str, String> {
+    let name = name.trim().to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(language, _)| *language == name)
+        .map(|(language, _)| *language)
+        .or_else(|| language_of_extension(&name))
+        .ok_or_else(|| {
+            let known: Vec<_> = LANGUAGES.iter().map(|(language, _)| *language).collect();
+            format!(
+                "Unknown language '{name}'. Known languages: {}",
+                known.join(", ")
+            )
+        })
+}
+
+/// The extensions of `language`, a canonical name; empty for languages only known by file name.
+#[cfg(feature = "highlight")]
+pub(crate) fn extensions_of(language: &str) -> &'static [&'static str] {
+    LANGUAGES
+        .iter()
+        .find(|(name, _)| *name == language)
+        .map_or(&[], |(_, extensions)| *extensions)
+}
+
+fn language_of_extension(extension: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(_, extensions)| extensions.contains(&extension))
+        .map(|(language, _)| *language)
+}
+
+/// `#!/bin/sh`, `#!/usr/bin/env python3`, `#!/usr/bin/env -S deno run`, ...
+fn shebang_language(content: &str) -> Option<&'static str> {
+    let line = content.strip_prefix("#!")?.lines().next()?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+    let program = program
+        .trim_end_matches(|c: char| c.is_ascii_digit() || c == '.')
+        .to_lowercase();
+
+    INTERPRETERS
+        .iter()
+        .find(|(interpreter, _)| *interpreter == program)
+        .map(|(_, language)| *language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(detect_language("src/main.rs", ""), Some("rust"));
+        assert_eq!(detect_language("/repo/App.TSX", ""), Some("typescript"));
+        assert_eq!(detect_language("lib/foo.hpp", ""), Some("cpp"));
+        assert_eq!(detect_language("Makefile", ""), Some("make"));
+        assert_eq!(
+            detect_language("docker/Dockerfile.dev", ""),
+            Some("dockerfile")
+        );
+        assert_eq!(detect_language("CMakeLists.txt", ""), Some("cmake"));
+        assert_eq!(detect_language("notes.txt", ""), None);
+        assert_eq!(detect_language("LICENSE", "MIT License"), None);
+
+        // Extensionless scripts
+        assert_eq!(
+            detect_language("bin/build", "#!/bin/bash\nset -e"),
+            Some("shell")
+        );
+        assert_eq!(
+            detect_language("bin/tool", "#!/usr/bin/env python3.12\nimport sys"),
+            Some("python")
+        );
+        assert_eq!(
+            detect_language("bin/serve", "#!/usr/bin/env -S node --no-warnings"),
+            Some("javascript")
+        );
+        assert_eq!(detect_language("bin/run", "#!/usr/bin/env unknown"), None);
+        // The extension wins over the shebang
+        assert_eq!(
+            detect_language("setup.sh", "#!/usr/bin/env python"),
+            Some("shell")
+        );
+    }
+
+    #[test]
+    fn test_normalize_language() {
+        assert_eq!(normalize_language("Rust"), Ok("rust"));
+        assert_eq!(normalize_language("py"), Ok("python"));
+        assert_eq!(normalize_language(" ts "), Ok("typescript"));
+        assert!(normalize_language("klingon")
+            .unwrap_err()
+            .contains("Known languages: c, clojure"));
+    }
+}
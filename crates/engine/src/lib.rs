@@ -1,15 +1,37 @@
+pub mod bench;
 mod catalog;
 pub mod change;
+mod chunks;
+pub mod doctor;
+mod encoding;
+pub mod encryption;
+pub mod git;
+#[cfg(feature = "highlight")]
+pub mod highlight;
+pub mod language;
+pub mod migration;
+pub mod options;
+pub mod paths;
 mod schema;
 pub mod search;
+pub mod snapshot;
 pub mod storage;
+pub mod symbols;
 mod tokenizers;
+pub mod usage;
+mod verify;
 mod writter;
 
-pub use catalog::IndexCatalog;
+pub use catalog::{
+    commits_index_name, slugify_index_name, submodule_index_name, validate_index_name,
+    IndexCatalog, IndexInfo, IndexSummary, PendingChanges, SchemaField, ScopedSearch, SegmentInfo,
+    ALL_INDEXES,
+};
 
 pub use crate::search::{IndexSearcher, SearchResultItem};
 
-pub use crate::storage::{FsStorage, IndexStorage};
+pub use crate::storage::{FsStorage, IndexStorage, RamStorage};
 
 pub use crate::tokenizers::CodeTokenizer;
+
+pub use crate::writter::{IndexingPhase, IndexingProgress, PlannedRename, UpdatePlan};
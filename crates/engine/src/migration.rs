@@ -0,0 +1,274 @@
+//! Upgrades index directories written by older versions of beetle before they are written to.
+//!
+//! Every index records the `format_version` of its layout in `meta.json`; indexes written before
+//! versioning count as version 0. Listing and opening an index only read it: an older index is
+//! listed with its version and refused by searches until it is migrated. Migrating, done by
+//! writers holding the index's lock (`beetle update`, `beetle reindex`, `beetle doctor --fix`),
+//! runs the migrations above its version in order and then rewrites `meta.json` with
+//! [`FORMAT_VERSION`]. Indexes from a newer beetle are refused with a clear message instead of
+//! failing deep inside tantivy.
+//!
+//! When a change to the tantivy schema makes existing indexes unreadable, bump
+//! [`FORMAT_VERSION`]: older indexes whose schema differs are then recreated empty, keeping their
+//! metadata and update history, and the next `beetle update` rebuilds them from the target path.
+
+use crate::options::IndexingOptions;
+use crate::schema::{index_settings, CodeIndexSchema};
+use crate::storage::{FsStorage, IndexStorageMetadata};
+use std::fs;
+use std::path::Path;
+use tantivy::Index;
+
+/// Layout version written into the `meta.json` of new indexes.
+pub const FORMAT_VERSION: u32 = 1;
+
+struct Migration {
+    /// The version an index has once the migration has run.
+    version: u32,
+    description: &'static str,
+    apply: fn(&Path) -> Result<(), String>,
+}
+
+const MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "rename metadata.json to meta.json and tantivy_index/ to index/",
+    apply: rename_legacy_layout,
+}];
+
+pub(crate) const LEGACY_META_JSON_FILE_NAME: &str = "metadata.json";
+const LEGACY_INDEX_DIR_NAME: &str = "tantivy_index";
+
+/// The metadata of the index directory `index_dir` without changing it. An index in an older
+/// layout has its `format_version`, and its directory as `index_path`.
+pub fn read(index_dir: &Path) -> Result<IndexStorageMetadata, String> {
+    let index_name = index_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let metadata_path = index_dir.join(FsStorage::META_JSON_FILE_NAME);
+    let legacy_metadata_path = index_dir.join(LEGACY_META_JSON_FILE_NAME);
+    let mut metadata = if metadata_path.exists() {
+        read_metadata(&metadata_path)?
+    } else if legacy_metadata_path.exists() {
+        read_metadata(&legacy_metadata_path)?
+    } else {
+        return Err(format!(
+            "Metadata file does not exist for index {index_name}"
+        ));
+    };
+
+    let version = metadata.format_version;
+    if version > FORMAT_VERSION {
+        return Err(format!(
+            "Index {index_name} was written by a newer beetle (format version {version}, this \
+             build supports up to {FORMAT_VERSION}); upgrade beetle to use it"
+        ));
+    }
+    if version < FORMAT_VERSION {
+        metadata.index_path = dunce::canonicalize(index_dir)
+            .unwrap_or_else(|_| index_dir.to_path_buf())
+            .to_string_lossy()
+            .to_string();
+    }
+
+    Ok(metadata)
+}
+
+/// Whether the index of `metadata` has to be migrated before it can be opened.
+pub fn needs_migration(metadata: &IndexStorageMetadata) -> bool {
+    metadata.format_version < FORMAT_VERSION
+}
+
+/// Fails for an index that has to be migrated, telling how to migrate it.
+pub(crate) fn check_migrated(metadata: &IndexStorageMetadata) -> Result<(), String> {
+    if !needs_migration(metadata) {
+        return Ok(());
+    }
+
+    let index_name = &metadata.index_name;
+    Err(format!(
+        "Index {index_name} needs migration from format version {} to {FORMAT_VERSION}; run \
+         `beetle update --index {index_name}` to migrate it",
+        metadata.format_version
+    ))
+}
+
+/// Brings the index directory `index_dir` up to [`FORMAT_VERSION`] and returns its metadata.
+/// Migrating rewrites the index, so the caller holds its lock.
+pub fn migrate(index_dir: &Path) -> Result<IndexStorageMetadata, String> {
+    let version = read(index_dir)?.format_version;
+    if version == FORMAT_VERSION {
+        return read(index_dir);
+    }
+    let index_name = index_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let metadata_path = index_dir.join(FsStorage::META_JSON_FILE_NAME);
+
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > version)
+    {
+        (migration.apply)(index_dir).map_err(|e| {
+            format!(
+                "Failed to migrate index {index_name} to format version {}: {e}",
+                migration.version
+            )
+        })?;
+        tracing::info!(
+            "Migrated index {index_name} to format version {}: {}",
+            migration.version,
+            migration.description
+        );
+    }
+    let mut metadata = read_metadata(&metadata_path)?;
+    // Encrypted indexes cannot be read without their key, and all of them were created with
+    // the current schema
+    if metadata.encryption_key_id.is_none()
+        && !has_current_schema(&index_dir.join("index"), &metadata.options)
+    {
+        recreate_index(index_dir, &metadata.options)
+            .map_err(|e| format!("Failed to recreate index {index_name}: {e}"))?;
+        tracing::warn!(
+            "Index {index_name} was built with an older schema and has been emptied; run \
+             `beetle update --index {index_name}` to rebuild it"
+        );
+    }
+
+    metadata.format_version = FORMAT_VERSION;
+    metadata.index_path = dunce::canonicalize(index_dir)
+        .unwrap_or_else(|_| index_dir.to_path_buf())
+        .to_string_lossy()
+        .to_string();
+    write_metadata(&metadata_path, &metadata)?;
+
+    Ok(metadata)
+}
+
+/// Version 1: the files got their current names.
+fn rename_legacy_layout(index_dir: &Path) -> Result<(), String> {
+    for (legacy, current) in [
+        (LEGACY_META_JSON_FILE_NAME, FsStorage::META_JSON_FILE_NAME),
+        (LEGACY_INDEX_DIR_NAME, "index"),
+    ] {
+        let legacy_path = index_dir.join(legacy);
+        let current_path = index_dir.join(current);
+        if legacy_path.exists() && !current_path.exists() {
+            fs::rename(&legacy_path, &current_path)
+                .map_err(|e| format!("Failed to rename {legacy_path:?}: {e}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the tantivy index at `index_path` can be opened with the current schema.
+fn has_current_schema(index_path: &Path, options: &IndexingOptions) -> bool {
+    Index::open_in_dir(index_path)
+        .is_ok_and(|index| index.schema() == CodeIndexSchema::with_options(options).schema)
+}
+
+/// Replaces the tantivy index with an empty one and forgets the indexed files, so the next
+/// update adds every file again.
+fn recreate_index(index_dir: &Path, options: &IndexingOptions) -> Result<(), String> {
+    let index_path = index_dir.join("index");
+    if index_path.exists() {
+        fs::remove_dir_all(&index_path)
+            .map_err(|e| format!("Failed to remove {index_path:?}: {e}"))?;
+    }
+    fs::create_dir_all(&index_path).map_err(|e| format!("Failed to create {index_path:?}: {e}"))?;
+    Index::builder()
+        .schema(CodeIndexSchema::with_options(options).schema)
+        .settings(index_settings(options.content_storage))
+        .create_in_dir(&index_path)
+        .map_err(|e| e.to_string())?;
+
+    let snapshot_path = index_dir.join(FsStorage::FILE_INDEX_SNAPSHOT_FILE_NAME);
+    if snapshot_path.exists() {
+        fs::remove_file(&snapshot_path)
+            .map_err(|e| format!("Failed to remove {snapshot_path:?}: {e}"))?;
+    }
+
+    Ok(())
+}
+
+fn read_metadata(metadata_path: &Path) -> Result<IndexStorageMetadata, String> {
+    let metadata_json = fs::read_to_string(metadata_path)
+        .map_err(|e| format!("Failed to read metadata file: {e}"))?;
+
+    serde_json::from_str(&metadata_json).map_err(|e| format!("Failed to parse metadata JSON: {e}"))
+}
+
+/// Written next to `meta.json` and renamed over it, so an interrupted migration keeps the old one.
+fn write_metadata(metadata_path: &Path, metadata: &IndexStorageMetadata) -> Result<(), String> {
+    let metadata_json = serde_json::to_string(metadata)
+        .map_err(|e| format!("Failed to serialize metadata: {e}"))?;
+    let temp_path = metadata_path.with_extension("json.tmp");
+    fs::write(&temp_path, metadata_json)
+        .map_err(|e| format!("Failed to write metadata file {temp_path:?}: {e}"))?;
+
+    fs::rename(&temp_path, metadata_path)
+        .map_err(|e| format!("Failed to write metadata file {metadata_path:?}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_layout_is_migrated() {
+        let home = tempfile::tempdir().unwrap();
+        let index_dir = home.path().join("legacy");
+        let legacy_index_path = index_dir.join(LEGACY_INDEX_DIR_NAME);
+        fs::create_dir_all(&legacy_index_path).unwrap();
+        Index::create_in_dir(&legacy_index_path, CodeIndexSchema::new().schema).unwrap();
+        fs::write(
+            index_dir.join(LEGACY_META_JSON_FILE_NAME),
+            r#"{"index_name":"legacy","index_path":"/old/legacy","target_path":"/src"}"#,
+        )
+        .unwrap();
+        fs::write(index_dir.join(FsStorage::FILE_INDEX_SNAPSHOT_FILE_NAME), "").unwrap();
+
+        // Reading leaves the index as it is
+        let metadata = read(&index_dir).unwrap();
+        assert!(needs_migration(&metadata));
+        assert_eq!(metadata.target_path, "/src");
+        assert!(check_migrated(&metadata).is_err());
+        assert!(index_dir.join(LEGACY_META_JSON_FILE_NAME).exists());
+        assert!(!index_dir.join("index").exists());
+
+        let metadata = migrate(&index_dir).unwrap();
+        assert_eq!(metadata.format_version, FORMAT_VERSION);
+        assert_eq!(metadata.target_path, "/src");
+        assert!(index_dir.join("index").join("meta.json").exists());
+        assert!(!index_dir.join(LEGACY_META_JSON_FILE_NAME).exists());
+        // The schema did not change, so the indexed files are kept
+        assert!(index_dir
+            .join(FsStorage::FILE_INDEX_SNAPSHOT_FILE_NAME)
+            .exists());
+
+        let persisted = read_metadata(&index_dir.join(FsStorage::META_JSON_FILE_NAME)).unwrap();
+        assert_eq!(persisted.format_version, FORMAT_VERSION);
+    }
+
+    #[test]
+    fn test_newer_format_is_refused() {
+        let home = tempfile::tempdir().unwrap();
+        let index_dir = home.path().join("future");
+        fs::create_dir_all(&index_dir).unwrap();
+        fs::write(
+            index_dir.join(FsStorage::META_JSON_FILE_NAME),
+            format!(
+                r#"{{"index_name":"future","index_path":"","target_path":"/src","format_version":{}}}"#,
+                FORMAT_VERSION + 1
+            ),
+        )
+        .unwrap();
+
+        let error = read(&index_dir).map(|_| ()).unwrap_err();
+        assert!(error.contains("newer beetle"), "{error}");
+        let error = migrate(&index_dir).map(|_| ()).unwrap_err();
+        assert!(error.contains("newer beetle"), "{error}");
+    }
+}
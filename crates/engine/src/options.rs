@@ -0,0 +1,1214 @@
+use crate::usage::total_memory_bytes;
+use std::path::Path;
+
+/// Options chosen when an index is created and applied by every scan of its target path.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct IndexingOptions {
+    /// Extensions (without the leading dot) to index; empty means every extension.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub only_extensions: Vec<String>,
+    /// Bytes of each file that are indexed; the rest of larger files is left out. Files are
+    /// indexed whole when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_file_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "ContentStorage::is_default")]
+    pub content_storage: ContentStorage,
+    #[serde(default, skip_serializing_if = "ContentTokenizer::is_default")]
+    pub tokenizer: ContentTokenizer,
+    #[serde(default, skip_serializing_if = "SchemaProfile::is_default")]
+    pub profile: SchemaProfile,
+    /// How the words of prose are filtered, for the docs and logs profiles.
+    #[serde(default, skip_serializing_if = "AnalyzerOptions::is_default")]
+    pub analyzer: AnalyzerOptions,
+    /// Ranking weights of the fields, which searches can override.
+    #[serde(default, skip_serializing_if = "FieldBoosts::is_default")]
+    pub boosts: FieldBoosts,
+    /// How the segments written by updates are merged in the background.
+    #[serde(default, skip_serializing_if = "MergePolicyOptions::is_default")]
+    pub merge_policy: MergePolicyOptions,
+    /// Whether the index files are encrypted at rest, see [`crate::encryption`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub encrypted: bool,
+    /// Whether files are read as UTF-8 only, instead of transcoded from the encoding they are
+    /// detected in, see [`crate::encoding`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub utf8_only: bool,
+    /// Whether files with the same indexed content as another are indexed once, under the first
+    /// path, and the others listed as its duplicates.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dedup: bool,
+    /// Whether the documents of files committed to git carry the author and hash of the last
+    /// commit that touched them, read with the `git` executable, see [`crate::git`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub git_blame: bool,
+    /// Whether the files to index are those git tracks under the target path, listed again by
+    /// every scan, instead of every file found walking it, see [`crate::git::tracked_files`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub tracked_only: bool,
+    /// Whether walks of the target path index hidden files and directories, those whose name
+    /// starts with a dot, which they skip by default.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+    /// Whether walks of the target path disregard the global gitignore of git, the
+    /// `core.excludesFile` of the user's config, which they respect by default.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_global_gitignore: bool,
+    /// Whether walks of the target path disregard the `.git/info/exclude` of its repository,
+    /// which they respect by default.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_git_exclude: bool,
+    /// What becomes of the files of the git submodules under the target path.
+    #[serde(default, skip_serializing_if = "SubmodulePolicy::is_default")]
+    pub submodules: SubmodulePolicy,
+    /// Branches whose committed files are indexed, each document carrying its branch, instead of
+    /// the files of the target path; empty to index the target path. See
+    /// [`crate::writter::IndexWriter::index`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub branches: Vec<String>,
+    /// Whether the index has a companion index of the commit history of the target path, with
+    /// the [`SchemaProfile::Commits`] profile, created along with it and brought up to date by
+    /// each of its updates. See [`crate::commits_index_name`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub commits: bool,
+    /// The git URL the target path is a shallow clone of, pulled by every update before it
+    /// indexes; `None` for a directory beetle does not manage. See [`crate::git::pull_repository`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    /// Files to index, relative to the target path, instead of every file found walking it,
+    /// e.g. those of `git ls-files`; empty to walk the target path.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+    #[serde(flatten)]
+    pub writer: WriterResources,
+}
+
+/// Memory, threads and batches of the writer that indexes files. Values left unset fall back to
+/// defaults sized for the machine, see [`WriterResources::resolve`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct WriterResources {
+    /// Memory the writer buffers documents in before flushing a segment, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_budget: Option<usize>,
+    /// Threads indexing documents in parallel.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index_threads: Option<usize>,
+    /// Files indexed in a batch, which progress and timings are reported for; 100 by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch_size: Option<usize>,
+    /// Batches between intermediate commits, so that an interrupted update resumes after the
+    /// last of them instead of starting over; updates only commit at the end when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_every: Option<usize>,
+}
+
+impl WriterResources {
+    /// Smallest share of the budget tantivy accepts for a thread, with some headroom.
+    pub const MIN_MEMORY_PER_THREAD: usize = 32 * 1024 * 1024;
+    /// Largest share of the budget tantivy accepts for a thread.
+    pub const MAX_MEMORY_PER_THREAD: usize = 4094 * 1024 * 1024;
+    /// Default share of the budget of a thread; more rarely speeds indexing up.
+    const DEFAULT_MAX_MEMORY_PER_THREAD: usize = 1024 * 1024 * 1024;
+    /// Tantivy gains little from more indexing threads.
+    const DEFAULT_MAX_THREADS: usize = 8;
+    pub const DEFAULT_BATCH_SIZE: usize = 100;
+
+    /// These resources, with the unset ones taken from `fallback`.
+    pub fn or(self, fallback: WriterResources) -> Self {
+        WriterResources {
+            memory_budget: self.memory_budget.or(fallback.memory_budget),
+            index_threads: self.index_threads.or(fallback.index_threads),
+            batch_size: self.batch_size.or(fallback.batch_size),
+            commit_every: self.commit_every.or(fallback.commit_every),
+        }
+    }
+
+    /// Returns the memory budget and thread count to use, filling in unset values: one thread
+    /// per core up to 8, but no more than the budget can feed, and an eighth of the machine's
+    /// memory, between 32 MB and 1 GB per thread.
+    pub fn resolve(&self) -> Result<(usize, usize), String> {
+        if self.batch_size == Some(0) {
+            return Err("The batch size must be at least 1 file".into());
+        }
+        if self.commit_every == Some(0) {
+            return Err("Intermediate commits must be at least 1 batch apart".into());
+        }
+        let index_threads = match (self.index_threads, self.memory_budget) {
+            (Some(0), _) => return Err("The number of index threads must be at least 1".into()),
+            (Some(index_threads), _) => index_threads,
+            (None, memory_budget) => {
+                let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+                let fed = memory_budget.map_or(usize::MAX, |budget| {
+                    (budget / Self::MIN_MEMORY_PER_THREAD).max(1)
+                });
+                cores.min(Self::DEFAULT_MAX_THREADS).min(fed)
+            }
+        };
+
+        let memory_budget = self.memory_budget.unwrap_or_else(|| {
+            // Assume a small machine when its memory is unknown
+            let total = total_memory_bytes().map_or(2 * 1024 * 1024 * 1024, |total| {
+                usize::try_from(total).unwrap_or(usize::MAX)
+            });
+            (total / 8).clamp(
+                index_threads * Self::MIN_MEMORY_PER_THREAD,
+                index_threads * Self::DEFAULT_MAX_MEMORY_PER_THREAD,
+            )
+        });
+
+        let per_thread = memory_budget / index_threads;
+        if per_thread < Self::MIN_MEMORY_PER_THREAD {
+            return Err(format!(
+                "A memory budget of {} MB is too small for {index_threads} index threads, which \
+                 need at least {} MB each; raise the memory budget or lower the index threads",
+                memory_budget / (1024 * 1024),
+                Self::MIN_MEMORY_PER_THREAD / (1024 * 1024)
+            ));
+        }
+        if per_thread > Self::MAX_MEMORY_PER_THREAD {
+            return Err(format!(
+                "A memory budget of {} MB is too large for {index_threads} index threads, which \
+                 can use at most {} MB each; lower the memory budget or raise the index threads",
+                memory_budget / (1024 * 1024),
+                Self::MAX_MEMORY_PER_THREAD / (1024 * 1024)
+            ));
+        }
+
+        Ok((memory_budget, index_threads))
+    }
+}
+
+/// How the content of indexed files is kept in the index. The search terms are always indexed;
+/// this only decides what is needed to show snippets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ContentStorage {
+    /// Stored with fast LZ4 compression; the index takes about as much space as the sources.
+    #[default]
+    Lz4,
+    /// Stored with stronger zstd compression in larger blocks: a smaller index, slightly slower
+    /// snippets and commits.
+    Zstd,
+    /// Not stored: the smallest index. Snippets and symbols are read from the files at search
+    /// time, so they reflect the files as they are now; ingested documents get none, and moved
+    /// files are indexed again instead of being recognised as renames.
+    None,
+}
+
+impl ContentStorage {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl std::str::FromStr for ContentStorage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lz4" => Ok(Self::Lz4),
+            "zstd" => Ok(Self::Zstd),
+            "none" => Ok(Self::None),
+            _ => Err(format!(
+                "Invalid content storage '{s}'. Use 'lz4', 'zstd' or 'none'"
+            )),
+        }
+    }
+}
+
+/// What becomes of the files of the git submodules of a repository, whether or not they are
+/// checked out. Submodules are those git records in the index of the repository (`git ls-files`
+/// entries of mode 160000), not any nested repository.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SubmodulePolicy {
+    /// Indexed along with the files of the repository: walks descend into checked out
+    /// submodules, and `--tracked-only` lists their files with `--recurse-submodules`.
+    #[default]
+    Index,
+    /// Left out, like ignored directories.
+    Skip,
+    /// Left out, and each checked out submodule indexed in an index of its own, created and
+    /// brought up to date by the updates of the index. See [`crate::submodule_index_name`].
+    Separate,
+}
+
+impl SubmodulePolicy {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl std::str::FromStr for SubmodulePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "index" => Ok(Self::Index),
+            "skip" => Ok(Self::Skip),
+            "separate" => Ok(Self::Separate),
+            _ => Err(format!(
+                "Invalid submodule policy '{s}'. Use 'index', 'skip' or 'separate'"
+            )),
+        }
+    }
+}
+
+/// How an update tells which indexed files changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChangeDetection {
+    /// Files whose size or modification time changed: no file is read, but files whose times
+    /// change without their content (a git checkout, a copy) are indexed again.
+    Fast,
+    /// Files whose size or content changed: every file of the same size is read and hashed,
+    /// which also catches changes that keep the modification time.
+    Hash,
+    /// Files whose size changed, or whose modification time changed along with their content:
+    /// only files with a new time and the same size are read and hashed.
+    #[default]
+    Auto,
+}
+
+impl std::str::FromStr for ChangeDetection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fast" => Ok(Self::Fast),
+            "hash" => Ok(Self::Hash),
+            "auto" => Ok(Self::Auto),
+            _ => Err(format!(
+                "Invalid change detection '{s}'. Use 'fast', 'hash' or 'auto'"
+            )),
+        }
+    }
+}
+
+/// How the content of indexed files is split into search terms.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ContentTokenizer {
+    /// The words of the index's [`SchemaProfile`]; for code, identifiers are also split into
+    /// their camelCase and snake_case parts.
+    #[default]
+    Code,
+    /// Every run of `min_gram` to `max_gram` characters, lowercased: queries match anywhere
+    /// inside words, at the cost of a much larger index.
+    Ngram { min_gram: usize, max_gram: usize },
+}
+
+impl ContentTokenizer {
+    /// Longest n-grams accepted; longer ones make the index explode without helping searches.
+    pub const MAX_GRAM: usize = 8;
+    const DEFAULT_GRAM: usize = 3;
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Self::Ngram { min_gram, max_gram }
+                if min_gram == 0 || min_gram > max_gram || max_gram > Self::MAX_GRAM =>
+            {
+                Err(format!(
+                    "Invalid n-gram sizes {min_gram}-{max_gram}: they must be from 1 to {} \
+                     and the minimum may not exceed the maximum",
+                    Self::MAX_GRAM
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl std::str::FromStr for ContentTokenizer {
+    type Err = String;
+
+    /// Parses `code`, `ngram` (trigrams), `ngram:N` or `ngram:MIN-MAX`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!("Invalid tokenizer '{s}'. Use 'code', 'ngram', 'ngram:N' or 'ngram:MIN-MAX'")
+        };
+        let (min_gram, max_gram) = match s.split_once(':') {
+            None if s == "code" => return Ok(Self::Code),
+            None if s == "ngram" => (Self::DEFAULT_GRAM, Self::DEFAULT_GRAM),
+            Some(("ngram", sizes)) => {
+                let (min_gram, max_gram) = sizes.split_once('-').unwrap_or((sizes, sizes));
+                (
+                    min_gram.trim().parse().map_err(|_| invalid())?,
+                    max_gram.trim().parse().map_err(|_| invalid())?,
+                )
+            }
+            _ => return Err(invalid()),
+        };
+        let tokenizer = Self::Ngram { min_gram, max_gram };
+        tokenizer.validate()?;
+
+        Ok(tokenizer)
+    }
+}
+
+impl std::fmt::Display for ContentTokenizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Code => write!(f, "code"),
+            Self::Ngram { min_gram, max_gram } if min_gram == max_gram => {
+                write!(f, "ngram:{min_gram}")
+            }
+            Self::Ngram { min_gram, max_gram } => write!(f, "ngram:{min_gram}-{max_gram}"),
+        }
+    }
+}
+
+/// What kind of files an index is made for. The profile decides how content is split into words
+/// and which metadata fields are extracted next to it; it is part of the index's schema.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaProfile {
+    /// Source code: identifiers are split into their parts and definition names are indexed in
+    /// `symbols`.
+    #[default]
+    Code,
+    /// Prose such as Markdown: English words are stemmed, so `indexing` matches `indexes`, and
+    /// the first heading is indexed and stored as `title`.
+    Docs,
+    /// Log files: lowercased words, with the severity levels found (`error`, `warn`, ...)
+    /// indexed as `level` and the number of lines stored as `line_count`.
+    Logs,
+    /// The commit history of a git repository instead of files: each document is a commit,
+    /// with its message as content and English words stemmed like docs, its author and hash in
+    /// the fields of [`IndexingOptions::git_blame`], and the paths it touched as `files`.
+    Commits,
+}
+
+impl SchemaProfile {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl std::str::FromStr for SchemaProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "code" => Ok(Self::Code),
+            "docs" => Ok(Self::Docs),
+            "logs" => Ok(Self::Logs),
+            "commits" => Ok(Self::Commits),
+            _ => Err(format!(
+                "Invalid schema profile '{s}'. Use 'code', 'docs', 'logs' or 'commits'"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for SchemaProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Code => write!(f, "code"),
+            Self::Docs => write!(f, "docs"),
+            Self::Logs => write!(f, "logs"),
+            Self::Commits => write!(f, "commits"),
+        }
+    }
+}
+
+/// How the words of docs and logs indexes are filtered before they are indexed and searched.
+/// Unset options keep the profile's behavior: words are lowercased, and stemmed as English for
+/// the docs profile.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AnalyzerOptions {
+    /// Whether words are lowercased, so that searches ignore case.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lowercase: Option<bool>,
+    /// The language words are reduced to their stem in, so that `indexing` matches `indexes`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stemming: Option<Stemming>,
+    /// Words left out of the index and of queries, e.g. `the` or `and`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop_words: Vec<String>,
+}
+
+impl AnalyzerOptions {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// These options with the unset ones filled in from the defaults of `profile`.
+    pub fn resolve(&self, profile: SchemaProfile) -> Self {
+        AnalyzerOptions {
+            lowercase: Some(self.lowercase.unwrap_or(true)),
+            stemming: Some(self.stemming.unwrap_or(match profile {
+                SchemaProfile::Docs | SchemaProfile::Commits => Stemming::English,
+                SchemaProfile::Code | SchemaProfile::Logs => Stemming::None,
+            })),
+            stop_words: self.stop_words.clone(),
+        }
+    }
+
+    /// Checks that the options apply to indexes created with `profile` and `tokenizer`: code is
+    /// split into identifiers and n-grams into characters, neither into words.
+    pub fn validate(
+        &self,
+        profile: SchemaProfile,
+        tokenizer: ContentTokenizer,
+    ) -> Result<(), String> {
+        if self.is_default() {
+            return Ok(());
+        }
+        if profile == SchemaProfile::Code || !tokenizer.is_default() {
+            return Err(
+                "Lowercasing, stemming and stop words only apply to the docs and logs profiles \
+                 with the default tokenizer"
+                    .to_string(),
+            );
+        }
+        if self.stop_words.iter().any(|word| word.trim().is_empty()) {
+            return Err("Stop words may not be empty".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// The language of a stemmer, or none to index words as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Stemming {
+    None,
+    Arabic,
+    Danish,
+    Dutch,
+    English,
+    Finnish,
+    French,
+    German,
+    Greek,
+    Hungarian,
+    Italian,
+    Norwegian,
+    Portuguese,
+    Romanian,
+    Russian,
+    Spanish,
+    Swedish,
+    Tamil,
+    Turkish,
+}
+
+impl Stemming {
+    const ALL: [Stemming; 19] = [
+        Self::None,
+        Self::Arabic,
+        Self::Danish,
+        Self::Dutch,
+        Self::English,
+        Self::Finnish,
+        Self::French,
+        Self::German,
+        Self::Greek,
+        Self::Hungarian,
+        Self::Italian,
+        Self::Norwegian,
+        Self::Portuguese,
+        Self::Romanian,
+        Self::Russian,
+        Self::Spanish,
+        Self::Swedish,
+        Self::Tamil,
+        Self::Turkish,
+    ];
+
+    /// The stemmer language of tantivy, `None` when words are not stemmed.
+    pub fn language(self) -> Option<tantivy::tokenizer::Language> {
+        use tantivy::tokenizer::Language;
+        Some(match self {
+            Self::None => return None,
+            Self::Arabic => Language::Arabic,
+            Self::Danish => Language::Danish,
+            Self::Dutch => Language::Dutch,
+            Self::English => Language::English,
+            Self::Finnish => Language::Finnish,
+            Self::French => Language::French,
+            Self::German => Language::German,
+            Self::Greek => Language::Greek,
+            Self::Hungarian => Language::Hungarian,
+            Self::Italian => Language::Italian,
+            Self::Norwegian => Language::Norwegian,
+            Self::Portuguese => Language::Portuguese,
+            Self::Romanian => Language::Romanian,
+            Self::Russian => Language::Russian,
+            Self::Spanish => Language::Spanish,
+            Self::Swedish => Language::Swedish,
+            Self::Tamil => Language::Tamil,
+            Self::Turkish => Language::Turkish,
+        })
+    }
+}
+
+impl std::str::FromStr for Stemming {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|stemming| stemming.to_string() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(Stemming::to_string).collect();
+                format!("Invalid stemming '{s}'. Use one of {}", names.join(", "))
+            })
+    }
+}
+
+impl std::fmt::Display for Stemming {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.language() {
+            Some(language) => write!(f, "{}", format!("{language:?}").to_lowercase()),
+            None => write!(f, "none"),
+        }
+    }
+}
+
+/// How much a match in each field counts when ranking results, relative to the others: a match
+/// in a field boosted by 3 counts three times as much as a match in a field boosted by 1. Unset
+/// fields fall back to the index's boosts, then to the defaults, which rank file name matches
+/// above symbol and path matches, and those above matches in the content.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FieldBoosts {
+    /// The whole path, matched by `path:` queries and prefix phrases.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<f32>,
+    /// The words of the file name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<f32>,
+    /// The names of the definitions in the file, see [`crate::symbols`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbols: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<f32>,
+}
+
+impl FieldBoosts {
+    const DEFAULT_PATH: f32 = 2.0;
+    const DEFAULT_FILE_NAME: f32 = 3.0;
+    const DEFAULT_SYMBOLS: f32 = 2.0;
+    const DEFAULT_CONTENT: f32 = 1.0;
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// These boosts, with the unset ones taken from `fallback`.
+    pub fn or(self, fallback: FieldBoosts) -> Self {
+        FieldBoosts {
+            path: self.path.or(fallback.path),
+            file_name: self.file_name.or(fallback.file_name),
+            symbols: self.symbols.or(fallback.symbols),
+            content: self.content.or(fallback.content),
+        }
+    }
+
+    /// The boost of every field, by schema field name, with unset ones at their default.
+    pub fn resolve(&self) -> [(&'static str, f32); 4] {
+        [
+            ("path", self.path.unwrap_or(Self::DEFAULT_PATH)),
+            (
+                "file_name",
+                self.file_name.unwrap_or(Self::DEFAULT_FILE_NAME),
+            ),
+            ("symbols", self.symbols.unwrap_or(Self::DEFAULT_SYMBOLS)),
+            ("content", self.content.unwrap_or(Self::DEFAULT_CONTENT)),
+        ]
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, boost) in [
+            ("path", self.path),
+            ("filename", self.file_name),
+            ("symbols", self.symbols),
+            ("content", self.content),
+        ] {
+            if let Some(boost) = boost.filter(|boost| !boost.is_finite() || *boost < 0.0) {
+                return Err(format!(
+                    "Invalid boost {boost} for {name}: boosts must be zero or positive numbers"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for FieldBoosts {
+    type Err = String;
+
+    /// Parses comma-separated `FIELD=BOOST` pairs, e.g. `filename=5,content=0.5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut boosts = FieldBoosts::default();
+        for pair in s.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let invalid = || {
+                format!(
+                    "Invalid boost '{pair}'. Use FIELD=BOOST, where FIELD is 'path', \
+                     'filename', 'symbols' or 'content'"
+                )
+            };
+            let (name, boost) = pair.split_once('=').ok_or_else(invalid)?;
+            let boost = Some(boost.trim().parse().map_err(|_| invalid())?);
+            match name.trim() {
+                "path" => boosts.path = boost,
+                "filename" | "file_name" => boosts.file_name = boost,
+                "symbols" => boosts.symbols = boost,
+                "content" => boosts.content = boost,
+                _ => return Err(invalid()),
+            }
+        }
+        boosts.validate()?;
+
+        Ok(boosts)
+    }
+}
+
+impl std::fmt::Display for FieldBoosts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pairs: Vec<_> = [
+            ("path", self.path),
+            ("filename", self.file_name),
+            ("symbols", self.symbols),
+            ("content", self.content),
+        ]
+        .into_iter()
+        .filter_map(|(name, boost)| Some(format!("{name}={}", boost?)))
+        .collect();
+        write!(f, "{}", pairs.join(","))
+    }
+}
+
+/// How the writer merges the segments each commit adds, in the background, following tantivy's
+/// log merge policy: segments are grouped in levels of similar sizes, and a level is merged once
+/// it holds enough segments. Unset values keep tantivy's defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MergePolicyOptions {
+    /// Never merge segments; only a full reindex compacts the index then.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    /// Segments of a level merged together; 8 by default. Fewer merges smaller sets more often.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_segments: Option<usize>,
+    /// Documents above which a segment is no longer merged, which caps the size of merges;
+    /// 10,000,000 by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_docs: Option<usize>,
+    /// Documents under which segments all belong to the first level; 10,000 by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_layer_size: Option<u32>,
+    /// Logarithm of the size ratio between two consecutive levels; 0.75 by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level_log_size: Option<f64>,
+    /// Share of deleted documents in a segment above which its level is merged to purge them,
+    /// between 0 (excluded) and 1; 1 by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deletes_ratio: Option<f32>,
+}
+
+impl MergePolicyOptions {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_segments.is_some_and(|segments| segments < 2) {
+            return Err("Merges must take at least 2 segments".to_string());
+        }
+        if self.max_docs == Some(0) {
+            return Err("The maximum documents of merged segments must be at least 1".to_string());
+        }
+        if let Some(size) = self
+            .level_log_size
+            .filter(|size| !size.is_finite() || *size <= 0.0)
+        {
+            return Err(format!(
+                "Invalid level log size {size}: it must be a positive number"
+            ));
+        }
+        if let Some(ratio) = self
+            .deletes_ratio
+            .filter(|ratio| !(*ratio > 0.0 && *ratio <= 1.0))
+        {
+            return Err(format!(
+                "Invalid deletes ratio {ratio}: it must be above 0 and at most 1"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The tantivy merge policy of these options, which must be valid.
+    pub fn merge_policy(&self) -> Box<dyn tantivy::merge_policy::MergePolicy> {
+        if self.disabled {
+            return Box::new(tantivy::merge_policy::NoMergePolicy);
+        }
+        let mut policy = tantivy::merge_policy::LogMergePolicy::default();
+        if let Some(segments) = self.min_segments {
+            policy.set_min_num_segments(segments);
+        }
+        if let Some(docs) = self.max_docs {
+            policy.set_max_docs_before_merge(docs);
+        }
+        if let Some(size) = self.min_layer_size {
+            policy.set_min_layer_size(size);
+        }
+        if let Some(size) = self.level_log_size {
+            policy.set_level_log_size(size);
+        }
+        if let Some(ratio) = self.deletes_ratio {
+            policy.set_del_docs_ratio_before_merge(ratio);
+        }
+        Box::new(policy)
+    }
+}
+
+impl std::str::FromStr for MergePolicyOptions {
+    type Err = String;
+
+    /// Parses `none`, or comma-separated `KEY=VALUE` pairs, e.g. `min-segments=4,max-docs=1000000`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut options = MergePolicyOptions::default();
+        if s.trim().eq_ignore_ascii_case("none") {
+            options.disabled = true;
+            return Ok(options);
+        }
+        for pair in s.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let invalid = || {
+                format!(
+                    "Invalid merge policy setting '{pair}'. Use KEY=VALUE, where KEY is \
+                     'min-segments', 'max-docs', 'min-layer-size', 'level-log-size' or \
+                     'deletes-ratio', or 'none' to disable merges"
+                )
+            };
+            let (key, value) = pair.split_once('=').ok_or_else(invalid)?;
+            let value = value.trim();
+            match key.trim() {
+                "min-segments" => {
+                    options.min_segments = Some(value.parse().map_err(|_| invalid())?)
+                }
+                "max-docs" => options.max_docs = Some(value.parse().map_err(|_| invalid())?),
+                "min-layer-size" => {
+                    options.min_layer_size = Some(value.parse().map_err(|_| invalid())?)
+                }
+                "level-log-size" => {
+                    options.level_log_size = Some(value.parse().map_err(|_| invalid())?)
+                }
+                "deletes-ratio" => {
+                    options.deletes_ratio = Some(value.parse().map_err(|_| invalid())?)
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        options.validate()?;
+
+        Ok(options)
+    }
+}
+
+impl std::fmt::Display for MergePolicyOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.disabled {
+            return write!(f, "none");
+        }
+        let pairs: Vec<_> = [
+            (
+                "min-segments",
+                self.min_segments.map(|value| value.to_string()),
+            ),
+            ("max-docs", self.max_docs.map(|value| value.to_string())),
+            (
+                "min-layer-size",
+                self.min_layer_size.map(|value| value.to_string()),
+            ),
+            (
+                "level-log-size",
+                self.level_log_size.map(|value| value.to_string()),
+            ),
+            (
+                "deletes-ratio",
+                self.deletes_ratio.map(|value| value.to_string()),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some(format!("{key}={}", value?)))
+        .collect();
+        write!(f, "{}", pairs.join(","))
+    }
+}
+
+impl IndexingOptions {
+    /// Normalizes user-provided extensions: leading dots are stripped and case is folded.
+    pub fn with_only_extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.only_extensions = extensions
+            .into_iter()
+            .map(|ext| ext.as_ref().trim().trim_start_matches('.').to_lowercase())
+            .filter(|ext| !ext.is_empty())
+            .collect();
+        self
+    }
+
+    pub fn with_max_file_size(mut self, max_file_size: Option<u64>) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    pub fn with_content_storage(mut self, content_storage: ContentStorage) -> Self {
+        self.content_storage = content_storage;
+        self
+    }
+
+    pub fn with_tokenizer(mut self, tokenizer: ContentTokenizer) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    pub fn with_profile(mut self, profile: SchemaProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_analyzer(mut self, analyzer: AnalyzerOptions) -> Self {
+        self.analyzer = analyzer;
+        self
+    }
+
+    pub fn with_boosts(mut self, boosts: FieldBoosts) -> Self {
+        self.boosts = boosts;
+        self
+    }
+
+    pub fn with_merge_policy(mut self, merge_policy: MergePolicyOptions) -> Self {
+        self.merge_policy = merge_policy;
+        self
+    }
+
+    pub fn with_writer_resources(mut self, writer: WriterResources) -> Self {
+        self.writer = writer;
+        self
+    }
+
+    pub fn with_encryption(mut self, encrypted: bool) -> Self {
+        self.encrypted = encrypted;
+        self
+    }
+
+    pub fn with_utf8_only(mut self, utf8_only: bool) -> Self {
+        self.utf8_only = utf8_only;
+        self
+    }
+
+    pub fn with_dedup(mut self, dedup: bool) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    pub fn with_git_blame(mut self, git_blame: bool) -> Self {
+        self.git_blame = git_blame;
+        self
+    }
+
+    pub fn with_tracked_only(mut self, tracked_only: bool) -> Self {
+        self.tracked_only = tracked_only;
+        self
+    }
+
+    pub fn with_hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    pub fn with_no_global_gitignore(mut self, no_global_gitignore: bool) -> Self {
+        self.no_global_gitignore = no_global_gitignore;
+        self
+    }
+
+    pub fn with_no_git_exclude(mut self, no_git_exclude: bool) -> Self {
+        self.no_git_exclude = no_git_exclude;
+        self
+    }
+
+    /// Whether walks of the target path differ from those of the defaults: they index hidden
+    /// files, or disregard some ignore files.
+    pub fn tunes_walks(&self) -> bool {
+        self.hidden || self.no_global_gitignore || self.no_git_exclude
+    }
+
+    pub fn with_submodules(mut self, submodules: SubmodulePolicy) -> Self {
+        self.submodules = submodules;
+        self
+    }
+
+    pub fn with_branches(mut self, branches: Vec<String>) -> Self {
+        self.branches = branches;
+        self
+    }
+
+    pub fn with_commits(mut self, commits: bool) -> Self {
+        self.commits = commits;
+        self
+    }
+
+    pub fn with_remote(mut self, remote: Option<String>) -> Self {
+        self.remote = remote;
+        self
+    }
+
+    /// Indexes only `files`, relative to the target path or absolute inside it, instead of
+    /// walking the target path; the index keeps them relative, see [`IndexingOptions::files`].
+    pub fn with_files(mut self, files: Vec<String>) -> Self {
+        self.files = files;
+        self
+    }
+
+    pub fn includes(&self, path: &Path) -> bool {
+        if self.only_extensions.is_empty() {
+            return true;
+        }
+
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                self.only_extensions
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(ext))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_extensions() {
+        let options = IndexingOptions::default().with_only_extensions([".RS", "toml", " md", ""]);
+
+        assert_eq!(options.only_extensions, vec!["rs", "toml", "md"]);
+        assert!(options.includes(Path::new("src/main.rs")));
+        assert!(options.includes(Path::new("Cargo.TOML")));
+        assert!(!options.includes(Path::new("build.sh")));
+        assert!(!options.includes(Path::new("Makefile")));
+        assert!(IndexingOptions::default().includes(Path::new("Makefile")));
+    }
+
+    #[test]
+    fn test_content_storage() {
+        assert_eq!("zstd".parse(), Ok(ContentStorage::Zstd));
+        assert!("gzip".parse::<ContentStorage>().is_err());
+
+        // Indexes created before the option existed keep the default
+        let options: IndexingOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options.content_storage, ContentStorage::Lz4);
+        assert_eq!(serde_json::to_string(&options).unwrap(), "{}");
+
+        let options = IndexingOptions::default().with_content_storage(ContentStorage::None);
+        assert_eq!(
+            serde_json::to_string(&options).unwrap(),
+            r#"{"content_storage":"none"}"#
+        );
+    }
+
+    #[test]
+    fn test_content_tokenizer() {
+        let ngram = |min_gram, max_gram| ContentTokenizer::Ngram { min_gram, max_gram };
+        assert_eq!("code".parse(), Ok(ContentTokenizer::Code));
+        assert_eq!("ngram".parse(), Ok(ngram(3, 3)));
+        assert_eq!("ngram:2".parse(), Ok(ngram(2, 2)));
+        assert_eq!("ngram:2-4".parse(), Ok(ngram(2, 4)));
+        for invalid in ["ngram:0", "ngram:4-2", "ngram:3-9", "ngram:x", "trigram"] {
+            assert!(invalid.parse::<ContentTokenizer>().is_err(), "{invalid}");
+        }
+        assert_eq!(ngram(2, 4).to_string(), "ngram:2-4");
+        assert_eq!(ngram(3, 3).to_string(), "ngram:3");
+
+        let options = IndexingOptions::default().with_tokenizer(ngram(2, 3));
+        let json = serde_json::to_string(&options).unwrap();
+        assert_eq!(
+            json,
+            r#"{"tokenizer":{"ngram":{"min_gram":2,"max_gram":3}}}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<IndexingOptions>(&json).unwrap(),
+            options
+        );
+    }
+
+    #[test]
+    fn test_schema_profile() {
+        assert_eq!("docs".parse(), Ok(SchemaProfile::Docs));
+        assert!("prose".parse::<SchemaProfile>().is_err());
+        assert_eq!(SchemaProfile::Logs.to_string(), "logs");
+
+        let options = IndexingOptions::default().with_profile(SchemaProfile::Logs);
+        assert_eq!(
+            serde_json::to_string(&options).unwrap(),
+            r#"{"profile":"logs"}"#
+        );
+        let options: IndexingOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options.profile, SchemaProfile::Code);
+    }
+
+    #[test]
+    fn test_analyzer_options() {
+        assert_eq!("french".parse(), Ok(Stemming::French));
+        assert_eq!("none".parse(), Ok(Stemming::None));
+        assert!("klingon".parse::<Stemming>().is_err());
+        assert_eq!(Stemming::Portuguese.to_string(), "portuguese");
+
+        let analyzer = AnalyzerOptions {
+            lowercase: Some(false),
+            stop_words: vec!["the".to_string()],
+            ..AnalyzerOptions::default()
+        };
+        assert_eq!(
+            analyzer.resolve(SchemaProfile::Docs),
+            AnalyzerOptions {
+                lowercase: Some(false),
+                stemming: Some(Stemming::English),
+                stop_words: vec!["the".to_string()],
+            }
+        );
+        assert!(analyzer
+            .validate(SchemaProfile::Logs, ContentTokenizer::Code)
+            .is_ok());
+        assert!(analyzer
+            .validate(SchemaProfile::Code, ContentTokenizer::Code)
+            .is_err());
+        assert!(AnalyzerOptions::default()
+            .validate(SchemaProfile::Code, ContentTokenizer::Code)
+            .is_ok());
+
+        let options = IndexingOptions::default().with_analyzer(analyzer);
+        assert_eq!(
+            serde_json::to_string(&options).unwrap(),
+            r#"{"analyzer":{"lowercase":false,"stop_words":["the"]}}"#
+        );
+    }
+
+    #[test]
+    fn test_field_boosts() {
+        let boosts: FieldBoosts = "filename=5, content=0.5".parse().unwrap();
+        assert_eq!(boosts.file_name, Some(5.0));
+        assert_eq!(boosts.content, Some(0.5));
+        assert_eq!(boosts.path, None);
+        assert_eq!(boosts.to_string(), "filename=5,content=0.5");
+        for invalid in ["title=2", "path", "path=x", "path=-1", "path=inf"] {
+            assert!(invalid.parse::<FieldBoosts>().is_err(), "{invalid}");
+        }
+
+        // Per-query boosts override the index's, which override the defaults
+        let index_boosts: FieldBoosts = "path=4,symbols=1".parse().unwrap();
+        let resolved = boosts.or(index_boosts).resolve();
+        assert_eq!(
+            resolved,
+            [
+                ("path", 4.0),
+                ("file_name", 5.0),
+                ("symbols", 1.0),
+                ("content", 0.5)
+            ]
+        );
+
+        let options = IndexingOptions::default().with_boosts("symbols=4".parse().unwrap());
+        assert_eq!(
+            serde_json::to_string(&options).unwrap(),
+            r#"{"boosts":{"symbols":4.0}}"#
+        );
+        let options: IndexingOptions = serde_json::from_str("{}").unwrap();
+        assert!(options.boosts.is_default());
+    }
+
+    #[test]
+    fn test_merge_policy_options() {
+        let options: MergePolicyOptions = "min-segments=4, max-docs=100000".parse().unwrap();
+        assert_eq!(options.min_segments, Some(4));
+        assert_eq!(options.max_docs, Some(100_000));
+        assert_eq!(options.deletes_ratio, None);
+        assert_eq!(options.to_string(), "min-segments=4,max-docs=100000");
+        assert!("none".parse::<MergePolicyOptions>().unwrap().disabled);
+        for invalid in [
+            "segments=4",
+            "max-docs",
+            "min-segments=1",
+            "max-docs=0",
+            "level-log-size=-1",
+            "deletes-ratio=0",
+            "deletes-ratio=1.5",
+        ] {
+            assert!(invalid.parse::<MergePolicyOptions>().is_err(), "{invalid}");
+        }
+
+        let options =
+            IndexingOptions::default().with_merge_policy("deletes-ratio=0.25".parse().unwrap());
+        assert_eq!(
+            serde_json::to_string(&options).unwrap(),
+            r#"{"merge_policy":{"deletes_ratio":0.25}}"#
+        );
+        let options: IndexingOptions = serde_json::from_str("{}").unwrap();
+        assert!(options.merge_policy.is_default());
+    }
+
+    #[test]
+    fn test_writer_resources() {
+        const MB: usize = 1024 * 1024;
+        let resources = |memory_budget, index_threads| WriterResources {
+            memory_budget,
+            index_threads,
+            ..WriterResources::default()
+        };
+
+        let (memory_budget, index_threads) = resources(None, None).resolve().unwrap();
+        assert!((1..=8).contains(&index_threads));
+        assert!(memory_budget / index_threads >= WriterResources::MIN_MEMORY_PER_THREAD);
+        assert!(memory_budget / index_threads <= 1024 * MB);
+
+        // A small budget is not spread over more threads than it can feed
+        let (_, index_threads) = resources(Some(64 * MB), None).resolve().unwrap();
+        assert!(index_threads <= 2);
+        assert_eq!(
+            resources(Some(256 * MB), Some(2)).resolve(),
+            Ok((256 * MB, 2))
+        );
+        assert!(resources(Some(64 * MB), Some(4)).resolve().is_err());
+        assert!(resources(Some(16 * 1024 * MB), Some(2)).resolve().is_err());
+        assert!(resources(None, Some(0)).resolve().is_err());
+        let batches = |batch_size, commit_every| WriterResources {
+            batch_size,
+            commit_every,
+            ..resources(Some(256 * MB), Some(2))
+        };
+        assert!(batches(Some(500), Some(10)).resolve().is_ok());
+        assert!(batches(Some(0), None).resolve().is_err());
+        assert!(batches(None, Some(0)).resolve().is_err());
+
+        // Overrides win over the index's own resources
+        let merged = resources(None, Some(2)).or(resources(Some(100 * MB), Some(4)));
+        assert_eq!(merged, resources(Some(100 * MB), Some(2)));
+
+        let options: IndexingOptions =
+            serde_json::from_str(r#"{"memory_budget":1048576000}"#).unwrap();
+        assert_eq!(options.writer, resources(Some(1000 * MB), None));
+        assert_eq!(
+            serde_json::to_string(&options).unwrap(),
+            r#"{"memory_budget":1048576000}"#
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! The form of the file paths beetle stores: the target paths of indexes, the paths of their file
+//! snapshots, and the `path` of their documents, which updates delete documents by.
+//!
+//! Stored paths are absolute and canonical, with symbolic links and `.` or `..` resolved and
+//! without the `\\?\` prefix of Windows, and use `/` as separator on every platform. A scan thus
+//! lists a file under the exact path its documents were indexed under, and indexes built on
+//! Windows show and filter paths like the others. Their case is kept as the file system reports
+//! it, but paths are compared ignoring case on Windows and macOS, whose file systems do.
+//!
+//! Snapshots of indexes built before on Windows list paths with `\`: their next update sees every
+//! file moved, deletes the documents under the old paths and moves their content to the new ones.
+
+use std::path::Path;
+
+/// Whether the file systems of the platform ignore case by default.
+const CASE_INSENSITIVE: bool = cfg!(any(windows, target_os = "macos"));
+
+/// The stored form of `path`, resolved against the working directory when relative. Paths that
+/// do not exist are only made absolute.
+pub fn normalize_path(path: &Path) -> String {
+    let resolved = dunce::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    to_slashes(&resolved.to_string_lossy())
+}
+
+/// `path` with `/` as separator. Backslashes are only separators on Windows; elsewhere they are
+/// part of file names.
+pub fn to_slashes(path: &str) -> String {
+    if cfg!(windows) {
+        path.replace('\\', "/")
+    } else {
+        path.to_string()
+    }
+}
+
+/// Whether the stored path `path` is `root` or below it.
+pub fn is_within(path: &str, root: &str) -> bool {
+    let (path, root) = (to_slashes(path), to_slashes(root));
+    let root = root.trim_end_matches('/');
+    let Some(prefix) = path.get(..root.len()) else {
+        return false;
+    };
+    let same = if CASE_INSENSITIVE {
+        prefix.to_lowercase() == root.to_lowercase()
+    } else {
+        prefix == root
+    };
+    same && (path.len() == root.len() || path[root.len()..].starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = normalize_path(dir.path());
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src").join("lib.rs"), "").unwrap();
+
+        assert!(!root.contains('\\'));
+        assert_eq!(
+            normalize_path(&dir.path().join("src").join("..").join("src").join("lib.rs")),
+            format!("{root}/src/lib.rs")
+        );
+        // Missing files are only made absolute
+        assert!(Path::new(&normalize_path(Path::new("missing.rs"))).is_absolute());
+
+        #[cfg(unix)]
+        {
+            let link = dir.path().join("link");
+            std::os::unix::fs::symlink(dir.path().join("src"), &link).unwrap();
+            assert_eq!(
+                normalize_path(&link.join("lib.rs")),
+                format!("{root}/src/lib.rs")
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_within() {
+        assert!(is_within("/repo/src/lib.rs", "/repo"));
+        assert!(is_within("/repo/src/lib.rs", "/repo/"));
+        assert!(is_within("/repo", "/repo"));
+        assert!(!is_within("/repository/lib.rs", "/repo"));
+        assert!(!is_within("/re", "/repo"));
+        assert_eq!(is_within("/Repo/lib.rs", "/repo"), CASE_INSENSITIVE);
+        assert_eq!(is_within("C:\\repo\\lib.rs", "C:/repo"), cfg!(windows));
+    }
+}
@@ -1,7 +1,19 @@
-use std::time::SystemTime;
+use std::io::BufRead;
+use std::time::{Duration, SystemTime};
 
+use crate::change::FileIndexMetadata;
+use crate::chunks::{ChunkStart, ContentChunks, MAX_CHUNK_SIZE};
+use crate::encoding::{bytes_text, open_text, open_text_at};
+use crate::git::LastCommit;
+
+use crate::language::detect_language;
+use crate::options::{ContentStorage, IndexingOptions, SchemaProfile};
+use crate::storage::SkipReason;
+use crate::symbols::extract_symbols;
+use crate::tokenizers::{tokenizer_name, CODE_TOKENIZER, LITERAL_TOKENIZER, STEMMED_TOKENIZER};
 use tantivy::schema::*;
-use tantivy::TantivyDocument;
+use tantivy::store::{Compressor, ZstdCompressor};
+use tantivy::{IndexSettings, TantivyDocument};
 
 #[allow(dead_code)]
 pub struct CodeIndexSchema {
@@ -10,25 +22,125 @@ pub struct CodeIndexSchema {
     pub content: Field,
     pub extension: Field,
     pub last_modified: Field,
+    pub size: Field,
+    pub language: Field,
 }
 
 impl CodeIndexSchema {
     pub fn new() -> CodeIndexSchema {
+        Self::with_options(&IndexingOptions::default())
+    }
+
+    /// The schema of indexes created with `options`, which decide how content is stored and
+    /// tokenized. Handles of the fields above are the same for every index, so `new` can be used
+    /// to look them up in any index; the file name, the literal content, the chunk fields and the
+    /// metadata fields of a [`SchemaProfile`] come after them and are looked up by name.
+    pub fn with_options(options: &IndexingOptions) -> CodeIndexSchema {
         let mut schema_builder = Schema::builder();
 
         let path = schema_builder.add_text_field(Self::PATH_FIELD, STRING | STORED);
 
-        let content_options = TextOptions::default()
-            .set_indexing_options(
-                TextFieldIndexing::default()
-                    .set_tokenizer("code")
-                    .set_index_option(IndexRecordOption::WithFreqsAndPositions),
-            )
-            .set_stored();
+        let content_tokenizer = tokenizer_name(options);
+        let mut content_options = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(&content_tokenizer)
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
+        if options.content_storage != ContentStorage::None {
+            content_options = content_options.set_stored();
+        }
         let content = schema_builder.add_text_field(Self::CONTENT_FIELD, content_options);
 
         let extension = schema_builder.add_text_field(Self::EXTENSION_FIELD, STRING | STORED);
         let last_modified = schema_builder.add_date_field(Self::LAST_MODIFIED_FIELD, FAST | STORED);
+        let size = schema_builder.add_u64_field(Self::SIZE_FIELD, FAST | STORED);
+        let language = schema_builder.add_text_field(Self::LANGUAGE_FIELD, STRING | STORED);
+        // Split like code, so that `parser` and `http` match `HttpParser.java`
+        schema_builder.add_text_field(
+            Self::FILE_NAME_FIELD,
+            TextOptions::default().set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(CODE_TOKENIZER)
+                    .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+            ),
+        );
+        // The content again, punctuation included, for exact searches
+        schema_builder.add_text_field(
+            Self::LITERAL_FIELD,
+            TextOptions::default().set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(LITERAL_TOKENIZER)
+                    .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+            ),
+        );
+
+        // Where the document's content starts in its file, for files indexed in several chunks
+        schema_builder.add_u64_field(Self::CHUNK_OFFSET_FIELD, STORED);
+        schema_builder.add_u64_field(Self::CHUNK_LINE_FIELD, STORED);
+
+        match options.profile {
+            SchemaProfile::Code => {
+                // Split like code whatever the content tokenizer, since symbols are identifiers;
+                // positions let plain queries search them, phrases included
+                schema_builder.add_text_field(
+                    Self::SYMBOLS_FIELD,
+                    TextOptions::default().set_indexing_options(
+                        TextFieldIndexing::default()
+                            .set_tokenizer(CODE_TOKENIZER)
+                            .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+                    ),
+                );
+            }
+            SchemaProfile::Docs => {
+                // Titles are words filtered like the content, even when it is split in n-grams
+                let title_tokenizer = if options.analyzer.is_default() {
+                    STEMMED_TOKENIZER
+                } else {
+                    &content_tokenizer
+                };
+                schema_builder.add_text_field(
+                    Self::TITLE_FIELD,
+                    TextOptions::default()
+                        .set_indexing_options(
+                            TextFieldIndexing::default()
+                                .set_tokenizer(title_tokenizer)
+                                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+                        )
+                        .set_stored(),
+                );
+            }
+            SchemaProfile::Logs => {
+                schema_builder.add_text_field(Self::LEVEL_FIELD, STRING | STORED);
+                schema_builder.add_u64_field(Self::LINE_COUNT_FIELD, FAST | STORED);
+            }
+            SchemaProfile::Commits => {
+                // Split like code, so that `writter` matches `src/writter.rs`
+                schema_builder.add_text_field(
+                    Self::FILES_FIELD,
+                    TextOptions::default()
+                        .set_indexing_options(
+                            TextFieldIndexing::default()
+                                .set_tokenizer(CODE_TOKENIZER)
+                                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+                        )
+                        .set_stored(),
+                );
+            }
+        }
+        // The path of the file whose documents hold the content, on the single empty document of
+        // each of its duplicates
+        if options.dedup {
+            schema_builder.add_text_field(Self::DUPLICATE_OF_FIELD, STRING | STORED);
+        }
+        // Authors are searched by any of their names, commits by their full hash
+        if options.git_blame || options.profile == SchemaProfile::Commits {
+            schema_builder.add_text_field(Self::LAST_AUTHOR_FIELD, TEXT | STORED);
+            schema_builder.add_text_field(Self::LAST_COMMIT_FIELD, STRING | STORED);
+        }
+        // A path is in the index once per branch that has it
+        if !options.branches.is_empty() {
+            schema_builder.add_text_field(Self::BRANCH_FIELD, STRING | STORED);
+        }
 
         Self {
             schema: schema_builder.build(),
@@ -36,6 +148,31 @@ impl CodeIndexSchema {
             content,
             extension,
             last_modified,
+            size,
+            language,
+        }
+    }
+
+    /// The profile `schema` was built for, told apart by its metadata fields.
+    pub fn profile(schema: &Schema) -> SchemaProfile {
+        if schema.get_field(Self::TITLE_FIELD).is_ok() {
+            SchemaProfile::Docs
+        } else if schema.get_field(Self::LEVEL_FIELD).is_ok() {
+            SchemaProfile::Logs
+        } else if schema.get_field(Self::FILES_FIELD).is_ok() {
+            SchemaProfile::Commits
+        } else {
+            SchemaProfile::Code
+        }
+    }
+
+    /// Names of the metadata fields of `schema`'s profile that are stored with each document.
+    pub fn stored_metadata_fields(schema: &Schema) -> &'static [&'static str] {
+        match Self::profile(schema) {
+            SchemaProfile::Code => &[],
+            SchemaProfile::Docs => &[Self::TITLE_FIELD],
+            SchemaProfile::Logs => &[Self::LEVEL_FIELD, Self::LINE_COUNT_FIELD],
+            SchemaProfile::Commits => &[Self::FILES_FIELD],
         }
     }
 
@@ -43,6 +180,36 @@ impl CodeIndexSchema {
     pub const CONTENT_FIELD: &'static str = "content";
     pub const EXTENSION_FIELD: &'static str = "extension";
     pub const LAST_MODIFIED_FIELD: &'static str = "last_modified";
+    pub const SIZE_FIELD: &'static str = "size";
+    pub const LANGUAGE_FIELD: &'static str = "language";
+    pub const FILE_NAME_FIELD: &'static str = "file_name";
+    pub const LITERAL_FIELD: &'static str = "literal";
+    pub const CHUNK_OFFSET_FIELD: &'static str = "chunk_offset";
+    pub const CHUNK_LINE_FIELD: &'static str = "chunk_line";
+    pub const SYMBOLS_FIELD: &'static str = "symbols";
+    pub const TITLE_FIELD: &'static str = "title";
+    pub const LEVEL_FIELD: &'static str = "level";
+    pub const LINE_COUNT_FIELD: &'static str = "line_count";
+    pub const DUPLICATE_OF_FIELD: &'static str = "duplicate_of";
+    pub const LAST_AUTHOR_FIELD: &'static str = "last_author";
+    pub const LAST_COMMIT_FIELD: &'static str = "last_commit";
+    pub const BRANCH_FIELD: &'static str = "branch";
+    pub const FILES_FIELD: &'static str = "files";
+}
+
+/// The settings of new indexes keeping file content as `content_storage`.
+pub fn index_settings(content_storage: ContentStorage) -> IndexSettings {
+    match content_storage {
+        ContentStorage::Zstd => IndexSettings {
+            docstore_compression: Compressor::Zstd(ZstdCompressor {
+                compression_level: Some(9),
+            }),
+            // Source files compress much better when more of them share a block
+            docstore_blocksize: 64 * 1024,
+            ..IndexSettings::default()
+        },
+        ContentStorage::Lz4 | ContentStorage::None => IndexSettings::default(),
+    }
 }
 
 pub struct CodeIndexDocument {
@@ -50,25 +217,147 @@ pub struct CodeIndexDocument {
     pub content: String,
     pub extension: String,
     pub last_modified: SystemTime,
+    pub size: u64,
+    /// See [`crate::language`]; `None` for files in no known language.
+    pub language: Option<&'static str>,
+    /// Where the content starts in the file, when the file is indexed in several chunks.
+    pub chunk: Option<ChunkStart>,
+    /// The path of the file with the same content, for duplicates indexed without it.
+    pub duplicate_of: Option<String>,
+    /// The last commit of the file, for indexes created with [`IndexingOptions::git_blame`].
+    pub last_commit: Option<LastCommit>,
+    /// The branch the file was read from, for indexes of [`IndexingOptions::branches`].
+    pub branch: Option<String>,
+    /// The paths the commit touched, for the documents of commits of
+    /// [`SchemaProfile::Commits`] indexes.
+    pub files: Vec<String>,
+}
+
+/// The documents of a file, see [`CodeIndexDocument::from_file`].
+pub struct FileDocuments<'a> {
+    file: &'a FileIndexMetadata,
+    chunks: ContentChunks<Box<dyn BufRead>>,
+    /// The file cannot be opened, and is indexed as a single empty document.
+    unreadable: bool,
+    last_modified: SystemTime,
+    /// Languages told by content are told by the start of the file
+    language: Option<Option<&'static str>>,
+}
+
+impl<'a> FileDocuments<'a> {
+    fn new(
+        file: &'a FileIndexMetadata,
+        reader: Box<dyn BufRead>,
+        unreadable: bool,
+        max_size: Option<u64>,
+        utf8_only: bool,
+    ) -> Self {
+        FileDocuments {
+            file,
+            chunks: ContentChunks::new(reader, max_size, MAX_CHUNK_SIZE).with_utf8_only(utf8_only),
+            unreadable,
+            last_modified: SystemTime::UNIX_EPOCH + Duration::from_secs(file.modified_time),
+            language: None,
+        }
+    }
+
+    /// Why the file is indexed only in part, as far as its documents were read.
+    pub fn skipped(&self) -> Option<SkipReason> {
+        if self.unreadable {
+            Some(SkipReason::Unreadable)
+        } else if self.chunks.is_binary() {
+            Some(SkipReason::Binary)
+        } else {
+            None
+        }
+    }
+}
+
+impl Iterator for FileDocuments<'_> {
+    type Item = CodeIndexDocument;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (chunk, content) = self.chunks.next()?;
+        let document =
+            CodeIndexDocument::from_content(self.file.path.clone(), content, self.last_modified);
+        Some(CodeIndexDocument {
+            size: self.file.size,
+            language: *self.language.get_or_insert(document.language),
+            chunk,
+            ..document
+        })
+    }
 }
 
 impl CodeIndexDocument {
-    pub fn from_path(path: &String) -> Self {
-        let content = std::fs::read_to_string(path).unwrap_or_default();
+    /// Builds the documents of a scanned file, dated with the modification time the scan saw, so
+    /// that date filters agree with the change detection of the next update.
+    ///
+    /// The file is streamed: files larger than [`MAX_CHUNK_SIZE`] are one document per chunk,
+    /// read as the documents are, and only its first `max_size` bytes are indexed when set.
+    /// Files in other encodings than UTF-8 are transcoded, see [`crate::encoding`], unless
+    /// `utf8_only`.
+    pub fn from_file(
+        file: &FileIndexMetadata,
+        max_size: Option<u64>,
+        utf8_only: bool,
+    ) -> FileDocuments<'_> {
+        let (reader, unreadable): (Box<dyn BufRead>, _) = match open_text(&file.path, utf8_only) {
+            Ok(reader) => (reader, false),
+            Err(_) => (Box::new(std::io::empty()), true),
+        };
+
+        FileDocuments::new(file, reader, unreadable, max_size, utf8_only)
+    }
+
+    /// [`CodeIndexDocument::from_file`] for `content` read from elsewhere than the file at
+    /// `file.path`, e.g. from a commit of a branch.
+    pub fn from_bytes(
+        file: &FileIndexMetadata,
+        content: Vec<u8>,
+        max_size: Option<u64>,
+        utf8_only: bool,
+    ) -> FileDocuments<'_> {
+        FileDocuments::new(
+            file,
+            bytes_text(content, utf8_only),
+            false,
+            max_size,
+            utf8_only,
+        )
+    }
+
+    /// Reads the content of the chunk of the file at `path` starting at `start`, as indexed by
+    /// [`CodeIndexDocument::from_file`] when the file has not changed since.
+    pub fn read_chunk(path: &str, start: ChunkStart) -> Option<String> {
+        let reader = open_text_at(path, false, start.offset).ok()?;
+        ContentChunks::new(reader, None, MAX_CHUNK_SIZE)
+            .next()
+            .map(|(_, content)| content)
+    }
+
+    /// Builds a document for content that does not come from a file on disk.
+    pub fn from_content(path: String, content: String, last_modified: SystemTime) -> Self {
         let extension = std::path::PathBuf::from(&path)
             .extension()
             .and_then(|ext| ext.to_str())
             .unwrap_or_default()
             .to_string();
-        let last_modified = std::fs::metadata(path)
-            .and_then(|meta| meta.modified())
-            .unwrap_or(SystemTime::now());
+        let size = content.len() as u64;
+        let language = detect_language(&path, &content);
 
         CodeIndexDocument {
-            path: path.clone(),
+            path,
             content,
             extension,
             last_modified,
+            size,
+            language,
+            chunk: None,
+            duplicate_of: None,
+            last_commit: None,
+            branch: None,
+            files: Vec::new(),
         }
     }
 
@@ -98,6 +387,181 @@ impl CodeIndexDocument {
                 .unwrap(),
             tantivy::DateTime::from_timestamp_secs(last_modified),
         );
+        // Indexes created before the size field existed keep their original schema.
+        if let Ok(size) = schema.get_field(CodeIndexSchema::SIZE_FIELD) {
+            doc.add_u64(size, self.size);
+        }
+        if let (Some(language), Ok(field)) = (
+            self.language,
+            schema.get_field(CodeIndexSchema::LANGUAGE_FIELD),
+        ) {
+            doc.add_text(field, language);
+        }
+        if let (Some(file_name), Ok(field)) = (
+            std::path::Path::new(&self.path).file_name(),
+            schema.get_field(CodeIndexSchema::FILE_NAME_FIELD),
+        ) {
+            doc.add_text(field, file_name.to_string_lossy());
+        }
+        if let Ok(field) = schema.get_field(CodeIndexSchema::LITERAL_FIELD) {
+            doc.add_text(field, &self.content);
+        }
+        if let (Some(chunk), Ok(offset), Ok(line)) = (
+            self.chunk,
+            schema.get_field(CodeIndexSchema::CHUNK_OFFSET_FIELD),
+            schema.get_field(CodeIndexSchema::CHUNK_LINE_FIELD),
+        ) {
+            doc.add_u64(offset, chunk.offset);
+            doc.add_u64(line, chunk.line);
+        }
+        if let (Some(duplicate_of), Ok(field)) = (
+            &self.duplicate_of,
+            schema.get_field(CodeIndexSchema::DUPLICATE_OF_FIELD),
+        ) {
+            doc.add_text(field, duplicate_of);
+        }
+        if let (Some(last_commit), Ok(author), Ok(commit)) = (
+            &self.last_commit,
+            schema.get_field(CodeIndexSchema::LAST_AUTHOR_FIELD),
+            schema.get_field(CodeIndexSchema::LAST_COMMIT_FIELD),
+        ) {
+            doc.add_text(author, &last_commit.author);
+            doc.add_text(commit, &last_commit.commit);
+        }
+        if let (Some(branch), Ok(field)) = (
+            &self.branch,
+            schema.get_field(CodeIndexSchema::BRANCH_FIELD),
+        ) {
+            doc.add_text(field, branch);
+        }
+        if let Ok(field) = schema.get_field(CodeIndexSchema::FILES_FIELD) {
+            for file in &self.files {
+                doc.add_text(field, file);
+            }
+        }
+        // Metadata fields of the schema's profile, only extracted when the schema has them
+        if let Ok(field) = schema.get_field(CodeIndexSchema::SYMBOLS_FIELD) {
+            for symbol in extract_symbols(&self.extension, &self.content) {
+                doc.add_text(field, symbol.name);
+            }
+        }
+        if let (Some(title), Ok(field)) = (
+            document_title(&self.content),
+            schema.get_field(CodeIndexSchema::TITLE_FIELD),
+        ) {
+            doc.add_text(field, title);
+        }
+        if let Ok(field) = schema.get_field(CodeIndexSchema::LEVEL_FIELD) {
+            for level in log_levels(&self.content) {
+                doc.add_text(field, level);
+            }
+        }
+        if let Ok(field) = schema.get_field(CodeIndexSchema::LINE_COUNT_FIELD) {
+            doc.add_u64(field, self.content.lines().count() as u64);
+        }
         doc
     }
 }
+
+/// Longest title kept; longer first lines are cut at a character boundary.
+const MAX_TITLE_LENGTH: usize = 200;
+
+/// The first Markdown, AsciiDoc or reStructuredText-style heading of `content`, or else its first
+/// non-blank line.
+fn document_title(content: &str) -> Option<String> {
+    let heading = content.lines().find_map(|line| {
+        let title = line.strip_prefix('#').or_else(|| line.strip_prefix('='))?;
+        let title = title.trim_start_matches(['#', '=']);
+        title.starts_with(' ').then(|| title.trim())
+    });
+    let title = heading.or_else(|| content.lines().map(str::trim).find(|line| !line.is_empty()))?;
+    if title.is_empty() {
+        return None;
+    }
+
+    let end = title
+        .char_indices()
+        .nth(MAX_TITLE_LENGTH)
+        .map_or(title.len(), |(index, _)| index);
+    Some(title[..end].to_string())
+}
+
+/// Severity levels found in log `content`, lowercased and in severity order. Levels are words in
+/// capitals (`ERROR`, `[WARN]`) or values of a `level` key in any case (`level=error`,
+/// `"level":"info"`).
+fn log_levels(content: &str) -> Vec<&'static str> {
+    const LEVELS: &[(&str, &[&str])] = &[
+        ("fatal", &["fatal", "critical", "crit"]),
+        ("error", &["error", "err"]),
+        ("warn", &["warn", "warning"]),
+        ("info", &["info"]),
+        ("debug", &["debug"]),
+        ("trace", &["trace"]),
+    ];
+
+    let mut found = [false; LEVELS.len()];
+    let mut after_level_key = false;
+    for word in content
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        let is_capitals = word.chars().all(|c| c.is_ascii_uppercase());
+        if is_capitals || after_level_key {
+            let word = word.to_ascii_lowercase();
+            if let Some(index) = LEVELS
+                .iter()
+                .position(|(_, spellings)| spellings.contains(&word.as_str()))
+            {
+                found[index] = true;
+            }
+        }
+        after_level_key = word.eq_ignore_ascii_case("level") || word.eq_ignore_ascii_case("lvl");
+    }
+
+    LEVELS
+        .iter()
+        .zip(found)
+        .filter(|(_, found)| *found)
+        .map(|((level, _), _)| *level)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_title() {
+        assert_eq!(
+            document_title("Intro\n\n# Getting started\n## Install").as_deref(),
+            Some("Getting started")
+        );
+        assert_eq!(
+            document_title("= AsciiDoc title\n").as_deref(),
+            Some("AsciiDoc title")
+        );
+        assert_eq!(
+            document_title("\n  Plain notes  \nmore").as_deref(),
+            Some("Plain notes")
+        );
+        assert_eq!(
+            document_title("#!/bin/sh\necho").as_deref(),
+            Some("#!/bin/sh")
+        );
+        assert_eq!(document_title(" \n"), None);
+        assert_eq!(
+            document_title(&"é".repeat(300)).map(|title| title.chars().count()),
+            Some(MAX_TITLE_LENGTH)
+        );
+    }
+
+    #[test]
+    fn test_log_levels() {
+        let content = "2024-05-01 [WARN] disk almost full\n\
+                       {\"level\":\"error\",\"msg\":\"an error occurred\"}\n\
+                       ts=1 lvl=Debug msg=\"info only\"\n";
+
+        assert_eq!(log_levels(content), vec!["error", "warn", "debug"]);
+        assert!(log_levels("no levels, just an error in prose").is_empty());
+    }
+}
@@ -1,22 +1,374 @@
-use crate::schema::CodeIndexSchema;
-use tantivy::schema::Value;
-use tantivy::snippet::SnippetGenerator;
+use crate::change::scan;
+use crate::chunks::ChunkStart;
+use crate::encoding::read_text;
+use crate::git::{changed_files, resolve_commit, FileChange};
+use crate::language::normalize_language;
+use crate::options::{FieldBoosts, IndexingOptions, SchemaProfile};
+use crate::paths::{is_within, normalize_path, to_slashes};
+use crate::schema::{CodeIndexDocument, CodeIndexSchema};
+use crate::storage::natural_cmp;
+use crate::symbols::{extract_symbols, FileSymbols, Symbol, SymbolKind};
+use crate::tokenizers::{
+    query_tokenizers, register_tokenizers, CodeTokenizer, LiteralTokenizer, CODE_TOKENIZER,
+};
+use tantivy::collector::{DocSetCollector, TopDocs};
+use tantivy::query::{BooleanQuery, Occur, PhraseQuery, Query, RangeQuery, TermQuery};
+use tantivy::schema::{Field, FieldType, IndexRecordOption, Schema, Value};
+use tantivy::snippet::{Snippet, SnippetGenerator};
+use tantivy::tokenizer::{TokenStream, Tokenizer};
 
-use tantivy::{Index, TantivyDocument};
+use tantivy::{DocSet, Index, Searcher, TantivyDocument, Term, TERMINATED};
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ops::Bound;
+use std::path::Path;
 
 #[derive(serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct SearchResultItem {
     pub path: String,
     pub snippet: String,
     pub extension: String,
     pub score: f32,
+    /// File size in bytes at indexing time.
+    pub size: u64,
+    /// Last modification time at indexing time, in seconds since the Unix epoch.
+    pub last_modified: i64,
+    /// Programming language of the file, see [`crate::language`]; not set for files in no known
+    /// language or indexed before languages were detected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Index the result came from; only set by searches over several indexes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index_name: Option<String>,
+    /// Stored metadata fields of the index's schema profile, e.g. `title` for docs indexes;
+    /// values of multi-valued fields are comma-separated.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    /// Other paths with the same content, indexed only under this one, for indexes created with
+    /// [`crate::options::IndexingOptions::dedup`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub duplicates: Vec<String>,
+    /// Author of the last commit of the file, for indexes created with
+    /// [`crate::options::IndexingOptions::git_blame`]; not set for files that were not committed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_author: Option<String>,
+    /// Hash of the last commit of the file, set along with `last_author`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_commit: Option<String>,
+    /// Branch the file was read from, for indexes created with
+    /// [`crate::options::IndexingOptions::branches`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// The fragments joined in `snippet`, in the order of the file; empty when the content did
+    /// not match.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub snippets: Vec<String>,
 }
 
 impl SearchResultItem {}
 
+/// A definition found by [`IndexSearcher::search_symbols`].
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SymbolMatch {
+    pub path: String,
+    pub name: String,
+    pub kind: SymbolKind,
+    /// 1-based line number of the definition.
+    pub line: usize,
+    /// The full definition line.
+    pub line_text: String,
+    /// Score of the file defining the symbol.
+    pub score: f32,
+    /// Index the definition came from; only set by searches over several indexes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index_name: Option<String>,
+}
+
+/// Number of results in a language, see [`language_counts`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LanguageCount {
+    pub language: String,
+    pub count: usize,
+}
+
+/// Counts `results` by language, most frequent first. Results in no known language are left out.
+pub fn language_counts(results: &[SearchResultItem]) -> Vec<LanguageCount> {
+    let mut counts = std::collections::BTreeMap::<&str, usize>::new();
+    for language in results
+        .iter()
+        .filter_map(|result| result.language.as_deref())
+    {
+        *counts.entry(language).or_default() += 1;
+    }
+
+    let mut counts: Vec<_> = counts
+        .into_iter()
+        .map(|(language, count)| LanguageCount {
+            language: language.to_string(),
+            count,
+        })
+        .collect();
+    counts.sort_by_key(|count| std::cmp::Reverse(count.count));
+    counts
+}
+
+/// A file or directory of an index's files, listed by [`IndexSearcher::tree`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TreeEntry {
+    pub name: String,
+    /// Path relative to the indexed directory, with `/` separators.
+    pub path: String,
+    pub kind: TreeEntryKind,
+    /// Indexed files at or below the entry, 1 for a file.
+    pub files: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum TreeEntryKind {
+    Directory,
+    File,
+}
+
+/// How the documents of an index are spread, see [`IndexSearcher::breakdown`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct IndexBreakdown {
+    /// Documents by file extension, most first; files without one are counted under `""`.
+    pub extensions: Vec<DocumentCount>,
+    /// Documents by top-level directory of the indexed directory, most first; the files directly
+    /// in it are counted under `""`.
+    pub directories: Vec<DocumentCount>,
+    /// The largest indexed files, largest first.
+    pub largest_files: Vec<FileSize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DocumentCount {
+    pub name: String,
+    pub documents: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FileSize {
+    pub path: String,
+    /// Size in bytes at indexing time.
+    pub size: u64,
+}
+
+pub const DEFAULT_LARGEST_FILES: usize = 10;
+
+/// A completion of the last word of a query, see [`IndexSearcher::suggest`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Suggestion {
+    pub text: String,
+    pub kind: SuggestionKind,
+    /// Documents containing the word, or files below the path.
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SuggestionKind {
+    /// The name, or a part of the name, of a definition.
+    Symbol,
+    /// A word of the content.
+    Word,
+    /// A file or directory, relative to the indexed directory; directories end with `/`.
+    Path,
+}
+
+pub const DEFAULT_SUGGESTIONS: usize = 10;
+
+/// Terms of a field of a segment read at most for one suggestion, so that a short prefix of a
+/// large index is answered as quickly as a long one.
+const MAX_SUGGESTION_TERMS: usize = 10_000;
+
+/// Upper bound on the number of terms a query may expand to before it is refused.
+pub const DEFAULT_MAX_QUERY_COST: u64 = 1000;
+
+pub const DEFAULT_HIGHLIGHT_TAG: &str = "b";
+
+/// Longest fragment of a snippet, in characters.
+pub const DEFAULT_SNIPPET_CHARS: usize = 150;
+
+pub const DEFAULT_MAX_FRAGMENTS: usize = 1;
+
+/// [`SearchOptions::max_fragments`] asking for every fragment with a match.
+pub const ALL_FRAGMENTS: usize = usize::MAX;
+
+/// Joins the fragments of a snippet made of several.
+pub const FRAGMENT_SEPARATOR: &str = " ... ";
+
+const PHRASE_PREFIX_MAX_EXPANSIONS: u64 = 50;
+
+/// Elements that may wrap highlighted matches; snippets end up in HTML, so anything else is refused.
+const HIGHLIGHT_TAGS: [&str; 5] = ["b", "strong", "em", "mark", "span"];
+
+/// The query of a search, the same restricted by its filters, and the field its snippets are
+/// highlighted in.
+type BuiltQuery = (Box<dyn Query>, Box<dyn Query>, Field);
+
+/// Why a search failed, telling the searches that cannot succeed as asked from failures of the
+/// index. Converts into the message for callers that only report it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchError {
+    /// The query does not parse, or an option such as `within` or `changed_since` is invalid.
+    InvalidRequest(String),
+    /// The query expands to more terms than [`SearchOptions::max_query_cost`].
+    TooExpensive(String),
+    IndexNotFound(String),
+    /// The index could not be read.
+    Failed(String),
+}
+
+impl std::fmt::Display for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchError::InvalidRequest(e)
+            | SearchError::TooExpensive(e)
+            | SearchError::IndexNotFound(e)
+            | SearchError::Failed(e) => f.write_str(e),
+        }
+    }
+}
+
+impl From<String> for SearchError {
+    fn from(error: String) -> Self {
+        SearchError::Failed(error)
+    }
+}
+
+impl From<SearchError> for String {
+    fn from(error: SearchError) -> Self {
+        error.to_string()
+    }
+}
+
+#[derive(Clone)]
+pub struct SearchOptions {
+    /// Queries whose estimated cost exceeds this value are refused; `None` disables the check.
+    pub max_query_cost: Option<u64>,
+    /// HTML tag wrapping highlighted matches in snippets.
+    pub highlight_tag: String,
+    /// Optional CSS class set on the highlight tag.
+    pub highlight_class: Option<String>,
+    /// Only return files in one of these languages (names or extensions, see
+    /// [`normalize_language`]); empty returns every file.
+    pub languages: Vec<String>,
+    /// Only return files last modified at or after this time, in seconds since the Unix epoch.
+    pub modified_after: Option<i64>,
+    /// Only return files last modified before this time, in seconds since the Unix epoch.
+    pub modified_before: Option<i64>,
+    /// Ranking weights overriding the index's for this search.
+    pub boosts: FieldBoosts,
+    /// Match the query as a literal string, punctuation included, instead of parsing it.
+    pub exact: bool,
+    /// Longest fragment of the snippets, in characters.
+    pub snippet_chars: usize,
+    /// Fragments of the content in a snippet at most, the best ones that do not overlap, in
+    /// the order of the file and joined by [`FRAGMENT_SEPARATOR`]; [`ALL_FRAGMENTS`] for a
+    /// fragment per block of lines with a match.
+    pub max_fragments: usize,
+    /// Only return files under this directory, relative to the indexed directory or absolute
+    /// within it.
+    pub within: Option<String>,
+    /// Leave out the files with one of these extensions, with or without the dot.
+    pub excluded_extensions: Vec<String>,
+    /// Leave out the files under these directories, or these files, relative to the indexed
+    /// directory or absolute within it.
+    pub excluded_paths: Vec<String>,
+    /// Only return files whose last commit is by an author with these words in their name, see
+    /// [`crate::options::IndexingOptions::git_blame`].
+    pub author: Option<String>,
+    /// Only return files of one of these branches, see
+    /// [`crate::options::IndexingOptions::branches`]; empty returns the files of every branch.
+    pub branches: Vec<String>,
+    /// Only return files added or modified between this revision, e.g. a commit, tag or branch,
+    /// and `HEAD` of the repository of the indexed directory, as `git diff` tells.
+    pub changed_since: Option<String>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            max_query_cost: Some(DEFAULT_MAX_QUERY_COST),
+            highlight_tag: DEFAULT_HIGHLIGHT_TAG.to_string(),
+            highlight_class: None,
+            languages: Vec::new(),
+            modified_after: None,
+            modified_before: None,
+            boosts: FieldBoosts::default(),
+            exact: false,
+            snippet_chars: DEFAULT_SNIPPET_CHARS,
+            max_fragments: DEFAULT_MAX_FRAGMENTS,
+            within: None,
+            excluded_extensions: Vec::new(),
+            excluded_paths: Vec::new(),
+            author: None,
+            branches: Vec::new(),
+            changed_since: None,
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Returns the opening and closing markup wrapped around highlighted matches.
+    fn highlight_markup(&self) -> Result<(String, String), String> {
+        let tag = &self.highlight_tag;
+        if !HIGHLIGHT_TAGS.contains(&tag.as_str()) {
+            return Err(format!(
+                "Invalid highlight tag '{tag}', expected one of {}",
+                HIGHLIGHT_TAGS.join(", ")
+            ));
+        }
+
+        let prefix = match &self.highlight_class {
+            Some(class) => {
+                let valid = class
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ' '));
+                if !valid {
+                    return Err(format!("Invalid highlight class '{class}'"));
+                }
+                format!("<{tag} class=\"{class}\">")
+            }
+            None => format!("<{tag}>"),
+        };
+
+        Ok((prefix, format!("</{tag}>")))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct QueryCost {
+    /// Number of terms the query expands to, including the expansions of prefix phrases.
+    pub expanded_terms: u64,
+    /// Number of postings visited for the non-expanded terms.
+    pub postings: u64,
+}
+
+/// Cheap to clone: clones share the index and its reader.
+#[derive(Clone)]
 pub struct IndexSearcher {
     index: Index,
     reader: tantivy::IndexReader,
+    /// False for indexes created with [`crate::options::ContentStorage::None`], whose content is read back from
+    /// the indexed files instead.
+    stores_content: bool,
+    /// Ranking weights chosen when the index was created.
+    boosts: FieldBoosts,
+    /// The indexed directory, in the stored form of paths.
+    root: Option<String>,
 }
 
 impl IndexSearcher {
@@ -24,35 +376,983 @@ impl IndexSearcher {
         let reader = index
             .reader()
             .map_err(|e| format!("Failed to create index reader for index: {e}"))?;
+        let stores_content = index
+            .schema()
+            .get_field_entry(CodeIndexSchema::new().content)
+            .is_stored();
 
-        Ok(IndexSearcher { index, reader })
+        Ok(IndexSearcher {
+            index,
+            reader,
+            stores_content,
+            boosts: FieldBoosts::default(),
+            root: None,
+        })
+    }
+
+    pub fn with_boosts(mut self, boosts: FieldBoosts) -> Self {
+        self.boosts = boosts;
+        self
+    }
+
+    /// Sets the indexed directory, which [`SearchOptions::within`] is relative to.
+    pub fn with_root(mut self, root: &str) -> Self {
+        self.root = Some(to_slashes(root));
+        self
+    }
+
+    /// Returns the content of `doc`, the document indexed under `path`. Indexes that do not store
+    /// content read the file (or the document's chunk of it) as it is now, so ingested documents,
+    /// which have no file, have none.
+    fn content(&self, doc: &TantivyDocument, path: &str) -> Option<String> {
+        if self.stores_content {
+            doc.get_first(CodeIndexSchema::new().content)
+                .and_then(|value| value.as_str())
+                .map(str::to_string)
+        } else if let Some(start) = self.chunk_start(doc) {
+            CodeIndexDocument::read_chunk(path, start)
+        } else {
+            read_text(path)
+        }
+    }
+
+    /// Where the content of `doc` starts in its file, when the file is indexed in several chunks.
+    fn chunk_start(&self, doc: &TantivyDocument) -> Option<ChunkStart> {
+        let schema = self.index.schema();
+        let value = |name| {
+            let field = schema.get_field(name).ok()?;
+            doc.get_first(field).and_then(|value| value.as_u64())
+        };
+        Some(ChunkStart {
+            offset: value(CodeIndexSchema::CHUNK_OFFSET_FIELD)?,
+            line: value(CodeIndexSchema::CHUNK_LINE_FIELD)?,
+        })
+    }
+
+    /// The symbols of `content`, the content of `doc`, located in the whole file.
+    fn extract_symbols(
+        &self,
+        doc: &TantivyDocument,
+        extension: &str,
+        content: &str,
+    ) -> Vec<Symbol> {
+        let mut symbols = extract_symbols(extension, content);
+        if let Some(start) = self.chunk_start(doc) {
+            for symbol in &mut symbols {
+                symbol.line += start.line as usize;
+                symbol.offset += start.offset as usize;
+            }
+        }
+        symbols
     }
 
     pub fn search(&self, query: &str) -> Result<Vec<SearchResultItem>, String> {
+        Ok(self.search_with_options(query, &SearchOptions::default())?)
+    }
+
+    /// Extracts the symbols of every document in the index, ordered by path.
+    pub fn symbols(&self) -> Result<Vec<FileSymbols>, String> {
         let code_index_schema = CodeIndexSchema::new();
+        let searcher = self.reader.searcher();
 
-        let query_parser = tantivy::query::QueryParser::for_index(
-            &self.index,
-            vec![
-                code_index_schema.path,
-                code_index_schema.content,
-                code_index_schema.extension,
-            ],
+        let mut files = Vec::new();
+        for segment_reader in searcher.segment_readers() {
+            let store_reader = segment_reader
+                .get_store_reader(64)
+                .map_err(|e| format!("Failed to open document store: {e}"))?;
+            for doc in store_reader.iter::<TantivyDocument>(segment_reader.alive_bitset()) {
+                let doc = doc.map_err(|e| format!("Failed to retrieve document: {e}"))?;
+                let field_text = |field: Field| {
+                    doc.get_first(field)
+                        .and_then(|value| value.as_str())
+                        .unwrap_or_default()
+                };
+
+                let content = self
+                    .content(&doc, field_text(code_index_schema.path))
+                    .unwrap_or_default();
+                let symbols =
+                    self.extract_symbols(&doc, field_text(code_index_schema.extension), &content);
+                if !symbols.is_empty() {
+                    files.push(FileSymbols {
+                        path: field_text(code_index_schema.path).to_string(),
+                        symbols,
+                    });
+                }
+            }
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        // Files indexed in several chunks are one document per chunk
+        files.dedup_by(|file, previous| {
+            if file.path != previous.path {
+                return false;
+            }
+            previous.symbols.append(&mut file.symbols);
+            previous.symbols.sort_by_key(|symbol| symbol.line);
+            true
+        });
+
+        Ok(files)
+    }
+
+    /// Finds the definitions named like `query`, best matching files first.
+    ///
+    /// A name matches when it has every identifier part of `query` whatever their case, so
+    /// `parse_http` finds `parseHttpRequest` and `ParseHttp` but not `parser`.
+    pub fn search_symbols(&self, query: &str) -> Result<Vec<SymbolMatch>, String> {
+        let code_index_schema = CodeIndexSchema::new();
+        let field = self
+            .index
+            .schema()
+            .get_field(CodeIndexSchema::SYMBOLS_FIELD)
+            .map_err(|_| match CodeIndexSchema::profile(&self.index.schema()) {
+                SchemaProfile::Code => "The index was created before symbols were indexed and \
+                     cannot be searched for symbols; rebuild it with `beetle update --reindex`"
+                    .to_string(),
+                profile => format!(
+                    "The index was created with the {profile} profile, which does not index \
+                     symbols"
+                ),
+            })?;
+
+        let parts = code_tokens(query);
+        if parts.is_empty() {
+            return Err(format!("Symbol query '{query}' has no identifier"));
+        }
+        let every_part: Vec<(Occur, Box<dyn Query>)> = parts
+            .iter()
+            .map(|part| {
+                let term = Term::from_field_text(field, part);
+                let part_query: Box<dyn Query> =
+                    Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs));
+                (Occur::Must, part_query)
+            })
+            .collect();
+
+        let searcher = self.reader.searcher();
+        let top_docs = searcher
+            .search(&BooleanQuery::new(every_part), &TopDocs::with_limit(10000))
+            .map_err(|e| format!("Search failed: {e}"))?;
+
+        let mut matches = Vec::new();
+        for (score, doc_address) in top_docs {
+            let doc = searcher
+                .doc::<TantivyDocument>(doc_address)
+                .map_err(|e| format!("Failed to retrieve document: {e}"))?;
+            let field_text = |field: Field| {
+                doc.get_first(field)
+                    .and_then(|value| value.as_str())
+                    .unwrap_or_default()
+            };
+            let path = field_text(code_index_schema.path);
+
+            // The parts may come from different definitions of the file
+            let content = self.content(&doc, path).unwrap_or_default();
+            let extension = field_text(code_index_schema.extension);
+            for symbol in self.extract_symbols(&doc, extension, &content) {
+                let name_parts = code_tokens(&symbol.name);
+                if parts.iter().all(|part| name_parts.contains(part)) {
+                    matches.push(SymbolMatch {
+                        path: path.to_string(),
+                        name: symbol.name,
+                        kind: symbol.kind,
+                        line: symbol.line,
+                        line_text: symbol.line_text,
+                        score,
+                        index_name: None,
+                    });
+                }
+            }
+        }
+
+        Ok(matches)
+    }
+
+    /// Restricts `query` to the files in one of `languages`.
+    fn filter_languages(
+        &self,
+        query: Box<dyn Query>,
+        languages: &[String],
+    ) -> Result<Box<dyn Query>, String> {
+        if languages.is_empty() {
+            return Ok(query);
+        }
+        let field = self
+            .index
+            .schema()
+            .get_field(CodeIndexSchema::LANGUAGE_FIELD)
+            .map_err(|_| {
+                "The index was created before languages were detected and cannot be filtered by \
+                 language; rebuild it with `beetle update --reindex`"
+                    .to_string()
+            })?;
+
+        let mut any_language = Vec::new();
+        for language in languages {
+            let term = Term::from_field_text(field, normalize_language(language)?);
+            let language_query: Box<dyn Query> =
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+            any_language.push((Occur::Should, language_query));
+        }
+
+        Ok(Box::new(BooleanQuery::new(vec![
+            (Occur::Must, query),
+            (Occur::Must, Box::new(BooleanQuery::new(any_language))),
+        ])))
+    }
+
+    /// Restricts `query` to the files under `within`, as the range of paths that start with the
+    /// directory. Paths are compared as stored, so case matters on every platform.
+    fn filter_within(
+        &self,
+        query: Box<dyn Query>,
+        within: Option<&str>,
+    ) -> Result<Box<dyn Query>, String> {
+        let Some(within) = within else {
+            return Ok(query);
+        };
+        let Some(root) = &self.root else {
+            return Err(format!(
+                "Cannot search in {within}: the indexed directory is not known"
+            ));
+        };
+        let Some(directory) = directory_within(root, within) else {
+            return Err(format!(
+                "Cannot search in {within}: it is not a directory of the indexed directory {root}"
+            ));
+        };
+        if directory == root.trim_end_matches('/') {
+            return Ok(query);
+        }
+
+        Ok(Box::new(BooleanQuery::new(vec![
+            (Occur::Must, query),
+            (Occur::Must, Box::new(paths_below(&directory))),
+        ])))
+    }
+
+    /// Leaves out of `query` the files with the excluded extensions of `options`, and those at or
+    /// below its excluded paths.
+    fn filter_excluded(
+        &self,
+        query: Box<dyn Query>,
+        options: &SearchOptions,
+    ) -> Result<Box<dyn Query>, String> {
+        if options.excluded_extensions.is_empty() && options.excluded_paths.is_empty() {
+            return Ok(query);
+        }
+        let schema = CodeIndexSchema::new();
+        let mut clauses = vec![(Occur::Must, query)];
+
+        for extension in &options.excluded_extensions {
+            let term = Term::from_field_text(schema.extension, extension.trim_start_matches('.'));
+            clauses.push((
+                Occur::MustNot,
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+            ));
+        }
+        for excluded in &options.excluded_paths {
+            let path = self
+                .root
+                .as_deref()
+                .and_then(|root| directory_within(root, excluded))
+                .ok_or_else(|| {
+                    format!("Cannot exclude {excluded}: it is not a path of the indexed directory")
+                })?;
+            let term = Term::from_field_text(schema.path, &path);
+            clauses.push((
+                Occur::MustNot,
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+            ));
+            clauses.push((Occur::MustNot, Box::new(paths_below(&path))));
+        }
+
+        Ok(Box::new(BooleanQuery::new(clauses)))
+    }
+
+    /// Restricts `query` to the files whose last commit is by `author`, matching every word of
+    /// the name as the field was tokenized, so that `alice` finds `Alice Smith`.
+    fn filter_author(
+        &self,
+        query: Box<dyn Query>,
+        author: Option<&str>,
+    ) -> Result<Box<dyn Query>, String> {
+        let Some(author) = author else {
+            return Ok(query);
+        };
+        let field = self
+            .index
+            .schema()
+            .get_field(CodeIndexSchema::LAST_AUTHOR_FIELD)
+            .map_err(|_| {
+                "The index was created without --git-blame and cannot be filtered by author"
+                    .to_string()
+            })?;
+        let mut tokenizer = self
+            .index
+            .tokenizer_for_field(field)
+            .map_err(|e| format!("Failed to tokenize author '{author}': {e}"))?;
+        let mut clauses = vec![(Occur::Must, query)];
+        let mut tokens = tokenizer.token_stream(author);
+        while tokens.advance() {
+            let term = Term::from_field_text(field, &tokens.token().text);
+            clauses.push((
+                Occur::Must,
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+            ));
+        }
+        if clauses.len() == 1 {
+            return Err(format!(
+                "Invalid author '{author}': it has no words to match"
+            ));
+        }
+
+        Ok(Box::new(BooleanQuery::new(clauses)))
+    }
+
+    /// Restricts `query` to the files of one of `branches`.
+    fn filter_branches(
+        &self,
+        query: Box<dyn Query>,
+        branches: &[String],
+    ) -> Result<Box<dyn Query>, String> {
+        if branches.is_empty() {
+            return Ok(query);
+        }
+        let field = self
+            .index
+            .schema()
+            .get_field(CodeIndexSchema::BRANCH_FIELD)
+            .map_err(|_| {
+                "The index was created without --branch and cannot be filtered by branch"
+                    .to_string()
+            })?;
+
+        let any_branch = branches
+            .iter()
+            .map(|branch| {
+                let term = Term::from_field_text(field, branch);
+                let branch_query: Box<dyn Query> =
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+                (Occur::Should, branch_query)
+            })
+            .collect();
+
+        Ok(Box::new(BooleanQuery::new(vec![
+            (Occur::Must, query),
+            (Occur::Must, Box::new(BooleanQuery::new(any_branch))),
+        ])))
+    }
+
+    /// Restricts `query` to the files added or modified since `rev`, as `git diff` lists them
+    /// between `rev` and `HEAD` in the indexed directory.
+    fn filter_changed_since(
+        &self,
+        query: Box<dyn Query>,
+        rev: Option<&str>,
+    ) -> Result<Box<dyn Query>, String> {
+        let Some(rev) = rev else {
+            return Ok(query);
+        };
+        let Some(root) = &self.root else {
+            return Err(format!(
+                "Cannot search the files changed since {rev}: the indexed directory is not known"
+            ));
+        };
+        let dir = Path::new(root);
+        let changes = changed_files(dir, &resolve_commit(dir, rev)?, "HEAD")
+            .map_err(|e| format!("Cannot search the files changed since {rev}: {e}"))?;
+
+        // None when no file changed
+        let path_field = CodeIndexSchema::new().path;
+        let any_path = changes
+            .into_iter()
+            .filter_map(|change| match change {
+                FileChange::Added(file) | FileChange::Modified(file) => Some(file),
+                FileChange::Removed(_) => None,
+            })
+            .map(|file| {
+                let path = format!("{}/{file}", root.trim_end_matches('/'));
+                let term = Term::from_field_text(path_field, &path);
+                let path_query: Box<dyn Query> =
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+                (Occur::Should, path_query)
+            })
+            .collect();
+
+        Ok(Box::new(BooleanQuery::new(vec![
+            (Occur::Must, query),
+            (Occur::Must, Box::new(BooleanQuery::new(any_path))),
+        ])))
+    }
+
+    /// Restricts `query` to the files last modified in the range of `options`.
+    fn filter_modified(
+        &self,
+        query: Box<dyn Query>,
+        options: &SearchOptions,
+    ) -> Result<Box<dyn Query>, String> {
+        if options.modified_after.is_none() && options.modified_before.is_none() {
+            return Ok(query);
+        }
+        let field = CodeIndexSchema::new().last_modified;
+        if !self.index.schema().get_field_entry(field).is_fast() {
+            return Err(
+                "The index was created before modification times were searchable and cannot be \
+                 filtered by date; rebuild it with `beetle update --reindex`"
+                    .to_string(),
+            );
+        }
+
+        let bound =
+            |secs: i64| Term::from_field_date(field, tantivy::DateTime::from_timestamp_secs(secs));
+        let range = RangeQuery::new(
+            options
+                .modified_after
+                .map_or(Bound::Unbounded, |secs| Bound::Included(bound(secs))),
+            options
+                .modified_before
+                .map_or(Bound::Unbounded, |secs| Bound::Excluded(bound(secs))),
+        );
+
+        Ok(Box::new(BooleanQuery::new(vec![
+            (Occur::Must, query),
+            (Occur::Must, Box::new(range)),
+        ])))
+    }
+
+    /// Parses `query`, ranking matches in each field by `boosts` over the index's boosts.
+    fn parse_query(
+        &self,
+        query: &str,
+        boosts: FieldBoosts,
+    ) -> Result<Box<dyn tantivy::query::Query>, String> {
+        let code_index_schema = CodeIndexSchema::new();
+        let schema = self.index.schema();
+
+        let mut default_fields = vec![
+            code_index_schema.path,
+            code_index_schema.content,
+            code_index_schema.extension,
+        ];
+        // Other metadata fields are only searched when named, e.g. `level:error`
+        default_fields.extend(
+            [
+                CodeIndexSchema::FILE_NAME_FIELD,
+                CodeIndexSchema::SYMBOLS_FIELD,
+                CodeIndexSchema::TITLE_FIELD,
+                CodeIndexSchema::FILES_FIELD,
+            ]
+            .into_iter()
+            .filter_map(|name| schema.get_field(name).ok())
+            .filter(|field| has_positions(&schema, *field)),
+        );
+        let mut query_parser = tantivy::query::QueryParser::new(
+            schema.clone(),
+            default_fields,
+            query_tokenizers(&schema),
         );
-        let parsed_query = query_parser
+        for (name, boost) in boosts.or(self.boosts).resolve() {
+            if let Ok(field) = schema.get_field(name) {
+                query_parser.set_field_boost(field, boost);
+            }
+        }
+        query_parser
             .parse_query(query)
-            .map_err(|e| format!("Failed to parse query '{query}': {e}"))?;
+            .map_err(|e| format!("Failed to parse query '{query}': {e}"))
+    }
+
+    /// The query matching `literal` verbatim, and the field its snippets are highlighted in.
+    fn literal_query(&self, literal: &str) -> Result<(Box<dyn Query>, Field), String> {
+        let field = self
+            .index
+            .schema()
+            .get_field(CodeIndexSchema::LITERAL_FIELD)
+            .map_err(|_| {
+                "The index was created before exact searches were supported; rebuild it with \
+                 `beetle update --reindex`"
+                    .to_string()
+            })?;
+
+        let mut tokenizer = LiteralTokenizer::default();
+        let mut token_stream = tokenizer.token_stream(literal);
+        let mut terms = Vec::new();
+        while let Some(token) = token_stream.next() {
+            terms.push(Term::from_field_text(field, &token.text));
+        }
+        let query: Box<dyn Query> = match terms.len() {
+            0 => return Err("An exact search needs some text to match".to_string()),
+            1 => Box::new(TermQuery::new(
+                terms.remove(0),
+                IndexRecordOption::WithFreqs,
+            )),
+            _ => Box::new(PhraseQuery::new(terms)),
+        };
+
+        Ok((query, field))
+    }
+
+    /// Estimates how expensive `query` is without executing it.
+    ///
+    /// Plain terms count once each, while prefix phrases (`"ab"*`) count every indexed term they
+    /// expand to, which is what makes short prefixes pathological on large indexes.
+    pub fn estimate_cost(&self, query: &str) -> Result<QueryCost, String> {
+        let parsed_query = self.parse_query(query, FieldBoosts::default())?;
+        let searcher = self.reader.searcher();
+
+        let mut terms = Vec::new();
+        parsed_query.query_terms(&mut |term, _| terms.push(term.clone()));
+
+        let mut cost = QueryCost::default();
+        for term in &terms {
+            cost.expanded_terms += 1;
+            cost.postings += searcher
+                .doc_freq(term)
+                .map_err(|e| format!("Failed to read term frequency: {e}"))?;
+        }
+
+        let code_index_schema = CodeIndexSchema::new();
+        for phrase in prefix_phrases(query) {
+            let content_tokens = code_tokens(&phrase);
+            if let Some(content_prefix) = content_tokens.last() {
+                let expansions =
+                    self.count_prefix_terms(&searcher, code_index_schema.content, content_prefix)?;
+                // Multi-term prefix phrases are capped by tantivy, single terms expand fully.
+                cost.expanded_terms += if content_tokens.len() > 1 {
+                    expansions.min(PHRASE_PREFIX_MAX_EXPANSIONS)
+                } else {
+                    expansions
+                };
+            }
+            cost.expanded_terms +=
+                self.count_prefix_terms(&searcher, code_index_schema.path, &phrase)?;
+            cost.expanded_terms +=
+                self.count_prefix_terms(&searcher, code_index_schema.extension, &phrase)?;
+        }
+
+        Ok(cost)
+    }
+
+    fn count_prefix_terms(
+        &self,
+        searcher: &tantivy::Searcher,
+        field: Field,
+        prefix: &str,
+    ) -> Result<u64, String> {
+        let mut count = 0;
+        for segment_reader in searcher.segment_readers() {
+            let inverted_index = segment_reader
+                .inverted_index(field)
+                .map_err(|e| format!("Failed to open inverted index: {e}"))?;
+            let mut stream = inverted_index
+                .terms()
+                .range()
+                .ge(prefix.as_bytes())
+                .into_stream()
+                .map_err(|e| format!("Failed to read term dictionary: {e}"))?;
+            while stream.advance() && stream.key().starts_with(prefix.as_bytes()) {
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// The files and directories directly in `directory`, relative to the indexed directory or
+    /// absolute, derived from the stored paths of the documents below it: directories first,
+    /// then files, each in natural order. Empty when nothing below `directory` is indexed.
+    pub fn tree(&self, directory: &str) -> Result<Vec<TreeEntry>, String> {
+        let Some(root) = &self.root else {
+            return Err(format!(
+                "Cannot list {directory}: the indexed directory is not known"
+            ));
+        };
+        let Some(directory) = directory_within(root, directory) else {
+            return Err(format!(
+                "Cannot list {directory}: it is not a directory of the indexed directory {root}"
+            ));
+        };
+        let relative = directory
+            .strip_prefix(root.trim_end_matches('/'))
+            .unwrap_or_default()
+            .trim_start_matches('/');
+
+        // Paths are read from the term dictionaries, leaving out those whose documents are all
+        // deleted, so no document is loaded
+        let field = CodeIndexSchema::new().path;
+        let prefix = format!("{directory}/");
+        let mut paths = std::collections::BTreeSet::new();
+        for segment in self.reader.searcher().segment_readers() {
+            let inverted_index = segment
+                .inverted_index(field)
+                .map_err(|e| format!("Failed to read paths: {e}"))?;
+            let mut terms = inverted_index
+                .terms()
+                .range()
+                .ge(prefix.as_bytes())
+                .lt(format!("{directory}0").as_bytes())
+                .into_stream()
+                .map_err(|e| format!("Failed to read paths: {e}"))?;
+            while terms.advance() {
+                if let Some(alive) = segment.alive_bitset() {
+                    let mut postings = inverted_index
+                        .read_postings_from_terminfo(terms.value(), IndexRecordOption::Basic)
+                        .map_err(|e| format!("Failed to read paths: {e}"))?;
+                    while postings.doc() != TERMINATED && !alive.is_alive(postings.doc()) {
+                        postings.advance();
+                    }
+                    if postings.doc() == TERMINATED {
+                        continue;
+                    }
+                }
+                if let Ok(path) = std::str::from_utf8(terms.key()) {
+                    paths.insert(path[prefix.len()..].to_string());
+                }
+            }
+        }
+
+        let mut entries: BTreeMap<&str, TreeEntry> = BTreeMap::new();
+        for path in &paths {
+            let (name, kind) = match path.split_once('/') {
+                Some((name, _)) => (name, TreeEntryKind::Directory),
+                None => (path.as_str(), TreeEntryKind::File),
+            };
+            entries
+                .entry(name)
+                .or_insert_with(|| TreeEntry {
+                    name: name.to_string(),
+                    path: match relative {
+                        "" => name.to_string(),
+                        relative => format!("{relative}/{name}"),
+                    },
+                    kind,
+                    files: 0,
+                })
+                .files += 1;
+        }
+        let mut entries: Vec<TreeEntry> = entries.into_values().collect();
+        entries.sort_by(|a, b| {
+            (a.kind == TreeEntryKind::File)
+                .cmp(&(b.kind == TreeEntryKind::File))
+                .then_with(|| natural_cmp(&a.name, &b.name))
+        });
+
+        Ok(entries)
+    }
+
+    /// Counts the documents by extension and by top-level directory, and finds the `largest`
+    /// largest files. Counts are read from the term dictionaries and sizes from the `size` fast
+    /// field; only the stored paths of the largest files are loaded. Files indexed in several
+    /// chunks count a document per chunk.
+    pub fn breakdown(&self, largest: usize) -> Result<IndexBreakdown, String> {
+        let schema = CodeIndexSchema::new();
+        let searcher = self.reader.searcher();
+        let read_error = |e: tantivy::TantivyError| format!("Failed to read the index: {e}");
+
+        let mut extensions = BTreeMap::<String, u64>::new();
+        let mut directories = BTreeMap::<String, u64>::new();
+        let root = self
+            .root
+            .as_deref()
+            .map(|root| format!("{}/", root.trim_end_matches('/')));
+        let mut sizes = Vec::new();
+        for (segment_ord, segment) in searcher.segment_readers().iter().enumerate() {
+            for_each_alive_term(segment, schema.extension, |extension, documents| {
+                *extensions.entry(extension.to_string()).or_default() += documents;
+            })
+            .map_err(read_error)?;
+            for_each_alive_term(segment, schema.path, |path, documents| {
+                let relative = root
+                    .as_deref()
+                    .and_then(|root| path.strip_prefix(root))
+                    .unwrap_or(path);
+                let directory = match relative.split_once('/') {
+                    Some((directory, _)) => directory,
+                    None => "",
+                };
+                *directories.entry(directory.to_string()).or_default() += documents;
+            })
+            .map_err(read_error)?;
+
+            // Fast fields are missing from indexes created before they were added to the schema
+            let Ok(column) = segment.fast_fields().u64(CodeIndexSchema::SIZE_FIELD) else {
+                continue;
+            };
+            for doc_id in segment.doc_ids_alive() {
+                if let Some(size) = column.first(doc_id) {
+                    sizes.push((size, tantivy::DocAddress::new(segment_ord as u32, doc_id)));
+                }
+            }
+        }
+
+        // The chunks of a file all carry its size, so paths are loaded until enough differ
+        sizes.sort_unstable_by_key(|(size, _)| std::cmp::Reverse(*size));
+        let mut largest_files: Vec<FileSize> = Vec::new();
+        for (size, address) in sizes {
+            if largest_files.len() == largest {
+                break;
+            }
+            let doc: TantivyDocument = searcher.doc(address).map_err(read_error)?;
+            let Some(path) = doc.get_first(schema.path).and_then(|value| value.as_str()) else {
+                continue;
+            };
+            if !largest_files.iter().any(|file| file.path == path) {
+                largest_files.push(FileSize {
+                    path: path.to_string(),
+                    size,
+                });
+            }
+        }
+
+        let by_documents = |counts: BTreeMap<String, u64>| {
+            let mut counts: Vec<DocumentCount> = counts
+                .into_iter()
+                .map(|(name, documents)| DocumentCount { name, documents })
+                .collect();
+            counts.sort_by_key(|count| std::cmp::Reverse(count.documents));
+            counts
+        };
+
+        Ok(IndexBreakdown {
+            extensions: by_documents(extensions),
+            directories: by_documents(directories),
+            largest_files,
+        })
+    }
+
+    /// Completes the last word of `query`, for search-as-you-type: words containing a `/` are
+    /// completed as paths relative to the indexed directory, see [`IndexSearcher::tree`], and
+    /// others from the terms of the indexed symbols and of the content of code indexes, whatever
+    /// their case. Words come as indexed, lowercased, most frequent first; counts include deleted
+    /// documents until their segment is merged.
+    pub fn suggest(&self, query: &str, limit: usize) -> Result<Vec<Suggestion>, String> {
+        let prefix = query.rsplit(char::is_whitespace).next().unwrap_or_default();
+        if prefix.is_empty() || limit == 0 {
+            return Ok(Vec::new());
+        }
+        if let Some((directory, name)) = prefix.rsplit_once('/') {
+            let name = name.to_lowercase();
+            return Ok(self
+                .tree(directory)?
+                .into_iter()
+                .filter(|entry| entry.name.to_lowercase().starts_with(&name))
+                .take(limit)
+                .map(|entry| Suggestion {
+                    text: match entry.kind {
+                        TreeEntryKind::Directory => format!("{}/", entry.path),
+                        TreeEntryKind::File => entry.path,
+                    },
+                    kind: SuggestionKind::Path,
+                    count: entry.files,
+                })
+                .collect());
+        }
+
+        // Only the code tokenizer indexes the content in whole words; n-grams and stems would
+        // make poor completions
+        let schema = self.index.schema();
+        let content = CodeIndexSchema::new().content;
+        let content_is_code = match schema.get_field_entry(content).field_type() {
+            FieldType::Str(options) => options
+                .get_indexing_options()
+                .is_some_and(|indexing| indexing.tokenizer() == CODE_TOKENIZER),
+            _ => false,
+        };
+        let fields = [
+            (
+                schema.get_field(CodeIndexSchema::SYMBOLS_FIELD).ok(),
+                SuggestionKind::Symbol,
+            ),
+            (content_is_code.then_some(content), SuggestionKind::Word),
+        ];
+
+        let prefix = prefix.to_lowercase();
+        // Documents with the word in the symbols and in the content
+        let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+        for segment in self.reader.searcher().segment_readers() {
+            for (field, kind) in fields {
+                let Some(field) = field else {
+                    continue;
+                };
+                let inverted_index = segment
+                    .inverted_index(field)
+                    .map_err(|e| format!("Failed to read terms: {e}"))?;
+                let mut terms = inverted_index
+                    .terms()
+                    .range()
+                    .ge(prefix.as_bytes())
+                    .into_stream()
+                    .map_err(|e| format!("Failed to read terms: {e}"))?;
+                for _ in 0..MAX_SUGGESTION_TERMS {
+                    if !terms.advance() || !terms.key().starts_with(prefix.as_bytes()) {
+                        break;
+                    }
+                    let Ok(text) = std::str::from_utf8(terms.key()) else {
+                        continue;
+                    };
+                    let count = counts.entry(text.to_string()).or_default();
+                    let docs = terms.value().doc_freq as usize;
+                    match kind {
+                        SuggestionKind::Symbol => count.0 += docs,
+                        _ => count.1 += docs,
+                    }
+                }
+            }
+        }
+
+        let mut suggestions: Vec<Suggestion> = counts
+            .into_iter()
+            .map(|(text, (symbol_docs, content_docs))| Suggestion {
+                text,
+                kind: match symbol_docs {
+                    0 => SuggestionKind::Word,
+                    _ => SuggestionKind::Symbol,
+                },
+                count: content_docs.max(symbol_docs),
+            })
+            .collect();
+        suggestions.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.text.len().cmp(&b.text.len()))
+                .then_with(|| a.text.cmp(&b.text))
+        });
+        suggestions.truncate(limit);
+
+        Ok(suggestions)
+    }
+
+    /// Returns the content of the document at `path`, or `None` when it is not indexed. The
+    /// chunks of files indexed in several documents are put back together.
+    pub fn file_content(&self, path: &str) -> Result<Option<String>, String> {
+        let code_index_schema = CodeIndexSchema::new();
+        let query = TermQuery::new(
+            Term::from_field_text(code_index_schema.path, path),
+            IndexRecordOption::Basic,
+        );
+
+        let searcher = self.reader.searcher();
+        let doc_addresses = searcher
+            .search(&query, &DocSetCollector)
+            .map_err(|e| format!("Search failed: {e}"))?;
+        let mut chunks = Vec::with_capacity(doc_addresses.len());
+        for doc_address in doc_addresses {
+            let doc = searcher
+                .doc::<TantivyDocument>(doc_address)
+                .map_err(|e| format!("Failed to retrieve document: {e}"))?;
+            chunks.push((self.chunk_start(&doc).unwrap_or_default().offset, doc));
+        }
+
+        match chunks.as_mut_slice() {
+            [] => Ok(None),
+            [(_, doc)] => Ok(self.content(doc, path)),
+            chunks if self.stores_content => {
+                chunks.sort_by_key(|(offset, _)| *offset);
+                Ok(chunks
+                    .iter()
+                    .map(|(_, doc)| self.content(doc, path))
+                    .collect())
+            }
+            _ => Ok(read_text(path)),
+        }
+    }
+
+    /// Parses `query` and applies the filters of `options` to it.
+    fn build_query(&self, query: &str, options: &SearchOptions) -> Result<BuiltQuery, String> {
+        let code_index_schema = CodeIndexSchema::new();
+        let (parsed_query, snippet_field) = if options.exact {
+            self.literal_query(query)?
+        } else {
+            (
+                self.parse_query(query, options.boosts)?,
+                code_index_schema.content,
+            )
+        };
+        let filtered_query = self.filter_changed_since(
+            self.filter_branches(
+                self.filter_author(
+                    self.filter_excluded(
+                        self.filter_within(
+                            self.filter_modified(
+                                self.filter_languages(
+                                    parsed_query.box_clone(),
+                                    &options.languages,
+                                )?,
+                                options,
+                            )?,
+                            options.within.as_deref(),
+                        )?,
+                        options,
+                    )?,
+                    options.author.as_deref(),
+                )?,
+                &options.branches,
+            )?,
+            options.changed_since.as_deref(),
+        )?;
+
+        Ok((parsed_query, filtered_query, snippet_field))
+    }
+
+    pub fn search_with_options(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResultItem>, SearchError> {
+        // Literal queries are plain phrases, which expand to nothing
+        if let Some(max_query_cost) = options.max_query_cost.filter(|_| !options.exact) {
+            let cost = self
+                .estimate_cost(query)
+                .map_err(SearchError::InvalidRequest)?;
+            if cost.expanded_terms > max_query_cost {
+                return Err(SearchError::TooExpensive(format!(
+                    "Query '{query}' is too expensive: it expands to {} terms, the limit is \
+                     {max_query_cost}. Use a more specific query or force its execution",
+                    cost.expanded_terms
+                )));
+            }
+        }
+
+        let (highlight_prefix, highlight_postfix) = options
+            .highlight_markup()
+            .map_err(SearchError::InvalidRequest)?;
+        if options.snippet_chars == 0 || options.max_fragments == 0 {
+            return Err(SearchError::InvalidRequest(
+                "Snippets need at least 1 character and 1 fragment".to_string(),
+            ));
+        }
 
+        let code_index_schema = CodeIndexSchema::new();
+        let (parsed_query, filtered_query, snippet_field) = self
+            .build_query(query, options)
+            .map_err(SearchError::InvalidRequest)?;
         let searcher = self.reader.searcher();
         let top_docs = searcher
             .search(
-                &parsed_query,
+                &filtered_query,
                 &tantivy::collector::TopDocs::with_limit(10000),
             )
             .map_err(|e| format!("Search failed: {e}"))?;
 
-        let snippet_generator =
-            SnippetGenerator::create(&searcher, &parsed_query, code_index_schema.content).unwrap();
+        let mut snippet_generator =
+            SnippetGenerator::create(&searcher, &parsed_query, snippet_field).unwrap();
+        snippet_generator.set_max_num_chars(options.snippet_chars);
+
+        // Missing from indexes created before languages were detected
+        let language_field = self
+            .index
+            .schema()
+            .get_field(CodeIndexSchema::LANGUAGE_FIELD)
+            .ok();
+        let schema = self.index.schema();
+        let metadata_fields: Vec<_> = CodeIndexSchema::stored_metadata_fields(&schema)
+            .iter()
+            .filter_map(|name| Some((*name, schema.get_field(name).ok()?)))
+            .collect();
+        let duplicate_of_field = schema.get_field(CodeIndexSchema::DUPLICATE_OF_FIELD).ok();
+        let last_author_field = schema.get_field(CodeIndexSchema::LAST_AUTHOR_FIELD).ok();
+        let last_commit_field = schema.get_field(CodeIndexSchema::LAST_COMMIT_FIELD).ok();
+        let branch_field = schema.get_field(CodeIndexSchema::BRANCH_FIELD).ok();
 
         let mut results = Vec::new();
         for (_score, doc_address) in top_docs {
@@ -65,22 +1365,1338 @@ impl IndexSearcher {
                 .unwrap()
                 .as_str()
                 .unwrap();
-            let snippet = snippet_generator.snippet_from_doc(&doc);
+            // The literal field is not stored, its snippets are cut from the content either way
+            let snippets: Vec<String> = snippet_fragments(
+                &snippet_generator,
+                &self.content(&doc, path).unwrap_or_default(),
+                options.snippet_chars,
+                options.max_fragments,
+            )
+            .into_iter()
+            .map(|mut fragment| {
+                fragment.set_snippet_prefix_postfix(&highlight_prefix, &highlight_postfix);
+                fragment.to_html()
+            })
+            .collect();
             let extension = doc
                 .get_first(code_index_schema.extension)
                 .unwrap()
                 .as_str()
                 .unwrap();
             let score = _score;
+            let language = language_field
+                .and_then(|field| doc.get_first(field))
+                .and_then(|value| value.as_str())
+                .map(str::to_string);
+
+            // Fast fields are missing from indexes created before they were added to the schema.
+            let fast_fields = searcher
+                .segment_reader(doc_address.segment_ord)
+                .fast_fields();
+            let size = fast_fields
+                .u64(CodeIndexSchema::SIZE_FIELD)
+                .ok()
+                .and_then(|column| column.first(doc_address.doc_id))
+                .unwrap_or_default();
+            let last_modified = fast_fields
+                .date(CodeIndexSchema::LAST_MODIFIED_FIELD)
+                .ok()
+                .and_then(|column| column.first(doc_address.doc_id))
+                .map(|date| date.into_timestamp_secs())
+                .unwrap_or_default();
+            let metadata = metadata_fields
+                .iter()
+                .filter_map(|(name, field)| {
+                    let values: Vec<String> = doc
+                        .get_all(*field)
+                        .filter_map(|value| {
+                            value
+                                .as_str()
+                                .map(str::to_string)
+                                .or_else(|| value.as_u64().map(|number| number.to_string()))
+                        })
+                        .collect();
+                    (!values.is_empty()).then(|| (name.to_string(), values.join(",")))
+                })
+                .collect();
+            let duplicates = match duplicate_of_field {
+                Some(field) => duplicate_paths(&searcher, field, path)?,
+                None => Vec::new(),
+            };
+            let stored = |field: Option<Field>| {
+                field
+                    .and_then(|field| doc.get_first(field))
+                    .and_then(|value| value.as_str())
+                    .map(str::to_string)
+            };
 
             results.push(SearchResultItem {
                 path: path.to_string(),
-                snippet: snippet.to_html().to_string(),
+                snippet: snippets.join(FRAGMENT_SEPARATOR),
                 extension: extension.to_string(),
                 score,
+                size,
+                last_modified,
+                language,
+                index_name: None,
+                metadata,
+                duplicates,
+                last_author: stored(last_author_field),
+                last_commit: stored(last_commit_field),
+                branch: stored(branch_field),
+                snippets,
             });
         }
 
         Ok(results)
     }
 }
+
+/// The best fragments of `text` for `generator`, at most `max_fragments` of them, which do not
+/// overlap, in the order of the text. The best fragment of the whole text comes first, then the
+/// best ones of the text left on either side of it, and so on.
+///
+/// Each fragment searched for tokenizes the text left around it again, so [`ALL_FRAGMENTS`] cuts
+/// the text once instead, in blocks of whole lines of at most `max_chars` bytes (longer lines
+/// are blocks of their own), and takes the best fragment of every block with a match.
+fn snippet_fragments(
+    generator: &SnippetGenerator,
+    text: &str,
+    max_chars: usize,
+    max_fragments: usize,
+) -> Vec<Snippet> {
+    if max_fragments == ALL_FRAGMENTS {
+        let mut blocks = Vec::new();
+        let mut block = 0..0;
+        for line in text.split_inclusive('\n') {
+            if !block.is_empty() && block.len() + line.len() > max_chars {
+                blocks.push(block.clone());
+                block = block.end..block.end;
+            }
+            block.end += line.len();
+        }
+        blocks.push(block);
+        return blocks
+            .into_iter()
+            .map(|block| generator.snippet(&text[block]))
+            .filter(|snippet| !snippet.is_empty())
+            .collect();
+    }
+
+    let mut fragments = Vec::new();
+    let mut pieces = VecDeque::new();
+    pieces.push_back(0..text.len());
+    while fragments.len() < max_fragments {
+        let Some(piece) = pieces.pop_front() else {
+            break;
+        };
+        let snippet = generator.snippet(&text[piece.clone()]);
+        if snippet.is_empty() {
+            continue;
+        }
+
+        let start = piece.start + text[piece.clone()].find(snippet.fragment()).unwrap_or(0);
+        let end = start + snippet.fragment().len();
+        pieces.push_back(piece.start..start);
+        pieces.push_back(end..piece.end);
+        fragments.push((start, snippet));
+    }
+    fragments.sort_by_key(|(start, _)| *start);
+
+    fragments.into_iter().map(|(_, snippet)| snippet).collect()
+}
+
+/// The paths of the files indexed as duplicates of the file at `path`, sorted.
+fn duplicate_paths(searcher: &Searcher, field: Field, path: &str) -> Result<Vec<String>, String> {
+    let query = TermQuery::new(Term::from_field_text(field, path), IndexRecordOption::Basic);
+    let path_field = CodeIndexSchema::new().path;
+    let mut paths = Vec::new();
+    for address in searcher
+        .search(&query, &DocSetCollector)
+        .map_err(|e| format!("Search failed: {e}"))?
+    {
+        let doc = searcher
+            .doc::<TantivyDocument>(address)
+            .map_err(|e| format!("Failed to retrieve document: {e}"))?;
+        if let Some(path) = doc.get_first(path_field).and_then(|value| value.as_str()) {
+            paths.push(path.to_string());
+        }
+    }
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Parses the fragment count given to `--max-fragments`: a number of at least 1, or `all` for
+/// [`ALL_FRAGMENTS`].
+pub fn parse_max_fragments(text: &str) -> Result<usize, String> {
+    if text.eq_ignore_ascii_case("all") {
+        return Ok(ALL_FRAGMENTS);
+    }
+    match text.parse::<usize>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!(
+            "Invalid fragment count '{text}'. Use a number of at least 1, or all"
+        )),
+    }
+}
+
+/// Parses the time given to `--modified-after` and `--modified-before` into seconds since the
+/// Unix epoch: an RFC 3339 timestamp (`2024-05-01T12:00:00Z`), a UTC date (`2024-05-01`) or an
+/// age relative to now in minutes, hours, days or weeks (`30m`, `12h`, `7d`, `2w`).
+pub fn parse_modified_time(text: &str) -> Result<i64, String> {
+    use tantivy::time::format_description::well_known::Rfc3339;
+    use tantivy::time::{Date, Month, OffsetDateTime};
+
+    let invalid = || {
+        format!(
+            "Invalid time '{text}'. Use an RFC 3339 timestamp (2024-05-01T12:00:00Z), a date \
+             (2024-05-01) or an age such as 30m, 12h, 7d or 2w"
+        )
+    };
+
+    if let Ok(time) = OffsetDateTime::parse(text, &Rfc3339) {
+        return Ok(time.unix_timestamp());
+    }
+
+    let mut date_parts = text.splitn(3, '-');
+    if let (Some(year), Some(month), Some(day)) =
+        (date_parts.next(), date_parts.next(), date_parts.next())
+    {
+        let year = year.parse().map_err(|_| invalid())?;
+        let month = month
+            .parse::<u8>()
+            .ok()
+            .and_then(|month| Month::try_from(month).ok())
+            .ok_or_else(invalid)?;
+        let day = day.parse().map_err(|_| invalid())?;
+        let date = Date::from_calendar_date(year, month, day).map_err(|_| invalid())?;
+        return Ok(date.midnight().assume_utc().unix_timestamp());
+    }
+
+    let unit_secs = match text.chars().last() {
+        Some('m') => 60,
+        Some('h') => 60 * 60,
+        Some('d') => 24 * 60 * 60,
+        Some('w') => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let amount: i64 = text[..text.len() - 1].parse().map_err(|_| invalid())?;
+    if amount < 0 {
+        return Err(invalid());
+    }
+    Ok(OffsetDateTime::now_utc().unix_timestamp() - amount * unit_secs)
+}
+
+/// Largest directory [`search_directory`] indexes on the fly before asking for a real index.
+pub const MAX_EPHEMERAL_FILES: usize = 5000;
+
+/// Searches the files under `root` through a throwaway in-memory index.
+///
+/// Used for directories no index covers; every call rescans and reindexes `root`, so it refuses
+/// directories with more than [`MAX_EPHEMERAL_FILES`] files.
+pub fn search_directory(
+    root: &Path,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<Vec<SearchResultItem>, String> {
+    let files = scan(&root.to_string_lossy(), &IndexingOptions::default());
+    if files.len() > MAX_EPHEMERAL_FILES {
+        return Err(format!(
+            "{} contains {} files, too many to search without an index (limit {MAX_EPHEMERAL_FILES})",
+            root.display(),
+            files.len()
+        ));
+    }
+
+    let schema = CodeIndexSchema::new().schema;
+    let index = Index::create_in_ram(schema.clone());
+    register_tokenizers(&index);
+
+    let mut writer: tantivy::IndexWriter = index
+        .writer_with_num_threads(1, 50 * 1024 * 1024)
+        .map_err(|e| format!("Failed to create in-memory index writer: {e}"))?;
+    for document in files
+        .iter()
+        .flat_map(|file| CodeIndexDocument::from_file(file, None, false))
+    {
+        writer
+            .add_document(document.to_tantivy_document(&schema))
+            .map_err(|e| format!("Failed to add document to in-memory index: {e}"))?;
+    }
+    writer
+        .commit()
+        .map_err(|e| format!("Failed to commit in-memory index: {e}"))?;
+
+    Ok(IndexSearcher::new(index)?
+        .with_root(&normalize_path(root))
+        .search_with_options(query, options)?)
+}
+
+/// The paths stored below `directory`, which sort from `directory/` to before `directory0`, `0`
+/// following `/`.
+fn paths_below(directory: &str) -> RangeQuery {
+    let field = CodeIndexSchema::new().path;
+    RangeQuery::new(
+        Bound::Included(Term::from_field_text(field, &format!("{directory}/"))),
+        Bound::Excluded(Term::from_field_text(field, &format!("{directory}0"))),
+    )
+}
+
+/// The stored path of the directory `within`, relative to `root` or absolute; `None` when it is
+/// not `root` or below it.
+fn directory_within(root: &str, within: &str) -> Option<String> {
+    let within = to_slashes(within);
+    if Path::new(&within).is_absolute() {
+        let directory = normalize_path(Path::new(&within));
+        return is_within(&directory, root).then(|| directory.trim_end_matches('/').to_string());
+    }
+
+    let mut directory = root.trim_end_matches('/').to_string();
+    for component in within.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => return None,
+            name => {
+                directory.push('/');
+                directory.push_str(name);
+            }
+        }
+    }
+
+    Some(directory)
+}
+
+/// Calls `f` with every term of `field` in `segment` and the number of its documents that are
+/// not deleted, leaving out the terms whose documents all are.
+fn for_each_alive_term(
+    segment: &tantivy::SegmentReader,
+    field: Field,
+    mut f: impl FnMut(&str, u64),
+) -> tantivy::Result<()> {
+    let inverted_index = segment.inverted_index(field)?;
+    let mut terms = inverted_index.terms().stream()?;
+    while terms.advance() {
+        let documents = match segment.alive_bitset() {
+            Some(alive) => {
+                let mut postings = inverted_index
+                    .read_postings_from_terminfo(terms.value(), IndexRecordOption::Basic)?;
+                let mut documents = 0;
+                while postings.doc() != TERMINATED {
+                    if alive.is_alive(postings.doc()) {
+                        documents += 1;
+                    }
+                    postings.advance();
+                }
+                documents
+            }
+            None => terms.value().doc_freq as u64,
+        };
+        if documents > 0 {
+            if let Ok(term) = std::str::from_utf8(terms.key()) {
+                f(term, documents);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `field` is indexed with positions, which the query parser needs to search phrases in
+/// it. Symbols were indexed without them before they were searched by default.
+fn has_positions(schema: &Schema, field: Field) -> bool {
+    match schema.get_field_entry(field).field_type() {
+        FieldType::Str(options) => options
+            .get_indexing_options()
+            .is_some_and(|indexing| indexing.index_option().has_positions()),
+        _ => false,
+    }
+}
+
+/// Returns the content of every `"..."*` prefix phrase in `query`.
+fn prefix_phrases(query: &str) -> Vec<String> {
+    let mut phrases = Vec::new();
+    let mut rest = query;
+    while let Some(start) = rest.find('"') {
+        let after_quote = &rest[start + 1..];
+        let Some(end) = after_quote.find('"') else {
+            break;
+        };
+        let phrase = &after_quote[..end];
+        rest = &after_quote[end + 1..];
+        if rest.starts_with('*') {
+            phrases.push(phrase.to_string());
+        }
+    }
+
+    phrases
+}
+
+fn code_tokens(text: &str) -> Vec<String> {
+    let mut tokenizer = CodeTokenizer::for_identifier_queries();
+    let mut token_stream = tokenizer.token_stream(text);
+    let mut tokens = Vec::new();
+    while token_stream.advance() {
+        tokens.push(token_stream.token().text.clone());
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::change::FileIndexMetadata;
+    use crate::options::{ContentStorage, ContentTokenizer};
+
+    #[test]
+    fn test_prefix_phrases() {
+        assert_eq!(prefix_phrases(r#""a"*"#), vec!["a"]);
+        assert_eq!(
+            prefix_phrases(r#"foo "bar baz"* "qux" content:"fn par"*"#),
+            vec!["bar baz", "fn par"]
+        );
+        assert!(prefix_phrases(r#"foo "bar""#).is_empty());
+        assert!(prefix_phrases(r#"foo "bar"#).is_empty());
+    }
+
+    #[test]
+    fn test_highlight_markup() {
+        let options = SearchOptions::default();
+        assert_eq!(
+            options.highlight_markup().unwrap(),
+            ("<b>".to_string(), "</b>".to_string())
+        );
+
+        let options = SearchOptions {
+            highlight_tag: "mark".to_string(),
+            highlight_class: Some("beetle-match hit".to_string()),
+            ..SearchOptions::default()
+        };
+        assert_eq!(
+            options.highlight_markup().unwrap(),
+            (
+                "<mark class=\"beetle-match hit\">".to_string(),
+                "</mark>".to_string()
+            )
+        );
+
+        let options = SearchOptions {
+            highlight_tag: "script><b".to_string(),
+            ..SearchOptions::default()
+        };
+        assert!(options.highlight_markup().is_err());
+
+        let options = SearchOptions {
+            highlight_tag: "script".to_string(),
+            ..SearchOptions::default()
+        };
+        assert!(options.highlight_markup().is_err());
+
+        let options = SearchOptions {
+            highlight_class: Some("x\" onclick=\"alert(1)".to_string()),
+            ..SearchOptions::default()
+        };
+        assert!(options.highlight_markup().is_err());
+    }
+
+    #[test]
+    fn test_snippets_of_unstored_content_are_read_from_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lib.rs");
+        std::fs::write(&path, "fn parse_header() {}").unwrap();
+        let path = path.to_string_lossy().to_string();
+        let file = FileIndexMetadata {
+            path: path.clone(),
+            size: 20,
+            modified_time: 0,
+            content_hash: None,
+        };
+
+        let schema = CodeIndexSchema::with_options(
+            &IndexingOptions::default().with_content_storage(ContentStorage::None),
+        )
+        .schema;
+        let index = Index::create_in_ram(schema.clone());
+        register_tokenizers(&index);
+        let mut writer: tantivy::IndexWriter =
+            index.writer_with_num_threads(1, 15_000_000).unwrap();
+        writer
+            .add_document(
+                CodeIndexDocument::from_file(&file, None, false)
+                    .next()
+                    .unwrap()
+                    .to_tantivy_document(&schema),
+            )
+            .unwrap();
+        writer.commit().unwrap();
+
+        let searcher = IndexSearcher::new(index).unwrap();
+        let results = searcher.search("header").unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(
+            results[0].snippet.contains("<b>header</b>"),
+            "{}",
+            results[0].snippet
+        );
+        assert_eq!(
+            searcher.file_content(&path).unwrap().as_deref(),
+            Some("fn parse_header() {}")
+        );
+    }
+
+    #[test]
+    fn test_snippet_length_and_fragments() {
+        let schema = CodeIndexSchema::new().schema;
+        let index = Index::create_in_ram(schema.clone());
+        register_tokenizers(&index);
+        let mut writer: tantivy::IndexWriter =
+            index.writer_with_num_threads(1, 15_000_000).unwrap();
+        let filler = "let unrelated = 0;\n".repeat(20);
+        let content = format!(
+            "fn first_parse() {{}}\n{filler}fn second_parse() {{}}\n{filler}fn third_parse() {{}}\n"
+        );
+        let document = CodeIndexDocument::from_content(
+            "src/parse.rs".to_string(),
+            content,
+            std::time::SystemTime::now(),
+        );
+        writer
+            .add_document(document.to_tantivy_document(&schema))
+            .unwrap();
+        writer.commit().unwrap();
+        let searcher = IndexSearcher::new(index).unwrap();
+        let result = |snippet_chars, max_fragments| {
+            let options = SearchOptions {
+                snippet_chars,
+                max_fragments,
+                ..SearchOptions::default()
+            };
+            searcher
+                .search_with_options("parse", &options)
+                .unwrap()
+                .remove(0)
+        };
+        let snippet = |snippet_chars, max_fragments| result(snippet_chars, max_fragments).snippet;
+
+        // One fragment by default, of at most the given length
+        assert_eq!(snippet(DEFAULT_SNIPPET_CHARS, 1).matches("<b>").count(), 1);
+        assert_eq!(snippet(10, 1), "<b>parse</b>");
+
+        // The best fragments in file order, as many as there are
+        let fragments = snippet(20, 5);
+        let fragments: Vec<_> = fragments.split(FRAGMENT_SEPARATOR).collect();
+        assert_eq!(fragments.len(), 3);
+        assert!(fragments[0].contains("first_<b>parse</b>"), "{fragments:?}");
+        assert!(fragments[2].contains("third_<b>parse</b>"), "{fragments:?}");
+
+        // Every fragment with a match, listed apart
+        let all = result(DEFAULT_SNIPPET_CHARS, ALL_FRAGMENTS);
+        assert_eq!(all.snippets.len(), 3);
+        assert_eq!(all.snippets.join(FRAGMENT_SEPARATOR), all.snippet);
+        assert!(all.snippets[1].contains("second_<b>parse</b>"));
+
+        let options = SearchOptions {
+            max_fragments: 0,
+            ..SearchOptions::default()
+        };
+        assert!(searcher.search_with_options("parse", &options).is_err());
+    }
+
+    #[test]
+    fn test_search_within() {
+        let schema = CodeIndexSchema::new().schema;
+        let index = Index::create_in_ram(schema.clone());
+        register_tokenizers(&index);
+        let mut writer: tantivy::IndexWriter =
+            index.writer_with_num_threads(1, 15_000_000).unwrap();
+        for path in [
+            "/repo/services/api/main.rs",
+            "/repo/services/api/handlers/user.rs",
+            "/repo/services/apiary/main.rs",
+            "/repo/web/main.rs",
+            "/repo/web/main.md",
+        ] {
+            let document = CodeIndexDocument::from_content(
+                path.to_string(),
+                "fn main() {}".to_string(),
+                std::time::SystemTime::now(),
+            );
+            writer
+                .add_document(document.to_tantivy_document(&schema))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        let searcher = IndexSearcher::new(index).unwrap().with_root("/repo");
+        let paths = |within: &str| {
+            let options = SearchOptions {
+                within: Some(within.to_string()),
+                ..SearchOptions::default()
+            };
+            let mut paths: Vec<_> = searcher
+                .search_with_options("main", &options)
+                .map(|results| results.into_iter().map(|result| result.path).collect())?;
+            paths.sort();
+            Ok::<Vec<String>, String>(paths)
+        };
+
+        // Only the directory itself, not its siblings sharing the prefix
+        assert_eq!(
+            paths("services/api").unwrap(),
+            vec![
+                "/repo/services/api/handlers/user.rs",
+                "/repo/services/api/main.rs"
+            ]
+        );
+        assert_eq!(paths("./web/").unwrap().len(), 2);
+        assert_eq!(paths("/repo/web").unwrap().len(), 2);
+        assert_eq!(paths(".").unwrap().len(), 5);
+        assert!(paths("missing").unwrap().is_empty());
+
+        // Directories outside the indexed one
+        assert!(paths("../elsewhere").is_err());
+        assert!(paths("/elsewhere").is_err());
+
+        // Extensions and paths left out
+        let excluded = |extensions: &[&str], paths: &[&str]| {
+            let options = SearchOptions {
+                excluded_extensions: extensions.iter().map(|ext| ext.to_string()).collect(),
+                excluded_paths: paths.iter().map(|path| path.to_string()).collect(),
+                ..SearchOptions::default()
+            };
+            let mut paths: Vec<_> = searcher
+                .search_with_options("main", &options)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.path)
+                .collect();
+            paths.sort();
+            paths
+        };
+        assert_eq!(excluded(&[".rs"], &[]), vec!["/repo/web/main.md"]);
+        assert_eq!(excluded(&["md"], &[]).len(), 4);
+        assert_eq!(
+            excluded(&[], &["services/api", "web/main.rs"]),
+            vec!["/repo/services/apiary/main.rs", "/repo/web/main.md"]
+        );
+    }
+
+    #[test]
+    fn test_breakdown() {
+        let schema = CodeIndexSchema::new().schema;
+        let index = Index::create_in_ram(schema.clone());
+        register_tokenizers(&index);
+        let mut writer: tantivy::IndexWriter =
+            index.writer_with_num_threads(1, 15_000_000).unwrap();
+        for (path, content) in [
+            ("/repo/Makefile", "all:"),
+            ("/repo/src/main.rs", "fn main() { run(); }"),
+            ("/repo/src/lib.rs", "pub fn run() {}"),
+            ("/repo/src/parser/mod.rs", "mod expr;"),
+            ("/repo/web/app.ts", "export const app = 1;"),
+            (
+                "/repo/web/big.ts",
+                "export const big = [1, 2, 3, 4, 5, 6, 7, 8, 9];",
+            ),
+        ] {
+            let document = CodeIndexDocument::from_content(
+                path.to_string(),
+                content.to_string(),
+                std::time::SystemTime::now(),
+            );
+            writer
+                .add_document(document.to_tantivy_document(&schema))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        writer.delete_term(Term::from_field_text(
+            CodeIndexSchema::new().path,
+            "/repo/web/app.ts",
+        ));
+        writer.commit().unwrap();
+        let searcher = IndexSearcher::new(index).unwrap().with_root("/repo");
+        let counts = |counts: Vec<DocumentCount>| {
+            counts
+                .into_iter()
+                .map(|count| (count.name, count.documents))
+                .collect::<Vec<_>>()
+        };
+
+        // Deleted documents are not counted
+        let breakdown = searcher.breakdown(2).unwrap();
+        assert_eq!(
+            counts(breakdown.extensions),
+            vec![
+                ("rs".to_string(), 3),
+                ("".to_string(), 1),
+                ("ts".to_string(), 1)
+            ]
+        );
+        assert_eq!(
+            counts(breakdown.directories),
+            vec![
+                ("src".to_string(), 3),
+                ("".to_string(), 1),
+                ("web".to_string(), 1)
+            ]
+        );
+        let largest: Vec<_> = breakdown
+            .largest_files
+            .iter()
+            .map(|file| (file.path.as_str(), file.size))
+            .collect();
+        assert_eq!(
+            largest,
+            vec![("/repo/web/big.ts", 47), ("/repo/src/main.rs", 20)]
+        );
+    }
+
+    #[test]
+    fn test_tree() {
+        let schema = CodeIndexSchema::new().schema;
+        let index = Index::create_in_ram(schema.clone());
+        register_tokenizers(&index);
+        let mut writer: tantivy::IndexWriter =
+            index.writer_with_num_threads(1, 15_000_000).unwrap();
+        for path in [
+            "/repo/README.md",
+            "/repo/src/main.rs",
+            "/repo/src/lib.rs",
+            "/repo/src/parser/mod.rs",
+            "/repo/src/parser/expr.rs",
+            "/repo/src10/old.rs",
+            "/repo/src2/new.rs",
+            "/repo/web/app.ts",
+        ] {
+            let document = CodeIndexDocument::from_content(
+                path.to_string(),
+                "fn main() {}".to_string(),
+                std::time::SystemTime::now(),
+            );
+            writer
+                .add_document(document.to_tantivy_document(&schema))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        writer.delete_term(Term::from_field_text(
+            CodeIndexSchema::new().path,
+            "/repo/web/app.ts",
+        ));
+        writer.commit().unwrap();
+        let searcher = IndexSearcher::new(index).unwrap().with_root("/repo");
+        let tree = |directory: &str| {
+            searcher.tree(directory).map(|entries| {
+                entries
+                    .into_iter()
+                    .map(|entry| (entry.path, entry.kind, entry.files))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        // Directories first, counting the files below them; deleted files are gone
+        assert_eq!(
+            tree("").unwrap(),
+            vec![
+                ("src".to_string(), TreeEntryKind::Directory, 4),
+                ("src2".to_string(), TreeEntryKind::Directory, 1),
+                ("src10".to_string(), TreeEntryKind::Directory, 1),
+                ("README.md".to_string(), TreeEntryKind::File, 1),
+            ]
+        );
+        assert_eq!(
+            tree("src/").unwrap(),
+            vec![
+                ("src/parser".to_string(), TreeEntryKind::Directory, 2),
+                ("src/lib.rs".to_string(), TreeEntryKind::File, 1),
+                ("src/main.rs".to_string(), TreeEntryKind::File, 1),
+            ]
+        );
+        assert_eq!(tree("/repo/src/parser").unwrap().len(), 2);
+        assert!(tree("web").unwrap().is_empty());
+        assert!(tree("../elsewhere").is_err());
+    }
+
+    #[test]
+    fn test_suggest() {
+        let schema = CodeIndexSchema::new().schema;
+        let index = Index::create_in_ram(schema.clone());
+        register_tokenizers(&index);
+        let mut writer: tantivy::IndexWriter =
+            index.writer_with_num_threads(1, 15_000_000).unwrap();
+        for (path, content) in [
+            (
+                "/repo/src/parser.rs",
+                "fn parse_request() {}\nlet part = 1;",
+            ),
+            ("/repo/src/main.rs", "fn main() { parse_request(); }"),
+            ("/repo/src/params.rs", "// parse the params"),
+            ("/repo/README.md", "Parsing"),
+        ] {
+            let document = CodeIndexDocument::from_content(
+                path.to_string(),
+                content.to_string(),
+                std::time::SystemTime::now(),
+            );
+            writer
+                .add_document(document.to_tantivy_document(&schema))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        let searcher = IndexSearcher::new(index).unwrap().with_root("/repo");
+        let suggest = |query: &str, limit| {
+            searcher
+                .suggest(query, limit)
+                .unwrap()
+                .into_iter()
+                .map(|suggestion| (suggestion.text, suggestion.kind, suggestion.count))
+                .collect::<Vec<_>>()
+        };
+
+        // The most frequent first, definitions told apart, whatever the case of the query
+        assert_eq!(
+            suggest("fn PAR", 3),
+            vec![
+                ("parse".to_string(), SuggestionKind::Symbol, 3),
+                ("parse_request".to_string(), SuggestionKind::Symbol, 2),
+                ("part".to_string(), SuggestionKind::Word, 1),
+            ]
+        );
+        assert_eq!(suggest("parsing", 10).len(), 1);
+        assert!(suggest("zzz", 10).is_empty());
+        assert!(suggest("", 10).is_empty());
+
+        // Paths
+        assert_eq!(
+            suggest("src/pa", 10),
+            vec![
+                ("src/params.rs".to_string(), SuggestionKind::Path, 1),
+                ("src/parser.rs".to_string(), SuggestionKind::Path, 1),
+            ]
+        );
+        assert_eq!(
+            suggest("/s", 10),
+            vec![("src/".to_string(), SuggestionKind::Path, 3)]
+        );
+    }
+
+    #[test]
+    fn test_language_filter_and_counts() {
+        let schema = CodeIndexSchema::new().schema;
+        let index = Index::create_in_ram(schema.clone());
+        register_tokenizers(&index);
+        let mut writer: tantivy::IndexWriter =
+            index.writer_with_num_threads(1, 15_000_000).unwrap();
+        for (path, content) in [
+            ("src/main.rs", "fn main() {}"),
+            ("src/lib.rs", "pub fn main_loop() {}"),
+            ("bin/main", "#!/usr/bin/env python3\nmain()"),
+            ("README", "main"),
+        ] {
+            let document = CodeIndexDocument::from_content(
+                path.to_string(),
+                content.to_string(),
+                std::time::SystemTime::now(),
+            );
+            writer
+                .add_document(document.to_tantivy_document(&schema))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        let searcher = IndexSearcher::new(index).unwrap();
+
+        let results = searcher.search("main").unwrap();
+        assert_eq!(results.len(), 4);
+        assert_eq!(
+            language_counts(&results),
+            vec![
+                LanguageCount {
+                    language: "rust".to_string(),
+                    count: 2
+                },
+                LanguageCount {
+                    language: "python".to_string(),
+                    count: 1
+                },
+            ]
+        );
+
+        let options = |languages: &[&str]| SearchOptions {
+            languages: languages
+                .iter()
+                .map(|language| language.to_string())
+                .collect(),
+            ..SearchOptions::default()
+        };
+        let results = searcher
+            .search_with_options("main", &options(&["py"]))
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, "bin/main");
+        assert_eq!(results[0].language.as_deref(), Some("python"));
+        let results = searcher
+            .search_with_options("main", &options(&["rust", "python"]))
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(searcher
+            .search_with_options("main", &options(&["go"]))
+            .unwrap()
+            .is_empty());
+        assert!(searcher
+            .search_with_options("main", &options(&["klingon"]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_modified_filters() {
+        let schema = CodeIndexSchema::new().schema;
+        let index = Index::create_in_ram(schema.clone());
+        register_tokenizers(&index);
+        let mut writer: tantivy::IndexWriter =
+            index.writer_with_num_threads(1, 15_000_000).unwrap();
+        for (path, modified) in [
+            ("old.rs", "2023-06-01"),
+            ("new.rs", "2024-05-01"),
+            ("newest.rs", "2024-05-01T12:00:00Z"),
+        ] {
+            let modified = parse_modified_time(modified).unwrap() as u64;
+            let document = CodeIndexDocument::from_content(
+                path.to_string(),
+                "fn main() {}".to_string(),
+                std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(modified),
+            );
+            writer
+                .add_document(document.to_tantivy_document(&schema))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        let searcher = IndexSearcher::new(index).unwrap();
+        let paths = |after: Option<&str>, before: Option<&str>| {
+            let options = SearchOptions {
+                modified_after: after.map(|time| parse_modified_time(time).unwrap()),
+                modified_before: before.map(|time| parse_modified_time(time).unwrap()),
+                ..SearchOptions::default()
+            };
+            let mut paths: Vec<_> = searcher
+                .search_with_options("main", &options)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.path)
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        assert_eq!(paths(None, None).len(), 3);
+        // The lower bound is inclusive and the upper bound exclusive
+        assert_eq!(paths(Some("2024-05-01"), None), vec!["new.rs", "newest.rs"]);
+        assert_eq!(paths(None, Some("2024-05-01")), vec!["old.rs"]);
+        assert_eq!(
+            paths(Some("2024-01-01"), Some("2024-05-01T06:00:00Z")),
+            vec!["new.rs"]
+        );
+        assert!(paths(Some("1d"), None).is_empty());
+    }
+
+    #[test]
+    fn test_changed_since_filter() {
+        let repo = crate::git::tests::repository();
+        let dir = repo.path().join("dir");
+        let changed = |rev: &str| {
+            let options = SearchOptions {
+                changed_since: Some(rev.to_string()),
+                ..SearchOptions::default()
+            };
+            search_directory(&dir, "fn", &options).map(|results| {
+                results
+                    .into_iter()
+                    .map(|result| result.path.rsplit('/').next().unwrap().to_string())
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(changed("HEAD~1").unwrap(), ["new.rs"]);
+        assert!(changed("HEAD").unwrap().is_empty());
+        assert!(changed("missing").is_err());
+
+        // Outside a repository
+        let plain = tempfile::tempdir().unwrap();
+        std::fs::write(plain.path().join("main.rs"), "fn main() {}").unwrap();
+        let options = SearchOptions {
+            changed_since: Some("HEAD~1".to_string()),
+            ..SearchOptions::default()
+        };
+        assert!(search_directory(plain.path(), "fn", &options).is_err());
+    }
+
+    #[test]
+    fn test_parse_modified_time() {
+        assert_eq!(parse_modified_time("2024-05-01"), Ok(1714521600));
+        assert_eq!(parse_modified_time("2024-05-01T12:00:00Z"), Ok(1714564800));
+        assert_eq!(
+            parse_modified_time("2024-05-01T14:00:00+02:00"),
+            Ok(1714564800)
+        );
+
+        let now = tantivy::time::OffsetDateTime::now_utc().unix_timestamp();
+        let two_weeks_ago = parse_modified_time("2w").unwrap();
+        assert!((now - 14 * 24 * 60 * 60 - two_weeks_ago).abs() <= 1);
+        assert!(parse_modified_time("30m").unwrap() <= now - 30 * 60);
+
+        for invalid in [
+            "",
+            "yesterday",
+            "2024-13-01",
+            "2024-02-30",
+            "7y",
+            "-3d",
+            "d",
+        ] {
+            assert!(parse_modified_time(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_field_boosts() {
+        let schema = CodeIndexSchema::new().schema;
+        let index = Index::create_in_ram(schema.clone());
+        register_tokenizers(&index);
+        let mut writer: tantivy::IndexWriter =
+            index.writer_with_num_threads(1, 15_000_000).unwrap();
+        for (path, content) in [
+            ("src/parser.rs", "fn run() {}\n"),
+            (
+                "src/util.rs",
+                "// the parser calls the parser, which calls the parser\nfn util() {}\n",
+            ),
+        ] {
+            let document = CodeIndexDocument::from_content(
+                path.to_string(),
+                content.to_string(),
+                std::time::SystemTime::now(),
+            );
+            writer
+                .add_document(document.to_tantivy_document(&schema))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        let paths = |searcher: &IndexSearcher, query: &str, boosts: &str| {
+            let options = SearchOptions {
+                boosts: boosts.parse().unwrap(),
+                ..SearchOptions::default()
+            };
+            searcher
+                .search_with_options(query, &options)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.path)
+                .collect::<Vec<_>>()
+        };
+
+        // File names outrank content by default, unless a search or the index says otherwise
+        let searcher = IndexSearcher::new(index.clone()).unwrap();
+        assert_eq!(
+            paths(&searcher, "parser", ""),
+            ["src/parser.rs", "src/util.rs"]
+        );
+        assert_eq!(
+            paths(&searcher, "parser", "filename=0.1"),
+            ["src/util.rs", "src/parser.rs"]
+        );
+        let searcher = IndexSearcher::new(index)
+            .unwrap()
+            .with_boosts("filename=0.1".parse().unwrap());
+        assert_eq!(
+            paths(&searcher, "parser", ""),
+            ["src/util.rs", "src/parser.rs"]
+        );
+        assert_eq!(
+            paths(&searcher, "parser", "filename=5"),
+            ["src/parser.rs", "src/util.rs"]
+        );
+
+        // Symbols are searched by default, phrases included
+        assert_eq!(paths(&searcher, "\"fn util\" OR util", ""), ["src/util.rs"]);
+    }
+
+    #[test]
+    fn test_exact_search() {
+        let schema = CodeIndexSchema::new().schema;
+        let index = Index::create_in_ram(schema.clone());
+        register_tokenizers(&index);
+        let mut writer: tantivy::IndexWriter =
+            index.writer_with_num_threads(1, 15_000_000).unwrap();
+        for (path, content) in [
+            ("bytes.rs", "fn read() -> Result<Vec<u8>, String> {}\n"),
+            ("words.rs", "fn read() -> Result<Vec<u16>, String> {}\n"),
+            ("prose.md", "A result is a vec of u8 or a string.\n"),
+        ] {
+            let document = CodeIndexDocument::from_content(
+                path.to_string(),
+                content.to_string(),
+                std::time::SystemTime::now(),
+            );
+            writer
+                .add_document(document.to_tantivy_document(&schema))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        let searcher = IndexSearcher::new(index).unwrap();
+        let options = SearchOptions {
+            exact: true,
+            ..SearchOptions::default()
+        };
+        let paths = |literal: &str| {
+            searcher
+                .search_with_options(literal, &options)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.path)
+                .collect::<Vec<_>>()
+        };
+
+        // Punctuation and case count, whitespace does not
+        assert_eq!(paths("Result<Vec<u8>, String>"), ["bytes.rs"]);
+        assert_eq!(paths("Vec<u8>,String"), ["bytes.rs"]);
+        assert!(paths("result<vec<u8>").is_empty());
+        let mut both = paths("-> Result<");
+        both.sort();
+        assert_eq!(both, ["bytes.rs", "words.rs"]);
+
+        let results = searcher.search_with_options("u8>", &options).unwrap();
+        assert!(results[0].snippet.contains("<b>u8</b><b>&gt;</b>"));
+        assert!(searcher.search_with_options("  ", &options).is_err());
+    }
+
+    #[test]
+    fn test_symbol_search_finds_definitions() {
+        let schema = CodeIndexSchema::new().schema;
+        let index = Index::create_in_ram(schema.clone());
+        register_tokenizers(&index);
+        let mut writer: tantivy::IndexWriter =
+            index.writer_with_num_threads(1, 15_000_000).unwrap();
+        for (path, content) in [
+            (
+                "src/http.rs",
+                "pub fn parse_http_request() {}\n\nstruct HttpParser;\n",
+            ),
+            ("src/main.rs", "fn main() {\n    parse_http_request();\n}\n"),
+            (
+                "client.py",
+                "class Http:\n    def parse(self):\n        pass\n",
+            ),
+        ] {
+            let document = CodeIndexDocument::from_content(
+                path.to_string(),
+                content.to_string(),
+                std::time::SystemTime::now(),
+            );
+            writer
+                .add_document(document.to_tantivy_document(&schema))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        let searcher = IndexSearcher::new(index).unwrap();
+        let symbols = |query: &str| {
+            let mut symbols: Vec<_> = searcher
+                .search_symbols(query)
+                .unwrap()
+                .into_iter()
+                .map(|symbol| (symbol.path, symbol.name, symbol.line))
+                .collect();
+            symbols.sort();
+            symbols
+        };
+
+        // Calls are not definitions, and parts of one file's definitions do not combine
+        assert_eq!(
+            symbols("parseHttp"),
+            vec![(
+                "src/http.rs".to_string(),
+                "parse_http_request".to_string(),
+                1
+            )]
+        );
+        assert_eq!(
+            symbols("http"),
+            vec![
+                ("client.py".to_string(), "Http".to_string(), 1),
+                ("src/http.rs".to_string(), "HttpParser".to_string(), 3),
+                (
+                    "src/http.rs".to_string(),
+                    "parse_http_request".to_string(),
+                    1
+                ),
+            ]
+        );
+        assert!(symbols("main_loop").is_empty());
+        assert!(searcher.search_symbols("::").is_err());
+    }
+
+    #[test]
+    fn test_identifier_parts_are_searchable() {
+        let schema = CodeIndexSchema::new().schema;
+        let index = Index::create_in_ram(schema.clone());
+        register_tokenizers(&index);
+        let mut writer: tantivy::IndexWriter =
+            index.writer_with_num_threads(1, 15_000_000).unwrap();
+        for (path, content) in [
+            ("client.ts", "const response = parseHttpRequest(raw);"),
+            ("client.py", "response = parse_http_request(raw)"),
+            ("notes.md", "Parse the request. HTTP is stateless."),
+        ] {
+            let document = CodeIndexDocument::from_content(
+                path.to_string(),
+                content.to_string(),
+                std::time::SystemTime::now(),
+            );
+            writer
+                .add_document(document.to_tantivy_document(&schema))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        let searcher = IndexSearcher::new(index).unwrap();
+        let paths = |query: &str| {
+            let mut paths: Vec<_> = searcher
+                .search(query)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.path)
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        assert_eq!(paths("\"http request\""), vec!["client.py", "client.ts"]);
+        assert_eq!(paths("HTTP"), vec!["client.py", "client.ts", "notes.md"]);
+        // Identifiers match in either spelling, but not their parts spread over a file
+        assert_eq!(paths("parseHttpRequest"), vec!["client.py", "client.ts"]);
+        assert_eq!(paths("parse_http_request"), vec!["client.py", "client.ts"]);
+        assert_eq!(
+            paths("content:\"parseHttp\"*"),
+            vec!["client.py", "client.ts"]
+        );
+        // Identifiers typed in lowercase match as a whole
+        assert_eq!(paths("parsehttprequest"), vec!["client.ts"]);
+
+        let results = searcher.search("request").unwrap();
+        let snippet = &results
+            .iter()
+            .find(|result| result.path == "client.ts")
+            .unwrap()
+            .snippet;
+        assert!(snippet.contains("parseHttp<b>Request</b>"), "{snippet}");
+    }
+
+    #[test]
+    fn test_ngram_index_matches_inside_words() {
+        let options = IndexingOptions::default().with_tokenizer(ContentTokenizer::Ngram {
+            min_gram: 3,
+            max_gram: 3,
+        });
+        let schema = CodeIndexSchema::with_options(&options).schema;
+        let index = Index::create_in_ram(schema.clone());
+        register_tokenizers(&index);
+        let mut writer: tantivy::IndexWriter =
+            index.writer_with_num_threads(1, 15_000_000).unwrap();
+        for (path, content) in [
+            ("a.rs", "let response = parseHttpRequest(raw);"),
+            ("b.rs", "fn request_handler() {}"),
+        ] {
+            let document = CodeIndexDocument::from_content(
+                path.to_string(),
+                content.to_string(),
+                std::time::SystemTime::now(),
+            );
+            writer
+                .add_document(document.to_tantivy_document(&schema))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        let searcher = IndexSearcher::new(index).unwrap();
+        let paths = |query: &str| {
+            let mut paths: Vec<_> = searcher
+                .search(query)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.path)
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        assert_eq!(paths("ttpreq"), vec!["a.rs"]);
+        assert_eq!(paths("EQUES"), vec!["a.rs", "b.rs"]);
+        assert_eq!(paths("uest_han"), vec!["b.rs"]);
+        assert!(paths("questhan").is_empty());
+    }
+
+    #[test]
+    fn test_schema_profiles() {
+        let searcher = |profile: SchemaProfile, files: &[(&str, &str)]| {
+            let schema =
+                CodeIndexSchema::with_options(&IndexingOptions::default().with_profile(profile))
+                    .schema;
+            let index = Index::create_in_ram(schema.clone());
+            register_tokenizers(&index);
+            let mut writer: tantivy::IndexWriter =
+                index.writer_with_num_threads(1, 15_000_000).unwrap();
+            for (path, content) in files {
+                let document = CodeIndexDocument::from_content(
+                    path.to_string(),
+                    content.to_string(),
+                    std::time::SystemTime::now(),
+                );
+                writer
+                    .add_document(document.to_tantivy_document(&schema))
+                    .unwrap();
+            }
+            writer.commit().unwrap();
+            IndexSearcher::new(index).unwrap()
+        };
+
+        let docs = searcher(
+            SchemaProfile::Docs,
+            &[
+                ("guide.md", "# Indexing guide\n\nHow files are indexed."),
+                ("faq.md", "# FAQ\n\nSearching a guide."),
+            ],
+        );
+        assert_eq!(
+            CodeIndexSchema::profile(&docs.index.schema()),
+            SchemaProfile::Docs
+        );
+        // Words are stemmed, and titles are searched and returned
+        let results = docs.search("indexes").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].metadata["title"], "Indexing guide");
+        let results = docs.search("title:guide").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, "guide.md");
+        assert!(docs.search_symbols("guide").is_err());
+
+        let logs = searcher(
+            SchemaProfile::Logs,
+            &[
+                (
+                    "app.log",
+                    "12:00 INFO started\n12:01 ERROR parseHttp failed\n",
+                ),
+                ("worker.log", "12:00 INFO started\n"),
+            ],
+        );
+        let results = logs.search("level:error").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, "app.log");
+        assert_eq!(results[0].metadata["level"], "error,info");
+        assert_eq!(results[0].metadata["line_count"], "2");
+        // Identifiers are not split into parts
+        assert!(logs.search("http").unwrap().is_empty());
+        assert_eq!(logs.search("parsehttp").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_code_tokens() {
+        assert_eq!(code_tokens("fn parseHttp"), vec!["fn", "parse", "http"]);
+        assert_eq!(code_tokens("a"), vec!["a"]);
+        assert!(code_tokens("").is_empty());
+    }
+}
@@ -0,0 +1,396 @@
+//! Point-in-time copies of an index directory, stored under `BEETLE_HOME/snapshots/<index>/<id>`.
+//!
+//! A snapshot holds the last commit of the tantivy index, the index metadata, the file index
+//! snapshot and the update history, plus a `manifest.json` listing every file with its size and
+//! CRC-32 so damaged snapshots are refused instead of restored.
+
+use crate::encryption::EncryptionKey;
+use crate::storage::committed_segment_files;
+use crc::Crc;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use tantivy::IndexMeta;
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Summary of a snapshot, as listed by `beetle snapshot list`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SnapshotInfo {
+    pub id: String,
+    pub index_name: String,
+    /// When the snapshot was taken, in seconds since the Unix epoch.
+    pub created_at: u64,
+    /// Number of documents in the captured commit.
+    pub docs: u64,
+    /// Total size of the snapshot's files, in bytes.
+    pub size_bytes: u64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SnapshotManifest {
+    #[serde(flatten)]
+    info: SnapshotInfo,
+    files: Vec<SnapshotFile>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SnapshotFile {
+    /// Path relative to the index directory, `/`-separated.
+    path: String,
+    size: u64,
+    crc32: String,
+}
+
+/// How a file of the index directory is captured and restored.
+enum Capture {
+    /// Segment files never change once written, so they are hard-linked rather than copied.
+    Link,
+    Copy,
+    /// Written from the loaded commit.
+    Write(Vec<u8>),
+}
+
+/// Captures the commit `metas` of the index directory `index_dir` into a new snapshot under
+/// `snapshots_dir`.
+///
+/// `metadata_files` are the files of `index_dir` besides the tantivy index, such as `meta.json`;
+/// missing ones are skipped. `key` is the key of encrypted indexes, whose files are copied as
+/// they are.
+pub fn create(
+    index_name: &str,
+    index_dir: &Path,
+    metas: &IndexMeta,
+    key: Option<&EncryptionKey>,
+    metadata_files: &[&str],
+    snapshots_dir: &Path,
+) -> Result<SnapshotInfo, String> {
+    let id = uuid::Uuid::new_v4().simple().to_string()[..8].to_string();
+    let snapshot_dir = snapshots_dir.join(index_name).join(&id);
+    // Built under a temporary name, so an interrupted snapshot is never listed
+    let staging_dir = snapshot_dir.with_extension("tmp");
+
+    let mut tantivy_meta = serde_json::to_vec_pretty(metas)
+        .map_err(|e| format!("Failed to serialize metas for index {index_name}: {e}"))?;
+    if let Some(key) = key {
+        tantivy_meta = key
+            .seal(&tantivy_meta)
+            .map_err(|e| format!("Failed to encrypt metas for index {index_name}: {e}"))?;
+    }
+    let mut captures = vec![("index/meta.json".to_string(), Capture::Write(tantivy_meta))];
+    for file in committed_segment_files(metas) {
+        captures.push((format!("index/{}", file.to_string_lossy()), Capture::Link));
+    }
+    for file in metadata_files.iter().chain(&["index/.managed.json"]) {
+        if index_dir.join(file).exists() {
+            captures.push((file.to_string(), Capture::Copy));
+        }
+    }
+
+    let captured = capture_files(index_dir, &staging_dir, captures).and_then(|files| {
+        let manifest = SnapshotManifest {
+            info: SnapshotInfo {
+                id: id.clone(),
+                index_name: index_name.to_string(),
+                created_at: SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+                docs: metas
+                    .segments
+                    .iter()
+                    .map(|segment| u64::from(segment.num_docs()))
+                    .sum(),
+                size_bytes: files.iter().map(|file| file.size).sum(),
+            },
+            files,
+        };
+        let manifest_json = serde_json::to_string_pretty(&manifest)
+            .map_err(|e| format!("Failed to serialize snapshot manifest: {e}"))?;
+        fs::write(staging_dir.join(MANIFEST_FILE_NAME), manifest_json)
+            .map_err(|e| format!("Failed to write snapshot manifest: {e}"))?;
+        fs::rename(&staging_dir, &snapshot_dir)
+            .map_err(|e| format!("Failed to save snapshot {snapshot_dir:?}: {e}"))?;
+
+        Ok(manifest.info)
+    });
+    if captured.is_err() {
+        let _ = fs::remove_dir_all(&staging_dir);
+    }
+
+    captured
+}
+
+/// Lists the snapshots of `index_name`, or of every index when `None`, oldest first.
+pub fn list(snapshots_dir: &Path, index_name: Option<&str>) -> Result<Vec<SnapshotInfo>, String> {
+    let index_dirs = match index_name {
+        Some(index_name) => vec![snapshots_dir.join(index_name)],
+        None => sub_directories(snapshots_dir)?,
+    };
+
+    let mut snapshots = Vec::new();
+    for index_dir in index_dirs {
+        for snapshot_dir in sub_directories(&index_dir)? {
+            // Snapshots being taken, and leftovers of interrupted restores
+            if !snapshot_dir.join(MANIFEST_FILE_NAME).exists() {
+                continue;
+            }
+            snapshots.push(read_manifest(&snapshot_dir)?.info);
+        }
+    }
+    snapshots.sort_by(|a, b| {
+        (a.created_at, &a.index_name, &a.id).cmp(&(b.created_at, &b.index_name, &b.id))
+    });
+
+    Ok(snapshots)
+}
+
+/// Replaces the index directory `index_dir` with the snapshot `id` of `index_name`, after
+/// checking every file against the manifest. Works whether or not the index still exists.
+pub fn restore(
+    index_name: &str,
+    id: &str,
+    index_dir: &Path,
+    snapshots_dir: &Path,
+) -> Result<SnapshotInfo, String> {
+    // Ids are generated alphanumerics; anything else could name a directory elsewhere
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!("Invalid snapshot id '{id}'"));
+    }
+    let snapshot_dir = snapshots_dir.join(index_name).join(id);
+    if !snapshot_dir.join(MANIFEST_FILE_NAME).exists() {
+        return Err(format!("Snapshot {id} of index {index_name} not found"));
+    }
+    let manifest = read_manifest(&snapshot_dir)?;
+    for file in &manifest.files {
+        if !is_relative_below(&file.path) {
+            return Err(format!(
+                "Snapshot {id} is damaged: {} is outside of it",
+                file.path
+            ));
+        }
+        let (size, crc32) = checksum(&snapshot_dir.join(&file.path))
+            .map_err(|e| format!("Snapshot {id} is damaged: {}: {e}", file.path))?;
+        if size != file.size || crc32 != file.crc32 {
+            return Err(format!(
+                "Snapshot {id} is damaged: {} does not match its checksum",
+                file.path
+            ));
+        }
+    }
+
+    let staging_dir = snapshots_dir.join(index_name).join(".restoring");
+    let replaced_dir = snapshots_dir.join(index_name).join(".replaced");
+    let _ = fs::remove_dir_all(&staging_dir);
+    let _ = fs::remove_dir_all(&replaced_dir);
+    let captures = manifest
+        .files
+        .iter()
+        .map(|file| {
+            let capture = if is_segment_file(&file.path) {
+                Capture::Link
+            } else {
+                Capture::Copy
+            };
+            (file.path.clone(), capture)
+        })
+        .collect();
+    if let Err(e) = capture_files(&snapshot_dir, &staging_dir, captures) {
+        let _ = fs::remove_dir_all(&staging_dir);
+        return Err(e);
+    }
+
+    if index_dir.exists() {
+        fs::rename(index_dir, &replaced_dir)
+            .map_err(|e| format!("Failed to move index {index_name} aside: {e}"))?;
+    }
+    if let Err(e) = fs::rename(&staging_dir, index_dir) {
+        // Put the live index back rather than leaving none
+        let _ = fs::rename(&replaced_dir, index_dir);
+        return Err(format!("Failed to restore index {index_name}: {e}"));
+    }
+    let _ = fs::remove_dir_all(&replaced_dir);
+
+    Ok(manifest.info)
+}
+
+/// Places every captured file of `source_dir` into `target_dir`, returning their manifest entries.
+fn capture_files(
+    source_dir: &Path,
+    target_dir: &Path,
+    captures: Vec<(String, Capture)>,
+) -> Result<Vec<SnapshotFile>, String> {
+    let mut files = Vec::new();
+    for (path, capture) in captures {
+        let source = source_dir.join(&path);
+        let target = target_dir.join(&path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create directory {parent:?}: {e}"))?;
+        }
+
+        let placed = match capture {
+            // Hard links fail across file systems, in which case the file is copied
+            Capture::Link => {
+                fs::hard_link(&source, &target).or_else(|_| fs::copy(&source, &target).map(|_| ()))
+            }
+            Capture::Copy => fs::copy(&source, &target).map(|_| ()),
+            Capture::Write(content) => fs::write(&target, content),
+        };
+        placed.map_err(|e| format!("Failed to copy {source:?}: {e}"))?;
+
+        let (size, crc32) =
+            checksum(&target).map_err(|e| format!("Failed to read {target:?}: {e}"))?;
+        files.push(SnapshotFile { path, size, crc32 });
+    }
+
+    Ok(files)
+}
+
+/// Tantivy segment files are named `<segment id>.<component>` directly in `index/`.
+fn is_segment_file(path: &str) -> bool {
+    path.strip_prefix("index/")
+        .is_some_and(|file| !file.contains('/') && !file.starts_with('.') && file != "meta.json")
+}
+
+/// Whether the manifest path `path` names a file below the directory it is relative to.
+fn is_relative_below(path: &str) -> bool {
+    let path = Path::new(path);
+    path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+fn checksum(path: &Path) -> std::io::Result<(u64, String)> {
+    let mut file = fs::File::open(path)?;
+    let mut digest = CRC32.digest();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        digest.update(&buffer[..read]);
+        size += read as u64;
+    }
+
+    Ok((size, format!("{:08x}", digest.finalize())))
+}
+
+fn read_manifest(snapshot_dir: &Path) -> Result<SnapshotManifest, String> {
+    let manifest_path = snapshot_dir.join(MANIFEST_FILE_NAME);
+    let manifest_json = fs::read_to_string(&manifest_path)
+        .map_err(|e| format!("Failed to read snapshot manifest {manifest_path:?}: {e}"))?;
+
+    serde_json::from_str(&manifest_json)
+        .map_err(|e| format!("Failed to parse snapshot manifest {manifest_path:?}: {e}"))
+}
+
+/// The directories directly inside `dir`; none when it does not exist.
+fn sub_directories(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read directory {dir:?}: {e}"))?;
+    let mut dirs = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read entry: {e}"))?;
+        if entry.path().is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+
+    Ok(dirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_files_are_linked() {
+        assert!(is_segment_file("index/0123abcd.idx"));
+        assert!(!is_segment_file("index/meta.json"));
+        assert!(!is_segment_file("index/.managed.json"));
+        assert!(!is_segment_file("updates.jsonl"));
+    }
+
+    #[test]
+    fn test_manifest_paths_stay_in_the_snapshot() {
+        assert!(is_relative_below("index/0123abcd.idx"));
+        assert!(is_relative_below("meta.json"));
+        for path in [
+            "",
+            "../meta.json",
+            "index/../../meta.json",
+            "/etc/passwd",
+            "./meta.json",
+        ] {
+            assert!(!is_relative_below(path), "{path}");
+        }
+    }
+
+    #[test]
+    fn test_damaged_snapshot_is_refused() {
+        let home = tempfile::tempdir().unwrap();
+        let index_dir = home.path().join("beetle");
+        let snapshots_dir = home.path().join("snapshots");
+        fs::create_dir_all(&index_dir).unwrap();
+        fs::write(index_dir.join("meta.json"), "{}").unwrap();
+        fs::write(index_dir.join("updates.jsonl"), "").unwrap();
+
+        let metas = IndexMeta::with_schema(tantivy::schema::Schema::builder().build());
+        let info = create(
+            "beetle",
+            &index_dir,
+            &metas,
+            None,
+            &["meta.json", "updates.jsonl", "file_index_snapshot.bin"],
+            &snapshots_dir,
+        )
+        .unwrap();
+        assert_eq!(info.docs, 0);
+        let listed = list(&snapshots_dir, None).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, info.id);
+
+        fs::write(index_dir.join("meta.json"), "changed").unwrap();
+        restore("beetle", &info.id, &index_dir, &snapshots_dir).unwrap();
+        assert_eq!(
+            fs::read_to_string(index_dir.join("meta.json")).unwrap(),
+            "{}"
+        );
+        assert!(index_dir.join("index/meta.json").exists());
+
+        let snapshot_dir = snapshots_dir.join("beetle").join(&info.id);
+        fs::write(snapshot_dir.join("meta.json"), "{\"x\"}").unwrap();
+        let error = restore("beetle", &info.id, &index_dir, &snapshots_dir).unwrap_err();
+        assert!(error.contains("damaged"), "{error}");
+        assert!(restore("beetle", "missing", &index_dir, &snapshots_dir).is_err());
+
+        // Ids and manifests cannot reach outside the snapshots
+        for id in ["..", "../beetle", ""] {
+            let error = restore("beetle", id, &index_dir, &snapshots_dir).unwrap_err();
+            assert!(error.contains("Invalid snapshot id"), "{error}");
+        }
+        let mut manifest = read_manifest(&snapshot_dir).unwrap();
+        manifest.files = vec![SnapshotFile {
+            path: "../../secret".to_string(),
+            size: 0,
+            crc32: "00000000".to_string(),
+        }];
+        fs::write(
+            snapshot_dir.join(MANIFEST_FILE_NAME),
+            serde_json::to_string(&manifest).unwrap(),
+        )
+        .unwrap();
+        let error = restore("beetle", &info.id, &index_dir, &snapshots_dir).unwrap_err();
+        assert!(error.contains("outside"), "{error}");
+    }
+}
@@ -1,20 +1,271 @@
+#[cfg(feature = "object-storage")]
+mod object;
+mod ram;
+#[cfg(feature = "object-storage")]
+mod s3;
+
 use crate::change::{self, FileIndexMetadata};
-use crate::schema::CodeIndexSchema;
-use crate::tokenizers::CodeTokenizer;
+use crate::doctor::{IndexIssue, IndexProblem, OrphanReason, OrphanedEntry};
+use crate::encryption::{EncryptedDirectory, EncryptionKey};
+use crate::migration::{self, FORMAT_VERSION};
+use crate::options::IndexingOptions;
+use crate::paths::normalize_path;
+use crate::schema::{index_settings, CodeIndexSchema};
+use crate::snapshot::{self, SnapshotInfo};
+use crate::tokenizers::register_tokenizers;
+use crate::usage::ResourceUsage;
+use std::cmp::Ordering;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
-use tantivy::Index;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tantivy::directory::MmapDirectory;
+use tantivy::index::SegmentComponent;
+use tantivy::{Directory, Index, IndexMeta, SegmentMeta};
+
+/// Number of records the update history of an index keeps, the most recent ones.
+pub const UPDATE_RECORDS_KEPT: usize = 1000;
+/// Size `updates.jsonl` is compacted at, down to its last [`UPDATE_RECORDS_KEPT`] records.
+const MAX_UPDATE_HISTORY_FILE_SIZE: u64 = 1024 * 1024;
+
+#[cfg(feature = "object-storage")]
+pub use object::ObjectStorage;
+pub use ram::RamStorage;
+#[cfg(feature = "object-storage")]
+pub use s3::{S3Config, S3Credentials};
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct IndexStorageMetadata {
     pub index_name: String,
     pub index_path: String,
     pub target_path: String,
+    /// Indexes created before options were persisted use the defaults.
+    #[serde(default)]
+    pub options: IndexingOptions,
+    /// Free-form labels used to group indexes, e.g. `backend` or `rust`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Layout version of the index directory, see [`crate::migration`]. Indexes created before
+    /// versioning have none and count as version 0.
+    #[serde(default)]
+    pub format_version: u32,
+    /// Fingerprint of the key an encrypted index was created with, so another key is refused
+    /// with a clear message instead of failing to decrypt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption_key_id: Option<String>,
+}
+
+/// Advisory write lock on an index, shared with other processes; released when dropped.
+///
+/// Storages whose indexes cannot be reached from other processes hand out an empty lock.
+#[derive(Default)]
+pub struct IndexLock {
+    _file: Option<fs::File>,
+}
+
+impl IndexStorageMetadata {
+    /// Whether the index carries every one of `tags`.
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| self.tags.contains(tag))
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct IndexStats {
+    /// Number of documents in the last commit.
+    pub docs: u64,
+    /// Size of the index directory on disk, in bytes.
+    pub size_bytes: u64,
+    /// Last time the index was written, in seconds since the Unix epoch.
+    pub updated_at: i64,
+    /// The most recent entry of the update history.
+    pub last_update: Option<UpdateRecord>,
+}
+
+/// One entry of an index's update history, written by every `IndexWriter::index` run.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UpdateRecord {
+    /// When the update was committed, in seconds since the Unix epoch.
+    pub finished_at: u64,
+    pub files_added: usize,
+    pub files_modified: usize,
+    pub files_removed: usize,
+    pub files_renamed: usize,
+    /// Added and modified files indexed as duplicates of another file, see
+    /// [`crate::options::IndexingOptions::dedup`].
+    #[serde(default)]
+    pub files_duplicated: usize,
+    /// Added and modified files read and indexed per second, commit excluded.
+    #[serde(default)]
+    pub files_per_sec: u64,
+    /// The batches added and modified files were indexed in, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub batches: Vec<BatchRecord>,
+    /// The interrupted update this one recovered from, if the previous one did not finish.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovered: Option<RecoveredUpdate>,
+    /// Files of the target path left out of the index, or indexed only in part.
+    #[serde(default, skip_serializing_if = "SkippedFiles::is_empty")]
+    pub skipped: SkippedFiles,
+    pub usage: ResourceUsage,
+    /// Why the companion commit index or submodule indexes failed to update after this index.
+    /// Only set on the record [`crate::writter::IndexWriter::index`] returns, as the companions
+    /// are updated once the record is in the history.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub companion_failures: Vec<String>,
+}
+
+/// Why a file of the target path was not indexed, or only in part.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The file or its directory cannot be read, e.g. for lack of permission. Files that fail to
+    /// open are indexed by path only.
+    Unreadable,
+    /// The content is not UTF-8 text: it is indexed up to there, which is nothing for most
+    /// binary files.
+    Binary,
+    /// The file is larger than the index's maximum file size, and only its start is indexed.
+    TooLarge,
+    /// The index's extension filter leaves the file out.
+    Ignored,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::Unreadable => write!(f, "unreadable"),
+            SkipReason::Binary => write!(f, "binary"),
+            SkipReason::TooLarge => write!(f, "too large"),
+            SkipReason::Ignored => write!(f, "ignored"),
+        }
+    }
+}
+
+/// The files an update skipped, counted by [`SkipReason`]. Every update counts the files of the
+/// target path that are ignored, too large or cannot be listed, and the files it reads that turn
+/// out to be unreadable or binary.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SkippedFiles {
+    pub unreadable: usize,
+    pub binary: usize,
+    pub too_large: usize,
+    pub ignored: usize,
+    /// The first skipped files, ignored ones aside, up to [`SkippedFiles::MAX_FILES`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<SkippedFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: SkipReason,
+}
+
+impl SkippedFiles {
+    /// Skipped files listed by path; the others are only counted.
+    pub const MAX_FILES: usize = 20;
+
+    /// Counts a file skipped for `reason`, listing it if `path` is known.
+    pub fn add(&mut self, path: Option<&str>, reason: SkipReason) {
+        let count = match reason {
+            SkipReason::Unreadable => &mut self.unreadable,
+            SkipReason::Binary => &mut self.binary,
+            SkipReason::TooLarge => &mut self.too_large,
+            SkipReason::Ignored => &mut self.ignored,
+        };
+        *count += 1;
+
+        // Ignored files are left out on purpose, and there can be many of them
+        if let Some(path) = path.filter(|_| reason != SkipReason::Ignored) {
+            if self.files.len() < Self::MAX_FILES {
+                self.files.push(SkippedFile {
+                    path: path.to_string(),
+                    reason,
+                });
+            }
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.unreadable + self.binary + self.too_large + self.ignored
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+}
+
+impl fmt::Display for SkippedFiles {
+    /// The counts of the reasons files were skipped for, e.g. `2 unreadable, 1 binary`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = [
+            (self.unreadable, SkipReason::Unreadable),
+            (self.binary, SkipReason::Binary),
+            (self.too_large, SkipReason::TooLarge),
+            (self.ignored, SkipReason::Ignored),
+        ];
+        let counts: Vec<String> = counts
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, reason)| format!("{count} {reason}"))
+            .collect();
+        write!(f, "{}", counts.join(", "))
+    }
+}
+
+/// An update that was interrupted, as found in its [`UpdateJournal`] by the next one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RecoveredUpdate {
+    /// When the interrupted update started, in seconds since the Unix epoch.
+    pub started_at: u64,
+    /// Intermediate commits it completed, which were kept.
+    pub commits: usize,
+    /// Files whose documents were rolled back, and indexed again by the recovering update.
+    pub files_rolled_back: usize,
+}
+
+/// Journal of a running update, saved before its changes are committed and removed once it
+/// finishes. A journal left behind tells the next update that the tantivy index may hold changes
+/// the file index snapshot does not account for: the documents of the pending paths are removed
+/// and the paths left out of the snapshot, so that they are indexed again.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct UpdateJournal {
+    /// When the update started, in seconds since the Unix epoch.
+    pub started_at: u64,
+    /// Commits whose file index snapshot was saved.
+    pub commits: usize,
+    /// Paths whose documents were changed since the last saved snapshot.
+    pub pending: Vec<String>,
+}
+
+/// One batch of an update, see [`WriterResources::batch_size`].
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BatchRecord {
+    pub files: usize,
+    /// Time spent reading and indexing the batch's files.
+    pub duration_ms: u64,
+    /// Time spent committing after the batch, for batches followed by an intermediate commit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_ms: Option<u64>,
 }
 
 pub trait IndexStorage: Send + Sync {
     fn index_dir(&self) -> String;
-    fn create(&self, index_name: &str, target_path: &str) -> Result<Index, String>;
+    fn create(
+        &self,
+        index_name: &str,
+        target_path: &str,
+        options: &IndexingOptions,
+        tags: &[String],
+    ) -> Result<Index, String>;
     fn open(&self, index_name: &str) -> Result<Index, String>;
     fn remove(&self, index_name: &str) -> Result<(), String>;
     fn list(&self) -> Result<Vec<IndexStorageMetadata>, String>;
@@ -28,40 +279,398 @@ pub trait IndexStorage: Send + Sync {
 
         Err(format!("Index {index_name} not found"))
     }
-    fn reset(&self, index_name: &str) -> Result<(), String> {
-        let metadata = self.get_metadata(index_name)?;
-        self.remove(index_name)?;
-        self.create(&metadata.index_name, &metadata.target_path)?;
-
-        Ok(())
-    }
+    /// Rebuilds `index_name` from scratch: `build` is given a storage holding an empty copy of the
+    /// index under the same name, with the same metadata and update history. The copy replaces
+    /// the index only once `build` succeeds, so the index stays searchable until then.
+    fn rebuild(
+        &self,
+        index_name: &str,
+        build: &mut dyn FnMut(&dyn IndexStorage) -> Result<(), String>,
+    ) -> Result<(), String>;
     fn read_file_index_metadata(&self, index_name: &str) -> Result<Vec<FileIndexMetadata>, String>;
     fn save_file_index_metadata(
         &self,
         index_name: &str,
         metadata: Vec<FileIndexMetadata>,
     ) -> Result<(), String>;
+    /// Appends `record` to the update history of `index_name`, which keeps its last
+    /// [`UPDATE_RECORDS_KEPT`] records.
+    fn append_update_record(&self, index_name: &str, record: &UpdateRecord) -> Result<(), String>;
+    /// Returns the update history of `index_name`, oldest first.
+    fn read_update_history(&self, index_name: &str) -> Result<Vec<UpdateRecord>, String>;
+    /// Returns the journal of the update of `index_name` that did not finish, if any.
+    fn read_update_journal(&self, index_name: &str) -> Result<Option<UpdateJournal>, String>;
+    /// Saves the journal of the running update of `index_name`, or removes it when `None`.
+    fn save_update_journal(
+        &self,
+        index_name: &str,
+        journal: Option<&UpdateJournal>,
+    ) -> Result<(), String>;
+    fn diagnose(&self, fix: bool) -> Result<Vec<IndexIssue>, String>;
+    /// Finds the entries of the storage that hold no usable index, such as the directory of an
+    /// index whose creation was interrupted, and removes them when `remove` is set. Entries of
+    /// indexes that are being written to are left alone.
+    fn clean(&self, _remove: bool) -> Result<Vec<OrphanedEntry>, String> {
+        Ok(Vec::new())
+    }
+    /// Removes `entries` found by [`IndexStorage::clean`], and only those, marking each one
+    /// removed. Entries of indexes that are being written to by then are left as they are.
+    fn remove_orphans(&self, entries: Vec<OrphanedEntry>) -> Result<Vec<OrphanedEntry>, String> {
+        Ok(entries)
+    }
+    fn stats(&self, index_name: &str) -> Result<IndexStats, String>;
+    /// Creates a frozen read-only copy of the last commit of `index_name`.
+    fn fork(&self, index_name: &str) -> Result<IndexFork, String>;
+    /// Where the repository of `index_name` is cloned by [`crate::catalog::IndexCatalog::create_from_git`];
+    /// `None` for storages that cannot hold clones.
+    fn checkout_dir(&self, _index_name: &str) -> Option<PathBuf> {
+        None
+    }
+    /// Takes the write lock of `index_name`. Fails if another writer holds it, unless `wait` is
+    /// set, in which case it blocks until the lock is released.
+    fn lock(&self, _index_name: &str, _wait: bool) -> Result<IndexLock, String> {
+        Ok(IndexLock::default())
+    }
+    /// Upgrades `index_name` if an older beetle wrote it, see [`crate::migration`]. The caller
+    /// holds the index's [`IndexStorage::lock`].
+    fn migrate(&self, _index_name: &str) -> Result<(), String> {
+        Ok(())
+    }
+    /// Called after every commit of `index_name`, for storages that copy indexes elsewhere.
+    fn publish(&self, _index_name: &str) -> Result<(), String> {
+        Ok(())
+    }
+    /// Saves a point-in-time copy of the last commit of `index_name`, see [`crate::snapshot`].
+    fn create_snapshot(&self, _index_name: &str) -> Result<SnapshotInfo, String> {
+        Err("Snapshots are not supported by this storage".to_string())
+    }
+    /// Lists the snapshots of `index_name`, or of every index when `None`, oldest first.
+    fn list_snapshots(&self, _index_name: Option<&str>) -> Result<Vec<SnapshotInfo>, String> {
+        Err("Snapshots are not supported by this storage".to_string())
+    }
+    /// Replaces `index_name` with its snapshot `snapshot_id`, recreating it if it was removed.
+    fn restore_snapshot(
+        &self,
+        _index_name: &str,
+        _snapshot_id: &str,
+    ) -> Result<SnapshotInfo, String> {
+        Err("Snapshots are not supported by this storage".to_string())
+    }
+}
+
+/// A read-only copy of an index that is deleted when dropped.
+pub struct IndexFork {
+    pub index: Index,
+    /// Directory holding the copy; `None` for copies kept in memory.
+    path: Option<PathBuf>,
+}
+
+impl Drop for IndexFork {
+    fn drop(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(e) = fs::remove_dir_all(path) {
+            tracing::warn!("Failed to remove index fork {path:?}: {e}");
+        }
+    }
 }
 
 pub struct FsStorage {
     pub root: PathBuf,
+    /// Key of the encrypted indexes, read from the environment the first time one is opened.
+    encryption_key: OnceLock<Result<EncryptionKey, String>>,
 }
 
 impl FsStorage {
     pub fn new(root: PathBuf) -> Self {
-        FsStorage { root }
+        FsStorage {
+            root,
+            encryption_key: OnceLock::new(),
+        }
     }
 
-    fn get_file_index_path(&self, index_name: &str) -> Result<PathBuf, String> {
+    /// Uses `key` for encrypted indexes instead of reading it from the environment.
+    pub fn with_encryption_key(self, key: EncryptionKey) -> Self {
+        FsStorage {
+            encryption_key: OnceLock::from(Ok(key)),
+            ..self
+        }
+    }
+
+    fn encryption_key(&self) -> Result<EncryptionKey, String> {
+        self.encryption_key
+            .get_or_init(EncryptionKey::from_env)
+            .clone()
+    }
+
+    /// The key of the index described by `metadata`, or `None` if it is not encrypted.
+    fn index_key(&self, metadata: &IndexStorageMetadata) -> Result<Option<EncryptionKey>, String> {
+        let Some(key_id) = &metadata.encryption_key_id else {
+            return Ok(None);
+        };
+        let index_name = &metadata.index_name;
+        let key = self
+            .encryption_key()
+            .map_err(|e| format!("Index {index_name} is encrypted. {e}"))?;
+        if key.id() != key_id {
+            return Err(format!(
+                "Index {index_name} is encrypted with another key (fingerprint {key_id}, the \
+                 configured key is {})",
+                key.id()
+            ));
+        }
+
+        Ok(Some(key))
+    }
+
+    fn key_of(&self, index_name: &str) -> Result<Option<EncryptionKey>, String> {
+        self.index_key(&migration::read(&self.root.join(index_name))?)
+    }
+
+    fn get_file_index_path(
+        &self,
+        index_name: &str,
+    ) -> Result<(PathBuf, Option<EncryptionKey>), String> {
         let index_metadata = self.get_metadata(index_name)?;
         let file_index_path =
             PathBuf::from(&index_metadata.index_path).join(Self::FILE_INDEX_SNAPSHOT_FILE_NAME);
 
-        Ok(file_index_path)
+        Ok((file_index_path, self.index_key(&index_metadata)?))
+    }
+
+    fn read_file_index(
+        file_index_path: &Path,
+        key: Option<&EncryptionKey>,
+    ) -> Result<Vec<FileIndexMetadata>, String> {
+        let mut data = fs::read(file_index_path).map_err(|e| e.to_string())?;
+        if let Some(key) = key {
+            data = key.open(&data).map_err(|e| e.to_string())?;
+        }
+
+        change::read_snapshot(data.as_slice()).map_err(|e| e.to_string())
+    }
+
+    /// The directory of the tantivy index at `index_path`, encrypted with `key` if there is one.
+    fn index_directory(
+        index_path: &Path,
+        key: Option<&EncryptionKey>,
+    ) -> Result<Box<dyn Directory>, String> {
+        let directory = MmapDirectory::open(index_path)
+            .map_err(|e| format!("Failed to open index directory {index_path:?}: {e}"))?;
+
+        Ok(match key {
+            Some(key) => Box::new(EncryptedDirectory::new(directory, key.clone())),
+            None => Box::new(directory),
+        })
+    }
+
+    fn open_in_dir(
+        index_name: &str,
+        index_path: &Path,
+        key: Option<&EncryptionKey>,
+    ) -> Result<Index, String> {
+        let index = Index::open(Self::index_directory(index_path, key)?)
+            .map_err(|e| format!("Failed to open index {index_name}: {e}"))?;
+        register_tokenizers(&index);
+
+        Ok(index)
+    }
+
+    fn diagnose_index(
+        &self,
+        index_name: &str,
+        index_root_path: &Path,
+        fix: bool,
+    ) -> Vec<IndexIssue> {
+        let has_metadata = index_root_path.join(Self::META_JSON_FILE_NAME).exists()
+            || index_root_path
+                .join(migration::LEGACY_META_JSON_FILE_NAME)
+                .exists();
+        if !has_metadata {
+            return vec![IndexIssue::new(index_name, IndexProblem::MissingMetadata)];
+        }
+        // Indexes from a newer beetle must not be "repaired" by recreating them
+        let mut metadata = match migration::read(index_root_path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                return vec![IndexIssue::new(
+                    index_name,
+                    IndexProblem::InvalidMetadata(e),
+                )]
+            }
+        };
+
+        let mut issues = Vec::new();
+        if migration::needs_migration(&metadata) {
+            let mut issue = IndexIssue::new(
+                index_name,
+                IndexProblem::OutdatedFormat(metadata.format_version),
+            );
+            let migrated = match fix {
+                true => self
+                    .lock(index_name, false)
+                    .and_then(|_lock| migration::migrate(index_root_path))
+                    .ok(),
+                false => None,
+            };
+            issue.fixed = migrated.is_some();
+            issues.push(issue);
+            // Nothing else can be checked in the older layout
+            match migrated {
+                Some(migrated) => metadata = migrated,
+                None => return issues,
+            }
+        }
+
+        let target_exists = Path::new(&metadata.target_path).exists();
+        if !target_exists {
+            issues.push(IndexIssue::new(
+                index_name,
+                IndexProblem::MissingTargetPath(metadata.target_path.clone()),
+            ));
+        }
+
+        let key = match self.index_key(&metadata) {
+            Ok(key) => key,
+            // Nothing else can be checked without the key, and recreating the index would not
+            // help
+            Err(e) => {
+                issues.push(IndexIssue::new(index_name, IndexProblem::UnavailableKey(e)));
+                return issues;
+            }
+        };
+
+        let snapshot_path = index_root_path.join(Self::FILE_INDEX_SNAPSHOT_FILE_NAME);
+        if snapshot_path.exists() {
+            if let Err(e) = Self::read_file_index(&snapshot_path, key.as_ref()) {
+                issues.push(IndexIssue::new(
+                    index_name,
+                    IndexProblem::CorruptedSnapshot(e),
+                ));
+            }
+        }
+
+        if let Err(e) = self.open(index_name) {
+            issues.push(IndexIssue::new(
+                index_name,
+                IndexProblem::UnopenableIndex(e),
+            ));
+        }
+
+        // Both recoverable problems are repaired the same way: the index is recreated empty so
+        // that the next update rebuilds it from the target path.
+        let needs_reset = issues.iter().any(|issue| issue.problem.is_recoverable());
+        if fix && needs_reset && target_exists {
+            // Not under a writer of the index, e.g. a running update
+            let reset = self.lock(index_name, false).and_then(|_lock| {
+                self.remove(index_name)?;
+                self.create(
+                    index_name,
+                    &metadata.target_path,
+                    &metadata.options,
+                    &metadata.tags,
+                )
+            });
+            match reset {
+                Ok(_) => {
+                    for issue in issues.iter_mut() {
+                        issue.fixed |= issue.problem.is_recoverable();
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to repair index {index_name}: {e}"),
+            }
+        }
+
+        issues
+    }
+
+    /// The index directories of `root` that hold no usable index, with the reason why.
+    fn find_orphaned_dirs(root: &Path) -> Result<Vec<(String, PathBuf, OrphanReason)>, String> {
+        let mut orphans = Vec::new();
+        if !root.exists() {
+            return Ok(orphans);
+        }
+
+        let entries =
+            fs::read_dir(root).map_err(|e| format!("Failed to read index directory: {e}"))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Failed to read entry: {e}"))?;
+            let index_name = entry.file_name().to_string_lossy().to_string();
+            // Directories that cannot be an index are not beetle's to remove
+            if !entry.file_type().is_ok_and(|file_type| file_type.is_dir())
+                || Self::is_reserved_dir(&entry)
+                || crate::catalog::validate_index_name(&index_name).is_err()
+            {
+                continue;
+            }
+
+            let path = entry.path();
+            let metadata_path = path.join(Self::META_JSON_FILE_NAME);
+            let reason = if metadata_path.exists() {
+                let metadata = fs::read_to_string(&metadata_path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|json| {
+                        serde_json::from_str::<serde_json::Value>(&json).map_err(|e| e.to_string())
+                    });
+                match metadata {
+                    Ok(_) => continue,
+                    Err(_) => OrphanReason::InvalidMetadata,
+                }
+            } else if path.join(migration::LEGACY_META_JSON_FILE_NAME).exists() {
+                continue;
+            } else {
+                OrphanReason::MissingMetadata
+            };
+            orphans.push((index_name, path, reason));
+        }
+
+        Ok(orphans)
+    }
+
+    fn is_reserved_dir(entry: &fs::DirEntry) -> bool {
+        Self::RESERVED_DIR_NAMES
+            .iter()
+            .any(|name| entry.file_name() == *name)
+    }
+
+    fn lock_path(&self, index_name: &str) -> PathBuf {
+        self.root
+            .join(format!("{index_name}.{}", Self::LOCK_FILE_EXTENSION))
+    }
+
+    /// Takes the lock of `index_name` to remove an entry of it, failing if a writer holds it.
+    /// There is no lock without a lock file, which is not created just for this.
+    fn lock_orphan(&self, index_name: &str) -> Result<Option<IndexLock>, String> {
+        match self.lock_path(index_name).exists() {
+            true => self.lock(index_name, false).map(Some),
+            false => Ok(None),
+        }
     }
 
     pub const META_JSON_FILE_NAME: &'static str = "meta.json";
+    pub const FORKS_DIR_NAME: &'static str = "forks";
     pub const FILE_INDEX_SNAPSHOT_FILE_NAME: &'static str = "file_index_snapshot.bin";
+    pub const UPDATE_HISTORY_FILE_NAME: &'static str = "updates.jsonl";
+    pub const UPDATE_JOURNAL_FILE_NAME: &'static str = "update_journal.json";
+    /// Directory of the root holding snapshots rather than an index.
+    pub const SNAPSHOTS_DIR_NAME: &'static str = "snapshots";
+    /// Directory of the root where full reindexes build the index that replaces the live one.
+    pub const REBUILDS_DIR_NAME: &'static str = ".rebuilds";
+    /// Directory of the root holding a home per tenant, each with its own indexes and tokens.
+    pub const TENANTS_DIR_NAME: &'static str = "tenants";
+    /// Directory of the root holding the clones of the repositories indexed by URL, one per index.
+    pub const CHECKOUTS_DIR_NAME: &'static str = "checkouts";
+    /// Directories of the root that do not hold an index.
+    const RESERVED_DIR_NAMES: [&'static str; 4] = [
+        Self::SNAPSHOTS_DIR_NAME,
+        Self::REBUILDS_DIR_NAME,
+        Self::TENANTS_DIR_NAME,
+        Self::CHECKOUTS_DIR_NAME,
+    ];
+    /// Extension of the lock files kept in the root next to the index directories, so removing
+    /// or resetting an index does not pull the lock away from a waiting writer.
+    pub const LOCK_FILE_EXTENSION: &'static str = "lock";
 }
 
 impl IndexStorage for FsStorage {
@@ -69,7 +678,22 @@ impl IndexStorage for FsStorage {
         self.root.to_string_lossy().to_string()
     }
 
-    fn create(&self, index_name: &str, target_path: &str) -> Result<Index, String> {
+    fn create(
+        &self,
+        index_name: &str,
+        target_path: &str,
+        options: &IndexingOptions,
+        tags: &[String],
+    ) -> Result<Index, String> {
+        if Self::RESERVED_DIR_NAMES.contains(&index_name) {
+            return Err(format!(
+                "'{index_name}' is reserved by beetle and cannot be used as an index name"
+            ));
+        }
+        let key = options
+            .encrypted
+            .then(|| self.encryption_key())
+            .transpose()?;
         let index_root_path = self.root.join(index_name);
         let absolute_index_root_path = dunce::canonicalize(self.root.join(index_name))
             .unwrap_or_else(|_| PathBuf::from(&index_root_path));
@@ -79,18 +703,20 @@ impl IndexStorage for FsStorage {
         fs::create_dir_all(&absolute_index_root_path)
             .map_err(|e| format!("Failed to create index directory {index_name}: {e}"))?;
 
-        let absolute_target_path = dunce::canonicalize(PathBuf::from(target_path))
-            .unwrap_or_else(|_| PathBuf::from(target_path));
-        if !absolute_target_path.exists() {
+        let absolute_target_path = normalize_path(Path::new(target_path));
+        if !Path::new(&absolute_target_path).exists() {
             return Err(format!(
-                "Target path '{}' does not exist",
-                absolute_target_path.to_string_lossy()
+                "Target path '{absolute_target_path}' does not exist"
             ));
         }
         let metadata = IndexStorageMetadata {
             index_name: index_name.to_string(),
             index_path: absolute_index_root_path.to_string_lossy().to_string(),
-            target_path: absolute_target_path.to_string_lossy().to_string(),
+            target_path: absolute_target_path,
+            options: options.clone(),
+            tags: tags.to_vec(),
+            format_version: FORMAT_VERSION,
+            encryption_key_id: key.as_ref().map(|key| key.id().to_string()),
         };
         let metadata_json = serde_json::to_string(&metadata)
             .map_err(|e| format!("Failed to serialize metadata for index {index_name}: {e}"))?;
@@ -101,28 +727,30 @@ impl IndexStorage for FsStorage {
         let index_path = absolute_index_root_path.join("index");
         fs::create_dir_all(&index_path)
             .map_err(|e| format!("Failed to create index directory {index_name}: {e}"))?;
-        let index = Index::create_in_dir(&index_path, CodeIndexSchema::new().schema)
-            .map_err(|e| format!("Failed to create index {index_name}: {e}"))?;
-        index
-            .tokenizers()
-            .register("code", CodeTokenizer::default());
+        let index = Index::create(
+            Self::index_directory(&index_path, key.as_ref())?,
+            CodeIndexSchema::with_options(options).schema,
+            index_settings(options.content_storage),
+        )
+        .map_err(|e| format!("Failed to create index {index_name}: {e}"))?;
+        register_tokenizers(&index);
 
         Ok(index)
     }
 
     fn open(&self, index_name: &str) -> Result<Index, String> {
-        let index_path = self.root.join(index_name).join("index");
+        let index_root_path = self.root.join(index_name);
+        let index_path = index_root_path.join("index");
+        if !index_root_path.exists() {
+            return Err(format!("Index {index_name} does not exist"));
+        }
+        let metadata = migration::read(&index_root_path)?;
+        migration::check_migrated(&metadata)?;
         if !index_path.exists() {
             return Err(format!("Index {index_name} does not exist"));
         }
 
-        let index = Index::open_in_dir(&index_path)
-            .map_err(|e| format!("Failed to open index {index_name}: {e}"))?;
-        index
-            .tokenizers()
-            .register("code", CodeTokenizer::default());
-
-        Ok(index)
+        Self::open_in_dir(index_name, &index_path, self.index_key(&metadata)?.as_ref())
     }
 
     fn remove(&self, index_name: &str) -> Result<(), String> {
@@ -136,8 +764,109 @@ impl IndexStorage for FsStorage {
         }
     }
 
+    fn checkout_dir(&self, index_name: &str) -> Option<PathBuf> {
+        Some(self.root.join(Self::CHECKOUTS_DIR_NAME).join(index_name))
+    }
+
+    fn lock(&self, index_name: &str, wait: bool) -> Result<IndexLock, String> {
+        fs::create_dir_all(&self.root)
+            .map_err(|e| format!("Failed to create index directory: {e}"))?;
+        let lock_path = self.lock_path(index_name);
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(|e| format!("Failed to open lock file {lock_path:?}: {e}"))?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(fs::TryLockError::WouldBlock) if wait => {
+                tracing::info!("Index {index_name} is busy, waiting for the other writer");
+                file.lock()
+                    .map_err(|e| format!("Failed to lock index {index_name}: {e}"))?;
+            }
+            Err(fs::TryLockError::WouldBlock) => {
+                return Err(format!(
+                    "Index {index_name} is busy: another beetle process is writing to it; \
+                     retry later or pass --wait to wait for it"
+                ));
+            }
+            Err(fs::TryLockError::Error(e)) => {
+                return Err(format!("Failed to lock index {index_name}: {e}"));
+            }
+        }
+
+        Ok(IndexLock { _file: Some(file) })
+    }
+
+    fn migrate(&self, index_name: &str) -> Result<(), String> {
+        let index_root_path = self.root.join(index_name);
+        if !index_root_path.exists() {
+            return Err(format!("Index {index_name} does not exist"));
+        }
+
+        migration::migrate(&index_root_path).map(|_| ())
+    }
+
+    /// Builds the replacement in `.rebuilds/<index_name>` and exchanges the two directories, so
+    /// searches see either the old or the new index. Searchers opened on the old index keep their
+    /// files until they are dropped.
+    fn rebuild(
+        &self,
+        index_name: &str,
+        build: &mut dyn FnMut(&dyn IndexStorage) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let metadata = self.get_metadata(index_name)?;
+        let index_dir = self.root.join(index_name);
+        let rebuild = FsStorage {
+            root: self.root.join(Self::REBUILDS_DIR_NAME),
+            encryption_key: self.encryption_key.clone(),
+        };
+        let rebuild_dir = rebuild.root.join(index_name);
+        // Left behind by an interrupted rebuild
+        if rebuild_dir.exists() {
+            fs::remove_dir_all(&rebuild_dir)
+                .map_err(|e| format!("Failed to remove stale rebuild {rebuild_dir:?}: {e}"))?;
+        }
+
+        rebuild.create(
+            index_name,
+            &metadata.target_path,
+            &metadata.options,
+            &metadata.tags,
+        )?;
+        let result = copy_if_exists(
+            &index_dir.join(Self::UPDATE_HISTORY_FILE_NAME),
+            &rebuild_dir.join(Self::UPDATE_HISTORY_FILE_NAME),
+        )
+        .and_then(|_| build(&rebuild))
+        // The copy keeps the live index's metadata, whose index_path points at the live directory
+        .and_then(|_| {
+            copy_if_exists(
+                &index_dir.join(Self::META_JSON_FILE_NAME),
+                &rebuild_dir.join(Self::META_JSON_FILE_NAME),
+            )
+        })
+        .and_then(|_| {
+            exchange_dirs(&rebuild_dir, &index_dir)
+                .map_err(|e| format!("Failed to swap in the rebuilt index {index_name}: {e}"))
+        });
+
+        // Holds the old index once swapped, or the failed rebuild
+        if let Err(e) = fs::remove_dir_all(&rebuild_dir) {
+            tracing::warn!("Failed to remove {rebuild_dir:?}: {e}");
+        }
+
+        result
+    }
+
     fn list(&self) -> Result<Vec<IndexStorageMetadata>, String> {
         let mut indices = Vec::new();
+        // Nothing has been created yet
+        if !self.root.exists() {
+            return Ok(indices);
+        }
 
         let entries =
             fs::read_dir(&self.root).map_err(|e| format!("Failed to read index directory: {e}"))?;
@@ -146,27 +875,18 @@ impl IndexStorage for FsStorage {
             let file_type = entry
                 .file_type()
                 .map_err(|e| format!("Failed to get file type: {e}"))?;
-            if !file_type.is_dir() {
+            if !file_type.is_dir() || Self::is_reserved_dir(&entry) {
                 continue;
             }
 
-            let index_metadata_path = entry.path().join(Self::META_JSON_FILE_NAME);
-            if !index_metadata_path.exists() {
-                return Err(format!(
-                    "Metadata file does not exist for index {}",
-                    entry.file_name().to_string_lossy()
-                ));
+            // One broken directory must not hide every other index; `clean` removes it
+            match migration::read(&entry.path()) {
+                Ok(metadata) => indices.push(metadata),
+                Err(e) => tracing::warn!("Skipping index directory {:?}: {e}", entry.path()),
             }
-
-            let metadata_json = fs::read_to_string(&index_metadata_path)
-                .map_err(|e| format!("Failed to read metadata file: {e}"))?;
-            let metadata: IndexStorageMetadata = serde_json::from_str(&metadata_json)
-                .map_err(|e| format!("Failed to parse metadata JSON: {e}"))?;
-
-            indices.push(metadata);
         }
 
-        indices.sort_by(|a, b| a.index_name.cmp(&b.index_name));
+        indices.sort_by(|a, b| natural_cmp(&a.index_name, &b.index_name));
 
         Ok(indices)
     }
@@ -176,24 +896,820 @@ impl IndexStorage for FsStorage {
         index_name: &str,
         metadata: Vec<FileIndexMetadata>,
     ) -> Result<(), String> {
-        let file_index_path = self.get_file_index_path(index_name)?;
-        let bytes = change::encode(&metadata)
+        let (file_index_path, key) = self.get_file_index_path(index_name)?;
+        let mut data = Vec::new();
+        change::write_snapshot(&mut data, &metadata)
             .map_err(|e| format!("Failed to encode file index metadata: {e}"))?;
-        fs::write(&file_index_path, bytes)
+        if let Some(key) = &key {
+            data = key
+                .seal(&data)
+                .map_err(|e| format!("Failed to encrypt file index metadata: {e}"))?;
+        }
+
+        // Written next to the snapshot and renamed over it, so a failed write keeps the old one.
+        let temp_path = file_index_path.with_extension("bin.tmp");
+        if let Err(e) = fs::write(&temp_path, data) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(format!(
+                "Failed to write file index metadata to {temp_path:?}: {e}"
+            ));
+        }
+        fs::rename(&temp_path, &file_index_path)
             .map_err(|e| format!("Failed to write file index metadata to {file_index_path:?}: {e}"))
     }
 
     fn read_file_index_metadata(&self, index_name: &str) -> Result<Vec<FileIndexMetadata>, String> {
-        let file_index_path = self.get_file_index_path(index_name)?;
+        let (file_index_path, key) = self.get_file_index_path(index_name)?;
         if !file_index_path.exists() {
             return Ok(Vec::new());
         }
 
-        let bytes = fs::read(&file_index_path).map_err(|e| {
+        Self::read_file_index(&file_index_path, key.as_ref()).map_err(|e| {
             format!("Failed to read file index metadata from {file_index_path:?}: {e}")
-        })?;
-        change::decode(&bytes).map_err(|e| {
-            format!("Failed to decode file index metadata from {file_index_path:?}: {e}")
         })
     }
+
+    fn append_update_record(&self, index_name: &str, record: &UpdateRecord) -> Result<(), String> {
+        let history_path = self
+            .root
+            .join(index_name)
+            .join(Self::UPDATE_HISTORY_FILE_NAME);
+        let line = serde_json::to_string(record)
+            .map_err(|e| format!("Failed to serialize update record: {e}"))?;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&history_path)
+            .map_err(|e| format!("Failed to open update history {history_path:?}: {e}"))?;
+
+        writeln!(file, "{line}")
+            .map_err(|e| format!("Failed to write update history {history_path:?}: {e}"))?;
+
+        let size = file
+            .metadata()
+            .map_err(|e| format!("Failed to read update history {history_path:?}: {e}"))?
+            .len();
+        if size > MAX_UPDATE_HISTORY_FILE_SIZE {
+            let history = self.read_update_history(index_name)?;
+            let mut lines = String::new();
+            for record in &history[history.len().saturating_sub(UPDATE_RECORDS_KEPT)..] {
+                let line = serde_json::to_string(record)
+                    .map_err(|e| format!("Failed to serialize update record: {e}"))?;
+                lines.push_str(&line);
+                lines.push('\n');
+            }
+            // Written aside and renamed, so that a reader never sees half of it
+            let compacted = history_path.with_extension("jsonl.tmp");
+            fs::write(&compacted, lines)
+                .and_then(|_| fs::rename(&compacted, &history_path))
+                .map_err(|e| format!("Failed to compact update history {history_path:?}: {e}"))?;
+        }
+
+        Ok(())
+    }
+
+    fn read_update_history(&self, index_name: &str) -> Result<Vec<UpdateRecord>, String> {
+        let history_path = self
+            .root
+            .join(index_name)
+            .join(Self::UPDATE_HISTORY_FILE_NAME);
+        if !history_path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let history = fs::read_to_string(&history_path)
+            .map_err(|e| format!("Failed to read update history {history_path:?}: {e}"))?;
+        // A line cut short by a crash mid-write is skipped rather than failing the whole history
+        Ok(history
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    fn read_update_journal(&self, index_name: &str) -> Result<Option<UpdateJournal>, String> {
+        let index_metadata = self.get_metadata(index_name)?;
+        let journal_path =
+            PathBuf::from(&index_metadata.index_path).join(Self::UPDATE_JOURNAL_FILE_NAME);
+        if !journal_path.exists() {
+            return Ok(None);
+        }
+
+        let mut data = fs::read(&journal_path)
+            .map_err(|e| format!("Failed to read update journal {journal_path:?}: {e}"))?;
+        if let Some(key) = self.index_key(&index_metadata)? {
+            data = key
+                .open(&data)
+                .map_err(|e| format!("Failed to decrypt update journal {journal_path:?}: {e}"))?;
+        }
+        serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| format!("Failed to parse update journal {journal_path:?}: {e}"))
+    }
+
+    fn save_update_journal(
+        &self,
+        index_name: &str,
+        journal: Option<&UpdateJournal>,
+    ) -> Result<(), String> {
+        let index_metadata = self.get_metadata(index_name)?;
+        let journal_path =
+            PathBuf::from(&index_metadata.index_path).join(Self::UPDATE_JOURNAL_FILE_NAME);
+        let Some(journal) = journal else {
+            return match fs::remove_file(&journal_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!(
+                    "Failed to remove update journal {journal_path:?}: {e}"
+                )),
+                _ => Ok(()),
+            };
+        };
+
+        let mut data = serde_json::to_vec(journal)
+            .map_err(|e| format!("Failed to serialize update journal: {e}"))?;
+        // The paths are as sensitive as the snapshot holding them
+        if let Some(key) = self.index_key(&index_metadata)? {
+            data = key
+                .seal(&data)
+                .map_err(|e| format!("Failed to encrypt update journal: {e}"))?;
+        }
+        // Renamed over the previous journal, so a crash mid-write leaves a whole one
+        let temp_path = journal_path.with_extension("json.tmp");
+        fs::write(&temp_path, data)
+            .and_then(|_| fs::rename(&temp_path, &journal_path))
+            .map_err(|e| format!("Failed to write update journal {journal_path:?}: {e}"))
+    }
+
+    fn diagnose(&self, fix: bool) -> Result<Vec<IndexIssue>, String> {
+        let mut issues = Vec::new();
+
+        let entries =
+            fs::read_dir(&self.root).map_err(|e| format!("Failed to read index directory: {e}"))?;
+        let mut index_dirs = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Failed to read entry: {e}"))?;
+            let file_type = entry
+                .file_type()
+                .map_err(|e| format!("Failed to get file type: {e}"))?;
+            if file_type.is_dir() && !Self::is_reserved_dir(&entry) {
+                index_dirs.push(entry);
+            }
+        }
+        index_dirs.sort_by_key(|entry| entry.file_name());
+
+        for entry in index_dirs {
+            let index_name = entry.file_name().to_string_lossy().to_string();
+            issues.extend(self.diagnose_index(&index_name, &entry.path(), fix));
+        }
+
+        Ok(issues)
+    }
+
+    fn stats(&self, index_name: &str) -> Result<IndexStats, String> {
+        let index = self.open(index_name)?;
+        let reader = index
+            .reader()
+            .map_err(|e| format!("Failed to create index reader for index {index_name}: {e}"))?;
+
+        let mut stats = IndexStats {
+            docs: reader.searcher().num_docs(),
+            last_update: self.read_update_history(index_name)?.pop(),
+            ..IndexStats::default()
+        };
+        let mut pending = vec![self.root.join(index_name)];
+        while let Some(dir) = pending.pop() {
+            let entries =
+                fs::read_dir(&dir).map_err(|e| format!("Failed to read directory {dir:?}: {e}"))?;
+            for entry in entries {
+                let entry = entry.map_err(|e| format!("Failed to read entry: {e}"))?;
+                let metadata = entry
+                    .metadata()
+                    .map_err(|e| format!("Failed to read metadata of {:?}: {e}", entry.path()))?;
+                if metadata.is_dir() {
+                    pending.push(entry.path());
+                    continue;
+                }
+
+                stats.size_bytes += metadata.len();
+                let modified = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|duration| duration.as_secs() as i64)
+                    .unwrap_or_default();
+                stats.updated_at = stats.updated_at.max(modified);
+            }
+        }
+
+        Ok(stats)
+    }
+
+    fn clean(&self, remove: bool) -> Result<Vec<OrphanedEntry>, String> {
+        let mut orphans = Self::find_orphaned_dirs(&self.root)?;
+        let rebuilds = self.root.join(Self::REBUILDS_DIR_NAME);
+        if let Ok(entries) = fs::read_dir(&rebuilds) {
+            for entry in entries.flatten() {
+                let index_name = entry.file_name().to_string_lossy().to_string();
+                orphans.push((index_name, entry.path(), OrphanReason::StaleRebuild));
+            }
+        }
+        if let Ok(entries) = fs::read_dir(&self.root) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let is_lock = path
+                    .extension()
+                    .is_some_and(|extension| extension == Self::LOCK_FILE_EXTENSION);
+                let Some(index_name) = path.file_stem().map(|stem| stem.to_string_lossy()) else {
+                    continue;
+                };
+                if is_lock && path.is_file() && !self.root.join(&*index_name).exists() {
+                    orphans.push((index_name.to_string(), path, OrphanReason::StaleLock));
+                }
+            }
+        }
+
+        let mut found = Vec::new();
+        for (index_name, path, reason) in orphans {
+            // A writer of the index, e.g. the reindex building the copy, holds its lock
+            if self.lock_orphan(&index_name).is_err() {
+                continue;
+            }
+            found.push(OrphanedEntry {
+                index_name,
+                path: path.to_string_lossy().to_string(),
+                reason,
+                size_bytes: disk_usage(&path),
+                removed: false,
+            });
+        }
+        found.sort_by(|a, b| natural_cmp(&a.path, &b.path));
+
+        match remove {
+            true => self.remove_orphans(found),
+            false => Ok(found),
+        }
+    }
+
+    fn remove_orphans(&self, entries: Vec<OrphanedEntry>) -> Result<Vec<OrphanedEntry>, String> {
+        let mut cleaned = Vec::new();
+        for mut entry in entries {
+            let path = PathBuf::from(&entry.path);
+            if !path.starts_with(&self.root) {
+                return Err(format!("{path:?} is not in the index directory"));
+            }
+            // Held until the entry is gone, so that no writer of the index starts meanwhile
+            let Ok(lock) = self.lock_orphan(&entry.index_name) else {
+                cleaned.push(entry);
+                continue;
+            };
+            let removed = match path.is_dir() {
+                true => fs::remove_dir_all(&path),
+                false => fs::remove_file(&path),
+            };
+            match removed {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("Failed to remove {path:?}: {e}")),
+            }
+            // The lock file of a directory that never held a usable index goes with it
+            let lock_path = self.lock_path(&entry.index_name);
+            if matches!(
+                entry.reason,
+                OrphanReason::MissingMetadata | OrphanReason::InvalidMetadata
+            ) && lock_path.exists()
+            {
+                let _ = fs::remove_file(&lock_path);
+            }
+            drop(lock);
+            entry.removed = true;
+            tracing::info!("Removed {path:?}: {}", entry.reason);
+            cleaned.push(entry);
+        }
+
+        Ok(cleaned)
+    }
+
+    fn fork(&self, index_name: &str) -> Result<IndexFork, String> {
+        let index = self.open(index_name)?;
+        let key = self.key_of(index_name)?;
+        // Segment files are immutable once committed, so hard links to the segments of the
+        // current commit stay valid after the live index merges or garbage-collects them.
+        let metas = index
+            .load_metas()
+            .map_err(|e| format!("Failed to load metas for index {index_name}: {e}"))?;
+
+        let index_path = self.root.join(index_name).join("index");
+        let fork_path = self
+            .root
+            .join(index_name)
+            .join(Self::FORKS_DIR_NAME)
+            .join(uuid::Uuid::new_v4().simple().to_string());
+        fs::create_dir_all(&fork_path)
+            .map_err(|e| format!("Failed to create fork directory {fork_path:?}: {e}"))?;
+
+        let linked = committed_segment_files(&metas).iter().try_for_each(|file| {
+            fs::hard_link(index_path.join(file), fork_path.join(file)).map_err(|e| {
+                format!("Failed to link {file:?} of index {index_name} into fork: {e}")
+            })
+        });
+        let opened = linked
+            .and_then(|_| {
+                let meta_json = serde_json::to_vec_pretty(&metas).map_err(|e| {
+                    format!("Failed to serialize metas for index {index_name}: {e}")
+                })?;
+                let meta_json = match &key {
+                    Some(key) => key.seal(&meta_json),
+                    None => Ok(meta_json),
+                };
+                meta_json
+                    .and_then(|meta_json| fs::write(fork_path.join("meta.json"), meta_json))
+                    .map_err(|e| format!("Failed to write fork metas for index {index_name}: {e}"))
+            })
+            .and_then(|_| Self::open_in_dir(index_name, &fork_path, key.as_ref()));
+
+        match opened {
+            Ok(index) => Ok(IndexFork {
+                index,
+                path: Some(fork_path),
+            }),
+            Err(e) => {
+                let _ = fs::remove_dir_all(&fork_path);
+                Err(e)
+            }
+        }
+    }
+
+    fn create_snapshot(&self, index_name: &str) -> Result<SnapshotInfo, String> {
+        let metas = self
+            .open(index_name)?
+            .load_metas()
+            .map_err(|e| format!("Failed to load metas for index {index_name}: {e}"))?;
+
+        snapshot::create(
+            index_name,
+            &self.root.join(index_name),
+            &metas,
+            self.key_of(index_name)?.as_ref(),
+            &[
+                Self::META_JSON_FILE_NAME,
+                Self::FILE_INDEX_SNAPSHOT_FILE_NAME,
+                Self::UPDATE_HISTORY_FILE_NAME,
+            ],
+            &self.root.join(Self::SNAPSHOTS_DIR_NAME),
+        )
+    }
+
+    fn list_snapshots(&self, index_name: Option<&str>) -> Result<Vec<SnapshotInfo>, String> {
+        snapshot::list(&self.root.join(Self::SNAPSHOTS_DIR_NAME), index_name)
+    }
+
+    fn restore_snapshot(
+        &self,
+        index_name: &str,
+        snapshot_id: &str,
+    ) -> Result<SnapshotInfo, String> {
+        snapshot::restore(
+            index_name,
+            snapshot_id,
+            &self.root.join(index_name),
+            &self.root.join(Self::SNAPSHOTS_DIR_NAME),
+        )
+    }
+}
+
+/// Bytes taken by the file or directory at `path`, counting what can be read.
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| disk_usage(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+fn copy_if_exists(from: &Path, to: &Path) -> Result<(), String> {
+    if from.exists() {
+        fs::copy(from, to).map_err(|e| format!("Failed to copy {from:?} to {to:?}: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Swaps the directories `a` and `b`, in a single step where the platform supports it.
+fn exchange_dirs(a: &Path, b: &Path) -> std::io::Result<()> {
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    match rename_exchange(a, b) {
+        Ok(()) => return Ok(()),
+        // Not every file system or kernel supports the exchange
+        Err(e) if matches!(e.raw_os_error(), Some(libc::EINVAL | libc::ENOSYS)) => {}
+        Err(e) => return Err(e),
+    }
+
+    // `b` is briefly missing between these renames
+    let mut swap = a.as_os_str().to_owned();
+    swap.push(".swap");
+    fs::rename(b, &swap)?;
+    if let Err(e) = fs::rename(a, b) {
+        // Put `b` back rather than leaving it missing
+        let _ = fs::rename(&swap, b);
+        return Err(e);
+    }
+
+    fs::rename(&swap, a)
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn rename_exchange(a: &Path, b: &Path) -> std::io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let to_c_path = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+    };
+    let (a, b) = (to_c_path(a)?, to_c_path(b)?);
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            a.as_ptr(),
+            libc::AT_FDCWD,
+            b.as_ptr(),
+            libc::RENAME_EXCHANGE,
+        )
+    };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// The files of the segments of the commit `metas`, relative to the index directory.
+pub(crate) fn committed_segment_files(metas: &IndexMeta) -> Vec<PathBuf> {
+    metas.segments.iter().flat_map(segment_files).collect()
+}
+
+/// The files of the committed `segment`, relative to the index directory.
+pub(crate) fn segment_files(segment: &SegmentMeta) -> impl Iterator<Item = PathBuf> + '_ {
+    SegmentComponent::iterator()
+        .filter(|component| match component {
+            SegmentComponent::TempStore => false,
+            SegmentComponent::Delete => segment.has_deletes(),
+            _ => true,
+        })
+        .map(|component| segment.relative_path(*component))
+}
+
+/// Compares names case-insensitively, treating runs of digits as numbers so `idx2 < idx10`.
+///
+/// Names that only differ in case or leading zeros fall back to a plain comparison, keeping the
+/// order total and stable.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x_digits = take_digits(&mut a_chars);
+                let y_digits = take_digits(&mut b_chars);
+                let x_number = x_digits.trim_start_matches('0');
+                let y_number = y_digits.trim_start_matches('0');
+                let ordering = x_number
+                    .len()
+                    .cmp(&y_number.len())
+                    .then_with(|| x_number.cmp(y_number));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        digits.push(c);
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = vec!["idx10", "Beta", "idx2", "alpha", "idx1", "IDX3", "idx02"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+
+        assert_eq!(
+            names,
+            vec!["alpha", "Beta", "idx1", "idx02", "idx2", "IDX3", "idx10"]
+        );
+    }
+
+    #[test]
+    fn test_natural_cmp_is_total() {
+        assert_eq!(natural_cmp("idx", "idx"), Ordering::Equal);
+        assert_ne!(natural_cmp("Idx", "idx"), Ordering::Equal);
+        assert_ne!(natural_cmp("idx2", "idx02"), Ordering::Equal);
+        assert_eq!(natural_cmp("idx", "idx1"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_index_lock_excludes_other_writers() {
+        let home = tempfile::tempdir().unwrap();
+        let storage = FsStorage::new(home.path().to_path_buf());
+
+        let lock = storage.lock("idx", false).unwrap();
+        let error = storage.lock("idx", false).map(|_| ()).unwrap_err();
+        assert!(error.contains("busy"), "{error}");
+        // Locks are per index
+        storage.lock("idx.v2", false).unwrap();
+
+        drop(lock);
+        storage.lock("idx", false).unwrap();
+    }
+
+    #[test]
+    fn test_rebuild_replaces_index_only_on_success() {
+        let home = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let storage = FsStorage::new(home.path().to_path_buf());
+        storage
+            .create(
+                "idx",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        let index_dir = home.path().join("idx");
+        let metadata = storage.get_metadata("idx").unwrap();
+        storage
+            .append_update_record("idx", &UpdateRecord::default())
+            .unwrap();
+        fs::write(index_dir.join("marker"), "").unwrap();
+
+        let error = storage
+            .rebuild("idx", &mut |_| Err("build failed".to_string()))
+            .unwrap_err();
+        assert_eq!(error, "build failed");
+        assert!(index_dir.join("marker").exists());
+
+        storage
+            .rebuild("idx", &mut |rebuild| {
+                assert_eq!(rebuild.read_update_history("idx")?.len(), 1);
+                assert!(rebuild.read_file_index_metadata("idx")?.is_empty());
+                Ok(())
+            })
+            .unwrap();
+        assert!(!index_dir.join("marker").exists());
+        assert_eq!(
+            storage.get_metadata("idx").unwrap().index_path,
+            metadata.index_path
+        );
+        assert_eq!(storage.read_update_history("idx").unwrap().len(), 1);
+        assert!(!home
+            .path()
+            .join(FsStorage::REBUILDS_DIR_NAME)
+            .join("idx")
+            .exists());
+        assert_eq!(storage.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_update_history_is_compacted() {
+        let home = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let storage = FsStorage::new(home.path().to_path_buf());
+        storage
+            .create(
+                "idx",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        let history_path = home
+            .path()
+            .join("idx")
+            .join(FsStorage::UPDATE_HISTORY_FILE_NAME);
+        let line = serde_json::to_string(&UpdateRecord::default()).unwrap();
+        fs::write(
+            &history_path,
+            format!("{line}\n").repeat(10 * UPDATE_RECORDS_KEPT),
+        )
+        .unwrap();
+
+        let record = UpdateRecord {
+            files_added: 7,
+            ..UpdateRecord::default()
+        };
+        storage.append_update_record("idx", &record).unwrap();
+        let history = storage.read_update_history("idx").unwrap();
+        assert_eq!(history.len(), UPDATE_RECORDS_KEPT);
+        assert_eq!(history.last().unwrap().files_added, 7);
+        assert!(fs::metadata(&history_path).unwrap().len() <= MAX_UPDATE_HISTORY_FILE_SIZE);
+        assert!(!history_path.with_extension("jsonl.tmp").exists());
+    }
+
+    #[test]
+    fn test_clean_removes_orphaned_entries() {
+        let home = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let storage = FsStorage::new(home.path().to_path_buf());
+        storage
+            .create(
+                "idx",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        let root = home.path();
+        fs::create_dir_all(root.join("partial").join("index")).unwrap();
+        fs::create_dir(root.join("broken")).unwrap();
+        fs::write(
+            root.join("broken").join(FsStorage::META_JSON_FILE_NAME),
+            "{",
+        )
+        .unwrap();
+        fs::create_dir_all(root.join(FsStorage::REBUILDS_DIR_NAME).join("idx")).unwrap();
+        fs::write(root.join("gone.lock"), "").unwrap();
+        // Not an index name, so not beetle's
+        fs::create_dir(root.join(".git")).unwrap();
+
+        // Broken directories do not hide the other indexes
+        assert_eq!(storage.list().unwrap().len(), 1);
+
+        let found = storage.clean(false).unwrap();
+        let reasons: Vec<(&str, OrphanReason)> = found
+            .iter()
+            .map(|entry| (entry.index_name.as_str(), entry.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("idx", OrphanReason::StaleRebuild),
+                ("broken", OrphanReason::InvalidMetadata),
+                ("gone", OrphanReason::StaleLock),
+                ("partial", OrphanReason::MissingMetadata),
+            ]
+        );
+        assert!(found.iter().all(|entry| !entry.removed));
+        assert!(root.join("partial").exists());
+
+        // Entries of an index being written to are left alone
+        let lock = storage.lock("partial", false).unwrap();
+        let cleaned = storage.clean(true).unwrap();
+        assert_eq!(cleaned.len(), 3);
+        assert!(cleaned.iter().all(|entry| entry.removed));
+        assert!(root.join("partial").exists());
+        assert!(!root.join("broken").exists());
+        assert!(!root.join("gone.lock").exists());
+        assert!(root.join(".git").exists());
+        drop(lock);
+
+        storage.clean(true).unwrap();
+        assert!(!root.join("partial").exists());
+        assert_eq!(storage.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_remove_orphans_removes_only_given_entries() {
+        let home = tempfile::tempdir().unwrap();
+        let root = home.path();
+        let storage = FsStorage::new(root.to_path_buf());
+        fs::create_dir(root.join("partial")).unwrap();
+        fs::create_dir(root.join("busy")).unwrap();
+        let found = storage.clean(false).unwrap();
+        assert_eq!(found.len(), 2);
+
+        // Orphaned after the entries were listed, so not confirmed
+        fs::create_dir(root.join("later")).unwrap();
+        let lock = storage.lock("busy", false).unwrap();
+        let cleaned = storage.remove_orphans(found).unwrap();
+        let removed: Vec<(&str, bool)> = cleaned
+            .iter()
+            .map(|entry| (entry.index_name.as_str(), entry.removed))
+            .collect();
+        assert_eq!(removed, vec![("busy", false), ("partial", true)]);
+        assert!(!root.join("partial").exists());
+        assert!(root.join("busy").exists());
+        assert!(root.join("later").exists());
+        drop(lock);
+
+        let outside = OrphanedEntry {
+            index_name: "outside".to_string(),
+            path: tempfile::tempdir()
+                .unwrap()
+                .path()
+                .to_string_lossy()
+                .to_string(),
+            reason: OrphanReason::MissingMetadata,
+            size_bytes: 0,
+            removed: false,
+        };
+        assert!(storage.remove_orphans(vec![outside]).is_err());
+    }
+
+    #[test]
+    fn test_diagnose_fixes_only_unlocked_indexes() {
+        let home = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let storage = FsStorage::new(home.path().to_path_buf());
+        storage
+            .create(
+                "idx",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        let snapshot = home
+            .path()
+            .join("idx")
+            .join(FsStorage::FILE_INDEX_SNAPSHOT_FILE_NAME);
+        fs::write(&snapshot, "not a snapshot").unwrap();
+
+        // A writer holds the index: fixing must leave it alone
+        let lock = storage.lock("idx", false).unwrap();
+        let issues = storage.diagnose(true).unwrap();
+        assert!(matches!(
+            issues[0].problem,
+            IndexProblem::CorruptedSnapshot(_)
+        ));
+        assert!(!issues[0].fixed);
+        assert_eq!(fs::read(&snapshot).unwrap(), b"not a snapshot");
+        drop(lock);
+
+        let issues = storage.diagnose(true).unwrap();
+        assert!(issues[0].fixed);
+        assert!(storage.diagnose(false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_encrypted_index_needs_its_key() {
+        let home = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let key = |hex: &str| EncryptionKey::parse(&hex.repeat(32)).unwrap();
+        let storage = FsStorage::new(home.path().to_path_buf()).with_encryption_key(key("01"));
+        storage
+            .create(
+                "idx",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default().with_encryption(true),
+                &[],
+            )
+            .unwrap();
+        let files = vec![FileIndexMetadata {
+            path: "secret.rs".to_string(),
+            size: 1,
+            modified_time: 0,
+            content_hash: None,
+        }];
+        storage.save_file_index_metadata("idx", files).unwrap();
+
+        let file_list = fs::read(
+            home.path()
+                .join("idx")
+                .join(FsStorage::FILE_INDEX_SNAPSHOT_FILE_NAME),
+        )
+        .unwrap();
+        assert!(!String::from_utf8_lossy(&file_list).contains("secret.rs"));
+        assert_eq!(storage.read_file_index_metadata("idx").unwrap().len(), 1);
+        storage.open("idx").unwrap();
+        storage.fork("idx").unwrap();
+
+        let other = FsStorage::new(home.path().to_path_buf()).with_encryption_key(key("02"));
+        let error = other.open("idx").map(|_| ()).unwrap_err();
+        assert!(error.contains("another key"), "{error}");
+        // A missing key is not a damaged index: fixing must leave it alone
+        let issues = other.diagnose(true).unwrap();
+        assert!(matches!(issues[0].problem, IndexProblem::UnavailableKey(_)));
+        assert!(!issues[0].fixed);
+        assert_eq!(storage.read_file_index_metadata("idx").unwrap().len(), 1);
+    }
 }
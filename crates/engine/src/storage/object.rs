@@ -0,0 +1,597 @@
+use super::s3::{Bucket, S3Config};
+use super::{
+    natural_cmp, FsStorage, IndexFork, IndexLock, IndexStats, IndexStorage, IndexStorageMetadata,
+};
+use super::{UpdateJournal, UpdateRecord};
+use crate::change::FileIndexMetadata;
+use crate::doctor::{IndexIssue, OrphanedEntry};
+use crate::options::IndexingOptions;
+use crate::snapshot::SnapshotInfo;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use tantivy::Index;
+
+/// How long a pulled index is used before the bucket is checked for a newer version.
+const PULL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Keeps indexes in an S3-compatible bucket, working on a local copy under the cache directory.
+///
+/// Every index is stored under `<prefix><index name>/` with the same layout as [`FsStorage`].
+/// Reading an index first pulls the objects that changed since the last pull (tantivy's
+/// `meta.json` last, so a half-finished pull never references missing segments); committing
+/// pushes the changed files back (again `meta.json` last) and deletes segments tantivy dropped.
+/// Segment files are immutable, so updates only transfer new segments.
+///
+/// There is no locking across machines: when two writers publish the same index, the last one
+/// wins. Build shared indexes in one place (e.g. CI) and read them everywhere else.
+pub struct ObjectStorage {
+    cache: FsStorage,
+    bucket: Bucket,
+    prefix: String,
+    /// When each index was last pulled in this process.
+    pulled: Mutex<HashMap<String, Instant>>,
+    /// Whether indexes created on another machine may be pushed from this one.
+    push_pulled: bool,
+}
+
+/// What the local copy of an index was last synced from, stored in the cache as `remote.json`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct SyncState {
+    /// Synced files by path relative to the index directory.
+    files: HashMap<String, SyncedFile>,
+    /// Whether the local copy was first pulled from the bucket rather than created here.
+    #[serde(default)]
+    pulled: bool,
+}
+
+#[derive(Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+struct SyncedFile {
+    etag: String,
+    /// Size and modification time of the local file when it was synced, to detect local changes.
+    size: u64,
+    modified: u64,
+}
+
+impl ObjectStorage {
+    /// `prefix` places the indexes below a "directory" of the bucket, e.g. `team/`.
+    pub fn new(cache_root: PathBuf, config: S3Config, prefix: &str) -> Self {
+        let prefix = prefix.trim_matches('/');
+        ObjectStorage {
+            cache: FsStorage::new(cache_root),
+            bucket: Bucket::new(config),
+            prefix: if prefix.is_empty() {
+                String::new()
+            } else {
+                format!("{prefix}/")
+            },
+            pulled: Mutex::new(HashMap::new()),
+            push_pulled: false,
+        }
+    }
+
+    /// Allows pushing indexes this machine pulled rather than created. Their target path names a
+    /// directory of the machine that created them, so an update here may find none of its files.
+    pub fn with_push_pulled(mut self, push_pulled: bool) -> Self {
+        self.push_pulled = push_pulled;
+        self
+    }
+
+    fn index_key(&self, index_name: &str) -> String {
+        format!("{}{index_name}/", self.prefix)
+    }
+
+    fn cache_path(&self, index_name: &str) -> PathBuf {
+        self.cache.root.join(index_name)
+    }
+
+    /// Pulls `index_name` unless it was pulled recently. The cached copy is used when the bucket
+    /// cannot be reached.
+    fn ensure_pulled(&self, index_name: &str) -> Result<(), String> {
+        let recently_pulled = self
+            .pulled
+            .lock()
+            .unwrap()
+            .get(index_name)
+            .is_some_and(|pulled| pulled.elapsed() < PULL_INTERVAL);
+        if recently_pulled {
+            return Ok(());
+        }
+
+        match self.pull(index_name) {
+            Ok(()) => {}
+            Err(e) if self.cache_path(index_name).exists() => {
+                tracing::warn!("Using cached copy of index {index_name}: {e}");
+            }
+            Err(e) => return Err(e),
+        }
+        self.pulled
+            .lock()
+            .unwrap()
+            .insert(index_name.to_string(), Instant::now());
+
+        Ok(())
+    }
+
+    fn pull(&self, index_name: &str) -> Result<(), String> {
+        let index_key = self.index_key(index_name);
+        let (objects, _) = self.bucket.list(&index_key, None)?;
+        if objects.is_empty() {
+            // Never published, or removed from the bucket
+            return Ok(());
+        }
+
+        let index_path = self.cache_path(index_name);
+        let mut state = read_sync_state(&index_path);
+        if state.files.is_empty() {
+            state.pulled = true;
+        }
+        let mut remote: Vec<_> = objects
+            .into_iter()
+            .filter_map(|object| {
+                let relative = object.key.strip_prefix(&index_key)?.to_string();
+                is_synced(&relative).then_some((relative, object.etag))
+            })
+            .collect();
+        remote.sort_by_key(|(relative, _)| relative == Self::TANTIVY_META_PATH);
+
+        for (relative, etag) in &remote {
+            let local_path = index_path.join(relative);
+            let unchanged = state.files.get(relative).is_some_and(|synced| {
+                synced.etag == *etag
+                    && file_stamp(&local_path) == Some((synced.size, synced.modified))
+            });
+            if unchanged {
+                continue;
+            }
+
+            if let Some(parent) = local_path.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create directory {parent:?}: {e}"))?;
+            }
+            let temp_path = local_path.with_extension("tmp");
+            self.bucket
+                .download(&format!("{index_key}{relative}"), &temp_path)?;
+            fs::rename(&temp_path, &local_path)
+                .map_err(|e| format!("Failed to write {local_path:?}: {e}"))?;
+            if relative == FsStorage::META_JSON_FILE_NAME {
+                self.localize_metadata(index_name, &local_path)?;
+            }
+
+            let (size, modified) = file_stamp(&local_path).unwrap_or_default();
+            state.files.insert(
+                relative.clone(),
+                SyncedFile {
+                    etag: etag.clone(),
+                    size,
+                    modified,
+                },
+            );
+        }
+
+        // Segments the publisher merged away
+        state.files.retain(|relative, _| {
+            let kept = remote
+                .iter()
+                .any(|(remote_relative, _)| remote_relative == relative);
+            if !kept {
+                let _ = fs::remove_file(index_path.join(relative));
+            }
+            kept
+        });
+
+        write_sync_state(&index_path, &state)
+    }
+
+    /// Points the pulled metadata at the cache, since it names the publisher's directory.
+    fn localize_metadata(&self, index_name: &str, metadata_path: &Path) -> Result<(), String> {
+        let mut metadata = read_metadata(metadata_path)?;
+        metadata.index_path = self.cache_path(index_name).to_string_lossy().to_string();
+        let metadata_json = serde_json::to_string(&metadata)
+            .map_err(|e| format!("Failed to serialize metadata for index {index_name}: {e}"))?;
+
+        fs::write(metadata_path, metadata_json)
+            .map_err(|e| format!("Failed to write metadata file for index {index_name}: {e}"))
+    }
+
+    fn push(&self, index_name: &str) -> Result<(), String> {
+        let index_key = self.index_key(index_name);
+        let index_path = self.cache_path(index_name);
+        let mut state = read_sync_state(&index_path);
+        self.check_push(index_name, &state)?;
+
+        let mut local = local_files(&index_path)?;
+        local.sort_by_key(|relative| relative == Self::TANTIVY_META_PATH);
+        for relative in &local {
+            let local_path = index_path.join(relative);
+            let Some((size, modified)) = file_stamp(&local_path) else {
+                continue;
+            };
+            let unchanged = state
+                .files
+                .get(relative)
+                .is_some_and(|synced| (synced.size, synced.modified) == (size, modified));
+            if unchanged {
+                continue;
+            }
+
+            let etag = self
+                .bucket
+                .upload(&format!("{index_key}{relative}"), &local_path)?;
+            state.files.insert(
+                relative.clone(),
+                SyncedFile {
+                    etag,
+                    size,
+                    modified,
+                },
+            );
+        }
+
+        // Segments tantivy merged or garbage-collected since the last push
+        let (objects, _) = self.bucket.list(&index_key, None)?;
+        for object in objects {
+            let relative = object.key.strip_prefix(&index_key).unwrap_or(&object.key);
+            if is_synced(relative) && !local.iter().any(|local| local == relative) {
+                self.bucket.delete(&object.key)?;
+            }
+        }
+        state.files.retain(|relative, _| local.contains(relative));
+        write_sync_state(&index_path, &state)?;
+
+        self.pulled
+            .lock()
+            .unwrap()
+            .insert(index_name.to_string(), Instant::now());
+
+        Ok(())
+    }
+
+    fn check_push(&self, index_name: &str, state: &SyncState) -> Result<(), String> {
+        if state.pulled && !self.push_pulled {
+            return Err(format!(
+                "Index {index_name} was pulled from the bucket rather than created on this \
+                 machine; pushing it has to be allowed explicitly"
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn list_remote(&self) -> Result<Vec<IndexStorageMetadata>, String> {
+        let (_, prefixes) = self.bucket.list(&self.prefix, Some("/"))?;
+
+        let mut indexes = Vec::new();
+        for index_key in prefixes {
+            let index_name = index_key
+                .strip_prefix(&self.prefix)
+                .unwrap_or(&index_key)
+                .trim_end_matches('/');
+            let metadata_key = format!("{index_key}{}", FsStorage::META_JSON_FILE_NAME);
+            // Not an index, or one still being created
+            let Some(metadata_json) = self.bucket.get(&metadata_key)? else {
+                continue;
+            };
+
+            let mut metadata: IndexStorageMetadata = serde_json::from_slice(&metadata_json)
+                .map_err(|e| format!("Failed to parse metadata JSON of {metadata_key}: {e}"))?;
+            metadata.index_path = self.cache_path(index_name).to_string_lossy().to_string();
+            indexes.push(metadata);
+        }
+
+        Ok(indexes)
+    }
+
+    /// Path of tantivy's list of committed segments, relative to the index directory.
+    const TANTIVY_META_PATH: &'static str = "index/meta.json";
+    const SYNC_STATE_FILE_NAME: &'static str = "remote.json";
+}
+
+impl IndexStorage for ObjectStorage {
+    fn index_dir(&self) -> String {
+        self.cache.index_dir()
+    }
+
+    fn create(
+        &self,
+        index_name: &str,
+        target_path: &str,
+        options: &IndexingOptions,
+        tags: &[String],
+    ) -> Result<Index, String> {
+        let metadata_key = format!(
+            "{}{}",
+            self.index_key(index_name),
+            FsStorage::META_JSON_FILE_NAME
+        );
+        if self.bucket.get(&metadata_key)?.is_some() {
+            return Err(format!("Index {index_name} already exists"));
+        }
+
+        let index = self.cache.create(index_name, target_path, options, tags)?;
+        self.push(index_name)?;
+
+        Ok(index)
+    }
+
+    fn open(&self, index_name: &str) -> Result<Index, String> {
+        self.ensure_pulled(index_name)?;
+        self.cache.open(index_name)
+    }
+
+    fn remove(&self, index_name: &str) -> Result<(), String> {
+        let index_key = self.index_key(index_name);
+        let (objects, _) = self.bucket.list(&index_key, None)?;
+        for object in &objects {
+            self.bucket.delete(&object.key)?;
+        }
+        self.pulled.lock().unwrap().remove(index_name);
+
+        match self.cache.remove(index_name) {
+            Err(_) if !objects.is_empty() => Ok(()),
+            removed => removed,
+        }
+    }
+
+    /// Lists the indexes of the bucket, plus cached ones that were never published. Falls back
+    /// to the cache when the bucket cannot be reached.
+    fn list(&self) -> Result<Vec<IndexStorageMetadata>, String> {
+        let cached = self.cache.list()?;
+        let mut indexes = match self.list_remote() {
+            Ok(indexes) => indexes,
+            Err(e) => {
+                tracing::warn!("Listing cached indexes only: {e}");
+                return Ok(cached);
+            }
+        };
+        for metadata in cached {
+            if !indexes
+                .iter()
+                .any(|index| index.index_name == metadata.index_name)
+            {
+                indexes.push(metadata);
+            }
+        }
+        indexes.sort_by(|a, b| natural_cmp(&a.index_name, &b.index_name));
+
+        Ok(indexes)
+    }
+
+    fn get_metadata(&self, index_name: &str) -> Result<IndexStorageMetadata, String> {
+        self.ensure_pulled(index_name)?;
+        self.cache.get_metadata(index_name)
+    }
+
+    fn read_file_index_metadata(&self, index_name: &str) -> Result<Vec<FileIndexMetadata>, String> {
+        self.ensure_pulled(index_name)?;
+        self.cache.read_file_index_metadata(index_name)
+    }
+
+    fn save_file_index_metadata(
+        &self,
+        index_name: &str,
+        metadata: Vec<FileIndexMetadata>,
+    ) -> Result<(), String> {
+        self.cache.save_file_index_metadata(index_name, metadata)
+    }
+
+    fn append_update_record(&self, index_name: &str, record: &UpdateRecord) -> Result<(), String> {
+        self.cache.append_update_record(index_name, record)
+    }
+
+    fn read_update_history(&self, index_name: &str) -> Result<Vec<UpdateRecord>, String> {
+        self.ensure_pulled(index_name)?;
+        self.cache.read_update_history(index_name)
+    }
+
+    /// Journals stay in the cache, next to the copy of the index the update wrote to.
+    fn read_update_journal(&self, index_name: &str) -> Result<Option<UpdateJournal>, String> {
+        self.cache.read_update_journal(index_name)
+    }
+
+    fn save_update_journal(
+        &self,
+        index_name: &str,
+        journal: Option<&UpdateJournal>,
+    ) -> Result<(), String> {
+        self.cache.save_update_journal(index_name, journal)
+    }
+
+    /// Checks the cached copies only.
+    fn diagnose(&self, fix: bool) -> Result<Vec<IndexIssue>, String> {
+        self.cache.diagnose(fix)
+    }
+
+    fn clean(&self, remove: bool) -> Result<Vec<OrphanedEntry>, String> {
+        self.cache.clean(remove)
+    }
+
+    fn remove_orphans(&self, entries: Vec<OrphanedEntry>) -> Result<Vec<OrphanedEntry>, String> {
+        self.cache.remove_orphans(entries)
+    }
+
+    fn checkout_dir(&self, index_name: &str) -> Option<PathBuf> {
+        self.cache.checkout_dir(index_name)
+    }
+
+    fn stats(&self, index_name: &str) -> Result<IndexStats, String> {
+        self.ensure_pulled(index_name)?;
+        self.cache.stats(index_name)
+    }
+
+    fn fork(&self, index_name: &str) -> Result<IndexFork, String> {
+        self.ensure_pulled(index_name)?;
+        self.cache.fork(index_name)
+    }
+
+    /// Rebuilds the cached copy, then uploads the result and deletes the replaced segments.
+    fn rebuild(
+        &self,
+        index_name: &str,
+        build: &mut dyn FnMut(&dyn IndexStorage) -> Result<(), String>,
+    ) -> Result<(), String> {
+        self.ensure_pulled(index_name)?;
+        self.cache.rebuild(index_name, build)?;
+        self.push(index_name)
+    }
+
+    /// Locks the cached copy only: writers on other machines are not excluded.
+    fn lock(&self, index_name: &str, wait: bool) -> Result<IndexLock, String> {
+        self.cache.lock(index_name, wait)
+    }
+
+    /// Migrates the cached copy; indexes are published in the current format.
+    fn migrate(&self, index_name: &str) -> Result<(), String> {
+        self.cache.migrate(index_name)
+    }
+
+    fn publish(&self, index_name: &str) -> Result<(), String> {
+        self.push(index_name)
+    }
+
+    /// Snapshots are kept in the cache only.
+    fn create_snapshot(&self, index_name: &str) -> Result<SnapshotInfo, String> {
+        self.ensure_pulled(index_name)?;
+        self.cache.create_snapshot(index_name)
+    }
+
+    fn list_snapshots(&self, index_name: Option<&str>) -> Result<Vec<SnapshotInfo>, String> {
+        self.cache.list_snapshots(index_name)
+    }
+
+    /// Restores the cached copy and publishes it.
+    fn restore_snapshot(
+        &self,
+        index_name: &str,
+        snapshot_id: &str,
+    ) -> Result<SnapshotInfo, String> {
+        let info = self.cache.restore_snapshot(index_name, snapshot_id)?;
+        // The restored files carry new modification times and are all uploaded again
+        self.push(index_name)?;
+
+        Ok(info)
+    }
+}
+
+/// Whether `relative` is part of the published index, rather than local state such as forks,
+/// tantivy's lock files or files being written.
+fn is_synced(relative: &str) -> bool {
+    let file_name = relative.rsplit('/').next().unwrap_or(relative);
+
+    relative.split('/').next() != Some(FsStorage::FORKS_DIR_NAME)
+        && relative != ObjectStorage::SYNC_STATE_FILE_NAME
+        && !file_name.starts_with(".tantivy-")
+        && !file_name.ends_with(".tmp")
+}
+
+/// Paths of the synced files of the index at `index_path`, relative to it and `/`-separated.
+fn local_files(index_path: &Path) -> Result<Vec<String>, String> {
+    let mut files = Vec::new();
+    let mut pending = vec![index_path.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries =
+            fs::read_dir(&dir).map_err(|e| format!("Failed to read directory {dir:?}: {e}"))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Failed to read entry: {e}"))?;
+            let path = entry.path();
+            let relative = path
+                .strip_prefix(index_path)
+                .unwrap_or(&path)
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if !is_synced(&relative) {
+                continue;
+            }
+
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                files.push(relative);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Size and modification time (in nanoseconds since the Unix epoch) of the file at `path`.
+fn file_stamp(path: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()?
+        .as_nanos() as u64;
+
+    Some((metadata.len(), modified))
+}
+
+fn read_metadata(path: &Path) -> Result<IndexStorageMetadata, String> {
+    let metadata_json =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read metadata file: {e}"))?;
+
+    serde_json::from_str(&metadata_json).map_err(|e| format!("Failed to parse metadata JSON: {e}"))
+}
+
+/// A missing or unreadable state makes the next sync transfer every file again.
+fn read_sync_state(index_path: &Path) -> SyncState {
+    fs::read_to_string(index_path.join(ObjectStorage::SYNC_STATE_FILE_NAME))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn write_sync_state(index_path: &Path, state: &SyncState) -> Result<(), String> {
+    let state_path = index_path.join(ObjectStorage::SYNC_STATE_FILE_NAME);
+    let state_json =
+        serde_json::to_string(state).map_err(|e| format!("Failed to serialize sync state: {e}"))?;
+
+    fs::write(&state_path, state_json)
+        .map_err(|e| format!("Failed to write sync state {state_path:?}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_state_is_not_synced() {
+        assert!(is_synced("meta.json"));
+        assert!(is_synced("index/meta.json"));
+        assert!(is_synced("index/.managed.json"));
+        assert!(is_synced("index/0123abcd.idx"));
+        assert!(!is_synced("remote.json"));
+        assert!(!is_synced("forks"));
+        assert!(!is_synced("forks/0123abcd/meta.json"));
+        assert!(!is_synced("index/.tantivy-writer.lock"));
+        assert!(!is_synced("file_index_snapshot.bin.tmp"));
+    }
+
+    #[test]
+    fn test_pulled_index_is_pushed_on_opt_in_only() {
+        let cache = tempfile::tempdir().unwrap();
+        let config = S3Config {
+            endpoint: "http://127.0.0.1:9".to_string(),
+            bucket: "indexes".to_string(),
+            region: "us-east-1".to_string(),
+            credentials: None,
+        };
+        let storage = ObjectStorage::new(cache.path().to_path_buf(), config, "");
+        let created = SyncState::default();
+        let pulled = SyncState {
+            pulled: true,
+            ..SyncState::default()
+        };
+
+        assert!(storage.check_push("code", &created).is_ok());
+        let error = storage.check_push("code", &pulled).unwrap_err();
+        assert!(error.contains("pulled from the bucket"), "{error}");
+        let storage = storage.with_push_pulled(true);
+        assert!(storage.check_push("code", &pulled).is_ok());
+    }
+}
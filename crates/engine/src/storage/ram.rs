@@ -0,0 +1,349 @@
+use super::{
+    natural_cmp, IndexFork, IndexStats, IndexStorage, IndexStorageMetadata, UpdateJournal,
+    UpdateRecord, UPDATE_RECORDS_KEPT,
+};
+use crate::change::FileIndexMetadata;
+use crate::doctor::{IndexIssue, IndexProblem};
+use crate::migration::FORMAT_VERSION;
+use crate::options::IndexingOptions;
+use crate::paths::normalize_path;
+use crate::schema::{index_settings, CodeIndexSchema};
+use crate::tokenizers::register_tokenizers;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tantivy::directory::RamDirectory;
+use tantivy::Index;
+
+/// Keeps indexes in memory, for throwaway indexes that must not touch `BEETLE_HOME`: tests,
+/// one-shot searches and scratch indexes. Everything is lost when the storage is dropped.
+///
+/// The indexed files are still read from their target path on disk.
+#[derive(Default)]
+pub struct RamStorage {
+    indexes: Mutex<HashMap<String, RamIndex>>,
+}
+
+struct RamIndex {
+    metadata: IndexStorageMetadata,
+    /// Shared with every [`Index`] opened on it, so commits are visible to all of them.
+    directory: RamDirectory,
+    file_index: Vec<FileIndexMetadata>,
+    history: Vec<UpdateRecord>,
+    journal: Option<UpdateJournal>,
+    /// Last commit or creation, in seconds since the Unix epoch.
+    updated_at: i64,
+}
+
+impl RamStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` on `index_name`, failing when there is no such index.
+    fn with_index<T>(
+        &self,
+        index_name: &str,
+        f: impl FnOnce(&mut RamIndex) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut indexes = self.indexes.lock().unwrap();
+        let index = indexes
+            .get_mut(index_name)
+            .ok_or_else(|| format!("Index {index_name} does not exist"))?;
+
+        f(index)
+    }
+
+    fn open_directory(index_name: &str, directory: RamDirectory) -> Result<Index, String> {
+        let index = Index::open(directory)
+            .map_err(|e| format!("Failed to open index {index_name}: {e}"))?;
+        register_tokenizers(&index);
+
+        Ok(index)
+    }
+}
+
+impl IndexStorage for RamStorage {
+    /// Indexes have no directory; this is a placeholder for display.
+    fn index_dir(&self) -> String {
+        ":memory:".to_string()
+    }
+
+    fn create(
+        &self,
+        index_name: &str,
+        target_path: &str,
+        options: &IndexingOptions,
+        tags: &[String],
+    ) -> Result<Index, String> {
+        if options.encrypted {
+            return Err(
+                "Encryption at rest is only supported for indexes stored on disk".to_string(),
+            );
+        }
+        let mut indexes = self.indexes.lock().unwrap();
+        if indexes.contains_key(index_name) {
+            return Err(format!("Index {index_name} already exists"));
+        }
+
+        let absolute_target_path = normalize_path(Path::new(target_path));
+        if !Path::new(&absolute_target_path).exists() {
+            return Err(format!(
+                "Target path '{absolute_target_path}' does not exist"
+            ));
+        }
+
+        let directory = RamDirectory::create();
+        let index = Index::create(
+            directory.clone(),
+            CodeIndexSchema::with_options(options).schema,
+            index_settings(options.content_storage),
+        )
+        .map_err(|e| format!("Failed to create index {index_name}: {e}"))?;
+        register_tokenizers(&index);
+
+        indexes.insert(
+            index_name.to_string(),
+            RamIndex {
+                metadata: IndexStorageMetadata {
+                    index_name: index_name.to_string(),
+                    index_path: String::new(),
+                    target_path: absolute_target_path,
+                    options: options.clone(),
+                    tags: tags.to_vec(),
+                    format_version: FORMAT_VERSION,
+                    encryption_key_id: None,
+                },
+                directory,
+                file_index: Vec::new(),
+                history: Vec::new(),
+                journal: None,
+                updated_at: now(),
+            },
+        );
+
+        Ok(index)
+    }
+
+    fn open(&self, index_name: &str) -> Result<Index, String> {
+        let directory = self.with_index(index_name, |index| Ok(index.directory.clone()))?;
+
+        Self::open_directory(index_name, directory)
+    }
+
+    fn remove(&self, index_name: &str) -> Result<(), String> {
+        self.indexes
+            .lock()
+            .unwrap()
+            .remove(index_name)
+            .map(|_| ())
+            .ok_or_else(|| format!("Index {index_name} does not exist"))
+    }
+
+    fn list(&self) -> Result<Vec<IndexStorageMetadata>, String> {
+        let mut indexes: Vec<IndexStorageMetadata> = self
+            .indexes
+            .lock()
+            .unwrap()
+            .values()
+            .map(|index| index.metadata.clone())
+            .collect();
+        indexes.sort_by(|a, b| natural_cmp(&a.index_name, &b.index_name));
+
+        Ok(indexes)
+    }
+
+    fn get_metadata(&self, index_name: &str) -> Result<IndexStorageMetadata, String> {
+        self.with_index(index_name, |index| Ok(index.metadata.clone()))
+            .map_err(|_| format!("Index {index_name} not found"))
+    }
+
+    fn rebuild(
+        &self,
+        index_name: &str,
+        build: &mut dyn FnMut(&dyn IndexStorage) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let (metadata, history) = self.with_index(index_name, |index| {
+            Ok((index.metadata.clone(), index.history.clone()))
+        })?;
+        let rebuild = RamStorage::new();
+        rebuild.create(
+            index_name,
+            &metadata.target_path,
+            &metadata.options,
+            &metadata.tags,
+        )?;
+        rebuild.with_index(index_name, |index| {
+            index.history = history;
+            Ok(())
+        })?;
+        build(&rebuild)?;
+
+        let mut rebuilt = rebuild
+            .indexes
+            .into_inner()
+            .unwrap()
+            .remove(index_name)
+            .ok_or_else(|| format!("Index {index_name} does not exist"))?;
+        rebuilt.metadata = metadata;
+        self.indexes
+            .lock()
+            .unwrap()
+            .insert(index_name.to_string(), rebuilt);
+
+        Ok(())
+    }
+
+    fn read_file_index_metadata(&self, index_name: &str) -> Result<Vec<FileIndexMetadata>, String> {
+        self.with_index(index_name, |index| Ok(index.file_index.clone()))
+    }
+
+    fn save_file_index_metadata(
+        &self,
+        index_name: &str,
+        metadata: Vec<FileIndexMetadata>,
+    ) -> Result<(), String> {
+        self.with_index(index_name, |index| {
+            index.file_index = metadata;
+            Ok(())
+        })
+    }
+
+    fn append_update_record(&self, index_name: &str, record: &UpdateRecord) -> Result<(), String> {
+        self.with_index(index_name, |index| {
+            index.history.push(record.clone());
+            let excess = index.history.len().saturating_sub(UPDATE_RECORDS_KEPT);
+            index.history.drain(..excess);
+            Ok(())
+        })
+    }
+
+    fn read_update_history(&self, index_name: &str) -> Result<Vec<UpdateRecord>, String> {
+        self.with_index(index_name, |index| Ok(index.history.clone()))
+    }
+
+    fn read_update_journal(&self, index_name: &str) -> Result<Option<UpdateJournal>, String> {
+        self.with_index(index_name, |index| Ok(index.journal.clone()))
+    }
+
+    fn save_update_journal(
+        &self,
+        index_name: &str,
+        journal: Option<&UpdateJournal>,
+    ) -> Result<(), String> {
+        self.with_index(index_name, |index| {
+            index.journal = journal.cloned();
+            Ok(())
+        })
+    }
+
+    /// Nothing is persisted, so the only possible problem is a target path that went away.
+    fn diagnose(&self, _fix: bool) -> Result<Vec<IndexIssue>, String> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|metadata| !Path::new(&metadata.target_path).exists())
+            .map(|metadata| {
+                IndexIssue::new(
+                    &metadata.index_name,
+                    IndexProblem::MissingTargetPath(metadata.target_path),
+                )
+            })
+            .collect())
+    }
+
+    fn stats(&self, index_name: &str) -> Result<IndexStats, String> {
+        let (directory, last_update, updated_at) = self.with_index(index_name, |index| {
+            Ok((
+                index.directory.clone(),
+                index.history.last().cloned(),
+                index.updated_at,
+            ))
+        })?;
+        let reader = Self::open_directory(index_name, directory.clone())?
+            .reader()
+            .map_err(|e| format!("Failed to create index reader for index {index_name}: {e}"))?;
+
+        Ok(IndexStats {
+            docs: reader.searcher().num_docs(),
+            size_bytes: directory.total_mem_usage() as u64,
+            updated_at,
+            last_update,
+        })
+    }
+
+    fn fork(&self, index_name: &str) -> Result<IndexFork, String> {
+        let directory = self.with_index(index_name, |index| Ok(index.directory.deep_clone()))?;
+
+        Ok(IndexFork {
+            index: Self::open_directory(index_name, directory)?,
+            path: None,
+        })
+    }
+
+    fn publish(&self, index_name: &str) -> Result<(), String> {
+        self.with_index(index_name, |index| {
+            index.updated_at = now();
+            Ok(())
+        })
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indexes_live_in_memory() {
+        let target = std::env::temp_dir();
+        let storage = RamStorage::new();
+        storage
+            .create(
+                "idx10",
+                &target.to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        storage
+            .create(
+                "idx2",
+                &target.to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        assert!(storage
+            .create(
+                "idx2",
+                &target.to_string_lossy(),
+                &IndexingOptions::default(),
+                &[]
+            )
+            .is_err());
+
+        let names: Vec<String> = storage
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|metadata| metadata.index_name)
+            .collect();
+        assert_eq!(names, vec!["idx2", "idx10"]);
+
+        let fork = storage.fork("idx2").unwrap();
+        assert_eq!(fork.index.reader().unwrap().searcher().num_docs(), 0);
+        assert_eq!(storage.stats("idx2").unwrap().docs, 0);
+
+        storage.remove("idx2").unwrap();
+        assert!(storage.open("idx2").is_err());
+        assert!(storage.remove("idx2").is_err());
+    }
+}
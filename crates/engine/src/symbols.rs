@@ -0,0 +1,288 @@
+#[cfg(feature = "tree-sitter")]
+mod grammars;
+
+/// A definition found in a source file.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// 1-based line number of the definition.
+    pub line: usize,
+    /// Byte offset of the start of the definition line.
+    pub offset: usize,
+    /// The full definition line, without its line terminator.
+    pub line_text: String,
+}
+
+/// The symbols defined in one indexed document.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FileSymbols {
+    pub path: String,
+    pub symbols: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolKind {
+    Function,
+    Class,
+    Struct,
+    Enum,
+    Interface,
+    Type,
+    Module,
+    Constant,
+    Macro,
+}
+
+impl SymbolKind {
+    /// Single-letter kind used by ctags.
+    pub fn ctags_kind(&self) -> char {
+        match self {
+            SymbolKind::Function => 'f',
+            SymbolKind::Class => 'c',
+            SymbolKind::Struct => 's',
+            SymbolKind::Enum => 'g',
+            SymbolKind::Interface => 'i',
+            SymbolKind::Type => 't',
+            SymbolKind::Module => 'n',
+            SymbolKind::Constant => 'v',
+            SymbolKind::Macro => 'd',
+        }
+    }
+}
+
+impl std::fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SymbolKind::Function => "function",
+            SymbolKind::Class => "class",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Interface => "interface",
+            SymbolKind::Type => "type",
+            SymbolKind::Module => "module",
+            SymbolKind::Constant => "constant",
+            SymbolKind::Macro => "macro",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Modifiers that may precede a definition keyword and are skipped before matching it.
+const MODIFIERS: &[&str] = &[
+    "pub",
+    "pub(crate)",
+    "pub(super)",
+    "export",
+    "default",
+    "async",
+    "unsafe",
+    "extern",
+    "static",
+    "abstract",
+    "final",
+    "public",
+    "private",
+    "protected",
+    "declare",
+];
+
+/// Extracts the definitions of `content`, a file with `extension`.
+///
+/// With the `tree-sitter` feature, files in languages with a grammar are parsed; other files
+/// go through [`keyword_symbols`].
+pub fn extract_symbols(extension: &str, content: &str) -> Vec<Symbol> {
+    #[cfg(feature = "tree-sitter")]
+    if let Some(symbols) = grammars::extract_symbols(extension, content) {
+        return symbols;
+    }
+
+    keyword_symbols(extension, content)
+}
+
+/// Extracts definitions from `content` using per-language definition keywords.
+///
+/// This is a line-based heuristic rather than a parser: it finds top-level and nested
+/// definitions introduced by a keyword, plus C-style function definitions at column 0.
+pub fn keyword_symbols(extension: &str, content: &str) -> Vec<Symbol> {
+    let keywords: &[(&str, SymbolKind)] = match extension {
+        "rs" => &[
+            ("fn", SymbolKind::Function),
+            ("struct", SymbolKind::Struct),
+            ("enum", SymbolKind::Enum),
+            ("trait", SymbolKind::Interface),
+            ("type", SymbolKind::Type),
+            ("mod", SymbolKind::Module),
+            ("const", SymbolKind::Constant),
+            ("macro_rules!", SymbolKind::Macro),
+        ],
+        "py" => &[("def", SymbolKind::Function), ("class", SymbolKind::Class)],
+        "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" => &[
+            ("function", SymbolKind::Function),
+            ("class", SymbolKind::Class),
+            ("interface", SymbolKind::Interface),
+            ("type", SymbolKind::Type),
+            ("enum", SymbolKind::Enum),
+        ],
+        "go" => &[("func", SymbolKind::Function), ("type", SymbolKind::Type)],
+        "java" | "kt" | "cs" | "scala" => &[
+            ("class", SymbolKind::Class),
+            ("interface", SymbolKind::Interface),
+            ("enum", SymbolKind::Enum),
+            ("fun", SymbolKind::Function),
+        ],
+        "c" | "h" | "cc" | "cpp" | "hpp" | "cxx" => &[
+            ("struct", SymbolKind::Struct),
+            ("enum", SymbolKind::Enum),
+            ("class", SymbolKind::Class),
+            ("namespace", SymbolKind::Module),
+        ],
+        _ => return Vec::new(),
+    };
+    let c_like = matches!(extension, "c" | "h" | "cc" | "cpp" | "hpp" | "cxx");
+
+    let mut symbols = Vec::new();
+    let mut offset = 0;
+    for (line_idx, raw_line) in content.split_inclusive('\n').enumerate() {
+        let line_text = raw_line.trim_end_matches(['\n', '\r']);
+        let symbol = match_keyword(line_text, keywords).or_else(|| {
+            if c_like {
+                match_c_definition(line_text)
+            } else {
+                None
+            }
+        });
+
+        if let Some((name, kind)) = symbol {
+            symbols.push(Symbol {
+                name,
+                kind,
+                line: line_idx + 1,
+                offset,
+                line_text: line_text.to_string(),
+            });
+        }
+        offset += raw_line.len();
+    }
+
+    symbols
+}
+
+fn match_keyword(line: &str, keywords: &[(&str, SymbolKind)]) -> Option<(String, SymbolKind)> {
+    let mut words = line.split_whitespace().peekable();
+    while words.next_if(|word| MODIFIERS.contains(word)).is_some() {}
+
+    let keyword = words.next()?;
+    let kind = keywords
+        .iter()
+        .find(|(candidate, _)| *candidate == keyword)
+        .map(|(_, kind)| *kind)?;
+
+    let mut rest = words.collect::<Vec<_>>().join(" ");
+    // Go methods: `func (r *Receiver) Name(...)`
+    if keyword == "func" && rest.starts_with('(') {
+        rest = rest.split_once(')')?.1.trim_start().to_string();
+    }
+
+    let name = identifier_prefix(&rest)?;
+    Some((name, kind))
+}
+
+/// Matches `#define`s and `type name(args) {`-style C function definitions at column 0.
+fn match_c_definition(line: &str) -> Option<(String, SymbolKind)> {
+    if let Some(rest) = line.strip_prefix("#define") {
+        return identifier_prefix(rest.trim_start()).map(|name| (name, SymbolKind::Macro));
+    }
+
+    if line.starts_with(char::is_whitespace)
+        || line.starts_with(['#', '/', '*', '}', '{'])
+        || line.trim_end().ends_with(';')
+    {
+        return None;
+    }
+
+    let (head, _) = line.split_once('(')?;
+    let name = head
+        .rsplit(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .next()?;
+    let is_keyword = matches!(
+        name,
+        "if" | "for" | "while" | "switch" | "return" | "sizeof"
+    );
+    if name.is_empty() || is_keyword || head.trim() == name {
+        return None;
+    }
+
+    Some((name.to_string(), SymbolKind::Function))
+}
+
+fn identifier_prefix(text: &str) -> Option<String> {
+    let name: String = text
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '$')
+        .collect();
+
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(extension: &str, content: &str) -> Vec<(String, SymbolKind, usize)> {
+        keyword_symbols(extension, content)
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.kind, symbol.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_extract_rust_symbols() {
+        let content = "pub struct Catalog {\n}\n\nimpl Catalog {\n    pub(crate) async fn open(&self) {}\n}\nmacro_rules! ensure {\n";
+
+        assert_eq!(
+            names("rs", content),
+            vec![
+                ("Catalog".to_string(), SymbolKind::Struct, 1),
+                ("open".to_string(), SymbolKind::Function, 5),
+                ("ensure".to_string(), SymbolKind::Macro, 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_go_method() {
+        assert_eq!(
+            names("go", "func (s *Server) Start() error {\n"),
+            vec![("Start".to_string(), SymbolKind::Function, 1)]
+        );
+    }
+
+    #[test]
+    fn test_extract_c_symbols() {
+        let content = "#define MAX 10\nint add(int a, int b);\nstatic int add(int a, int b)\n{\n    if (a) {\n        return add(a, b);\n    }\n}\nstruct point {\n";
+
+        assert_eq!(
+            names("c", content),
+            vec![
+                ("MAX".to_string(), SymbolKind::Macro, 1),
+                ("add".to_string(), SymbolKind::Function, 3),
+                ("point".to_string(), SymbolKind::Struct, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_symbol_offsets() {
+        let symbols = keyword_symbols("py", "import os\r\ndef main():\n");
+
+        assert_eq!(symbols[0].offset, 11);
+        assert_eq!(symbols[0].line_text, "def main():");
+    }
+}
@@ -0,0 +1,247 @@
+//! Symbol extraction with the tags queries shipped with tree-sitter grammars.
+
+use super::{Symbol, SymbolKind};
+use std::sync::OnceLock;
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{Language, Node, Parser, Query, QueryCursor};
+
+/// C preprocessor macros are not part of the tags queries of the C grammars.
+const C_MACROS_QUERY: &str = "
+(preproc_def name: (identifier) @name) @definition.macro
+(preproc_function_def name: (identifier) @name) @definition.macro
+";
+
+struct Grammar {
+    language: Language,
+    /// `None` when the tags query does not compile against the grammar.
+    query: Option<Query>,
+}
+
+impl Grammar {
+    fn new(language: impl Into<Language>, queries: &[&str]) -> Self {
+        let language = language.into();
+        let query = Query::new(&language, &queries.concat()).ok();
+        Self { language, query }
+    }
+}
+
+fn grammar(extension: &str) -> Option<&'static Grammar> {
+    macro_rules! grammar {
+        ($language:expr, $($query:expr),+) => {{
+            static GRAMMAR: OnceLock<Grammar> = OnceLock::new();
+            GRAMMAR.get_or_init(|| Grammar::new($language, &[$($query),+]))
+        }};
+    }
+
+    let grammar = match extension {
+        "rs" => grammar!(tree_sitter_rust::LANGUAGE, tree_sitter_rust::TAGS_QUERY),
+        "py" | "pyi" => grammar!(tree_sitter_python::LANGUAGE, tree_sitter_python::TAGS_QUERY),
+        "js" | "jsx" | "mjs" | "cjs" => grammar!(
+            tree_sitter_javascript::LANGUAGE,
+            tree_sitter_javascript::TAGS_QUERY
+        ),
+        // The TypeScript tags query only covers what TypeScript adds to JavaScript.
+        "ts" | "mts" | "cts" => grammar!(
+            tree_sitter_typescript::LANGUAGE_TYPESCRIPT,
+            tree_sitter_javascript::TAGS_QUERY,
+            tree_sitter_typescript::TAGS_QUERY
+        ),
+        "tsx" => grammar!(
+            tree_sitter_typescript::LANGUAGE_TSX,
+            tree_sitter_javascript::TAGS_QUERY,
+            tree_sitter_typescript::TAGS_QUERY
+        ),
+        "go" => grammar!(tree_sitter_go::LANGUAGE, tree_sitter_go::TAGS_QUERY),
+        "java" => grammar!(tree_sitter_java::LANGUAGE, tree_sitter_java::TAGS_QUERY),
+        "c" | "h" => grammar!(
+            tree_sitter_c::LANGUAGE,
+            tree_sitter_c::TAGS_QUERY,
+            C_MACROS_QUERY
+        ),
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" | "hxx" => grammar!(
+            tree_sitter_cpp::LANGUAGE,
+            tree_sitter_cpp::TAGS_QUERY,
+            C_MACROS_QUERY
+        ),
+        _ => return None,
+    };
+    Some(grammar)
+}
+
+/// Extracts the definitions of `content` by parsing it, or `None` when there is no grammar for
+/// `extension` and the keyword heuristic should be used instead.
+pub(super) fn extract_symbols(extension: &str, content: &str) -> Option<Vec<Symbol>> {
+    let grammar = grammar(extension)?;
+    let query = grammar.query.as_ref()?;
+    let mut parser = Parser::new();
+    parser.set_language(&grammar.language).ok()?;
+    let tree = parser.parse(content, None)?;
+
+    let name_index = query.capture_index_for_name("name")?;
+    let mut definitions = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(query, tree.root_node(), content.as_bytes());
+    while let Some(query_match) = matches.next() {
+        let Some(name) = query_match
+            .captures
+            .iter()
+            .find(|capture| capture.index == name_index)
+        else {
+            continue;
+        };
+        let Some((definition, tag)) = query_match.captures.iter().find_map(|capture| {
+            let tag = query.capture_names()[capture.index as usize].strip_prefix("definition.")?;
+            Some((capture.node, tag))
+        }) else {
+            continue;
+        };
+        if let Some(kind) = symbol_kind(tag, definition) {
+            definitions.push((name.node, kind));
+        }
+    }
+
+    // Methods match both the method and the function patterns of some queries.
+    definitions.sort_by_key(|(name, _)| name.start_byte());
+    definitions.dedup_by_key(|(name, _)| name.start_byte());
+
+    let line_starts = std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(index, _)| index + 1))
+        .collect::<Vec<_>>();
+    let symbols = definitions
+        .into_iter()
+        .filter_map(|(name, kind)| {
+            let row = name.start_position().row;
+            let offset = *line_starts.get(row)?;
+            let line_text = content[offset..].lines().next().unwrap_or_default();
+            Some(Symbol {
+                name: name.utf8_text(content.as_bytes()).ok()?.to_string(),
+                kind,
+                line: row + 1,
+                offset,
+                line_text: line_text.to_string(),
+            })
+        })
+        .collect();
+    Some(symbols)
+}
+
+/// The kind of the `definition` node tagged `definition.<tag>`. Tags are coarse, e.g. Rust
+/// structs and enums are both classes, so the node kind refines them.
+fn symbol_kind(tag: &str, definition: Node) -> Option<SymbolKind> {
+    let node_kind = match definition.kind() {
+        // Go types are named by a spec whose type tells what they are
+        "type_spec" => definition
+            .child_by_field_name("type")
+            .map_or("type_spec", |node| node.kind()),
+        kind => kind,
+    };
+    // Prototypes and fields declare functions without defining them
+    if node_kind == "function_declarator" && !is_function_definition(definition) {
+        return None;
+    }
+
+    let kind = if node_kind.contains("struct") || node_kind.contains("union") {
+        SymbolKind::Struct
+    } else if node_kind.contains("enum") {
+        SymbolKind::Enum
+    } else if node_kind.contains("interface") {
+        SymbolKind::Interface
+    } else if node_kind.starts_with("type_") {
+        SymbolKind::Type
+    } else {
+        match tag {
+            "function" | "method" => SymbolKind::Function,
+            "class" => SymbolKind::Class,
+            "interface" => SymbolKind::Interface,
+            "type" => SymbolKind::Type,
+            "module" => SymbolKind::Module,
+            "constant" => SymbolKind::Constant,
+            "macro" => SymbolKind::Macro,
+            _ => return None,
+        }
+    };
+    Some(kind)
+}
+
+fn is_function_definition(declarator: Node) -> bool {
+    let mut node = declarator;
+    while let Some(parent) = node.parent() {
+        match parent.kind() {
+            "function_definition" => return true,
+            "pointer_declarator" | "reference_declarator" => node = parent,
+            _ => return false,
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(extension: &str, content: &str) -> Vec<(String, SymbolKind, usize)> {
+        extract_symbols(extension, content)
+            .unwrap()
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.kind, symbol.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_extract_rust_symbols() {
+        let content = "pub struct Catalog {\n}\n\nimpl Catalog {\n    pub(crate) async fn open(&self) {}\n}\n\nenum Mode { A }\n\nmacro_rules! ensure {\n    () => {};\n}\n";
+
+        assert_eq!(
+            names("rs", content),
+            vec![
+                ("Catalog".to_string(), SymbolKind::Struct, 1),
+                ("open".to_string(), SymbolKind::Function, 5),
+                ("Mode".to_string(), SymbolKind::Enum, 8),
+                ("ensure".to_string(), SymbolKind::Macro, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_go_types() {
+        let content = "type Server struct {}\n\ntype Handler interface {}\n\nfunc (s *Server) Start() error {\n\treturn nil\n}\n";
+
+        assert_eq!(
+            names("go", content),
+            vec![
+                ("Server".to_string(), SymbolKind::Struct, 1),
+                ("Handler".to_string(), SymbolKind::Interface, 3),
+                ("Start".to_string(), SymbolKind::Function, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_c_symbols() {
+        let content = "#define MAX 10\nint add(int a, int b);\nstatic int *add(int a, int b)\n{\n    return 0;\n}\nstruct point {\n    int x;\n};\n";
+
+        assert_eq!(
+            names("c", content),
+            vec![
+                ("MAX".to_string(), SymbolKind::Macro, 1),
+                ("add".to_string(), SymbolKind::Function, 3),
+                ("point".to_string(), SymbolKind::Struct, 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_typescript_symbols() {
+        let content = "export interface Options {}\n\nexport class Client {\n  connect(): void {}\n}\n\nconst handler = () => {};\n";
+
+        assert_eq!(
+            names("ts", content),
+            vec![
+                ("Options".to_string(), SymbolKind::Interface, 1),
+                ("Client".to_string(), SymbolKind::Class, 3),
+                ("connect".to_string(), SymbolKind::Function, 4),
+                ("handler".to_string(), SymbolKind::Function, 7),
+            ]
+        );
+    }
+}
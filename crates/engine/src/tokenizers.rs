@@ -1,3 +1,197 @@
 mod code;
+mod literal;
 
 pub use code::CodeTokenizer;
+pub use literal::LiteralTokenizer;
+
+use crate::options::{AnalyzerOptions, ContentTokenizer, IndexingOptions, SchemaProfile};
+use tantivy::schema::{FieldType, Schema};
+use tantivy::tokenizer::{
+    LowerCaser, NgramTokenizer, RemoveLongFilter, SimpleTokenizer, Stemmer, StopWordFilter,
+    TextAnalyzer, TokenizerManager,
+};
+use tantivy::Index;
+
+/// Tokenizer of the content of new indexes, see [`CodeTokenizer::with_identifiers`].
+pub const CODE_TOKENIZER: &str = "code_identifiers";
+/// Tokenizer of the content of indexes created before identifiers were split; they keep it
+/// until they are rebuilt, since their terms are neither lowercased nor whole identifiers.
+const LEGACY_CODE_TOKENIZER: &str = "code";
+/// Tokenizer of the verbatim copy of the content searched by exact searches, see
+/// [`LiteralTokenizer`].
+pub const LITERAL_TOKENIZER: &str = "literal";
+const NGRAM_TOKENIZER_PREFIX: &str = "ngram_";
+const ANALYZER_TOKENIZER_PREFIX: &str = "analyzer";
+/// Tantivy's built-in tokenizer of English prose: lowercased and stemmed words.
+pub const STEMMED_TOKENIZER: &str = "en_stem";
+/// Tantivy's built-in tokenizer of lowercased words.
+const WORDS_TOKENIZER: &str = "default";
+
+/// Name of the tantivy tokenizer splitting the content of an index created with `options`.
+/// N-gram sizes and analyzer options are part of the name, so that every index carries the
+/// tokenizer it was built with in its schema.
+pub fn tokenizer_name(options: &IndexingOptions) -> String {
+    match (options.tokenizer, options.profile) {
+        (ContentTokenizer::Ngram { min_gram, max_gram }, _) => {
+            format!("{NGRAM_TOKENIZER_PREFIX}{min_gram}_{max_gram}")
+        }
+        (ContentTokenizer::Code, SchemaProfile::Code) => CODE_TOKENIZER.to_string(),
+        (ContentTokenizer::Code, profile) if !options.analyzer.is_default() => {
+            let analyzer = serde_json::to_string(&options.analyzer.resolve(profile))
+                .expect("analyzer options serialize to JSON");
+            format!("{ANALYZER_TOKENIZER_PREFIX}{analyzer}")
+        }
+        (ContentTokenizer::Code, SchemaProfile::Docs | SchemaProfile::Commits) => {
+            STEMMED_TOKENIZER.to_string()
+        }
+        (ContentTokenizer::Code, SchemaProfile::Logs) => WORDS_TOKENIZER.to_string(),
+    }
+}
+
+/// Registers the tokenizers the content of `index` may be indexed with.
+pub fn register_tokenizers(index: &Index) {
+    let tokenizers = index.tokenizers();
+    tokenizers.register(CODE_TOKENIZER, CodeTokenizer::with_identifiers());
+    tokenizers.register(LEGACY_CODE_TOKENIZER, CodeTokenizer::default());
+    tokenizers.register(LITERAL_TOKENIZER, LiteralTokenizer::default());
+    register_schema_tokenizers(tokenizers, &index.schema());
+}
+
+/// Tokenizers applied to queries on `schema`, which differ from the ones of the indexed content
+/// for code, see [`CodeTokenizer::for_identifier_queries`].
+pub fn query_tokenizers(schema: &Schema) -> TokenizerManager {
+    let tokenizers = TokenizerManager::default();
+    tokenizers.register(CODE_TOKENIZER, CodeTokenizer::for_identifier_queries());
+    tokenizers.register(LEGACY_CODE_TOKENIZER, CodeTokenizer::default());
+    tokenizers.register(LITERAL_TOKENIZER, LiteralTokenizer::default());
+    register_schema_tokenizers(&tokenizers, schema);
+    tokenizers
+}
+
+/// Registers the tokenizers described by their name in `schema`, see [`tokenizer_name`]. They are
+/// the same for content and queries, so that a query is looked up as the phrase of its n-grams,
+/// or of its words filtered like the content.
+fn register_schema_tokenizers(tokenizers: &TokenizerManager, schema: &Schema) {
+    for (_, entry) in schema.fields() {
+        let FieldType::Str(options) = entry.field_type() else {
+            continue;
+        };
+        let Some(name) = options
+            .get_indexing_options()
+            .map(|indexing| indexing.tokenizer())
+        else {
+            continue;
+        };
+        if let Some(analyzer) = name
+            .strip_prefix(ANALYZER_TOKENIZER_PREFIX)
+            .and_then(|analyzer| serde_json::from_str(analyzer).ok())
+        {
+            tokenizers.register(name, words_analyzer(&analyzer));
+            continue;
+        }
+        let sizes = name
+            .strip_prefix(NGRAM_TOKENIZER_PREFIX)
+            .and_then(|sizes| sizes.split_once('_'))
+            .and_then(|(min_gram, max_gram)| {
+                Some((min_gram.parse().ok()?, max_gram.parse().ok()?))
+            });
+        let Some(Ok(tokenizer)) =
+            sizes.map(|(min_gram, max_gram)| NgramTokenizer::new(min_gram, max_gram, false))
+        else {
+            continue;
+        };
+
+        tokenizers.register(
+            name,
+            TextAnalyzer::builder(tokenizer).filter(LowerCaser).build(),
+        );
+    }
+}
+
+/// Longest word indexed, like tantivy's built-in word tokenizers.
+const MAX_WORD_LENGTH: usize = 40;
+
+/// Splits text into words filtered by `options`, whose unset values are left out.
+fn words_analyzer(options: &AnalyzerOptions) -> TextAnalyzer {
+    let lowercase = options.lowercase.unwrap_or(true);
+    let mut builder = TextAnalyzer::builder(SimpleTokenizer::default())
+        .filter(RemoveLongFilter::limit(MAX_WORD_LENGTH))
+        .dynamic();
+    if lowercase {
+        builder = builder.filter_dynamic(LowerCaser);
+    }
+    if !options.stop_words.is_empty() {
+        let stop_words = options.stop_words.iter().map(|word| {
+            let word = word.trim();
+            if lowercase {
+                word.to_lowercase()
+            } else {
+                word.to_string()
+            }
+        });
+        builder = builder.filter_dynamic(StopWordFilter::remove(stop_words));
+    }
+    if let Some(language) = options.stemming.and_then(|stemming| stemming.language()) {
+        builder = builder.filter_dynamic(Stemmer::new(language));
+    }
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Stemming;
+    use tantivy::tokenizer::TokenStream;
+
+    #[test]
+    fn test_words_analyzer() {
+        let words = |options: &AnalyzerOptions, text: &str| {
+            let mut analyzer = words_analyzer(options);
+            let mut token_stream = analyzer.token_stream(text);
+            let mut words = Vec::new();
+            while let Some(token) = token_stream.next() {
+                words.push(token.text.clone());
+            }
+            words
+        };
+
+        let options = AnalyzerOptions {
+            lowercase: Some(true),
+            stemming: Some(Stemming::French),
+            stop_words: vec!["Les".to_string()],
+        };
+        assert_eq!(
+            words(&options, "Les Chevaux mangent"),
+            ["cheval", "mangent"]
+        );
+
+        let options = AnalyzerOptions {
+            lowercase: Some(false),
+            stemming: Some(Stemming::None),
+            stop_words: vec!["the".to_string()],
+        };
+        assert_eq!(
+            words(&options, "The cat and the hats"),
+            ["The", "cat", "and", "hats"]
+        );
+
+        // The options are recovered from the name of the tokenizer in the schema
+        let indexing = IndexingOptions::default()
+            .with_profile(SchemaProfile::Docs)
+            .with_analyzer(AnalyzerOptions {
+                stop_words: vec!["the".to_string()],
+                ..AnalyzerOptions::default()
+            });
+        let name = tokenizer_name(&indexing);
+        assert_eq!(
+            name,
+            r#"analyzer{"lowercase":true,"stemming":"english","stop_words":["the"]}"#
+        );
+        let schema = crate::schema::CodeIndexSchema::with_options(&indexing).schema;
+        let mut analyzer = query_tokenizers(&schema).get(&name).unwrap();
+        let mut token_stream = analyzer.token_stream("The Indexes");
+        assert!(token_stream.advance());
+        assert_eq!(token_stream.token().text, "index");
+        assert!(!token_stream.advance());
+    }
+}
@@ -16,6 +16,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::VecDeque;
 use std::ops::Range;
 use std::str::CharIndices;
 
@@ -29,10 +30,26 @@ use tantivy::tokenizer::{Token, TokenStream, Tokenizer};
 ///
 /// Optionally, it can keep sequences of hexadecimal chars together, which can be useful when
 /// dealing with ids encoded in that way, such as UUIDs.
+///
+/// It can also lowercase tokens and emit whole identifiers next to their parts, see
+/// [`CodeTokenizer::with_identifiers`].
 #[derive(Clone, Default)]
 pub struct CodeTokenizer {
     token: Token,
     enable_hex: bool,
+    identifiers: Identifiers,
+}
+
+/// How [`CodeTokenizer`] emits identifiers made of several parts.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+enum Identifiers {
+    /// Only their parts, as they are written.
+    #[default]
+    Parts,
+    /// Only their parts, lowercased.
+    LowercaseParts,
+    /// The whole identifier, then its parts, all lowercased.
+    WholeAndParts,
 }
 
 impl CodeTokenizer {
@@ -42,6 +59,30 @@ impl CodeTokenizer {
         CodeTokenizer {
             token: Token::default(),
             enable_hex: true,
+            identifiers: Identifiers::Parts,
+        }
+    }
+
+    /// Lowercases tokens and emits every identifier made of several parts (`parseHttpRequest`,
+    /// `parse_http_request`) as a whole before its parts, at the position of its first part, so
+    /// that `http request` and `parsehttprequest` both find `parseHttpRequest`.
+    pub fn with_identifiers() -> Self {
+        CodeTokenizer {
+            token: Token::default(),
+            enable_hex: false,
+            identifiers: Identifiers::WholeAndParts,
+        }
+    }
+
+    /// Tokenizes queries on text indexed [`CodeTokenizer::with_identifiers`]: identifiers are
+    /// only split into lowercased parts, so `parseHttpRequest` is looked up as the phrase
+    /// `parse http request`, which also matches `parse_http_request`, and `"parseHttp"*` still
+    /// expands `http` to `httpRequest`.
+    pub fn for_identifier_queries() -> Self {
+        CodeTokenizer {
+            token: Token::default(),
+            enable_hex: false,
+            identifiers: Identifiers::LowercaseParts,
         }
     }
 }
@@ -57,6 +98,10 @@ impl Tokenizer for CodeTokenizer {
             text,
             token: &mut self.token,
             enable_hex: self.enable_hex,
+            identifiers: self.identifiers,
+            pending: VecDeque::new(),
+            lookahead: None,
+            next_position: 0,
         }
     }
 }
@@ -67,6 +112,12 @@ pub struct CodeTokenStream<'a> {
     token: &'a mut Token,
     state: CodeTokenStreamState,
     enable_hex: bool,
+    identifiers: Identifiers,
+    /// Tokens of the current identifier that are still to be emitted.
+    pending: VecDeque<Token>,
+    /// The first part of the next identifier, read while looking for the end of the current one.
+    lookahead: Option<TokenOffsets>,
+    next_position: usize,
 }
 
 enum AdvanceResult {
@@ -115,8 +166,67 @@ impl CodeTokenStream<'_> {
     }
 }
 
+impl CodeTokenStream<'_> {
+    fn next_part(&mut self) -> Option<TokenOffsets> {
+        self.token.text.clear();
+        if !self.advance_inner(self.enable_hex) {
+            return None;
+        }
+        Some(self.token.offset_from..self.token.offset_to)
+    }
+
+    /// Queues the tokens of the next identifier, whose parts are only separated by underscores.
+    fn read_identifier(&mut self) {
+        let Some(first) = self.lookahead.take().or_else(|| self.next_part()) else {
+            return;
+        };
+        let mut parts = vec![first];
+        while let Some(part) = self.next_part() {
+            let previous_end = parts.last().map_or(part.start, |previous| previous.end);
+            if self.text[previous_end..part.start]
+                .bytes()
+                .all(|b| b == b'_')
+            {
+                parts.push(part);
+            } else {
+                self.lookahead = Some(part);
+                break;
+            }
+        }
+
+        let token = |offsets: TokenOffsets, position: usize, position_length: usize| Token {
+            offset_from: offsets.start,
+            offset_to: offsets.end,
+            position,
+            text: self.text[offsets].to_lowercase(),
+            position_length,
+        };
+        if self.identifiers == Identifiers::WholeAndParts && parts.len() > 1 {
+            let whole = parts[0].start..parts[parts.len() - 1].end;
+            self.pending
+                .push_back(token(whole, self.next_position, parts.len()));
+        }
+        for (index, part) in parts.iter().enumerate() {
+            self.pending
+                .push_back(token(part.clone(), self.next_position + index, 1));
+        }
+        self.next_position += parts.len();
+    }
+}
+
 impl TokenStream for CodeTokenStream<'_> {
     fn advance(&mut self) -> bool {
+        if self.identifiers != Identifiers::Parts {
+            if self.pending.is_empty() {
+                self.read_identifier();
+            }
+            let Some(token) = self.pending.pop_front() else {
+                return false;
+            };
+            *self.token = token;
+            return true;
+        }
+
         self.token.text.clear();
         self.token.position = self.token.position.wrapping_add(1);
 
@@ -317,9 +427,9 @@ fn get_char_type(c: char) -> CharType {
             CharType::LowerCase
         }
     } else if c.is_numeric() {
-        return CharType::Numeric;
+        CharType::Numeric
     } else {
-        return CharType::Delimiter;
+        CharType::Delimiter
     }
 }
 
@@ -381,7 +491,7 @@ impl ProcessingHexState {
                     // end of sequence, check if size is multiple of 2, or try to generate code
                     // state. We use next_char_offset as it already takes into account the size of
                     // the last character
-                    if (next_char_offset - self.start_offset) % 2 == 0 {
+                    if (next_char_offset - self.start_offset).is_multiple_of(2) {
                         return HexResult::Emit(self.start_offset..next_char_offset);
                     }
                 }
@@ -415,7 +525,7 @@ impl ProcessingHexState {
 
     fn finalize(&self) -> HexResult {
         let next_char_offset = self.current_char_offset + self.current_char.len_utf8();
-        if (next_char_offset - self.start_offset) % 2 == 0 {
+        if (next_char_offset - self.start_offset).is_multiple_of(2) {
             return HexResult::Emit(self.start_offset..next_char_offset);
         }
         self.to_processing_chars_state()
@@ -714,6 +824,57 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_code_tokenizer_identifiers() {
+        let tokens = |mut tokenizer: CodeTokenizer, text: &str| {
+            let mut token_stream = tokenizer.token_stream(text);
+            let mut res = Vec::new();
+            while let Some(tok) = token_stream.next() {
+                res.push((
+                    tok.text.clone(),
+                    tok.position,
+                    tok.offset_from..tok.offset_to,
+                ));
+            }
+            res
+        };
+        let token = |text: &str, position, offsets| (text.to_owned(), position, offsets);
+
+        assert_eq!(
+            tokens(
+                CodeTokenizer::with_identifiers(),
+                "fn parseHttpRequest(raw_body)"
+            ),
+            vec![
+                token("fn", 0, 0..2),
+                token("parsehttprequest", 1, 3..19),
+                token("parse", 1, 3..8),
+                token("http", 2, 8..12),
+                token("request", 3, 12..19),
+                token("raw_body", 4, 20..28),
+                token("raw", 4, 20..23),
+                token("body", 5, 24..28),
+            ]
+        );
+        assert_eq!(
+            tokens(CodeTokenizer::with_identifiers(), "__init__ HTTPServer"),
+            vec![
+                token("init", 0, 2..6),
+                token("httpserver", 1, 9..19),
+                token("http", 1, 9..13),
+                token("server", 2, 13..19),
+            ]
+        );
+        assert_eq!(
+            tokens(CodeTokenizer::for_identifier_queries(), "parse_HttpRequest"),
+            vec![
+                token("parse", 0, 0..5),
+                token("http", 1, 6..10),
+                token("request", 2, 10..17),
+            ]
+        );
+    }
+
     #[test]
     fn test_code_tokenizer_hex_scenaris() {
         let test_vectors = vec![
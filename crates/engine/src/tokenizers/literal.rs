@@ -0,0 +1,111 @@
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use tantivy::tokenizer::{Token, TokenStream, Tokenizer};
+
+/// A tokenizer keeping text verbatim for exact searches: runs of letters, digits and underscores
+/// are tokens as they are written, every other character but whitespace is a token of its own.
+///
+/// For instance, it splits `Result<Vec<u8>, String>` as
+/// `[Result, <, Vec, <, u8, >, ",", String, >]`, so that the phrase of a literal's tokens only
+/// matches that literal, give or take whitespace.
+#[derive(Clone, Default)]
+pub struct LiteralTokenizer {
+    token: Token,
+}
+
+impl Tokenizer for LiteralTokenizer {
+    type TokenStream<'a> = LiteralTokenStream<'a>;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        self.token.reset();
+        LiteralTokenStream {
+            text,
+            chars: text.char_indices().peekable(),
+            token: &mut self.token,
+        }
+    }
+}
+
+pub struct LiteralTokenStream<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    token: &'a mut Token,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl TokenStream for LiteralTokenStream<'_> {
+    fn advance(&mut self) -> bool {
+        let Some((start, first)) = self.chars.find(|(_, c)| !c.is_whitespace()) else {
+            return false;
+        };
+        let mut end = start + first.len_utf8();
+        if is_word_char(first) {
+            while let Some((offset, c)) = self.chars.next_if(|(_, c)| is_word_char(*c)) {
+                end = offset + c.len_utf8();
+            }
+        }
+
+        self.token.position = self.token.position.wrapping_add(1);
+        self.token.offset_from = start;
+        self.token.offset_to = end;
+        self.token.text.clear();
+        self.token.text.push_str(&self.text[start..end]);
+        true
+    }
+
+    fn token(&self) -> &Token {
+        self.token
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        self.token
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::tokenizer::{TokenStream, Tokenizer};
+
+    use super::LiteralTokenizer;
+
+    fn tokens(text: &str) -> Vec<(String, usize)> {
+        let mut tokenizer = LiteralTokenizer::default();
+        let mut token_stream = tokenizer.token_stream(text);
+        let mut tokens = Vec::new();
+        while let Some(token) = token_stream.next() {
+            tokens.push((token.text.clone(), token.position));
+        }
+        tokens
+    }
+
+    #[test]
+    fn test_literal_tokenizer() {
+        let texts: Vec<_> = tokens("fn f() -> Result<Vec<u8>, String>")
+            .into_iter()
+            .map(|(text, _)| text)
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "fn", "f", "(", ")", "-", ">", "Result", "<", "Vec", "<", "u8", ">", ",", "String",
+                ">"
+            ]
+        );
+
+        // Case and non-ASCII words are kept, positions count every token
+        assert_eq!(
+            tokens("  Café_2 ==é"),
+            [
+                ("Café_2".to_string(), 0),
+                ("=".to_string(), 1),
+                ("=".to_string(), 2),
+                ("é".to_string(), 3)
+            ]
+        );
+        assert!(tokens(" \n\t").is_empty());
+    }
+}
@@ -0,0 +1,245 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(20);
+
+/// CPU and memory used by one operation.
+///
+/// Figures are for the whole process, so operations running concurrently in the same process
+/// (e.g. in `beetle serve`) are counted together.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ResourceUsage {
+    pub wall_time_ms: u64,
+    /// User plus system CPU time, in milliseconds.
+    pub cpu_time_ms: u64,
+    /// Highest resident set size seen while the operation ran, in bytes; 0 when the platform
+    /// does not expose it.
+    pub peak_rss_bytes: u64,
+}
+
+impl fmt::Display for ResourceUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.2}s, CPU {:.2}s",
+            self.wall_time_ms as f64 / 1000.0,
+            self.cpu_time_ms as f64 / 1000.0
+        )?;
+        if self.peak_rss_bytes > 0 {
+            write!(
+                f,
+                ", peak RSS {:.1} MB",
+                self.peak_rss_bytes as f64 / (1024.0 * 1024.0)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Tracks the peak RSS of the process on a background thread while an operation runs.
+pub struct ResourceSampler {
+    started_at: Instant,
+    cpu_time_at_start: Duration,
+    peak_rss_bytes: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+    sampler: Option<JoinHandle<()>>,
+}
+
+impl ResourceSampler {
+    pub fn start() -> Self {
+        let peak_rss_bytes = Arc::new(AtomicU64::new(current_rss_bytes().unwrap_or_default()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        // Without a live RSS reading there is nothing to sample
+        let sampler = current_rss_bytes().map(|_| {
+            let peak_rss_bytes = peak_rss_bytes.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    if let Some(rss) = current_rss_bytes() {
+                        peak_rss_bytes.fetch_max(rss, Ordering::Relaxed);
+                    }
+                    thread::park_timeout(SAMPLE_INTERVAL);
+                }
+            })
+        });
+
+        ResourceSampler {
+            started_at: Instant::now(),
+            cpu_time_at_start: process_cpu_time(),
+            peak_rss_bytes,
+            stop,
+            sampler,
+        }
+    }
+
+    pub fn finish(mut self) -> ResourceUsage {
+        let wall_time = self.started_at.elapsed();
+        let cpu_time = process_cpu_time().saturating_sub(self.cpu_time_at_start);
+
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(sampler) = self.sampler.take() {
+            sampler.thread().unpark();
+            let _ = sampler.join();
+        }
+        let peak_rss_bytes = match current_rss_bytes() {
+            Some(rss) => self.peak_rss_bytes.load(Ordering::Relaxed).max(rss),
+            None => lifetime_peak_rss_bytes(),
+        };
+
+        ResourceUsage {
+            wall_time_ms: wall_time.as_millis() as u64,
+            cpu_time_ms: cpu_time.as_millis() as u64,
+            peak_rss_bytes,
+        }
+    }
+}
+
+impl Drop for ResourceSampler {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(sampler) = &self.sampler {
+            sampler.thread().unpark();
+        }
+    }
+}
+
+/// Runs `operation` and returns its result together with the resources it used.
+pub fn measure<T>(operation: impl FnOnce() -> T) -> (T, ResourceUsage) {
+    let sampler = ResourceSampler::start();
+    let result = operation();
+
+    (result, sampler.finish())
+}
+
+/// Memory the process may use, in bytes: the physical memory, or the limit of the container it
+/// runs in when lower. `None` when the platform does not expose it.
+pub fn total_memory_bytes() -> Option<u64> {
+    let physical = physical_memory_bytes()?;
+
+    Some(cgroup_memory_limit().map_or(physical, |limit| limit.min(physical)))
+}
+
+#[cfg(unix)]
+fn physical_memory_bytes() -> Option<u64> {
+    let pages = unsafe { libc::sysconf(libc::_SC_PHYS_PAGES) };
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+
+    Some(u64::try_from(pages).ok()? * u64::try_from(page_size).ok()?)
+}
+
+#[cfg(not(unix))]
+fn physical_memory_bytes() -> Option<u64> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn cgroup_memory_limit() -> Option<u64> {
+    // cgroup v2; holds `max` when the container is not limited
+    std::fs::read_to_string("/sys/fs/cgroup/memory.max")
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cgroup_memory_limit() -> Option<u64> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn current_rss_bytes() -> Option<u64> {
+    // The second field of statm is the resident set size in pages
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let resident_pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+
+    u64::try_from(page_size)
+        .ok()
+        .map(|page_size| resident_pages * page_size)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn current_rss_bytes() -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn rusage() -> Option<libc::rusage> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    let result = unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) };
+
+    (result == 0).then(|| unsafe { usage.assume_init() })
+}
+
+#[cfg(unix)]
+fn process_cpu_time() -> Duration {
+    let Some(usage) = rusage() else {
+        return Duration::ZERO;
+    };
+    let to_duration = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+
+    to_duration(usage.ru_utime) + to_duration(usage.ru_stime)
+}
+
+#[cfg(not(unix))]
+fn process_cpu_time() -> Duration {
+    Duration::ZERO
+}
+
+/// Peak RSS over the lifetime of the process, used where the current RSS cannot be sampled.
+#[cfg(unix)]
+fn lifetime_peak_rss_bytes() -> u64 {
+    let max_rss = rusage()
+        .map(|usage| usage.ru_maxrss as u64)
+        .unwrap_or_default();
+    // macOS reports bytes, other Unixes kilobytes
+    if cfg!(target_os = "macos") {
+        max_rss
+    } else {
+        max_rss * 1024
+    }
+}
+
+#[cfg(not(unix))]
+fn lifetime_peak_rss_bytes() -> u64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure_reports_usage() {
+        let (sum, usage) = measure(|| {
+            let data = vec![1u64; 4 * 1024 * 1024];
+            data.iter().sum::<u64>()
+        });
+
+        assert_eq!(sum, 4 * 1024 * 1024);
+        if cfg!(target_os = "linux") {
+            assert!(usage.peak_rss_bytes > 0);
+            assert!(total_memory_bytes().unwrap() > usage.peak_rss_bytes);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let usage = ResourceUsage {
+            wall_time_ms: 1500,
+            cpu_time_ms: 1250,
+            peak_rss_bytes: 48 * 1024 * 1024,
+        };
+
+        assert_eq!(usage.to_string(), "1.50s, CPU 1.25s, peak RSS 48.0 MB");
+    }
+}
@@ -0,0 +1,293 @@
+//! Deep checks of a single index, run by `beetle verify`.
+//!
+//! Where `beetle doctor` checks that each index opens, verifying an index reads every file of its
+//! last commit against the checksum tantivy wrote in its footer, and compares the documents with
+//! the file index snapshot. Repairs drop the damaged segments and rebuild the snapshot from the
+//! documents left, so that the next update indexes again only the files that lost theirs.
+
+use crate::change::FileIndexMetadata;
+use crate::doctor::{IndexIssue, IndexProblem};
+use crate::options::SchemaProfile;
+use crate::paths::is_within;
+use crate::schema::CodeIndexSchema;
+use crate::storage::{segment_files, IndexStorage, IndexStorageMetadata};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tantivy::index::SegmentId;
+use tantivy::schema::Value;
+use tantivy::{Directory, Index, IndexMeta, SegmentMeta, SegmentReader, TantivyDocument};
+
+/// The commit file of the tantivy index, in the index directory.
+const TANTIVY_META_FILE_NAME: &str = "meta.json";
+
+/// Checks the index of `metadata`. Its segment files are only read when it opens, and its
+/// documents are only compared with the file snapshot when every segment is intact.
+pub(crate) fn verify(
+    storage: &dyn IndexStorage,
+    metadata: &IndexStorageMetadata,
+) -> Result<Vec<IndexIssue>, String> {
+    let index_name = &metadata.index_name;
+    let issue = |problem| IndexIssue::new(index_name, problem);
+    let mut issues = Vec::new();
+
+    if !Path::new(&metadata.target_path).exists() {
+        issues.push(issue(IndexProblem::MissingTargetPath(
+            metadata.target_path.clone(),
+        )));
+    }
+
+    let (index, metas) = match open(storage, index_name) {
+        Ok(opened) => opened,
+        Err(e) => {
+            issues.push(issue(IndexProblem::UnopenableIndex(e)));
+            return Ok(issues);
+        }
+    };
+
+    let damaged = damaged_segments(&index, &metas);
+    let intact = damaged.is_empty();
+    issues.extend(damaged.into_iter().map(|(_, problem)| issue(problem)));
+
+    let snapshot = match storage.read_file_index_metadata(index_name) {
+        Ok(snapshot) => Some(snapshot),
+        Err(e) => {
+            issues.push(issue(IndexProblem::CorruptedSnapshot(e)));
+            None
+        }
+    };
+    // Indexes of branches and of commits index commits rather than the files of a snapshot
+    let snapshotted =
+        metadata.options.branches.is_empty() && metadata.options.profile != SchemaProfile::Commits;
+    if let (Some(snapshot), true, true) = (snapshot, intact, snapshotted) {
+        let indexed = indexed_files(&index, &metas.segments, &metadata.target_path)?;
+        let listed: HashSet<&str> = snapshot.iter().map(|file| file.path.as_str()).collect();
+        let unindexed = snapshot
+            .iter()
+            .filter(|file| !indexed.contains_key(&file.path))
+            .count();
+        let unlisted = indexed
+            .keys()
+            .filter(|path| !listed.contains(String::as_str(path)))
+            .count();
+        if unindexed > 0 {
+            issues.push(issue(IndexProblem::UnindexedFiles(unindexed)));
+        }
+        if unlisted > 0 {
+            issues.push(issue(IndexProblem::UnlistedFiles(unlisted)));
+        }
+    }
+
+    Ok(issues)
+}
+
+/// Drops the damaged segments of the index of `metadata` from its last commit, and rebuilds its
+/// file snapshot from the documents left. Files that lost their documents are left out of the
+/// snapshot for the next update to index them again; the others keep their content hash.
+pub(crate) fn repair(
+    storage: &dyn IndexStorage,
+    metadata: &IndexStorageMetadata,
+) -> Result<(), String> {
+    let index_name = &metadata.index_name;
+    let (index, mut metas) = open(storage, index_name)?;
+
+    let damaged: HashSet<SegmentId> = damaged_segments(&index, &metas)
+        .into_iter()
+        .map(|(segment, _)| segment)
+        .collect();
+    if !damaged.is_empty() {
+        tracing::warn!(
+            segments = damaged.len(),
+            "dropping the damaged segments of index {index_name}"
+        );
+        metas
+            .segments
+            .retain(|segment| !damaged.contains(&segment.id()));
+        let json = serde_json::to_vec_pretty(&metas)
+            .map_err(|e| format!("Failed to serialize metas for index {index_name}: {e}"))?;
+        index
+            .directory()
+            .atomic_write(Path::new(TANTIVY_META_FILE_NAME), &json)
+            .map_err(|e| format!("Failed to write metas for index {index_name}: {e}"))?;
+    }
+
+    let mut files = indexed_files(&index, &metas.segments, &metadata.target_path)?;
+    for file in storage
+        .read_file_index_metadata(index_name)
+        .unwrap_or_default()
+    {
+        if let Some(indexed) = files.get_mut(&file.path) {
+            *indexed = file;
+        }
+    }
+    let mut snapshot: Vec<_> = files.into_values().collect();
+    snapshot.sort_by(|a, b| a.path.cmp(&b.path));
+
+    storage.save_file_index_metadata(index_name, snapshot)
+}
+
+fn open(storage: &dyn IndexStorage, index_name: &str) -> Result<(Index, IndexMeta), String> {
+    let index = storage.open(index_name)?;
+    let metas = index
+        .load_metas()
+        .map_err(|e| format!("Failed to read the last commit of index {index_name}: {e}"))?;
+
+    Ok((index, metas))
+}
+
+/// The segments of the commit `metas` with files that are missing or do not match their
+/// checksum, once per such file.
+fn damaged_segments(index: &Index, metas: &IndexMeta) -> Vec<(SegmentId, IndexProblem)> {
+    let directory = index.directory();
+    let mut damaged = Vec::new();
+    for segment in &metas.segments {
+        for path in segment_files(segment) {
+            let name = path.to_string_lossy().to_string();
+            let problem = match directory.exists(&path) {
+                Ok(false) => IndexProblem::MissingSegmentFile(name),
+                Ok(true) if directory.validate_checksum(&path).unwrap_or(false) => continue,
+                // Files too short to hold a footer cannot be checked at all
+                Ok(true) | Err(_) => IndexProblem::DamagedSegmentFile(name),
+            };
+            damaged.push((segment.id(), problem));
+        }
+    }
+
+    damaged
+}
+
+/// The files of `target_path` with documents in `segments`, by path, as the file snapshot would
+/// list them before their content is hashed. Ingested records, which have no file, are left out.
+fn indexed_files(
+    index: &Index,
+    segments: &[SegmentMeta],
+    target_path: &str,
+) -> Result<HashMap<String, FileIndexMetadata>, String> {
+    let schema = CodeIndexSchema::new();
+    let mut files = HashMap::new();
+    for segment in segments {
+        let reader = SegmentReader::open(&index.segment(segment.clone())).map_err(|e| {
+            format!(
+                "Failed to open segment {}: {e}",
+                segment.id().short_uuid_string()
+            )
+        })?;
+        let store = reader
+            .get_store_reader(64)
+            .map_err(|e| format!("Failed to open document store: {e}"))?;
+        for doc in store.iter::<TantivyDocument>(reader.alive_bitset()) {
+            let doc = doc.map_err(|e| format!("Failed to retrieve document: {e}"))?;
+            let Some(path) = doc.get_first(schema.path).and_then(|value| value.as_str()) else {
+                continue;
+            };
+            if !is_within(path, target_path) || files.contains_key(path) {
+                continue;
+            }
+
+            let size = doc.get_first(schema.size).and_then(|value| value.as_u64());
+            let modified = doc
+                .get_first(schema.last_modified)
+                .and_then(|value| value.as_datetime());
+            files.insert(
+                path.to_string(),
+                FileIndexMetadata {
+                    path: path.to_string(),
+                    size: size.unwrap_or_default(),
+                    modified_time: modified.map_or(0, |time| time.into_timestamp_secs() as u64),
+                    content_hash: None,
+                },
+            );
+        }
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::IndexingOptions;
+    use crate::search::SearchOptions;
+    use crate::{FsStorage, IndexCatalog};
+    use tantivy::index::SegmentComponent;
+
+    #[test]
+    fn test_verify_and_repair() {
+        let home = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let catalog = IndexCatalog::new(FsStorage::new(home.path().to_path_buf()));
+        let storage = FsStorage::new(home.path().to_path_buf());
+        catalog
+            .create(
+                "files",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        // Two updates commit two segments
+        for name in ["first", "second"] {
+            let content = format!("fn {name}() {{}}\n");
+            std::fs::write(target.path().join(format!("{name}.rs")), content).unwrap();
+            catalog.get_writer("files", false).unwrap().index().unwrap();
+        }
+        assert!(catalog.verify("files", false).unwrap().is_empty());
+
+        // Damaging the segment of one file only reindexes that file
+        let metas = storage.open("files").unwrap().load_metas().unwrap();
+        let store = metas.segments[0].relative_path(SegmentComponent::Store);
+        let store_path = home.path().join("files").join("index").join(&store);
+        let mut bytes = std::fs::read(&store_path).unwrap();
+        bytes[0] ^= 0xff;
+        std::fs::write(&store_path, bytes).unwrap();
+        let issues = catalog.verify("files", false).unwrap();
+        assert_eq!(
+            issues[0].problem,
+            IndexProblem::DamagedSegmentFile(store.to_string_lossy().to_string())
+        );
+        let issues = catalog.verify("files", true).unwrap();
+        assert!(issues.iter().all(|issue| issue.fixed));
+        assert!(catalog.verify("files", false).unwrap().is_empty());
+        let history = storage.read_update_history("files").unwrap();
+        assert_eq!(history.last().unwrap().files_added, 1);
+        for query in ["first", "second"] {
+            let results = catalog
+                .search("files", query, &SearchOptions::default())
+                .unwrap();
+            assert_eq!(results.len(), 1);
+        }
+
+        // A snapshot that does not list every indexed file is rebuilt from the documents
+        let mut snapshot = storage.read_file_index_metadata("files").unwrap();
+        snapshot.pop();
+        storage.save_file_index_metadata("files", snapshot).unwrap();
+        let issues = catalog.verify("files", false).unwrap();
+        assert_eq!(issues[0].problem, IndexProblem::UnlistedFiles(1));
+        catalog.verify("files", true).unwrap();
+        assert_eq!(storage.read_file_index_metadata("files").unwrap().len(), 2);
+        assert_eq!(
+            storage
+                .read_update_history("files")
+                .unwrap()
+                .last()
+                .unwrap()
+                .files_added,
+            0
+        );
+
+        // So is a snapshot that does not match its checksum
+        let snapshot_path = home
+            .path()
+            .join("files")
+            .join(FsStorage::FILE_INDEX_SNAPSHOT_FILE_NAME);
+        let mut bytes = std::fs::read(&snapshot_path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        std::fs::write(&snapshot_path, bytes).unwrap();
+        let issues = catalog.verify("files", true).unwrap();
+        assert!(matches!(
+            issues[0].problem,
+            IndexProblem::CorruptedSnapshot(_)
+        ));
+        assert!(catalog.verify("files", false).unwrap().is_empty());
+    }
+}
@@ -1,73 +1,448 @@
-use crate::change::{diff_file_index_metadata, scan};
+use crate::change::{
+    content_digest, content_hash, detect_changes, detect_renames, indexed_content_hash, scan_files,
+    scan_with_skipped, Delta, FileIndexMetadata,
+};
+use crate::chunks::MAX_CHUNK_SIZE;
+use crate::git::{last_commits, pull_repository};
+use crate::options::{
+    ChangeDetection, IndexingOptions, SchemaProfile, SubmodulePolicy, WriterResources,
+};
 use crate::schema::{CodeIndexDocument, CodeIndexSchema};
-use crate::storage::{IndexStorage, IndexStorageMetadata};
+use crate::storage::{
+    BatchRecord, IndexLock, IndexStorage, IndexStorageMetadata, RecoveredUpdate, SkipReason,
+    SkippedFile, UpdateJournal, UpdateRecord,
+};
+use crate::usage::ResourceSampler;
 use rayon::prelude::*;
-use std::time::Instant;
-use tantivy::{Index, TantivyDocument};
-use tracing::{info, span, Level};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::BufRead;
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tantivy::collector::{DocSetCollector, TopDocs};
+use tantivy::query::TermQuery;
+use tantivy::schema::{IndexRecordOption, Value};
+use tantivy::{Index, Searcher, TantivyDocument};
+use tracing::{info, span, warn, Level};
+
+mod branches;
+mod history;
+mod submodules;
+
+pub(crate) use branches::branches_stale;
+pub(crate) use history::history_stale;
+pub(crate) use submodules::is_submodule_index;
+
+/// Documents read ahead of the writer; bounds the memory held by files waiting to be indexed,
+/// along with the size of the chunks large files are split in.
+const DOCUMENT_CHANNEL_CAPACITY: usize = 256;
+
+/// A document streamed into an index without a backing file, see [`IndexWriter::ingest`].
+#[derive(serde::Deserialize)]
+pub struct IngestRecord {
+    pub path: String,
+    pub content: String,
+    /// Seconds since the Unix epoch; defaults to the ingestion time.
+    #[serde(default)]
+    pub last_modified: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum IndexingPhase {
+    Scanning,
+    Removing,
+    Indexing,
+    Committing,
+}
+
+/// Progress of [`IndexWriter::index_with_progress`], reported at every phase change and batch.
+#[derive(Debug, Clone, Copy)]
+pub struct IndexingProgress {
+    pub phase: IndexingPhase,
+    pub files_processed: usize,
+    pub files_total: usize,
+}
+
+/// What an update would do, worked out by [`plan_update`] without writing to the index.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct UpdatePlan {
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    pub removed: Vec<String>,
+    pub renamed: Vec<PlannedRename>,
+    /// Every file of the target path the scan leaves out, ignored ones included, in path order.
+    /// Files found binary or unreadable only when read are not known before indexing them.
+    pub skipped: Vec<SkippedFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct PlannedRename {
+    pub from: String,
+    pub to: String,
+}
 
 pub struct IndexWriter<'a> {
     storage: &'a dyn IndexStorage,
     index_metadata: IndexStorageMetadata,
+    schema: tantivy::schema::Schema,
     writer: tantivy::IndexWriter,
+    batch_size: usize,
+    commit_every: Option<usize>,
+    change_detection: ChangeDetection,
+    /// Pool reading files, shared with the other writers of the catalog; rayon's global pool
+    /// when unset.
+    reader_pool: Option<Arc<rayon::ThreadPool>>,
+    /// Keeps other processes from writing to the index while this writer exists.
+    _lock: IndexLock,
+    /// Counts this writer against the catalog's limit of concurrent index jobs.
+    _permit: Option<JobPermit>,
+}
+
+/// Bounds the number of index jobs (writers and rebuilds) running at once in a catalog.
+pub(crate) struct JobSlots {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+impl JobSlots {
+    pub(crate) fn new(max_jobs: usize) -> Self {
+        JobSlots {
+            available: Mutex::new(max_jobs),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Blocks until a job slot is free, and takes it until the permit is dropped.
+    pub(crate) fn acquire(self: &Arc<Self>) -> JobPermit {
+        let mut available = self.available.lock().unwrap();
+        if *available == 0 {
+            info!("waiting for another index job to finish");
+        }
+        while *available == 0 {
+            available = self.released.wait(available).unwrap();
+        }
+        *available -= 1;
+
+        JobPermit {
+            slots: self.clone(),
+        }
+    }
+}
+
+pub(crate) struct JobPermit {
+    slots: Arc<JobSlots>,
+}
+
+impl Drop for JobPermit {
+    fn drop(&mut self) {
+        *self.slots.available.lock().unwrap() += 1;
+        self.slots.released.notify_one();
+    }
+}
+
+/// Runs `operation` on `pool`, so that the parallel iterators it uses run there, or on rayon's
+/// global pool when unset.
+fn in_pool<R: Send>(pool: Option<&rayon::ThreadPool>, operation: impl FnOnce() -> R + Send) -> R {
+    match pool {
+        Some(pool) => pool.install(operation),
+        None => operation(),
+    }
 }
 
 impl<'a> IndexWriter<'a> {
+    /// `resources` override the writer resources recorded in the index's options.
     pub fn new(
         storage: &'a dyn IndexStorage,
         index_metadata: IndexStorageMetadata,
         index: Index,
+        lock: IndexLock,
+        resources: WriterResources,
     ) -> Result<Self, String> {
-        let writer = index.writer(4 * 1024 * 1024 * 1024).map_err(|e| {
-            format!(
-                "Failed to create index writer for index {}: {}",
-                index_metadata.index_name, e
-            )
-        })?;
+        let resources = resources.or(index_metadata.options.writer);
+        let (memory_budget, index_threads) = resources.resolve()?;
+        info!(
+            memory_budget_mb = memory_budget / (1024 * 1024),
+            index_threads, "opening index writer"
+        );
+        let writer = index
+            .writer_with_num_threads(index_threads, memory_budget)
+            .map_err(|e| {
+                format!(
+                    "Failed to create index writer for index {}: {}",
+                    index_metadata.index_name, e
+                )
+            })?;
+        writer.set_merge_policy(index_metadata.options.merge_policy.merge_policy());
 
         Ok(IndexWriter {
             storage,
             index_metadata,
+            schema: index.schema(),
             writer,
+            batch_size: resources
+                .batch_size
+                .unwrap_or(WriterResources::DEFAULT_BATCH_SIZE),
+            commit_every: resources.commit_every,
+            change_detection: ChangeDetection::default(),
+            reader_pool: None,
+            _lock: lock,
+            _permit: None,
+        })
+    }
+
+    /// Reads files on `pool` instead of rayon's global pool.
+    pub(crate) fn with_reader_pool(mut self, pool: Option<Arc<rayon::ThreadPool>>) -> Self {
+        self.reader_pool = pool;
+        self
+    }
+
+    /// Holds `permit` for as long as the writer exists.
+    pub(crate) fn with_job_permit(mut self, permit: Option<JobPermit>) -> Self {
+        self._permit = permit;
+        self
+    }
+
+    /// Sets how [`IndexWriter::index`] tells which files changed since the last update.
+    pub fn with_change_detection(mut self, change_detection: ChangeDetection) -> Self {
+        self.change_detection = change_detection;
+        self
+    }
+
+    /// Undoes what the interrupted update of `journal` changed after its last saved snapshot:
+    /// the documents of its pending paths are removed and the paths left out of `snapshot`, so
+    /// that the current update indexes them again.
+    fn roll_back(
+        &mut self,
+        journal: UpdateJournal,
+        snapshot: &mut Vec<FileIndexMetadata>,
+    ) -> Result<RecoveredUpdate, String> {
+        let index_name = &self.index_metadata.index_name;
+        let pending: HashSet<&str> = journal.pending.iter().map(String::as_str).collect();
+        warn!(
+            started_at = journal.started_at,
+            commits = journal.commits,
+            files = pending.len(),
+            "rolling back an interrupted update"
+        );
+
+        let path_field = CodeIndexSchema::new().path;
+        for path in &pending {
+            self.writer
+                .delete_term(tantivy::Term::from_field_text(path_field, path));
+        }
+        self.writer
+            .commit()
+            .map_err(|e| format!("Failed to commit index writer for index {index_name}: {e}"))?;
+        snapshot.retain(|file| !pending.contains(file.path.as_str()));
+        self.storage
+            .save_file_index_metadata(index_name, snapshot.clone())?;
+        self.storage.save_update_journal(index_name, None)?;
+
+        Ok(RecoveredUpdate {
+            started_at: journal.started_at,
+            commits: journal.commits,
+            files_rolled_back: pending.len(),
         })
     }
 
-    pub fn index(&mut self) -> Result<(), String> {
+    pub fn index(&mut self) -> Result<UpdateRecord, String> {
+        self.index_with_progress(&|_| {})
+    }
+
+    /// Brings the index up to date with its target path, its branches or its history, and
+    /// appends the run to the index's update history. The companion commit index of an index
+    /// created with [`IndexingOptions::commits`], and the submodule indexes of one with separate
+    /// submodules, are brought up to date next; their failures are listed in
+    /// [`UpdateRecord::companion_failures`] rather than failing the update.
+    pub fn index_with_progress(
+        &mut self,
+        on_progress: &dyn Fn(IndexingProgress),
+    ) -> Result<UpdateRecord, String> {
+        let options = &self.index_metadata.options;
+        if let Some(remote) = &options.remote {
+            pull_repository(Path::new(&self.index_metadata.target_path))
+                .map_err(|e| format!("Failed to pull {remote}: {e}"))?;
+        }
+        let mut record = if options.profile == SchemaProfile::Commits {
+            self.index_history(on_progress)
+        } else if !options.branches.is_empty() {
+            self.index_branches(on_progress)
+        } else {
+            self.index_files(on_progress)
+        }?;
+        if self.index_metadata.options.commits {
+            if let Err(e) = self.update_commits_index() {
+                record.companion_failures.push(e);
+            }
+        }
+        if self.index_metadata.options.submodules == SubmodulePolicy::Separate {
+            record
+                .companion_failures
+                .extend(self.update_submodule_indexes());
+        }
+        for failure in &record.companion_failures {
+            warn!("{failure}");
+        }
+
+        Ok(record)
+    }
+
+    /// [`IndexWriter::index_with_progress`] for an index of the files of its target path.
+    fn index_files(
+        &mut self,
+        on_progress: &dyn Fn(IndexingProgress),
+    ) -> Result<UpdateRecord, String> {
         let _span = span!(Level::INFO, "index_writer_index",
             index_name = %self.index_metadata.index_name,
             target_path = %self.index_metadata.target_path
         )
         .entered();
 
+        check_target_path(&self.index_metadata)?;
         let start_time = Instant::now();
+        let sampler = ResourceSampler::start();
+        on_progress(IndexingProgress {
+            phase: IndexingPhase::Scanning,
+            files_processed: 0,
+            files_total: 0,
+        });
 
-        let file_index_snapshot = self
+        let mut file_index_snapshot = self
             .storage
             .read_file_index_metadata(&self.index_metadata.index_name)?;
         info!(
             "loaded file index snapshot with {} files",
             file_index_snapshot.len()
         );
+        let recovered = match self
+            .storage
+            .read_update_journal(&self.index_metadata.index_name)?
+        {
+            Some(journal) => Some(self.roll_back(journal, &mut file_index_snapshot)?),
+            None => None,
+        };
 
-        let manifest = scan(&self.index_metadata.target_path);
-        info!("scanned current file index with {} files", manifest.len());
+        let (mut manifest, skipped) = scan_with_skipped(
+            &self.index_metadata.target_path,
+            &self.index_metadata.options,
+        );
+        info!(
+            skipped = skipped.total(),
+            "scanned current file index with {} files",
+            manifest.len()
+        );
 
-        let delta = diff_file_index_metadata(&file_index_snapshot, &manifest);
+        let code_index_schema = CodeIndexSchema::new();
+        let max_file_size = self.index_metadata.options.max_file_size;
+        let utf8_only = self.index_metadata.options.utf8_only;
+        let change_detection = self.change_detection;
+        let dedup = self.index_metadata.options.dedup;
+        let mut delta = in_pool(self.reader_pool.as_deref(), || {
+            detect_changes(
+                &file_index_snapshot,
+                &mut manifest,
+                change_detection,
+                |file| indexed_content_hash(&file.path, max_file_size, utf8_only),
+            )
+        });
+        let searcher = if !dedup && (delta.removed.is_empty() || delta.added.is_empty()) {
+            None
+        } else {
+            let reader = self.writer.index().reader().map_err(|e| {
+                format!(
+                    "Failed to create reader for index {}: {}",
+                    self.index_metadata.index_name, e
+                )
+            })?;
+            Some(reader.searcher())
+        };
+        if let Some(searcher) = &searcher {
+            detect_stored_renames(&mut delta, searcher);
+        }
+        let duplicates = match (dedup, &searcher) {
+            (true, Some(searcher)) => {
+                requeue_duplicates(&mut delta, &manifest, searcher);
+                in_pool(self.reader_pool.as_deref(), || {
+                    find_duplicates(&delta, &manifest, searcher, |file| {
+                        indexed_content_hash(&file.path, max_file_size, utf8_only)
+                    })
+                })
+            }
+            _ => HashMap::new(),
+        };
         info!(
             files_added = delta.added.len(),
             files_modified = delta.modified.len(),
             files_removed = delta.removed.len(),
+            files_renamed = delta.renamed.len(),
             "calculated file delta"
         );
 
-        let code_index_schema = CodeIndexSchema::new();
-        let removed = delta.removed;
+        let total_files = delta.added.len() + delta.modified.len();
+        // Read once for every file of the update; an index of files that are not, or no longer,
+        // in a repository is still updated, only without the commits
+        let last_commits = if self.index_metadata.options.git_blame
+            && (total_files > 0 || !delta.renamed.is_empty())
+        {
+            let paths = (delta.added.iter().chain(&delta.modified))
+                .map(|file| file.path.as_str())
+                .chain(delta.renamed.iter().map(|rename| rename.to.path.as_str()));
+            last_commits(&self.index_metadata.target_path, "HEAD", paths).unwrap_or_else(|e| {
+                warn!("Failed to read the last commits of the indexed files: {e}");
+                HashMap::new()
+            })
+        } else {
+            HashMap::new()
+        };
+        let mut record = UpdateRecord {
+            files_added: delta.added.len(),
+            files_modified: delta.modified.len(),
+            files_removed: delta.removed.len(),
+            files_renamed: delta.renamed.len(),
+            files_duplicated: duplicates.len(),
+            recovered,
+            skipped,
+            ..UpdateRecord::default()
+        };
+
+        // Journaled before anything is written, so that an interruption is noticed by the next
+        // update; the paths of the indexed files are added as they are written
+        let mut journal = UpdateJournal {
+            started_at: unix_time(SystemTime::now()),
+            commits: 0,
+            pending: delta
+                .removed
+                .iter()
+                .chain(&delta.modified)
+                .chain(
+                    delta
+                        .renamed
+                        .iter()
+                        .flat_map(|rename| [&rename.from, &rename.to]),
+                )
+                .map(|file| file.path.clone())
+                .collect(),
+        };
+        let journaled = total_files > 0 || !journal.pending.is_empty();
+        if journaled {
+            self.storage
+                .save_update_journal(&self.index_metadata.index_name, Some(&journal))?;
+        }
+        on_progress(IndexingProgress {
+            phase: IndexingPhase::Removing,
+            files_processed: 0,
+            files_total: total_files,
+        });
+
+        // Modified files are indexed again below, in place of all their documents
         let removal_start = Instant::now();
-        for file in removed {
-            let file_path = file.path.clone();
+        for file in delta.removed.iter().chain(&delta.modified) {
             self.writer.delete_term(tantivy::Term::from_field_text(
                 code_index_schema.path,
-                &file_path,
+                &file.path,
             ));
         }
         let removal_duration = removal_start.elapsed();
@@ -76,60 +451,251 @@ impl<'a> IndexWriter<'a> {
             "completed file removals"
         );
 
-        let files_to_update: Vec<_> = delta.added.into_iter().chain(delta.modified).collect();
-        let total_files = files_to_update.len();
-
-        const BATCH_SIZE: usize = 100;
-        let batch_count = total_files.div_ceil(BATCH_SIZE);
-        let processing_start = Instant::now();
+        // Content hashes of the files indexed below, recorded in the file index snapshot for
+        // the change detection of the next update
+        let mut content_hashes = HashMap::new();
 
-        for (batch_idx, batch) in files_to_update.chunks(BATCH_SIZE).enumerate() {
-            let batch_span = span!(
-                Level::INFO,
-                "process_batch",
-                batch_index = batch_idx,
-                batch_size = batch.len(),
-                total_batches = batch_count
-            );
-            let _batch_guard = batch_span.enter();
-
-            let batch_start = Instant::now();
-
-            let documents: Result<Vec<_>, _> = batch
-                .par_iter()
-                .map(|file| -> Result<TantivyDocument, String> {
-                    let document = CodeIndexDocument::from_path(&file.path);
-                    Ok(document.to_tantivy_document(&code_index_schema.schema))
-                })
+        // Intermediate commits save the snapshot of the files whose documents they committed,
+        // so that an interrupted update resumes with the other files. Modified files are left
+        // out until they are indexed again, since their documents are deleted by the first one.
+        let mut committed_files = self.commit_every.map(|_| {
+            let mut files: HashMap<_, _> = file_index_snapshot
+                .into_iter()
+                .map(|file| (file.path.clone(), file))
                 .collect();
+            for file in delta.removed.iter().chain(&delta.modified) {
+                files.remove(&file.path);
+            }
+            for rename in &delta.renamed {
+                files.remove(&rename.from.path);
+                let to = FileIndexMetadata {
+                    content_hash: rename.from.content_hash,
+                    ..rename.to.clone()
+                };
+                files.insert(to.path.clone(), to);
+            }
+            files
+        });
 
-            let doc_creation_duration = batch_start.elapsed();
-
-            let add_start = Instant::now();
-            for doc in documents? {
-                self.writer.add_document(doc).map_err(|e| {
+        // A rename only changes the path, so the new document reuses the indexed content instead
+        // of going through the batches below.
+        let renamed_files = delta.renamed.len();
+        for rename in delta.renamed {
+            if let Some(hash) = rename.from.content_hash {
+                content_hashes.insert(rename.to.path.clone(), hash);
+            }
+            let content = searcher
+                .as_ref()
+                .and_then(|searcher| {
+                    stored_content(searcher, &code_index_schema, &rename.from.path)
+                })
+                .unwrap_or_default();
+            self.writer.delete_term(tantivy::Term::from_field_text(
+                code_index_schema.path,
+                &rename.from.path,
+            ));
+            let last_modified =
+                SystemTime::UNIX_EPOCH + Duration::from_secs(rename.to.modified_time);
+            let document = CodeIndexDocument {
+                last_commit: last_commits.get(&rename.to.path).cloned(),
+                ..CodeIndexDocument::from_content(rename.to.path, content, last_modified)
+            };
+            self.writer
+                .add_document(document.to_tantivy_document(&self.schema))
+                .map_err(|e| {
                     format!(
                         "Failed to add document to index {}: {}",
                         self.index_metadata.index_name, e
                     )
                 })?;
-            }
-            let add_duration = add_start.elapsed();
-            let total_batch_duration = batch_start.elapsed();
-
-            info!(
-                batch_size = batch.len(),
-                doc_creation_ms = doc_creation_duration.as_millis(),
-                doc_add_ms = add_duration.as_millis(),
-                total_batch_ms = total_batch_duration.as_millis(),
-                files_per_sec = (batch.len() as f64 / total_batch_duration.as_secs_f64()) as u64,
-                "completed batch processing"
-            );
+        }
+        if renamed_files > 0 {
+            info!(files_renamed = renamed_files, "completed file renames");
         }
 
+        let files_to_update: Vec<_> = delta.added.into_iter().chain(delta.modified).collect();
+
+        let processing_start = Instant::now();
+        let mut files_processed = 0;
+        on_progress(IndexingProgress {
+            phase: IndexingPhase::Indexing,
+            files_processed,
+            files_total: total_files,
+        });
+
+        // Files are read and turned into documents by the rayon pool while this thread feeds
+        // them to the writer; the bounded channel keeps readers from getting far ahead of it.
+        // The last chunk of each file comes with the file's position and content hash.
+        let schema = &self.schema;
+        let files = &files_to_update;
+        let duplicates = &duplicates;
+        let last_commits = &last_commits;
+        let reader_pool = self.reader_pool.as_deref();
+        let (sender, receiver) = std::sync::mpsc::sync_channel(DOCUMENT_CHANNEL_CAPACITY);
+        let mut add_result = Ok(());
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                // Sending only fails once the writer stopped on an error
+                let _: Result<(), std::sync::mpsc::SendError<_>> = in_pool(reader_pool, || {
+                    files.par_iter().enumerate().try_for_each_with(
+                        sender,
+                        |sender, (position, file)| {
+                            if let Some(duplicate) = duplicates.get(&file.path) {
+                                let last_modified = SystemTime::UNIX_EPOCH
+                                    + Duration::from_secs(file.modified_time);
+                                let document = CodeIndexDocument {
+                                    size: file.size,
+                                    duplicate_of: Some(duplicate.of.clone()),
+                                    last_commit: last_commits.get(&file.path).cloned(),
+                                    ..CodeIndexDocument::from_content(
+                                        file.path.clone(),
+                                        String::new(),
+                                        last_modified,
+                                    )
+                                };
+                                let file_end = FileEnd {
+                                    position,
+                                    hash: duplicate.hash,
+                                    skipped: None,
+                                };
+                                return sender
+                                    .send((document.to_tantivy_document(schema), Some(file_end)));
+                            }
+                            let mut documents =
+                                CodeIndexDocument::from_file(file, max_file_size, utf8_only);
+                            let mut digest = content_digest();
+                            let last_commit = last_commits.get(&file.path);
+                            let mut next = documents.next();
+                            while let Some(mut document) = next {
+                                document.last_commit = last_commit.cloned();
+                                digest.update(document.content.as_bytes());
+                                next = documents.next();
+                                let file_end = next.is_none().then(|| FileEnd {
+                                    position,
+                                    hash: digest.clone().finalize(),
+                                    skipped: documents.skipped(),
+                                });
+                                sender.send((document.to_tantivy_document(schema), file_end))?;
+                            }
+                            Ok(())
+                        },
+                    )
+                });
+            });
+
+            let mut batch_start = Instant::now();
+            let mut batch_files = 0;
+            let mut uncommitted = Vec::new();
+            for (document, file_end) in receiver {
+                if let Err(e) = self.writer.add_document(document) {
+                    add_result = Err(format!(
+                        "Failed to add document to index {}: {}",
+                        self.index_metadata.index_name, e
+                    ));
+                    break;
+                }
+                let Some(FileEnd {
+                    position,
+                    hash,
+                    skipped,
+                }) = file_end
+                else {
+                    continue;
+                };
+                if let Some(reason) = skipped {
+                    record.skipped.add(Some(&files[position].path), reason);
+                }
+                content_hashes.insert(files[position].path.clone(), hash);
+                journal.pending.push(files[position].path.clone());
+                uncommitted.push(position);
+                files_processed += 1;
+                batch_files += 1;
+                if batch_files < self.batch_size && files_processed < total_files {
+                    continue;
+                }
+
+                let mut batch = BatchRecord {
+                    files: batch_files,
+                    duration_ms: batch_start.elapsed().as_millis() as u64,
+                    commit_ms: None,
+                };
+                on_progress(IndexingProgress {
+                    phase: IndexingPhase::Indexing,
+                    files_processed,
+                    files_total: total_files,
+                });
+                let commit_due = self
+                    .commit_every
+                    .is_some_and(|every| (record.batches.len() + 1).is_multiple_of(every));
+                if let Some(committed) = committed_files
+                    .as_mut()
+                    .filter(|_| commit_due && files_processed < total_files)
+                {
+                    let commit_start = Instant::now();
+                    for position in uncommitted.drain(..) {
+                        let file = FileIndexMetadata {
+                            content_hash: content_hashes.get(&files[position].path).copied(),
+                            ..files[position].clone()
+                        };
+                        committed.insert(file.path.clone(), file);
+                    }
+                    let index_name = &self.index_metadata.index_name;
+                    let commit = self
+                        .storage
+                        .save_update_journal(index_name, Some(&journal))
+                        .and_then(|_| {
+                            self.writer.commit().map_err(|e| {
+                                format!("Failed to commit index writer for index {index_name}: {e}")
+                            })
+                        })
+                        .and_then(|_| {
+                            self.storage.save_file_index_metadata(
+                                index_name,
+                                committed.values().cloned().collect(),
+                            )
+                        })
+                        .and_then(|_| {
+                            journal.commits += 1;
+                            journal.pending.clear();
+                            self.storage.save_update_journal(index_name, Some(&journal))
+                        });
+                    if let Err(e) = commit {
+                        add_result = Err(e);
+                        break;
+                    }
+                    batch.commit_ms = Some(commit_start.elapsed().as_millis() as u64);
+                    info!(
+                        files_processed,
+                        commit_ms = batch.commit_ms,
+                        "committed intermediate batch"
+                    );
+                }
+                record.batches.push(batch);
+                batch_start = Instant::now();
+                batch_files = 0;
+            }
+        });
+        add_result?;
+
         let processing_duration = processing_start.elapsed();
+        record.files_per_sec = files_per_sec(files_processed, processing_duration);
+        info!(
+            files_processed,
+            files_per_sec = record.files_per_sec,
+            processing_duration_ms = processing_duration.as_millis(),
+            "completed file indexing"
+        );
 
+        on_progress(IndexingProgress {
+            phase: IndexingPhase::Committing,
+            files_processed,
+            files_total: total_files,
+        });
         let commit_start = Instant::now();
+        if journaled {
+            self.storage
+                .save_update_journal(&self.index_metadata.index_name, Some(&journal))?;
+        }
         self.writer.commit().map_err(|e| {
             format!(
                 "Failed to commit index writer for index {}: {}",
@@ -138,29 +704,989 @@ impl<'a> IndexWriter<'a> {
         })?;
         let commit_duration = commit_start.elapsed();
 
+        for file in &mut manifest {
+            if let Some(hash) = content_hashes.remove(&file.path) {
+                file.content_hash = Some(hash);
+            }
+        }
         self.storage
             .save_file_index_metadata(&self.index_metadata.index_name, manifest)?;
+        if journaled {
+            self.storage
+                .save_update_journal(&self.index_metadata.index_name, None)?;
+        }
 
         let total_duration = start_time.elapsed();
+        record.usage = sampler.finish();
+        record.finished_at = unix_time(SystemTime::now());
+        self.storage
+            .append_update_record(&self.index_metadata.index_name, &record)?;
+        self.storage.publish(&self.index_metadata.index_name)?;
 
         info!(
             total_files = total_files,
+            files_renamed = renamed_files,
             total_duration_ms = total_duration.as_millis(),
+            cpu_time_ms = record.usage.cpu_time_ms,
+            peak_rss_bytes = record.usage.peak_rss_bytes,
             processing_duration_ms = processing_duration.as_millis(),
             commit_duration_ms = commit_duration.as_millis(),
-            files_per_sec = if total_duration.as_secs_f64() > 0.0 {
-                (total_files as f64 / total_duration.as_secs_f64()) as u64
-            } else {
-                0
-            },
-            docs_per_sec = if processing_duration.as_secs_f64() > 0.0 {
-                (total_files as f64 / processing_duration.as_secs_f64()) as u64
-            } else {
-                0
-            },
+            files_per_sec = files_per_sec(total_files, total_duration),
             "indexing completed"
         );
 
+        Ok(record)
+    }
+
+    /// Commits the writer with `commits`, the payload of the commit, so that they are only
+    /// recorded along with the documents indexed from them. See [`indexed_commits`].
+    fn commit_with_commits(&mut self, commits: BTreeMap<&str, &str>) -> Result<(), String> {
+        let index_name = &self.index_metadata.index_name;
+        let payload = serde_json::to_string(&commits)
+            .map_err(|e| format!("Failed to serialize the commits of index {index_name}: {e}"))?;
+        let mut prepared = self
+            .writer
+            .prepare_commit()
+            .map_err(|e| format!("Failed to commit index writer for index {index_name}: {e}"))?;
+        prepared.set_payload(&payload);
+        prepared
+            .commit()
+            .map_err(|e| format!("Failed to commit index writer for index {index_name}: {e}"))?;
         Ok(())
     }
+
+    /// Indexes NDJSON [`IngestRecord`]s read from `reader`, prefixing every path with
+    /// `path_prefix`. Records replace earlier documents with the same path.
+    ///
+    /// Ingested documents are not part of the file index snapshot, so `index` never removes them.
+    pub fn ingest<R: BufRead>(&mut self, reader: R, path_prefix: &str) -> Result<usize, String> {
+        let _span = span!(Level::INFO, "index_writer_ingest",
+            index_name = %self.index_metadata.index_name,
+            path_prefix = %path_prefix
+        )
+        .entered();
+
+        let start_time = Instant::now();
+        let code_index_schema = CodeIndexSchema::new();
+        let mut total_records = 0;
+
+        for (line_idx, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| format!("Failed to read line {}: {e}", line_idx + 1))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let record: IngestRecord = serde_json::from_str(&line)
+                .map_err(|e| format!("Failed to parse record on line {}: {e}", line_idx + 1))?;
+            let path = format!("{path_prefix}{}", record.path);
+            let last_modified = record
+                .last_modified
+                .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+                .unwrap_or_else(SystemTime::now);
+
+            self.writer.delete_term(tantivy::Term::from_field_text(
+                code_index_schema.path,
+                &path,
+            ));
+            let document = CodeIndexDocument::from_content(path, record.content, last_modified);
+            self.writer
+                .add_document(document.to_tantivy_document(&self.schema))
+                .map_err(|e| {
+                    format!(
+                        "Failed to add document to index {}: {}",
+                        self.index_metadata.index_name, e
+                    )
+                })?;
+            total_records += 1;
+        }
+
+        self.writer.commit().map_err(|e| {
+            format!(
+                "Failed to commit index writer for index {}: {}",
+                self.index_metadata.index_name, e
+            )
+        })?;
+        self.storage.publish(&self.index_metadata.index_name)?;
+
+        info!(
+            total_records = total_records,
+            total_duration_ms = start_time.elapsed().as_millis(),
+            "ingestion completed"
+        );
+
+        Ok(total_records)
+    }
+}
+
+/// Sent by the readers of an update with the last document of each file.
+struct FileEnd {
+    /// Position of the file among the files read.
+    position: usize,
+    /// [`indexed_content_hash`] of the file.
+    hash: u64,
+    skipped: Option<SkipReason>,
+}
+
+/// A file indexed as a single empty document, since another file has the same content.
+struct Duplicate {
+    /// Path of the file indexed with the content.
+    of: String,
+    /// [`indexed_content_hash`] of both files.
+    hash: u64,
+}
+
+/// Moves the unchanged files that were indexed as duplicates of files that `delta` removes,
+/// modifies or renames to its modified files, so that one of them is indexed in their place.
+fn requeue_duplicates(delta: &mut Delta, manifest: &[FileIndexMetadata], searcher: &Searcher) {
+    let Ok(field) = searcher
+        .schema()
+        .get_field(CodeIndexSchema::DUPLICATE_OF_FIELD)
+    else {
+        return;
+    };
+    let path_field = CodeIndexSchema::new().path;
+    let mut duplicates = HashSet::new();
+    for path in delta
+        .removed
+        .iter()
+        .chain(&delta.modified)
+        .chain(delta.renamed.iter().map(|rename| &rename.from))
+        .map(|file| &file.path)
+    {
+        let query = TermQuery::new(
+            tantivy::Term::from_field_text(field, path),
+            IndexRecordOption::Basic,
+        );
+        for address in searcher
+            .search(&query, &DocSetCollector)
+            .unwrap_or_default()
+        {
+            let Ok(document) = searcher.doc::<TantivyDocument>(address) else {
+                continue;
+            };
+            if let Some(path) = document
+                .get_first(path_field)
+                .and_then(|value| value.as_str())
+            {
+                duplicates.insert(path.to_string());
+            }
+        }
+    }
+
+    let queued: HashSet<&str> = delta
+        .added
+        .iter()
+        .chain(&delta.modified)
+        .chain(delta.renamed.iter().map(|rename| &rename.to))
+        .map(|file| file.path.as_str())
+        .collect();
+    let requeued: Vec<_> = manifest
+        .iter()
+        .filter(|file| duplicates.contains(&file.path) && !queued.contains(file.path.as_str()))
+        .cloned()
+        .collect();
+    delta.modified.extend(requeued);
+}
+
+/// Tells which of the files that `delta` adds or modifies have the same indexed content as
+/// another file, hashing them with `hash`, by path.
+///
+/// Files that keep their documents come first, so that their duplicates stay duplicates of them;
+/// the other files are duplicates of the first of them in path order with their content. Files
+/// with no indexed content, such as empty or binary files, are never duplicates.
+fn find_duplicates(
+    delta: &Delta,
+    manifest: &[FileIndexMetadata],
+    searcher: &Searcher,
+    hash: impl Fn(&FileIndexMetadata) -> u64 + Sync,
+) -> HashMap<String, Duplicate> {
+    let mut files: Vec<&FileIndexMetadata> = delta.added.iter().chain(&delta.modified).collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let hashes: Vec<u64> = files.par_iter().map(|file| hash(file)).collect();
+
+    // The files that keep their documents may be duplicates themselves, which is only looked up
+    // for the contents found again
+    let updated: HashSet<&str> = files.iter().map(|file| file.path.as_str()).collect();
+    let mut canonical: HashMap<u64, (String, bool)> = HashMap::new();
+    let kept = manifest
+        .iter()
+        .filter(|file| !updated.contains(file.path.as_str()))
+        .filter_map(|file| Some((file.content_hash?, &file.path)))
+        .chain(
+            delta
+                .renamed
+                .iter()
+                .filter_map(|rename| Some((rename.from.content_hash?, &rename.to.path))),
+        );
+    for (hash, path) in kept {
+        canonical
+            .entry(hash)
+            .or_insert_with(|| (path.clone(), true));
+    }
+
+    let duplicate_of_field = searcher
+        .schema()
+        .get_field(CodeIndexSchema::DUPLICATE_OF_FIELD)
+        .ok();
+    let no_content = content_hash(&[]);
+    let mut duplicates = HashMap::new();
+    for (file, hash) in files.into_iter().zip(hashes) {
+        if hash == no_content {
+            continue;
+        }
+        match canonical.entry(hash) {
+            Entry::Vacant(entry) => {
+                entry.insert((file.path.clone(), false));
+            }
+            Entry::Occupied(mut entry) => {
+                let (of, kept) = entry.get_mut();
+                if std::mem::take(kept) {
+                    if let Some(canonical) =
+                        duplicate_of_field.and_then(|field| stored_text(searcher, field, of))
+                    {
+                        *of = canonical;
+                    }
+                }
+                duplicates.insert(
+                    file.path.clone(),
+                    Duplicate {
+                        of: of.clone(),
+                        hash,
+                    },
+                );
+            }
+        }
+    }
+
+    duplicates
+}
+
+/// Seconds from the Unix epoch to `time`.
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Returns the stored content of the document indexed under `path`.
+/// Pairs the removed and added files of `delta` whose content is the same, comparing the added
+/// files with the content stored for the removed ones in `searcher`.
+fn detect_stored_renames(delta: &mut Delta, searcher: &Searcher) {
+    let schema = CodeIndexSchema::new();
+    detect_renames(delta, |from, to| {
+        // Only the first chunk of larger files is at hand, so they are indexed again
+        if to.size > MAX_CHUNK_SIZE as u64 {
+            return false;
+        }
+        let Some(indexed) = stored_content(searcher, &schema, &from.path) else {
+            return false;
+        };
+        std::fs::read(&to.path)
+            .is_ok_and(|current| content_hash(indexed.as_bytes()) == content_hash(&current))
+    });
+}
+
+/// What an update of the index of `metadata` would add, modify, remove, move and skip, found
+/// like [`IndexWriter::index`] finds them, with `change_detection`, but without writing to the
+/// index. An update that recovers from an interrupted one rolls back its files first, which
+/// the plan does not account for; duplicates are not looked for either.
+pub(crate) fn plan_update(
+    storage: &dyn IndexStorage,
+    metadata: &IndexStorageMetadata,
+    change_detection: ChangeDetection,
+) -> Result<UpdatePlan, String> {
+    let index_name = &metadata.index_name;
+    if metadata.options.profile == SchemaProfile::Commits {
+        let indexed = indexed_commits(&storage.open(index_name)?)?;
+        return history::plan_history(&metadata.target_path, &indexed);
+    }
+    if !metadata.options.branches.is_empty() {
+        let indexed = indexed_commits(&storage.open(index_name)?)?;
+        return branches::plan_branches(&metadata.target_path, &metadata.options, &indexed);
+    }
+    check_target_path(metadata)?;
+    let snapshot = storage.read_file_index_metadata(index_name)?;
+    let (mut manifest, skipped) = scan_planned(&metadata.target_path, &metadata.options);
+
+    let options = &metadata.options;
+    let mut delta = detect_changes(&snapshot, &mut manifest, change_detection, |file| {
+        indexed_content_hash(&file.path, options.max_file_size, options.utf8_only)
+    });
+    if !delta.removed.is_empty() && !delta.added.is_empty() {
+        let reader = storage
+            .open(index_name)?
+            .reader()
+            .map_err(|e| format!("Failed to create reader for index {index_name}: {e}"))?;
+        detect_stored_renames(&mut delta, &reader.searcher());
+    }
+
+    let paths = |files: Vec<FileIndexMetadata>| -> Vec<String> {
+        let mut paths: Vec<String> = files.into_iter().map(|file| file.path).collect();
+        paths.sort();
+        paths
+    };
+    let mut renamed: Vec<PlannedRename> = delta
+        .renamed
+        .into_iter()
+        .map(|rename| PlannedRename {
+            from: rename.from.path,
+            to: rename.to.path,
+        })
+        .collect();
+    renamed.sort_by(|a, b| a.to.cmp(&b.to));
+
+    Ok(UpdatePlan {
+        added: paths(delta.added),
+        modified: paths(delta.modified),
+        removed: paths(delta.removed),
+        renamed,
+        skipped,
+    })
+}
+
+/// Fails when the target path of the index of `metadata` cannot be listed, e.g. on a machine
+/// that did not create the index: a scan would find none of its files and remove them all.
+fn check_target_path(metadata: &IndexStorageMetadata) -> Result<(), String> {
+    std::fs::read_dir(&metadata.target_path)
+        .map(|_| ())
+        .map_err(|e| {
+            format!(
+                "Cannot read target path {} of index {}: {e}",
+                metadata.target_path, metadata.index_name
+            )
+        })
+}
+
+/// The files a first update of `target_path` with `options` would index, as [`plan_update`]
+/// tells them, for an index not created yet.
+pub(crate) fn plan_create(
+    target_path: &str,
+    options: &IndexingOptions,
+) -> Result<UpdatePlan, String> {
+    if options.profile == SchemaProfile::Commits {
+        return history::plan_history(target_path, &Default::default());
+    }
+    if !options.branches.is_empty() {
+        return branches::plan_branches(target_path, options, &Default::default());
+    }
+    let (files, skipped) = scan_planned(target_path, options);
+    let mut added: Vec<String> = files.into_iter().map(|file| file.path).collect();
+    added.sort();
+
+    Ok(UpdatePlan {
+        added,
+        skipped,
+        ..UpdatePlan::default()
+    })
+}
+
+/// [`scan_files`], listing every skipped file.
+fn scan_planned(
+    target_path: &str,
+    options: &IndexingOptions,
+) -> (Vec<FileIndexMetadata>, Vec<SkippedFile>) {
+    let skipped = Mutex::new(Vec::new());
+    let files = scan_files(target_path, options, &|path, reason| {
+        if let Some(path) = path {
+            skipped.lock().unwrap().push(SkippedFile { path, reason });
+        }
+    });
+    let mut skipped = skipped.into_inner().unwrap();
+    skipped.sort_by(|a, b| a.path.cmp(&b.path));
+
+    (files, skipped)
+}
+
+fn stored_content(searcher: &Searcher, schema: &CodeIndexSchema, path: &str) -> Option<String> {
+    stored_text(searcher, schema.content, path)
+}
+
+/// Returns the stored value of `field` of the first document indexed under `path`.
+fn stored_text(searcher: &Searcher, field: tantivy::schema::Field, path: &str) -> Option<String> {
+    let schema = CodeIndexSchema::new();
+    let query = TermQuery::new(
+        tantivy::Term::from_field_text(schema.path, path),
+        IndexRecordOption::Basic,
+    );
+    let (_, address) = searcher
+        .search(&query, &TopDocs::with_limit(1))
+        .ok()?
+        .into_iter()
+        .next()?;
+    let document: TantivyDocument = searcher.doc(address).ok()?;
+
+    document
+        .get_first(field)
+        .and_then(|value| value.as_str())
+        .map(str::to_string)
+}
+
+/// The commits of a git repository `index` was last updated with, by branch, or `HEAD` for
+/// commit indexes, as [`IndexWriter::commit_with_commits`] keeps them.
+pub(crate) fn indexed_commits(index: &Index) -> Result<BTreeMap<String, String>, String> {
+    let metas = index
+        .load_metas()
+        .map_err(|e| format!("Failed to read the commits of the index: {e}"))?;
+    match metas.payload {
+        Some(payload) => serde_json::from_str(&payload)
+            .map_err(|e| format!("Invalid commits of the index '{payload}': {e}")),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+/// Throughput of `files` processed in `duration`, 0 when no time was measured.
+fn files_per_sec(files: usize, duration: Duration) -> u64 {
+    if duration.is_zero() {
+        return 0;
+    }
+    (files as f64 / duration.as_secs_f64()) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::{ChangeDetection, ContentStorage, IndexingOptions, WriterResources};
+    use crate::search::SearchOptions;
+    use crate::storage::{RamStorage, RecoveredUpdate, SkipReason, UpdateJournal};
+    use crate::IndexCatalog;
+    use std::path::Path;
+
+    #[test]
+    fn test_index_reads_files_in_parallel() {
+        let target = tempfile::tempdir().unwrap();
+        for i in 0..450 {
+            let content = format!("fn file_{i}() {{}}\n// shared\n");
+            std::fs::write(target.path().join(format!("file_{i}.rs")), content).unwrap();
+        }
+        let catalog = IndexCatalog::new(RamStorage::new());
+        catalog
+            .create(
+                "files",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+
+        let progress = std::sync::Mutex::new(Vec::new());
+        let record = catalog
+            .get_writer("files", false)
+            .unwrap()
+            .index_with_progress(&|update| {
+                if update.phase == super::IndexingPhase::Indexing {
+                    progress.lock().unwrap().push(update.files_processed);
+                }
+            })
+            .unwrap();
+
+        assert_eq!(record.files_added, 450);
+        assert!(record.files_per_sec > 0);
+        assert_eq!(*progress.lock().unwrap(), [0, 100, 200, 300, 400, 450]);
+        let results = catalog
+            .search("files", "shared", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(results.len(), 450);
+        let results = catalog
+            .search("files", "file_123", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(
+            results[0].path,
+            target.path().join("file_123.rs").to_string_lossy()
+        );
+    }
+
+    #[test]
+    fn test_index_commits_every_few_batches() {
+        let target = tempfile::tempdir().unwrap();
+        for i in 0..35 {
+            let content = format!("fn file_{i}() {{}}\n");
+            std::fs::write(target.path().join(format!("file_{i}.rs")), content).unwrap();
+        }
+        let catalog = IndexCatalog::new(RamStorage::new()).with_writer_resources(WriterResources {
+            batch_size: Some(10),
+            commit_every: Some(2),
+            ..WriterResources::default()
+        });
+        catalog
+            .create(
+                "files",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+
+        let record = catalog.get_writer("files", false).unwrap().index().unwrap();
+        let batches: Vec<_> = record
+            .batches
+            .iter()
+            .map(|batch| (batch.files, batch.commit_ms.is_some()))
+            .collect();
+        // The last batch is committed with the update rather than on its own
+        assert_eq!(batches, [(10, false), (10, true), (10, false), (5, false)]);
+        let update = catalog.get_writer("files", false).unwrap().index().unwrap();
+        assert_eq!((update.files_added, update.files_modified), (0, 0));
+        let results = catalog
+            .search("files", "file_34", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_update_rolls_back_interrupted_update() {
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("kept.rs"), "fn kept() {}").unwrap();
+        let catalog = IndexCatalog::new(RamStorage::new());
+        catalog
+            .create(
+                "files",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        catalog.get_writer("files", false).unwrap().index().unwrap();
+
+        // An update committed the documents of a new file, then stopped before saving the snapshot
+        std::fs::write(target.path().join("added.rs"), "fn added() {}").unwrap();
+        let mut writer = catalog.get_writer("files", false).unwrap();
+        let snapshot = writer.storage.read_file_index_metadata("files").unwrap();
+        writer.index().unwrap();
+        let added = writer
+            .storage
+            .read_file_index_metadata("files")
+            .unwrap()
+            .into_iter()
+            .find(|file| file.path.ends_with("added.rs"))
+            .unwrap();
+        writer
+            .storage
+            .save_file_index_metadata("files", snapshot)
+            .unwrap();
+        let journal = UpdateJournal {
+            started_at: 1,
+            commits: 0,
+            pending: vec![added.path],
+        };
+        writer
+            .storage
+            .save_update_journal("files", Some(&journal))
+            .unwrap();
+        drop(writer);
+
+        let mut writer = catalog.get_writer("files", false).unwrap();
+        let record = writer.index().unwrap();
+        assert_eq!(
+            record.recovered,
+            Some(RecoveredUpdate {
+                started_at: 1,
+                commits: 0,
+                files_rolled_back: 1
+            })
+        );
+        assert_eq!(record.files_added, 1);
+        assert_eq!(writer.storage.read_update_journal("files").unwrap(), None);
+        drop(writer);
+        let results = catalog
+            .search("files", "added", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_update_reports_skipped_files() {
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("text.rs"), "fn text() {}").unwrap();
+        std::fs::write(
+            target.path().join("image.rs"),
+            b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR",
+        )
+        .unwrap();
+        std::fs::write(target.path().join("large.rs"), "fn large() {}\n".repeat(10)).unwrap();
+        std::fs::write(target.path().join("notes.txt"), "notes").unwrap();
+        let options = IndexingOptions::default()
+            .with_only_extensions(["rs"])
+            .with_max_file_size(Some(100));
+        let catalog = IndexCatalog::new(RamStorage::new());
+        catalog
+            .create("files", &target.path().to_string_lossy(), &options, &[])
+            .unwrap();
+
+        let record = catalog.get_writer("files", false).unwrap().index().unwrap();
+        let skipped = &record.skipped;
+        assert_eq!(
+            (
+                skipped.unreadable,
+                skipped.binary,
+                skipped.too_large,
+                skipped.ignored
+            ),
+            (0, 1, 1, 1)
+        );
+        let mut files: Vec<_> = skipped
+            .files
+            .iter()
+            .map(|file| {
+                let name = Path::new(&file.path).file_name().unwrap();
+                (name.to_string_lossy().to_string(), file.reason)
+            })
+            .collect();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            files,
+            [
+                ("image.rs".to_string(), SkipReason::Binary),
+                ("large.rs".to_string(), SkipReason::TooLarge)
+            ]
+        );
+        // Files that were read are only reported by the update that read them
+        let update = catalog.get_writer("files", false).unwrap().index().unwrap();
+        assert_eq!((update.skipped.binary, update.skipped.too_large), (0, 1));
+    }
+
+    #[test]
+    fn test_update_transcodes_legacy_encodings() {
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("latin1.rs"), b"fn caf\xe9() {}\n").unwrap();
+        let utf16: Vec<u8> = "fn stra\u{df}e() {}\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        std::fs::write(
+            target.path().join("utf16.rs"),
+            [&[0xff, 0xfe], &utf16[..]].concat(),
+        )
+        .unwrap();
+        let catalog = IndexCatalog::new(RamStorage::new());
+        let options = IndexingOptions::default().with_content_storage(ContentStorage::None);
+        catalog
+            .create("files", &target.path().to_string_lossy(), &options, &[])
+            .unwrap();
+        let options = options.with_utf8_only(true);
+        catalog
+            .create("utf8", &target.path().to_string_lossy(), &options, &[])
+            .unwrap();
+
+        let record = catalog.get_writer("files", false).unwrap().index().unwrap();
+        assert!(record.skipped.is_empty());
+        for query in ["café", "straße"] {
+            let results = catalog
+                .search("files", query, &SearchOptions::default())
+                .unwrap();
+            assert_eq!(results.len(), 1, "{query}");
+            // Snippets of indexes that do not store content read the files back the same way
+            assert_eq!(results[0].snippet, format!("fn <b>{query}</b>"));
+        }
+
+        let record = catalog.get_writer("utf8", false).unwrap().index().unwrap();
+        assert_eq!(record.skipped.binary, 2);
+        let results = catalog
+            .search("utf8", "café", &SearchOptions::default())
+            .unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_update_reads_multibyte_encodings_and_stray_bytes() {
+        let target = tempfile::tempdir().unwrap();
+        let (shift_jis, _, _) = encoding_rs::SHIFT_JIS
+            .encode("// \u{691c}\u{7d22}\u{3059}\u{308b}\u{95a2}\u{6570}\u{3067}\u{3059}\u{3002}\nfn kensaku() {}\n");
+        std::fs::write(target.path().join("sjis.rs"), &shift_jis).unwrap();
+        // An invalid byte past the start the encoding is told by
+        let mut stray = b"fn stray_first() {}\n".to_vec();
+        stray.extend(b"// padding\n".repeat(1024));
+        stray.extend(b"fn stray_\xff_last() {}\n");
+        std::fs::write(target.path().join("stray.rs"), &stray).unwrap();
+        let catalog = IndexCatalog::new(RamStorage::new());
+        catalog
+            .create(
+                "files",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+
+        let record = catalog.get_writer("files", false).unwrap().index().unwrap();
+        assert!(record.skipped.is_empty());
+        let results = catalog
+            .search("files", "kensaku", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(
+            results[0].snippet.contains("\u{691c}\u{7d22}"),
+            "{}",
+            results[0].snippet
+        );
+        for query in ["stray_first", "_last"] {
+            let results = catalog
+                .search("files", query, &SearchOptions::default())
+                .unwrap();
+            assert_eq!(results.len(), 1, "{query}");
+        }
+    }
+
+    #[test]
+    fn test_update_indexes_duplicates_once() {
+        let target = tempfile::tempdir().unwrap();
+        for name in ["a.rs", "b.rs", "c.rs"] {
+            std::fs::write(target.path().join(name), "fn shared() {}\n").unwrap();
+        }
+        for name in ["empty.rs", "empty.txt"] {
+            std::fs::write(target.path().join(name), "").unwrap();
+        }
+        let catalog = IndexCatalog::new(RamStorage::new());
+        catalog
+            .create(
+                "files",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default().with_dedup(true),
+                &[],
+            )
+            .unwrap();
+        let search = || {
+            catalog
+                .search("files", "shared", &SearchOptions::default())
+                .unwrap()
+        };
+        let name = |path: &str| {
+            Path::new(path)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string()
+        };
+
+        // Empty files are not duplicates of each other
+        let record = catalog.get_writer("files", false).unwrap().index().unwrap();
+        assert_eq!(record.files_duplicated, 2);
+        let results = search();
+        assert_eq!(results.len(), 1);
+        assert_eq!(name(&results[0].path), "a.rs");
+        let duplicates: Vec<_> = results[0]
+            .duplicates
+            .iter()
+            .map(|path| name(path))
+            .collect();
+        assert_eq!(duplicates, vec!["b.rs", "c.rs"]);
+
+        // Removing the indexed file indexes one of its duplicates in its place
+        std::fs::remove_file(target.path().join("a.rs")).unwrap();
+        let record = catalog.get_writer("files", false).unwrap().index().unwrap();
+        assert_eq!(record.files_duplicated, 1);
+        let results = search();
+        assert_eq!(results.len(), 1);
+        assert_eq!(name(&results[0].path), "b.rs");
+        assert_eq!(name(&results[0].duplicates[0]), "c.rs");
+
+        // A new copy is a duplicate of the file already indexed
+        std::fs::write(target.path().join("a.rs"), "fn shared() {}\n").unwrap();
+        catalog.get_writer("files", false).unwrap().index().unwrap();
+        let results = search();
+        assert_eq!(name(&results[0].path), "b.rs");
+        assert_eq!(results[0].duplicates.len(), 2);
+    }
+
+    #[test]
+    fn test_update_of_missing_target_path_keeps_files() {
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("kept.rs"), "fn kept() {}").unwrap();
+        let catalog = IndexCatalog::new(RamStorage::new());
+        catalog
+            .create(
+                "files",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        catalog.get_writer("files", false).unwrap().index().unwrap();
+
+        drop(target);
+        let error = catalog
+            .plan_update("files", ChangeDetection::default())
+            .unwrap_err();
+        assert!(error.contains("Cannot read target path"), "{error}");
+        let error = catalog
+            .get_writer("files", false)
+            .unwrap()
+            .index()
+            .unwrap_err();
+        assert!(error.contains("Cannot read target path"), "{error}");
+        let results = catalog
+            .search("files", "kept", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_update_detects_changed_content() {
+        let target = tempfile::tempdir().unwrap();
+        let touched = target.path().join("touched.rs");
+        let edited = target.path().join("edited.rs");
+        std::fs::write(&touched, "fn touched() {}").unwrap();
+        std::fs::write(&edited, "fn original() {}").unwrap();
+        let catalog = IndexCatalog::new(RamStorage::new());
+        catalog
+            .create(
+                "files",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        catalog.get_writer("files", false).unwrap().index().unwrap();
+
+        // A checkout sets new times on both files, but only one of them has new content
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        std::fs::write(&edited, "fn replaced() {}").unwrap();
+        for path in [&touched, &edited] {
+            let file = std::fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(later).unwrap();
+        }
+        let record = catalog
+            .get_writer("files", false)
+            .unwrap()
+            .with_change_detection(ChangeDetection::Auto)
+            .index()
+            .unwrap();
+        assert_eq!(record.files_modified, 1);
+        let search = |query| {
+            catalog
+                .search("files", query, &SearchOptions::default())
+                .unwrap()
+                .len()
+        };
+        assert_eq!(search("replaced"), 1);
+        assert_eq!(search("original"), 0);
+        assert_eq!(search("touched"), 1);
+
+        // Content changed without a new time is only found by hashing
+        std::fs::write(&edited, "fn repaired() {}").unwrap();
+        let file = std::fs::File::options().write(true).open(&edited).unwrap();
+        file.set_modified(later).unwrap();
+        let update = |change_detection| {
+            catalog
+                .get_writer("files", false)
+                .unwrap()
+                .with_change_detection(change_detection)
+                .index()
+                .unwrap()
+                .files_modified
+        };
+        assert_eq!(update(ChangeDetection::Fast), 0);
+        assert_eq!(update(ChangeDetection::Hash), 1);
+        assert_eq!(search("repaired"), 1);
+    }
+
+    #[test]
+    fn test_index_large_files_in_chunks() {
+        let target = tempfile::tempdir().unwrap();
+        let mut content = String::new();
+        while content.len() < 2 * super::MAX_CHUNK_SIZE {
+            content.push_str("// filler line\n");
+        }
+        let last_line = content.lines().count() + 1;
+        content.push_str("fn needle_at_the_end() {}\n");
+        let path = target.path().join("large.rs");
+        std::fs::write(&path, &content).unwrap();
+        let path = path.to_string_lossy();
+
+        let catalog = IndexCatalog::new(RamStorage::new());
+        let options = IndexingOptions::default().with_max_file_size(Some(1024));
+        catalog
+            .create("large", &target.path().to_string_lossy(), &options, &[])
+            .unwrap();
+        catalog.get_writer("large", false).unwrap().index().unwrap();
+        let capped = catalog
+            .get_searcher("large")
+            .unwrap()
+            .file_content(&path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(capped.len(), 1024);
+
+        catalog
+            .create(
+                "whole",
+                &target.path().to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        catalog.get_writer("whole", false).unwrap().index().unwrap();
+        let searcher = catalog.get_searcher("whole").unwrap();
+        let results = searcher.search("needle_at_the_end").unwrap();
+        assert_eq!(results.len(), 1);
+        let symbols = searcher.search_symbols("needle_at_the_end").unwrap();
+        assert_eq!(symbols[0].line, last_line);
+        assert_eq!(searcher.file_content(&path).unwrap().unwrap(), content);
+    }
+
+    #[test]
+    fn test_update_records_last_commits() {
+        let repo = crate::git::tests::repository();
+        let target = repo.path().join("dir");
+        let catalog = IndexCatalog::new(RamStorage::new());
+        catalog
+            .create(
+                "files",
+                &target.to_string_lossy(),
+                &IndexingOptions::default().with_git_blame(true),
+                &[],
+            )
+            .unwrap();
+        std::fs::write(target.join("untracked.rs"), "fn untracked() {}").unwrap();
+        catalog.get_writer("files", false).unwrap().index().unwrap();
+        let search = |query: &str, author: Option<&str>| {
+            let options = SearchOptions {
+                author: author.map(str::to_string),
+                ..SearchOptions::default()
+            };
+            let mut results = catalog.search("files", query, &options).unwrap();
+            results.sort_by(|a, b| a.path.cmp(&b.path));
+            results
+                .into_iter()
+                .map(|result| {
+                    let name = Path::new(&result.path).file_name().unwrap();
+                    (name.to_string_lossy().to_string(), result.last_author)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let all = search("fn", None);
+        assert_eq!(
+            all,
+            [
+                ("new.rs".to_string(), Some("Bob".to_string())),
+                ("old.rs".to_string(), Some("Alice Smith".to_string())),
+                ("untracked.rs".to_string(), None),
+            ]
+        );
+        // Any of the words of the name, in any case, finds the author
+        assert_eq!(search("fn", Some("alice")), all[1..2]);
+        assert_eq!(search("fn", Some("SMITH alice")), all[1..2]);
+        assert!(search("fn", Some("alice bob")).is_empty());
+        let results = catalog
+            .search("files", "old", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(results[0].last_commit.as_ref().map(String::len), Some(40));
+
+        // Indexes created without the option have no authors to filter by
+        catalog
+            .create(
+                "plain",
+                &target.to_string_lossy(),
+                &IndexingOptions::default(),
+                &[],
+            )
+            .unwrap();
+        let options = SearchOptions {
+            author: Some("alice".to_string()),
+            ..SearchOptions::default()
+        };
+        let Err(e) = catalog.search("plain", "fn", &options) else {
+            panic!("an index without authors was filtered by author");
+        };
+        assert!(e.to_string().contains("--git-blame"), "{e}");
+    }
 }
@@ -0,0 +1,486 @@
+//! Indexes of the committed files of several branches, see [`IndexingOptions::branches`].
+//!
+//! The files of each branch are read from its head commit instead of the target path, and
+//! indexed with the branch in [`CodeIndexSchema::BRANCH_FIELD`], so that a path is in the index
+//! once per branch that has it. The commit each branch was indexed at is kept in the payload of
+//! the index's last commit: updates index the files that changed since, by diffing the commits,
+//! and the payload is only written along with the documents.
+
+use super::{
+    files_per_sec, indexed_commits, unix_time, IndexWriter, IndexingPhase, IndexingProgress,
+    UpdatePlan,
+};
+use crate::change::FileIndexMetadata;
+use crate::git::{
+    changed_files, commit_time, last_commits, read_blobs, resolve_commit, tree_files, FileChange,
+};
+use crate::options::IndexingOptions;
+use crate::schema::{CodeIndexDocument, CodeIndexSchema};
+use crate::storage::{
+    BatchRecord, IndexStorage, IndexStorageMetadata, SkipReason, SkippedFile, UpdateRecord,
+};
+use crate::usage::ResourceSampler;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::{Instant, SystemTime};
+use tantivy::query::{BooleanQuery, Query, TermQuery};
+use tantivy::schema::IndexRecordOption;
+use tantivy::Term;
+use tracing::{info, span, warn, Level};
+
+/// Whether a branch of the index of `metadata` has moved since it was indexed.
+pub(crate) fn branches_stale(
+    storage: &dyn IndexStorage,
+    metadata: &IndexStorageMetadata,
+) -> Result<bool, String> {
+    let indexed = indexed_commits(&storage.open(&metadata.index_name)?)?;
+    let dir = Path::new(&metadata.target_path);
+    Ok(metadata.options.branches.iter().any(|branch| {
+        resolve_commit(dir, branch).map_or(true, |commit| indexed.get(branch) != Some(&commit))
+    }))
+}
+
+/// What an update of the branches of `options` would index, with the commits of `indexed`, as
+/// [`super::plan_update`] tells it. Paths are prefixed with their branch, as in `main:/src/a.rs`.
+pub(crate) fn plan_branches(
+    target_path: &str,
+    options: &IndexingOptions,
+    indexed: &BTreeMap<String, String>,
+) -> Result<UpdatePlan, String> {
+    let root = target_path.trim_end_matches('/');
+    let mut plan = UpdatePlan::default();
+    for (branch, changes) in branch_changes(root, options, indexed)? {
+        let paths = |files: &[String]| -> Vec<String> {
+            files
+                .iter()
+                .map(|file| format!("{branch}:{root}/{file}"))
+                .collect()
+        };
+        plan.added.extend(paths(&changes.added));
+        plan.modified.extend(paths(&changes.modified));
+        plan.removed.extend(paths(&changes.removed));
+        plan.skipped
+            .extend(paths(&changes.ignored).into_iter().map(|path| SkippedFile {
+                path,
+                reason: SkipReason::Ignored,
+            }));
+    }
+    plan.added.sort();
+    plan.modified.sort();
+    plan.removed.sort();
+    plan.skipped.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(plan)
+}
+
+/// The files of a branch an update indexes, relative to the target path.
+#[derive(Default)]
+struct BranchChanges {
+    /// The head commit of the branch.
+    commit: String,
+    /// Whether every document of the branch is replaced, rather than those of `modified` and
+    /// `removed`: the branch is new to the index, or its indexed commit cannot be diffed, e.g.
+    /// after a force push.
+    full: bool,
+    added: Vec<String>,
+    modified: Vec<String>,
+    removed: Vec<String>,
+    /// Files the index's extension filter leaves out.
+    ignored: Vec<String>,
+}
+
+/// The changes of every branch of `options` since the commits of `indexed`, in order.
+fn branch_changes(
+    root: &str,
+    options: &IndexingOptions,
+    indexed: &BTreeMap<String, String>,
+) -> Result<Vec<(String, BranchChanges)>, String> {
+    let dir = Path::new(root);
+    options
+        .branches
+        .iter()
+        .map(|branch| {
+            let commit = resolve_commit(dir, branch)?;
+            let diff = match indexed.get(branch) {
+                Some(indexed) if *indexed == commit => Some(Vec::new()),
+                Some(indexed) => changed_files(dir, indexed, &commit)
+                    .inspect_err(|e| warn!("Failed to diff branch {branch}, indexing it all: {e}"))
+                    .ok(),
+                None => None,
+            };
+
+            let mut changes = BranchChanges {
+                full: diff.is_none(),
+                ..BranchChanges::default()
+            };
+            match diff {
+                Some(diff) => {
+                    for change in diff {
+                        match change {
+                            FileChange::Added(file) => changes.added.push(file),
+                            FileChange::Modified(file) => changes.modified.push(file),
+                            FileChange::Removed(file) => changes.removed.push(file),
+                        }
+                    }
+                }
+                None => changes.added = tree_files(dir, &commit)?,
+            }
+            // Removed files may have been indexed before the filter applied to them; removing
+            // them anyway is harmless
+            for files in [&mut changes.added, &mut changes.modified] {
+                let (included, ignored) = std::mem::take(files)
+                    .into_iter()
+                    .partition(|file| options.includes(Path::new(file)));
+                *files = included;
+                changes.ignored.extend::<Vec<_>>(ignored);
+            }
+            changes.commit = commit;
+
+            Ok((branch.clone(), changes))
+        })
+        .collect()
+}
+
+impl IndexWriter<'_> {
+    /// [`IndexWriter::index_with_progress`] for an index of branches.
+    pub(super) fn index_branches(
+        &mut self,
+        on_progress: &dyn Fn(IndexingProgress),
+    ) -> Result<UpdateRecord, String> {
+        let _span = span!(Level::INFO, "index_writer_index_branches",
+            index_name = %self.index_metadata.index_name,
+            target_path = %self.index_metadata.target_path
+        )
+        .entered();
+
+        let start_time = Instant::now();
+        let sampler = ResourceSampler::start();
+        on_progress(IndexingProgress {
+            phase: IndexingPhase::Scanning,
+            files_processed: 0,
+            files_total: 0,
+        });
+
+        let index_name = self.index_metadata.index_name.clone();
+        let options = self.index_metadata.options.clone();
+        let root = self
+            .index_metadata
+            .target_path
+            .trim_end_matches('/')
+            .to_string();
+        let dir = Path::new(&root);
+        let indexed = indexed_commits(self.writer.index())?;
+        let changes = branch_changes(&root, &options, &indexed)?;
+
+        let total_files: usize = changes
+            .iter()
+            .map(|(_, changes)| changes.added.len() + changes.modified.len())
+            .sum();
+        let mut record = UpdateRecord::default();
+        for (_, changes) in &changes {
+            record.files_added += changes.added.len();
+            record.files_modified += changes.modified.len();
+            record.files_removed += changes.removed.len();
+            for file in &changes.ignored {
+                record
+                    .skipped
+                    .add(Some(&format!("{root}/{file}")), SkipReason::Ignored);
+            }
+        }
+        info!(
+            branches = changes.len(),
+            files_added = record.files_added,
+            files_modified = record.files_modified,
+            files_removed = record.files_removed,
+            "calculated branch changes"
+        );
+        on_progress(IndexingProgress {
+            phase: IndexingPhase::Removing,
+            files_processed: 0,
+            files_total: total_files,
+        });
+
+        // Modified files are indexed again below, in place of all their documents of the branch
+        let code_index_schema = CodeIndexSchema::new();
+        let branch_field = self
+            .schema
+            .get_field(CodeIndexSchema::BRANCH_FIELD)
+            .map_err(|_| format!("Index {index_name} has no branch field"))?;
+        for (branch, changes) in &changes {
+            let branch_term = Term::from_field_text(branch_field, branch);
+            if changes.full {
+                self.writer.delete_term(branch_term);
+                continue;
+            }
+            for file in changes.removed.iter().chain(&changes.modified) {
+                let path_term =
+                    Term::from_field_text(code_index_schema.path, &format!("{root}/{file}"));
+                let query = BooleanQuery::intersection(vec![
+                    Box::new(TermQuery::new(
+                        branch_term.clone(),
+                        IndexRecordOption::Basic,
+                    )) as Box<dyn Query>,
+                    Box::new(TermQuery::new(path_term, IndexRecordOption::Basic)),
+                ]);
+                self.writer.delete_query(Box::new(query)).map_err(|e| {
+                    format!(
+                        "Failed to remove {file} of branch {branch} from index {index_name}: {e}"
+                    )
+                })?;
+            }
+        }
+
+        let processing_start = Instant::now();
+        let mut files_processed = 0;
+        on_progress(IndexingProgress {
+            phase: IndexingPhase::Indexing,
+            files_processed,
+            files_total: total_files,
+        });
+
+        let max_file_size = options.max_file_size;
+        let utf8_only = options.utf8_only;
+        let mut batch_start = Instant::now();
+        let mut batch_files = 0;
+        for (branch, changes) in &changes {
+            let files: Vec<String> = changes
+                .added
+                .iter()
+                .chain(&changes.modified)
+                .cloned()
+                .collect();
+            if files.is_empty() {
+                continue;
+            }
+            // Files of a branch are as old as its head commit
+            let modified_time = commit_time(dir, &changes.commit)?;
+            let last_commits = if options.git_blame {
+                let paths: Vec<String> =
+                    files.iter().map(|file| format!("{root}/{file}")).collect();
+                last_commits(&root, &changes.commit, paths.iter().map(String::as_str))
+                    .unwrap_or_else(|e| {
+                        warn!("Failed to read the last commits of branch {branch}: {e}");
+                        HashMap::new()
+                    })
+            } else {
+                HashMap::new()
+            };
+
+            read_blobs(dir, &changes.commit, &files, |file, content| {
+                // Listed from the commit, so always in it
+                let content = content.unwrap_or_default();
+                let file = FileIndexMetadata {
+                    path: format!("{root}/{file}"),
+                    size: content.len() as u64,
+                    modified_time,
+                    content_hash: None,
+                };
+                if max_file_size.is_some_and(|max| file.size > max) {
+                    record.skipped.add(Some(&file.path), SkipReason::TooLarge);
+                }
+                let last_commit = last_commits.get(&file.path);
+                let mut documents =
+                    CodeIndexDocument::from_bytes(&file, content, max_file_size, utf8_only);
+                for document in documents.by_ref() {
+                    let document = CodeIndexDocument {
+                        branch: Some(branch.clone()),
+                        last_commit: last_commit.cloned(),
+                        ..document
+                    };
+                    self.writer
+                        .add_document(document.to_tantivy_document(&self.schema))
+                        .map_err(|e| {
+                            format!("Failed to add document to index {index_name}: {e}")
+                        })?;
+                }
+                if let Some(reason) = documents.skipped() {
+                    record.skipped.add(Some(&file.path), reason);
+                }
+
+                files_processed += 1;
+                batch_files += 1;
+                if batch_files == self.batch_size {
+                    record.batches.push(BatchRecord {
+                        files: batch_files,
+                        duration_ms: batch_start.elapsed().as_millis() as u64,
+                        commit_ms: None,
+                    });
+                    on_progress(IndexingProgress {
+                        phase: IndexingPhase::Indexing,
+                        files_processed,
+                        files_total: total_files,
+                    });
+                    batch_start = Instant::now();
+                    batch_files = 0;
+                }
+                Ok(())
+            })?;
+        }
+        if batch_files > 0 {
+            record.batches.push(BatchRecord {
+                files: batch_files,
+                duration_ms: batch_start.elapsed().as_millis() as u64,
+                commit_ms: None,
+            });
+        }
+
+        let processing_duration = processing_start.elapsed();
+        record.files_per_sec = files_per_sec(files_processed, processing_duration);
+        info!(
+            files_processed,
+            files_per_sec = record.files_per_sec,
+            processing_duration_ms = processing_duration.as_millis(),
+            "completed branch indexing"
+        );
+
+        on_progress(IndexingProgress {
+            phase: IndexingPhase::Committing,
+            files_processed,
+            files_total: total_files,
+        });
+        self.commit_with_commits(
+            changes
+                .iter()
+                .map(|(branch, changes)| (branch.as_str(), changes.commit.as_str()))
+                .collect(),
+        )?;
+
+        record.usage = sampler.finish();
+        record.finished_at = unix_time(SystemTime::now());
+        self.storage.append_update_record(&index_name, &record)?;
+        self.storage.publish(&index_name)?;
+
+        info!(
+            total_files,
+            total_duration_ms = start_time.elapsed().as_millis(),
+            "branch indexing completed"
+        );
+
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::git;
+    use crate::options::IndexingOptions;
+    use crate::search::SearchOptions;
+    use crate::storage::RamStorage;
+    use crate::IndexCatalog;
+    use std::path::Path;
+
+    #[test]
+    fn test_index_branches() {
+        let repo = crate::git::tests::repository();
+        let run = |args: &[&str]| git(repo.path(), args).unwrap();
+        let head = run(&["rev-parse", "--abbrev-ref", "HEAD"])
+            .trim()
+            .to_string();
+        run(&["branch", "stable", "HEAD~1"]);
+        let target = repo.path().join("dir");
+        let target_path = target.to_string_lossy().to_string();
+        let catalog = IndexCatalog::new(RamStorage::new());
+        let options = IndexingOptions::default().with_branches(vec![head.clone(), "stable".into()]);
+        catalog
+            .create("branches", &target_path, &options, &[])
+            .unwrap();
+        catalog
+            .get_writer("branches", false)
+            .unwrap()
+            .index()
+            .unwrap();
+        let search = |query: &str, branches: &[&str]| {
+            let options = SearchOptions {
+                branches: branches.iter().map(|branch| branch.to_string()).collect(),
+                ..SearchOptions::default()
+            };
+            let mut results: Vec<_> = catalog
+                .search("branches", query, &options)
+                .unwrap()
+                .into_iter()
+                .map(|result| {
+                    let name = Path::new(&result.path).file_name().unwrap();
+                    (name.to_string_lossy().to_string(), result.branch.unwrap())
+                })
+                .collect();
+            results.sort();
+            results
+        };
+
+        // Each branch has its own version of a file
+        assert_eq!(
+            search("new", &[]),
+            [
+                ("new.rs".to_string(), head.clone()),
+                ("new.rs".into(), "stable".into())
+            ]
+        );
+        assert_eq!(
+            search("changed", &[]),
+            [("new.rs".to_string(), head.clone())]
+        );
+        assert!(search("changed", &["stable"]).is_empty());
+        assert_eq!(search("old", &["stable"]).len(), 1);
+
+        // Commits, not the working tree, are indexed, and only the files they changed
+        std::fs::remove_file(target.join("old.rs")).unwrap();
+        std::fs::write(target.join("added.rs"), "fn added() {}").unwrap();
+        std::fs::write(target.join("uncommitted.rs"), "fn uncommitted() {}").unwrap();
+        run(&["add", "dir/old.rs", "dir/added.rs"]);
+        run(&[
+            "-c",
+            "user.name=Bob",
+            "-c",
+            "user.email=dev@example.com",
+            "commit",
+            "-qm",
+            "Third",
+        ]);
+        let plan = catalog.plan_update("branches", Default::default()).unwrap();
+        assert_eq!(plan.added, [format!("{head}:{target_path}/added.rs")]);
+        assert_eq!(plan.removed, [format!("{head}:{target_path}/old.rs")]);
+        let record = catalog
+            .get_writer("branches", false)
+            .unwrap()
+            .index()
+            .unwrap();
+        assert_eq!(
+            (
+                record.files_added,
+                record.files_modified,
+                record.files_removed
+            ),
+            (1, 0, 1)
+        );
+        assert_eq!(
+            search("old", &[]),
+            [("old.rs".to_string(), "stable".to_string())]
+        );
+        assert_eq!(
+            search("added", &[]),
+            [("added.rs".to_string(), head.clone())]
+        );
+        assert!(search("uncommitted", &[]).is_empty());
+
+        // Up to date
+        let record = catalog
+            .get_writer("branches", false)
+            .unwrap()
+            .index()
+            .unwrap();
+        assert_eq!(
+            record.files_added + record.files_modified + record.files_removed,
+            0
+        );
+
+        // Branches must exist, and their files cannot be deduplicated
+        for options in [
+            IndexingOptions::default().with_branches(vec!["missing".into()]),
+            options.clone().with_dedup(true),
+        ] {
+            assert!(catalog
+                .create("invalid", &target_path, &options, &[])
+                .is_err());
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! Indexes of the commit history of a git repository, see [`SchemaProfile::Commits`], and the
+//! companion commit indexes of [`IndexingOptions::commits`].
+//!
+//! Each commit is a document whose path is the commit's full hash. The head the history was
+//! indexed up to is kept in the payload of the index's last commit, as `HEAD`: updates index the
+//! commits since, as `git log` lists them, and a head that cannot be walked from, e.g. after the
+//! history was rewritten and collected, has the whole history indexed again.
+//!
+//! [`SchemaProfile::Commits`]: crate::options::SchemaProfile::Commits
+//! [`IndexingOptions::commits`]: crate::options::IndexingOptions::commits
+
+use super::{
+    files_per_sec, indexed_commits, unix_time, IndexWriter, IndexingPhase, IndexingProgress,
+    UpdatePlan,
+};
+use crate::catalog::commits_index_name;
+use crate::git::{commit_log, resolve_commit, CommitInfo, LastCommit};
+use crate::options::WriterResources;
+use crate::schema::{CodeIndexDocument, CodeIndexSchema};
+use crate::storage::{BatchRecord, IndexStorage, IndexStorageMetadata, UpdateRecord};
+use crate::usage::ResourceSampler;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+use tantivy::Term;
+use tracing::{info, span, warn, Level};
+
+/// The key of the indexed head in the commits of [`indexed_commits`].
+const HEAD: &str = "HEAD";
+
+/// Whether the repository of the commit index of `metadata` has commits it has not indexed.
+pub(crate) fn history_stale(
+    storage: &dyn IndexStorage,
+    metadata: &IndexStorageMetadata,
+) -> Result<bool, String> {
+    let indexed = indexed_commits(&storage.open(&metadata.index_name)?)?;
+    Ok(resolve_commit(Path::new(&metadata.target_path), HEAD)
+        .map_or(true, |head| indexed.get(HEAD) != Some(&head)))
+}
+
+/// What an update of the commit index of `target_path` would index, after the commits of
+/// `indexed`, as [`super::plan_update`] tells it: the hashes of the commits it would add.
+pub(crate) fn plan_history(
+    target_path: &str,
+    indexed: &BTreeMap<String, String>,
+) -> Result<UpdatePlan, String> {
+    let dir = Path::new(target_path);
+    let (commits, _) = new_commits(dir, &resolve_commit(dir, HEAD)?, indexed)?;
+    let mut added: Vec<String> = commits.into_iter().map(|commit| commit.commit).collect();
+    added.sort();
+
+    Ok(UpdatePlan {
+        added,
+        ..UpdatePlan::default()
+    })
+}
+
+/// The commits of the repository of `dir` since the head of `indexed` up to `head`, or every
+/// commit up to `head` with `true` when there is no indexed head or it cannot be walked from.
+fn new_commits(
+    dir: &Path,
+    head: &str,
+    indexed: &BTreeMap<String, String>,
+) -> Result<(Vec<CommitInfo>, bool), String> {
+    let Some(since) = indexed.get(HEAD) else {
+        return Ok((commit_log(dir, None, head)?, true));
+    };
+    match commit_log(dir, Some(since), head) {
+        Ok(commits) => Ok((commits, false)),
+        Err(e) => {
+            warn!("Failed to read the commits since {since}, indexing them all: {e}");
+            Ok((commit_log(dir, None, head)?, true))
+        }
+    }
+}
+
+impl IndexWriter<'_> {
+    /// [`IndexWriter::index_with_progress`] for an index of commits.
+    pub(super) fn index_history(
+        &mut self,
+        on_progress: &dyn Fn(IndexingProgress),
+    ) -> Result<UpdateRecord, String> {
+        let _span = span!(Level::INFO, "index_writer_index_history",
+            index_name = %self.index_metadata.index_name,
+            target_path = %self.index_metadata.target_path
+        )
+        .entered();
+
+        let start_time = Instant::now();
+        let sampler = ResourceSampler::start();
+        on_progress(IndexingProgress {
+            phase: IndexingPhase::Scanning,
+            files_processed: 0,
+            files_total: 0,
+        });
+
+        let index_name = self.index_metadata.index_name.clone();
+        let dir = Path::new(&self.index_metadata.target_path).to_path_buf();
+        let indexed = indexed_commits(self.writer.index())?;
+        let head = resolve_commit(&dir, HEAD)?;
+        if indexed.get(HEAD) == Some(&head) {
+            return self.finish_history(UpdateRecord::default(), sampler, start_time);
+        }
+        let (commits, full) = new_commits(&dir, &head, &indexed)?;
+        let total = commits.len();
+        info!(commits = total, full, "read new commits");
+
+        let code_index_schema = CodeIndexSchema::new();
+        if full {
+            self.writer
+                .delete_all_documents()
+                .map_err(|e| format!("Failed to remove the commits of index {index_name}: {e}"))?;
+        }
+        on_progress(IndexingProgress {
+            phase: IndexingPhase::Indexing,
+            files_processed: 0,
+            files_total: total,
+        });
+
+        let mut record = UpdateRecord {
+            files_added: total,
+            ..UpdateRecord::default()
+        };
+        let processing_start = Instant::now();
+        let mut commits_processed = 0;
+        for batch in commits.chunks(self.batch_size.max(1)) {
+            let batch_start = Instant::now();
+            for commit in batch {
+                // Commits indexed before the head moved back and forth are indexed again
+                self.writer.delete_term(Term::from_field_text(
+                    code_index_schema.path,
+                    &commit.commit,
+                ));
+                let document = CodeIndexDocument {
+                    size: commit.message.len() as u64,
+                    last_commit: Some(LastCommit {
+                        commit: commit.commit.clone(),
+                        author: commit.author.clone(),
+                    }),
+                    files: commit.files.clone(),
+                    ..CodeIndexDocument::from_content(
+                        commit.commit.clone(),
+                        commit.message.clone(),
+                        SystemTime::UNIX_EPOCH + Duration::from_secs(commit.time),
+                    )
+                };
+                self.writer
+                    .add_document(document.to_tantivy_document(&self.schema))
+                    .map_err(|e| format!("Failed to add document to index {index_name}: {e}"))?;
+            }
+            record.batches.push(BatchRecord {
+                files: batch.len(),
+                duration_ms: batch_start.elapsed().as_millis() as u64,
+                commit_ms: None,
+            });
+            commits_processed += batch.len();
+            on_progress(IndexingProgress {
+                phase: IndexingPhase::Indexing,
+                files_processed: commits_processed,
+                files_total: total,
+            });
+        }
+        record.files_per_sec = files_per_sec(total, processing_start.elapsed());
+
+        on_progress(IndexingProgress {
+            phase: IndexingPhase::Committing,
+            files_processed: total,
+            files_total: total,
+        });
+        self.commit_with_commits(BTreeMap::from([(HEAD, head.as_str())]))?;
+
+        self.finish_history(record, sampler, start_time)
+    }
+
+    /// Appends `record` to the update history of the index, and publishes it.
+    fn finish_history(
+        &mut self,
+        mut record: UpdateRecord,
+        sampler: ResourceSampler,
+        start_time: Instant,
+    ) -> Result<UpdateRecord, String> {
+        let index_name = &self.index_metadata.index_name;
+        record.usage = sampler.finish();
+        record.finished_at = unix_time(SystemTime::now());
+        self.storage.append_update_record(index_name, &record)?;
+        self.storage.publish(index_name)?;
+
+        info!(
+            commits = record.files_added,
+            total_duration_ms = start_time.elapsed().as_millis(),
+            "history indexing completed"
+        );
+
+        Ok(record)
+    }
+
+    /// Brings the companion commit index up to date, waiting for its lock.
+    pub(super) fn update_commits_index(&self) -> Result<(), String> {
+        let index_name = commits_index_name(&self.index_metadata.index_name);
+        let update = || {
+            let lock = self.storage.lock(&index_name, true)?;
+            let metadata = self.storage.get_metadata(&index_name)?;
+            let index = self.storage.open(&index_name)?;
+            IndexWriter::new(
+                self.storage,
+                metadata,
+                index,
+                lock,
+                WriterResources::default(),
+            )?
+            .index()
+        };
+        update()
+            .map(|_| ())
+            .map_err(|e| format!("Failed to update the commit index {index_name}: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::git;
+    use crate::options::{IndexingOptions, SchemaProfile};
+    use crate::search::SearchOptions;
+    use crate::storage::RamStorage;
+    use crate::{commits_index_name, IndexCatalog, ALL_INDEXES};
+
+    #[test]
+    fn test_index_history() {
+        let repo = crate::git::tests::repository();
+        let target_path = repo.path().join("dir").to_string_lossy().to_string();
+        let catalog = IndexCatalog::new(RamStorage::new());
+        let options = IndexingOptions::default().with_commits(true);
+        catalog
+            .create("files", &target_path, &options, &[])
+            .unwrap();
+        let commits_index = commits_index_name("files");
+        let metadata = catalog.get_matadata(&commits_index).unwrap();
+        assert_eq!(metadata.options.profile, SchemaProfile::Commits);
+
+        // Updating the index updates its commit index
+        catalog.get_writer("files", false).unwrap().index().unwrap();
+        let search = |query: &str, author: Option<&str>| {
+            let options = SearchOptions {
+                author: author.map(str::to_string),
+                ..SearchOptions::default()
+            };
+            let mut results = catalog.search(&commits_index, query, &options).unwrap();
+            results.sort_by(|a, b| a.snippet.cmp(&b.snippet));
+            results
+        };
+        let results = search("second OR first", None);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].last_author.as_deref(), Some("Alice Smith"));
+        assert_eq!(results[0].last_commit.as_ref(), Some(&results[0].path));
+        assert_eq!(results[0].metadata["files"], "new.rs,old.rs");
+        assert_eq!(results[1].snippet, "<b>Second</b>");
+        // Messages are stemmed, touched paths split like code
+        assert_eq!(search("seconds", None).len(), 1);
+        assert_eq!(search("old", None).len(), 1);
+        assert_eq!(search("fir*", Some("bob")).len(), 0);
+
+        // Only new commits are indexed
+        std::fs::write(repo.path().join("dir/old.rs"), "fn fixed() {}").unwrap();
+        git(repo.path(), &["add", "."]).unwrap();
+        git(
+            repo.path(),
+            &[
+                "-c",
+                "user.name=Carol",
+                "-c",
+                "user.email=dev@example.com",
+                "commit",
+                "-qm",
+                "Fix the deadlock\n\nWhen updates overlap.",
+            ],
+        )
+        .unwrap();
+        let plan = catalog
+            .plan_update(&commits_index, Default::default())
+            .unwrap();
+        assert_eq!(plan.added.len(), 1);
+        let record = catalog
+            .get_writer(&commits_index, false)
+            .unwrap()
+            .index()
+            .unwrap();
+        assert_eq!(record.files_added, 1);
+        let results = search("deadlocks overlap", None);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].last_author.as_deref(), Some("Carol"));
+        assert_eq!(search("*", None).len(), 3);
+
+        // Searches of every index leave commit indexes out
+        assert_eq!(catalog.searchable_indexes().unwrap(), ["files"]);
+        let results = catalog
+            .search(ALL_INDEXES, "deadlock", &SearchOptions::default())
+            .unwrap();
+        assert!(results.is_empty());
+
+        // Removed with the index
+        catalog.remove("files").unwrap();
+        assert!(catalog.get_matadata(&commits_index).is_err());
+
+        // Outside a repository there are no commits to index
+        let dir = tempfile::tempdir().unwrap();
+        let e = catalog
+            .create("plain", &dir.path().to_string_lossy(), &options, &[])
+            .unwrap_err();
+        assert!(e.contains("commits"), "{e}");
+    }
+
+    #[test]
+    fn test_failed_commit_index_updates_are_reported() {
+        let repo = crate::git::tests::repository();
+        let target_path = repo.path().join("dir").to_string_lossy().to_string();
+        let catalog = IndexCatalog::new(RamStorage::new());
+        let options = IndexingOptions::default().with_commits(true);
+        catalog
+            .create("files", &target_path, &options, &[])
+            .unwrap();
+        let commits_index = commits_index_name("files");
+        catalog.remove(&commits_index).unwrap();
+
+        // The index is updated regardless
+        let record = catalog.get_writer("files", false).unwrap().index().unwrap();
+        assert_eq!(record.files_added, 2);
+        assert_eq!(record.companion_failures.len(), 1);
+        assert!(
+            record.companion_failures[0].contains(&commits_index),
+            "{:?}",
+            record.companion_failures
+        );
+    }
+}
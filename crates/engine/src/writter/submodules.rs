@@ -0,0 +1,180 @@
+//! The indexes of the submodules of an index with [`SubmodulePolicy::Separate`], one per checked
+//! out submodule, named by [`submodule_index_name`].
+//!
+//! [`SubmodulePolicy::Separate`]: crate::options::SubmodulePolicy::Separate
+
+use super::IndexWriter;
+use crate::catalog::{submodule_index_name, validate_index_name};
+use crate::git::submodules;
+use crate::options::{IndexingOptions, WriterResources};
+use crate::paths::normalize_path;
+use crate::storage::IndexStorageMetadata;
+use std::path::Path;
+
+/// Whether `index` is the index of a submodule of `parent`.
+pub(crate) fn is_submodule_index(
+    parent: &IndexStorageMetadata,
+    index: &IndexStorageMetadata,
+) -> bool {
+    index
+        .target_path
+        .strip_prefix(&parent.target_path)
+        .and_then(|submodule| submodule.strip_prefix('/'))
+        .is_some_and(|submodule| {
+            submodule_index_name(&parent.index_name, submodule) == index.index_name
+        })
+}
+
+/// The options of the index of a submodule of an index with `options`: the same, but for what
+/// is about the parent's directory only.
+fn submodule_options(options: &IndexingOptions) -> IndexingOptions {
+    IndexingOptions {
+        files: Vec::new(),
+        commits: false,
+        remote: None,
+        ..options.clone()
+    }
+}
+
+impl IndexWriter<'_> {
+    /// Creates the indexes of the checked out submodules of the target path that have none, and
+    /// brings each up to date, waiting for their locks. Returns why the submodules that failed
+    /// did, the others being updated regardless.
+    pub(super) fn update_submodule_indexes(&self) -> Vec<String> {
+        let target_path = &self.index_metadata.target_path;
+        let submodules = match submodules(target_path) {
+            Ok(submodules) => submodules,
+            Err(e) => {
+                return vec![format!(
+                    "Failed to list the submodules of {target_path}: {e}"
+                )]
+            }
+        };
+
+        let mut failures = Vec::new();
+
+        for submodule in submodules {
+            let dir = Path::new(target_path).join(&submodule);
+            // Submodules that are not checked out have no files to index
+            if !dir.join(".git").exists() {
+                continue;
+            }
+            let index_name = submodule_index_name(&self.index_metadata.index_name, &submodule);
+            let update = || {
+                if self.storage.get_metadata(&index_name).is_err() {
+                    validate_index_name(&index_name)?;
+                    self.storage.create(
+                        &index_name,
+                        &normalize_path(&dir),
+                        &submodule_options(&self.index_metadata.options),
+                        &self.index_metadata.tags,
+                    )?;
+                }
+                let lock = self.storage.lock(&index_name, true)?;
+                let metadata = self.storage.get_metadata(&index_name)?;
+                let index = self.storage.open(&index_name)?;
+                IndexWriter::new(
+                    self.storage,
+                    metadata,
+                    index,
+                    lock,
+                    WriterResources::default(),
+                )?
+                .index()
+            };
+            if let Err(e) = update() {
+                failures.push(format!(
+                    "Failed to update the index {index_name} of submodule {submodule}: {e}"
+                ));
+            }
+        }
+
+        failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::change::scan;
+    use crate::git::git;
+    use crate::options::{IndexingOptions, SubmodulePolicy};
+    use crate::paths::normalize_path;
+    use crate::search::SearchOptions;
+    use crate::storage::RamStorage;
+    use crate::{submodule_index_name, IndexCatalog};
+
+    #[test]
+    fn test_submodules() {
+        let submodule = crate::git::tests::repository();
+        let repo = tempfile::tempdir().unwrap();
+        git(repo.path(), &["init", "--quiet"]).unwrap();
+        std::fs::write(repo.path().join("main.rs"), "fn main() {}").unwrap();
+        git(repo.path(), &["add", "main.rs"]).unwrap();
+        let url = format!("file://{}", normalize_path(submodule.path()));
+        git(
+            repo.path(),
+            &[
+                "-c",
+                "protocol.file.allow=always",
+                "submodule",
+                "add",
+                "--quiet",
+                &url,
+                "libs/parser",
+            ],
+        )
+        .unwrap();
+        let target_path = normalize_path(repo.path());
+
+        // Scans descend into submodules, or leave them out, whether they walk or list files
+        let scanned = |submodules, tracked_only| {
+            let options = IndexingOptions::default()
+                .with_submodules(submodules)
+                .with_tracked_only(tracked_only);
+            let mut files: Vec<String> = scan(&target_path, &options)
+                .into_iter()
+                .map(|file| file.path[target_path.len() + 1..].to_string())
+                .filter(|file| file.ends_with(".rs"))
+                .collect();
+            files.sort();
+            files
+        };
+        for tracked_only in [false, true] {
+            assert_eq!(
+                scanned(SubmodulePolicy::Index, tracked_only),
+                [
+                    "libs/parser/dir/new.rs",
+                    "libs/parser/dir/old.rs",
+                    "libs/parser/top.rs",
+                    "main.rs"
+                ]
+            );
+            assert_eq!(scanned(SubmodulePolicy::Skip, tracked_only), ["main.rs"]);
+        }
+
+        // Separately, in an index of their own created by the updates
+        let catalog = IndexCatalog::new(RamStorage::new());
+        let options = IndexingOptions::default().with_submodules(SubmodulePolicy::Separate);
+        catalog
+            .create("app", &target_path, &options, &["backend".to_string()])
+            .unwrap();
+        catalog.get_writer("app", false).unwrap().index().unwrap();
+        let parser_index = submodule_index_name("app", "libs/parser");
+        assert_eq!(parser_index, "app.libs-parser");
+        let metadata = catalog.get_matadata(&parser_index).unwrap();
+        assert_eq!(metadata.target_path, format!("{target_path}/libs/parser"));
+        assert_eq!(metadata.tags, ["backend"]);
+        let search = |index_name: &str| {
+            catalog
+                .search(index_name, "top OR main", &SearchOptions::default())
+                .unwrap()
+                .len()
+        };
+        assert_eq!(search("app"), 1);
+        assert_eq!(search(&parser_index), 1);
+
+        // Removed with the index
+        catalog.remove("app").unwrap();
+        assert!(catalog.get_matadata(&parser_index).is_err());
+    }
+}
@@ -8,6 +8,8 @@ export interface SearchResult {
 	line_number?: number;
 	content?: string;
 	relevance_score?: number;
+	size?: number;
+	last_modified?: number;
 }
 
 export interface BeetleIndex {